@@ -1,6 +1,6 @@
 use std::{
     fs,
-    io::{Read, Seek, SeekFrom, Write, stderr, stdout},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write, stderr, stdout},
     path::{Path, PathBuf},
     sync::{
         Arc, Mutex,
@@ -13,13 +13,47 @@ use clap::{
     ArgAction, ArgGroup, Args, ColorChoice, CommandFactory, FromArgMatches, Parser, Subcommand,
     builder::styling::{AnsiColor, Color, Style, Styles},
 };
-use rayon::{ThreadPoolBuilder, prelude::*};
 use mimalloc::MiMalloc;
+use rayon::{ThreadPool, ThreadPoolBuilder, prelude::*};
 use regex::Regex;
 use serde::Serialize;
 
 use octo::{
-    b64::{decoder::decode, encoder::encode::encode, FileEncoderOutput}, mzml::{bin_to_mzml::bin_to_mzml, parse_mzml::parse_mzml, structs::*}
+    andi::parse_andi,
+    b64::{
+        FileEncoderOutput,
+        decoder::decode,
+        encoder::encode::{
+            DEFAULT_CHECKPOINT_INTERVAL_BYTES, Encoder, EncodingConfig, WritingMode,
+        },
+        salvage_b000,
+    },
+    imzml::{ImzmlMode, ImzmlWriteOptions, parse_imzml, write_b000_as_imzml, write_imzml},
+    json::parse_json,
+    mgf::{parse_mgf, write_b000_as_mgf, write_mgf},
+    msn::{MsnFormat, parse_msn, write_b000_as_msn, write_msn},
+    mzml::validate_cv::{CvValidation, Requirement, validate_cv_terms},
+    mzml::validate_refs::{RefValidation, fix_refs, validate_refs},
+    mzml::validate_schema::{SchemaValidation, validate_schema, validate_schema_mzml},
+    mzml::verify_roundtrip::{
+        CompareOptions, DiffKind, DiffReport, compare_mzml, verify_roundtrip,
+    },
+    mzml::{
+        bin_to_mzml::{
+            ArrayCompression, ArrayPrecision, MzmlWriteOptions, write_b000_as_mzml,
+            write_mzml_with_options,
+        },
+        parse_mzml::{
+            ParseOptions, is_gzip, parse_mzml, parse_mzml_parallel, parse_mzml_reader,
+            parse_mzml_reader_with_report, parse_mzml_with_report,
+        },
+        parse_report::ParseReport,
+        salvage::salvage_mzml_reader,
+        structs::*,
+    },
+    mzxml::{parse_mzxml, write_b000_as_mzxml, write_mzxml},
+    npz::{write_b000_as_npz, write_npz},
+    parquet::{write_b000_as_parquet, write_parquet},
 };
 
 #[global_allocator]
@@ -38,12 +72,21 @@ const AFTER_HELP: &str = "
 \x1b[1;33mQUICK REFERENCE\x1b[0m (full flags are in `octo convert --help` / `octo cat --help`)
 
 \x1b[1;32mUSAGE:\x1b[0m
-  \x1b[96mocto convert\x1b[0m [--mzml-to-b64 | --mzml-to-b32 | --b64-to-mzml]
+  \x1b[96mocto convert\x1b[0m [--mzml-to-b64 | --mzml-to-b32 | --b64-to-mzml] [--preserve-unknown] [--lenient] [--salvage] [--checkpoints] [--fix-refs]
+               [--from mgf|ms1|ms2|mzxml|imzml|andi|json] [--to mgf|ms1|ms2|mzml|mzxml|imzml|b64|b32] [--imzml-mode processed|continuous]
+               [--mzml-compression keep|none|zlib|numpress] [--zlib-level N] [--mzml-precision keep|f32|f64]
+               [--no-index] [--compact] [--add-missing-cv]
                -i, --input-path DIR
                -o, --output-path DIR
 
   \x1b[96mocto cat\x1b[0m PATH
 
+  \x1b[96mocto verify-roundtrip\x1b[0m PATH [OTHER] [--tolerance REL] [--json]
+
+  \x1b[96mocto validate\x1b[0m PATH [--json]
+
+  \x1b[96mocto export\x1b[0m --npz | --parquet PATH [-o, --output FILE] [--compress]
+
 \x1b[1;32mOPTIONS:\x1b[0m
   \x1b[96m-h\x1b[0m, \x1b[96m--help\x1b[0m
   \x1b[96m-v\x1b[0m, \x1b[96m--version\x1b[0m
@@ -51,7 +94,16 @@ const AFTER_HELP: &str = "
 \x1b[1;32mEXAMPLES:\x1b[0m
   \x1b[96mocto convert\x1b[0m -i crates/parser/data/mzml -o crates/parser/data/b64
   \x1b[96mocto convert\x1b[0m --b64-to-mzml -i crates/parser/data/b64 -o crates/parser/data/mzml_out
+  \x1b[96mocto convert\x1b[0m --to mgf -i crates/parser/data/b64 -o crates/parser/data/mgf
+  \x1b[96mocto convert\x1b[0m --to ms2 -i crates/parser/data/b64 -o crates/parser/data/ms2
+  \x1b[96mocto convert\x1b[0m --to mzxml -i crates/parser/data/mzml -o crates/parser/data/mzxml
+  \x1b[96mocto convert\x1b[0m --from imzml --to b64 -i imaging -o imaging_b64
+  \x1b[96mocto convert\x1b[0m --from andi --to mzml -i gcms -o gcms_mzml
+  \x1b[96mocto convert\x1b[0m --from json --to mzml -i edited_json -o edited_mzml
   \x1b[96mocto cat\x1b[0m crates/parser/data/b64/tiny.msdata.mzML0.99.9.b64
+  \x1b[96mocto verify-roundtrip\x1b[0m crates/parser/data/mzml/test.mzML
+  \x1b[96mocto export\x1b[0m --npz crates/parser/data/b64/tiny.pwiz.mzML0.99.10.b64 -o tiny.npz
+  \x1b[96mocto export\x1b[0m --parquet --compress run.b64 -o run
 ";
 
 fn cli_styles() -> Styles {
@@ -78,6 +130,9 @@ struct Cli {
 enum Cmd {
     Convert(ConvertArgs),
     Cat(CatArgs),
+    VerifyRoundtrip(VerifyArgs),
+    Validate(ValidateArgs),
+    Export(ExportArgs),
 }
 
 #[derive(Args)]
@@ -110,6 +165,79 @@ struct ConvertArgs {
     #[arg(long, default_value_t = false, action = ArgAction::SetTrue)]
     overwrite: bool,
 
+    /// Keep unmodelled mzML elements and comments in place, and unknown attributes of mzML, run, spectrum and chromatogram elements (mzML input without --salvage only)
+    #[arg(long = "preserve-unknown", default_value_t = false, action = ArgAction::SetTrue)]
+    preserve_unknown: bool,
+
+    /// Recover from damaged binary arrays of mzML input and print what was skipped, truncated or defaulted
+    #[arg(long, default_value_t = false, action = ArgAction::SetTrue)]
+    lenient: bool,
+
+    /// Keep the complete spectra and chromatograms of truncated mzML files,
+    /// or of damaged .b64/.b32 files. Only .b64/.b32 files written with
+    /// --checkpoints can be salvaged: without them the metadata is at the
+    /// end and a cut-short file keeps nothing. With them, a spectrum is kept
+    /// once its block and the checkpoint after it were written
+    #[arg(long, default_value_t = false, action = ArgAction::SetTrue)]
+    salvage: bool,
+
+    /// Write recovery checkpoints into .b64/.b32 output so it can be salvaged if cut short;
+    /// without them a partly written file cannot be recovered
+    #[arg(long, default_value_t = false, action = ArgAction::SetTrue)]
+    checkpoints: bool,
+
+    /// Rename duplicate ids and rewrite or drop references to missing ids before writing
+    #[arg(long = "fix-refs", default_value_t = false, action = ArgAction::SetTrue)]
+    fix_refs: bool,
+
+    /// Compression of the binary arrays in mzML output; keep uses each array's own cvParam
+    #[arg(
+        long = "mzml-compression",
+        default_value = "keep",
+        value_parser = ["keep", "none", "zlib", "numpress"]
+    )]
+    mzml_compression: String,
+
+    /// zlib level for zlib-compressed arrays in mzML output
+    #[arg(
+        long = "zlib-level",
+        default_value_t = 6,
+        value_parser = clap::value_parser!(u8).range(0..=10)
+    )]
+    zlib_level: u8,
+
+    /// Precision of floating-point arrays in mzML output
+    #[arg(long = "mzml-precision", default_value = "keep", value_parser = ["keep", "f32", "f64"])]
+    mzml_precision: String,
+
+    /// Write plain mzML without the offset index and checksum
+    #[arg(long = "no-index", default_value_t = false, action = ArgAction::SetTrue)]
+    no_index: bool,
+
+    /// Write mzML without indentation
+    #[arg(long, default_value_t = false, action = ArgAction::SetTrue)]
+    compact: bool,
+
+    /// Add missing compression and binary data type cvParams to arrays instead of failing
+    #[arg(long = "add-missing-cv", default_value_t = false, action = ArgAction::SetTrue)]
+    add_missing_cv: bool,
+
+    /// Read this format instead of mzML or .b64/.b32: mgf, ms1, ms2, mzxml, imzml (with the .ibd next to each .imzML), andi (ANDI-MS .cdf) or json (the output of octo cat --full, or one spectrum per line)
+    #[arg(long = "from", value_parser = ["mgf", "ms1", "ms2", "mzxml", "imzml", "andi", "json"], conflicts_with = "convert_mode")]
+    from: Option<String>,
+
+    /// Write this format: mgf, ms1, ms2, mzxml or imzml from mzML or .b64/.b32 input, or any of mgf, ms1, ms2, mzml, mzxml, imzml, b64 (default) and b32 with --from
+    #[arg(
+        long = "to",
+        value_parser = ["mgf", "ms1", "ms2", "mzml", "mzxml", "imzml", "b64", "b32"],
+        conflicts_with = "convert_mode"
+    )]
+    to: Option<String>,
+
+    /// .ibd layout for --to imzml: processed (an m/z array per spectrum) or continuous (one shared m/z array)
+    #[arg(long = "imzml-mode", default_value = "processed", value_parser = ["processed", "continuous"])]
+    imzml_mode: String,
+
     #[arg(long = "pattern")]
     pattern: Option<String>,
 
@@ -151,6 +279,79 @@ struct CatArgs {
     full: bool,
 }
 
+#[derive(Args)]
+struct VerifyArgs {
+    /// mzML file to round-trip through B000, or the left side when OTHER is given
+    #[arg(value_name = "PATH")]
+    file_path: PathBuf,
+
+    /// Compare PATH against this file instead of round-tripping PATH
+    #[arg(value_name = "OTHER")]
+    other_path: Option<PathBuf>,
+
+    #[arg(
+        long = "level",
+        default_value_t = 12,
+        value_parser = clap::value_parser!(u8).range(0..=22)
+    )]
+    compression_level: u8,
+
+    /// Store float arrays as f32 during the round trip
+    #[arg(long = "f32", action = ArgAction::SetTrue, default_value_t = false)]
+    force_f32: bool,
+
+    /// Relative tolerance for binary arrays and numeric cvParam values
+    #[arg(long = "tolerance", default_value_t = 1e-9)]
+    rel_tolerance: f64,
+
+    /// Absolute tolerance for binary arrays and numeric cvParam values
+    #[arg(long = "abs-tolerance", default_value_t = 0.0)]
+    abs_tolerance: f64,
+
+    /// Print the report as JSON
+    #[arg(long = "json", action = ArgAction::SetTrue, default_value_t = false)]
+    json: bool,
+
+    /// Also list differences in representation only (counts, encoded lengths, cvParam labels, empty attributes and writer defaults)
+    #[arg(long = "show-normalized", action = ArgAction::SetTrue, default_value_t = false)]
+    show_normalized: bool,
+}
+
+#[derive(Args)]
+struct ValidateArgs {
+    /// mzML file to check against the mzML schema and the PSI-MS CV-mapping rules, and for dangling references and duplicate ids; .b64/.b32 files are checked as written back to mzML
+    #[arg(value_name = "PATH")]
+    file_path: PathBuf,
+
+    /// Print the issues as JSON
+    #[arg(long = "json", action = ArgAction::SetTrue, default_value_t = false)]
+    json: bool,
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("export_format").args(["npz", "parquet"]).required(true)))]
+struct ExportArgs {
+    /// mzML, .b64/.b32 or other readable file to export; .b64/.b32 files are decoded one spectrum at a time
+    #[arg(value_name = "PATH")]
+    file_path: PathBuf,
+
+    /// Write a NumPy .npz archive of concatenated peak arrays with per-spectrum offsets and columns
+    #[arg(long = "npz", action = ArgAction::SetTrue, default_value_t = false)]
+    npz: bool,
+
+    /// Write the Parquet tables FILE.peaks.parquet, one row per peak, and FILE.spectra.parquet, one row per spectrum
+    #[arg(long = "parquet", action = ArgAction::SetTrue, default_value_t = false)]
+    parquet: bool,
+
+    /// Output file; defaults to PATH with the format's extension. For --parquet, the prefix of both tables
+    #[arg(short = 'o', long = "output")]
+    output_path: Option<PathBuf>,
+
+    /// Compress the arrays: deflate for .npz, as numpy.savez_compressed does, zstd for Parquet
+    #[arg(long = "compress", action = ArgAction::SetTrue, default_value_t = false)]
+    compress: bool,
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut cmd = Cli::command();
     cmd = cmd
@@ -169,6 +370,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     match cli.cmd {
         Some(Cmd::Convert(cmd)) => convert(cmd).map_err(|e| e.into()),
         Some(Cmd::Cat(cmd)) => cat(cmd).map_err(|e| e.into()),
+        Some(Cmd::VerifyRoundtrip(cmd)) => verify(cmd).map_err(|e| e.into()),
+        Some(Cmd::Validate(cmd)) => validate(cmd).map_err(|e| e.into()),
+        Some(Cmd::Export(cmd)) => export(cmd).map_err(|e| e.into()),
         None => Ok(()),
     }
 }
@@ -189,18 +393,283 @@ fn cat(cmd: CatArgs) -> Result<(), String> {
     print_json_full(&mzml)
 }
 
+fn verify(cmd: VerifyArgs) -> Result<(), String> {
+    let cwd = std::env::current_dir().map_err(|e| format!("get current dir failed: {e}"))?;
+    let file_path = resolve_user_path(&cwd, &cmd.file_path);
+    let options = CompareOptions {
+        abs_tolerance: cmd.abs_tolerance,
+        rel_tolerance: cmd.rel_tolerance,
+    };
+
+    let left = read_mzml_or_b64(&file_path)?;
+    let report = match &cmd.other_path {
+        Some(other) => {
+            let right = read_mzml_or_b64(&resolve_user_path(&cwd, other))?;
+            compare_mzml(&left, &right, &options)
+        }
+        None => verify_roundtrip(&left, cmd.compression_level, cmd.force_f32, &options)
+            .map_err(|e| format!("round trip failed: {e}"))?,
+    };
+
+    if cmd.json {
+        print_json_full(&report)?;
+    } else {
+        print_diff_report(&report, cmd.show_normalized);
+    }
+
+    if report.is_identical() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} differences in {}",
+            report.diffs.len(),
+            basename(&file_path)
+        ))
+    }
+}
+
+fn validate(cmd: ValidateArgs) -> Result<(), String> {
+    let cwd = std::env::current_dir().map_err(|e| format!("get current dir failed: {e}"))?;
+    let file_path = resolve_user_path(&cwd, &cmd.file_path);
+    let ext = file_ext_lower(&file_path);
+
+    let (schema, mzml) = if ext == "b64" || ext == "b32" {
+        let mzml = read_mzml_or_b64(&file_path)?;
+        (validate_schema_mzml(&mzml)?, mzml)
+    } else {
+        let bytes = fs::read(&file_path).map_err(|e| format!("read failed: {e}"))?;
+        let schema = validate_schema(&bytes).map_err(|e| format!("validate failed: {e}"))?;
+        let mzml = parse_mzml_reader(&bytes[..]).map_err(|e| format!("parse_mzml failed: {e}"))?;
+        (schema, mzml)
+    };
+    let report = ValidateReport {
+        cv: validate_cv_terms(&mzml),
+        refs: validate_refs(&mzml),
+        schema,
+    };
+
+    if cmd.json {
+        print_json_full(&report)?;
+    } else {
+        print_schema_issues(&report.schema);
+        print_cv_issues(&report.cv);
+        print_ref_issues(&report.refs);
+    }
+
+    if report.schema.is_valid() && report.cv.is_valid() && report.refs.is_valid() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} schema issues, {} CV issues and {} reference issues in {}",
+            report.schema.issues.len(),
+            report.cv.issues.len(),
+            report.refs.issues.len(),
+            basename(&file_path)
+        ))
+    }
+}
+
+fn export(cmd: ExportArgs) -> Result<(), String> {
+    const MB: f64 = 1024.0 * 1024.0;
+    let cwd = std::env::current_dir().map_err(|e| format!("get current dir failed: {e}"))?;
+    let file_path = resolve_user_path(&cwd, &cmd.file_path);
+    let t0 = Instant::now();
+    let in_len = fs::metadata(&file_path)
+        .map_err(|e| format!("read failed: {e}"))?
+        .len();
+    let ext = file_ext_lower(&file_path);
+    // B000 files are decoded one spectrum at a time as they are read; other
+    // inputs are parsed whole first.
+    let b000 = ext == "b64" || ext == "b32";
+    let open = || {
+        let file = fs::File::open(&file_path).map_err(|e| format!("read failed: {e}"))?;
+        Ok::<_, String>(BufReader::new(file))
+    };
+    let read_mzml = || {
+        let bytes = fs::read(&file_path).map_err(|e| format!("read failed: {e}"))?;
+        read_mzml_or_b64_from_bytes(&file_path, &bytes)
+    };
+
+    if cmd.parquet {
+        let prefix = match &cmd.output_path {
+            Some(p) => resolve_user_path(&cwd, p),
+            None => without_ext(&file_path),
+        };
+        let prefix = prefix.to_string_lossy();
+        let prefix = prefix.strip_suffix(".parquet").unwrap_or(&prefix);
+        let peaks_path = PathBuf::from(format!("{prefix}.peaks.parquet"));
+        let spectra_path = PathBuf::from(format!("{prefix}.spectra.parquet"));
+
+        let paths = [peaks_path.as_path(), spectra_path.as_path()];
+        let [peaks_len, spectra_len] = if b000 {
+            let input = open()?;
+            write_output_files(paths, "write_b000_as_parquet", |peaks, spectra| {
+                write_b000_as_parquet(peaks, spectra, input, cmd.compress)
+            })?
+        } else {
+            let mzml = read_mzml()?;
+            write_output_files(paths, "write_parquet", |peaks, spectra| {
+                write_parquet(peaks, spectra, &mzml, cmd.compress)
+            })?
+        };
+        println!(
+            "{ANSI_GREEN}[ok]{ANSI_RESET} output: {}, {}  input={:.2} MB, output={:.2} MB, time={:.3}s",
+            basename(&peaks_path),
+            basename(&spectra_path),
+            in_len as f64 / MB,
+            (peaks_len + spectra_len) as f64 / MB,
+            t0.elapsed().as_secs_f64()
+        );
+        return Ok(());
+    }
+
+    let out_path = match &cmd.output_path {
+        Some(p) => resolve_user_path(&cwd, p),
+        None => without_ext(&file_path).with_extension("npz"),
+    };
+    let out_len = if b000 {
+        let input = open()?;
+        write_output_file(&out_path, "write_b000_as_npz", |out| {
+            write_b000_as_npz(out, input, cmd.compress)
+        })?
+    } else {
+        let mzml = read_mzml()?;
+        write_output_file(&out_path, "write_npz", |out| {
+            write_npz(out, &mzml, cmd.compress)
+        })?
+    };
+    println!(
+        "{ANSI_GREEN}[ok]{ANSI_RESET} output: {}  input={:.2} MB, output={:.2} MB, time={:.3}s",
+        basename(&out_path),
+        in_len as f64 / MB,
+        out_len as f64 / MB,
+        t0.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ValidateReport {
+    schema: SchemaValidation,
+    cv: CvValidation,
+    refs: RefValidation,
+}
+
+fn print_schema_issues(validation: &SchemaValidation) {
+    let mut out = stdout().lock();
+    for issue in &validation.issues {
+        let _ = writeln!(out, "{ANSI_RED}[invalid]{ANSI_RESET} {issue}");
+    }
+    let _ = writeln!(
+        out,
+        "{ANSI_GREEN}[summary]{ANSI_RESET} issues={}",
+        validation.issues.len()
+    );
+}
+
+fn print_ref_issues(validation: &RefValidation) {
+    let mut out = stdout().lock();
+    for issue in &validation.issues {
+        let _ = writeln!(out, "{ANSI_RED}[invalid]{ANSI_RESET} {issue}");
+    }
+    let _ = writeln!(
+        out,
+        "{ANSI_GREEN}[summary]{ANSI_RESET} ref_issues={}",
+        validation.issues.len()
+    );
+}
+
+fn print_ref_fixes(mzml: &mut MzML, in_path: &Path, print_lock: &Mutex<()>) {
+    let fixed = fix_refs(mzml);
+    if fixed.is_valid() {
+        return;
+    }
+    let name = basename(in_path);
+    let _g = print_lock.lock().unwrap_or_else(|e| e.into_inner());
+    for issue in &fixed.issues {
+        eprintln!("{ANSI_YELLOW}[fixed]{ANSI_RESET} {name}: {issue}");
+    }
+    let _ = stderr().flush();
+}
+
+fn print_cv_issues(validation: &CvValidation) {
+    let mut out = stdout().lock();
+    for issue in &validation.issues {
+        let (label, color) = match issue.requirement {
+            Requirement::Must => ("invalid", ANSI_RED),
+            _ => ("warn", ANSI_YELLOW),
+        };
+        let _ = writeln!(out, "{color}[{label}]{ANSI_RESET} {issue}");
+    }
+    let _ = writeln!(
+        out,
+        "{ANSI_GREEN}[summary]{ANSI_RESET} cv_issues={}",
+        validation.issues.len()
+    );
+}
+
+fn print_diff_report(report: &DiffReport, show_normalized: bool) {
+    let mut out = stdout().lock();
+    let normalized = report.normalized.iter().filter(|_| show_normalized);
+    let diffs = report.diffs.iter().map(|d| (d, false));
+    for (diff, normalized) in diffs.chain(normalized.map(|d| (d, true))) {
+        let (label, color) = match diff.kind {
+            _ if normalized => ("normalized", ANSI_RESET),
+            DiffKind::Lost => ("lost", ANSI_RED),
+            DiffKind::Changed => ("changed", ANSI_YELLOW),
+            DiffKind::Added => ("added", ANSI_BLUE),
+        };
+        let left = diff.left.as_deref().unwrap_or("-");
+        let right = diff.right.as_deref().unwrap_or("-");
+        let _ = writeln!(
+            out,
+            "{color}[{label}]{ANSI_RESET} {}: {left} -> {right}",
+            diff.path
+        );
+    }
+    let _ = writeln!(
+        out,
+        "{ANSI_GREEN}[summary]{ANSI_RESET} lost={} changed={} added={} normalized={}",
+        report.count(DiffKind::Lost),
+        report.count(DiffKind::Changed),
+        report.count(DiffKind::Added),
+        report.normalized.len()
+    );
+}
+
+/// Lower-cased extension; gzip-compressed mzML and mzXML are reported as
+/// `mzml.gz` and `mzxml.gz`.
 fn file_ext_lower(path: &Path) -> String {
-    path.extension()
+    let ext = path
+        .extension()
         .and_then(|s| s.to_str())
         .unwrap_or("")
-        .to_ascii_lowercase()
+        .to_ascii_lowercase();
+    if ext == "gz" {
+        let inner = path.file_stem().map(Path::new).map(file_ext_lower);
+        if let Some(inner @ ("mzml" | "mzxml")) = inner.as_deref() {
+            return format!("{inner}.gz");
+        }
+    }
+    ext
+}
+
+/// `path` without its extension, both parts of `.mzML.gz` and `.mzXML.gz`.
+fn without_ext(path: &Path) -> PathBuf {
+    let ext = file_ext_lower(path);
+    match path.file_name().map(|n| n.to_string_lossy()) {
+        Some(name) if !ext.is_empty() => path.with_file_name(&name[..name.len() - ext.len() - 1]),
+        _ => path.to_path_buf(),
+    }
 }
 
 fn out_name_for_mzml_file(path: &Path, out_ext: &str) -> Option<String> {
-    if file_ext_lower(path) != "mzml" {
+    let ext = file_ext_lower(path);
+    if !matches!(ext.as_str(), "mzml" | "mzml.gz" | "mzxml" | "mzxml.gz") {
         return None;
     }
-    let stem = path.file_stem()?.to_string_lossy();
+    let name = path.file_name()?.to_string_lossy();
+    let stem = &name[..name.len() - ext.len() - 1];
     Some(format!("{stem}.{out_ext}"))
 }
 
@@ -223,9 +692,22 @@ fn read_mzml_or_b64(file_path: &Path) -> Result<MzML, String> {
     if ext == "mzml" {
         return parse_mzml(&bytes).map_err(|e| format!("parse_mzml failed: {e}"));
     }
+    if ext == "mzml.gz" {
+        return parse_mzml_reader(&bytes[..]).map_err(|e| format!("parse_mzml failed: {e}"));
+    }
+    if ext == "mzxml" || ext == "mzxml.gz" {
+        return parse_mzxml(&bytes).map_err(|e| format!("parse_mzxml failed: {e}"));
+    }
+    if ext == "imzml" {
+        return read_imzml(file_path, &bytes);
+    }
+    if ext == "cdf" {
+        return parse_andi(&bytes, &basename(file_path))
+            .map_err(|e| format!("parse_andi failed: {e}"));
+    }
 
     Err(format!(
-        "unsupported file extension: {ext:?} (expected .mzML or .b64/.b32)"
+        "unsupported file extension: {ext:?} (expected .mzML, .mzML.gz, .mzXML, .imzML, .cdf or .b64/.b32)"
     ))
 }
 
@@ -382,6 +864,10 @@ fn convert(cmd: ConvertArgs) -> Result<(), String> {
         .build()
         .map_err(|e| format!("rayon thread pool init failed: {e}"))?;
 
+    if cmd.from.is_some() || cmd.to.is_some() {
+        return convert_formats(&cmd, &pool, &input_root, &output_root, filter.as_deref());
+    }
+
     let t_all = Instant::now();
 
     let default_mzml_to_b64 =
@@ -403,14 +889,30 @@ fn convert(cmd: ConvertArgs) -> Result<(), String> {
         let out_ext = if mzml_to_b32 { "b32" } else { "b64" };
         let f32_compress = mzml_to_b32;
 
-        let files = collect_files_with_exts(&input_root, &["mzml"], filter.as_deref())?;
+        let files = collect_files_with_exts(
+            &input_root,
+            &["mzml", "mzml.gz", "mzxml", "mzxml.gz"],
+            filter.as_deref(),
+        )?;
         if files.is_empty() {
             return Err(format!(
-                "no matching .mzML files found under {}",
+                "no matching .mzML, .mzML.gz or .mzXML files found under {}",
                 input_root.display()
             ));
         }
 
+        if let Some(flag) = mzml_reading_flag(&cmd) {
+            if let Some(mzxml) = files
+                .iter()
+                .find(|f| file_ext_lower(f).starts_with("mzxml"))
+            {
+                return Err(format!(
+                    "{flag} is not supported for mzXML input ({}); convert mzXML without it",
+                    mzxml.display()
+                ));
+            }
+        }
+
         let total = files.len();
 
         pool.install(|| {
@@ -495,24 +997,14 @@ fn convert(cmd: ConvertArgs) -> Result<(), String> {
 
                 let t0 = Instant::now();
 
-                let bytes = match fs::read(in_path) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        had_failed.store(true, Ordering::Relaxed);
-                        failed.fetch_add(1, Ordering::Relaxed);
-                        let n = done.fetch_add(1, Ordering::Relaxed) + 1;
-                        let name = basename(in_path);
-                        let _g = print_lock.lock().unwrap_or_else(|e| e.into_inner());
-                        eprintln!(
-                            "{ANSI_RED}[error]{ANSI_RESET} [{}/{}] {}: read failed: {e}",
-                            n, total, name
-                        );
-                        let _ = stderr().flush();
-                        return;
-                    }
+                let in_mb = fs::metadata(in_path).map(|m| m.len() as f64 / MB).unwrap_or(0.0);
+                let warn = |message: String| {
+                    let name = basename(in_path);
+                    let _g = print_lock.lock().unwrap_or_else(|e| e.into_inner());
+                    eprintln!("{ANSI_YELLOW}[warn]{ANSI_RESET} {name}: {message}");
+                    let _ = stderr().flush();
                 };
-
-                let mzml = match parse_mzml(&bytes) {
+                let mut mzml = match read_mzml_input(in_path, &cmd, &warn) {
                     Ok(v) => v,
                     Err(e) => {
                         had_failed.store(true, Ordering::Relaxed);
@@ -521,16 +1013,16 @@ fn convert(cmd: ConvertArgs) -> Result<(), String> {
                         let name = basename(in_path);
                         let _g = print_lock.lock().unwrap_or_else(|e| e.into_inner());
                         eprintln!(
-                            "{ANSI_RED}[error]{ANSI_RESET} [{}/{}] {}: parse_mzml failed: {e}",
+                            "{ANSI_RED}[error]{ANSI_RESET} [{}/{}] {}: {e}",
                             n, total, name
                         );
                         let _ = stderr().flush();
                         return;
                     }
                 };
-
-                let in_mb = bytes.len() as f64 / MB;
-                drop(bytes);
+                if cmd.fix_refs {
+                    print_ref_fixes(&mut mzml, in_path, &print_lock);
+                }
 
                 let out_path_str = out_path.to_string_lossy();
                 let mut file_output = match FileEncoderOutput::open_for_writing(out_path_str.as_ref()) {
@@ -550,7 +1042,13 @@ fn convert(cmd: ConvertArgs) -> Result<(), String> {
                     }
                 };
 
-                if let Err(e) = encode(&mzml, cmd.compression_level, f32_compress, octo::encoder::encode::WritingMode::Streaming, &mut file_output) {
+                let config = EncodingConfig {
+                    compression_level: cmd.compression_level,
+                    force_f32: f32_compress,
+                    writing_mode: WritingMode::Streaming,
+                };
+                let checkpoints = cmd.checkpoints.then_some(DEFAULT_CHECKPOINT_INTERVAL_BYTES);
+                if let Err(e) = Encoder::new(&mut file_output, config).with_checkpoints(checkpoints).encode(&mzml) {
                     had_failed.store(true, Ordering::Relaxed);
                     failed.fetch_add(1, Ordering::Relaxed);
                     let n = done.fetch_add(1, Ordering::Relaxed) + 1;
@@ -576,7 +1074,7 @@ fn convert(cmd: ConvertArgs) -> Result<(), String> {
                 let n = done.fetch_add(1, Ordering::Relaxed) + 1;
 
                 let elapsed_s = t0.elapsed().as_secs_f64();
-                
+
                 let (tag, color) = if fixed_bad {
                     ("[fixed]", ANSI_BLUE)
                 } else {
@@ -617,6 +1115,7 @@ fn convert(cmd: ConvertArgs) -> Result<(), String> {
     }
 
     if b64_to_mzml {
+        let write_options = mzml_write_options(&cmd);
         let files = collect_files_with_exts(&input_root, &["b64", "b32"], filter.as_deref())?;
         if files.is_empty() {
             return Err(format!(
@@ -702,30 +1201,86 @@ fn convert(cmd: ConvertArgs) -> Result<(), String> {
 
                 let t0 = Instant::now();
 
-                let in_bytes = match fs::read(in_path) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        had_failed.store(true, Ordering::Relaxed);
-                        failed.fetch_add(1, Ordering::Relaxed);
-                        let n = done.fetch_add(1, Ordering::Relaxed) + 1;
-                        let name = basename(in_path);
-                        let _g = print_lock.lock().unwrap_or_else(|e| e.into_inner());
-                        eprintln!(
-                            "{ANSI_RED}[error]{ANSI_RESET} [{}/{}] {}: read failed: {e}",
-                            n, total, name
-                        );
-                        let _ = stderr().flush();
-                        return;
+                let in_mb = fs::metadata(in_path)
+                    .map(|m| m.len() as f64 / MB)
+                    .unwrap_or(0.0);
+                let ext = file_ext_lower(in_path);
+                let streamable =
+                    !cmd.salvage && !cmd.fix_refs && (ext == "b64" || ext == "b32");
+
+                let written = if streamable {
+                    // Read through the file handle, one spectrum at a time.
+                    fs::File::open(in_path)
+                        .map_err(|e| format!("read failed: {e}"))
+                        .and_then(|file| {
+                            write_output_file(&out_path, "write_mzml", |out| {
+                                write_b000_as_mzml(out, BufReader::new(file), &write_options)
+                            })
+                        })
+                } else {
+                    let in_bytes = match fs::read(in_path) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            had_failed.store(true, Ordering::Relaxed);
+                            failed.fetch_add(1, Ordering::Relaxed);
+                            let n = done.fetch_add(1, Ordering::Relaxed) + 1;
+                            let name = basename(in_path);
+                            let _g = print_lock.lock().unwrap_or_else(|e| e.into_inner());
+                            eprintln!(
+                                "{ANSI_RED}[error]{ANSI_RESET} [{}/{}] {}: read failed: {e}",
+                                n, total, name
+                            );
+                            let _ = stderr().flush();
+                            return;
+                        }
+                    };
+                    let decoded = if cmd.salvage {
+                        salvage_b000(&in_bytes)
+                            .map(|(mzml, report)| {
+                                if !report.complete {
+                                    let name = basename(in_path);
+                                    let _g = print_lock.lock().unwrap_or_else(|e| e.into_inner());
+                                    eprintln!("{ANSI_YELLOW}[warn]{ANSI_RESET} {name}: {report}");
+                                    let _ = stderr().flush();
+                                }
+                                mzml
+                            })
+                            .map_err(|e| format!("salvage failed: {e}"))
+                    } else {
+                        read_mzml_or_b64_from_bytes(in_path, &in_bytes)
+                    };
+                    let mut mzml = match decoded {
+                        Ok(v) => v,
+                        Err(e) => {
+                            had_failed.store(true, Ordering::Relaxed);
+                            failed.fetch_add(1, Ordering::Relaxed);
+                            let n = done.fetch_add(1, Ordering::Relaxed) + 1;
+                            let name = basename(in_path);
+                            let _g = print_lock.lock().unwrap_or_else(|e| e.into_inner());
+                            eprintln!(
+                                "{ANSI_RED}[error]{ANSI_RESET} [{}/{}] {}: {e}",
+                                n, total, name
+                            );
+                            let _ = stderr().flush();
+                            return;
+                        }
+                    };
+                    if cmd.fix_refs {
+                        print_ref_fixes(&mut mzml, in_path, &print_lock);
                     }
+                    drop(in_bytes);
+                    write_output_file(&out_path, "write_mzml", |out| {
+                        write_mzml_with_options(out, &mzml, &write_options)
+                    })
                 };
 
-                let mzml = match read_mzml_or_b64_from_bytes(in_path, &in_bytes) {
+                let out_len = match written {
                     Ok(v) => v,
                     Err(e) => {
                         had_failed.store(true, Ordering::Relaxed);
                         failed.fetch_add(1, Ordering::Relaxed);
                         let n = done.fetch_add(1, Ordering::Relaxed) + 1;
-                        let name = basename(in_path);
+                        let name = basename(&out_path);
                         let _g = print_lock.lock().unwrap_or_else(|e| e.into_inner());
                         eprintln!(
                             "{ANSI_RED}[error]{ANSI_RESET} [{}/{}] {}: {e}",
@@ -735,42 +1290,7 @@ fn convert(cmd: ConvertArgs) -> Result<(), String> {
                         return;
                     }
                 };
-                let in_mb = in_bytes.len() as f64 / MB;
-                drop(in_bytes);
-
-                let xml = match bin_to_mzml(&mzml) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        had_failed.store(true, Ordering::Relaxed);
-                        failed.fetch_add(1, Ordering::Relaxed);
-                        let n = done.fetch_add(1, Ordering::Relaxed) + 1;
-                        let name = basename(in_path);
-                        let _g = print_lock.lock().unwrap_or_else(|e| e.into_inner());
-                        eprintln!(
-                            "{ANSI_RED}[error]{ANSI_RESET} [{}/{}] {}: bin_to_mzml failed: {e}",
-                            n, total, name
-                        );
-                        let _ = stderr().flush();
-                        return;
-                    }
-                };
-                drop(mzml);
-
-                if let Err(e) = fs::write(&out_path, xml.as_bytes()) {
-                    had_failed.store(true, Ordering::Relaxed);
-                    failed.fetch_add(1, Ordering::Relaxed);
-                    let n = done.fetch_add(1, Ordering::Relaxed) + 1;
-                    let name = basename(&out_path);
-                    let _g = print_lock.lock().unwrap_or_else(|e| e.into_inner());
-                    eprintln!(
-                        "{ANSI_RED}[error]{ANSI_RESET} [{}/{}] {}: write failed: {e}",
-                        n, total, name
-                    );
-                    let _ = stderr().flush();
-                    return;
-                }
-                let out_mb = xml.len() as f64 / MB;
-                drop(xml);
+                let out_mb = out_len as f64 / MB;
 
                 ok.fetch_add(1, Ordering::Relaxed);
                 let n = done.fetch_add(1, Ordering::Relaxed) + 1;
@@ -812,6 +1332,376 @@ fn convert(cmd: ConvertArgs) -> Result<(), String> {
     Err("no convert mode selected".to_string())
 }
 
+/// Reads one mzML or mzXML input of `--mzml-to-b64`/`--mzml-to-b32`.
+/// Gzipped mzML is inflated while parsing rather than in memory first;
+/// plain mzML is read whole and its spectra parsed in parallel.
+fn read_mzml_input(path: &Path, cmd: &ConvertArgs, warn: &dyn Fn(String)) -> Result<MzML, String> {
+    let open = || fs::File::open(path).map_err(|e| format!("read failed: {e}"));
+    if file_ext_lower(path).starts_with("mzxml") {
+        let bytes = fs::read(path).map_err(|e| format!("read failed: {e}"))?;
+        return parse_mzxml(&bytes).map_err(|e| format!("parse_mzxml failed: {e}"));
+    }
+    fn parse_failed(e: impl std::fmt::Display) -> String {
+        format!("parse_mzml failed: {e}")
+    }
+    if cmd.salvage {
+        let salvaged = salvage_mzml_reader(open()?).map_err(parse_failed)?;
+        if let Some(e) = &salvaged.error {
+            warn(format!(
+                "truncated after byte {} ({e}); kept complete entries only",
+                salvaged.last_good_offset
+            ));
+        }
+        return Ok(salvaged.mzml);
+    }
+
+    let mut head = [0u8; 2];
+    let gzipped = open()?.read_exact(&mut head).is_ok() && is_gzip(&head);
+    let (mzml, report) = if cmd.preserve_unknown || cmd.lenient {
+        let options = ParseOptions {
+            strict: !cmd.lenient,
+            preserve_unknown: cmd.preserve_unknown,
+        };
+        if gzipped {
+            parse_mzml_reader_with_report(open()?, &options)
+        } else {
+            let bytes = fs::read(path).map_err(|e| format!("read failed: {e}"))?;
+            parse_mzml_with_report(&bytes, &options)
+        }
+        .map_err(parse_failed)?
+    } else if gzipped {
+        (
+            parse_mzml_reader(open()?).map_err(parse_failed)?,
+            ParseReport::default(),
+        )
+    } else {
+        let bytes = fs::read(path).map_err(|e| format!("read failed: {e}"))?;
+        (
+            parse_mzml_parallel(&bytes).map_err(parse_failed)?,
+            ParseReport::default(),
+        )
+    };
+    if cmd.lenient {
+        for warning in &report.warnings {
+            warn(warning.to_string());
+        }
+    }
+    Ok(mzml)
+}
+
+/// The first of --lenient, --preserve-unknown and --salvage that is set;
+/// they only change how mzML (and, for --salvage, .b64/.b32) is read.
+fn mzml_reading_flag(cmd: &ConvertArgs) -> Option<&'static str> {
+    [
+        (cmd.lenient, "--lenient"),
+        (cmd.preserve_unknown, "--preserve-unknown"),
+        (cmd.salvage, "--salvage"),
+    ]
+    .into_iter()
+    .find_map(|(set, flag)| set.then_some(flag))
+}
+
+fn mzml_write_options(cmd: &ConvertArgs) -> MzmlWriteOptions {
+    MzmlWriteOptions {
+        compression: match cmd.mzml_compression.as_str() {
+            "none" => ArrayCompression::None,
+            "zlib" => ArrayCompression::Zlib,
+            "numpress" => ArrayCompression::Numpress,
+            _ => ArrayCompression::Keep,
+        },
+        zlib_level: cmd.zlib_level,
+        precision: match cmd.mzml_precision.as_str() {
+            "f32" => ArrayPrecision::F32,
+            "f64" => ArrayPrecision::F64,
+            _ => ArrayPrecision::Keep,
+        },
+        indexed: !cmd.no_index,
+        pretty: !cmd.compact,
+        add_missing_cv_params: cmd.add_missing_cv,
+    }
+}
+
+/// Writes a new file at `path` through `write` and returns its size. Errors
+/// of `write` are prefixed with `what`. A partly written file is removed,
+/// so it is not skipped on the next run.
+fn write_output_file(
+    path: &Path,
+    what: &str,
+    write: impl FnOnce(BufWriter<fs::File>) -> Result<BufWriter<fs::File>, String>,
+) -> Result<u64, String> {
+    let file = fs::File::create(path).map_err(|e| format!("write failed: {e}"))?;
+    let written = write(BufWriter::new(file))
+        .map_err(|e| format!("{what} failed: {e}"))
+        .and_then(output_len);
+    if written.is_err() {
+        let _ = fs::remove_file(path);
+    }
+    written
+}
+
+/// Like `write_output_file`, for two files written together; both are
+/// removed if either fails.
+fn write_output_files(
+    paths: [&Path; 2],
+    what: &str,
+    write: impl FnOnce(
+        BufWriter<fs::File>,
+        BufWriter<fs::File>,
+    ) -> Result<(BufWriter<fs::File>, BufWriter<fs::File>), String>,
+) -> Result<[u64; 2], String> {
+    let create = |path: &Path| {
+        let file = fs::File::create(path).map_err(|e| format!("write failed: {e}"))?;
+        Ok::<_, String>(BufWriter::new(file))
+    };
+    let written = create(paths[0])
+        .and_then(|first| Ok((first, create(paths[1])?)))
+        .and_then(|(first, second)| write(first, second).map_err(|e| format!("{what} failed: {e}")))
+        .and_then(|(first, second)| Ok([output_len(first)?, output_len(second)?]));
+    if written.is_err() {
+        paths.iter().for_each(|path| {
+            let _ = fs::remove_file(path);
+        });
+    }
+    written
+}
+
+/// Flushes `out` and returns the length of its file.
+fn output_len(out: BufWriter<fs::File>) -> Result<u64, String> {
+    let file = out
+        .into_inner()
+        .map_err(|e| format!("write failed: {}", e.error()))?;
+    let meta = file.metadata().map_err(|e| format!("write failed: {e}"))?;
+    Ok(meta.len())
+}
+
+/// Conversions selected with `--from`/`--to`: mzML or .b64/.b32 to MGF,
+/// MS1/MS2, mzXML or imzML, and MGF, MS1/MS2, mzXML, imzML or ANDI-MS to any other format. Each input file becomes one
+/// output file next to its relative path under the output directory.
+fn convert_formats(
+    cmd: &ConvertArgs,
+    pool: &ThreadPool,
+    input_root: &Path,
+    output_root: &Path,
+    filter: Option<&dyn Fn(&str) -> bool>,
+) -> Result<(), String> {
+    const MB: f64 = 1024.0 * 1024.0;
+
+    let from = cmd.from.as_deref();
+    let to = cmd.to.as_deref().unwrap_or("b64");
+    let in_exts: &[&str] = match (from, to) {
+        (Some(from), to) if from == to => {
+            return Err(format!("--from {from} and --to {to} are the same format"));
+        }
+        (Some("mgf"), _) => &["mgf"],
+        (Some("ms1"), _) => &["ms1"],
+        (Some("ms2"), _) => &["ms2"],
+        (Some("imzml"), _) => &["imzml"],
+        (Some("andi"), _) => &["cdf"],
+        (Some("json"), _) => &["json", "ndjson", "jsonl"],
+        (Some(_), _) => &["mzxml", "mzxml.gz"],
+        (None, "mgf" | "ms1" | "ms2" | "mzxml" | "imzml") => &["mzml", "mzml.gz", "b64", "b32"],
+        (_, to) => {
+            return Err(format!(
+                "--to {to} needs --from; use --mzml-to-b64, --mzml-to-b32 or --b64-to-mzml for mzML and .b64/.b32"
+            ));
+        }
+    };
+    if let Some(flag) = mzml_reading_flag(cmd) {
+        return Err(format!(
+            "{flag} is not supported with --from/--to; use --mzml-to-b64, --mzml-to-b32 or --b64-to-mzml"
+        ));
+    }
+    let out_ext = match to {
+        "mzml" => "mzML",
+        "mzxml" => "mzXML",
+        "imzml" => "imzML",
+        to => to,
+    };
+
+    let files = collect_files_with_exts(input_root, in_exts, filter)?;
+    if files.is_empty() {
+        return Err(format!(
+            "no matching .{} files found under {}",
+            in_exts.join("/."),
+            input_root.display()
+        ));
+    }
+
+    let t_all = Instant::now();
+    let total = files.len();
+    let write_options = mzml_write_options(cmd);
+    let print_lock = Mutex::new(());
+    let done = AtomicUsize::new(0);
+    let ok = AtomicU32::new(0);
+    let failed = AtomicU32::new(0);
+    let skipped = AtomicU32::new(0);
+
+    pool.install(|| {
+        files.par_iter().for_each(|in_path| {
+            let rel = in_path.strip_prefix(input_root).unwrap_or(in_path);
+            let out_dir = output_root.join(rel.parent().unwrap_or_else(|| Path::new("")));
+            let name = basename(in_path);
+            let stem = &name[..name.len() - file_ext_lower(in_path).len() - 1];
+            let out_path = out_dir.join(format!("{stem}.{out_ext}"));
+
+            if !cmd.overwrite && fs::metadata(&out_path).is_ok_and(|m| m.is_file() && m.len() > 0) {
+                skipped.fetch_add(1, Ordering::Relaxed);
+                let n = done.fetch_add(1, Ordering::Relaxed) + 1;
+                let _g = print_lock.lock().unwrap_or_else(|e| e.into_inner());
+                println!("{ANSI_YELLOW}[skipped]{ANSI_RESET} [{}/{}] {}", n, total, basename(&out_path));
+                let _ = stdout().flush();
+                return;
+            }
+
+            let t0 = Instant::now();
+            let converted = fs::create_dir_all(&out_dir)
+                .map_err(|e| format!("create output dir failed: {e}"))
+                .and_then(|()| fs::metadata(in_path).map_err(|e| format!("read failed: {e}")))
+                .and_then(|meta| {
+                    let out_len = convert_format_file(cmd, in_path, &out_path, to, &write_options)?;
+                    Ok((meta.len(), out_len))
+                });
+
+            let n = done.fetch_add(1, Ordering::Relaxed) + 1;
+            let _g = print_lock.lock().unwrap_or_else(|e| e.into_inner());
+            match converted {
+                Ok((in_len, out_len)) => {
+                    ok.fetch_add(1, Ordering::Relaxed);
+                    println!(
+                        "{ANSI_GREEN}[ok]{ANSI_RESET} [{}/{}] output: {}  input={:.2} MB, output={:.2} MB, time={:.3}s",
+                        n,
+                        total,
+                        basename(&out_path),
+                        in_len as f64 / MB,
+                        out_len as f64 / MB,
+                        t0.elapsed().as_secs_f64()
+                    );
+                    let _ = stdout().flush();
+                }
+                Err(e) => {
+                    failed.fetch_add(1, Ordering::Relaxed);
+                    eprintln!("{ANSI_RED}[error]{ANSI_RESET} [{}/{}] {}: {e}", n, total, name);
+                    let _ = stderr().flush();
+                }
+            }
+        })
+    });
+
+    let ok = ok.load(Ordering::Relaxed);
+    let failed = failed.load(Ordering::Relaxed);
+    let skipped = skipped.load(Ordering::Relaxed);
+
+    let total_secs = t_all.elapsed().as_secs();
+    let h = total_secs / 3600;
+    let m = (total_secs % 3600) / 60;
+    let s = total_secs % 60;
+
+    println!(
+        "ok={ok} failed={failed} skipped={skipped} total_time={:02}:{:02}:{:02}",
+        h, m, s
+    );
+
+    if failed > 0 {
+        return Err("some files failed".to_string());
+    }
+    Ok(())
+}
+
+/// Converts one `--from`/`--to` input and returns the output size.
+fn convert_format_file(
+    cmd: &ConvertArgs,
+    in_path: &Path,
+    out_path: &Path,
+    to: &str,
+    write_options: &MzmlWriteOptions,
+) -> Result<u64, String> {
+    let ext = file_ext_lower(in_path);
+    let b000 = ext == "b64" || ext == "b32";
+    let imzml_options = ImzmlWriteOptions {
+        mode: match cmd.imzml_mode.as_str() {
+            "continuous" => ImzmlMode::Continuous,
+            _ => ImzmlMode::Processed,
+        },
+        ..Default::default()
+    };
+    if b000 && cmd.from.is_none() {
+        // B000 input is read through the file handle, one spectrum at a time.
+        let input =
+            BufReader::new(fs::File::open(in_path).map_err(|e| format!("read failed: {e}"))?);
+        return match to {
+            "mgf" => write_output_file(out_path, "write_mgf", |out| write_b000_as_mgf(out, input)),
+            "ms1" | "ms2" => {
+                let format = msn_format(to);
+                write_output_file(out_path, "write_msn", |out| {
+                    write_b000_as_msn(out, input, format)
+                })
+            }
+            "imzml" => write_imzml_files(out_path, |xml, ibd| {
+                write_b000_as_imzml(xml, ibd, input, &imzml_options)
+            }),
+            _ => write_output_file(out_path, "write_mzxml", |out| {
+                write_b000_as_mzxml(out, input)
+            }),
+        };
+    }
+    let bytes = &fs::read(in_path).map_err(|e| format!("read failed: {e}"))?;
+    let mzml =
+        match cmd.from.as_deref() {
+            Some("mgf") => parse_mgf(bytes, &basename(in_path))
+                .map_err(|e| format!("parse_mgf failed: {e}"))?,
+            Some("ms1" | "ms2") => parse_msn(bytes, &basename(in_path))
+                .map_err(|e| format!("parse_msn failed: {e}"))?,
+            Some("imzml") => read_imzml(in_path, bytes)?,
+            Some("andi") => parse_andi(bytes, &basename(in_path))
+                .map_err(|e| format!("parse_andi failed: {e}"))?,
+            Some("json") => parse_json(bytes, &basename(in_path))
+                .map_err(|e| format!("parse_json failed: {e}"))?,
+            Some(_) => parse_mzxml(bytes).map_err(|e| format!("parse_mzxml failed: {e}"))?,
+            None => read_mzml_or_b64_from_bytes(in_path, bytes)?,
+        };
+    match to {
+        "mgf" => return write_output_file(out_path, "write_mgf", |out| write_mgf(out, &mzml)),
+        "ms1" | "ms2" => {
+            return write_output_file(out_path, "write_msn", |out| {
+                write_msn(out, &mzml, msn_format(to))
+            });
+        }
+        "mzxml" => {
+            return write_output_file(out_path, "write_mzxml", |out| write_mzxml(out, &mzml));
+        }
+        "imzml" => {
+            return write_imzml_files(out_path, |xml, ibd| {
+                write_imzml(xml, ibd, &mzml, &imzml_options)
+            });
+        }
+        "mzml" => {
+            return write_output_file(out_path, "write_mzml", |out| {
+                write_mzml_with_options(out, &mzml, write_options)
+            });
+        }
+        _ => {}
+    }
+
+    let mut file_output = FileEncoderOutput::open_for_writing(out_path.to_string_lossy().as_ref())?;
+    let config = EncodingConfig {
+        compression_level: cmd.compression_level,
+        force_f32: to == "b32",
+        writing_mode: WritingMode::Streaming,
+    };
+    let checkpoints = cmd.checkpoints.then_some(DEFAULT_CHECKPOINT_INTERVAL_BYTES);
+    let encoded = Encoder::new(&mut file_output, config)
+        .with_checkpoints(checkpoints)
+        .encode(&mzml);
+    drop(file_output);
+    if let Err(e) = encoded {
+        let _ = fs::remove_file(out_path);
+        return Err(format!("encode failed: {e}"));
+    }
+    fs::metadata(out_path)
+        .map(|m| m.len())
+        .map_err(|e| format!("write failed: {e}"))
+}
+
 fn read_mzml_or_b64_from_bytes(file_path: &Path, bytes: &[u8]) -> Result<MzML, String> {
     let ext = file_ext_lower(file_path);
 
@@ -821,12 +1711,73 @@ fn read_mzml_or_b64_from_bytes(file_path: &Path, bytes: &[u8]) -> Result<MzML, S
     if ext == "mzml" {
         return parse_mzml(bytes).map_err(|e| format!("parse_mzml failed: {e}"));
     }
+    if ext == "mzml.gz" {
+        return parse_mzml_reader(bytes).map_err(|e| format!("parse_mzml failed: {e}"));
+    }
+    if ext == "mzxml" || ext == "mzxml.gz" {
+        return parse_mzxml(bytes).map_err(|e| format!("parse_mzxml failed: {e}"));
+    }
+    if ext == "imzml" {
+        return read_imzml(file_path, bytes);
+    }
+    if ext == "cdf" {
+        return parse_andi(bytes, &basename(file_path))
+            .map_err(|e| format!("parse_andi failed: {e}"));
+    }
 
     Err(format!(
-        "unsupported file extension: {ext:?} (expected .mzML or .b64/.b32)"
+        "unsupported file extension: {ext:?} (expected .mzML, .mzML.gz, .mzXML, .imzML, .cdf or .b64/.b32)"
     ))
 }
 
+fn msn_format(ext: &str) -> MsnFormat {
+    if ext == "ms1" {
+        MsnFormat::Ms1
+    } else {
+        MsnFormat::Ms2
+    }
+}
+
+/// Parses an imzML file with the arrays read from the `.ibd` file next to it.
+fn read_imzml(file_path: &Path, bytes: &[u8]) -> Result<MzML, String> {
+    let ibd_path = file_path.with_extension("ibd");
+    let ibd = fs::File::open(&ibd_path)
+        .map_err(|e| format!("read {} failed: {e}", ibd_path.display()))?;
+    parse_imzml(bytes, BufReader::new(ibd)).map_err(|e| format!("parse_imzml failed: {e}"))
+}
+
+/// Writes `path` and the `.ibd` file next to it through `write` and returns
+/// their total size. Errors remove both files.
+fn write_imzml_files(
+    path: &Path,
+    write: impl FnOnce(
+        BufWriter<fs::File>,
+        BufWriter<fs::File>,
+    ) -> Result<(BufWriter<fs::File>, BufWriter<fs::File>), String>,
+) -> Result<u64, String> {
+    let ibd_path = path.with_extension("ibd");
+    let mut ibd_len = 0;
+    let written = write_output_file(path, "write_imzml", |xml| {
+        let ibd = fs::File::create(&ibd_path).map_err(|e| format!("write failed: {e}"))?;
+        let (xml, ibd) = write(xml, BufWriter::new(ibd))?;
+        let ibd = ibd
+            .into_inner()
+            .map_err(|e| format!("write failed: {}", e.error()))?;
+        ibd_len = ibd
+            .metadata()
+            .map_err(|e| format!("write failed: {e}"))?
+            .len();
+        Ok(xml)
+    });
+    match written {
+        Ok(len) => Ok(len + ibd_len),
+        Err(e) => {
+            let _ = fs::remove_file(&ibd_path);
+            Err(e)
+        }
+    }
+}
+
 fn resolve_user_path(cwd: &Path, p: &Path) -> PathBuf {
     if p.is_absolute() {
         p.to_path_buf()
@@ -874,4 +1825,4 @@ fn trim_mzml_for_cat(mzml: &mut MzML) {
     if let Some(c) = mzml.run.chromatogram_list.as_mut() {
         c.chromatograms.clear();
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,3 @@
+pub(crate) mod netcdf;
+pub mod parse_andi;
+pub use parse_andi::parse_andi;
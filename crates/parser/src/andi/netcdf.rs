@@ -0,0 +1,376 @@
+//! Reader for netCDF classic and 64-bit offset files, the container of
+//! ANDI-MS (AIA) data.
+
+const NC_DIMENSION: u32 = 0x0A;
+const NC_VARIABLE: u32 = 0x0B;
+const NC_ATTRIBUTE: u32 = 0x0C;
+/// `numrecs` of a file still being written.
+const STREAMING: u32 = u32::MAX;
+
+/// Values of an attribute or variable; `byte` and `char` data are kept as
+/// raw bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Values {
+    Bytes(Vec<u8>),
+    Short(Vec<i16>),
+    Int(Vec<i32>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+}
+
+impl Values {
+    pub(crate) fn to_f64(&self) -> Vec<f64> {
+        match self {
+            Values::Bytes(v) => v.iter().map(|&b| f64::from(b as i8)).collect(),
+            Values::Short(v) => v.iter().map(|&x| f64::from(x)).collect(),
+            Values::Int(v) => v.iter().map(|&x| f64::from(x)).collect(),
+            Values::Float(v) => v.iter().map(|&x| f64::from(x)).collect(),
+            Values::Double(v) => v.clone(),
+        }
+    }
+
+    /// `char` data up to the first NUL, with surrounding whitespace removed.
+    pub(crate) fn text(&self) -> Option<&str> {
+        let Values::Bytes(bytes) = self else {
+            return None;
+        };
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        Some(std::str::from_utf8(&bytes[..end]).ok()?.trim())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Dimension {
+    /// 0 for the record (unlimited) dimension.
+    pub(crate) len: usize,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Attribute {
+    pub(crate) name: String,
+    pub(crate) values: Values,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Variable {
+    pub(crate) name: String,
+    pub(crate) dim_ids: Vec<usize>,
+    pub(crate) attributes: Vec<Attribute>,
+    nc_type: u32,
+    begin: u64,
+}
+
+impl Variable {
+    pub(crate) fn attribute(&self, name: &str) -> Option<&Values> {
+        find_attribute(&self.attributes, name)
+    }
+}
+
+fn find_attribute<'a>(attributes: &'a [Attribute], name: &str) -> Option<&'a Values> {
+    attributes
+        .iter()
+        .find(|a| a.name == name)
+        .map(|a| &a.values)
+}
+
+/// Header of a netCDF file, with the file to read variables from.
+#[derive(Debug)]
+pub(crate) struct NetCdf<'a> {
+    bytes: &'a [u8],
+    num_records: usize,
+    pub(crate) dimensions: Vec<Dimension>,
+    pub(crate) attributes: Vec<Attribute>,
+    pub(crate) variables: Vec<Variable>,
+}
+
+impl<'a> NetCdf<'a> {
+    pub(crate) fn parse(bytes: &'a [u8]) -> Result<Self, String> {
+        let offset64 = match bytes.get(..4) {
+            Some(b"CDF\x01") => false,
+            Some(b"CDF\x02") => true,
+            Some([b'C', b'D', b'F', 5]) => {
+                return Err("netCDF-5 (CDF5) files are not supported".to_string());
+            }
+            Some([0x89, b'H', b'D', b'F']) => {
+                return Err("netCDF-4 (HDF5) files are not supported".to_string());
+            }
+            _ => return Err("not a netCDF file".to_string()),
+        };
+        let mut header = Header { bytes, pos: 4 };
+        let num_records = header.u32()?;
+
+        let dimensions = header.list(NC_DIMENSION, |h| {
+            h.name()?;
+            Ok(Dimension {
+                len: h.u32()? as usize,
+            })
+        })?;
+        let attributes = header.list(NC_ATTRIBUTE, Header::attribute)?;
+        let variables = header.list(NC_VARIABLE, |h| {
+            let name = h.name()?;
+            let ndims = h.u32()? as usize;
+            let dim_ids = (0..ndims)
+                .map(|_| {
+                    let id = h.u32()? as usize;
+                    if id >= dimensions.len() {
+                        return Err(format!("variable {name}: unknown dimension {id}"));
+                    }
+                    Ok(id)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let attributes = h.list(NC_ATTRIBUTE, Header::attribute)?;
+            let nc_type = h.u32()?;
+            type_size(nc_type)?;
+            // vsize overflows for variables over 4 GiB and is recomputed.
+            h.u32()?;
+            let begin = if offset64 {
+                h.u64()?
+            } else {
+                u64::from(h.u32()?)
+            };
+            Ok(Variable {
+                name,
+                dim_ids,
+                attributes,
+                nc_type,
+                begin,
+            })
+        })?;
+
+        let mut cdf = NetCdf {
+            bytes,
+            num_records: num_records as usize,
+            dimensions,
+            attributes,
+            variables,
+        };
+        if num_records == STREAMING {
+            let record_size = cdf.record_size();
+            let first = cdf
+                .variables
+                .iter()
+                .filter(|v| cdf.is_record(v))
+                .map(|v| v.begin as usize)
+                .min();
+            cdf.num_records = match first {
+                Some(first) if record_size > 0 => bytes.len().saturating_sub(first) / record_size,
+                _ => 0,
+            };
+        }
+        Ok(cdf)
+    }
+
+    pub(crate) fn attribute(&self, name: &str) -> Option<&Values> {
+        find_attribute(&self.attributes, name)
+    }
+
+    pub(crate) fn variable(&self, name: &str) -> Option<&Variable> {
+        self.variables.iter().find(|v| v.name == name)
+    }
+
+    fn is_record(&self, variable: &Variable) -> bool {
+        variable
+            .dim_ids
+            .first()
+            .is_some_and(|&id| self.dimensions[id].len == 0)
+    }
+
+    /// Bytes of `variable` per record, or in total for a fixed-size one.
+    fn slab_size(&self, variable: &Variable) -> usize {
+        let dims = &variable.dim_ids[usize::from(self.is_record(variable))..];
+        let size = type_size(variable.nc_type).unwrap_or(1);
+        dims.iter()
+            .fold(size, |n, &id| n.saturating_mul(self.dimensions[id].len))
+    }
+
+    /// Bytes between the starts of two records. Each variable's part is
+    /// padded to 4 bytes unless there is a single record variable.
+    fn record_size(&self) -> usize {
+        let record: Vec<_> = self
+            .variables
+            .iter()
+            .filter(|v| self.is_record(v))
+            .collect();
+        match record[..] {
+            [single] => self.slab_size(single),
+            _ => record.iter().map(|v| padded(self.slab_size(v))).sum(),
+        }
+    }
+
+    /// All values of `variable`, records concatenated.
+    pub(crate) fn values(&self, variable: &Variable) -> Result<Values, String> {
+        let slab = self.slab_size(variable);
+        let out_of_range = || {
+            format!(
+                "variable {}: data is past the end of the file",
+                variable.name
+            )
+        };
+        let begin = variable.begin as usize;
+        let data = if self.is_record(variable) {
+            let record_size = self.record_size();
+            let mut data =
+                Vec::with_capacity(slab.saturating_mul(self.num_records).min(self.bytes.len()));
+            for record in (0..self.num_records).take_while(|_| slab > 0) {
+                let start = record.saturating_mul(record_size).saturating_add(begin);
+                let end = start.saturating_add(slab);
+                data.extend_from_slice(self.bytes.get(start..end).ok_or_else(out_of_range)?);
+            }
+            data
+        } else {
+            let end = begin.saturating_add(slab);
+            self.bytes
+                .get(begin..end)
+                .ok_or_else(out_of_range)?
+                .to_vec()
+        };
+        decode(variable.nc_type, &data)
+    }
+
+    /// Values of the variable `name`, as `f64`.
+    pub(crate) fn values_f64(&self, name: &str) -> Result<Option<Vec<f64>>, String> {
+        match self.variable(name) {
+            Some(variable) => Ok(Some(self.values(variable)?.to_f64())),
+            None => Ok(None),
+        }
+    }
+}
+
+fn padded(len: usize) -> usize {
+    len.div_ceil(4) * 4
+}
+
+fn type_size(nc_type: u32) -> Result<usize, String> {
+    match nc_type {
+        1 | 2 => Ok(1),
+        3 => Ok(2),
+        4 | 5 => Ok(4),
+        6 => Ok(8),
+        other => Err(format!("unknown netCDF type {other}")),
+    }
+}
+
+/// Big-endian `data` of type `nc_type`.
+fn decode(nc_type: u32, data: &[u8]) -> Result<Values, String> {
+    fn chunks<const N: usize, T>(data: &[u8], f: fn([u8; N]) -> T) -> Vec<T> {
+        data.chunks_exact(N)
+            .map(|c| f(c.try_into().unwrap()))
+            .collect()
+    }
+    Ok(match nc_type {
+        1 | 2 => Values::Bytes(data.to_vec()),
+        3 => Values::Short(chunks(data, i16::from_be_bytes)),
+        4 => Values::Int(chunks(data, i32::from_be_bytes)),
+        5 => Values::Float(chunks(data, f32::from_be_bytes)),
+        6 => Values::Double(chunks(data, f64::from_be_bytes)),
+        other => return Err(format!("unknown netCDF type {other}")),
+    })
+}
+
+struct Header<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Header<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], String> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len());
+        let end = end.ok_or_else(|| "unexpected end of the netCDF header".to_string())?;
+        let taken = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        let name = String::from_utf8_lossy(self.take(len)?).into_owned();
+        self.take(padded(len) - len)?;
+        Ok(name)
+    }
+
+    /// `ABSENT` or a `tag`ged list of `nelems` items.
+    fn list<T>(
+        &mut self,
+        tag: u32,
+        mut item: impl FnMut(&mut Self) -> Result<T, String>,
+    ) -> Result<Vec<T>, String> {
+        let (found, count) = (self.u32()?, self.u32()?);
+        match found {
+            0 if count == 0 => Ok(Vec::new()),
+            found if found == tag => (0..count).map(|_| item(self)).collect(),
+            _ => Err(format!(
+                "expected netCDF list tag {tag:#x}, found {found:#x}"
+            )),
+        }
+    }
+
+    fn attribute(&mut self) -> Result<Attribute, String> {
+        let name = self.name()?;
+        let nc_type = self.u32()?;
+        let count = self.u32()? as usize;
+        let len = count
+            .checked_mul(type_size(nc_type)?)
+            .ok_or_else(|| format!("attribute {name} is too large"))?;
+        let values = decode(nc_type, self.take(len)?)?;
+        self.take(padded(len) - len)?;
+        Ok(Attribute { name, values })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_fixed_and_record_variables() {
+        let bytes = std::fs::read("data/andi/tiny_gcms.cdf").unwrap();
+        let cdf = NetCdf::parse(&bytes).unwrap();
+        assert_eq!(cdf.dimensions[2].len, 0);
+        assert_eq!(
+            cdf.attribute("experiment_title").and_then(Values::text),
+            Some("tiny GC-MS")
+        );
+
+        let scan_index = cdf.variable("scan_index").unwrap();
+        assert_eq!(cdf.values(scan_index).unwrap(), Values::Int(vec![0, 3, 3]));
+        // mass_values and intensity_values are interleaved record variables.
+        let mass = cdf.variable("mass_values").unwrap();
+        assert_eq!(
+            cdf.values(mass).unwrap(),
+            Values::Short(vec![500, 515, 730, 1000, 2071])
+        );
+        assert_eq!(
+            mass.attribute("scale_factor").map(Values::to_f64),
+            Some(vec![0.1])
+        );
+        assert_eq!(
+            cdf.values_f64("intensity_values").unwrap(),
+            Some(vec![100.0, 200.0, 50.0, 10.0, 20.0])
+        );
+    }
+
+    #[test]
+    fn rejects_other_formats_and_truncated_headers() {
+        assert!(NetCdf::parse(b"<?xml").is_err());
+        assert!(NetCdf::parse(b"\x89HDF\r\n").unwrap_err().contains("HDF5"));
+        let bytes = std::fs::read("data/andi/tiny_gcms.cdf").unwrap();
+        assert!(NetCdf::parse(&bytes[..100]).is_err());
+
+        // numrecs of a file still being written comes from its length.
+        let mut streaming = bytes.clone();
+        streaming[4..8].copy_from_slice(&[0xFF; 4]);
+        let cdf = NetCdf::parse(&streaming).unwrap();
+        assert_eq!(cdf.values_f64("mass_values").unwrap().unwrap().len(), 5);
+    }
+}
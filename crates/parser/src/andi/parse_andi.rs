@@ -0,0 +1,320 @@
+use crate::{
+    andi::netcdf::{NetCdf, Values},
+    mzml::{
+        structs::*,
+        utilities::peak_list::{
+            component_list, data_array, ms_cv, peak_list_mzml, peak_list_spectrum,
+            set_scan_start_seconds,
+        },
+    },
+    mzxml::parse_mzxml::lookup,
+};
+
+const ACC_TOTAL_ION_CURRENT: &str = "MS:1000285";
+const ACC_CENTROID_SPECTRUM: &str = "MS:1000127";
+const ACC_PROFILE_SPECTRUM: &str = "MS:1000128";
+
+/// `test_ionization_mode` values and their ionization type terms.
+const IONIZATION_TERMS: [(&str, &str, &str); 12] = [
+    ("Electron Impact", "MS:1000389", "electron ionization"),
+    ("Chemical Ionization", "MS:1000071", "chemical ionization"),
+    (
+        "Fast Atom Bombardment",
+        "MS:1000074",
+        "fast atom bombardment ionization",
+    ),
+    ("Field Desorption", "MS:1000257", "field desorption"),
+    ("Field Ionization", "MS:1000258", "field ionization"),
+    ("Electrospray", "MS:1000073", "electrospray ionization"),
+    (
+        "APCI",
+        "MS:1000070",
+        "atmospheric pressure chemical ionization",
+    ),
+    (
+        "Plasma Desorption",
+        "MS:1000400",
+        "plasma desorption ionization",
+    ),
+    (
+        "Laser Desorption",
+        "MS:1000393",
+        "laser desorption ionization",
+    ),
+    ("Spark Ionization", "MS:1000404", "spark ionization"),
+    ("Thermal Ionization", "MS:1000407", "thermal ionization"),
+    ("Surface Ionization", "MS:1000406", "surface ionization"),
+];
+
+/// `test_detector_type` values and their detector type terms.
+const DETECTOR_TERMS: [(&str, &str, &str); 7] = [
+    ("Electron Multiplier", "MS:1000253", "electron multiplier"),
+    ("Photomultiplier", "MS:1000116", "photomultiplier"),
+    ("Focal Plane Array", "MS:1000113", "focal plane array"),
+    ("Faraday Cup", "MS:1000112", "faraday cup"),
+    (
+        "Conversion Dynode Electron Multiplier",
+        "MS:1000108",
+        "conversion dynode electron multiplier",
+    ),
+    (
+        "Conversion Dynode Photomultiplier",
+        "MS:1000109",
+        "conversion dynode photomultiplier",
+    ),
+    ("Multicollector", "MS:1000115", "multi-collector"),
+];
+
+/// Reads an ANDI-MS (AIA) netCDF file into a document with one MS1
+/// spectrum per scan and a TIC chromatogram. `name` is the file name, kept
+/// as the source file; its stem becomes the run id.
+///
+/// Scan `i` becomes the spectrum `scan=<i + 1>` with the `point_count`
+/// points from `scan_index` of `mass_values` and `intensity_values` (scaled
+/// by their `scale_factor`), `scan_acquisition_time` as the scan start time
+/// and `total_intensity` as the total ion current, summed from the points
+/// when absent. Spectra are centroided unless `experiment_type` says
+/// continuum. `test_ionization_mode` and `test_detector_type` give the
+/// types of the instrument's source and detector when they have a term.
+pub fn parse_andi(bytes: &[u8], name: &str) -> Result<MzML, String> {
+    let cdf = NetCdf::parse(bytes)?;
+    let required = |name: &str| {
+        cdf.values_f64(name)?
+            .ok_or_else(|| format!("missing ANDI-MS variable {name}"))
+    };
+    let mz = scaled(&cdf, "mass_values", required("mass_values")?);
+    let intensity = scaled(&cdf, "intensity_values", required("intensity_values")?);
+    if mz.len() != intensity.len() {
+        return Err(format!(
+            "mass_values has {} points but intensity_values has {}",
+            mz.len(),
+            intensity.len()
+        ));
+    }
+    let scan_index = required("scan_index")?;
+    let point_count = cdf.values_f64("point_count")?;
+    let total_intensity = cdf.values_f64("total_intensity")?;
+    let times = match cdf.variable("scan_acquisition_time") {
+        Some(variable) => {
+            let minutes = variable
+                .attribute("units")
+                .and_then(Values::text)
+                .is_some_and(|unit| unit.to_ascii_lowercase().starts_with("min"));
+            let scale = if minutes { 60.0 } else { 1.0 };
+            let times = cdf.values(variable)?.to_f64();
+            times.into_iter().map(|t| t * scale).collect()
+        }
+        None => Vec::new(),
+    };
+    let continuum = cdf
+        .attribute("experiment_type")
+        .and_then(Values::text)
+        .is_some_and(|t| t.to_ascii_lowercase().contains("continuum"));
+
+    let mut spectra = Vec::with_capacity(scan_index.len());
+    for (i, &start) in scan_index.iter().enumerate() {
+        let start = start as usize;
+        let end = match point_count.as_ref().and_then(|counts| counts.get(i)) {
+            Some(&count) => start.saturating_add(count as usize),
+            None => scan_index
+                .get(i + 1)
+                .map_or(mz.len(), |&next| next as usize),
+        };
+        if start > end || end > mz.len() {
+            return Err(format!(
+                "scan {}: points {start}..{end} are out of range ({} points)",
+                i + 1,
+                mz.len()
+            ));
+        }
+        let peaks = (mz[start..end].to_vec(), intensity[start..end].to_vec());
+        let tic = total_intensity
+            .as_ref()
+            .and_then(|t| t.get(i).copied())
+            .unwrap_or_else(|| peaks.1.iter().sum());
+
+        let mut spectrum = peak_list_spectrum(i, 1, peaks.0, peaks.1);
+        spectrum.id = format!("scan={}", i + 1);
+        if continuum {
+            let params = spectrum.cv_params.iter_mut();
+            let centroid = params.filter(|p| p.accession.as_deref() == Some(ACC_CENTROID_SPECTRUM));
+            for param in centroid {
+                *param = ms_cv(ACC_PROFILE_SPECTRUM, "profile spectrum", None);
+            }
+        }
+        spectrum.cv_params.push(ms_cv(
+            ACC_TOTAL_ION_CURRENT,
+            "total ion current",
+            Some(tic.to_string()),
+        ));
+        if let Some(&seconds) = times.get(i) {
+            set_scan_start_seconds(&mut spectrum, seconds);
+        }
+        spectra.push(spectrum);
+    }
+
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let source = SourceFile {
+        id: "ANDI".to_string(),
+        name: name.to_string(),
+        location: "file://".to_string(),
+        cv_param: vec![
+            ms_cv("MS:1002441", "Andi-MS format", None),
+            ms_cv("MS:1000776", "scan number only nativeID format", None),
+        ],
+        ..Default::default()
+    };
+    let tic = tic_chromatogram(&spectra, &times);
+    let mut mzml = peak_list_mzml(stem, vec![source], spectra);
+    let term = |attribute: &str, terms: &[(&str, &str, &str)]| {
+        let value = cdf.attribute(attribute).and_then(Values::text)?;
+        let (accession, name) = lookup(terms, value.trim())?;
+        Some(ms_cv(accession, name, None))
+    };
+    let instrument = &mut mzml.instrument_list.as_mut().unwrap().instrument[0];
+    instrument.component_list = Some(component_list(
+        term("test_ionization_mode", &IONIZATION_TERMS)
+            .into_iter()
+            .collect(),
+        vec![],
+        term("test_detector_type", &DETECTOR_TERMS)
+            .into_iter()
+            .collect(),
+    ));
+    mzml.run.chromatogram_list = Some(ChromatogramList {
+        count: Some(1),
+        default_data_processing_ref: Some("octo_conversion".to_string()),
+        chromatograms: vec![tic],
+    });
+    Ok(mzml)
+}
+
+/// `values` of `variable` times its `scale_factor`.
+fn scaled(cdf: &NetCdf, variable: &str, mut values: Vec<f64>) -> Vec<f64> {
+    let scale = cdf
+        .variable(variable)
+        .and_then(|v| v.attribute("scale_factor"))
+        .and_then(|s| s.to_f64().first().copied())
+        .filter(|&s| s != 1.0 && s != 0.0);
+    if let Some(scale) = scale {
+        values.iter_mut().for_each(|v| *v *= scale);
+    }
+    values
+}
+
+/// TIC of `spectra` against the scan times, scans without one left out.
+fn tic_chromatogram(spectra: &[Spectrum], times: &[f64]) -> Chromatogram {
+    let (time, intensity): (Vec<f64>, Vec<f64>) = spectra
+        .iter()
+        .zip(times)
+        .map(|(spectrum, &time)| {
+            let tic = spectrum
+                .cv_params
+                .iter()
+                .find(|p| p.accession.as_deref() == Some(ACC_TOTAL_ION_CURRENT))
+                .and_then(|p| p.value.as_deref()?.parse().ok());
+            (time, tic.unwrap_or(0.0))
+        })
+        .unzip();
+    Chromatogram {
+        id: "TIC".to_string(),
+        index: Some(0),
+        default_array_length: Some(time.len()),
+        cv_params: vec![ms_cv("MS:1000235", "total ion current chromatogram", None)],
+        binary_data_array_list: Some(BinaryDataArrayList {
+            count: Some(2),
+            binary_data_arrays: vec![
+                data_array(
+                    "MS:1000595",
+                    "time array",
+                    ("UO:0000010", "second"),
+                    BinaryData::F64(time),
+                    false,
+                ),
+                data_array(
+                    "MS:1000515",
+                    "intensity array",
+                    ("MS:1000131", "number of detector counts"),
+                    BinaryData::F64(intensity),
+                    false,
+                ),
+            ],
+        }),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        b64::{decode, encode, encoder::encode::WritingMode},
+        mzml::utilities::peak_list::{
+            ACC_INTENSITY_ARRAY, ACC_MZ_ARRAY, array_f64, scan_start_seconds,
+        },
+    };
+
+    fn chromatogram_f64(chromatogram: &Chromatogram, index: usize) -> Vec<f64> {
+        let arrays = &chromatogram.binary_data_array_list.as_ref().unwrap();
+        match arrays.binary_data_arrays[index].binary.as_ref().unwrap() {
+            BinaryData::F64(v) => v.clone(),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn maps_scans_to_spectra_and_a_tic() {
+        let bytes = std::fs::read("data/andi/tiny_gcms.cdf").unwrap();
+        let mzml = parse_andi(&bytes, "tiny_gcms.cdf").unwrap();
+        assert_eq!(mzml.run.id, "tiny_gcms");
+
+        let spectra = &mzml.run.spectrum_list.as_ref().unwrap().spectra;
+        assert_eq!(spectra.len(), 3);
+        assert_eq!(spectra[0].id, "scan=1");
+        let mz = array_f64(&spectra[0], ACC_MZ_ARRAY).unwrap();
+        assert!(
+            mz.iter()
+                .zip([50.0, 51.5, 73.0])
+                .all(|(a, b)| (a - b).abs() < 1e-9)
+        );
+        assert_eq!(
+            array_f64(&spectra[0], ACC_INTENSITY_ARRAY).unwrap(),
+            [100.0, 200.0, 50.0]
+        );
+        assert_eq!(spectra[1].default_array_length, Some(0));
+        assert_eq!(
+            array_f64(&spectra[2], ACC_INTENSITY_ARRAY).unwrap(),
+            [10.0, 20.0]
+        );
+        assert_eq!(scan_start_seconds(&spectra[2]), Some(2.5));
+
+        let tic = &mzml.run.chromatogram_list.as_ref().unwrap().chromatograms[0];
+        assert_eq!(chromatogram_f64(tic, 0), [1.5, 2.0, 2.5]);
+        assert_eq!(chromatogram_f64(tic, 1), [350.0, 0.0, 30.0]);
+
+        let mut encoded = Vec::new();
+        encode(&mzml, 12, false, WritingMode::Memory, &mut encoded).unwrap();
+        let decoded = decode(&encoded).unwrap();
+        let spectra = &decoded.run.spectrum_list.as_ref().unwrap().spectra;
+        assert_eq!(
+            array_f64(&spectra[0], ACC_INTENSITY_ARRAY).unwrap(),
+            [100.0, 200.0, 50.0]
+        );
+        assert_eq!(
+            decoded.run.chromatogram_list.unwrap().chromatograms.len(),
+            1
+        );
+    }
+
+    #[test]
+    fn rejects_scans_past_the_points() {
+        let mut bytes = std::fs::read("data/andi/tiny_gcms.cdf").unwrap();
+        // The last point_count, 2, right before the 5 records of 8 bytes,
+        // becomes 3.
+        let at = bytes.len() - 5 * 8 - 4;
+        assert_eq!(bytes[at..at + 4], [0, 0, 0, 2]);
+        bytes[at + 3] = 3;
+        let err = parse_andi(&bytes, "bad.cdf").unwrap_err();
+        assert!(err.contains("scan 3"), "{err}");
+    }
+}
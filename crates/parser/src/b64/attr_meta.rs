@@ -7,6 +7,7 @@ pub(crate) const CV_CODE_UO: u8 = 1;
 pub(crate) const CV_CODE_NCIT: u8 = 2;
 pub(crate) const CV_CODE_PEFF: u8 = 3;
 pub(crate) const CV_CODE_B000: u8 = 4;
+pub(crate) const CV_CODE_IMS: u8 = 5;
 pub(crate) const CV_CODE_UNKNOWN: u8 = 255;
 
 #[inline]
@@ -17,6 +18,7 @@ pub(crate) fn cv_ref_code_from_str(cv_ref: Option<&str>) -> u8 {
         Some("NCIT") => CV_CODE_NCIT,
         Some("PEFF") => CV_CODE_PEFF,
         Some(CV_REF_ATTR) => CV_CODE_B000,
+        Some("IMS") => CV_CODE_IMS,
         _ => CV_CODE_UNKNOWN,
     }
 }
@@ -29,6 +31,7 @@ pub(crate) fn cv_ref_prefix_from_code(code: u8) -> Option<&'static str> {
         CV_CODE_NCIT => Some("NCIT"),
         CV_CODE_PEFF => Some("PEFF"),
         CV_CODE_B000 => Some(CV_REF_ATTR),
+        CV_CODE_IMS => Some("IMS"),
         _ => None,
     }
 }
@@ -42,6 +45,7 @@ pub(crate) fn format_accession(cv_ref_code: u8, tail_raw: u32) -> Option<String>
             normalize_ms_accession_tail(cv_ref_code, tail_raw)
         )),
         "UO" => Some(format!("UO:{tail_raw:07}")),
+        "IMS" => Some(format!("IMS:{tail_raw:07}")),
         "NCIT" => Some(format!("NCIT:C{tail_raw}")),
         x if x == CV_REF_ATTR => Some(format!("{CV_REF_ATTR}:{tail_raw}")),
         _ => Some(format!("{pref}:{tail_raw}")),
@@ -142,6 +146,11 @@ pub(crate) const ACC_ATTR_INSTRUMENT_CONFIGURATION_REF: AccessionTail = Accessio
 pub(crate) const ACC_ATTR_SOFTWARE_REF: AccessionTail = AccessionTail(9_910_018);
 pub(crate) const ACC_ATTR_VERSION: AccessionTail = AccessionTail(9_910_019);
 
+// Preserved unmodelled XML (`Extensions`); attributes are "name\0value",
+// elements "parent\0position\0xml"
+pub(crate) const ACC_ATTR_EXTENSION_ATTRIBUTE: AccessionTail = AccessionTail(9_910_021);
+pub(crate) const ACC_ATTR_EXTENSION_ELEMENT: AccessionTail = AccessionTail(9_910_022);
+
 // Numeric-valued
 pub(crate) const ACC_ATTR_COUNT: AccessionTail = AccessionTail(9_910_100);
 pub(crate) const ACC_ATTR_ORDER: AccessionTail = AccessionTail(9_910_101);
@@ -377,6 +386,7 @@ mod tests {
             CV_CODE_NCIT,
             CV_CODE_PEFF,
             CV_CODE_B000,
+            CV_CODE_IMS,
         ] {
             let prefix = cv_ref_prefix_from_code(code).unwrap();
             assert_eq!(cv_ref_code_from_str(Some(prefix)), code);
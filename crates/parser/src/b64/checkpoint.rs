@@ -0,0 +1,221 @@
+//! Recovery checkpoints written by the encoder in streaming mode.
+//!
+//! A checkpoint is a zstd skippable frame, so it is ignored by zstd and by
+//! `decode`, which only follows header offsets. The payload starts with
+//! `CHECKPOINT_MAGIC` and a kind byte:
+//!
+//! - `KIND_PRELUDE`, written right after the header: a provisional header
+//!   whose metadata offsets point into the checkpoint body, followed by the
+//!   compressed spectrum, chromatogram and global metadata sections.
+//! - `KIND_SPECTRA` / `KIND_CHROMATOGRAMS`, written between blocks of a
+//!   container: the item index entries and array refs added since the last
+//!   checkpoint of that kind, plus the directory entries of newly sealed
+//!   blocks.
+
+use crate::b64::encoder::utilities::container_builder::BlockDirEntry;
+
+pub(crate) const SKIPPABLE_FRAME_MAGIC: [u8; 4] = 0x184D_2A50u32.to_le_bytes();
+pub(crate) const CHECKPOINT_MAGIC: [u8; 4] = *b"B0CP";
+
+pub(crate) const KIND_PRELUDE: u8 = 0;
+pub(crate) const KIND_SPECTRA: u8 = 1;
+pub(crate) const KIND_CHROMATOGRAMS: u8 = 2;
+
+const PROGRESS_FIXED_SIZE: usize = 48;
+const SEALED_ENTRY_SIZE: usize = 32;
+
+/// Wraps a checkpoint body in a skippable frame.
+pub(crate) fn checkpoint_frame(kind: u8, body: &[u8]) -> Vec<u8> {
+    let payload_len = CHECKPOINT_MAGIC.len() + 4 + body.len();
+    let mut frame = Vec::with_capacity(8 + payload_len);
+    frame.extend_from_slice(&SKIPPABLE_FRAME_MAGIC);
+    frame.extend_from_slice(&(payload_len as u32).to_le_bytes());
+    frame.extend_from_slice(&CHECKPOINT_MAGIC);
+    frame.extend_from_slice(&[kind, 0, 0, 0]);
+    frame.extend_from_slice(body);
+    frame
+}
+
+/// Progress of one container since the previous checkpoint of its kind.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Progress<'a> {
+    /// Absolute file offset of the container.
+    pub(crate) container_offset: u64,
+    /// Index of the first item in `entries`.
+    pub(crate) first_item: u64,
+    /// Index of the first array ref in `arrayrefs`.
+    pub(crate) first_arrayref: u64,
+    pub(crate) entries: &'a [u8],
+    pub(crate) arrayrefs: &'a [u8],
+    pub(crate) sealed: Vec<(u32, BlockDirEntry)>,
+}
+
+impl Progress<'_> {
+    pub(crate) fn to_body(&self) -> Vec<u8> {
+        let mut body = Vec::with_capacity(
+            PROGRESS_FIXED_SIZE
+                + self.entries.len()
+                + self.arrayrefs.len()
+                + self.sealed.len() * SEALED_ENTRY_SIZE,
+        );
+        for value in [
+            self.container_offset,
+            self.first_item,
+            self.first_arrayref,
+            self.entries.len() as u64,
+            self.arrayrefs.len() as u64,
+            self.sealed.len() as u64,
+        ] {
+            body.extend_from_slice(&value.to_le_bytes());
+        }
+        body.extend_from_slice(self.entries);
+        body.extend_from_slice(self.arrayrefs);
+        for (block_id, entry) in &self.sealed {
+            body.extend_from_slice(&(*block_id as u64).to_le_bytes());
+            body.extend_from_slice(&entry.payload_offset.to_le_bytes());
+            body.extend_from_slice(&entry.payload_size.to_le_bytes());
+            body.extend_from_slice(&entry.uncompressed_len_bytes.to_le_bytes());
+        }
+        body
+    }
+
+    fn from_body(body: &[u8]) -> Option<Progress<'_>> {
+        let word = |i: usize| -> Option<u64> {
+            Some(u64::from_le_bytes(
+                body.get(i * 8..i * 8 + 8)?.try_into().ok()?,
+            ))
+        };
+        let entries_len = word(3)? as usize;
+        let arrayrefs_len = word(4)? as usize;
+        let sealed_count = word(5)? as usize;
+
+        let entries_end = PROGRESS_FIXED_SIZE.checked_add(entries_len)?;
+        let arrayrefs_end = entries_end.checked_add(arrayrefs_len)?;
+        let sealed_end = sealed_count
+            .checked_mul(SEALED_ENTRY_SIZE)?
+            .checked_add(arrayrefs_end)?;
+        if sealed_end != body.len() {
+            return None;
+        }
+
+        let sealed = body[arrayrefs_end..]
+            .chunks_exact(SEALED_ENTRY_SIZE)
+            .map(|raw| {
+                let at = |i: usize| u64::from_le_bytes(raw[i * 8..i * 8 + 8].try_into().unwrap());
+                (
+                    at(0) as u32,
+                    BlockDirEntry {
+                        payload_offset: at(1),
+                        payload_size: at(2),
+                        uncompressed_len_bytes: at(3),
+                    },
+                )
+            })
+            .collect();
+
+        Some(Progress {
+            container_offset: word(0)?,
+            first_item: word(1)?,
+            first_arrayref: word(2)?,
+            entries: &body[PROGRESS_FIXED_SIZE..entries_end],
+            arrayrefs: &body[entries_end..arrayrefs_end],
+            sealed,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Checkpoint<'a> {
+    /// Provisional header and the metadata sections it points into.
+    Prelude {
+        header: &'a [u8],
+        meta: &'a [u8],
+    },
+    Progress {
+        kind: u8,
+        progress: Progress<'a>,
+    },
+}
+
+/// Parses the payload of a skippable frame; `None` when it is not a
+/// well-formed checkpoint.
+pub(crate) fn parse_checkpoint(payload: &[u8]) -> Option<Checkpoint<'_>> {
+    if payload.get(..4)? != CHECKPOINT_MAGIC {
+        return None;
+    }
+    let kind = *payload.get(4)?;
+    let body = payload.get(8..)?;
+    match kind {
+        KIND_PRELUDE => {
+            let header = body.get(..crate::b64::encoder::encode::HEADER_SIZE)?;
+            Some(Checkpoint::Prelude {
+                header,
+                meta: &body[header.len()..],
+            })
+        }
+        KIND_SPECTRA | KIND_CHROMATOGRAMS => Some(Checkpoint::Progress {
+            kind,
+            progress: Progress::from_body(body)?,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_round_trips_through_a_frame() {
+        let entries = [1u8; 32];
+        let arrayrefs = [2u8; 64];
+        let progress = Progress {
+            container_offset: 4096,
+            first_item: 3,
+            first_arrayref: 6,
+            entries: &entries,
+            arrayrefs: &arrayrefs,
+            sealed: vec![(
+                2,
+                BlockDirEntry {
+                    payload_offset: 10,
+                    payload_size: 20,
+                    uncompressed_len_bytes: 30,
+                },
+            )],
+        };
+        let frame = checkpoint_frame(KIND_SPECTRA, &progress.to_body());
+
+        assert_eq!(frame[..4], SKIPPABLE_FRAME_MAGIC);
+        let size = u32::from_le_bytes(frame[4..8].try_into().unwrap()) as usize;
+        assert_eq!(size, frame.len() - 8);
+
+        match parse_checkpoint(&frame[8..]).unwrap() {
+            Checkpoint::Progress { kind, progress: p } => {
+                assert_eq!(kind, KIND_SPECTRA);
+                assert_eq!(p.container_offset, 4096);
+                assert_eq!((p.first_item, p.first_arrayref), (3, 6));
+                assert_eq!(p.entries, entries);
+                assert_eq!(p.arrayrefs, arrayrefs);
+                assert_eq!(p.sealed[0].0, 2);
+                assert_eq!(p.sealed[0].1.uncompressed_len_bytes, 30);
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn truncated_progress_is_rejected() {
+        let progress = Progress {
+            container_offset: 0,
+            first_item: 0,
+            first_arrayref: 0,
+            entries: &[0u8; 16],
+            arrayrefs: &[],
+            sealed: Vec::new(),
+        };
+        let frame = checkpoint_frame(KIND_SPECTRA, &progress.to_body());
+        assert!(parse_checkpoint(&frame[8..frame.len() - 1]).is_none());
+        assert!(parse_checkpoint(b"XXXX\0\0\0\0").is_none());
+    }
+}
@@ -11,14 +11,18 @@ use crate::{
             parse_accession_tail, // ← canonical, returns AccessionTail
         },
         encoder::utilities::FilterType,
+        file_cv_table::FileCvTable,
         utilities::{
             children_lookup::{ChildrenLookup, DefaultMetadataPolicy, OwnerRows},
-            common::get_attr_text,
-            container_view::{ArrayData, BinaryStore, BinaryStoreConfig},
+            common::{get_attr_text, get_extensions},
+            container_view::{
+                ArrayData, BinaryCursor, BinaryStore, BinaryStoreConfig, ByteSource, Section,
+            },
             parse_chromatogram_list, parse_cv_and_user_params, parse_cv_list,
             parse_data_processing_list, parse_file_description,
             parse_global_metadata::parse_global_metadata,
-            parse_header, parse_instrument_list, parse_metadata,
+            parse_header, parse_instrument_list,
+            parse_metadata::MetadataColumns,
             parse_referenceable_param_group_list, parse_sample_list, parse_scan_settings_list,
             parse_software_list, parse_spectrum_list,
         },
@@ -29,12 +33,40 @@ use crate::{
 #[inline]
 pub fn decode(bytes: &[u8]) -> Result<MzML, String> {
     let header = parse_header(bytes)?;
-    let global_meta = parse_global_section(bytes, &header)?;
+    let mut source = bytes;
+    let (mut mzml, cv_table) = decode_header_metadata(&mut source, &header)?;
+    let policy = DefaultMetadataPolicy;
+
+    let spec_meta = parse_metadata_section(&mut source, &header, &cv_table, true)?;
+    let chrom_meta = parse_metadata_section(&mut source, &header, &cv_table, false)?;
+    let spec_refs: Vec<&Metadatum> = spec_meta.iter().collect();
+    let chrom_refs: Vec<&Metadatum> = chrom_meta.iter().collect();
+    mzml.run.spectrum_list =
+        parse_spectrum_list(&spec_refs, &ChildrenLookup::new(&spec_meta), &policy);
+    mzml.run.chromatogram_list =
+        parse_chromatogram_list(&chrom_refs, &ChildrenLookup::new(&chrom_meta), &policy);
+
+    let (spec, chrom) = binary_cursors(&mut source, &header)?;
+    attach_binaries(
+        &mut mzml.run,
+        &mut BinaryStore::collect(spec, &mut source)?,
+        &mut BinaryStore::collect(chrom, &mut source)?,
+    );
+    Ok(mzml)
+}
+
+/// Everything but the spectrum and chromatogram lists, which are read from
+/// their own metadata sections, and the file's CV table needed to read them.
+pub(crate) fn decode_header_metadata<'a, S: ByteSource<'a>>(
+    source: &mut S,
+    header: &Header,
+) -> Result<(MzML, FileCvTable), String> {
+    let (global_meta, cv_table) = parse_global_section(source, header)?;
     let lookup = ChildrenLookup::new(&global_meta);
     let meta_refs: Vec<&Metadatum> = global_meta.iter().collect();
     let policy = DefaultMetadataPolicy;
 
-    Ok(MzML {
+    let mzml = MzML {
         cv_list: parse_cv_list(&meta_refs, &lookup),
         file_description: parse_file_description(&meta_refs, &lookup, &policy),
         referenceable_param_group_list: parse_referenceable_param_group_list(
@@ -45,17 +77,23 @@ pub fn decode(bytes: &[u8]) -> Result<MzML, String> {
         software_list: parse_software_list(&meta_refs, &lookup, &policy),
         data_processing_list: parse_data_processing_list(&meta_refs, &lookup, &policy),
         scan_settings_list: parse_scan_settings_list(&meta_refs, &lookup, &policy),
-        run: parse_run(bytes, &header, &global_meta, &policy)?,
-    })
+        run: parse_run(&global_meta, &policy),
+        extensions: parse_mzml_extensions(&meta_refs),
+    };
+    Ok((mzml, cv_table))
+}
+
+fn parse_mzml_extensions(meta: &[&Metadatum]) -> Option<Extensions> {
+    let rows: Vec<&Metadatum> = meta
+        .iter()
+        .copied()
+        .filter(|m| m.tag_id == TagId::MzML)
+        .collect();
+    get_extensions(&rows)
 }
 
 #[inline]
-fn parse_run(
-    bytes: &[u8],
-    header: &Header,
-    global_meta: &[Metadatum],
-    policy: &DefaultMetadataPolicy,
-) -> Result<Run, String> {
+fn parse_run(global_meta: &[Metadatum], policy: &DefaultMetadataPolicy) -> Run {
     let mut owner_rows = OwnerRows::with_capacity(global_meta.len());
     for m in global_meta {
         owner_rows.insert(m.id, m);
@@ -74,13 +112,7 @@ fn parse_run(
     children_lookup.get_param_rows_into(&owner_rows, run_id, policy, &mut param_buffer);
     let (cv_params, user_params) = parse_cv_and_user_params(&param_buffer);
 
-    let spec_meta = parse_metadata_section(bytes, header, true)?;
-    let chrom_meta = parse_metadata_section(bytes, header, false)?;
-
-    let spec_refs: Vec<&Metadatum> = spec_meta.iter().collect();
-    let chrom_refs: Vec<&Metadatum> = chrom_meta.iter().collect();
-
-    let mut run = Run {
+    Run {
         id: get_attr_text(rows, ACC_ATTR_ID).unwrap_or_default(),
         start_time_stamp: get_attr_text(rows, ACC_ATTR_START_TIME_STAMP).filter(|s| !s.is_empty()),
         default_instrument_configuration_ref: get_attr_text(
@@ -89,42 +121,30 @@ fn parse_run(
         )
         .or_else(|| get_attr_text(rows, ACC_ATTR_INSTRUMENT_CONFIGURATION_REF)),
         sample_ref: get_attr_text(rows, ACC_ATTR_SAMPLE_REF),
+        extensions: get_extensions(rows),
         cv_params,
         user_params,
         source_file_ref_list: parse_run_source_file_refs(&owner_rows, &children_lookup, run_id),
-        spectrum_list: parse_spectrum_list(&spec_refs, &ChildrenLookup::new(&spec_meta), policy),
-        chromatogram_list: parse_chromatogram_list(
-            &chrom_refs,
-            &ChildrenLookup::new(&chrom_meta),
-            policy,
-        ),
         ..Default::default()
-    };
+    }
+}
 
-    // ── binary data ───────────────────────────────────────────────────────────
-    // BinaryStore owns the full extraction pipeline; decode.rs sees only
-    // pre-decoded slots that are consumed once via `take`.
+// ── binary data ───────────────────────────────────────────────────────────────
+// BinaryCursor owns the full extraction pipeline; decode.rs sees only
+// pre-decoded arrays, either per item or collected into a BinaryStore.
+
+/// Spectrum and chromatogram array readers.
+pub(crate) fn binary_cursors<'a, S: ByteSource<'a>>(
+    source: &mut S,
+    header: &Header,
+) -> Result<(BinaryCursor<'a>, BinaryCursor<'a>), String> {
     let filter = FilterType::try_from(header.array_filter)?;
 
-    let mut spec_store = BinaryStore::build(
-        slice_at(
-            bytes,
-            header.off_container_spect,
-            header.len_container_spect,
-            "spec",
-        )?,
-        slice_at(
-            bytes,
-            header.off_spec_arrayrefs,
-            header.len_spec_arrayrefs,
-            "A1-spec",
-        )?,
-        slice_at(
-            bytes,
-            header.off_spec_entries,
-            header.len_spec_entries,
-            "A0-spec",
-        )?,
+    let spec = BinaryCursor::new(
+        source,
+        section(header.off_container_spect, header.len_container_spect),
+        section(header.off_spec_arrayrefs, header.len_spec_arrayrefs),
+        section(header.off_spec_entries, header.len_spec_entries),
         BinaryStoreConfig {
             block_count: header.block_count_spect,
             item_count: header.spectrum_count,
@@ -134,25 +154,11 @@ fn parse_run(
         },
     )?;
 
-    let mut chrom_store = BinaryStore::build(
-        slice_at(
-            bytes,
-            header.off_container_chrom,
-            header.len_container_chrom,
-            "chrom",
-        )?,
-        slice_at(
-            bytes,
-            header.off_chrom_arrayrefs,
-            header.len_chrom_arrayrefs,
-            "A1-chrom",
-        )?,
-        slice_at(
-            bytes,
-            header.off_chrom_entries,
-            header.len_chrom_entries,
-            "A0-chrom",
-        )?,
+    let chrom = BinaryCursor::new(
+        source,
+        section(header.off_container_chrom, header.len_container_chrom),
+        section(header.off_chrom_arrayrefs, header.len_chrom_arrayrefs),
+        section(header.off_chrom_entries, header.len_chrom_entries),
         BinaryStoreConfig {
             block_count: header.block_count_chrom,
             item_count: header.chrom_count,
@@ -162,8 +168,12 @@ fn parse_run(
         },
     )?;
 
-    attach_binaries(&mut run, &mut spec_store, &mut chrom_store);
-    Ok(run)
+    Ok((spec, chrom))
+}
+
+#[inline]
+fn section(offset: u64, len: u64) -> Section {
+    Section { offset, len }
 }
 
 // ── Binary attachment ─────────────────────────────────────────────────────────
@@ -171,32 +181,31 @@ fn parse_run(
 fn attach_binaries(run: &mut Run, spec: &mut BinaryStore, chrom: &mut BinaryStore) {
     if let Some(list) = run.spectrum_list.as_mut() {
         for (i, spectrum) in list.spectra.iter_mut().enumerate() {
-            let Some(arrays) = spec.take(i) else { continue };
-            if arrays.is_empty() {
-                continue;
-            }
-            let bdal = spectrum
-                .binary_data_array_list
-                .get_or_insert_with(BinaryDataArrayList::default);
-            bind_arrays(bdal, arrays);
+            attach_arrays(&mut spectrum.binary_data_array_list, spec.take(i));
         }
     }
     if let Some(list) = run.chromatogram_list.as_mut() {
         for (i, chromatogram) in list.chromatograms.iter_mut().enumerate() {
-            let Some(arrays) = chrom.take(i) else {
-                continue;
-            };
-            if arrays.is_empty() {
-                continue;
-            }
-            let bdal = chromatogram
-                .binary_data_array_list
-                .get_or_insert_with(BinaryDataArrayList::default);
-            bind_arrays(bdal, arrays);
+            attach_arrays(&mut chromatogram.binary_data_array_list, chrom.take(i));
         }
     }
 }
 
+/// Binds decoded `arrays` into `list`, creating it if there are any.
+pub(crate) fn attach_arrays(
+    list: &mut Option<BinaryDataArrayList>,
+    arrays: Option<Vec<(u32, ArrayData)>>,
+) {
+    let Some(arrays) = arrays else { return };
+    if arrays.is_empty() {
+        return;
+    }
+    bind_arrays(
+        list.get_or_insert_with(BinaryDataArrayList::default),
+        arrays,
+    );
+}
+
 // AFTER
 fn bind_arrays(list: &mut BinaryDataArrayList, arrays: Vec<(u32, ArrayData)>) {
     for (kind, data) in arrays {
@@ -312,25 +321,15 @@ fn ms_numeric_param(tail: u32) -> CvParam {
     }
 }
 
-// ── Section slicing and metadata helpers ──────────────────────────────────────
-
-#[inline]
-pub(crate) fn slice_at<'a>(
-    bytes: &'a [u8],
-    off: u64,
-    len: u64,
-    f: &str,
-) -> Result<&'a [u8], String> {
-    let (o, l) = (off as usize, len as usize);
-    bytes
-        .get(o..o + l)
-        .ok_or_else(|| format!("{f}: range error"))
-}
+// ── Section reading and metadata helpers ──────────────────────────────────────
 
 #[inline]
-fn parse_global_section(bytes: &[u8], h: &Header) -> Result<Vec<Metadatum>, String> {
+fn parse_global_section<'a, S: ByteSource<'a>>(
+    source: &mut S,
+    h: &Header,
+) -> Result<(Vec<Metadatum>, FileCvTable), String> {
     parse_global_metadata(
-        slice_at(bytes, h.off_global_meta, h.len_global_meta, "global")?,
+        &source.read_at(h.off_global_meta, h.len_global_meta, "global")?,
         0,
         h.global_meta_count,
         h.global_meta_num_count,
@@ -340,12 +339,13 @@ fn parse_global_section(bytes: &[u8], h: &Header) -> Result<Vec<Metadatum>, Stri
     )
 }
 
-#[inline]
-fn parse_metadata_section(
-    bytes: &[u8],
+/// The spectrum (`is_spec`) or chromatogram metadata section in its column
+/// layout.
+pub(crate) fn read_metadata_columns<'a, S: ByteSource<'a>>(
+    source: &mut S,
     h: &Header,
     is_spec: bool,
-) -> Result<Vec<Metadatum>, String> {
+) -> Result<MetadataColumns, String> {
     let (off, len, count, n_count, s_count, uncompressed) = if is_spec {
         (
             h.off_spec_meta,
@@ -365,8 +365,8 @@ fn parse_metadata_section(
             h.chrom_meta_uncompressed_bytes,
         )
     };
-    parse_metadata(
-        slice_at(bytes, off, len, "meta")?,
+    MetadataColumns::parse(
+        &source.read_at(off, len, "meta")?,
         if is_spec {
             h.spectrum_count
         } else {
@@ -380,6 +380,16 @@ fn parse_metadata_section(
     )
 }
 
+#[inline]
+fn parse_metadata_section<'a, S: ByteSource<'a>>(
+    source: &mut S,
+    h: &Header,
+    cv_table: &FileCvTable,
+    is_spec: bool,
+) -> Result<Vec<Metadatum>, String> {
+    read_metadata_columns(source, h, is_spec)?.rows(cv_table)
+}
+
 #[inline]
 fn parse_run_source_file_refs(
     owner_rows: &OwnerRows,
@@ -423,5 +433,13 @@ pub(crate) struct Metadatum {
     pub(crate) tag_id: TagId,
     pub(crate) accession: Option<String>,
     pub(crate) unit_accession: Option<String>,
+    /// cvRef and unitCvRef as written, which need not match the prefix of
+    /// the accession.
+    pub(crate) cv_ref: Option<String>,
+    pub(crate) unit_cv_ref: Option<String>,
+    /// Term names kept in the per-file CV table; `None` for built-in CV terms,
+    /// whose names come from the bundled `cv_table`.
+    pub(crate) name: Option<String>,
+    pub(crate) unit_name: Option<String>,
     pub(crate) value: MetadatumValue,
 }
@@ -1,5 +1,8 @@
 pub mod decode;
 pub use decode::decode;
+pub mod reader;
+pub use reader::{B000Reader, Spectra};
+pub mod salvage;
 pub(crate) mod utilities;
 
 #[cfg(test)]
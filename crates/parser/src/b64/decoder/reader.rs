@@ -0,0 +1,411 @@
+use std::io::{Read, Seek};
+
+use crate::{
+    b64::{
+        attr_meta::{ACC_ATTR_COUNT, ACC_ATTR_DEFAULT_DATA_PROCESSING_REF},
+        decoder::{
+            decode::{
+                Metadatum, attach_arrays, binary_cursors, decode_header_metadata,
+                read_metadata_columns,
+            },
+            utilities::{
+                Header,
+                children_lookup::{ChildrenLookup, DefaultMetadataPolicy},
+                common::{get_attr_text, get_attr_u32},
+                container_view::{BinaryCursor, ByteSource, ReadSource},
+                parse_chromatogram_list::parse_chromatogram_entry,
+                parse_header,
+                parse_metadata::MetadataColumns,
+                parse_spectrum_list::parse_spectrum_entry,
+            },
+        },
+        encoder::encode::HEADER_SIZE,
+        file_cv_table::FileCvTable,
+    },
+    mzml::{mzml_reader::MzMLItem, schema::TagId, structs::*},
+};
+
+/// Pull reader for B000 files that decodes one spectrum or chromatogram at a
+/// time from a seekable input.
+///
+/// `new` decodes the file-level metadata into `header()`, whose spectrum and
+/// chromatogram lists carry their `count` and default data processing but no
+/// entries. Entries are then yielded in file order, spectra first, by
+/// `next_item` or the `Iterator` impl: each one's metadata rows and arrays are
+/// read and decoded on demand. Only the compact column form of the spectrum
+/// and chromatogram metadata sections and the block being read stay in
+/// memory, never the whole file.
+pub struct B000Reader<R> {
+    source: ReadSource<R>,
+    header: MzML,
+    cv_table: FileCvTable,
+    spectra: EntryRows,
+    chromatograms: EntryRows,
+    spectrum_arrays: BinaryCursor<'static>,
+    chromatogram_arrays: BinaryCursor<'static>,
+    rows: Vec<Metadatum>,
+    /// Set after the first error, after which nothing more is read.
+    failed: bool,
+}
+
+/// Metadata section of one entry kind and the next item to read from it.
+struct EntryRows {
+    columns: MetadataColumns,
+    default_data_processing_ref: Option<String>,
+    next: usize,
+}
+
+impl EntryRows {
+    /// Replaces `rows` with those of the next item, returning its index.
+    fn next_into(
+        &mut self,
+        cv_table: &FileCvTable,
+        rows: &mut Vec<Metadatum>,
+    ) -> Result<Option<usize>, String> {
+        if self.next >= self.columns.item_count() {
+            return Ok(None);
+        }
+        let index = self.next;
+        self.next += 1;
+        rows.clear();
+        self.columns.rows_into(index, cv_table, rows)?;
+        Ok(Some(index))
+    }
+}
+
+impl<R: Read + Seek> B000Reader<R> {
+    pub fn new(inner: R) -> Result<Self, String> {
+        let mut source = ReadSource(inner);
+        let file_header: Header =
+            parse_header(&source.read_at(0, HEADER_SIZE as u64, "header")?)?;
+        let (mut header, cv_table) = decode_header_metadata(&mut source, &file_header)?;
+        let spectrum_columns = read_metadata_columns(&mut source, &file_header, true)?;
+        let chromatogram_columns = read_metadata_columns(&mut source, &file_header, false)?;
+        let (spectrum_arrays, chromatogram_arrays) = binary_cursors(&mut source, &file_header)?;
+
+        let mut rows = Vec::new();
+        let spectra = list_attributes(spectrum_columns, &cv_table, TagId::SpectrumList, &mut rows)?;
+        header.run.spectrum_list = spectra.count.map(|count| SpectrumList {
+            count: Some(count),
+            default_data_processing_ref: spectra.entries.default_data_processing_ref.clone(),
+            spectra: Vec::new(),
+        });
+        let chromatograms = list_attributes(
+            chromatogram_columns,
+            &cv_table,
+            TagId::ChromatogramList,
+            &mut rows,
+        )?;
+        header.run.chromatogram_list = chromatograms.count.map(|count| ChromatogramList {
+            count: Some(count),
+            default_data_processing_ref: chromatograms.entries.default_data_processing_ref.clone(),
+            chromatograms: Vec::new(),
+        });
+
+        Ok(Self {
+            source,
+            header,
+            cv_table,
+            spectra: spectra.entries,
+            chromatograms: chromatograms.entries,
+            spectrum_arrays,
+            chromatogram_arrays,
+            rows,
+            failed: false,
+        })
+    }
+
+    /// Everything but the spectra and chromatograms.
+    #[inline]
+    pub fn header(&self) -> &MzML {
+        &self.header
+    }
+
+    #[inline]
+    pub fn into_header(self) -> MzML {
+        self.header
+    }
+
+    /// Next spectrum or chromatogram with its arrays, or `None` after the
+    /// last chromatogram. After an error every call returns `Ok(None)`.
+    pub fn next_item(&mut self) -> Result<Option<MzMLItem>, String> {
+        if self.failed {
+            return Ok(None);
+        }
+        let item = self.read_item();
+        self.failed = item.is_err();
+        item
+    }
+
+    fn read_item(&mut self) -> Result<Option<MzMLItem>, String> {
+        let policy = DefaultMetadataPolicy;
+        if let Some(index) = self.spectra.next_into(&self.cv_table, &mut self.rows)? {
+            let mut spectrum = parse_spectrum_entry(
+                &self.rows,
+                index as u32,
+                self.spectra.default_data_processing_ref.as_deref(),
+                &policy,
+            )
+            .ok_or_else(|| format!("spectrum entry {index} has no spectrum element"))?;
+            let arrays = self.spectrum_arrays.read(&mut self.source, index)?;
+            attach_arrays(&mut spectrum.binary_data_array_list, arrays);
+            return Ok(Some(MzMLItem::Spectrum(spectrum)));
+        }
+        if let Some(index) = self
+            .chromatograms
+            .next_into(&self.cv_table, &mut self.rows)?
+        {
+            let mut chromatogram = parse_chromatogram_entry(
+                &self.rows,
+                index as u32,
+                self.chromatograms.default_data_processing_ref.as_deref(),
+                &policy,
+            )
+            .ok_or_else(|| format!("chromatogram entry {index} has no chromatogram element"))?;
+            let arrays = self.chromatogram_arrays.read(&mut self.source, index)?;
+            attach_arrays(&mut chromatogram.binary_data_array_list, arrays);
+            return Ok(Some(MzMLItem::Chromatogram(chromatogram)));
+        }
+        Ok(None)
+    }
+
+    /// The spectra, ending before the first chromatogram, which is never
+    /// decoded, or at the first error, which `Spectra::finish` returns.
+    #[inline]
+    pub fn spectra(self) -> Spectra<R> {
+        Spectra {
+            reader: self,
+            done: false,
+            error: None,
+        }
+    }
+}
+
+impl<R: Read + Seek> Iterator for B000Reader<R> {
+    type Item = Result<MzMLItem, String>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_item().transpose()
+    }
+}
+
+impl<R: Read + Seek> std::iter::FusedIterator for B000Reader<R> {}
+
+/// Spectra of a `B000Reader`, from `B000Reader::spectra`. Iterate it with
+/// `by_ref`, then call `finish` to learn whether it stopped at an error.
+pub struct Spectra<R> {
+    reader: B000Reader<R>,
+    done: bool,
+    error: Option<String>,
+}
+
+impl<R> Spectra<R> {
+    /// The error that ended the spectra, if any.
+    #[inline]
+    pub fn finish(self) -> Result<(), String> {
+        self.error.map_or(Ok(()), Err)
+    }
+}
+
+impl<R: Read + Seek> Iterator for Spectra<R> {
+    type Item = Spectrum;
+
+    fn next(&mut self) -> Option<Spectrum> {
+        if self.done {
+            return None;
+        }
+        match self.reader.next_item() {
+            Ok(Some(MzMLItem::Spectrum(s))) => return Some(s),
+            Ok(Some(MzMLItem::Chromatogram(_)) | None) => {}
+            Err(e) => self.error = Some(e),
+        }
+        self.done = true;
+        None
+    }
+}
+
+impl<R: Read + Seek> std::iter::FusedIterator for Spectra<R> {}
+
+struct ListAttributes {
+    entries: EntryRows,
+    /// `None` when the section holds no list.
+    count: Option<usize>,
+}
+
+/// Reads the list element's attributes, which are stored with the rows of
+/// the first item.
+fn list_attributes(
+    columns: MetadataColumns,
+    cv_table: &FileCvTable,
+    list_tag: TagId,
+    rows: &mut Vec<Metadatum>,
+) -> Result<ListAttributes, String> {
+    rows.clear();
+    if columns.item_count() > 0 {
+        columns.rows_into(0, cv_table, rows)?;
+    }
+    let lookup = ChildrenLookup::new(rows);
+    let list_rows: Vec<&Metadatum> = match lookup.all_ids(list_tag).first() {
+        Some(&list_id) => rows.iter().filter(|m| m.id == list_id).collect(),
+        None => {
+            return Ok(ListAttributes {
+                entries: EntryRows {
+                    columns,
+                    default_data_processing_ref: None,
+                    next: 0,
+                },
+                count: None,
+            });
+        }
+    };
+    let count = get_attr_u32(&list_rows, ACC_ATTR_COUNT)
+        .map(|v| v as usize)
+        .unwrap_or(columns.item_count());
+    let default_data_processing_ref =
+        get_attr_text(&list_rows, ACC_ATTR_DEFAULT_DATA_PROCESSING_REF);
+
+    Ok(ListAttributes {
+        entries: EntryRows {
+            columns,
+            default_data_processing_ref,
+            next: 0,
+        },
+        count: Some(count),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        b64::{
+            decoder::decode::decode,
+            encoder::encode::{WritingMode, encode},
+        },
+        mzml::{bin_to_mzml::convert_bin_to_mzml_bytes, parse_mzml::parse_mzml},
+    };
+    use std::{cell::Cell, io::Cursor, rc::Rc};
+
+    fn json<T: serde::Serialize>(value: &T) -> serde_json::Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    #[test]
+    fn yields_the_same_entries_as_decode() {
+        for path in ["data/mzml/test.mzML", "data/mzml/tiny2_SRM.mzML0.99.1.mzML"] {
+            let mzml = parse_mzml(&std::fs::read(path).unwrap()).unwrap();
+            let mut bytes = Vec::new();
+            encode(&mzml, 12, false, WritingMode::Memory, &mut bytes).unwrap();
+            let decoded = decode(&bytes).unwrap();
+
+            let mut reader = B000Reader::new(Cursor::new(&bytes)).unwrap();
+            let mut spectra = Vec::new();
+            let mut chromatograms = Vec::new();
+            for item in reader.by_ref() {
+                match item.unwrap() {
+                    MzMLItem::Spectrum(s) => spectra.push(s),
+                    MzMLItem::Chromatogram(c) => chromatograms.push(c),
+                }
+            }
+            let run = &decoded.run;
+            let expected_spectra = run.spectrum_list.as_ref().map_or(&[][..], |l| &l.spectra);
+            let expected_chromatograms = run
+                .chromatogram_list
+                .as_ref()
+                .map_or(&[][..], |l| &l.chromatograms);
+            assert_eq!(json(&spectra), json(&expected_spectra), "{path}");
+            assert_eq!(
+                json(&chromatograms),
+                json(&expected_chromatograms),
+                "{path}"
+            );
+
+            let mut header = reader.into_header();
+            assert!(
+                header
+                    .run
+                    .spectrum_list
+                    .iter()
+                    .all(|l| l.spectra.is_empty())
+            );
+            if let Some(list) = header.run.spectrum_list.as_mut() {
+                list.spectra = spectra;
+            }
+            if let Some(list) = header.run.chromatogram_list.as_mut() {
+                list.chromatograms = chromatograms;
+            }
+            assert_eq!(
+                convert_bin_to_mzml_bytes(&header).unwrap(),
+                convert_bin_to_mzml_bytes(&decoded).unwrap(),
+                "{path}"
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_input_is_an_error() {
+        let mzml = parse_mzml(&std::fs::read("data/mzml/test.mzML").unwrap()).unwrap();
+        let mut bytes = Vec::new();
+        encode(&mzml, 12, false, WritingMode::Memory, &mut bytes).unwrap();
+        bytes.truncate(bytes.len() / 2);
+        assert!(B000Reader::new(Cursor::new(&bytes)).is_err());
+    }
+
+    /// Input whose reads fail once `cut` is set.
+    struct Cut {
+        inner: Cursor<Vec<u8>>,
+        cut: Rc<Cell<bool>>,
+    }
+
+    impl Read for Cut {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.cut.get() {
+                return Err(std::io::Error::other("cut"));
+            }
+            self.inner.read(buf)
+        }
+    }
+
+    impl Seek for Cut {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn stops_after_the_first_error() {
+        let mzml = parse_mzml(&std::fs::read("data/mzml/test.mzML").unwrap()).unwrap();
+        let mut bytes = Vec::new();
+        encode(&mzml, 12, false, WritingMode::Memory, &mut bytes).unwrap();
+        let open = || {
+            let cut = Rc::new(Cell::new(false));
+            let input = Cut {
+                inner: Cursor::new(bytes.clone()),
+                cut: cut.clone(),
+            };
+            (B000Reader::new(input).unwrap(), cut)
+        };
+
+        let (mut reader, cut) = open();
+        assert!(matches!(reader.next(), Some(Ok(MzMLItem::Spectrum(_)))));
+        cut.set(true);
+        assert!(reader.next().unwrap().is_err());
+        cut.set(false);
+        assert!(reader.next().is_none());
+
+        let (reader, cut) = open();
+        let mut spectra = reader.spectra();
+        assert!(spectra.next().is_some());
+        cut.set(true);
+        assert!(spectra.next().is_none());
+        cut.set(false);
+        assert!(spectra.next().is_none());
+        assert!(spectra.finish().is_err());
+
+        let (reader, _) = open();
+        let mut spectra = reader.spectra();
+        assert_eq!(spectra.by_ref().count(), 2);
+        assert!(spectra.finish().is_ok());
+    }
+}
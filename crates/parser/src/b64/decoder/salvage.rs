@@ -0,0 +1,673 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::{Display, Formatter, Result as FmtResult},
+};
+
+use zstd::zstd_safe::{find_frame_compressed_size, get_frame_content_size};
+
+use crate::{
+    b64::{
+        checkpoint::{Checkpoint, KIND_SPECTRA, Progress, SKIPPABLE_FRAME_MAGIC, parse_checkpoint},
+        decoder::decode::decode,
+        encoder::{
+            encode::{FILE_TRAILER, HEADER_SIZE},
+            utilities::container_builder::BlockDirEntry,
+        },
+        utilities::{
+            common::decompress_zstd,
+            parse_header::{
+                HEADER_CHROM_BLOCK_COUNT, HEADER_CHROM_COUNT, HEADER_COMPRESSION_LEVEL,
+                HEADER_LEN_CHROM_ARRAYREFS, HEADER_LEN_CHROM_ENTRIES, HEADER_LEN_CHROM_META,
+                HEADER_LEN_GLOBAL_META, HEADER_LEN_PACKED_CHROMS, HEADER_LEN_PACKED_SPECTRA,
+                HEADER_LEN_SPEC_ARRAYREFS, HEADER_LEN_SPEC_ENTRIES, HEADER_LEN_SPEC_META,
+                HEADER_OFFSET_CHROM_ARRAYREFS, HEADER_OFFSET_CHROM_ENTRIES,
+                HEADER_OFFSET_CHROM_META, HEADER_OFFSET_GLOBAL_META, HEADER_OFFSET_PACKED_CHROMS,
+                HEADER_OFFSET_PACKED_SPECTRA, HEADER_OFFSET_SPEC_ARRAYREFS,
+                HEADER_OFFSET_SPEC_ENTRIES, HEADER_OFFSET_SPEC_META, HEADER_SPECTRUM_BLOCK_COUNT,
+                HEADER_SPECTRUM_COUNT,
+            },
+        },
+    },
+    mzml::{salvage::INCOMPLETE_RUN_PARAM, structs::*},
+};
+
+const ZSTD_FRAME_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+const ITEM_ENTRY_SIZE: usize = 16;
+const ARRAYREF_SIZE: usize = 32;
+const ARRAYREF_BLOCK_ID_AT: usize = 16;
+
+/// What `salvage_b000` could and could not recover.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct B000SalvageReport {
+    /// The file decoded normally; nothing was lost.
+    pub complete: bool,
+    /// Recovery checkpoints found.
+    pub checkpoints: usize,
+    /// Well-formed zstd frames found after the header.
+    pub zstd_frames: usize,
+    /// Sealed blocks whose payload was missing or did not decompress.
+    pub damaged_blocks: usize,
+    pub spectra_recovered: usize,
+    pub spectra_lost: usize,
+    pub chromatograms_recovered: usize,
+    pub chromatograms_lost: usize,
+    /// End of the last intact checkpoint, or the file length when complete.
+    pub last_good_offset: u64,
+}
+
+impl Display for B000SalvageReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        if self.complete {
+            return write!(f, "file is complete");
+        }
+        write!(
+            f,
+            "recovered {}/{} spectra and {}/{} chromatograms from {} checkpoints \
+             (last good byte {}, {} damaged blocks, {} zstd frames)",
+            self.spectra_recovered,
+            self.spectra_recovered + self.spectra_lost,
+            self.chromatograms_recovered,
+            self.chromatograms_recovered + self.chromatograms_lost,
+            self.checkpoints,
+            self.last_good_offset,
+            self.damaged_blocks,
+            self.zstd_frames,
+        )
+    }
+}
+
+/// Decodes what is left of a damaged or unfinished B000 file.
+///
+/// Intact files decode as with `decode`. Otherwise the container region is
+/// scanned for zstd frames and the recovery checkpoints written by the
+/// encoder (see `Encoder::with_checkpoints`): the metadata
+/// comes from the prelude checkpoint, and every spectrum or chromatogram
+/// whose arrays all lie in intact blocks is rebuilt. The others are dropped
+/// and counted in the report, and the run gets an `INCOMPLETE_RUN_PARAM`
+/// user param. Checkpoint contents are bounds-checked against the file and
+/// the item counts; blocks they describe inconsistently count as damaged.
+///
+/// Only files written with checkpoints can be salvaged once cut short:
+/// otherwise the metadata is only written at the end, and nothing maps the
+/// blocks to spectra. What a cut keeps depends on where it falls. Nothing
+/// is recovered before the prelude checkpoint, which holds the metadata
+/// and follows the header, is complete. After it, a spectrum or
+/// chromatogram is recovered once the block holding its arrays and the
+/// checkpoint sealing that block are both written, so with a large
+/// interval a cut loses everything since the last checkpoint.
+pub fn salvage_b000(bytes: &[u8]) -> Result<(MzML, B000SalvageReport), String> {
+    let decode_error = match decode(bytes) {
+        Ok(mzml) => {
+            let report = B000SalvageReport {
+                complete: true,
+                spectra_recovered: mzml
+                    .run
+                    .spectrum_list
+                    .as_ref()
+                    .map_or(0, |l| l.spectra.len()),
+                chromatograms_recovered: mzml
+                    .run
+                    .chromatogram_list
+                    .as_ref()
+                    .map_or(0, |l| l.chromatograms.len()),
+                last_good_offset: bytes.len() as u64,
+                ..Default::default()
+            };
+            return Ok((mzml, report));
+        }
+        Err(e) => e,
+    };
+
+    let scan = scan_frames(bytes);
+    let Some((header, meta)) = scan.prelude else {
+        if scan.cut_checkpoint {
+            return Err(format!(
+                "cannot salvage: {decode_error}; the file ends inside its first recovery \
+                 checkpoint, which holds the metadata, so nothing can be recovered"
+            ));
+        }
+        return Err(format!(
+            "cannot salvage: {decode_error}; no recovery checkpoints found \
+             ({} zstd frames cannot be mapped to spectra without them)",
+            scan.zstd_frames
+        ));
+    };
+
+    let compressed = header[HEADER_COMPRESSION_LEVEL] != 0;
+    let spectra = recover_container(
+        bytes,
+        &scan.spectra,
+        read_u32(header, HEADER_SPECTRUM_COUNT),
+        compressed,
+    );
+    let chromatograms = recover_container(
+        bytes,
+        &scan.chromatograms,
+        read_u32(header, HEADER_CHROM_COUNT),
+        compressed,
+    );
+
+    let rebuilt = rebuild(header, meta, &spectra, &chromatograms)?;
+    let mut mzml = decode(&rebuilt)?;
+
+    let run = &mut mzml.run;
+    if let Some(list) = run.spectrum_list.as_mut() {
+        keep_recovered(&mut list.spectra, &spectra.lost);
+        list.count = Some(list.spectra.len());
+    }
+    if let Some(list) = run.chromatogram_list.as_mut() {
+        keep_recovered(&mut list.chromatograms, &chromatograms.lost);
+        list.count = Some(list.chromatograms.len());
+    }
+    run.user_params.push(UserParam {
+        name: INCOMPLETE_RUN_PARAM.to_string(),
+        r#type: Some("xsd:unsignedLong".to_string()),
+        value: Some(scan.last_good_offset.to_string()),
+        ..Default::default()
+    });
+
+    let lost = |c: &RecoveredContainer| c.lost.iter().filter(|&&l| l).count();
+    let report = B000SalvageReport {
+        complete: false,
+        checkpoints: scan.checkpoints,
+        zstd_frames: scan.zstd_frames,
+        damaged_blocks: spectra.damaged_blocks + chromatograms.damaged_blocks,
+        spectra_recovered: spectra.lost.len() - lost(&spectra),
+        spectra_lost: lost(&spectra),
+        chromatograms_recovered: chromatograms.lost.len() - lost(&chromatograms),
+        chromatograms_lost: lost(&chromatograms),
+        last_good_offset: scan.last_good_offset,
+    };
+    Ok((mzml, report))
+}
+
+fn keep_recovered<T>(items: &mut Vec<T>, lost: &[bool]) {
+    let mut index = 0;
+    items.retain(|_| {
+        let keep = !lost.get(index).copied().unwrap_or(true);
+        index += 1;
+        keep
+    });
+}
+
+/// Checkpoints of one container, concatenated in order.
+#[derive(Default)]
+struct ContainerProgress {
+    container_offset: u64,
+    entries: Vec<u8>,
+    arrayrefs: Vec<u8>,
+    sealed: BTreeMap<u32, BlockDirEntry>,
+    /// A checkpoint went missing; later ones would not line up.
+    broken: bool,
+}
+
+impl ContainerProgress {
+    fn absorb(&mut self, progress: Progress<'_>) {
+        let in_order = progress.first_item as usize * ITEM_ENTRY_SIZE == self.entries.len()
+            && progress.first_arrayref as usize * ARRAYREF_SIZE == self.arrayrefs.len();
+        if self.broken || !in_order {
+            self.broken = true;
+            return;
+        }
+        self.container_offset = progress.container_offset;
+        self.entries.extend_from_slice(progress.entries);
+        self.arrayrefs.extend_from_slice(progress.arrayrefs);
+        self.sealed.extend(progress.sealed);
+    }
+}
+
+#[derive(Default)]
+struct FrameScan<'a> {
+    prelude: Option<(&'a [u8], &'a [u8])>,
+    spectra: ContainerProgress,
+    chromatograms: ContainerProgress,
+    checkpoints: usize,
+    zstd_frames: usize,
+    last_good_offset: u64,
+    /// A checkpoint frame runs past the end of the file.
+    cut_checkpoint: bool,
+}
+
+/// Walks everything after the header, collecting checkpoints and counting
+/// zstd frames. Unrecognised bytes are stepped over one at a time.
+fn scan_frames(bytes: &[u8]) -> FrameScan<'_> {
+    let mut scan = FrameScan::default();
+    let mut pos = HEADER_SIZE;
+    while pos + 8 <= bytes.len() {
+        let magic = &bytes[pos..pos + 4];
+        if magic == SKIPPABLE_FRAME_MAGIC {
+            let size = read_u32(bytes, pos + 4) as usize;
+            let payload = bytes.get(pos + 8..pos + 8 + size);
+            scan.cut_checkpoint |= payload.is_none();
+            if let Some(checkpoint) = payload.and_then(parse_checkpoint) {
+                match checkpoint {
+                    Checkpoint::Prelude { header, meta } => {
+                        scan.prelude.get_or_insert((header, meta));
+                    }
+                    Checkpoint::Progress { kind, progress } if kind == KIND_SPECTRA => {
+                        scan.spectra.absorb(progress)
+                    }
+                    Checkpoint::Progress { progress, .. } => scan.chromatograms.absorb(progress),
+                }
+                scan.checkpoints += 1;
+                pos += 8 + size;
+                scan.last_good_offset = pos as u64;
+                continue;
+            }
+        } else if magic == ZSTD_FRAME_MAGIC
+            && let Ok(size) = find_frame_compressed_size(&bytes[pos..])
+        {
+            scan.zstd_frames += 1;
+            pos += size.max(1);
+            continue;
+        }
+        pos += 1;
+    }
+    scan
+}
+
+struct RecoveredContainer {
+    /// Intact block payloads followed by a directory over all block ids.
+    container: Vec<u8>,
+    block_count: u32,
+    /// Item index entries, padded with empty entries to the item count.
+    entries: Vec<u8>,
+    arrayrefs: Vec<u8>,
+    lost: Vec<bool>,
+    damaged_blocks: usize,
+}
+
+/// Payload of the sealed block `entry`, if it lies in `bytes` and
+/// decompresses to the length the checkpoint gives.
+fn intact_payload<'a>(
+    bytes: &'a [u8],
+    container_offset: u64,
+    entry: &BlockDirEntry,
+    compressed: bool,
+) -> Option<&'a [u8]> {
+    let start = usize::try_from(container_offset.checked_add(entry.payload_offset)?).ok()?;
+    let end = start.checked_add(usize::try_from(entry.payload_size).ok()?)?;
+    let payload = bytes.get(start..end)?;
+    if !compressed {
+        return (entry.uncompressed_len_bytes == entry.payload_size).then_some(payload);
+    }
+    // Check the frame's own content size before allocating for it.
+    let content_size = get_frame_content_size(payload).ok()??;
+    if content_size != entry.uncompressed_len_bytes {
+        return None;
+    }
+    decompress_zstd(payload, content_size as usize).ok()?;
+    Some(payload)
+}
+
+fn recover_container(
+    bytes: &[u8],
+    progress: &ContainerProgress,
+    item_count: u32,
+    compressed: bool,
+) -> RecoveredContainer {
+    let arrayref_count = progress.arrayrefs.len() / ARRAYREF_SIZE;
+    // Block ids are dense and every block holds at least one array, so a
+    // valid id is below the number of array refs plus sealed blocks; this
+    // also keeps the directory no larger than the checkpoints describing it.
+    let max_blocks = arrayref_count + progress.sealed.len();
+    let mut damaged_blocks = 0;
+    let mut payloads = Vec::new();
+    for (&block_id, entry) in &progress.sealed {
+        let payload = intact_payload(bytes, progress.container_offset, entry, compressed);
+        match payload.filter(|_| (block_id as usize) < max_blocks) {
+            Some(payload) => payloads.push((block_id, entry, payload)),
+            None => damaged_blocks += 1,
+        }
+    }
+
+    let block_count = payloads.last().map_or(0, |&(id, ..)| id + 1);
+    let mut directory = vec![BlockDirEntry::default(); block_count as usize];
+    let mut container = Vec::new();
+    let mut intact = HashSet::new();
+    for (block_id, entry, payload) in payloads {
+        directory[block_id as usize] = BlockDirEntry {
+            payload_offset: container.len() as u64,
+            ..*entry
+        };
+        container.extend_from_slice(payload);
+        intact.insert(block_id);
+    }
+    for entry in &directory {
+        entry.write_to_buffer(&mut container);
+    }
+
+    let arrayrefs = &progress.arrayrefs;
+    let recorded_items = progress.entries.len() / ITEM_ENTRY_SIZE;
+    let lost = (0..item_count as usize)
+        .map(|item| {
+            if item >= recorded_items {
+                return true;
+            }
+            let entry = &progress.entries[item * ITEM_ENTRY_SIZE..];
+            let first = read_u64(entry, 0);
+            let count = read_u64(entry, 8);
+            let Some(end) = first
+                .checked_add(count)
+                .filter(|&end| end <= arrayref_count as u64)
+            else {
+                return true;
+            };
+            (first as usize..end as usize).any(|r| {
+                let raw = &arrayrefs[r * ARRAYREF_SIZE..];
+                !intact.contains(&read_u32(raw, ARRAYREF_BLOCK_ID_AT))
+            })
+        })
+        .collect();
+
+    // The item count comes from the prelude header; entries past those the
+    // checkpoints recorded are empty and their items lost.
+    let mut entries = progress.entries.clone();
+    entries.truncate(item_count as usize * ITEM_ENTRY_SIZE);
+    entries.resize(item_count as usize * ITEM_ENTRY_SIZE, 0);
+
+    RecoveredContainer {
+        container,
+        block_count,
+        entries,
+        arrayrefs: arrayrefs.clone(),
+        lost,
+        damaged_blocks,
+    }
+}
+
+/// Lays the recovered sections out as a regular B000 file.
+fn rebuild(
+    prelude_header: &[u8],
+    meta: &[u8],
+    spectra: &RecoveredContainer,
+    chromatograms: &RecoveredContainer,
+) -> Result<Vec<u8>, String> {
+    let mut header = prelude_header.to_vec();
+    let mut out = vec![0u8; HEADER_SIZE];
+
+    let mut place = |header: &mut [u8], off_at: usize, len_at: usize, section: &[u8]| {
+        out.resize(out.len().next_multiple_of(8), 0);
+        write_u64(header, off_at, out.len() as u64);
+        write_u64(header, len_at, section.len() as u64);
+        out.extend_from_slice(section);
+    };
+
+    place(
+        &mut header,
+        HEADER_OFFSET_PACKED_SPECTRA,
+        HEADER_LEN_PACKED_SPECTRA,
+        &spectra.container,
+    );
+    place(
+        &mut header,
+        HEADER_OFFSET_PACKED_CHROMS,
+        HEADER_LEN_PACKED_CHROMS,
+        &chromatograms.container,
+    );
+    place(
+        &mut header,
+        HEADER_OFFSET_SPEC_ENTRIES,
+        HEADER_LEN_SPEC_ENTRIES,
+        &spectra.entries,
+    );
+    place(
+        &mut header,
+        HEADER_OFFSET_SPEC_ARRAYREFS,
+        HEADER_LEN_SPEC_ARRAYREFS,
+        &spectra.arrayrefs,
+    );
+    place(
+        &mut header,
+        HEADER_OFFSET_CHROM_ENTRIES,
+        HEADER_LEN_CHROM_ENTRIES,
+        &chromatograms.entries,
+    );
+    place(
+        &mut header,
+        HEADER_OFFSET_CHROM_ARRAYREFS,
+        HEADER_LEN_CHROM_ARRAYREFS,
+        &chromatograms.arrayrefs,
+    );
+    for (off_at, len_at) in [
+        (HEADER_OFFSET_SPEC_META, HEADER_LEN_SPEC_META),
+        (HEADER_OFFSET_CHROM_META, HEADER_LEN_CHROM_META),
+        (HEADER_OFFSET_GLOBAL_META, HEADER_LEN_GLOBAL_META),
+    ] {
+        let start = read_u64(prelude_header, off_at) as usize;
+        let len = read_u64(prelude_header, len_at) as usize;
+        let section = start
+            .checked_add(len)
+            .and_then(|end| meta.get(start..end))
+            .ok_or("prelude checkpoint: metadata is truncated")?;
+        place(&mut header, off_at, len_at, section);
+    }
+    write_u32(
+        &mut header,
+        HEADER_SPECTRUM_BLOCK_COUNT,
+        spectra.block_count,
+    );
+    write_u32(
+        &mut header,
+        HEADER_CHROM_BLOCK_COUNT,
+        chromatograms.block_count,
+    );
+
+    out.extend_from_slice(&FILE_TRAILER);
+    out[..HEADER_SIZE].copy_from_slice(&header);
+    Ok(out)
+}
+
+#[inline]
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+#[inline]
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+#[inline]
+fn write_u32(bytes: &mut [u8], at: usize, value: u32) {
+    bytes[at..at + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+#[inline]
+fn write_u64(bytes: &mut [u8], at: usize, value: u64) {
+    bytes[at..at + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        b64::encoder::encode::{Encoder, EncodingConfig, WritingMode},
+        mzml::parse_mzml::parse_mzml,
+    };
+
+    const PATH: &str = "data/mzml/tiny.pwiz.mzML0.99.10.mzML";
+
+    fn encode_with(checkpoint_interval_bytes: Option<usize>) -> (MzML, Vec<u8>) {
+        let mzml = parse_mzml(&std::fs::read(PATH).unwrap()).unwrap();
+        let mut out = Vec::new();
+        Encoder::new(
+            &mut out,
+            EncodingConfig {
+                compression_level: 3,
+                force_f32: false,
+                writing_mode: WritingMode::Streaming,
+            },
+        )
+        .with_checkpoints(checkpoint_interval_bytes)
+        .encode(&mzml)
+        .unwrap();
+        (mzml, out)
+    }
+
+    fn spectra(mzml: &MzML) -> &[Spectrum] {
+        &mzml.run.spectrum_list.as_ref().unwrap().spectra
+    }
+
+    fn header_u64(bytes: &[u8], at: usize) -> usize {
+        read_u64(bytes, at) as usize
+    }
+
+    #[test]
+    fn checkpoints_do_not_change_decoding() {
+        let (_, plain) = encode_with(None);
+        let (_, checkpointed) = encode_with(Some(1));
+        assert!(checkpointed.len() > plain.len());
+
+        let expected = format!("{:?}", decode(&plain).unwrap());
+        assert_eq!(format!("{:?}", decode(&checkpointed).unwrap()), expected);
+
+        let (salvaged, report) = salvage_b000(&checkpointed).unwrap();
+        assert!(report.complete);
+        assert_eq!(report.spectra_recovered, 2);
+        assert_eq!(format!("{salvaged:?}"), expected);
+    }
+
+    #[test]
+    fn truncated_file_keeps_checkpointed_spectra() {
+        let (_, mut bytes) = encode_with(Some(1));
+        let original = decode(&bytes).unwrap();
+        let chroms_at = header_u64(&bytes, HEADER_OFFSET_PACKED_CHROMS);
+        bytes.truncate(chroms_at + 10);
+        bytes[..HEADER_SIZE].fill(0);
+
+        let (mzml, report) = salvage_b000(&bytes).unwrap();
+        assert!(!report.complete);
+        assert_eq!((report.spectra_recovered, report.spectra_lost), (2, 0));
+        assert_eq!(report.chromatograms_lost, 2);
+        assert!(report.last_good_offset <= chroms_at as u64);
+
+        for (got, want) in spectra(&mzml).iter().zip(spectra(&original)) {
+            assert_eq!(got.id, want.id);
+            assert_eq!(
+                format!("{:?}", got.binary_data_array_list),
+                format!("{:?}", want.binary_data_array_list)
+            );
+        }
+        let list = mzml.run.chromatogram_list.as_ref().unwrap();
+        assert!(list.chromatograms.is_empty());
+        assert_eq!(list.count, Some(0));
+
+        let param = mzml.run.user_params.last().unwrap();
+        assert_eq!(param.name, INCOMPLETE_RUN_PARAM);
+        assert_eq!(param.value, Some(report.last_good_offset.to_string()));
+    }
+
+    #[test]
+    fn cut_inside_the_spectra_loses_the_unfinished_ones() {
+        let (_, mut bytes) = encode_with(Some(1));
+        let spectra_at = header_u64(&bytes, HEADER_OFFSET_PACKED_SPECTRA);
+        let spectra_len = header_u64(&bytes, HEADER_LEN_PACKED_SPECTRA);
+        bytes.truncate(spectra_at + spectra_len / 2);
+        bytes[..HEADER_SIZE].fill(0);
+
+        let (mzml, report) = salvage_b000(&bytes).unwrap();
+        assert!(report.spectra_recovered < 2);
+        assert_eq!(report.spectra_recovered + report.spectra_lost, 2);
+        assert_eq!(spectra(&mzml).len(), report.spectra_recovered);
+        assert_eq!(
+            mzml.run.spectrum_list.as_ref().unwrap().count,
+            Some(report.spectra_recovered)
+        );
+    }
+
+    #[test]
+    fn damaged_block_drops_only_its_items() {
+        let (_, mut bytes) = encode_with(Some(1));
+        let spectra_at = header_u64(&bytes, HEADER_OFFSET_PACKED_SPECTRA);
+        bytes[..HEADER_SIZE].fill(0);
+        // First block payload, past its zstd frame header.
+        bytes[spectra_at + 8] ^= 0xFF;
+
+        let (_, report) = salvage_b000(&bytes).unwrap();
+        assert_eq!(report.damaged_blocks, 1);
+        assert!(report.spectra_lost >= 1);
+        assert_eq!(report.chromatograms_recovered, 2);
+    }
+
+    #[test]
+    fn corrupt_checkpoint_blocks_count_as_damaged() {
+        let (_, mut bytes) = encode_with(Some(1));
+        bytes[..HEADER_SIZE].fill(0);
+        // Sealed entries (block id, offset, size, uncompressed length) end
+        // each spectra checkpoint; point them far out of range.
+        let mut sealed = 0;
+        let mut pos = HEADER_SIZE;
+        while let Some(at) = bytes[pos..].windows(5).position(|w| w == b"B0CP\x01") {
+            let frame = pos + at - 8;
+            let end = frame + 8 + read_u32(&bytes, frame + 4) as usize;
+            let body = frame + 16;
+            let count = read_u64(&bytes, body + 40) as usize;
+            for entry in (0..count).map(|i| end - (count - i) * 32) {
+                let (at, value) = if sealed % 2 == 0 {
+                    (entry, u32::MAX as u64)
+                } else {
+                    (entry + 24, u64::MAX / 2)
+                };
+                write_u64(&mut bytes, at, value);
+                sealed += 1;
+            }
+            pos = end;
+        }
+        assert!(sealed >= 2);
+
+        let (mzml, report) = salvage_b000(&bytes).unwrap();
+        assert_eq!(report.damaged_blocks, sealed);
+        assert_eq!(report.spectra_lost, 2);
+        assert!(spectra(&mzml).is_empty());
+        assert_eq!(report.chromatograms_recovered, 2);
+    }
+
+    #[test]
+    fn file_without_checkpoints_cannot_be_salvaged() {
+        let (_, mut bytes) = encode_with(None);
+        bytes[..HEADER_SIZE].fill(0);
+        let err = salvage_b000(&bytes).unwrap_err();
+        assert!(err.contains("no recovery checkpoints"), "{err}");
+    }
+
+    #[test]
+    fn recovery_grows_with_each_complete_checkpoint() {
+        let (_, bytes) = encode_with(Some(1));
+        let mut ends = Vec::new();
+        let mut pos = HEADER_SIZE;
+        while let Some(at) = bytes[pos..]
+            .windows(4)
+            .position(|w| w == SKIPPABLE_FRAME_MAGIC)
+        {
+            let frame = pos + at;
+            pos = frame + 8 + read_u32(&bytes, frame + 4) as usize;
+            ends.push(pos);
+        }
+        let recovered = |len: usize| {
+            let (_, report) = salvage_b000(&bytes[..len]).unwrap();
+            (report.spectra_recovered, report.chromatograms_recovered)
+        };
+
+        // A cut inside the prelude checkpoint keeps nothing.
+        let err = salvage_b000(&bytes[..ends[0] - 1]).unwrap_err();
+        assert!(
+            err.contains("ends inside its first recovery checkpoint"),
+            "{err}"
+        );
+        // Then each checkpoint seals one more block of one item.
+        let stages = [(0, 0), (1, 0), (2, 0), (2, 1), (2, 2)];
+        assert_eq!(ends.len(), stages.len());
+        for (i, (&end, &stage)) in ends.iter().zip(&stages).enumerate() {
+            assert_eq!(recovered(end), stage, "cut at checkpoint {i} end {end}");
+            if let Some(&next) = ends.get(i + 1) {
+                assert_eq!(
+                    recovered(next - 1),
+                    stage,
+                    "cut before checkpoint {}",
+                    i + 1
+                );
+            }
+        }
+    }
+}
@@ -53,6 +53,10 @@ pub(crate) fn assign_attributes<T: Serialize>(
             tag_id,
             accession: Some(accession),
             unit_accession: None,
+            cv_ref: None,
+            unit_cv_ref: None,
+            name: None,
+            unit_name: None,
             value: metadatum_value,
         });
         item_index += 1;
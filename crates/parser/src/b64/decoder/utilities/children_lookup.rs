@@ -181,6 +181,10 @@ mod tests {
             tag_id,
             accession: None,
             unit_accession: None,
+            cv_ref: None,
+            unit_cv_ref: None,
+            name: None,
+            unit_name: None,
             value: MetadatumValue::Empty,
         }
     }
@@ -193,6 +197,10 @@ mod tests {
             tag_id: TagId::CvParam,
             accession: Some(accession.to_string()),
             unit_accession: None,
+            cv_ref: None,
+            unit_cv_ref: None,
+            name: None,
+            unit_name: None,
             value: MetadatumValue::Empty,
         }
     }
@@ -302,6 +310,10 @@ mod tests {
             tag_id: TagId::CvParam,
             accession: Some("B000:9910001".to_string()),
             unit_accession: None,
+            cv_ref: None,
+            unit_cv_ref: None,
+            name: None,
+            unit_name: None,
             value: MetadatumValue::Empty,
         };
         assert!(DefaultMetadataPolicy.should_exclude(&row));
@@ -316,6 +328,10 @@ mod tests {
             tag_id: TagId::CvParam,
             accession: Some("MS:1000511".to_string()),
             unit_accession: None,
+            cv_ref: None,
+            unit_cv_ref: None,
+            name: None,
+            unit_name: None,
             value: MetadatumValue::Empty,
         };
         assert!(!DefaultMetadataPolicy.should_exclude(&row));
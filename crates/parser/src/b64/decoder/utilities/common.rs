@@ -1,8 +1,8 @@
 use zstd::zstd_safe;
 
 use crate::{
-    BinaryData, BinaryDataArray, BinaryDataArrayList,
-    b64::attr_meta::{AccessionTail, CV_CODE_UNKNOWN, cv_ref_code_from_str},
+    BinaryData, BinaryDataArray, BinaryDataArrayList, ExtensionElement, Extensions,
+    b64::attr_meta::{ACC_ATTR_EXTENSION_ATTRIBUTE, ACC_ATTR_EXTENSION_ELEMENT, AccessionTail},
     decoder::decode::{Metadatum, MetadatumValue},
     mzml::schema::{SchemaNode, SchemaTree as Schema, TagId},
 };
@@ -130,18 +130,6 @@ pub(crate) fn decompress_zstd_allow_aligned_padding(
     }
 }
 
-#[inline]
-pub(crate) fn is_cv_prefix(p: &str) -> bool {
-    cv_ref_code_from_str(Some(p)) != CV_CODE_UNKNOWN
-}
-
-#[inline]
-pub(crate) fn unit_cv_ref(unit_accession: Option<&str>) -> Option<String> {
-    unit_accession
-        .and_then(|ua| ua.split_once(':'))
-        .map(|(pref, _)| pref.to_owned())
-}
-
 #[inline]
 pub(crate) fn value_to_opt_string(v: &MetadatumValue) -> Option<String> {
     match v {
@@ -190,6 +178,40 @@ pub(crate) fn get_attr_text(rows: &[&Metadatum], tail: AccessionTail) -> Option<
     None
 }
 
+/// Preserved unmodelled XML stored on `rows`, in write order.
+pub(crate) fn get_extensions(rows: &[&Metadatum]) -> Option<Extensions> {
+    let mut extensions: Option<Extensions> = None;
+    for m in rows {
+        let tail = parse_accession_tail(m.accession.as_deref());
+        let MetadatumValue::Text(text) = &m.value else {
+            continue;
+        };
+        if tail == ACC_ATTR_EXTENSION_ATTRIBUTE {
+            let (name, value) = text.split_once('\0').unwrap_or((text, ""));
+            extensions
+                .get_or_insert_with(Default::default)
+                .attributes
+                .push((name.to_string(), value.to_string()));
+        } else if tail == ACC_ATTR_EXTENSION_ELEMENT {
+            let mut parts = text.splitn(3, '\0');
+            let (Some(parent), Some(position), Some(xml)) =
+                (parts.next(), parts.next(), parts.next())
+            else {
+                continue;
+            };
+            extensions
+                .get_or_insert_with(Default::default)
+                .elements
+                .push(ExtensionElement {
+                    parent: parent.to_string(),
+                    position: position.parse().unwrap_or(usize::MAX),
+                    xml: xml.to_string(),
+                });
+        }
+    }
+    extensions
+}
+
 #[inline]
 pub(crate) fn vs_len_bytes(
     vk: &[u8],
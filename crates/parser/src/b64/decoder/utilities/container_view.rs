@@ -3,7 +3,10 @@ use crate::b64::encoder::utilities::container_builder::{
 };
 use crate::b64::utilities::common::{decompress_zstd, read_u32_le_at, read_u64_le_at, take};
 use crate::mzml::structs::NumericType;
-use std::ops::Deref;
+use std::{
+    io::{Read, Seek, SeekFrom},
+    ops::{Deref, Range},
+};
 
 pub(crate) trait BlockProcessor {
     fn decompress(&self, source: &[u8], target_len: usize) -> Result<Vec<u8>, String>;
@@ -49,9 +52,70 @@ impl<'a> Deref for BlockData<'a> {
     }
 }
 
+impl BlockData<'_> {
+    #[inline]
+    fn into_vec(self) -> Vec<u8> {
+        match self {
+            Self::Borrowed(data) => data.to_vec(),
+            Self::Owned(data) => data,
+        }
+    }
+}
+
+/// Random access to the bytes of a B000 file, either held in memory or read
+/// through a seekable handle.
+pub(crate) trait ByteSource<'a> {
+    /// `len` bytes at file offset `offset`.
+    fn read_at(&mut self, offset: u64, len: u64, ctx: &str) -> Result<BlockData<'a>, String>;
+}
+
+impl<'a> ByteSource<'a> for &'a [u8] {
+    #[inline]
+    fn read_at(&mut self, offset: u64, len: u64, ctx: &str) -> Result<BlockData<'a>, String> {
+        let bytes: &'a [u8] = self;
+        usize::try_from(offset)
+            .ok()
+            .zip(usize::try_from(len).ok())
+            .and_then(|(start, len)| bytes.get(start..start.checked_add(len)?))
+            .map(BlockData::Borrowed)
+            .ok_or_else(|| format!("{ctx}: range error"))
+    }
+}
+
+/// Reads each requested range into a fresh buffer, so only what the caller
+/// keeps stays in memory.
+pub(crate) struct ReadSource<R>(pub(crate) R);
+
+impl<R: Read + Seek> ByteSource<'static> for ReadSource<R> {
+    fn read_at(&mut self, offset: u64, len: u64, ctx: &str) -> Result<BlockData<'static>, String> {
+        self.0
+            .seek(SeekFrom::Start(offset))
+            .map_err(|e| format!("{ctx}: seek failed: {e}"))?;
+        let mut buf = Vec::new();
+        (&mut self.0)
+            .take(len)
+            .read_to_end(&mut buf)
+            .map_err(|e| format!("{ctx}: read failed: {e}"))?;
+        if buf.len() as u64 != len {
+            return Err(format!("{ctx}: range error"));
+        }
+        Ok(BlockData::Owned(buf))
+    }
+}
+
+/// Location of a section in the file.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Section {
+    pub(crate) offset: u64,
+    pub(crate) len: u64,
+}
+
 #[derive(Debug)]
 pub(crate) struct ContainerView<'a, P: BlockProcessor> {
-    raw_data: &'a [u8],
+    /// File offset of the first payload byte.
+    offset: u64,
+    /// Bytes before the block directory.
+    payload_len: u64,
     entries: Vec<BlockDirEntry>,
     cache: Vec<Option<BlockData<'a>>>,
     scratch_buffer: Vec<u8>,
@@ -62,8 +126,10 @@ pub(crate) struct ContainerView<'a, P: BlockProcessor> {
 }
 
 impl<'a, P: BlockProcessor> ContainerView<'a, P> {
-    pub(crate) fn new(
-        raw_data: &'a [u8],
+    /// Reads the block directory at the end of `container`.
+    pub(crate) fn new<S: ByteSource<'a>>(
+        source: &mut S,
+        container: Section,
         block_count: u32,
         compression_level: u8,
         filter: FilterType,
@@ -71,16 +137,15 @@ impl<'a, P: BlockProcessor> ContainerView<'a, P> {
         processor: P,
     ) -> Result<Self, String> {
         let block_count = block_count as usize;
-        let directory_byte_size = block_count * BLOCK_DIRECTORY_ENTRY_SIZE;
-
-        if raw_data.len() < directory_byte_size {
-            return Err(format!(
-                "{ctx}: container too small to hold block directory"
-            ));
-        }
-
-        let directory_start_offset = raw_data.len() - directory_byte_size;
-        let directory_bytes = &raw_data[directory_start_offset..];
+        let directory_byte_size = (block_count as u64)
+            .checked_mul(BLOCK_DIRECTORY_ENTRY_SIZE as u64)
+            .filter(|&size| size <= container.len)
+            .ok_or_else(|| format!("{ctx}: container too small to hold block directory"))?;
+
+        let payload_len = container.len - directory_byte_size;
+        let directory_bytes =
+            source.read_at(container.offset + payload_len, directory_byte_size, ctx)?;
+        let directory_bytes = &directory_bytes[..];
         let mut read_position = 0;
         let mut entries = Vec::with_capacity(block_count);
 
@@ -100,7 +165,8 @@ impl<'a, P: BlockProcessor> ContainerView<'a, P> {
         cache.resize_with(block_count, || None);
 
         Ok(Self {
-            raw_data,
+            offset: container.offset,
+            payload_len,
             entries,
             cache,
             scratch_buffer: Vec::new(),
@@ -112,15 +178,16 @@ impl<'a, P: BlockProcessor> ContainerView<'a, P> {
     }
 
     #[inline]
-    pub(crate) fn get_item_from_block(
+    pub(crate) fn get_item_from_block<S: ByteSource<'a>>(
         &mut self,
+        source: &mut S,
         block_id: u32,
         element_offset: u64,
         element_count: u64,
         element_stride: usize,
         ctx: &'static str,
     ) -> Result<&[u8], String> {
-        self.ensure_block_loaded(block_id, element_stride, ctx)?;
+        self.ensure_block_loaded(source, block_id, element_stride, ctx)?;
 
         let block = self.cache[block_id as usize].as_ref().unwrap();
         let start_byte = (element_offset as usize) * element_stride;
@@ -135,8 +202,17 @@ impl<'a, P: BlockProcessor> ContainerView<'a, P> {
         Ok(&block[start_byte..end_byte])
     }
 
-    fn ensure_block_loaded(
+    /// Drops the decoded copies of `blocks`; they are decoded again if needed.
+    pub(crate) fn release_blocks(&mut self, blocks: Range<usize>) {
+        let end = blocks.end.min(self.cache.len());
+        for slot in &mut self.cache[blocks.start.min(end)..end] {
+            *slot = None;
+        }
+    }
+
+    fn ensure_block_loaded<S: ByteSource<'a>>(
         &mut self,
+        source: &mut S,
         block_id: u32,
         element_stride: usize,
         ctx: &'static str,
@@ -156,22 +232,21 @@ impl<'a, P: BlockProcessor> ContainerView<'a, P> {
         self.record_stride_or_fail(block_index, stride, ctx)?;
 
         let entry = self.entries[block_index];
-        let payload_start = entry.payload_offset as usize;
-        let payload_end = payload_start
-            .checked_add(entry.payload_size as usize)
+        let payload_end = entry
+            .payload_offset
+            .checked_add(entry.payload_size)
             .ok_or_else(|| format!("{ctx}: block {block_index} payload size overflows"))?;
 
-        if payload_end > self.raw_data.len() - (self.entries.len() * BLOCK_DIRECTORY_ENTRY_SIZE) {
+        if payload_end > self.payload_len {
             return Err(format!(
                 "{ctx}: block {block_index} payload exceeds payload region bounds"
             ));
         }
 
-        let decoded = self.run_decode_pipeline(
-            &self.raw_data[payload_start..payload_end],
-            entry.uncompressed_len_bytes as usize,
-            stride,
-        )?;
+        let payload =
+            source.read_at(self.offset + entry.payload_offset, entry.payload_size, ctx)?;
+        let decoded =
+            self.run_decode_pipeline(payload, entry.uncompressed_len_bytes as usize, stride)?;
         self.cache[block_index] = Some(decoded);
         Ok(())
     }
@@ -199,7 +274,7 @@ impl<'a, P: BlockProcessor> ContainerView<'a, P> {
 
     fn run_decode_pipeline(
         &mut self,
-        payload: &'a [u8],
+        payload: BlockData<'a>,
         uncompressed_len: usize,
         stride: Stride,
     ) -> Result<BlockData<'a>, String> {
@@ -213,13 +288,13 @@ impl<'a, P: BlockProcessor> ContainerView<'a, P> {
                     payload.len()
                 ));
             }
-            return Ok(BlockData::Borrowed(payload));
+            return Ok(payload);
         }
 
         let mut decompressed = if self.compression_level == 0 {
-            payload.to_vec()
+            payload.into_vec()
         } else {
-            self.processor.decompress(payload, uncompressed_len)?
+            self.processor.decompress(&payload, uncompressed_len)?
         };
 
         if needs_unshuffle {
@@ -314,6 +389,19 @@ pub(crate) struct BinaryStore {
     slots: Vec<Option<Vec<(u32, ArrayData)>>>,
 }
 
+/// Extracts the arrays of one item at a time, reading its index entry and
+/// array refs from the source on demand. Items are expected roughly in
+/// storage order: the blocks before the first one the current item reads
+/// from are released, so only a few blocks are kept decoded at once.
+pub(crate) struct BinaryCursor<'a> {
+    view: ContainerView<'a, DefaultProcessor>,
+    array_refs: Section,
+    item_index: Section,
+    item_count: usize,
+    context_label: &'static str,
+    released_before: usize,
+}
+
 struct ArrayRef {
     array_type_accession: u32,
     dtype: u8,
@@ -328,6 +416,7 @@ struct ItemIndexEntry {
 }
 
 pub(crate) const ARRAYREF_ENTRY_BYTE_SIZE: u64 = 32;
+const ITEM_INDEX_ENTRY_BYTE_SIZE: u64 = 16;
 
 #[derive(Clone, Debug)]
 pub(crate) enum ArrayData {
@@ -339,36 +428,104 @@ pub(crate) enum ArrayData {
     I64(Vec<i64>),
 }
 
-impl BinaryStore {
-    pub(crate) fn build(
-        container_bytes: &[u8],
-        arrayref_bytes: &[u8],
-        item_index_bytes: &[u8],
+impl<'a> BinaryCursor<'a> {
+    pub(crate) fn new<S: ByteSource<'a>>(
+        source: &mut S,
+        container: Section,
+        array_refs: Section,
+        item_index: Section,
         config: BinaryStoreConfig,
     ) -> Result<Self, String> {
-        let mut view = ContainerView::new(
-            container_bytes,
+        let view = ContainerView::new(
+            source,
+            container,
             config.block_count,
             config.compression_level,
             config.filter,
             config.context_label,
             DefaultProcessor,
         )?;
+        if item_index.len < u64::from(config.item_count) * ITEM_INDEX_ENTRY_BYTE_SIZE {
+            return Err(format!(
+                "{}: item index too small for {} items",
+                config.context_label, config.item_count
+            ));
+        }
 
-        let array_refs = Self::parse_arrayrefs(arrayref_bytes)?;
-        let item_index = Self::parse_item_index(item_index_bytes, config.item_count)?;
+        Ok(Self {
+            view,
+            array_refs,
+            item_index,
+            item_count: config.item_count as usize,
+            context_label: config.context_label,
+            released_before: 0,
+        })
+    }
 
-        let slots = item_index
-            .iter()
-            .map(|entry| {
-                Some(Self::extract_arrays_for_entry(
-                    &mut view,
-                    &array_refs,
-                    entry,
-                ))
-            })
-            .collect();
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.item_count
+    }
+
+    /// Arrays of item `slot_index`, or `None` past the last item.
+    pub(crate) fn read<S: ByteSource<'a>>(
+        &mut self,
+        source: &mut S,
+        slot_index: usize,
+    ) -> Result<Option<Vec<(u32, ArrayData)>>, String> {
+        if slot_index >= self.item_count {
+            return Ok(None);
+        }
+        let ctx = self.context_label;
+        let raw = source.read_at(
+            self.item_index.offset + slot_index as u64 * ITEM_INDEX_ENTRY_BYTE_SIZE,
+            ITEM_INDEX_ENTRY_BYTE_SIZE,
+            ctx,
+        )?;
+        let Some(entry) = BinaryStore::parse_item_index(&raw, 1)?.pop() else {
+            return Ok(None);
+        };
 
+        let refs_len = entry
+            .arrayref_count
+            .checked_mul(ARRAYREF_ENTRY_BYTE_SIZE)
+            .filter(|&len| {
+                entry
+                    .arrayref_start
+                    .checked_mul(ARRAYREF_ENTRY_BYTE_SIZE)
+                    .and_then(|start| start.checked_add(len))
+                    .is_some_and(|end| end <= self.array_refs.len)
+            });
+        let Some(refs_len) = refs_len else {
+            return Ok(Some(Vec::new()));
+        };
+        let raw = source.read_at(
+            self.array_refs.offset + entry.arrayref_start * ARRAYREF_ENTRY_BYTE_SIZE,
+            refs_len,
+            ctx,
+        )?;
+        let refs = BinaryStore::parse_arrayrefs(&raw)?;
+        let arrays = BinaryStore::extract_arrays_for_entry(&mut self.view, source, &refs);
+
+        let first_block = refs.iter().map(|r| r.block_id as usize).min();
+        if let Some(first_block) = first_block
+            && first_block > self.released_before
+        {
+            self.view.release_blocks(self.released_before..first_block);
+            self.released_before = first_block;
+        }
+        Ok(Some(arrays))
+    }
+}
+
+impl BinaryStore {
+    pub(crate) fn collect<'a, S: ByteSource<'a>>(
+        mut cursor: BinaryCursor<'a>,
+        source: &mut S,
+    ) -> Result<Self, String> {
+        let slots = (0..cursor.len())
+            .map(|i| cursor.read(source, i))
+            .collect::<Result<_, _>>()?;
         Ok(Self { slots })
     }
 
@@ -413,23 +570,19 @@ impl BinaryStore {
         Ok(refs)
     }
 
-    fn extract_arrays_for_entry<P: BlockProcessor>(
-        view: &mut ContainerView<'_, P>,
+    fn extract_arrays_for_entry<'a, P: BlockProcessor, S: ByteSource<'a>>(
+        view: &mut ContainerView<'a, P>,
+        source: &mut S,
         array_refs: &[ArrayRef],
-        entry: &ItemIndexEntry,
     ) -> Vec<(u32, ArrayData)> {
-        let range_start = entry.arrayref_start as usize;
-        let range_end = range_start + entry.arrayref_count as usize;
-        if range_end > array_refs.len() {
-            return Vec::new();
-        }
-        array_refs[range_start..range_end]
+        array_refs
             .iter()
             .filter_map(|array_ref| {
                 let (element_stride, numeric_type) =
                     Self::dtype_to_stride_and_type(array_ref.dtype).ok()?;
                 let raw_bytes = view
                     .get_item_from_block(
+                        source,
                         array_ref.block_id,
                         array_ref.element_offset,
                         array_ref.element_count,
@@ -499,18 +652,41 @@ mod tests {
         entry
     }
 
+    fn whole(raw: &[u8]) -> Section {
+        Section {
+            offset: 0,
+            len: raw.len() as u64,
+        }
+    }
+
     #[test]
     fn container_view_rejects_data_smaller_than_directory() {
         let tiny = vec![0u8; 10];
-        let result = ContainerView::new(&tiny, 1, 0, FilterType::None, "test", DefaultProcessor);
+        let result = ContainerView::new(
+            &mut tiny.as_slice(),
+            whole(&tiny),
+            1,
+            0,
+            FilterType::None,
+            "test",
+            DefaultProcessor,
+        );
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("too small"));
     }
 
     #[test]
     fn container_view_accepts_empty_container_with_zero_blocks() {
-        let empty = vec![];
-        let result = ContainerView::new(&empty, 0, 0, FilterType::None, "test", DefaultProcessor);
+        let empty: Vec<u8> = vec![];
+        let result = ContainerView::new(
+            &mut empty.as_slice(),
+            whole(&empty),
+            0,
+            0,
+            FilterType::None,
+            "test",
+            DefaultProcessor,
+        );
         assert!(result.is_ok());
     }
 
@@ -522,9 +698,20 @@ mod tests {
         raw.extend_from_slice(&payload);
         raw.extend_from_slice(&directory);
 
-        let mut view =
-            ContainerView::new(&raw, 1, 0, FilterType::None, "test", DefaultProcessor).unwrap();
-        let result = view.get_item_from_block(0, 1, 1, 4, "test").unwrap();
+        let mut source = raw.as_slice();
+        let mut view = ContainerView::new(
+            &mut source,
+            whole(&raw),
+            1,
+            0,
+            FilterType::None,
+            "test",
+            DefaultProcessor,
+        )
+        .unwrap();
+        let result = view
+            .get_item_from_block(&mut source, 0, 1, 1, 4, "test")
+            .unwrap();
         assert_eq!(result, &[4u8, 5, 6, 7]);
     }
 
@@ -536,9 +723,20 @@ mod tests {
         raw.extend_from_slice(&payload);
         raw.extend_from_slice(&directory);
 
-        let mut view =
-            ContainerView::new(&raw, 1, 0, FilterType::None, "test", DefaultProcessor).unwrap();
-        let result = view.get_item_from_block(0, 0, 2, 4, "test").unwrap();
+        let mut source = raw.as_slice();
+        let mut view = ContainerView::new(
+            &mut source,
+            whole(&raw),
+            1,
+            0,
+            FilterType::None,
+            "test",
+            DefaultProcessor,
+        )
+        .unwrap();
+        let result = view
+            .get_item_from_block(&mut source, 0, 0, 2, 4, "test")
+            .unwrap();
         assert_eq!(result, &[0u8, 1, 2, 3, 4, 5, 6, 7]);
     }
 
@@ -550,18 +748,36 @@ mod tests {
         raw.extend_from_slice(&payload);
         raw.extend_from_slice(&directory);
 
-        let mut view =
-            ContainerView::new(&raw, 1, 0, FilterType::None, "test", DefaultProcessor).unwrap();
-        let result = view.get_item_from_block(0, 0, 3, 4, "test");
+        let mut source = raw.as_slice();
+        let mut view = ContainerView::new(
+            &mut source,
+            whole(&raw),
+            1,
+            0,
+            FilterType::None,
+            "test",
+            DefaultProcessor,
+        )
+        .unwrap();
+        let result = view.get_item_from_block(&mut source, 0, 0, 3, 4, "test");
         assert!(result.is_err());
     }
 
     #[test]
     fn container_view_rejects_invalid_block_id() {
-        let empty = vec![];
-        let mut view =
-            ContainerView::new(&empty, 0, 0, FilterType::None, "test", DefaultProcessor).unwrap();
-        let result = view.get_item_from_block(99, 0, 1, 4, "test");
+        let empty: Vec<u8> = vec![];
+        let mut source = empty.as_slice();
+        let mut view = ContainerView::new(
+            &mut source,
+            whole(&empty),
+            0,
+            0,
+            FilterType::None,
+            "test",
+            DefaultProcessor,
+        )
+        .unwrap();
+        let result = view.get_item_from_block(&mut source, 99, 0, 1, 4, "test");
         assert!(result.is_err());
     }
 
@@ -574,12 +790,14 @@ mod tests {
             filter: FilterType::None,
             context_label: "test",
         };
-        let mut store = BinaryStore::build(&[], &[], &[], config).unwrap();
+        let mut source: &[u8] = &[];
+        let cursor = BinaryCursor::new(&mut source, whole(&[]), whole(&[]), whole(&[]), config);
+        let mut store = BinaryStore::collect(cursor.unwrap(), &mut source).unwrap();
         assert!(store.take(0).is_none());
     }
 
     #[test]
-    fn binary_store_build_with_empty_sections_succeeds() {
+    fn binary_cursor_with_empty_sections_succeeds() {
         let config = BinaryStoreConfig {
             block_count: 0,
             item_count: 0,
@@ -587,7 +805,9 @@ mod tests {
             filter: FilterType::None,
             context_label: "test",
         };
-        assert!(BinaryStore::build(&[], &[], &[], config).is_ok());
+        let mut source: &[u8] = &[];
+        let cursor = BinaryCursor::new(&mut source, whole(&[]), whole(&[]), whole(&[]), config);
+        assert!(cursor.is_ok());
     }
 
     #[test]
@@ -8,7 +8,7 @@ use crate::{
         },
         utilities::{
             children_lookup::{ChildrenLookup, MetadataPolicy, OwnerRows},
-            common::{get_attr_text, get_attr_u32, xy_lengths_from_bdal},
+            common::{get_attr_text, get_attr_u32, get_extensions, xy_lengths_from_bdal},
             parse_binary_data_array_list::parse_binary_data_array_list,
             parse_cv_and_user_params,
         },
@@ -71,6 +71,35 @@ pub(crate) fn parse_chromatogram_list<P: MetadataPolicy>(
     })
 }
 
+/// The chromatogram stored as item `index` of its metadata section, built from
+/// that item's rows alone.
+pub(crate) fn parse_chromatogram_entry<P: MetadataPolicy>(
+    metadata: &[Metadatum],
+    index: u32,
+    default_data_processing_ref: Option<&str>,
+    policy: &P,
+) -> Option<Chromatogram> {
+    let children_lookup = ChildrenLookup::new(metadata);
+    let chromatogram_id = children_lookup
+        .all_ids(TagId::Chromatogram)
+        .first()
+        .copied()?;
+    let mut owner_rows = OwnerRows::with_capacity(metadata.len());
+    for entry in metadata {
+        owner_rows.insert(entry.id, entry);
+    }
+
+    Some(parse_chromatogram(
+        &owner_rows,
+        &children_lookup,
+        chromatogram_id,
+        index,
+        default_data_processing_ref,
+        policy,
+        &mut Vec::new(),
+    ))
+}
+
 #[inline]
 fn parse_chromatogram<'a, P: MetadataPolicy>(
     owner_rows: &'a OwnerRows<'a>,
@@ -120,6 +149,7 @@ fn parse_chromatogram<'a, P: MetadataPolicy>(
             param_buffer,
         ),
         binary_data_array_list,
+        extensions: get_extensions(rows),
     }
 }
 
@@ -2,10 +2,7 @@ use crate::{
     CvParam, UserParam,
     b64::{
         attr_meta::CV_REF_ATTR,
-        utilities::{
-            common::{is_cv_prefix, unit_cv_ref, value_to_opt_string},
-            cv_table,
-        },
+        utilities::{common::value_to_opt_string, cv_table},
     },
     decoder::decode::{Metadatum, MetadatumValue},
     mzml::schema::TagId,
@@ -26,15 +23,14 @@ pub(crate) fn parse_cv_and_user_params(metadata: &[&Metadatum]) -> (Vec<CvParam>
             continue;
         };
 
-        let Some((prefix, _)) = accession.split_once(':') else {
-            continue;
-        };
-
-        if prefix == CV_REF_ATTR || !is_cv_prefix(prefix) {
+        if accession
+            .split_once(':')
+            .is_none_or(|(prefix, _)| prefix == CV_REF_ATTR)
+        {
             continue;
         }
 
-        cv_params.push(parse_cv_param(entry, accession, prefix));
+        cv_params.push(parse_cv_param(entry, accession));
     }
 
     (cv_params, user_params)
@@ -63,30 +59,35 @@ fn parse_user_param(entry: &Metadatum) -> UserParam {
         value,
         r#type: None,
         unit_accession: entry.unit_accession.clone(),
-        unit_cv_ref: unit_cv_ref(entry.unit_accession.as_deref()),
-        unit_name: None,
+        unit_cv_ref: entry.unit_cv_ref.clone(),
+        unit_name: entry.unit_name.clone(),
     }
 }
 
 #[inline]
-fn parse_cv_param(entry: &Metadatum, accession: &str, prefix: &str) -> CvParam {
+fn parse_cv_param(entry: &Metadatum, accession: &str) -> CvParam {
     let unit_accession_str = entry.unit_accession.as_deref();
 
-    let name = cv_table::get(accession)
-        .and_then(|v| v.as_str())
-        .unwrap_or(accession)
-        .to_owned();
+    let name = match &entry.name {
+        Some(name) => name.clone(),
+        None => cv_table::get(accession)
+            .and_then(|v| v.as_str())
+            .unwrap_or(accession)
+            .to_owned(),
+    };
 
-    let unit_name = unit_accession_str
-        .and_then(|unit_acc| cv_table::get(unit_acc).and_then(|v| v.as_str()))
-        .map(str::to_owned);
+    let unit_name = entry.unit_name.clone().or_else(|| {
+        unit_accession_str
+            .and_then(|unit_acc| cv_table::get(unit_acc).and_then(|v| v.as_str()))
+            .map(str::to_owned)
+    });
 
     CvParam {
-        cv_ref: Some(prefix.to_owned()),
+        cv_ref: entry.cv_ref.clone(),
         accession: Some(accession.to_owned()),
         name,
         value: value_to_opt_string(&entry.value),
-        unit_cv_ref: unit_cv_ref(unit_accession_str),
+        unit_cv_ref: entry.unit_cv_ref.clone(),
         unit_name,
         unit_accession: entry.unit_accession.clone(),
     }
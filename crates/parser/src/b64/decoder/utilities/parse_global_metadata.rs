@@ -1,7 +1,11 @@
 use crate::{
-    b64::utilities::{
-        common::{decompress_zstd_allow_aligned_padding, read_u32_le_at},
-        parse_metadata::{HDR_CODEC_NONE, HDR_CODEC_ZSTD, parse_metadata},
+    b64::{
+        file_cv_table::FileCvTable,
+        utilities::{
+            common::{decompress_zstd_allow_aligned_padding, read_u32_le_at},
+            parse_metadata,
+            parse_metadata::{HDR_CODEC_NONE, HDR_CODEC_ZSTD},
+        },
     },
     decoder::decode::Metadatum,
 };
@@ -16,7 +20,7 @@ pub(crate) fn parse_global_metadata(
     str_count: u32,
     compression_codec: u8,
     expected_uncompressed: u64,
-) -> Result<Vec<Metadatum>, String> {
+) -> Result<(Vec<Metadatum>, FileCvTable), String> {
     let expected_byte_count = usize::try_from(expected_uncompressed)
         .map_err(|_| "global metadata: expected_uncompressed overflow".to_string())?;
 
@@ -85,20 +89,25 @@ pub(crate) fn parse_global_metadata(
         return Err("global metadata: item_count mismatch".to_string());
     }
 
+    let cv_table = FileCvTable::read_from(bytes, &mut read_pos)?;
+    let columns_start = read_pos;
+
     let first_index_entry = read_u32_le_at(bytes, &mut read_pos, "first_index_entry")?;
     if first_index_entry != 0 {
         return Err("global metadata: missing header or corrupted CI".to_string());
     }
 
-    parse_metadata(
-        &bytes[GLOBAL_SECTION_HEADER_BYTE_SIZE..],
+    let meta = parse_metadata(
+        &bytes[columns_start..],
         derived_item_count,
         meta_count,
         num_count,
         str_count,
         HDR_CODEC_NONE,
         0,
-    )
+        &cv_table,
+    )?;
+    Ok((meta, cv_table))
 }
 
 #[cfg(test)]
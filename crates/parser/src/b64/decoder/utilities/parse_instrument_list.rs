@@ -255,6 +255,10 @@ mod tests {
             tag_id,
             accession: None,
             unit_accession: None,
+            cv_ref: None,
+            unit_cv_ref: None,
+            name: None,
+            unit_name: None,
             value: MetadatumValue::Empty,
         }
     }
@@ -298,6 +302,10 @@ mod tests {
                 s
             }),
             unit_accession: None,
+            cv_ref: None,
+            unit_cv_ref: None,
+            name: None,
+            unit_name: None,
             value: MetadatumValue::Number(3.0),
         };
         let metadata = vec![make_metadatum(2, 1, TagId::ComponentSource), order_row];
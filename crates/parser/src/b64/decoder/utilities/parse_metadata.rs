@@ -1,5 +1,5 @@
 use crate::{
-    b64::{attr_meta::format_accession, utilities::common::*},
+    b64::{file_cv_table::FileCvTable, utilities::common::*},
     decoder::{
         decode::{Metadatum, MetadatumValue},
         utilities::common::{
@@ -12,6 +12,7 @@ use crate::{
 pub(crate) const HDR_CODEC_NONE: u8 = 0;
 pub(crate) const HDR_CODEC_ZSTD: u8 = 1;
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn parse_metadata(
     bytes: &[u8],
     item_count: u32,
@@ -20,89 +21,178 @@ pub(crate) fn parse_metadata(
     str_count: u32,
     compression_codec: u8,
     expected_uncompressed_bytes: usize,
+    cv_table: &FileCvTable,
 ) -> Result<Vec<Metadatum>, String> {
-    let owned;
-    let bytes = match compression_codec {
-        HDR_CODEC_NONE => bytes,
-        HDR_CODEC_ZSTD => {
-            owned = decompress_zstd_allow_aligned_padding(bytes, expected_uncompressed_bytes)?;
-            owned.as_slice()
+    MetadataColumns::parse(
+        bytes,
+        item_count,
+        meta_count,
+        num_count,
+        str_count,
+        compression_codec,
+        expected_uncompressed_bytes,
+    )?
+    .rows(cv_table)
+}
+
+/// A metadata section kept in its column layout, so the rows of one item
+/// can be decoded at a time without materializing the rest.
+pub(crate) struct MetadataColumns {
+    children_index: Vec<u32>,
+    owner_ids: Vec<u32>,
+    parent_ids: Vec<u32>,
+    tag_ids: Vec<u8>,
+    ref_ids: Vec<u8>,
+    accessions: Vec<u32>,
+    unit_refs: Vec<u8>,
+    unit_accessions: Vec<u32>,
+    value_kinds: Vec<u8>,
+    value_indices: Vec<u32>,
+    numeric_values: Vec<f64>,
+    string_offsets: Vec<u32>,
+    string_lengths: Vec<u32>,
+    string_data: Vec<u8>,
+}
+
+impl MetadataColumns {
+    pub(crate) fn parse(
+        bytes: &[u8],
+        item_count: u32,
+        meta_count: u32,
+        num_count: u32,
+        str_count: u32,
+        compression_codec: u8,
+        expected_uncompressed_bytes: usize,
+    ) -> Result<Self, String> {
+        let owned;
+        let bytes = match compression_codec {
+            HDR_CODEC_NONE => bytes,
+            HDR_CODEC_ZSTD => {
+                owned = decompress_zstd_allow_aligned_padding(bytes, expected_uncompressed_bytes)?;
+                owned.as_slice()
+            }
+            other => return Err(format!("unsupported compression_codec={other}")),
+        };
+
+        let item_count = item_count as usize;
+        let meta_count = meta_count as usize;
+        let num_count = num_count as usize;
+        let str_count = str_count as usize;
+
+        let mut pos = 0usize;
+
+        let children_index = read_u32_vec(bytes, &mut pos, item_count + 1)?;
+        let owner_ids = read_u32_vec(bytes, &mut pos, meta_count)?;
+        let parent_ids = read_u32_vec(bytes, &mut pos, meta_count)?;
+        let tag_ids = take(bytes, &mut pos, meta_count, "metadatum tag id")?.to_vec();
+        let ref_ids = take(bytes, &mut pos, meta_count, "metadatum ref id")?.to_vec();
+        let accessions = read_u32_vec(bytes, &mut pos, meta_count)?;
+        let unit_refs = take(bytes, &mut pos, meta_count, "metadatum unit ref id")?.to_vec();
+        let unit_accessions = read_u32_vec(bytes, &mut pos, meta_count)?;
+        let value_kinds = take(bytes, &mut pos, meta_count, "metadatum value kind")?.to_vec();
+        let value_indices = read_u32_vec(bytes, &mut pos, meta_count)?;
+
+        let numeric_values = read_f64_vec(bytes, &mut pos, num_count)?;
+        let string_offsets = read_u32_vec(bytes, &mut pos, str_count)?;
+        let string_lengths = read_u32_vec(bytes, &mut pos, str_count)?;
+
+        let string_bytes_needed = vs_len_bytes(
+            &value_kinds,
+            &value_indices,
+            &string_offsets,
+            &string_lengths,
+        )?;
+        let string_data = take(bytes, &mut pos, string_bytes_needed, "string values")?.to_vec();
+
+        validate_trailing_bytes(bytes, pos, compression_codec, expected_uncompressed_bytes)?;
+        validate_children_index(&children_index, item_count, meta_count)?;
+
+        Ok(Self {
+            children_index,
+            owner_ids,
+            parent_ids,
+            tag_ids,
+            ref_ids,
+            accessions,
+            unit_refs,
+            unit_accessions,
+            value_kinds,
+            value_indices,
+            numeric_values,
+            string_offsets,
+            string_lengths,
+            string_data,
+        })
+    }
+
+    #[inline]
+    pub(crate) fn item_count(&self) -> usize {
+        self.children_index.len() - 1
+    }
+
+    /// The rows of every item, in item order.
+    pub(crate) fn rows(&self, cv_table: &FileCvTable) -> Result<Vec<Metadatum>, String> {
+        let mut out = Vec::with_capacity(self.owner_ids.len());
+        for item_index in 0..self.item_count() {
+            self.rows_into(item_index, cv_table, &mut out)?;
         }
-        other => return Err(format!("unsupported compression_codec={other}")),
-    };
-
-    let item_count = item_count as usize;
-    let meta_count = meta_count as usize;
-    let num_count = num_count as usize;
-    let str_count = str_count as usize;
-
-    let mut pos = 0usize;
-
-    let children_index = read_u32_vec(bytes, &mut pos, item_count + 1)?;
-    let metadatum_owner_ids = read_u32_vec(bytes, &mut pos, meta_count)?;
-    let metadatum_parent_ids = read_u32_vec(bytes, &mut pos, meta_count)?;
-    let metadatum_tag_ids = take(bytes, &mut pos, meta_count, "metadatum tag id")?;
-    let metadatum_ref_ids = take(bytes, &mut pos, meta_count, "metadatum ref id")?;
-    let metadatum_accessions = read_u32_vec(bytes, &mut pos, meta_count)?;
-    let metadatum_unit_refs = take(bytes, &mut pos, meta_count, "metadatum unit ref id")?;
-    let metadatum_unit_accs = read_u32_vec(bytes, &mut pos, meta_count)?;
-    let value_kinds = take(bytes, &mut pos, meta_count, "metadatum value kind")?;
-    let value_indices = read_u32_vec(bytes, &mut pos, meta_count)?;
-
-    let numeric_values = read_f64_vec(bytes, &mut pos, num_count)?;
-    let string_offsets = read_u32_vec(bytes, &mut pos, str_count)?;
-    let string_lengths = read_u32_vec(bytes, &mut pos, str_count)?;
-
-    let string_bytes_needed = vs_len_bytes(
-        value_kinds,
-        &value_indices,
-        &string_offsets,
-        &string_lengths,
-    )?;
-    let string_data = take(bytes, &mut pos, string_bytes_needed, "string values")?;
-
-    validate_trailing_bytes(bytes, pos, compression_codec, expected_uncompressed_bytes)?;
-    validate_children_index(&children_index, item_count, meta_count)?;
-
-    let mut out = Vec::with_capacity(meta_count);
-
-    for item_index in 0..item_count {
-        let meta_start = children_index[item_index] as usize;
-        let meta_end = children_index[item_index + 1] as usize;
+        Ok(out)
+    }
+
+    /// Appends the rows of item `item_index` to `out`.
+    pub(crate) fn rows_into(
+        &self,
+        item_index: usize,
+        cv_table: &FileCvTable,
+        out: &mut Vec<Metadatum>,
+    ) -> Result<(), String> {
+        let meta_start = self.children_index[item_index] as usize;
+        let meta_end = self.children_index[item_index + 1] as usize;
 
         for meta_index in meta_start..meta_end {
-            let tag_id = TagId::from_u8(metadatum_tag_ids[meta_index]).unwrap_or(TagId::Unknown);
+            let tag_id = TagId::from_u8(self.tag_ids[meta_index]).unwrap_or(TagId::Unknown);
             let value = parse_value(
-                value_kinds[meta_index],
-                value_indices[meta_index],
-                &numeric_values,
-                &string_offsets,
-                &string_lengths,
-                string_data,
+                self.value_kinds[meta_index],
+                self.value_indices[meta_index],
+                &self.numeric_values,
+                &self.string_offsets,
+                &self.string_lengths,
+                &self.string_data,
             )?;
 
-            let accession = format_accession(
-                metadatum_ref_ids[meta_index],
-                metadatum_accessions[meta_index],
-            );
-            let unit_accession = format_accession(
-                metadatum_unit_refs[meta_index],
-                metadatum_unit_accs[meta_index],
-            );
+            let accession =
+                cv_table.format_accession(self.ref_ids[meta_index], self.accessions[meta_index]);
+            let unit_accession = cv_table
+                .format_accession(self.unit_refs[meta_index], self.unit_accessions[meta_index]);
+            let cv_ref = cv_table
+                .cv_prefix(self.ref_ids[meta_index])
+                .map(str::to_owned);
+            let unit_cv_ref = cv_table
+                .cv_prefix(self.unit_refs[meta_index])
+                .map(str::to_owned);
+            let name = cv_table
+                .term(self.accessions[meta_index])
+                .map(|t| t.name.clone());
+            let unit_name = cv_table
+                .term(self.unit_accessions[meta_index])
+                .map(|t| t.name.clone());
 
             out.push(Metadatum {
                 item_index: item_index as u32,
-                id: metadatum_owner_ids[meta_index],
-                parent_id: metadatum_parent_ids[meta_index],
+                id: self.owner_ids[meta_index],
+                parent_id: self.parent_ids[meta_index],
                 tag_id,
                 accession,
                 unit_accession,
+                cv_ref,
+                unit_cv_ref,
+                name,
+                unit_name,
                 value,
             });
         }
+        Ok(())
     }
-
-    Ok(out)
 }
 
 #[inline]
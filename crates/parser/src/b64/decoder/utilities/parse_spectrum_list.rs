@@ -8,7 +8,7 @@ use crate::{
         },
         utilities::{
             children_lookup::{ChildrenLookup, MetadataPolicy, OwnerRows},
-            common::{get_attr_text, get_attr_u32, xy_lengths_from_bdal},
+            common::{get_attr_text, get_attr_u32, get_extensions, xy_lengths_from_bdal},
             parse_binary_data_array_list, parse_cv_and_user_params, parse_precursor_list,
             parse_product_list, parse_scan_list,
         },
@@ -70,6 +70,32 @@ pub(crate) fn parse_spectrum_list<P: MetadataPolicy>(
     })
 }
 
+/// The spectrum stored as item `index` of its metadata section, built from
+/// that item's rows alone.
+pub(crate) fn parse_spectrum_entry<P: MetadataPolicy>(
+    metadata: &[Metadatum],
+    index: u32,
+    default_data_processing_ref: Option<&str>,
+    policy: &P,
+) -> Option<Spectrum> {
+    let children_lookup = ChildrenLookup::new(metadata);
+    let spectrum_id = children_lookup.all_ids(TagId::Spectrum).first().copied()?;
+    let mut owner_rows = OwnerRows::with_capacity(metadata.len());
+    for entry in metadata {
+        owner_rows.insert(entry.id, entry);
+    }
+
+    Some(parse_spectrum(
+        &owner_rows,
+        &children_lookup,
+        spectrum_id,
+        index,
+        default_data_processing_ref,
+        policy,
+        &mut Vec::new(),
+    ))
+}
+
 #[inline]
 fn parse_spectrum<'a, P: MetadataPolicy>(
     owner_rows: &'a OwnerRows<'a>,
@@ -137,6 +163,7 @@ fn parse_spectrum<'a, P: MetadataPolicy>(
         precursor_list: parse_precursor_list(owner_rows, children_lookup, spectrum_id),
         product_list: parse_product_list(owner_rows, children_lookup, spectrum_id),
         binary_data_array_list,
+        extensions: get_extensions(rows),
     }
 }
 
@@ -5,6 +5,7 @@ use std::{
     sync::OnceLock,
 };
 
+use crate::b64::file_cv_table::FileCvTable;
 use crate::{
     b64::{
         attr_meta::*,
@@ -52,6 +53,7 @@ fn spectra_meta_from_test_b64() -> Vec<Metadatum> {
         header.spec_meta_str_count,
         header.compression_codec,
         expected,
+        &FileCvTable::default(),
     )
     .expect("parse_metadata(spectra) failed")
 }
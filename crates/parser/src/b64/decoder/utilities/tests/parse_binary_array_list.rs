@@ -4,6 +4,7 @@ use std::{
     path::PathBuf,
 };
 
+use crate::b64::file_cv_table::FileCvTable;
 use crate::{
     CvParam,
     b64::{
@@ -62,7 +63,14 @@ fn parse_metadata_section_from_test_file(
         .unwrap_or_else(|_| panic!("{section_name}: expected_uncompressed overflow"));
 
     parse_metadata(
-        slice, item_count, meta_count, num_count, str_count, codec_id, expected,
+        slice,
+        item_count,
+        meta_count,
+        num_count,
+        str_count,
+        codec_id,
+        expected,
+        &FileCvTable::default(),
     )
     .unwrap_or_else(|e| panic!("{section_name}: parse_metadata failed: {e}"))
 }
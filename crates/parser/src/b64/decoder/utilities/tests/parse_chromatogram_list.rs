@@ -1,6 +1,7 @@
 use std::{fs, path::PathBuf};
 
 use crate::b64::decoder::decode::Metadatum;
+use crate::b64::file_cv_table::FileCvTable;
 use crate::b64::utilities::children_lookup::{ChildrenLookup, DefaultMetadataPolicy};
 use crate::b64::utilities::{parse_chromatogram_list, parse_header, parse_metadata};
 use crate::{ChromatogramList, CvParam};
@@ -53,7 +54,14 @@ fn parse_metadata_section_from_test_file(
     };
 
     let meta = parse_metadata(
-        slice, item_count, meta_count, num_count, str_count, codec_id, expected,
+        slice,
+        item_count,
+        meta_count,
+        num_count,
+        str_count,
+        codec_id,
+        expected,
+        &FileCvTable::default(),
     )
     .expect("parse_metadata failed");
 
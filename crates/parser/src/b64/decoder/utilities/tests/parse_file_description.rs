@@ -47,6 +47,7 @@ fn parse_global_metadata_from_test_file() -> Vec<Metadatum> {
         header.global_meta_uncompressed_bytes,
     )
     .expect("parse_global_metadata failed")
+    .0
 }
 
 fn must_obj<'a>(v: &'a Value, key: &str) -> &'a serde_json::Map<String, Value> {
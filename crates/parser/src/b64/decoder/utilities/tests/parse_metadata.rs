@@ -5,6 +5,7 @@ use crate::b64::{
     decoder::decode::{Metadatum, MetadatumValue},
     utilities::{parse_header, parse_metadata},
 };
+use crate::b64::file_cv_table::FileCvTable;
 use crate::mzml::schema::TagId;
 
 const PATH: &str = "data/b64/test.b64";
@@ -55,7 +56,14 @@ fn parse_metadata_section_from_test_file(
     };
 
     let meta = parse_metadata(
-        slice, item_count, meta_count, num_count, str_count, codec_id, expected,
+        slice,
+        item_count,
+        meta_count,
+        num_count,
+        str_count,
+        codec_id,
+        expected,
+        &FileCvTable::default(),
     )
     .expect("parse_metadata failed");
 
@@ -1,5 +1,6 @@
 use std::{fs, path::PathBuf};
 
+use crate::b64::file_cv_table::FileCvTable;
 use crate::b64::utilities::children_lookup::{ChildrenLookup, OwnerRows};
 use crate::mzml::schema::TagId;
 use crate::{
@@ -56,7 +57,14 @@ fn parse_metadata_section_from_test_file(
     };
 
     let meta = parse_metadata(
-        slice, item_count, meta_count, num_count, str_count, codec_id, expected,
+        slice,
+        item_count,
+        meta_count,
+        num_count,
+        str_count,
+        codec_id,
+        expected,
+        &FileCvTable::default(),
     )
     .expect("parse_metadata failed");
 
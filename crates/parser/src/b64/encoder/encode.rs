@@ -2,7 +2,12 @@ use std::collections::HashSet;
 
 use crate::{
     BinaryData, NumericType,
-    b64::encoder::utilities::{CompressionMode, ContainerBuilder, DefaultCompressor, FilterType},
+    b64::{
+        checkpoint::{KIND_CHROMATOGRAMS, KIND_PRELUDE, KIND_SPECTRA, Progress, checkpoint_frame},
+        encoder::utilities::{
+            BlockCompressor, CompressionMode, ContainerBuilder, DefaultCompressor, FilterType,
+        },
+    },
     encoder::utilities::{FileHeader, encoder_output::EncoderOutput},
     mzml::structs::{BinaryDataArray, BinaryDataArrayList, Chromatogram, MzML, Spectrum},
 };
@@ -23,6 +28,8 @@ use crate::encoder::utilities::{
 pub const HEADER_SIZE: usize = 512;
 pub const FILE_TRAILER: [u8; 8] = *b"END\0\0\0\0\0";
 pub const TARGET_BLOCK_UNCOMPRESSED_BYTES: usize = 64 * 1024 * 1024;
+/// Suggested interval for `Encoder::with_checkpoints`.
+pub const DEFAULT_CHECKPOINT_INTERVAL_BYTES: usize = 16 * 1024 * 1024;
 
 const ARRAY_FILTER_NONE: u8 = 0;
 const ARRAY_FILTER_BYTE_SHUFFLE: u8 = 1;
@@ -43,11 +50,25 @@ pub enum WritingMode {
 pub struct Encoder<'o> {
     output: &'o mut dyn EncoderOutput,
     config: EncodingConfig,
+    checkpoint_interval_bytes: Option<usize>,
 }
 
 impl<'o> Encoder<'o> {
     pub fn new(output: &'o mut dyn EncoderOutput, config: EncodingConfig) -> Self {
-        Self { output, config }
+        Self {
+            output,
+            config,
+            checkpoint_interval_bytes: None,
+        }
+    }
+
+    /// In streaming mode, writes recovery checkpoints for `salvage_b000`
+    /// after about `interval_bytes` of array data (open blocks are sealed
+    /// early to make them recoverable) and whenever a block fills up.
+    /// Ignored in memory mode.
+    pub fn with_checkpoints(mut self, interval_bytes: Option<usize>) -> Self {
+        self.checkpoint_interval_bytes = interval_bytes;
+        self
     }
 
     pub fn encode(&mut self, mzml: &MzML) -> Result<(), String> {
@@ -89,16 +110,46 @@ impl<'o> Encoder<'o> {
             &chrom_meta,
             &global_meta,
             &global_counts,
+            collector.cv_table(),
             self.config.compression_level,
         );
 
         self.output.write_bytes(&[0u8; HEADER_SIZE])?;
 
         let (spec_arrays, chrom_arrays) = match self.config.writing_mode {
-            WritingMode::Streaming => (
-                pack_arrays_streaming(spectra, self.config, spec_policy, self.output)?,
-                pack_arrays_streaming(chroms, self.config, chrom_policy, self.output)?,
-            ),
+            WritingMode::Streaming => {
+                if self.checkpoint_interval_bytes.is_some() {
+                    let header = Self::build_prelude_header(
+                        &self.config,
+                        &spectrum_meta,
+                        &chrom_meta,
+                        &global_meta,
+                        &compressed,
+                        &global_counts,
+                        spectra.len() as u32,
+                        chroms.len() as u32,
+                    );
+                    self.write_prelude(&header, &compressed)?;
+                }
+                (
+                    pack_arrays_streaming(
+                        spectra,
+                        self.config,
+                        self.checkpoint_interval_bytes,
+                        spec_policy,
+                        KIND_SPECTRA,
+                        self.output,
+                    )?,
+                    pack_arrays_streaming(
+                        chroms,
+                        self.config,
+                        self.checkpoint_interval_bytes,
+                        chrom_policy,
+                        KIND_CHROMATOGRAMS,
+                        self.output,
+                    )?,
+                )
+            }
             WritingMode::Memory => (
                 pack_arrays_into_memory(spectra, self.config, spec_policy, self.output)?,
                 pack_arrays_into_memory(chroms, self.config, chrom_policy, self.output)?,
@@ -126,6 +177,62 @@ impl<'o> Encoder<'o> {
         self.output.patch_bytes_at(0, &header_bytes)
     }
 
+    /// Header for the prelude checkpoint. Metadata offsets are relative to
+    /// the metadata that follows it in the checkpoint; containers are empty.
+    #[allow(clippy::too_many_arguments)]
+    fn build_prelude_header(
+        config: &EncodingConfig,
+        spectrum_meta: &PackedMeta,
+        chrom_meta: &PackedMeta,
+        global_meta: &PackedMeta,
+        compressed: &CompressedMetaSections,
+        global_counts: &GlobalCounts,
+        spectrum_count: u32,
+        chrom_count: u32,
+    ) -> FileHeader {
+        let spec_len = compressed.spectrum_bytes.len() as u64;
+        let chrom_len = compressed.chromatogram_bytes.len() as u64;
+        let offsets = SectionOffsets {
+            offset_spec_entries: 0,
+            offset_spec_arrayrefs: 0,
+            offset_chrom_entries: 0,
+            offset_chrom_arrayrefs: 0,
+            offset_spec_meta: 0,
+            offset_chrom_meta: spec_len,
+            offset_global_meta: spec_len + chrom_len,
+            offset_packed_spectra: 0,
+            offset_packed_chroms: 0,
+        };
+        let no_arrays = PackedArraySection::default();
+        Self::build_header(
+            config,
+            &offsets,
+            &no_arrays,
+            &no_arrays,
+            spectrum_meta,
+            chrom_meta,
+            global_meta,
+            compressed,
+            global_counts,
+            spectrum_count,
+            chrom_count,
+        )
+    }
+
+    fn write_prelude(
+        &mut self,
+        header: &FileHeader,
+        compressed: &CompressedMetaSections,
+    ) -> Result<(), String> {
+        let mut body = vec![0u8; HEADER_SIZE];
+        header.write_into(&mut body);
+        body.extend_from_slice(&compressed.spectrum_bytes);
+        body.extend_from_slice(&compressed.chromatogram_bytes);
+        body.extend_from_slice(&compressed.global_bytes);
+        self.output
+            .write_bytes(&checkpoint_frame(KIND_PRELUDE, &body))
+    }
+
     fn spectra(mzml: &MzML) -> &[Spectrum] {
         mzml.run
             .spectrum_list
@@ -454,6 +561,7 @@ fn write_arrayref_entry(
     buf.extend_from_slice(&[0u8; 7]);
 }
 
+#[derive(Default)]
 struct PackedArraySection {
     block_count: u32,
     container_offset: u64,
@@ -548,10 +656,55 @@ fn pack_arrays_into_memory<T: HasBinaryDataArrayList>(
     })
 }
 
+/// Tracks what the previous checkpoint of a container already covered.
+struct CheckpointCursor {
+    kind: u8,
+    interval_bytes: usize,
+    container_offset: u64,
+    entries_len: usize,
+    arrayrefs_len: usize,
+    bytes_since: usize,
+}
+
+impl CheckpointCursor {
+    /// Writes a checkpoint if blocks were sealed or the interval has passed
+    /// (`force` writes one whenever there is anything new).
+    fn maybe_write<C: BlockCompressor>(
+        &mut self,
+        builder: &mut ContainerBuilder<'_, C>,
+        entries: &[u8],
+        arrayrefs: &[u8],
+        force: bool,
+    ) -> Result<(), String> {
+        if force || self.bytes_since >= self.interval_bytes {
+            builder.seal_open_blocks()?;
+            self.bytes_since = 0;
+        }
+        let sealed = builder.take_sealed();
+        if sealed.is_empty() && !(force && entries.len() > self.entries_len) {
+            return Ok(());
+        }
+        let progress = Progress {
+            container_offset: self.container_offset,
+            first_item: (self.entries_len / 16) as u64,
+            first_arrayref: (self.arrayrefs_len / 32) as u64,
+            entries: &entries[self.entries_len..],
+            arrayrefs: &arrayrefs[self.arrayrefs_len..],
+            sealed,
+        };
+        builder.write_checkpoint(&checkpoint_frame(self.kind, &progress.to_body()))?;
+        self.entries_len = entries.len();
+        self.arrayrefs_len = arrayrefs.len();
+        Ok(())
+    }
+}
+
 fn pack_arrays_streaming<T: HasBinaryDataArrayList>(
     items: &[T],
     config: EncodingConfig,
+    checkpoint_interval_bytes: Option<usize>,
     policy: ArrayPolicy,
+    checkpoint_kind: u8,
     output: &mut dyn EncoderOutput,
 ) -> Result<PackedArraySection, String> {
     let mut index_entries_bytes = Vec::new();
@@ -560,6 +713,14 @@ fn pack_arrays_streaming<T: HasBinaryDataArrayList>(
     let mut arrayref_cursor: u64 = 0;
 
     let container_offset = write_aligned_section(output, &[])?;
+    let mut checkpoints = checkpoint_interval_bytes.map(|interval_bytes| CheckpointCursor {
+        kind: checkpoint_kind,
+        interval_bytes,
+        container_offset,
+        entries_len: 0,
+        arrayrefs_len: 0,
+        bytes_since: 0,
+    });
 
     let mut container_builder = ContainerBuilder::new(
         output,
@@ -601,12 +762,32 @@ fn pack_arrays_streaming<T: HasBinaryDataArrayList>(
                 );
                 arrayref_cursor += 1;
                 arrayref_count += 1;
+                if let Some(cursor) = checkpoints.as_mut() {
+                    cursor.bytes_since += data.element_count() * elem_bytes;
+                }
             }
         }
         write_u64_le(&mut index_entries_bytes, arrayref_start);
         write_u64_le(&mut index_entries_bytes, arrayref_count);
+
+        if let Some(cursor) = checkpoints.as_mut() {
+            cursor.maybe_write(
+                &mut container_builder,
+                &index_entries_bytes,
+                &array_refs_bytes,
+                false,
+            )?;
+        }
     }
 
+    if let Some(cursor) = checkpoints.as_mut() {
+        cursor.maybe_write(
+            &mut container_builder,
+            &index_entries_bytes,
+            &array_refs_bytes,
+            true,
+        )?;
+    }
     let (block_count, container_total_bytes) = container_builder.finish()?;
 
     Ok(PackedArraySection {
@@ -625,6 +806,7 @@ mod tests {
     use crate::b64::utilities::parse_header::{
         HEADER_CHROM_BLOCK_COUNT, HEADER_SPECTRUM_BLOCK_COUNT,
     };
+    use crate::{CvParam, Run, Sample, SampleList, SpectrumList};
 
     #[test]
     fn encoder_struct_and_free_fn_are_equivalent() {
@@ -860,4 +1042,126 @@ mod tests {
         assert_eq!(element_byte_size_for_dtype(FILE_DTYPE_I32), 4);
         assert_eq!(element_byte_size_for_dtype(FILE_DTYPE_I64), 8);
     }
+
+    fn lims_cv_param(cv_ref: &str, accession: &str, name: &str, value: Option<&str>) -> CvParam {
+        CvParam {
+            cv_ref: Some(cv_ref.to_string()),
+            accession: Some(accession.to_string()),
+            name: name.to_string(),
+            value: value.map(str::to_string),
+            ..CvParam::default()
+        }
+    }
+
+    #[test]
+    fn arbitrary_cv_terms_round_trip_through_decode() {
+        let sample_params = vec![
+            lims_cv_param("NCIT", "NCIT:C25330", "Duration", None),
+            lims_cv_param("BTO", "BTO:0000142", "brain", None),
+            lims_cv_param("EFO", "EFO:0000408", "disease", Some("healthy")),
+        ];
+        let spectrum_params = vec![
+            lims_cv_param("MS", "MS:1000511", "ms level", Some("2")),
+            lims_cv_param("UNIMOD", "UNIMOD:35", "Oxidation", None),
+            lims_cv_param("MOD", "MOD:00719", "L-methionine sulfoxide", None),
+        ];
+        let mzml = MzML {
+            sample_list: Some(SampleList {
+                count: Some(1),
+                samples: vec![Sample {
+                    id: "s1".to_string(),
+                    name: "s1".to_string(),
+                    cv_params: sample_params.clone(),
+                    ..Sample::default()
+                }],
+                ..SampleList::default()
+            }),
+            run: Run {
+                id: "run".to_string(),
+                spectrum_list: Some(SpectrumList {
+                    count: Some(1),
+                    spectra: vec![Spectrum {
+                        id: "scan=1".to_string(),
+                        cv_params: spectrum_params.clone(),
+                        ..Spectrum::default()
+                    }],
+                    ..SpectrumList::default()
+                }),
+                ..Run::default()
+            },
+            ..MzML::default()
+        };
+
+        let mut buf = Vec::new();
+        encode(&mzml, 3, false, WritingMode::Memory, &mut buf).unwrap();
+        let decoded = crate::b64::decode(&buf).unwrap();
+
+        let key = |p: &CvParam| {
+            (
+                p.cv_ref.clone(),
+                p.accession.clone(),
+                p.name.clone(),
+                p.value.clone(),
+            )
+        };
+        let sample = &decoded.sample_list.unwrap().samples[0];
+        let got: Vec<_> = sample.cv_params.iter().map(key).collect();
+        let want: Vec<_> = sample_params.iter().map(key).collect();
+        assert_eq!(got, want);
+
+        let spectrum = &decoded.run.spectrum_list.unwrap().spectra[0];
+        for expected in &spectrum_params[1..] {
+            assert!(
+                spectrum.cv_params.iter().any(|p| key(p) == key(expected)),
+                "missing {:?} in {:?}",
+                expected.accession,
+                spectrum.cv_params
+            );
+        }
+    }
+
+    #[test]
+    fn cv_ref_is_kept_apart_from_the_accession_prefix() {
+        let params = vec![
+            lims_cv_param("PSI-MOD", "MOD:00719", "L-methionine sulfoxide", None),
+            CvParam {
+                unit_cv_ref: Some("PATO".to_string()),
+                unit_accession: Some("UO:0000010".to_string()),
+                unit_name: Some("second".to_string()),
+                ..lims_cv_param("MS", "MS:1000016", "scan start time", Some("1.5"))
+            },
+        ];
+        let mzml = MzML {
+            run: Run {
+                id: "run".to_string(),
+                spectrum_list: Some(SpectrumList {
+                    count: Some(1),
+                    spectra: vec![Spectrum {
+                        id: "scan=1".to_string(),
+                        cv_params: params.clone(),
+                        ..Spectrum::default()
+                    }],
+                    ..SpectrumList::default()
+                }),
+                ..Run::default()
+            },
+            ..MzML::default()
+        };
+
+        let mut buf = Vec::new();
+        encode(&mzml, 3, false, WritingMode::Memory, &mut buf).unwrap();
+        let decoded = crate::b64::decode(&buf).unwrap();
+
+        let spectrum = &decoded.run.spectrum_list.unwrap().spectra[0];
+        for expected in &params {
+            let got = spectrum
+                .cv_params
+                .iter()
+                .find(|p| p.accession == expected.accession)
+                .unwrap_or_else(|| panic!("missing {:?}", expected.accession));
+            assert_eq!(got.cv_ref, expected.cv_ref);
+            assert_eq!(got.unit_cv_ref, expected.unit_cv_ref);
+            assert_eq!(got.unit_accession, expected.unit_accession);
+        }
+    }
 }
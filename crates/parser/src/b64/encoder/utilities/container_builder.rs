@@ -105,7 +105,7 @@ pub(crate) enum CompressionMode<C: BlockCompressor> {
     Compressed(C),
 }
 
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
 pub(crate) struct BlockDirEntry {
     pub(crate) payload_offset: u64,
     pub(crate) payload_size: u64,
@@ -113,7 +113,7 @@ pub(crate) struct BlockDirEntry {
 }
 
 impl BlockDirEntry {
-    fn write_to_buffer(&self, buffer: &mut Vec<u8>) {
+    pub(crate) fn write_to_buffer(&self, buffer: &mut Vec<u8>) {
         buffer.extend_from_slice(&self.payload_offset.to_le_bytes());
         buffer.extend_from_slice(&self.payload_size.to_le_bytes());
         buffer.extend_from_slice(&self.uncompressed_len_bytes.to_le_bytes());
@@ -295,6 +295,8 @@ pub(crate) struct ContainerBuilder<'output, C: BlockCompressor> {
     store: BlockStore,
     seal_scratch: SealScratch,
     compressor: CompressionMode<C>,
+    /// Blocks sealed since the last `take_sealed`, for checkpoints.
+    newly_sealed: Vec<(u32, BlockDirEntry)>,
 }
 
 impl<'output, C: BlockCompressor> ContainerBuilder<'output, C> {
@@ -311,9 +313,30 @@ impl<'output, C: BlockCompressor> ContainerBuilder<'output, C> {
             store: BlockStore::new(max_block_uncompressed_size),
             seal_scratch: SealScratch::new(),
             compressor,
+            newly_sealed: Vec::new(),
         }
     }
 
+    /// Directory entries of the blocks sealed since the previous call.
+    pub(crate) fn take_sealed(&mut self) -> Vec<(u32, BlockDirEntry)> {
+        std::mem::take(&mut self.newly_sealed)
+    }
+
+    pub(crate) fn seal_open_blocks(&mut self) -> Result<(), String> {
+        for stride in Stride::all_variants() {
+            self.seal_open_block_for_stride(stride)?;
+        }
+        Ok(())
+    }
+
+    /// Writes `frame` between block payloads. Block offsets stay explicit in
+    /// the directory, so readers skip it.
+    pub(crate) fn write_checkpoint(&mut self, frame: &[u8]) -> Result<(), String> {
+        self.output.write_bytes(frame)?;
+        self.cumulative_payload_bytes += frame.len() as u64;
+        Ok(())
+    }
+
     pub(crate) fn add_item_to_box<WriteAction>(
         &mut self,
         item_byte_size: usize,
@@ -393,14 +416,13 @@ impl<'output, C: BlockCompressor> ContainerBuilder<'output, C> {
             self.compress_and_write_block_payload(&active_block.accumulated_data, stride)?;
 
         self.cumulative_payload_bytes += written_byte_len;
-        self.store.seal(
-            active_block.block_id,
-            BlockDirEntry {
-                payload_offset,
-                payload_size: written_byte_len,
-                uncompressed_len_bytes: uncompressed_byte_len,
-            },
-        )
+        let entry = BlockDirEntry {
+            payload_offset,
+            payload_size: written_byte_len,
+            uncompressed_len_bytes: uncompressed_byte_len,
+        };
+        self.newly_sealed.push((active_block.block_id, entry));
+        self.store.seal(active_block.block_id, entry)
     }
 
     fn compress_and_write_block_payload(
@@ -438,9 +460,7 @@ impl<'output, C: BlockCompressor> ContainerBuilder<'output, C> {
     }
 
     pub(crate) fn finish(mut self) -> Result<(u32, u64), String> {
-        for stride in Stride::all_variants() {
-            self.seal_open_block_for_stride(stride)?;
-        }
+        self.seal_open_blocks()?;
 
         let block_count = self.store.block_count();
         let mut directory_bytes =
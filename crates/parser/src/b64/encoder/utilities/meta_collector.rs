@@ -8,11 +8,13 @@ use crate::{
         attr_meta::{
             ACC_ATTR_COUNT, ACC_ATTR_CV_FULL_NAME, ACC_ATTR_CV_URI, ACC_ATTR_CV_VERSION,
             ACC_ATTR_DEFAULT_INSTRUMENT_CONFIGURATION_REF, ACC_ATTR_DEFAULT_SOURCE_FILE_REF,
-            ACC_ATTR_ID, ACC_ATTR_INDEX, ACC_ATTR_INSTRUMENT_CONFIGURATION_REF, ACC_ATTR_LABEL,
-            ACC_ATTR_LOCATION, ACC_ATTR_NAME, ACC_ATTR_ORDER, ACC_ATTR_REF, ACC_ATTR_SAMPLE_REF,
+            ACC_ATTR_EXTENSION_ATTRIBUTE, ACC_ATTR_EXTENSION_ELEMENT, ACC_ATTR_ID, ACC_ATTR_INDEX,
+            ACC_ATTR_INSTRUMENT_CONFIGURATION_REF, ACC_ATTR_LABEL, ACC_ATTR_LOCATION,
+            ACC_ATTR_NAME, ACC_ATTR_ORDER, ACC_ATTR_REF, ACC_ATTR_SAMPLE_REF,
             ACC_ATTR_START_TIME_STAMP, ACC_ATTR_VERSION, AccessionTail, CV_REF_ATTR, attr_cv_param,
-            cv_ref_code_from_str, parse_accession_tail,
+            parse_accession_tail,
         },
+        file_cv_table::FileCvTable,
         utilities::assign_attributes,
     },
     decoder::decode::MetadatumValue,
@@ -20,9 +22,9 @@ use crate::{
     mzml::{
         schema::TagId,
         structs::{
-            BinaryDataArray, BinaryDataArrayList, Chromatogram, CvParam, MzML, Precursor, Product,
-            ReferenceableParamGroup, ReferenceableParamGroupRef, ScanList, Spectrum,
-            SpectrumDescription,
+            BinaryDataArray, BinaryDataArrayList, Chromatogram, CvParam, Extensions, MzML,
+            Precursor, Product, ReferenceableParamGroup, ReferenceableParamGroupRef, ScanList,
+            Spectrum, SpectrumDescription,
         },
     },
 };
@@ -37,12 +39,14 @@ const USER_PARAM_NAME_VALUE_SEPARATOR: char = '\0';
 
 pub(crate) struct MetaCollector<'m> {
     ctx: TraversalCtx<'m>,
+    cv_table: FileCvTable,
 }
 
 impl<'m> MetaCollector<'m> {
     pub(crate) fn new(ref_groups: &'m HashMap<&'m str, &'m ReferenceableParamGroup>) -> Self {
         Self {
             ctx: TraversalCtx::new(ref_groups),
+            cv_table: FileCvTable::default(),
         }
     }
 
@@ -62,11 +66,25 @@ impl<'m> MetaCollector<'m> {
         T: MzmlListItem,
         L: Serialize,
     {
-        pack_item_list_meta(items, list_node_id, list_schema, &mut self.ctx, policy)
+        pack_item_list_meta(
+            items,
+            list_node_id,
+            list_schema,
+            &mut self.ctx,
+            &mut self.cv_table,
+            policy,
+        )
     }
 
     pub(crate) fn collect_global_meta(&mut self, mzml: &MzML) -> (PackedMeta, GlobalCounts) {
-        pack_global_meta(mzml, &mut self.ctx)
+        pack_global_meta(mzml, &mut self.ctx, &mut self.cv_table)
+    }
+
+    /// CVs and terms registered while collecting; complete once
+    /// `collect_global_meta` has run.
+    #[inline]
+    pub(crate) fn cv_table(&self) -> &FileCvTable {
+        &self.cv_table
     }
 }
 
@@ -132,11 +150,12 @@ impl CompressedMetaSections {
         chrom_meta: &PackedMeta,
         global_meta: &PackedMeta,
         counts: &GlobalCounts,
+        cv_table: &FileCvTable,
         level: u8,
     ) -> Self {
         let raw_s = serialize_packed_meta(spectrum_meta);
         let raw_c = serialize_packed_meta(chrom_meta);
-        let raw_g = serialize_global_meta_with_counts(counts, global_meta);
+        let raw_g = serialize_global_meta_with_counts(counts, cv_table, global_meta);
         Self {
             spectrum_uncompressed_size: raw_s.len() as u64,
             chromatogram_uncompressed_size: raw_c.len() as u64,
@@ -390,6 +409,41 @@ impl<'b> MetaParamWriter<'b> {
             }
         }
     }
+    fn push_extensions(
+        &mut self,
+        tag: TagId,
+        owner_id: u32,
+        parent_id: u32,
+        extensions: Option<&Extensions>,
+    ) {
+        let Some(extensions) = extensions else {
+            return;
+        };
+        for (name, value) in &extensions.attributes {
+            let packed = format!("{name}{USER_PARAM_NAME_VALUE_SEPARATOR}{value}");
+            self.push_str_attr(
+                tag,
+                owner_id,
+                parent_id,
+                ACC_ATTR_EXTENSION_ATTRIBUTE,
+                &packed,
+            );
+        }
+        for element in &extensions.elements {
+            let sep = USER_PARAM_NAME_VALUE_SEPARATOR;
+            let packed = format!(
+                "{}{sep}{}{sep}{}",
+                element.parent, element.position, element.xml
+            );
+            self.push_str_attr(
+                tag,
+                owner_id,
+                parent_id,
+                ACC_ATTR_EXTENSION_ELEMENT,
+                &packed,
+            );
+        }
+    }
     fn push_schema_attrs<T: Serialize>(
         &mut self,
         tag: TagId,
@@ -455,29 +509,46 @@ impl PackedMetaBuilder {
         b
     }
 
-    fn flush_buffer(&mut self, buffer: &MetaParamBuffer) {
+    fn flush_buffer(&mut self, buffer: &MetaParamBuffer, cv_table: &mut FileCvTable) {
         for row in &buffer.rows {
-            self.push_row(row.tag_id, row.owner_id, row.parent_id, &row.cv_param);
+            self.push_row(
+                row.tag_id,
+                row.owner_id,
+                row.parent_id,
+                &row.cv_param,
+                cv_table,
+            );
         }
         self.end_item();
     }
 
-    fn push_row(&mut self, tag_id: u8, owner_id: u32, parent_id: u32, cv_param: &CvParam) {
+    fn push_row(
+        &mut self,
+        tag_id: u8,
+        owner_id: u32,
+        parent_id: u32,
+        cv_param: &CvParam,
+        cv_table: &mut FileCvTable,
+    ) {
         self.tag_ids.push(tag_id);
         self.ids.push(owner_id);
         self.parent_indices.push(parent_id);
 
-        let cv_ref = cv_ref_prefix_from_accession(cv_param.accession.as_deref())
-            .or(cv_param.cv_ref.as_deref());
-        self.ref_codes.push(cv_ref_code_from_str(cv_ref));
-        self.accession_numbers
-            .push(parse_accession_tail_raw(cv_param.accession.as_deref()));
+        let (ref_code, accession) = cv_table.encode_term(
+            cv_param.cv_ref.as_deref(),
+            cv_param.accession.as_deref(),
+            Some(cv_param.name.as_str()),
+        );
+        self.ref_codes.push(ref_code);
+        self.accession_numbers.push(accession);
 
-        let unit_ref = cv_ref_prefix_from_accession(cv_param.unit_accession.as_deref())
-            .or(cv_param.unit_cv_ref.as_deref());
-        self.unit_ref_codes.push(cv_ref_code_from_str(unit_ref));
-        self.unit_accession_numbers
-            .push(parse_accession_tail_raw(cv_param.unit_accession.as_deref()));
+        let (unit_ref_code, unit_accession) = cv_table.encode_term(
+            cv_param.unit_cv_ref.as_deref(),
+            cv_param.unit_accession.as_deref(),
+            cv_param.unit_name.as_deref(),
+        );
+        self.unit_ref_codes.push(unit_ref_code);
+        self.unit_accession_numbers.push(unit_accession);
 
         let enc = self.value_pool.encode(cv_param.value.as_deref());
         self.value_kinds.push(enc.kind);
@@ -559,10 +630,6 @@ pub(crate) fn parse_accession_tail_raw(accession: Option<&str>) -> u32 {
     parse_accession_tail(accession).raw()
 }
 
-fn cv_ref_prefix_from_accession(accession: Option<&str>) -> Option<&str> {
-    accession.and_then(|s| s.split_once(':').map(|(prefix, _)| prefix))
-}
-
 pub(crate) fn array_type_accession_from_binary_data_array(bda: &BinaryDataArray) -> u32 {
     for cv in &bda.cv_params {
         let t = parse_accession_tail_raw(cv.accession.as_deref());
@@ -679,7 +746,11 @@ fn serialize_packed_meta(m: &PackedMeta) -> Vec<u8> {
     buf
 }
 
-fn serialize_global_meta_with_counts(counts: &GlobalCounts, m: &PackedMeta) -> Vec<u8> {
+fn serialize_global_meta_with_counts(
+    counts: &GlobalCounts,
+    cv_table: &FileCvTable,
+    m: &PackedMeta,
+) -> Vec<u8> {
     let mut buf = Vec::with_capacity(9 * 4 + packed_meta_byte_size(m));
     for n in [
         counts.n_file_description,
@@ -694,6 +765,7 @@ fn serialize_global_meta_with_counts(counts: &GlobalCounts, m: &PackedMeta) -> V
     ] {
         write_u32_le(&mut buf, n);
     }
+    cv_table.write_into(&mut buf);
     write_packed_meta(&mut buf, m);
     buf
 }
@@ -708,6 +780,7 @@ fn compress_bytes_if_enabled(bytes: Vec<u8>, level: u8) -> Vec<u8> {
 
 fn pack_meta_for_each<F: FnMut(&mut MetaParamWriter<'_>, usize)>(
     item_count: usize,
+    cv_table: &mut FileCvTable,
     mut fill: F,
 ) -> PackedMeta {
     let mut builder = PackedMetaBuilder::new();
@@ -716,7 +789,7 @@ fn pack_meta_for_each<F: FnMut(&mut MetaParamWriter<'_>, usize)>(
         buffer.clear();
         fill(&mut buffer.as_writer(), i);
         buffer.normalize_attr_cv_values();
-        builder.flush_buffer(&buffer);
+        builder.flush_buffer(&buffer, cv_table);
     }
     builder.build()
 }
@@ -738,6 +811,7 @@ pub(crate) trait MzmlListItem: Serialize {
     fn cv_params(&self) -> &[CvParam];
     fn user_params(&self) -> &[UserParam];
     fn group_refs(&self) -> &[ReferenceableParamGroupRef];
+    fn extensions(&self) -> Option<&Extensions>;
     fn flatten_children(
         &self,
         writer: &mut MetaParamWriter<'_>,
@@ -766,6 +840,9 @@ impl MzmlListItem for Spectrum {
     fn group_refs(&self) -> &[ReferenceableParamGroupRef] {
         &self.referenceable_param_group_refs
     }
+    fn extensions(&self) -> Option<&Extensions> {
+        self.extensions.as_ref()
+    }
     fn flatten_children(
         &self,
         writer: &mut MetaParamWriter<'_>,
@@ -796,6 +873,9 @@ impl MzmlListItem for Chromatogram {
     fn group_refs(&self) -> &[ReferenceableParamGroupRef] {
         &self.referenceable_param_group_refs
     }
+    fn extensions(&self) -> Option<&Extensions> {
+        self.extensions.as_ref()
+    }
     fn flatten_children(
         &self,
         writer: &mut MetaParamWriter<'_>,
@@ -812,13 +892,14 @@ fn pack_item_list_meta<T, L>(
     list_node_id: u32,
     list_schema: Option<&L>,
     ctx: &mut TraversalCtx<'_>,
+    cv_table: &mut FileCvTable,
     policy: ArrayPolicy,
 ) -> PackedMeta
 where
     T: MzmlListItem,
     L: Serialize,
 {
-    pack_meta_for_each(items.len(), |writer, i| {
+    pack_meta_for_each(items.len(), cv_table, |writer, i| {
         let item = &items[i];
         if i == 0 && list_node_id != 0 {
             if let Some(schema) = list_schema {
@@ -839,6 +920,7 @@ where
         }
         writer.push_ref_group_params(item_id, list_node_id, item.group_refs(), ctx.ref_groups);
         writer.push_cv_and_user_params(item_id, list_node_id, item.cv_params(), item.user_params());
+        writer.push_extensions(T::item_tag(), item_id, list_node_id, item.extensions());
         item.flatten_children(writer, item_id, ctx, policy);
     })
 }
@@ -1053,6 +1135,12 @@ fn flatten_scan_list(
         let scan_id = ctx.alloc();
         writer.touch(TagId::Scan, scan_id, sl_id);
         writer.push_schema_attrs(TagId::Scan, scan_id, sl_id, scan);
+        writer.push_ref_group_params(
+            scan_id,
+            sl_id,
+            &scan.referenceable_param_group_refs,
+            ctx.ref_groups,
+        );
         writer.push_cv_and_user_params(scan_id, sl_id, &scan.cv_params, &scan.user_params);
         if let Some(swl) = &scan.scan_window_list {
             let swl_id = ctx.alloc();
@@ -1072,7 +1160,11 @@ fn flatten_scan_list(
     }
 }
 
-fn pack_global_meta(mzml: &MzML, ctx: &mut TraversalCtx<'_>) -> (PackedMeta, GlobalCounts) {
+fn pack_global_meta(
+    mzml: &MzML,
+    ctx: &mut TraversalCtx<'_>,
+    cv_table: &mut FileCvTable,
+) -> (PackedMeta, GlobalCounts) {
     let mut buffers: Vec<MetaParamBuffer> = Vec::new();
 
     let n_file_description = append_file_description_meta(mzml, ctx, &mut buffers);
@@ -1098,7 +1190,7 @@ fn pack_global_meta(mzml: &MzML, ctx: &mut TraversalCtx<'_>) -> (PackedMeta, Glo
     };
     let mut builder = PackedMetaBuilder::new();
     for buffer in &buffers {
-        builder.flush_buffer(buffer);
+        builder.flush_buffer(buffer, cv_table);
     }
     (builder.build(), counts)
 }
@@ -1237,6 +1329,12 @@ fn append_run_meta(
             );
         }
         writer.push_cv_and_user_params(run_id, 0, &run.cv_params, &run.user_params);
+        writer.push_extensions(TagId::Run, run_id, 0, run.extensions.as_ref());
+        // Document-level extensions ride along with the run item.
+        if mzml.extensions.is_some() {
+            let mzml_id = ctx.alloc();
+            writer.push_extensions(TagId::MzML, mzml_id, 0, mzml.extensions.as_ref());
+        }
     });
     1
 }
@@ -1283,6 +1381,7 @@ fn append_samples_meta(
             if let Some(gr) = &sample.referenceable_param_group_ref {
                 writer.push_ref_group_params(sid, 0, slice::from_ref(gr), ctx.ref_groups);
             }
+            writer.push_cv_and_user_params(sid, 0, &sample.cv_params, &sample.user_params);
         });
     }
     list.samples.len() as u32
@@ -1680,7 +1779,7 @@ mod tests {
         let mut buffer = MetaParamBuffer::new();
         buffer.push(TagId::CvParam, 1, 0, empty_cv_param());
         buffer.push(TagId::CvParam, 1, 0, empty_cv_param());
-        builder.flush_buffer(&buffer);
+        builder.flush_buffer(&buffer, &mut FileCvTable::default());
         let meta = builder.build();
         assert_eq!(meta.index_offsets, vec![0, 2]);
         assert_eq!(meta.ids.len(), 2);
@@ -1791,7 +1890,8 @@ mod tests {
             n_acquisition_settings: 0,
             n_cvs: 0,
         };
-        let compressed = CompressedMetaSections::build(&meta, &meta, &meta, &counts, 0);
+        let compressed =
+            CompressedMetaSections::build(&meta, &meta, &meta, &counts, &FileCvTable::default(), 0);
         let raw_s = serialize_packed_meta(&meta);
         assert_eq!(compressed.spectrum_bytes, raw_s);
         assert_eq!(compressed.spectrum_uncompressed_size, raw_s.len() as u64);
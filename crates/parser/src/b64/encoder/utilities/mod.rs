@@ -1,6 +1,6 @@
 pub(crate) mod container_builder;
 pub(crate) use container_builder::{
-    CompressionMode, ContainerBuilder, DefaultCompressor, FilterType,
+    BlockCompressor, CompressionMode, ContainerBuilder, DefaultCompressor, FilterType,
 };
 pub(crate) mod encoder_output;
 pub use encoder_output::FileEncoderOutput;
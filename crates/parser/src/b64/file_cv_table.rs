@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+
+use crate::b64::{
+    attr_meta::{
+        CV_CODE_IMS, CV_CODE_UNKNOWN, cv_ref_code_from_str, cv_ref_prefix_from_code,
+        format_accession, parse_accession_tail,
+    },
+    decoder::utilities::cv_table,
+    encoder::utilities::le_writers::write_u32_le,
+    utilities::common::{read_u32_le_at, read_u32_vec, take},
+};
+
+/// Marks the start of the CV table inside Section E ("CVT0", little endian).
+pub(crate) const CV_TABLE_MAGIC: u32 = u32::from_le_bytes(*b"CVT0");
+
+/// First MRI/MURI code assigned to a CV declared in the per-file table.
+pub(crate) const CV_CODE_FILE_FIRST: u8 = 16;
+
+/// Set on an MAN/MUAN value when the low bits index the term table instead of
+/// holding a numeric accession tail.
+pub(crate) const TERM_INDEX_FLAG: u32 = 1 << 31;
+
+const MAX_FILE_CVS: usize = (CV_CODE_UNKNOWN - CV_CODE_FILE_FIRST) as usize;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FileCvTerm {
+    pub(crate) accession: String,
+    pub(crate) name: String,
+}
+
+/// Per-file table of controlled vocabularies and terms that the fixed
+/// MS/UO/NCIT/PEFF/IMS codes cannot express.
+///
+/// Rows whose cvRef is not built in get a code from `CV_CODE_FILE_FIRST`
+/// upwards. Rows whose accession cannot be rebuilt from `(code, tail)` (other
+/// CVs, non-numeric ids, leading zeros) store `TERM_INDEX_FLAG | index` into
+/// the term list, which keeps the accession and name verbatim.
+#[derive(Debug, Default)]
+pub(crate) struct FileCvTable {
+    cvs: Vec<String>,
+    terms: Vec<FileCvTerm>,
+    cv_codes: HashMap<String, u8>,
+    term_indices: HashMap<String, u32>,
+}
+
+impl FileCvTable {
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.cvs.is_empty() && self.terms.is_empty()
+    }
+
+    // ── Encoding ─────────────────────────────────────────────────────────────
+
+    /// Returns the `(MRI, MAN)` pair for one accession, registering the CV
+    /// and the term in the table when the built-in codes are not enough.
+    pub(crate) fn encode_term(
+        &mut self,
+        cv_ref: Option<&str>,
+        accession: Option<&str>,
+        name: Option<&str>,
+    ) -> (u8, u32) {
+        let code = self.cv_code(cv_ref);
+        let Some(accession) = accession else {
+            return (code, 0);
+        };
+        let tail = parse_accession_tail(Some(accession)).raw();
+        let rebuilt = format_accession(code, tail);
+        // IMS names are only partly in the built-in table, so the others
+        // are kept in the term list.
+        let named = code != CV_CODE_IMS
+            || name.is_none()
+            || cv_table::get(accession).and_then(|v| v.as_str()) == name;
+        if tail & TERM_INDEX_FLAG == 0 && rebuilt.as_deref() == Some(accession) && named {
+            return (code, tail);
+        }
+        (
+            code,
+            TERM_INDEX_FLAG | self.term_index(accession, name.unwrap_or("")),
+        )
+    }
+
+    fn cv_code(&mut self, cv_ref: Option<&str>) -> u8 {
+        let code = cv_ref_code_from_str(cv_ref);
+        if code != CV_CODE_UNKNOWN {
+            return code;
+        }
+        let Some(prefix) = cv_ref.filter(|p| !p.is_empty()) else {
+            return CV_CODE_UNKNOWN;
+        };
+        if let Some(&code) = self.cv_codes.get(prefix) {
+            return code;
+        }
+        if self.cvs.len() >= MAX_FILE_CVS {
+            return CV_CODE_UNKNOWN;
+        }
+        let code = CV_CODE_FILE_FIRST + self.cvs.len() as u8;
+        self.cvs.push(prefix.to_string());
+        self.cv_codes.insert(prefix.to_string(), code);
+        code
+    }
+
+    fn term_index(&mut self, accession: &str, name: &str) -> u32 {
+        if let Some(&index) = self.term_indices.get(accession) {
+            return index;
+        }
+        let index = self.terms.len() as u32;
+        self.terms.push(FileCvTerm {
+            accession: accession.to_string(),
+            name: name.to_string(),
+        });
+        self.term_indices.insert(accession.to_string(), index);
+        index
+    }
+
+    /// Appends the table as laid out in `B000.MD` (Section E, CV table).
+    /// Writes nothing when the table is empty so files that only use the
+    /// built-in CVs keep their previous layout.
+    pub(crate) fn write_into(&self, buf: &mut Vec<u8>) {
+        if self.is_empty() {
+            return;
+        }
+        write_u32_le(buf, CV_TABLE_MAGIC);
+        write_u32_le(buf, self.cvs.len() as u32);
+        write_u32_le(buf, self.terms.len() as u32);
+        for cv in &self.cvs {
+            write_u32_le(buf, cv.len() as u32);
+        }
+        for term in &self.terms {
+            write_u32_le(buf, term.accession.len() as u32);
+        }
+        for term in &self.terms {
+            write_u32_le(buf, term.name.len() as u32);
+        }
+        for cv in &self.cvs {
+            buf.extend_from_slice(cv.as_bytes());
+        }
+        for term in &self.terms {
+            buf.extend_from_slice(term.accession.as_bytes());
+        }
+        for term in &self.terms {
+            buf.extend_from_slice(term.name.as_bytes());
+        }
+    }
+
+    // ── Decoding ─────────────────────────────────────────────────────────────
+
+    /// Reads a table at `pos` if one is present (identified by
+    /// `CV_TABLE_MAGIC`); otherwise leaves `pos` untouched and returns an
+    /// empty table.
+    pub(crate) fn read_from(bytes: &[u8], pos: &mut usize) -> Result<Self, String> {
+        let mut cursor = *pos;
+        if read_u32_le_at(bytes, &mut cursor, "cv table magic").ok() != Some(CV_TABLE_MAGIC) {
+            return Ok(Self::default());
+        }
+        let n_cvs = read_u32_le_at(bytes, &mut cursor, "cv table n_cvs")? as usize;
+        let n_terms = read_u32_le_at(bytes, &mut cursor, "cv table n_terms")? as usize;
+        if n_cvs > MAX_FILE_CVS {
+            return Err(format!("cv table: too many CVs ({n_cvs})"));
+        }
+
+        let cv_lens = read_u32_vec(bytes, &mut cursor, n_cvs)?;
+        let accession_lens = read_u32_vec(bytes, &mut cursor, n_terms)?;
+        let name_lens = read_u32_vec(bytes, &mut cursor, n_terms)?;
+
+        let mut table = Self::default();
+        for len in cv_lens {
+            let prefix = read_str(bytes, &mut cursor, len, "cv table CV")?;
+            table.cv_code(Some(&prefix));
+        }
+        let accessions = accession_lens
+            .into_iter()
+            .map(|len| read_str(bytes, &mut cursor, len, "cv table accession"))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (accession, len) in accessions.into_iter().zip(name_lens) {
+            let name = read_str(bytes, &mut cursor, len, "cv table name")?;
+            table.term_index(&accession, &name);
+        }
+
+        *pos = cursor;
+        Ok(table)
+    }
+
+    #[inline]
+    pub(crate) fn cv_prefix(&self, code: u8) -> Option<&str> {
+        cv_ref_prefix_from_code(code).or_else(|| {
+            let index = code.checked_sub(CV_CODE_FILE_FIRST)? as usize;
+            self.cvs.get(index).map(String::as_str)
+        })
+    }
+
+    /// Term stored in the table for a flagged MAN/MUAN value.
+    #[inline]
+    pub(crate) fn term(&self, tail_raw: u32) -> Option<&FileCvTerm> {
+        if tail_raw & TERM_INDEX_FLAG == 0 {
+            return None;
+        }
+        self.terms.get((tail_raw & !TERM_INDEX_FLAG) as usize)
+    }
+
+    /// `format_accession` extended with the per-file CVs and terms.
+    pub(crate) fn format_accession(&self, code: u8, tail_raw: u32) -> Option<String> {
+        if tail_raw & TERM_INDEX_FLAG != 0 {
+            return self.term(tail_raw).map(|t| t.accession.clone());
+        }
+        if code < CV_CODE_FILE_FIRST {
+            return format_accession(code, tail_raw);
+        }
+        self.cv_prefix(code)
+            .map(|pref| format!("{pref}:{tail_raw}"))
+    }
+}
+
+#[inline]
+fn read_str(
+    bytes: &[u8],
+    pos: &mut usize,
+    len: u32,
+    field: &'static str,
+) -> Result<String, String> {
+    let raw = take(bytes, pos, len as usize, field)?;
+    Ok(String::from_utf8_lossy(raw).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::b64::attr_meta::{CV_CODE_MS, CV_CODE_NCIT, CV_CODE_UO};
+
+    #[test]
+    fn builtin_terms_stay_numeric() {
+        let mut table = FileCvTable::default();
+        assert_eq!(
+            table.encode_term(Some("MS"), Some("MS:1000514"), Some("m/z array")),
+            (CV_CODE_MS, 1_000_514)
+        );
+        assert_eq!(
+            table.encode_term(Some("UO"), Some("UO:0000031"), Some("minute")),
+            (CV_CODE_UO, 31)
+        );
+        assert_eq!(
+            table.encode_term(Some("NCIT"), Some("NCIT:C25330"), None),
+            (CV_CODE_NCIT, 25_330)
+        );
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn ims_terms_without_a_builtin_name_are_registered() {
+        let mut table = FileCvTable::default();
+        assert_eq!(
+            table.encode_term(Some("IMS"), Some("IMS:1000050"), Some("position x")),
+            (CV_CODE_IMS, 1_000_050)
+        );
+        assert!(table.is_empty());
+
+        let (code, man) =
+            table.encode_term(Some("IMS"), Some("IMS:1000120"), Some("mass resolution"));
+        assert_eq!(code, CV_CODE_IMS);
+        assert_eq!(man, TERM_INDEX_FLAG);
+        assert!(table.cvs.is_empty());
+        assert_eq!(table.terms[0].name, "mass resolution");
+    }
+
+    #[test]
+    fn unknown_cv_and_terms_are_registered() {
+        let mut table = FileCvTable::default();
+        let (code, man) = table.encode_term(Some("UNIMOD"), Some("UNIMOD:35"), Some("Oxidation"));
+        assert_eq!(code, CV_CODE_FILE_FIRST);
+        assert_eq!(man, TERM_INDEX_FLAG);
+
+        let (code2, man2) = table.encode_term(Some("BTO"), Some("BTO:0000142"), Some("brain"));
+        assert_eq!(code2, CV_CODE_FILE_FIRST + 1);
+        assert_eq!(man2, TERM_INDEX_FLAG | 1);
+
+        assert_eq!(
+            table.encode_term(Some("UNIMOD"), Some("UNIMOD:35"), Some("Oxidation")),
+            (code, man)
+        );
+        assert_eq!(table.cvs, ["UNIMOD", "BTO"]);
+        assert_eq!(table.terms.len(), 2);
+    }
+
+    #[test]
+    fn non_canonical_builtin_accession_is_kept_verbatim() {
+        let mut table = FileCvTable::default();
+        let (code, man) = table.encode_term(Some("MS"), Some("MS:0000514"), Some("x"));
+        assert_eq!(code, CV_CODE_MS);
+        assert_eq!(
+            table.format_accession(code, man).as_deref(),
+            Some("MS:0000514")
+        );
+    }
+
+    #[test]
+    fn write_then_read_round_trip() {
+        let mut table = FileCvTable::default();
+        let (c1, m1) = table.encode_term(Some("UNIMOD"), Some("UNIMOD:35"), Some("Oxidation"));
+        let (c2, m2) = table.encode_term(Some("EFO"), Some("EFO:0000408"), Some("disease"));
+
+        let mut buf = vec![0xAA];
+        table.write_into(&mut buf);
+        buf.extend_from_slice(&0u32.to_le_bytes());
+
+        let mut pos = 1;
+        let decoded = FileCvTable::read_from(&buf, &mut pos).unwrap();
+        assert_eq!(pos, buf.len() - 4);
+        assert_eq!(decoded.cvs, table.cvs);
+        assert_eq!(decoded.terms, table.terms);
+        assert_eq!(
+            decoded.format_accession(c1, m1).as_deref(),
+            Some("UNIMOD:35")
+        );
+        assert_eq!(decoded.term(m2).unwrap().name, "disease");
+        assert_eq!(decoded.cv_prefix(c2), Some("EFO"));
+    }
+
+    #[test]
+    fn missing_table_leaves_position_untouched() {
+        let bytes = 0u32.to_le_bytes();
+        let mut pos = 0;
+        let table = FileCvTable::read_from(&bytes, &mut pos).unwrap();
+        assert!(table.is_empty());
+        assert_eq!(pos, 0);
+    }
+}
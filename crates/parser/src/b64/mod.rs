@@ -1,6 +1,10 @@
 pub mod decoder;
 pub use decoder::decode::decode;
+pub use decoder::reader::{B000Reader, Spectra};
+pub use decoder::salvage::{B000SalvageReport, salvage_b000};
 pub(crate) use decoder::utilities;
 pub mod encoder;
 pub use encoder::{encode::WritingMode, encode::encode, utilities::FileEncoderOutput};
 pub mod attr_meta;
+pub(crate) mod checkpoint;
+pub(crate) mod file_cv_table;
@@ -0,0 +1,245 @@
+use std::{
+    borrow::Borrow,
+    io::{Read, Seek},
+};
+
+use crate::{
+    b64::B000Reader,
+    imzml::parse_imzml::{
+        ACC_MAX_PIXELS_X, ACC_MAX_PIXELS_Y, ACC_POSITION_X, ACC_POSITION_Y, ACC_POSITION_Z,
+    },
+    mzml::{
+        structs::*,
+        utilities::peak_list::{
+            ACC_INTENSITY_ARRAY, ACC_MZ_ARRAY, array_f64, cv_f64, first_scan_params,
+        },
+    },
+};
+
+/// Position of an imaging spectrum, 1-based as in the imzML `position`
+/// cvParams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Pixel {
+    pub x: u32,
+    pub y: u32,
+    pub z: Option<u32>,
+}
+
+/// Pixel of `spectrum`, from the `position x`, `position y` and
+/// `position z` cvParams of its first scan.
+pub fn pixel(spectrum: &Spectrum) -> Option<Pixel> {
+    let params = first_scan_params(spectrum);
+    let coordinate = |accession| cv_f64(params, accession).map(|v| v as u32);
+    Some(Pixel {
+        x: coordinate(ACC_POSITION_X)?,
+        y: coordinate(ACC_POSITION_Y)?,
+        z: coordinate(ACC_POSITION_Z),
+    })
+}
+
+/// Summed intensity of the peaks in an m/z window at each pixel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IonImage {
+    pub width: u32,
+    pub height: u32,
+    /// Row-major: pixel `(x, y)` is at `(y - 1) * width + (x - 1)`. Pixels
+    /// without a spectrum are 0.
+    pub intensities: Vec<f64>,
+}
+
+impl IonImage {
+    /// Intensity at the 1-based pixel `(x, y)`.
+    pub fn get(&self, x: u32, y: u32) -> Option<f64> {
+        if x == 0 || y == 0 || x > self.width || y > self.height {
+            return None;
+        }
+        let at = (y - 1) as usize * self.width as usize + (x - 1) as usize;
+        self.intensities.get(at).copied()
+    }
+}
+
+/// Ion image of the peaks of `mzml` with `mz_min <= m/z <= mz_max`.
+///
+/// The image is at least as large as the `max count of pixels` of the scan
+/// settings. Spectra without a pixel are left out and `position z` is
+/// ignored, so 3D data is summed along z.
+pub fn ion_image(mzml: &MzML, mz_min: f64, mz_max: f64) -> IonImage {
+    let spectra = mzml.run.spectrum_list.iter().flat_map(|l| &l.spectra);
+    ion_image_with_size(image_size(mzml), spectra, mz_min, mz_max)
+}
+
+/// Ion image of the B000 file read from `input`, decoding one spectrum at
+/// a time.
+pub fn b000_ion_image<R: Read + Seek>(
+    input: R,
+    mz_min: f64,
+    mz_max: f64,
+) -> Result<IonImage, String> {
+    let reader = B000Reader::new(input)?;
+    let size = image_size(reader.header());
+    let mut spectra = reader.spectra();
+    let image = ion_image_with_size(size, spectra.by_ref(), mz_min, mz_max);
+    spectra.finish().map(|()| image)
+}
+
+/// Ion image of `spectra`, sized to the largest pixel seen.
+pub fn ion_image_streaming<S, I>(spectra: I, mz_min: f64, mz_max: f64) -> IonImage
+where
+    S: Borrow<Spectrum>,
+    I: IntoIterator<Item = S>,
+{
+    ion_image_with_size((0, 0), spectra, mz_min, mz_max)
+}
+
+/// `max count of pixels x` and `y` of the first scan settings that has them.
+fn image_size(mzml: &MzML) -> (u32, u32) {
+    let settings = mzml
+        .scan_settings_list
+        .iter()
+        .flat_map(|l| &l.scan_settings);
+    settings
+        .map(|s| &s.cv_params)
+        .find_map(|params| {
+            let x = cv_f64(params, ACC_MAX_PIXELS_X)?;
+            let y = cv_f64(params, ACC_MAX_PIXELS_Y)?;
+            Some((x as u32, y as u32))
+        })
+        .unwrap_or((0, 0))
+}
+
+fn ion_image_with_size<S, I>(
+    (mut width, mut height): (u32, u32),
+    spectra: I,
+    mz_min: f64,
+    mz_max: f64,
+) -> IonImage
+where
+    S: Borrow<Spectrum>,
+    I: IntoIterator<Item = S>,
+{
+    let mut sums = Vec::new();
+    for spectrum in spectra {
+        let spectrum = spectrum.borrow();
+        let Some(Pixel { x, y, .. }) = pixel(spectrum).filter(|p| p.x > 0 && p.y > 0) else {
+            continue;
+        };
+        let mz = array_f64(spectrum, ACC_MZ_ARRAY).unwrap_or_default();
+        let intensity = array_f64(spectrum, ACC_INTENSITY_ARRAY).unwrap_or_default();
+        let sum: f64 = mz
+            .iter()
+            .zip(&intensity)
+            .filter(|&(&mz, _)| mz >= mz_min && mz <= mz_max)
+            .map(|(_, &intensity)| intensity)
+            .sum();
+        width = width.max(x);
+        height = height.max(y);
+        sums.push((x, y, sum));
+    }
+
+    let mut intensities = vec![0.0; width as usize * height as usize];
+    for (x, y, sum) in sums {
+        intensities[(y - 1) as usize * width as usize + (x - 1) as usize] += sum;
+    }
+    IonImage {
+        width,
+        height,
+        intensities,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        b64::{encode, encoder::encode::WritingMode},
+        imzml::{
+            parse_imzml::{ims_cv, parse_imzml},
+            write_imzml::{ImzmlWriteOptions, write_imzml},
+        },
+        mzml::utilities::peak_list::{peak_list_mzml, peak_list_spectrum},
+    };
+    use std::io::Cursor;
+
+    /// 3x2 image with one spectrum per pixel except (3, 2); the peak at
+    /// m/z 500 has intensity `10 * x + y`.
+    fn image() -> MzML {
+        let mut spectra = Vec::new();
+        for (x, y) in [(1, 1), (2, 1), (3, 1), (1, 2), (2, 2)] {
+            let peak = f64::from(10 * x + y);
+            let mut spectrum = peak_list_spectrum(
+                spectra.len(),
+                1,
+                vec![100.0, 500.0, 500.004],
+                vec![1.0, peak, 1.0],
+            );
+            spectrum.scan_list = Some(ScanList {
+                count: Some(1),
+                scans: vec![Scan {
+                    cv_params: vec![
+                        ims_cv(ACC_POSITION_X, "position x", Some(x.to_string())),
+                        ims_cv(ACC_POSITION_Y, "position y", Some(y.to_string())),
+                    ],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            });
+            spectra.push(spectrum);
+        }
+        let mut mzml = peak_list_mzml("image", Vec::new(), spectra);
+        mzml.scan_settings_list = Some(ScanSettingsList {
+            count: Some(1),
+            scan_settings: vec![ScanSettings {
+                id: Some("scansettings1".to_string()),
+                cv_params: vec![
+                    ims_cv(ACC_MAX_PIXELS_X, "max count of pixels x", Some("3".into())),
+                    ims_cv(ACC_MAX_PIXELS_Y, "max count of pixels y", Some("2".into())),
+                ],
+                ..Default::default()
+            }],
+        });
+        mzml
+    }
+
+    #[test]
+    fn sums_the_window_at_each_pixel() {
+        let ions = ion_image(&image(), 499.99, 500.001);
+        assert_eq!((ions.width, ions.height), (3, 2));
+        assert_eq!(ions.intensities, [11.0, 21.0, 31.0, 12.0, 22.0, 0.0]);
+        assert_eq!(ions.get(2, 2), Some(22.0));
+        assert_eq!(ions.get(4, 1), None);
+
+        let wide = ion_image_streaming(&image().run.spectrum_list.unwrap().spectra, 499.0, 501.0);
+        assert_eq!(wide.get(1, 1), Some(12.0));
+        assert_eq!((wide.width, wide.height), (3, 2));
+    }
+
+    #[test]
+    fn pixels_survive_b000_and_imzml() {
+        let mzml = image();
+        let expected = ion_image(&mzml, 499.99, 500.001);
+
+        let mut bytes = Vec::new();
+        encode(&mzml, 12, false, WritingMode::Memory, &mut bytes).unwrap();
+        assert_eq!(
+            b000_ion_image(Cursor::new(&bytes), 499.99, 500.001).unwrap(),
+            expected
+        );
+
+        let options = ImzmlWriteOptions {
+            uuid: Some([7; 16]),
+            ..Default::default()
+        };
+        let (xml, ibd) = write_imzml(Vec::new(), Vec::new(), &mzml, &options).unwrap();
+        let reread = parse_imzml(&xml, Cursor::new(&ibd)).unwrap();
+        let spectra = &reread.run.spectrum_list.as_ref().unwrap().spectra;
+        assert_eq!(
+            pixel(&spectra[3]),
+            Some(Pixel {
+                x: 1,
+                y: 2,
+                z: None
+            })
+        );
+        assert_eq!(ion_image(&reread, 499.99, 500.001), expected);
+    }
+}
@@ -0,0 +1,6 @@
+pub mod parse_imzml;
+pub use parse_imzml::{ImzmlMode, ImzmlReader, imzml_mode, parse_imzml};
+pub mod write_imzml;
+pub use write_imzml::{ImzmlWriteOptions, write_b000_as_imzml, write_imzml, write_imzml_streaming};
+pub mod ion_image;
+pub use ion_image::{IonImage, Pixel, b000_ion_image, ion_image, ion_image_streaming, pixel};
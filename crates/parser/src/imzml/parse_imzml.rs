@@ -0,0 +1,544 @@
+use std::{
+    collections::HashSet,
+    io::{Read, Seek, SeekFrom},
+};
+
+use miniz_oxide::inflate::decompress_to_vec_zlib;
+
+use crate::mzml::{
+    bin_to_mzml::{binary_len, numeric_type_of},
+    parse_mzml::parse_mzml,
+    structs::*,
+    utilities::{
+        parse_bda_list::{decode_binary_data, encoding_for_array, stride},
+        peak_list::{ACC_MZ_ARRAY, find_cv, ms_cv},
+    },
+};
+
+pub(crate) const ACC_CONTINUOUS: &str = "IMS:1000030";
+pub(crate) const ACC_PROCESSED: &str = "IMS:1000031";
+pub(crate) const ACC_MAX_PIXELS_X: &str = "IMS:1000042";
+pub(crate) const ACC_MAX_PIXELS_Y: &str = "IMS:1000043";
+pub(crate) const ACC_POSITION_X: &str = "IMS:1000050";
+pub(crate) const ACC_POSITION_Y: &str = "IMS:1000051";
+pub(crate) const ACC_POSITION_Z: &str = "IMS:1000052";
+pub(crate) const ACC_UUID: &str = "IMS:1000080";
+pub(crate) const ACC_IBD_MD5: &str = "IMS:1000090";
+pub(crate) const ACC_IBD_SHA1: &str = "IMS:1000091";
+pub(crate) const ACC_EXTERNAL_DATA: &str = "IMS:1000101";
+pub(crate) const ACC_EXTERNAL_OFFSET: &str = "IMS:1000102";
+pub(crate) const ACC_EXTERNAL_ARRAY_LENGTH: &str = "IMS:1000103";
+pub(crate) const ACC_EXTERNAL_ENCODED_LENGTH: &str = "IMS:1000104";
+
+/// cvParams that point an array at its data in the `.ibd` file.
+pub(crate) const EXTERNAL_ACCESSIONS: [&str; 4] = [
+    ACC_EXTERNAL_DATA,
+    ACC_EXTERNAL_OFFSET,
+    ACC_EXTERNAL_ARRAY_LENGTH,
+    ACC_EXTERNAL_ENCODED_LENGTH,
+];
+
+/// How the arrays of an imzML file are laid out in its `.ibd` file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImzmlMode {
+    /// One m/z array, shared by every spectrum.
+    Continuous,
+    /// An m/z array per spectrum.
+    #[default]
+    Processed,
+}
+
+/// `IMS` cvParam without a unit.
+pub(crate) fn ims_cv(accession: &str, name: &str, value: Option<String>) -> CvParam {
+    CvParam {
+        cv_ref: Some("IMS".to_string()),
+        ..ms_cv(accession, name, value)
+    }
+}
+
+#[inline]
+pub(crate) fn is_external(param: &CvParam) -> bool {
+    param
+        .accession
+        .as_deref()
+        .is_some_and(|a| EXTERNAL_ACCESSIONS.contains(&a))
+}
+
+/// Parses the imzML document `imzml` and reads its arrays from `ibd`, the
+/// matching `.ibd` file, by their external offsets.
+///
+/// The arrays come back inline, as if parsed from mzML: the params of the
+/// referenceable param groups that declare `external data` are copied into
+/// each array without the external cvParams, and those groups are dropped.
+/// The result can be written as mzML or encoded as B000 like any other
+/// document; the pixel coordinates stay in the scan cvParams (see `pixel`).
+/// In continuous mode the m/z array is read once, but every spectrum of the
+/// document holds a copy; `ImzmlReader` hands out one spectrum at a time.
+pub fn parse_imzml<R: Read + Seek>(imzml: &[u8], ibd: R) -> Result<MzML, String> {
+    let mut reader = ImzmlReader::new(imzml, ibd)?;
+    let spectra = reader.by_ref().collect::<Result<Vec<_>, _>>()?;
+    let mut mzml = reader.into_header();
+    if let Some(list) = mzml.run.spectrum_list.as_mut() {
+        list.spectra = spectra;
+    }
+    Ok(mzml)
+}
+
+/// Pull reader yielding the spectra of an imzML document one at a time,
+/// with their arrays read from the `.ibd` file `ibd` (see `parse_imzml`).
+///
+/// The XML is parsed up front and its spectrum list, without arrays, is
+/// kept; `header()` has that list's `count` but no spectra. An m/z array at
+/// the same offset as the previous one, as in continuous mode, is not read
+/// again but shared: each spectrum gets a copy of the decoded array.
+pub struct ImzmlReader<R> {
+    ibd: R,
+    ibd_len: u64,
+    header: MzML,
+    spectra: std::vec::IntoIter<Spectrum>,
+    external_groups: Vec<ReferenceableParamGroup>,
+    /// Last m/z array read, with its external offset and encoded length.
+    shared_mz: Option<(usize, usize, BinaryData)>,
+    failed: bool,
+}
+
+impl<R: Read + Seek> ImzmlReader<R> {
+    pub fn new(imzml: &[u8], mut ibd: R) -> Result<Self, String> {
+        let mut header = parse_mzml(imzml).map_err(|e| e.to_string())?;
+        let ibd_len = ibd
+            .seek(SeekFrom::End(0))
+            .map_err(|e| format!("ibd seek failed: {e}"))?;
+        check_uuid(&header, &mut ibd)?;
+        let external_groups = take_external_groups(&mut header);
+        let spectra = match header.run.spectrum_list.as_mut() {
+            Some(list) => {
+                list.count.get_or_insert(list.spectra.len());
+                std::mem::take(&mut list.spectra)
+            }
+            None => Vec::new(),
+        };
+        Ok(Self {
+            ibd,
+            ibd_len,
+            header,
+            spectra: spectra.into_iter(),
+            external_groups,
+            shared_mz: None,
+            failed: false,
+        })
+    }
+
+    /// Everything but the spectra, without the external data groups.
+    #[inline]
+    pub fn header(&self) -> &MzML {
+        &self.header
+    }
+
+    #[inline]
+    pub fn into_header(self) -> MzML {
+        self.header
+    }
+
+    /// Next spectrum with its arrays, `None` after the last one.
+    pub fn next_spectrum(&mut self) -> Option<Result<Spectrum, String>> {
+        if self.failed {
+            return None;
+        }
+        let mut spectrum = self.spectra.next()?;
+        let read = self
+            .read_arrays(&mut spectrum)
+            .map_err(|e| format!("spectrum {}: {e}", spectrum.id));
+        self.failed = read.is_err();
+        Some(read.map(|()| spectrum))
+    }
+
+    fn read_arrays(&mut self, spectrum: &mut Spectrum) -> Result<(), String> {
+        let Some(list) = spectrum.binary_data_array_list.as_mut() else {
+            return Ok(());
+        };
+        let external_ids: HashSet<&str> =
+            self.external_groups.iter().map(|g| g.id.as_str()).collect();
+        for bda in &mut list.binary_data_arrays {
+            let mut params = Vec::new();
+            for group in &self.external_groups {
+                if bda
+                    .referenceable_param_group_refs
+                    .iter()
+                    .any(|r| r.r#ref == group.id)
+                {
+                    params.extend(group.cv_params.iter().cloned());
+                }
+            }
+            bda.referenceable_param_group_refs
+                .retain(|r| !external_ids.contains(r.r#ref.as_str()));
+            params.append(&mut bda.cv_params);
+
+            if find_cv(&params, ACC_EXTERNAL_OFFSET).is_some() {
+                let binary = if find_cv(&params, ACC_MZ_ARRAY).is_some() {
+                    let location = external_location(&params)?;
+                    match &self.shared_mz {
+                        Some((offset, len, mz)) if (*offset, *len) == location => mz.clone(),
+                        _ => {
+                            let mz = read_external_array(&params, &mut self.ibd, self.ibd_len)?;
+                            self.shared_mz = Some((location.0, location.1, mz.clone()));
+                            mz
+                        }
+                    }
+                } else {
+                    read_external_array(&params, &mut self.ibd, self.ibd_len)?
+                };
+                bda.numeric_type = Some(numeric_type_of(&binary));
+                bda.array_length = Some(binary_len(&binary));
+                bda.encoded_length = None;
+                bda.binary = Some(binary);
+            }
+            params.retain(|p| !is_external(p));
+            bda.cv_params = params;
+        }
+        let mz_length = list
+            .binary_data_arrays
+            .iter()
+            .find(|bda| find_cv(&bda.cv_params, ACC_MZ_ARRAY).is_some())
+            .and_then(|bda| bda.array_length);
+        if let Some(length) = mz_length {
+            spectrum.default_array_length = Some(length);
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Iterator for ImzmlReader<R> {
+    type Item = Result<Spectrum, String>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_spectrum()
+    }
+}
+
+/// Storage mode declared in the fileContent of an imzML document.
+pub fn imzml_mode(mzml: &MzML) -> Option<ImzmlMode> {
+    let params = &mzml.file_description.as_ref()?.file_content.cv_params;
+    if find_cv(params, ACC_CONTINUOUS).is_some() {
+        Some(ImzmlMode::Continuous)
+    } else if find_cv(params, ACC_PROCESSED).is_some() {
+        Some(ImzmlMode::Processed)
+    } else {
+        None
+    }
+}
+
+/// UUID declared in the fileContent, which the `.ibd` file starts with.
+pub(crate) fn file_content_uuid(mzml: &MzML) -> Option<[u8; 16]> {
+    let params = &mzml.file_description.as_ref()?.file_content.cv_params;
+    parse_uuid(find_cv(params, ACC_UUID)?.value.as_deref()?)
+}
+
+/// The 32 hex digits of `s`, ignoring braces and dashes.
+pub(crate) fn parse_uuid(s: &str) -> Option<[u8; 16]> {
+    let digits: Vec<u8> = s
+        .bytes()
+        .filter(|b| !matches!(b, b'{' | b'}' | b'-'))
+        .map(|b| (b as char).to_digit(16).map(|d| d as u8))
+        .collect::<Option<_>>()?;
+    if digits.len() != 32 {
+        return None;
+    }
+    let mut uuid = [0u8; 16];
+    for (byte, pair) in uuid.iter_mut().zip(digits.chunks_exact(2)) {
+        *byte = pair[0] << 4 | pair[1];
+    }
+    Some(uuid)
+}
+
+/// `{8-4-4-4-12}` in upper case, as in the imzML specification.
+pub(crate) fn format_uuid(uuid: &[u8; 16]) -> String {
+    let hex: String = uuid.iter().map(|b| format!("{b:02X}")).collect();
+    format!(
+        "{{{}-{}-{}-{}-{}}}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+fn check_uuid<R: Read + Seek>(mzml: &MzML, ibd: &mut R) -> Result<(), String> {
+    let mut header = [0u8; 16];
+    ibd.seek(SeekFrom::Start(0))
+        .and_then(|_| ibd.read_exact(&mut header))
+        .map_err(|_| "ibd is shorter than its 16-byte UUID".to_string())?;
+    match file_content_uuid(mzml) {
+        Some(declared) if declared != header => Err(format!(
+            "ibd UUID {} does not match the imzML UUID {}",
+            format_uuid(&header),
+            format_uuid(&declared)
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[inline]
+fn cv_usize(params: &[CvParam], accession: &str) -> Option<usize> {
+    find_cv(params, accession)?
+        .value
+        .as_deref()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Removes the referenceable param groups that declare `external data`
+/// and returns them.
+fn take_external_groups(mzml: &mut MzML) -> Vec<ReferenceableParamGroup> {
+    let Some(list) = mzml.referenceable_param_group_list.as_mut() else {
+        return Vec::new();
+    };
+    let (external, kept) = std::mem::take(&mut list.referenceable_param_groups)
+        .into_iter()
+        .partition(|g| find_cv(&g.cv_params, ACC_EXTERNAL_DATA).is_some());
+    list.referenceable_param_groups = kept;
+    list.count = Some(list.referenceable_param_groups.len());
+    external
+}
+
+/// `external offset` and encoded length in bytes of the array described
+/// by `params`.
+fn external_location(params: &[CvParam]) -> Result<(usize, usize), String> {
+    let offset = cv_usize(params, ACC_EXTERNAL_OFFSET).ok_or("invalid external offset")?;
+    let array_length =
+        cv_usize(params, ACC_EXTERNAL_ARRAY_LENGTH).ok_or("missing external array length")?;
+    let encoding = encoding_for_array(&BinaryDataArray {
+        cv_params: params.to_vec(),
+        ..Default::default()
+    });
+    let compressed = encoding.is_zlib_compressed || encoding.numpress.is_some();
+    let encoded_length = match cv_usize(params, ACC_EXTERNAL_ENCODED_LENGTH) {
+        Some(n) => n,
+        None if !compressed => array_length * stride(encoding.numeric_type),
+        None => return Err("missing external encoded length".into()),
+    };
+    Ok((offset, encoded_length))
+}
+
+/// Data of one array, read from `ibd` (`ibd_len` bytes long) at the
+/// `external offset`, `external array length` and `external encoded length`
+/// in `params`.
+fn read_external_array<R: Read + Seek>(
+    params: &[CvParam],
+    ibd: &mut R,
+    ibd_len: u64,
+) -> Result<BinaryData, String> {
+    let (offset, encoded_length) = external_location(params)?;
+    let array_length =
+        cv_usize(params, ACC_EXTERNAL_ARRAY_LENGTH).ok_or("missing external array length")?;
+    let encoding = encoding_for_array(&BinaryDataArray {
+        cv_params: params.to_vec(),
+        ..Default::default()
+    });
+    let in_bounds = offset
+        .checked_add(encoded_length)
+        .is_some_and(|end| end as u64 <= ibd_len);
+    if !in_bounds {
+        return Err(format!(
+            "{encoded_length} bytes at external offset {offset} run past the end of the \
+             {ibd_len}-byte ibd"
+        ));
+    }
+    let mut bytes = vec![0u8; encoded_length];
+    ibd.seek(SeekFrom::Start(offset as u64))
+        .and_then(|_| ibd.read_exact(&mut bytes))
+        .map_err(|e| format!("ibd read at external offset {offset} failed: {e}"))?;
+
+    if encoding.is_zlib_compressed {
+        bytes = decompress_to_vec_zlib(&bytes)
+            .map_err(|e| format!("zlib stream is damaged ({:?})", e.status))?;
+    }
+    let binary = match encoding.numpress {
+        Some(codec) => BinaryData::F64(codec.decode(&bytes)?),
+        None => decode_binary_data(encoding.numeric_type, &bytes, Some(array_length)),
+    };
+    let read = binary_len(&binary);
+    if read != array_length {
+        return Err(format!(
+            "external array length is {array_length} but {read} values were read"
+        ));
+    }
+    Ok(binary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const UUID: &str = "{554A27FA-79D2-4766-9A2C-862E2D6E2D0D}";
+
+    /// Two 2-peak spectra in continuous mode: one 64-bit m/z array shared at
+    /// offset 16, then one 32-bit intensity array per spectrum.
+    fn continuous_fixture() -> (String, Vec<u8>) {
+        let mut ibd = parse_uuid(UUID).unwrap().to_vec();
+        for mz in [100.0f64, 200.0] {
+            ibd.extend_from_slice(&mz.to_le_bytes());
+        }
+        for intensity in [1.0f32, 2.0, 3.0, 4.0] {
+            ibd.extend_from_slice(&intensity.to_le_bytes());
+        }
+        let spectrum = |i: usize, x: u32, intensity_offset: usize| {
+            format!(
+                r#"<spectrum id="Scan={n}" index="{i}" defaultArrayLength="0">
+<scanList count="1"><scan>
+<cvParam cvRef="IMS" accession="IMS:1000050" name="position x" value="{x}"/>
+<cvParam cvRef="IMS" accession="IMS:1000051" name="position y" value="1"/>
+</scan></scanList>
+<binaryDataArrayList count="2">
+<binaryDataArray encodedLength="0">
+<referenceableParamGroupRef ref="mzArray"/>
+<cvParam cvRef="IMS" accession="IMS:1000103" name="external array length" value="2"/>
+<cvParam cvRef="IMS" accession="IMS:1000104" name="external encoded length" value="16"/>
+<cvParam cvRef="IMS" accession="IMS:1000102" name="external offset" value="16"/>
+<binary/></binaryDataArray>
+<binaryDataArray encodedLength="0">
+<referenceableParamGroupRef ref="intensityArray"/>
+<cvParam cvRef="IMS" accession="IMS:1000103" name="external array length" value="2"/>
+<cvParam cvRef="IMS" accession="IMS:1000104" name="external encoded length" value="8"/>
+<cvParam cvRef="IMS" accession="IMS:1000102" name="external offset" value="{intensity_offset}"/>
+<binary/></binaryDataArray>
+</binaryDataArrayList></spectrum>"#,
+                n = i + 1
+            )
+        };
+        let xml = format!(
+            r#"<?xml version="1.0" encoding="ISO-8859-1"?>
+<mzML xmlns="http://psi.hupo.org/ms/mzml" version="1.1">
+<cvList count="2">
+<cv id="MS" fullName="Proteomics Standards Initiative Mass Spectrometry Ontology"/>
+<cv id="IMS" fullName="Imaging MS Ontology"/>
+</cvList>
+<fileDescription><fileContent>
+<cvParam cvRef="MS" accession="MS:1000579" name="MS1 spectrum"/>
+<cvParam cvRef="IMS" accession="IMS:1000080" name="universally unique identifier" value="{UUID}"/>
+<cvParam cvRef="IMS" accession="IMS:1000030" name="continuous"/>
+</fileContent></fileDescription>
+<referenceableParamGroupList count="2">
+<referenceableParamGroup id="mzArray">
+<cvParam cvRef="MS" accession="MS:1000576" name="no compression"/>
+<cvParam cvRef="MS" accession="MS:1000514" name="m/z array" unitCvRef="MS" unitAccession="MS:1000040" unitName="m/z"/>
+<cvParam cvRef="MS" accession="MS:1000523" name="64-bit float"/>
+<cvParam cvRef="IMS" accession="IMS:1000101" name="external data" value="true"/>
+</referenceableParamGroup>
+<referenceableParamGroup id="intensityArray">
+<cvParam cvRef="MS" accession="MS:1000521" name="32-bit float"/>
+<cvParam cvRef="MS" accession="MS:1000515" name="intensity array" unitCvRef="MS" unitAccession="MS:1000131" unitName="number of detector counts"/>
+<cvParam cvRef="MS" accession="MS:1000576" name="no compression"/>
+<cvParam cvRef="IMS" accession="IMS:1000101" name="external data" value="true"/>
+</referenceableParamGroup>
+</referenceableParamGroupList>
+<run id="image"><spectrumList count="2">
+{}
+{}
+</spectrumList></run></mzML>"#,
+            spectrum(0, 1, 32),
+            spectrum(1, 2, 40)
+        );
+        (xml, ibd)
+    }
+
+    #[test]
+    fn reads_continuous_arrays_from_the_ibd() {
+        let (xml, ibd) = continuous_fixture();
+        let mzml = parse_imzml(xml.as_bytes(), Cursor::new(&ibd)).unwrap();
+        assert_eq!(imzml_mode(&mzml), Some(ImzmlMode::Continuous));
+        assert!(
+            mzml.referenceable_param_group_list
+                .unwrap()
+                .referenceable_param_groups
+                .is_empty()
+        );
+
+        let spectra = &mzml.run.spectrum_list.as_ref().unwrap().spectra;
+        let arrays = |i: usize| {
+            &spectra[i]
+                .binary_data_array_list
+                .as_ref()
+                .unwrap()
+                .binary_data_arrays
+        };
+        for (i, intensities) in [[1.0f32, 2.0], [3.0, 4.0]].into_iter().enumerate() {
+            let [mz, intensity] = &arrays(i)[..] else {
+                panic!()
+            };
+            assert_eq!(mz.binary, Some(BinaryData::F64(vec![100.0, 200.0])));
+            assert_eq!(
+                intensity.binary,
+                Some(BinaryData::F32(intensities.to_vec()))
+            );
+            assert_eq!(spectra[i].default_array_length, Some(2));
+            assert!(mz.referenceable_param_group_refs.is_empty());
+            assert!(find_cv(&mz.cv_params, ACC_MZ_ARRAY).is_some());
+            assert!(!mz.cv_params.iter().any(is_external));
+        }
+    }
+
+    /// Counts the seeks to each offset of the `.ibd`.
+    struct SeekCounter {
+        inner: Cursor<Vec<u8>>,
+        seeks: Vec<u64>,
+    }
+
+    impl Read for SeekCounter {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.inner.read(buf)
+        }
+    }
+
+    impl Seek for SeekCounter {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            let offset = self.inner.seek(pos)?;
+            self.seeks.push(offset);
+            Ok(offset)
+        }
+    }
+
+    #[test]
+    fn reads_the_shared_mz_array_once() {
+        let (xml, ibd) = continuous_fixture();
+        let mut ibd = SeekCounter {
+            inner: Cursor::new(ibd),
+            seeks: Vec::new(),
+        };
+        let mut reader = ImzmlReader::new(xml.as_bytes(), &mut ibd).unwrap();
+        assert_eq!(
+            reader.header().run.spectrum_list.as_ref().unwrap().count,
+            Some(2)
+        );
+        let spectra = reader.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(spectra.len(), 2);
+        drop(reader);
+        assert_eq!(ibd.seeks.iter().filter(|&&offset| offset == 16).count(), 1);
+    }
+
+    #[test]
+    fn rejects_a_foreign_ibd() {
+        let (xml, mut ibd) = continuous_fixture();
+        ibd[0] ^= 0xff;
+        let err = parse_imzml(xml.as_bytes(), Cursor::new(&ibd)).unwrap_err();
+        assert!(err.contains("does not match the imzML UUID"), "{err}");
+
+        let (xml, ibd) = continuous_fixture();
+        let err = parse_imzml(xml.as_bytes(), Cursor::new(&ibd[..40])).unwrap_err();
+        assert!(
+            err.starts_with("spectrum Scan=2: 8 bytes at external offset 40"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn uuid_text_round_trips() {
+        let uuid = parse_uuid(UUID).unwrap();
+        assert_eq!(format_uuid(&uuid), UUID);
+        assert_eq!(parse_uuid("554a27fa79d247669a2c862e2d6e2d0d"), Some(uuid));
+        assert_eq!(parse_uuid("554a27fa"), None);
+    }
+}
@@ -0,0 +1,370 @@
+use std::{
+    borrow::Borrow,
+    io::{Read, Seek, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    b64::B000Reader,
+    imzml::parse_imzml::{
+        ACC_CONTINUOUS, ACC_EXTERNAL_ARRAY_LENGTH, ACC_EXTERNAL_DATA, ACC_EXTERNAL_ENCODED_LENGTH,
+        ACC_EXTERNAL_OFFSET, ACC_IBD_MD5, ACC_IBD_SHA1, ACC_PROCESSED, ACC_UUID, ImzmlMode,
+        file_content_uuid, format_uuid, ims_cv, is_external,
+    },
+    mzml::{
+        MzmlWriteOptions,
+        bin_to_mzml::{
+            COMPRESSION_ACCESSIONS, NUMERIC_TYPE_ACCESSIONS, Sink, binary_len, default_cv_list,
+            le_bytes, numeric_type_of, numeric_type_term, replace_cv_term, write_mzml_with_options,
+        },
+        structs::*,
+        utilities::peak_list::{ACC_MZ_ARRAY, find_cv},
+    },
+};
+
+/// Options for `write_imzml`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ImzmlWriteOptions {
+    /// Layout of the `.ibd` file. Continuous mode needs the same m/z array
+    /// in every spectrum.
+    pub mode: ImzmlMode,
+    /// UUID that starts the `.ibd` file and is declared in the fileContent.
+    /// Defaults to the UUID the document already declares, else one derived
+    /// from the run id and the current time.
+    pub uuid: Option<[u8; 16]>,
+}
+
+/// Writes the spectra of `mzml` as imzML to `imzml_out` and their arrays to
+/// `ibd_out`, and returns both.
+pub fn write_imzml<W: Write, D: Write>(
+    imzml_out: W,
+    ibd_out: D,
+    mzml: &MzML,
+    options: &ImzmlWriteOptions,
+) -> Result<(W, D), String> {
+    let spectra = mzml.run.spectrum_list.iter().flat_map(|l| &l.spectra);
+    write_imzml_streaming(imzml_out, ibd_out, mzml, spectra, options)
+}
+
+/// Writes the B000 file read from `input` as imzML, decoding one spectrum
+/// at a time.
+pub fn write_b000_as_imzml<W: Write, D: Write, R: Read + Seek>(
+    imzml_out: W,
+    ibd_out: D,
+    input: R,
+    options: &ImzmlWriteOptions,
+) -> Result<(W, D), String> {
+    let reader = B000Reader::new(input)?;
+    let header = reader.header().clone();
+    let mut spectra = reader.spectra();
+    let out = write_imzml_streaming(imzml_out, ibd_out, &header, spectra.by_ref(), options)?;
+    spectra.finish().map(|()| out)
+}
+
+/// Writes the metadata of `header` and `spectra` as imzML, with the arrays
+/// in the `.ibd` written to `ibd_out`.
+///
+/// Arrays are stored uncompressed, in their own numeric type, and each
+/// gets its external cvParams inline. The `.ibd` is written as the spectra
+/// come; the XML comes last because its fileContent holds the `.ibd`
+/// SHA-1, so the spectra are kept until then without their arrays.
+/// Chromatograms are not written.
+pub fn write_imzml_streaming<W, D, S, I>(
+    imzml_out: W,
+    ibd_out: D,
+    header: &MzML,
+    spectra: I,
+    options: &ImzmlWriteOptions,
+) -> Result<(W, D), String>
+where
+    W: Write,
+    D: Write,
+    S: Borrow<Spectrum>,
+    I: IntoIterator<Item = S>,
+{
+    let uuid = options
+        .uuid
+        .or_else(|| file_content_uuid(header))
+        .unwrap_or_else(|| new_uuid(&header.run.id));
+    let mut ibd = Sink::new(ibd_out);
+    ibd.write_all(&uuid).map_err(|e| e.to_string())?;
+
+    // Continuous mode: the m/z array written for the first spectrum and the
+    // external cvParams pointing at it.
+    let mut shared_mz: Option<(BinaryData, Vec<CvParam>)> = None;
+    let mut written = Vec::new();
+    for spectrum in spectra {
+        let mut spectrum = spectrum.borrow().clone();
+        let arrays = spectrum
+            .binary_data_array_list
+            .iter_mut()
+            .flat_map(|l| &mut l.binary_data_arrays);
+        for bda in arrays {
+            let Some(binary) = bda.binary.take() else {
+                continue;
+            };
+            let numeric_type = numeric_type_of(&binary);
+            let shared = options.mode == ImzmlMode::Continuous
+                && find_cv(&bda.cv_params, ACC_MZ_ARRAY).is_some();
+            let external = match &shared_mz {
+                Some((first, external)) if shared => {
+                    if *first != binary {
+                        return Err(format!(
+                            "spectrum {}: continuous imzML needs the same m/z array in every \
+                             spectrum",
+                            spectrum.id
+                        ));
+                    }
+                    external.clone()
+                }
+                _ => {
+                    let external = write_array(&mut ibd, &binary)?;
+                    if shared {
+                        shared_mz = Some((binary, external.clone()));
+                    }
+                    external
+                }
+            };
+
+            bda.cv_params.retain(|p| !is_external(p));
+            replace_cv_term(
+                &mut bda.cv_params,
+                &COMPRESSION_ACCESSIONS,
+                ("MS:1000576", "no compression"),
+            );
+            let (_, accession, name) = numeric_type_term(numeric_type);
+            replace_cv_term(
+                &mut bda.cv_params,
+                &NUMERIC_TYPE_ACCESSIONS,
+                (accession, name),
+            );
+            bda.cv_params.extend(external);
+            bda.numeric_type = Some(numeric_type);
+            bda.array_length = None;
+            bda.encoded_length = None;
+            bda.binary = Some(empty_binary(numeric_type));
+        }
+        spectrum.default_array_length = Some(0);
+        written.push(spectrum);
+    }
+    ibd.flush().map_err(|e| e.to_string())?;
+    let sha1 = ibd.digest().to_uppercase();
+
+    let mut document = without_entries(header);
+    declare_ims_cv(&mut document);
+    let file_content = &mut document
+        .file_description
+        .get_or_insert_with(Default::default)
+        .file_content
+        .cv_params;
+    let (mode_accession, mode_name) = match options.mode {
+        ImzmlMode::Continuous => (ACC_CONTINUOUS, "continuous"),
+        ImzmlMode::Processed => (ACC_PROCESSED, "processed"),
+    };
+    let ims = [
+        ACC_CONTINUOUS,
+        ACC_PROCESSED,
+        ACC_UUID,
+        ACC_IBD_MD5,
+        ACC_IBD_SHA1,
+    ];
+    file_content.retain(|p| p.accession.as_deref().is_none_or(|a| !ims.contains(&a)));
+    file_content.extend([
+        ims_cv(mode_accession, mode_name, None),
+        ims_cv(
+            ACC_UUID,
+            "universally unique identifier",
+            Some(format_uuid(&uuid)),
+        ),
+        ims_cv(ACC_IBD_SHA1, "ibd SHA-1", Some(sha1)),
+    ]);
+    let list = document
+        .run
+        .spectrum_list
+        .get_or_insert_with(Default::default);
+    list.count = Some(written.len());
+    list.spectra = written;
+
+    let xml_options = MzmlWriteOptions {
+        indexed: false,
+        add_missing_cv_params: true,
+        ..Default::default()
+    };
+    let imzml_out = write_mzml_with_options(imzml_out, &document, &xml_options)?;
+    Ok((imzml_out, ibd.into_inner()))
+}
+
+/// Appends the little-endian values of `binary` to the `.ibd` and returns
+/// the external cvParams that point at them.
+fn write_array<D: Write>(ibd: &mut Sink<D>, binary: &BinaryData) -> Result<Vec<CvParam>, String> {
+    let offset = ibd.position();
+    let bytes = le_bytes(binary);
+    ibd.write_all(&bytes).map_err(|e| e.to_string())?;
+    Ok(vec![
+        ims_cv(ACC_EXTERNAL_DATA, "external data", Some("true".to_string())),
+        ims_cv(
+            ACC_EXTERNAL_ARRAY_LENGTH,
+            "external array length",
+            Some(binary_len(binary).to_string()),
+        ),
+        ims_cv(
+            ACC_EXTERNAL_ENCODED_LENGTH,
+            "external encoded length",
+            Some(bytes.len().to_string()),
+        ),
+        ims_cv(
+            ACC_EXTERNAL_OFFSET,
+            "external offset",
+            Some(offset.to_string()),
+        ),
+    ])
+}
+
+fn empty_binary(numeric_type: NumericType) -> BinaryData {
+    match numeric_type {
+        NumericType::Float64 => BinaryData::F64(Vec::new()),
+        NumericType::Float32 => BinaryData::F32(Vec::new()),
+        NumericType::Float16 => BinaryData::F16(Vec::new()),
+        NumericType::Int64 => BinaryData::I64(Vec::new()),
+        NumericType::Int32 => BinaryData::I32(Vec::new()),
+        NumericType::Int16 => BinaryData::I16(Vec::new()),
+    }
+}
+
+/// `header` with an empty spectrum list and no chromatograms, without
+/// cloning the entries.
+fn without_entries(header: &MzML) -> MzML {
+    let run = &header.run;
+    MzML {
+        cv_list: header.cv_list.clone(),
+        file_description: header.file_description.clone(),
+        referenceable_param_group_list: header.referenceable_param_group_list.clone(),
+        sample_list: header.sample_list.clone(),
+        instrument_list: header.instrument_list.clone(),
+        software_list: header.software_list.clone(),
+        data_processing_list: header.data_processing_list.clone(),
+        scan_settings_list: header.scan_settings_list.clone(),
+        run: Run {
+            id: run.id.clone(),
+            start_time_stamp: run.start_time_stamp.clone(),
+            default_instrument_configuration_ref: run.default_instrument_configuration_ref.clone(),
+            default_source_file_ref: run.default_source_file_ref.clone(),
+            sample_ref: run.sample_ref.clone(),
+            referenceable_param_group_refs: run.referenceable_param_group_refs.clone(),
+            cv_params: run.cv_params.clone(),
+            user_params: run.user_params.clone(),
+            source_file_ref_list: run.source_file_ref_list.clone(),
+            spectrum_list: run.spectrum_list.as_ref().map(|l| SpectrumList {
+                count: l.count,
+                default_data_processing_ref: l.default_data_processing_ref.clone(),
+                spectra: Vec::new(),
+            }),
+            chromatogram_list: None,
+            extensions: run.extensions.clone(),
+        },
+        extensions: header.extensions.clone(),
+    }
+}
+
+fn declare_ims_cv(mzml: &mut MzML) {
+    let list = mzml.cv_list.get_or_insert_with(default_cv_list);
+    if list.cv.iter().any(|cv| cv.id == "IMS") {
+        return;
+    }
+    list.cv.push(CvEntry {
+        id: "IMS".to_string(),
+        full_name: Some("Imaging MS Ontology".to_string()),
+        version: Some("1.1.0".to_string()),
+        uri: Some("https://raw.githubusercontent.com/imzML/imzML/master/imagingMS.obo".to_string()),
+    });
+    list.count = Some(list.cv.len());
+}
+
+/// Random-looking version 4 UUID from the SHA-1 of the run id and the
+/// current time.
+fn new_uuid(run_id: &str) -> [u8; 16] {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    let mut sha1 = sha1_smol::Sha1::new();
+    sha1.update(run_id.as_bytes());
+    sha1.update(&nanos.to_le_bytes());
+    let mut uuid = [0u8; 16];
+    uuid.copy_from_slice(&sha1.digest().bytes()[..16]);
+    uuid[6] = uuid[6] & 0x0f | 0x40;
+    uuid[8] = uuid[8] & 0x3f | 0x80;
+    uuid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        imzml::parse_imzml::{imzml_mode, parse_imzml},
+        mzml::utilities::peak_list::{peak_list_mzml, peak_list_spectrum},
+    };
+    use std::io::Cursor;
+
+    fn spectra(mz: [[f64; 2]; 2]) -> MzML {
+        let spectra = mz
+            .into_iter()
+            .enumerate()
+            .map(|(i, mz)| peak_list_spectrum(i, 1, mz.to_vec(), vec![i as f64, 2.0]))
+            .collect();
+        peak_list_mzml("run", Vec::new(), spectra)
+    }
+
+    #[test]
+    fn continuous_mode_shares_the_mz_array() {
+        let mzml = spectra([[100.0, 200.0], [100.0, 200.0]]);
+        let options = ImzmlWriteOptions {
+            mode: ImzmlMode::Continuous,
+            uuid: Some([1; 16]),
+        };
+        let (xml, ibd) = write_imzml(Vec::new(), Vec::new(), &mzml, &options).unwrap();
+        // UUID, one m/z array and two intensity arrays, all 2 x 8 bytes.
+        assert_eq!(ibd.len(), 16 + 3 * 16);
+        assert_eq!(ibd[..16], [1; 16]);
+
+        let text = String::from_utf8(xml.clone()).unwrap();
+        assert!(text.starts_with("<?xml"));
+        assert!(text.contains("<mzML"));
+        assert_eq!(
+            text.matches(r#"name="external offset" value="16""#).count(),
+            2
+        );
+        let sha1 = sha1_smol::Sha1::from(&ibd)
+            .digest()
+            .to_string()
+            .to_uppercase();
+        assert!(text.contains(&format!(r#"name="ibd SHA-1" value="{sha1}""#)));
+
+        let reread = parse_imzml(&xml, Cursor::new(&ibd)).unwrap();
+        assert_eq!(imzml_mode(&reread), Some(ImzmlMode::Continuous));
+        let spectra = &reread.run.spectrum_list.as_ref().unwrap().spectra;
+        let arrays = &spectra[1]
+            .binary_data_array_list
+            .as_ref()
+            .unwrap()
+            .binary_data_arrays;
+        assert_eq!(arrays[0].binary, Some(BinaryData::F64(vec![100.0, 200.0])));
+        assert_eq!(arrays[1].binary, Some(BinaryData::F64(vec![1.0, 2.0])));
+    }
+
+    #[test]
+    fn continuous_mode_rejects_different_mz_arrays() {
+        let mzml = spectra([[100.0, 200.0], [100.0, 201.0]]);
+        let options = ImzmlWriteOptions {
+            mode: ImzmlMode::Continuous,
+            ..Default::default()
+        };
+        let err = write_imzml(Vec::new(), Vec::new(), &mzml, &options).unwrap_err();
+        assert!(err.contains("same m/z array"), "{err}");
+
+        let (xml, ibd) =
+            write_imzml(Vec::new(), Vec::new(), &mzml, &ImzmlWriteOptions::default()).unwrap();
+        assert_eq!(ibd.len(), 16 + 4 * 16);
+        let reread = parse_imzml(&xml, Cursor::new(&ibd)).unwrap();
+        assert_eq!(imzml_mode(&reread), Some(ImzmlMode::Processed));
+    }
+}
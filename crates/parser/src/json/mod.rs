@@ -0,0 +1,2 @@
+pub mod parse_json;
+pub use parse_json::{mzml_from_json, parse_json, spectrum_from_json};
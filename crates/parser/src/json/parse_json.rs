@@ -0,0 +1,336 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+use miniz_oxide::inflate::decompress_to_vec_zlib;
+use serde_json::{Deserializer, Value};
+
+use crate::mzml::{
+    bin_to_mzml::{binary_len, numeric_type_of},
+    structs::*,
+    utilities::{
+        parse_bda_list::{decode_binary_data, encoding_for_array},
+        peak_list::peak_list_mzml,
+    },
+};
+
+/// Reads JSON as printed by `octo cat --full`, or newline-delimited JSON
+/// with one spectrum per line, optionally after a document (as printed by
+/// `octo cat`) that the spectra are appended to. Without a document the
+/// spectra get a minimal one whose run id is the stem of the file `name`.
+///
+/// Binary data is an array of numbers of the array's numeric type (`null`
+/// for NaN), or a base64 string decoded as in mzML, following the array's
+/// compression cvParams.
+///
+/// Fields may be left out, and take their default: `null`, an empty list or
+/// an empty string. The exceptions are the run `id`, the `file_description`
+/// of a document and the `id` of each spectrum and chromatogram, which are
+/// required. Array lengths are kept as given, so a round trip keeps them
+/// even where the source disagrees with its data; a missing
+/// `default_array_length` is the length of the first array, so leaving it
+/// out after editing a binary has it taken from the new data.
+pub fn parse_json(bytes: &[u8], name: &str) -> Result<MzML, String> {
+    let mut document = None;
+    let mut spectra = Vec::new();
+    for (i, value) in Deserializer::from_slice(bytes).into_iter().enumerate() {
+        let value: Value = value.map_err(|e| format!("JSON value {}: {e}", i + 1))?;
+        let parsed = if i == 0 && value.get("run").is_some() {
+            mzml_from_json(value).map(|mzml| document = Some(mzml))
+        } else {
+            spectrum_from_json(value).map(|spectrum| spectra.push(spectrum))
+        };
+        parsed.map_err(|e| format!("JSON value {}: {e}", i + 1))?;
+    }
+
+    let mut mzml = match document {
+        Some(mzml) => mzml,
+        None if spectra.is_empty() => return Err("no JSON document or spectra".to_string()),
+        None => {
+            let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+            peak_list_mzml(stem, Vec::new(), Vec::new())
+        }
+    };
+    if !spectra.is_empty() {
+        let data_processing = mzml.data_processing_list.as_ref();
+        let data_processing = data_processing.and_then(|l| l.data_processing.first());
+        let list = mzml.run.spectrum_list.get_or_insert_with(|| SpectrumList {
+            default_data_processing_ref: data_processing.map(|dp| dp.id.clone()),
+            ..Default::default()
+        });
+        for mut spectrum in spectra {
+            spectrum.index = spectrum.index.or(Some(list.spectra.len() as u32));
+            list.spectra.push(spectrum);
+        }
+        list.count = Some(list.spectra.len());
+    }
+    Ok(mzml)
+}
+
+/// Document of `value`, the JSON of an `MzML`.
+pub fn mzml_from_json(mut value: Value) -> Result<MzML, String> {
+    let mut take = |pointer: &str| match value.pointer_mut(pointer) {
+        Some(Value::Array(items)) => std::mem::take(items),
+        _ => Vec::new(),
+    };
+    let spectra = take("/run/spectrum_list/spectra");
+    let chromatograms = take("/run/chromatogram_list/chromatograms");
+    let mut mzml: MzML = serde_json::from_value(value).map_err(|e| e.to_string())?;
+    if mzml.run.id.is_empty() {
+        return Err("run has no id".to_string());
+    }
+    if mzml.file_description.is_none() {
+        return Err("document has no file_description".to_string());
+    }
+
+    if let Some(list) = mzml.run.spectrum_list.as_mut() {
+        list.spectra = spectra
+            .into_iter()
+            .map(spectrum_from_json)
+            .collect::<Result<_, _>>()?;
+    }
+    if let Some(list) = mzml.run.chromatogram_list.as_mut() {
+        list.chromatograms = chromatograms
+            .into_iter()
+            .map(|mut value| {
+                let binaries = take_binaries(&mut value);
+                let mut chromatogram: Chromatogram =
+                    serde_json::from_value(value).map_err(|e| e.to_string())?;
+                if chromatogram.id.is_empty() {
+                    return Err("chromatogram has no id".to_string());
+                }
+                let arrays = chromatogram.binary_data_array_list.as_mut();
+                let length = fill_binaries(arrays, binaries)
+                    .map_err(|e| format!("chromatogram {}: {e}", chromatogram.id))?;
+                chromatogram.default_array_length = chromatogram.default_array_length.or(length);
+                Ok::<_, String>(chromatogram)
+            })
+            .collect::<Result<_, _>>()?;
+    }
+    Ok(mzml)
+}
+
+/// Spectrum of `value`, the JSON of a `Spectrum`.
+pub fn spectrum_from_json(mut value: Value) -> Result<Spectrum, String> {
+    let binaries = take_binaries(&mut value);
+    let mut spectrum: Spectrum = serde_json::from_value(value).map_err(|e| e.to_string())?;
+    if spectrum.id.is_empty() {
+        return Err("spectrum has no id".to_string());
+    }
+    let arrays = spectrum.binary_data_array_list.as_mut();
+    let length =
+        fill_binaries(arrays, binaries).map_err(|e| format!("spectrum {}: {e}", spectrum.id))?;
+    spectrum.default_array_length = spectrum.default_array_length.or(length);
+    Ok(spectrum)
+}
+
+/// Takes the `binary` of each binary data array of a spectrum or
+/// chromatogram, which the untagged `BinaryData` would read as `F64`.
+fn take_binaries(value: &mut Value) -> Vec<Value> {
+    match value.pointer_mut("/binary_data_array_list/binary_data_arrays") {
+        Some(Value::Array(arrays)) => arrays
+            .iter_mut()
+            .map(|array| array.get_mut("binary").map_or(Value::Null, Value::take))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Decodes `binaries` into the arrays of `list` and returns the length of
+/// the first one. The encoded lengths, of the source's encoding, no longer
+/// apply.
+fn fill_binaries(
+    list: Option<&mut BinaryDataArrayList>,
+    binaries: Vec<Value>,
+) -> Result<Option<usize>, String> {
+    let Some(list) = list else {
+        return Ok(None);
+    };
+    let mut length = None;
+    for (bda, binary) in list.binary_data_arrays.iter_mut().zip(binaries) {
+        bda.binary = binary_from_json(bda, binary)?;
+        bda.encoded_length = None;
+        if let Some(binary) = &bda.binary {
+            bda.numeric_type = Some(numeric_type_of(binary));
+            length = length.or(Some(binary_len(binary)));
+        }
+    }
+    Ok(length)
+}
+
+fn binary_from_json(bda: &BinaryDataArray, value: Value) -> Result<Option<BinaryData>, String> {
+    let encoding = encoding_for_array(bda);
+    let text = match value {
+        Value::Null => return Ok(None),
+        Value::Array(values) => return numbers(encoding.numeric_type, &values).map(Some),
+        Value::String(text) => text,
+        other => {
+            return Err(format!(
+                "binary is {other}, not an array or a base64 string"
+            ));
+        }
+    };
+    let mut bytes = STANDARD
+        .decode(text.trim())
+        .map_err(|e| format!("invalid base64 binary: {e}"))?;
+    if encoding.is_zlib_compressed {
+        bytes = decompress_to_vec_zlib(&bytes)
+            .map_err(|e| format!("invalid zlib binary: {:?}", e.status))?;
+    }
+    if let Some(codec) = encoding.numpress {
+        return codec
+            .decode(&bytes)
+            .map(|values| Some(BinaryData::F64(values)));
+    }
+    Ok(Some(decode_binary_data(
+        encoding.numeric_type,
+        &bytes,
+        None,
+    )))
+}
+
+/// `values` as `numeric_type`; `null` is NaN in float arrays, as
+/// `serde_json` writes it.
+fn numbers(numeric_type: NumericType, values: &[Value]) -> Result<BinaryData, String> {
+    fn collect<T>(values: &[Value], f: impl Fn(&Value) -> Option<T>) -> Result<Vec<T>, String> {
+        let number = |(i, value)| f(value).ok_or_else(|| format!("binary[{i}] is {value}"));
+        values.iter().enumerate().map(number).collect()
+    }
+    let float = |v: &Value| {
+        if v.is_null() {
+            Some(f64::NAN)
+        } else {
+            v.as_f64()
+        }
+    };
+    let int = |v: &Value| v.as_i64();
+    Ok(match numeric_type {
+        NumericType::Float64 => BinaryData::F64(collect(values, float)?),
+        NumericType::Float32 => BinaryData::F32(collect(values, |v| float(v).map(|f| f as f32))?),
+        NumericType::Float16 => {
+            BinaryData::F16(collect(values, |v| u16::try_from(v.as_u64()?).ok())?)
+        }
+        NumericType::Int64 => BinaryData::I64(collect(values, int)?),
+        NumericType::Int32 => BinaryData::I32(collect(values, |v| i32::try_from(int(v)?).ok())?),
+        NumericType::Int16 => BinaryData::I16(collect(values, |v| i16::try_from(int(v)?).ok())?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        b64::{decode, encode, encoder::encode::WritingMode},
+        mzml::{
+            parse_mzml::parse_mzml,
+            utilities::peak_list::{ACC_MZ_ARRAY, array_f64, peak_list_spectrum},
+        },
+    };
+
+    fn binaries(mzml: &MzML) -> Vec<Option<BinaryData>> {
+        let spectra = mzml.run.spectrum_list.iter().flat_map(|l| &l.spectra);
+        let spectra = spectra.flat_map(|s| &s.binary_data_array_list);
+        let chromatograms = mzml
+            .run
+            .chromatogram_list
+            .iter()
+            .flat_map(|l| &l.chromatograms);
+        let lists = spectra.chain(chromatograms.flat_map(|c| &c.binary_data_array_list));
+        let arrays = lists.flat_map(|l| &l.binary_data_arrays);
+        arrays.map(|bda| bda.binary.clone()).collect()
+    }
+
+    #[test]
+    fn reads_back_cat_full_output() {
+        let bytes = std::fs::read("data/mzml/tiny4_LTQ-FT.mzML0.99.1.mzML").unwrap();
+        let mzml = parse_mzml(&bytes).unwrap();
+        let json = serde_json::to_string_pretty(&mzml).unwrap();
+
+        let read = parse_json(json.as_bytes(), "tiny4.json").unwrap();
+        assert!(matches!(binaries(&mzml)[1], Some(BinaryData::F32(_))));
+        assert_eq!(binaries(&read), binaries(&mzml));
+        let json = serde_json::to_string_pretty(&read).unwrap();
+        let again = parse_json(json.as_bytes(), "tiny4.json").unwrap();
+        assert_eq!(serde_json::to_string_pretty(&again).unwrap(), json);
+
+        let mut encoded = Vec::new();
+        encode(&read, 12, false, WritingMode::Memory, &mut encoded).unwrap();
+        assert_eq!(binaries(&decode(&encoded).unwrap()), binaries(&mzml));
+    }
+
+    #[test]
+    fn reads_spectra_per_line_with_base64_binaries() {
+        let spectrum = peak_list_spectrum(0, 2, vec![100.5, 200.25], vec![1.0, 2.0]);
+        let mut value = serde_json::to_value(&spectrum).unwrap();
+        let mz: Vec<u8> = [100.5f64, 200.25]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        value["binary_data_array_list"]["binary_data_arrays"][0]["binary"] =
+            Value::String(STANDARD.encode(mz));
+        let mut second = serde_json::to_value(&spectrum).unwrap();
+        second["id"] = Value::from("scan=2");
+        second["index"] = Value::Null;
+        let ndjson = format!("{value}\n{second}\n");
+
+        let mzml = parse_json(ndjson.as_bytes(), "edited.ndjson").unwrap();
+        assert_eq!(mzml.run.id, "edited");
+        let spectra = &mzml.run.spectrum_list.as_ref().unwrap().spectra;
+        assert_eq!(spectra.len(), 2);
+        assert_eq!(
+            array_f64(&spectra[0], ACC_MZ_ARRAY).unwrap(),
+            [100.5, 200.25]
+        );
+        assert_eq!(
+            array_f64(&spectra[1], ACC_MZ_ARRAY).unwrap(),
+            [100.5, 200.25]
+        );
+        assert_eq!(spectra[1].index, Some(1));
+
+        let arrays = &mut second["binary_data_array_list"]["binary_data_arrays"];
+        arrays[0]["binary"] = serde_json::json!([100.5]);
+        arrays[1]["binary"] = serde_json::json!([1.0]);
+        arrays[0]["encoded_length"] = Value::from(24);
+        second["default_array_length"] = Value::from(340032);
+        let edited = spectrum_from_json(second.clone()).unwrap();
+        assert_eq!(edited.default_array_length, Some(340032));
+        let bdal = edited.binary_data_array_list.unwrap();
+        assert_eq!(bdal.binary_data_arrays[0].encoded_length, None);
+        let mut dropped = second.clone();
+        dropped
+            .as_object_mut()
+            .unwrap()
+            .remove("default_array_length");
+        dropped.as_object_mut().unwrap().remove("scan_list");
+        let edited = spectrum_from_json(dropped).unwrap();
+        assert_eq!(edited.default_array_length, Some(1));
+        assert!(edited.scan_list.is_none());
+        let mut dropped = second.clone();
+        dropped.as_object_mut().unwrap().remove("id");
+        assert_eq!(
+            spectrum_from_json(dropped).unwrap_err(),
+            "spectrum has no id"
+        );
+
+        second["binary_data_array_list"]["binary_data_arrays"][0]["binary"] = Value::from("a");
+        let err = parse_json(format!("{value}\n{second}").as_bytes(), "x.ndjson").unwrap_err();
+        assert!(
+            err.starts_with("JSON value 2: spectrum scan=2: invalid base64"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn keeps_array_lengths_and_requires_a_file_description() {
+        let bytes = std::fs::read("data/mzml/tiny.pwiz.mzML0.99.10.mzML").unwrap();
+        let mzml = parse_mzml(&bytes).unwrap();
+        let mut value = serde_json::to_value(&mzml).unwrap();
+        let read = mzml_from_json(value.clone()).unwrap();
+        // Spectrum 0 and the TIC declare 15 points but hold 10.
+        let spectra = &read.run.spectrum_list.as_ref().unwrap().spectra;
+        assert_eq!(spectra[0].default_array_length, Some(15));
+        let chromatograms = &read.run.chromatogram_list.as_ref().unwrap().chromatograms;
+        assert_eq!(chromatograms[0].default_array_length, Some(15));
+
+        value["file_description"] = Value::Null;
+        let err = mzml_from_json(value).unwrap_err();
+        assert_eq!(err, "document has no file_description");
+    }
+}
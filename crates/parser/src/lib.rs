@@ -1,5 +1,35 @@
 pub mod mzml;
-pub use mzml::{bin_to_mzml, parse_indexed_mzml, parse_mzml, structs::*};
+pub use mzml::{
+    ArrayCompression, ArrayPrecision, IndexedMzMLReader, MzMLItem, MzMLReader, MzmlWriteOptions,
+    ParseOptions, ParseReport, ParseWarning, WarningAction, bin_to_mzml, bin_to_mzml_with_options,
+    compare_mzml, fix_refs, parse_indexed_mzml, parse_mzml, parse_mzml_parallel, parse_mzml_reader,
+    parse_mzml_reader_with_report, parse_mzml_with_options, parse_mzml_with_report, salvage_mzml,
+    structs::*, validate_cv_terms, validate_indexed_mzml, validate_refs, validate_schema,
+    validate_schema_mzml, verify_roundtrip, write_b000_as_mzml, write_mzml, write_mzml_streaming,
+    write_mzml_with_options,
+};
 pub mod b64;
 pub use b64::{decoder, encoder, utilities::Header};
+pub mod mgf;
+pub use mgf::{MgfReader, parse_mgf, write_b000_as_mgf, write_mgf, write_mgf_streaming};
+pub mod mzxml;
+pub use mzxml::{parse_mzxml, write_b000_as_mzxml, write_mzxml, write_mzxml_streaming};
+pub mod msn;
+pub use msn::{MsnFormat, MsnReader, parse_msn, write_b000_as_msn, write_msn, write_msn_streaming};
+pub mod imzml;
+pub use imzml::{
+    ImzmlMode, ImzmlReader, ImzmlWriteOptions, IonImage, Pixel, b000_ion_image, imzml_mode,
+    ion_image, ion_image_streaming, parse_imzml, pixel, write_b000_as_imzml, write_imzml,
+    write_imzml_streaming,
+};
+pub mod npz;
+pub use npz::{write_b000_as_npz, write_npz};
+pub mod parquet;
+pub use parquet::{write_b000_as_parquet, write_parquet, write_parquet_streaming};
+pub mod andi;
+pub use andi::parse_andi;
+pub mod json;
+pub use json::{mzml_from_json, parse_json, spectrum_from_json};
+pub mod traml;
+pub use traml::{TraML, TransitionMatch, match_transitions, parse_traml};
 pub mod utilities;
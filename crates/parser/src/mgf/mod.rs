@@ -0,0 +1,4 @@
+pub mod parse_mgf;
+pub use parse_mgf::{MgfReader, parse_mgf};
+pub mod write_mgf;
+pub use write_mgf::{write_b000_as_mgf, write_mgf, write_mgf_streaming};
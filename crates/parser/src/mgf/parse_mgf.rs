@@ -0,0 +1,307 @@
+use std::io::BufRead;
+
+use crate::mzml::{
+    structs::*,
+    utilities::peak_list::{
+        ACC_CHARGE_STATE, ACC_MZ, ACC_PEAK_INTENSITY, ACC_POSSIBLE_CHARGE_STATE,
+        ACC_SELECTED_ION_MZ, ms_cv, ms_cv_with_unit, peak_list_mzml, peak_list_spectrum,
+        selected_ion_params_mut, set_scan_start_seconds,
+    },
+};
+
+/// Reads the MGF file `bytes` into a document with one spectrum per
+/// `BEGIN IONS` block. `name` is the file name, kept as the source file;
+/// its stem becomes the run id.
+pub fn parse_mgf(bytes: &[u8], name: &str) -> Result<MzML, String> {
+    let spectra = MgfReader::new(bytes).collect::<Result<Vec<_>, _>>()?;
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let source = SourceFile {
+        id: "MGF".to_string(),
+        name: name.to_string(),
+        location: "file://".to_string(),
+        cv_param: vec![
+            ms_cv("MS:1001062", "Mascot MGF format", None),
+            ms_cv("MS:1000774", "multiple peak list nativeID format", None),
+        ],
+        ..Default::default()
+    };
+    Ok(peak_list_mzml(stem, vec![source], spectra))
+}
+
+/// Pull reader yielding one `Spectrum` per `BEGIN IONS` block.
+///
+/// TITLE becomes the native id, PEPMASS the selected ion m/z and intensity,
+/// CHARGE the charge state (or possible charge states when several are
+/// listed), RTINSECONDS the scan start time and SCANS the scan number;
+/// MSLEVEL overrides the default ms level of 2. Other keys are kept as
+/// userParams. A CHARGE given before the first block applies to blocks
+/// without one.
+pub struct MgfReader<R> {
+    reader: R,
+    line: String,
+    line_number: usize,
+    index: usize,
+    default_charge: Option<String>,
+    failed: bool,
+}
+
+impl<R: BufRead> MgfReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: String::new(),
+            line_number: 0,
+            index: 0,
+            default_charge: None,
+            failed: false,
+        }
+    }
+
+    /// Next spectrum, `None` at the end of the input.
+    pub fn next_spectrum(&mut self) -> Option<Result<Spectrum, String>> {
+        if self.failed {
+            return None;
+        }
+        let next = self.read_block().transpose();
+        if matches!(next, Some(Err(_))) {
+            self.failed = true;
+        }
+        next
+    }
+
+    /// Trimmed next line that is neither blank nor a comment.
+    fn next_line(&mut self) -> Result<Option<&str>, String> {
+        loop {
+            self.line.clear();
+            let read = self
+                .reader
+                .read_line(&mut self.line)
+                .map_err(|e| format!("MGF line {}: {e}", self.line_number + 1))?;
+            if read == 0 {
+                return Ok(None);
+            }
+            self.line_number += 1;
+            let line = self.line.trim();
+            if !line.is_empty() && !line.starts_with(['#', ';', '!', '/']) {
+                return Ok(Some(self.line.trim()));
+            }
+        }
+    }
+
+    fn read_block(&mut self) -> Result<Option<Spectrum>, String> {
+        loop {
+            let Some(line) = self.next_line()? else {
+                return Ok(None);
+            };
+            if line.eq_ignore_ascii_case("BEGIN IONS") {
+                break;
+            }
+            match line.split_once('=') {
+                Some((key, value)) if key.trim().eq_ignore_ascii_case("CHARGE") => {
+                    self.default_charge = Some(value.trim().to_string());
+                }
+                Some(_) => {}
+                None => return Err(self.error("expected BEGIN IONS")),
+            }
+        }
+
+        let mut params: Vec<(String, String)> = Vec::new();
+        let (mut mz, mut intensity) = (Vec::new(), Vec::new());
+        loop {
+            let Some(line) = self.next_line()? else {
+                return Err(format!(
+                    "MGF line {}: missing END IONS",
+                    self.line_number + 1
+                ));
+            };
+            if line.eq_ignore_ascii_case("END IONS") {
+                break;
+            }
+            if line.starts_with(|c: char| c.is_ascii_digit() || c == '.' || c == '-') {
+                let mut fields = line.split_whitespace().map(str::parse::<f64>);
+                match (fields.next(), fields.next()) {
+                    (Some(Ok(m)), None) => (mz.push(m), intensity.push(0.0)),
+                    (Some(Ok(m)), Some(Ok(i))) => (mz.push(m), intensity.push(i)),
+                    _ => return Err(self.error("bad peak line")),
+                };
+            } else if let Some((key, value)) = line.split_once('=') {
+                params.push((key.trim().to_ascii_uppercase(), value.trim().to_string()));
+            } else {
+                return Err(self.error("expected KEY=value, a peak or END IONS"));
+            }
+        }
+
+        let spectrum = self.spectrum(params, mz, intensity)?;
+        self.index += 1;
+        Ok(Some(spectrum))
+    }
+
+    fn spectrum(
+        &self,
+        params: Vec<(String, String)>,
+        mz: Vec<f64>,
+        intensity: Vec<f64>,
+    ) -> Result<Spectrum, String> {
+        let ms_level = match params.iter().find(|(key, _)| key == "MSLEVEL") {
+            Some((_, level)) => level
+                .parse()
+                .map_err(|_| self.error(&format!("bad MSLEVEL {level:?}")))?,
+            None => 2,
+        };
+        let mut spectrum = peak_list_spectrum(self.index, ms_level, mz, intensity);
+
+        let mut charge = None;
+        for (key, value) in params {
+            match key.as_str() {
+                "TITLE" => spectrum.native_id = Some(value),
+                "MSLEVEL" => {}
+                "CHARGE" => charge = Some(value),
+                "PEPMASS" => {
+                    let mut fields = value.split_whitespace().map(str::parse::<f64>);
+                    let Some(Ok(precursor_mz)) = fields.next() else {
+                        return Err(self.error(&format!("bad PEPMASS {value:?}")));
+                    };
+                    let ion = selected_ion_params_mut(&mut spectrum);
+                    ion.push(ms_cv_with_unit(
+                        ACC_SELECTED_ION_MZ,
+                        "selected ion m/z",
+                        precursor_mz.to_string(),
+                        (ACC_MZ, "m/z"),
+                    ));
+                    if let Some(Ok(precursor_intensity)) = fields.next() {
+                        ion.push(ms_cv_with_unit(
+                            ACC_PEAK_INTENSITY,
+                            "peak intensity",
+                            precursor_intensity.to_string(),
+                            ("MS:1000131", "number of detector counts"),
+                        ));
+                    }
+                }
+                "RTINSECONDS" => match value.parse::<f64>() {
+                    Ok(seconds) => set_scan_start_seconds(&mut spectrum, seconds),
+                    Err(_) => spectrum.user_params.push(user_param(key, value)),
+                },
+                "SCANS" => match value.parse::<u32>() {
+                    Ok(scan) => spectrum.scan_number = Some(scan),
+                    Err(_) => spectrum.user_params.push(user_param(key, value)),
+                },
+                _ => spectrum.user_params.push(user_param(key, value)),
+            }
+        }
+
+        if let Some(charge) = charge.or_else(|| self.default_charge.clone()) {
+            let charges = parse_charges(&charge)
+                .ok_or_else(|| self.error(&format!("bad CHARGE {charge:?}")))?;
+            let (accession, name) = match charges.len() {
+                1 => (ACC_CHARGE_STATE, "charge state"),
+                _ => (ACC_POSSIBLE_CHARGE_STATE, "possible charge state"),
+            };
+            let ion = selected_ion_params_mut(&mut spectrum);
+            for z in charges {
+                ion.push(ms_cv(accession, name, Some(z.to_string())));
+            }
+        }
+        Ok(spectrum)
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("MGF line {}: {message}", self.line_number)
+    }
+}
+
+impl<R: BufRead> Iterator for MgfReader<R> {
+    type Item = Result<Spectrum, String>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_spectrum()
+    }
+}
+
+#[inline]
+fn user_param(name: String, value: String) -> UserParam {
+    UserParam {
+        name,
+        value: Some(value),
+        ..Default::default()
+    }
+}
+
+/// `2+`, `+2`, `2`, `2+ and 3+` or `2+,3+`.
+fn parse_charges(value: &str) -> Option<Vec<i32>> {
+    value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty() && !s.eq_ignore_ascii_case("and"))
+        .map(|s| {
+            let negative = s.contains('-');
+            let z: i32 = s.trim_matches(['+', '-']).parse().ok()?;
+            Some(if negative { -z } else { z })
+        })
+        .collect::<Option<Vec<_>>>()
+        .filter(|charges| !charges.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mgf::write_mgf::write_mgf;
+
+    const MGF: &str = "\
+COM=test library
+CHARGE=2+
+
+BEGIN IONS
+TITLE=scan=11
+PEPMASS=445.34 120053
+RTINSECONDS=353.43
+SCANS=11
+SEQ=PEPTIDE
+100.5 10
+200.25 20.5
+END IONS
+
+# comment
+BEGIN IONS
+TITLE=scan=12
+PEPMASS=512.3
+CHARGE=2+ and 3+
+300 1
+END IONS
+";
+
+    #[test]
+    fn reads_blocks_into_spectra() {
+        let mzml = parse_mgf(MGF.as_bytes(), "lib.mgf").unwrap();
+        assert_eq!(mzml.run.id, "lib");
+        let spectra = &mzml.run.spectrum_list.as_ref().unwrap().spectra;
+        assert_eq!(spectra.len(), 2);
+
+        let first = &spectra[0];
+        assert_eq!(first.native_id.as_deref(), Some("scan=11"));
+        assert_eq!(first.scan_number, Some(11));
+        assert_eq!(first.user_params[0].name, "SEQ");
+        let first_arrays = &first.binary_data_array_list.as_ref().unwrap();
+        assert!(matches!(
+            &first_arrays.binary_data_arrays[1].binary,
+            Some(BinaryData::F64(v)) if v == &[10.0, 20.5]
+        ));
+
+        let mgf = String::from_utf8(write_mgf(Vec::new(), &mzml).unwrap()).unwrap();
+        assert_eq!(
+            mgf,
+            "BEGIN IONS\nTITLE=scan=11\nPEPMASS=445.34 120053\nCHARGE=2+\n\
+             RTINSECONDS=353.43\nSCANS=11\n100.5 10\n200.25 20.5\nEND IONS\n\n\
+             BEGIN IONS\nTITLE=scan=12\nPEPMASS=512.3\nCHARGE=2+ and 3+\n300 1\nEND IONS\n\n"
+        );
+    }
+
+    #[test]
+    fn reports_the_line_of_malformed_input() {
+        let err = parse_mgf(b"BEGIN IONS\nTITLE=a\n100 x\nEND IONS\n", "a.mgf").unwrap_err();
+        assert_eq!(err, "MGF line 3: bad peak line");
+        let err = parse_mgf(b"BEGIN IONS\n100 1\n", "a.mgf").unwrap_err();
+        assert!(err.contains("missing END IONS"), "{err}");
+        assert_eq!(parse_charges("3-"), Some(vec![-3]));
+        assert_eq!(parse_charges("+2,3+"), Some(vec![2, 3]));
+    }
+}
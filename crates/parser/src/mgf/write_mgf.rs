@@ -0,0 +1,148 @@
+use std::{
+    borrow::Borrow,
+    io::{Read, Seek, Write},
+};
+
+use crate::{
+    b64::B000Reader,
+    mzml::{
+        structs::*,
+        utilities::peak_list::{
+            ACC_INTENSITY_ARRAY, ACC_MZ_ARRAY, array_f64, ms_level, precursor_charges,
+            precursor_intensity, precursor_mz, scan_start_seconds,
+        },
+    },
+};
+
+/// Writes the MSn spectra of `mzml` as MGF to `out` and returns `out`.
+///
+/// MS1 spectra are left out; spectra without an ms level are written.
+pub fn write_mgf<W: Write>(out: W, mzml: &MzML) -> Result<W, String> {
+    let spectra = mzml.run.spectrum_list.iter().flat_map(|l| &l.spectra);
+    write_mgf_streaming(out, spectra)
+}
+
+/// Writes the MSn spectra of the B000 file read from `input` as MGF,
+/// decoding one spectrum at a time.
+pub fn write_b000_as_mgf<W: Write, R: Read + Seek>(out: W, input: R) -> Result<W, String> {
+    let mut spectra = B000Reader::new(input)?.spectra();
+    let out = write_mgf_streaming(out, spectra.by_ref())?;
+    spectra.finish().map(|()| out)
+}
+
+/// Writes one `BEGIN IONS` block per MSn spectrum of `spectra`.
+pub fn write_mgf_streaming<W, S, I>(mut out: W, spectra: I) -> Result<W, String>
+where
+    W: Write,
+    S: Borrow<Spectrum>,
+    I: IntoIterator<Item = S>,
+{
+    for spectrum in spectra {
+        let spectrum = spectrum.borrow();
+        if ms_level(spectrum) == Some(1) {
+            continue;
+        }
+        write_mgf_spectrum(&mut out, spectrum).map_err(|e| e.to_string())?;
+    }
+    out.flush().map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+/// `BEGIN IONS` block of `spectrum`: TITLE from the native id, PEPMASS,
+/// CHARGE, RTINSECONDS and SCANS when known, then one `m/z intensity` line
+/// per peak.
+fn write_mgf_spectrum<W: Write>(out: &mut W, spectrum: &Spectrum) -> std::io::Result<()> {
+    writeln!(out, "BEGIN IONS")?;
+
+    let title = spectrum.native_id.as_deref().unwrap_or(&spectrum.id);
+    writeln!(out, "TITLE={title}")?;
+
+    if let Some(mz) = precursor_mz(spectrum) {
+        match precursor_intensity(spectrum) {
+            Some(intensity) => writeln!(out, "PEPMASS={mz} {intensity}")?,
+            None => writeln!(out, "PEPMASS={mz}")?,
+        }
+    }
+
+    let charges = precursor_charges(spectrum);
+    if !charges.is_empty() {
+        let charges: Vec<String> = charges.iter().map(|&z| format_charge(z)).collect();
+        writeln!(out, "CHARGE={}", charges.join(" and "))?;
+    }
+
+    if let Some(seconds) = scan_start_seconds(spectrum) {
+        writeln!(out, "RTINSECONDS={seconds}")?;
+    }
+    if let Some(scan) = spectrum.scan_number {
+        writeln!(out, "SCANS={scan}")?;
+    }
+
+    let mz = array_f64(spectrum, ACC_MZ_ARRAY).unwrap_or_default();
+    let intensity = array_f64(spectrum, ACC_INTENSITY_ARRAY).unwrap_or_default();
+    for (i, mz) in mz.iter().enumerate() {
+        let intensity = intensity.get(i).copied().unwrap_or(0.0);
+        writeln!(out, "{mz} {intensity}")?;
+    }
+
+    writeln!(out, "END IONS")?;
+    writeln!(out)
+}
+
+/// `2+`, `3-`: MGF puts the sign after the magnitude.
+#[inline]
+fn format_charge(z: i32) -> String {
+    let sign = if z < 0 { '-' } else { '+' };
+    format!("{}{sign}", z.unsigned_abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        b64::{encode, encoder::encode::WritingMode},
+        mgf::parse_mgf::parse_mgf,
+        mzml::{bin_to_mzml::convert_bin_to_mzml_bytes, parse_mzml::parse_mzml},
+    };
+    use std::io::Cursor;
+
+    #[test]
+    fn writes_msn_spectra_of_mzml_fixtures() {
+        let bytes = std::fs::read("data/mzml/tiny.pwiz.mzML0.99.10.mzML").unwrap();
+        let mgf = String::from_utf8(write_mgf(Vec::new(), &parse_mzml(&bytes).unwrap()).unwrap())
+            .unwrap();
+        let block = mgf.split("\n\n").next().unwrap();
+        let head: Vec<&str> = block.lines().take(5).collect();
+        assert_eq!(
+            head,
+            [
+                "BEGIN IONS",
+                "TITLE=20",
+                "PEPMASS=445.34 120053",
+                "CHARGE=2+",
+                "RTINSECONDS=359.43"
+            ]
+        );
+        assert_eq!(
+            mgf.matches("BEGIN IONS").count(),
+            1,
+            "MS1 spectra are skipped"
+        );
+    }
+
+    #[test]
+    fn mgf_round_trips_through_b000_and_mzml() {
+        let mgf = "BEGIN IONS\nTITLE=a\nPEPMASS=500.25\nCHARGE=3+\nRTINSECONDS=12.5\n\
+                   100 1\n101.5 2\nEND IONS\n\n";
+        let mzml = parse_mgf(mgf.as_bytes(), "a.mgf").unwrap();
+
+        let mut bytes = Vec::new();
+        encode(&mzml, 12, false, WritingMode::Memory, &mut bytes).unwrap();
+        let written = write_b000_as_mgf(Vec::new(), Cursor::new(&bytes)).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), mgf);
+
+        let xml = convert_bin_to_mzml_bytes(&mzml).unwrap();
+        let reparsed = parse_mzml(&xml).unwrap();
+        let written = write_mgf(Vec::new(), &reparsed).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), mgf);
+    }
+}
@@ -0,0 +1,4 @@
+pub mod parse_msn;
+pub use parse_msn::{MsnReader, parse_msn};
+pub mod write_msn;
+pub use write_msn::{MsnFormat, write_b000_as_msn, write_msn, write_msn_streaming};
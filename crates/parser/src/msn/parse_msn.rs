@@ -0,0 +1,340 @@
+use std::io::BufRead;
+
+use crate::{
+    msn::write_msn::PROTON,
+    mzml::{
+        structs::*,
+        utilities::peak_list::{
+            ACC_CHARGE_STATE, ACC_ION_INJECTION_TIME, ACC_MZ, ACC_POSSIBLE_CHARGE_STATE,
+            ACC_SELECTED_ION_MZ, first_scan_params_mut, ms_cv, ms_cv_with_unit, peak_list_mzml,
+            peak_list_spectrum, selected_ion_params_mut, set_scan_start_seconds,
+        },
+    },
+    mzxml::parse_mzxml::{ACTIVATION_TERMS, lookup},
+};
+
+/// Reads an `.ms1` or `.ms2` file `bytes` into a document with one spectrum
+/// per S line. `name` is the file name, kept as the source file; its stem
+/// becomes the run id.
+pub fn parse_msn(bytes: &[u8], name: &str) -> Result<MzML, String> {
+    let spectra = MsnReader::new(bytes).collect::<Result<Vec<_>, _>>()?;
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let format = if spectra.iter().any(|s| s.ms_level == Some(2)) {
+        ms_cv("MS:1001466", "MS2 format", None)
+    } else {
+        ms_cv("MS:1002597", "MS1 format", None)
+    };
+    let source = SourceFile {
+        id: "MSn".to_string(),
+        name: name.to_string(),
+        location: "file://".to_string(),
+        cv_param: vec![
+            format,
+            ms_cv("MS:1000776", "scan number only nativeID format", None),
+        ],
+        ..Default::default()
+    };
+    Ok(peak_list_mzml(stem, vec![source], spectra))
+}
+
+/// Pull reader yielding one `Spectrum` per S line of an `.ms1` or `.ms2`
+/// file.
+///
+/// The first scan number of the S line becomes the scan number and the
+/// `scan=N` native id. Spectra with a precursor m/z, on the S line or from
+/// the `[M+H]+` mass of a Z line, are MS2 and the others MS1. Z lines give
+/// the charge state (or possible charge states when there are several),
+/// the RetTime (or RTime) I line the scan start time in minutes, the
+/// IonInjectionTime I line the ion injection time in milliseconds and the
+/// ActivationType I line of an MS2 spectrum its dissociation method. Other
+/// I lines, and activation types without a term, are kept as userParams;
+/// H and D lines are skipped.
+pub struct MsnReader<R> {
+    reader: R,
+    line: String,
+    line_number: usize,
+    index: usize,
+    /// S line starting the next spectrum, read at the end of the previous
+    /// one.
+    next_scan: Option<ScanLine>,
+    failed: bool,
+}
+
+struct ScanLine {
+    scan: u32,
+    precursor_mz: Option<f64>,
+}
+
+impl<R: BufRead> MsnReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: String::new(),
+            line_number: 0,
+            index: 0,
+            next_scan: None,
+            failed: false,
+        }
+    }
+
+    /// Next spectrum, `None` at the end of the input.
+    pub fn next_spectrum(&mut self) -> Option<Result<Spectrum, String>> {
+        if self.failed {
+            return None;
+        }
+        let next = self.read_spectrum().transpose();
+        if matches!(next, Some(Err(_))) {
+            self.failed = true;
+        }
+        next
+    }
+
+    /// Trimmed next line that is not blank.
+    fn next_line(&mut self) -> Result<Option<&str>, String> {
+        loop {
+            self.line.clear();
+            let read = self
+                .reader
+                .read_line(&mut self.line)
+                .map_err(|e| format!("MS1/MS2 line {}: {e}", self.line_number + 1))?;
+            if read == 0 {
+                return Ok(None);
+            }
+            self.line_number += 1;
+            if !self.line.trim().is_empty() {
+                return Ok(Some(self.line.trim()));
+            }
+        }
+    }
+
+    fn read_spectrum(&mut self) -> Result<Option<Spectrum>, String> {
+        let scan = match self.next_scan.take() {
+            Some(scan) => scan,
+            None => loop {
+                let Some(line) = self.next_line()? else {
+                    return Ok(None);
+                };
+                match line.split_whitespace().next() {
+                    Some("H") => {}
+                    Some("S") => match scan_line(line) {
+                        Some(scan) => break scan,
+                        None => return Err(self.error("bad S line")),
+                    },
+                    _ => return Err(self.error("expected an H or S line")),
+                }
+            },
+        };
+
+        let mut info: Vec<(String, String)> = Vec::new();
+        let mut charges: Vec<(i32, f64)> = Vec::new();
+        let (mut mz, mut intensity) = (Vec::new(), Vec::new());
+        while let Some(line) = self.next_line()? {
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("S") => match scan_line(line) {
+                    Some(scan) => {
+                        self.next_scan = Some(scan);
+                        break;
+                    }
+                    None => return Err(self.error("bad S line")),
+                },
+                Some("I") => {
+                    let key = fields.next().unwrap_or_default().to_string();
+                    let value = fields.collect::<Vec<_>>().join(" ");
+                    info.push((key, value));
+                }
+                Some("Z") => {
+                    let z = fields.next().and_then(|z| z.parse().ok());
+                    let mass = fields.next().and_then(|m| m.parse().ok());
+                    match (z, mass) {
+                        (Some(z), Some(mass)) => charges.push((z, mass)),
+                        _ => return Err(self.error("bad Z line")),
+                    }
+                }
+                Some("D" | "H") => {}
+                Some(first) if first.starts_with(|c: char| c.is_ascii_digit() || c == '.') => {
+                    let peak = first.parse::<f64>().ok().zip(match fields.next() {
+                        Some(i) => i.parse::<f64>().ok(),
+                        None => Some(0.0),
+                    });
+                    let Some((m, i)) = peak else {
+                        return Err(self.error("bad peak line"));
+                    };
+                    mz.push(m);
+                    intensity.push(i);
+                }
+                _ => return Err(self.error("expected an S, I, Z or D line or a peak")),
+            }
+        }
+
+        let spectrum = self.spectrum(scan, info, charges, mz, intensity)?;
+        self.index += 1;
+        Ok(Some(spectrum))
+    }
+
+    fn spectrum(
+        &self,
+        scan: ScanLine,
+        info: Vec<(String, String)>,
+        charges: Vec<(i32, f64)>,
+        mz: Vec<f64>,
+        intensity: Vec<f64>,
+    ) -> Result<Spectrum, String> {
+        // The S line of MS2 files written without one still has the
+        // precursor in the [M+H]+ mass of the Z lines.
+        let precursor_mz = scan.precursor_mz.or_else(|| {
+            let &(z, mass) = charges.iter().find(|(z, _)| *z != 0)?;
+            Some((mass - PROTON) / f64::from(z) + PROTON)
+        });
+        let ms_level = if precursor_mz.is_some() { 2 } else { 1 };
+        let mut spectrum = peak_list_spectrum(self.index, ms_level, mz, intensity);
+        spectrum.scan_number = Some(scan.scan);
+        spectrum.native_id = Some(format!("scan={}", scan.scan));
+
+        let mut dissociation = None;
+        for (key, value) in info {
+            let number = value.parse::<f64>().ok();
+            let term = lookup(&ACTIVATION_TERMS, &value).filter(|_| precursor_mz.is_some());
+            match (key.as_str(), number) {
+                ("ActivationType", _) if term.is_some() => dissociation = term,
+                ("RetTime" | "RTime", Some(minutes)) => {
+                    let seconds = (minutes * 60.0 * 1e9).round() / 1e9;
+                    set_scan_start_seconds(&mut spectrum, seconds);
+                }
+                ("IonInjectionTime", Some(_)) => {
+                    first_scan_params_mut(&mut spectrum).push(ms_cv_with_unit(
+                        ACC_ION_INJECTION_TIME,
+                        "ion injection time",
+                        value,
+                        ("UO:0000028", "millisecond"),
+                    ));
+                }
+                _ => spectrum.user_params.push(UserParam {
+                    name: key,
+                    value: Some(value),
+                    ..Default::default()
+                }),
+            }
+        }
+
+        if let Some(precursor_mz) = precursor_mz {
+            let ion = selected_ion_params_mut(&mut spectrum);
+            ion.push(ms_cv_with_unit(
+                ACC_SELECTED_ION_MZ,
+                "selected ion m/z",
+                precursor_mz.to_string(),
+                (ACC_MZ, "m/z"),
+            ));
+            let (accession, name) = match charges.len() {
+                1 => (ACC_CHARGE_STATE, "charge state"),
+                _ => (ACC_POSSIBLE_CHARGE_STATE, "possible charge state"),
+            };
+            for (z, _) in charges {
+                ion.push(ms_cv(accession, name, Some(z.to_string())));
+            }
+        }
+        if let Some((accession, name)) = dissociation {
+            let precursor = &mut spectrum.precursor_list.as_mut().unwrap().precursors[0];
+            let activation = precursor.activation.get_or_insert_with(Default::default);
+            activation.cv_params.push(ms_cv(accession, name, None));
+        }
+        Ok(spectrum)
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("MS1/MS2 line {}: {message}", self.line_number)
+    }
+}
+
+/// `S <first scan> <last scan> [<precursor m/z>]`.
+fn scan_line(line: &str) -> Option<ScanLine> {
+    let mut fields = line.split_whitespace().skip(1);
+    let scan = fields.next()?.parse().ok()?;
+    let precursor_mz = match fields.nth(1) {
+        Some(mz) => Some(mz.parse().ok()?),
+        None => None,
+    };
+    Some(ScanLine { scan, precursor_mz })
+}
+
+impl<R: BufRead> Iterator for MsnReader<R> {
+    type Item = Result<Spectrum, String>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_spectrum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        b64::{encode, encoder::encode::WritingMode},
+        msn::write_msn::{MsnFormat, write_b000_as_msn, write_msn},
+        mzml::utilities::peak_list::{precursor_charges, precursor_mz, scan_start_seconds},
+    };
+    use std::io::Cursor;
+
+    const MS2: &str = "\
+H\tExtractor\tocto
+H\tExtractorVersion\t0.0.0
+S\t11\t11\t445.34
+I\tRetTime\t5.8905
+I\tIonInjectionTime\t12.5
+I\tActivationType\tCID
+Z\t2\t889.672724
+100.5 10
+200.25 20.5
+S\t12\t12\t512.3
+Z\t2\t1023.592724
+Z\t3\t1534.885447
+300 1
+";
+
+    #[test]
+    fn reads_scans_into_spectra() {
+        let mzml = parse_msn(MS2.as_bytes(), "run.ms2").unwrap();
+        assert_eq!(mzml.run.id, "run");
+        let spectra = &mzml.run.spectrum_list.as_ref().unwrap().spectra;
+        assert_eq!(spectra.len(), 2);
+
+        let first = &spectra[0];
+        assert_eq!(first.native_id.as_deref(), Some("scan=11"));
+        assert_eq!((first.scan_number, first.ms_level), (Some(11), Some(2)));
+        assert_eq!(precursor_mz(first), Some(445.34));
+        assert_eq!(precursor_charges(first), [2]);
+        assert_eq!(scan_start_seconds(first), Some(353.43));
+        assert!(first.user_params.is_empty());
+        let activation = &first.precursor_list.as_ref().unwrap().precursors[0].activation;
+        let activation = &activation.as_ref().unwrap().cv_params;
+        assert_eq!(activation[0].accession.as_deref(), Some("MS:1000133"));
+        assert_eq!(precursor_charges(&spectra[1]), [2, 3]);
+
+        let mut bytes = Vec::new();
+        encode(&mzml, 12, false, WritingMode::Memory, &mut bytes).unwrap();
+        let written = write_b000_as_msn(Vec::new(), Cursor::new(&bytes), MsnFormat::Ms2).unwrap();
+        let expected = MS2.replace("0.0.0", env!("CARGO_PKG_VERSION"));
+        assert_eq!(String::from_utf8(written).unwrap(), expected);
+    }
+
+    #[test]
+    fn reads_ms1_and_reports_malformed_lines() {
+        let ms1 = "H\tA\tb\nS\t1\t1\nI\tRetTime\t0.5\n100 5\n\nS\t2\t2\n";
+        let mzml = parse_msn(ms1.as_bytes(), "run.ms1").unwrap();
+        let spectra = &mzml.run.spectrum_list.as_ref().unwrap().spectra;
+        assert_eq!((spectra[0].ms_level, spectra.len()), (Some(1), 2));
+        assert_eq!(spectra[1].default_array_length, Some(0));
+        let written = write_msn(Vec::new(), &mzml, MsnFormat::Ms1).unwrap();
+        assert!(
+            String::from_utf8(written)
+                .unwrap()
+                .ends_with("S\t1\t1\nI\tRetTime\t0.5\n100 5\nS\t2\t2\n")
+        );
+
+        let err = parse_msn(b"S\t1\t1\n100 x\n", "a.ms1").unwrap_err();
+        assert_eq!(err, "MS1/MS2 line 2: bad peak line");
+        let err = parse_msn(b"100 1\n", "a.ms1").unwrap_err();
+        assert_eq!(err, "MS1/MS2 line 1: expected an H or S line");
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    borrow::Borrow,
+    io::{Read, Seek, Write},
+};
+
+use crate::{
+    b64::B000Reader,
+    mzml::{
+        structs::*,
+        utilities::peak_list::{
+            ACC_INTENSITY_ARRAY, ACC_MZ_ARRAY, array_f64, ion_injection_ms, ms_level,
+            native_scan_number, precursor_charges, precursor_list, precursor_mz,
+            scan_start_seconds,
+        },
+    },
+    mzxml::parse_mzxml::ACTIVATION_TERMS,
+};
+
+/// Mass of a proton, for the `[M+H]+` masses of Z lines.
+pub(crate) const PROTON: f64 = 1.007_276_466_812;
+
+/// `.ms1` (MS1 spectra) or `.ms2` (MSn spectra with their precursor).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MsnFormat {
+    Ms1,
+    Ms2,
+}
+
+impl MsnFormat {
+    /// Whether `spectrum` belongs in this format. MS2 files take the
+    /// spectra without an ms level too.
+    fn includes(self, spectrum: &Spectrum) -> bool {
+        match self {
+            MsnFormat::Ms1 => ms_level(spectrum) == Some(1),
+            MsnFormat::Ms2 => ms_level(spectrum) != Some(1),
+        }
+    }
+}
+
+/// Writes the spectra of `mzml` that belong in `format` to `out` and
+/// returns `out`.
+pub fn write_msn<W: Write>(out: W, mzml: &MzML, format: MsnFormat) -> Result<W, String> {
+    let spectra = mzml.run.spectrum_list.iter().flat_map(|l| &l.spectra);
+    write_msn_streaming(out, spectra, format)
+}
+
+/// Writes the spectra of the B000 file read from `input` that belong in
+/// `format`, decoding one spectrum at a time.
+pub fn write_b000_as_msn<W: Write, R: Read + Seek>(
+    out: W,
+    input: R,
+    format: MsnFormat,
+) -> Result<W, String> {
+    let mut spectra = B000Reader::new(input)?.spectra();
+    let out = write_msn_streaming(out, spectra.by_ref(), format)?;
+    spectra.finish().map(|()| out)
+}
+
+/// Writes the H lines, then an S line block per spectrum of `spectra` that
+/// belongs in `format`.
+pub fn write_msn_streaming<W, S, I>(mut out: W, spectra: I, format: MsnFormat) -> Result<W, String>
+where
+    W: Write,
+    S: Borrow<Spectrum>,
+    I: IntoIterator<Item = S>,
+{
+    let header = format!(
+        "H\tExtractor\tocto\nH\tExtractorVersion\t{}\n",
+        env!("CARGO_PKG_VERSION")
+    );
+    out.write_all(header.as_bytes())
+        .map_err(|e| e.to_string())?;
+    let mut last = 0;
+    for spectrum in spectra {
+        let spectrum = spectrum.borrow();
+        if !format.includes(spectrum) {
+            continue;
+        }
+        // Scan numbers must increase; ids without one continue from the
+        // previous spectrum. Early mzML has bare scan numbers as native ids.
+        let native_id = spectrum.native_id.as_deref();
+        let scan = spectrum
+            .scan_number
+            .or_else(|| native_scan_number(native_id?).or_else(|| native_id?.parse().ok()))
+            .or_else(|| native_scan_number(&spectrum.id))
+            .filter(|&n| n > last)
+            .unwrap_or(last + 1);
+        last = scan;
+        write_msn_spectrum(&mut out, spectrum, scan, format).map_err(|e| e.to_string())?;
+    }
+    out.flush().map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+/// Key of the dissociation method of the first precursor of `spectrum`.
+fn activation_type(spectrum: &Spectrum) -> Option<&'static str> {
+    let activation = precursor_list(spectrum)?
+        .precursors
+        .first()?
+        .activation
+        .as_ref()?;
+    activation.cv_params.iter().find_map(|p| {
+        let accession = p.accession.as_deref()?;
+        let term = ACTIVATION_TERMS.iter().find(|(_, a, _)| *a == accession);
+        term.map(|(key, ..)| *key)
+    })
+}
+
+/// S line (with the precursor m/z for MS2), RetTime, IonInjectionTime and
+/// (for MS2) ActivationType I lines, one Z line per charge for MS2, then
+/// one `m/z intensity` line
+/// per peak.
+fn write_msn_spectrum<W: Write>(
+    out: &mut W,
+    spectrum: &Spectrum,
+    scan: u32,
+    format: MsnFormat,
+) -> std::io::Result<()> {
+    let precursor = match format {
+        MsnFormat::Ms1 => None,
+        MsnFormat::Ms2 => precursor_mz(spectrum),
+    };
+    match precursor {
+        Some(mz) => writeln!(out, "S\t{scan}\t{scan}\t{mz}")?,
+        None => writeln!(out, "S\t{scan}\t{scan}")?,
+    }
+
+    if let Some(seconds) = scan_start_seconds(spectrum) {
+        writeln!(out, "I\tRetTime\t{}", format_decimal(seconds / 60.0, 6))?;
+    }
+    if let Some(ms) = ion_injection_ms(spectrum) {
+        writeln!(out, "I\tIonInjectionTime\t{}", format_decimal(ms, 6))?;
+    }
+    if precursor.is_some()
+        && let Some(method) = activation_type(spectrum)
+    {
+        writeln!(out, "I\tActivationType\t{method}")?;
+    }
+    if let Some(mz) = precursor {
+        for z in precursor_charges(spectrum) {
+            let mass = (mz - PROTON) * f64::from(z) + PROTON;
+            writeln!(out, "Z\t{z}\t{}", format_decimal(mass, 6))?;
+        }
+    }
+
+    let mz = array_f64(spectrum, ACC_MZ_ARRAY).unwrap_or_default();
+    let intensity = array_f64(spectrum, ACC_INTENSITY_ARRAY).unwrap_or_default();
+    for (i, mz) in mz.iter().enumerate() {
+        let intensity = intensity.get(i).copied().unwrap_or(0.0);
+        writeln!(out, "{mz} {intensity}")?;
+    }
+    Ok(())
+}
+
+/// `value` with at most `decimals` decimals and no trailing zeros.
+fn format_decimal(value: f64, decimals: usize) -> String {
+    let text = format!("{value:.decimals$}");
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    match text {
+        "-0" => "0".to_string(),
+        text => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mzml::parse_mzml::parse_mzml;
+
+    #[test]
+    fn writes_ms1_and_ms2_of_an_mzml_fixture() {
+        let bytes = std::fs::read("data/mzml/tiny.pwiz.mzML0.99.10.mzML").unwrap();
+        let mzml = parse_mzml(&bytes).unwrap();
+
+        let ms2 = String::from_utf8(write_msn(Vec::new(), &mzml, MsnFormat::Ms2).unwrap()).unwrap();
+        let lines: Vec<&str> = ms2.lines().skip(2).take(5).collect();
+        assert_eq!(
+            lines,
+            [
+                "S\t20\t20\t445.34",
+                "I\tRetTime\t5.9905",
+                "I\tActivationType\tCID",
+                "Z\t2\t889.672724",
+                "0.1 0.1"
+            ]
+        );
+
+        let ms1 = String::from_utf8(write_msn(Vec::new(), &mzml, MsnFormat::Ms1).unwrap()).unwrap();
+        assert!(ms1.starts_with("H\tExtractor\tocto\n"));
+        assert!(ms1.contains("\nS\t19\t19\n"));
+        assert!(!ms1.contains("\nZ\t"));
+    }
+
+    #[test]
+    fn formats_decimals_without_trailing_zeros() {
+        assert_eq!(format_decimal(5.890_500_000_1, 6), "5.8905");
+        assert_eq!(format_decimal(2.0, 6), "2");
+        assert_eq!(format_decimal(-0.000_000_1, 6), "0");
+    }
+}
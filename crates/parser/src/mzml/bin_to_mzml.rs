@@ -1,3 +1,10 @@
+use std::{
+    borrow::{Borrow, Cow},
+    fmt::Display,
+    io::{self, Read, Seek, Write},
+    iter::Peekable,
+};
+
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD;
 
@@ -5,7 +12,71 @@ use miniz_oxide::deflate::compress_to_vec_zlib;
 use quick_xml::Writer;
 use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
 
-use crate::mzml::structs::*;
+use crate::{
+    b64::decoder::reader::B000Reader,
+    mzml::{
+        mzml_reader::MzMLItem,
+        structs::*,
+        utilities::{child_location, count_seen, numpress::Numpress, path_name},
+    },
+};
+
+/// How `<binary>` arrays are compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArrayCompression {
+    /// As named by each array's compression cvParam.
+    #[default]
+    Keep,
+    None,
+    Zlib,
+    /// MS-Numpress linear prediction for m/z and time arrays and short
+    /// logged float for intensity arrays; other arrays use zlib.
+    Numpress,
+}
+
+/// Precision of floating-point arrays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArrayPrecision {
+    #[default]
+    Keep,
+    F32,
+    F64,
+}
+
+/// Options for `write_mzml_with_options` and `bin_to_mzml_with_options`.
+#[derive(Debug, Clone, Copy)]
+pub struct MzmlWriteOptions {
+    /// Compression of the binary arrays. The compression cvParam of each
+    /// array is rewritten to match.
+    pub compression: ArrayCompression,
+    /// zlib level, 0 (store) to 10, for zlib-compressed arrays.
+    pub zlib_level: u8,
+    /// Converts 32- and 64-bit float arrays, rewriting their binary data type
+    /// cvParam; integer and 16-bit float arrays are kept.
+    pub precision: ArrayPrecision,
+    /// Wraps the document in `<indexedmzML>` with an offset index and a
+    /// SHA-1 checksum. Plain mzML has `<mzML>` as the root.
+    pub indexed: bool,
+    /// Indents nested elements by two spaces, one per line. When unset, no
+    /// whitespace is written between tags.
+    pub pretty: bool,
+    /// Adds a missing compression or binary data type cvParam to arrays
+    /// instead of failing.
+    pub add_missing_cv_params: bool,
+}
+
+impl Default for MzmlWriteOptions {
+    fn default() -> Self {
+        Self {
+            compression: ArrayCompression::Keep,
+            zlib_level: 6,
+            precision: ArrayPrecision::Keep,
+            indexed: true,
+            pretty: true,
+            add_missing_cv_params: false,
+        }
+    }
+}
 
 #[derive(Default)]
 struct IndexAcc {
@@ -18,6 +89,258 @@ struct IndexOffsetAcc {
     offset: u64,
 }
 
+/// Output wrapper that counts the bytes written, for the index offsets, and
+/// hashes them, for `<fileChecksum>`, so nothing written is kept around.
+pub(crate) struct Sink<W> {
+    out: W,
+    position: u64,
+    sha1: sha1_smol::Sha1,
+    /// Set by `write_start_capture_offset`: the next `'<'` written is recorded
+    /// in `mark`.
+    armed: bool,
+    mark: Option<u64>,
+}
+
+impl<W> Sink<W> {
+    pub(crate) fn new(out: W) -> Self {
+        Self {
+            out,
+            position: 0,
+            sha1: sha1_smol::Sha1::new(),
+            armed: false,
+            mark: None,
+        }
+    }
+
+    /// Number of bytes written so far.
+    pub(crate) fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// SHA-1 of the bytes written so far.
+    pub(crate) fn digest(&self) -> String {
+        self.sha1.digest().to_string()
+    }
+
+    pub(crate) fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> Write for Sink<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.out.write(buf)?;
+        let written = &buf[..n];
+        if self.armed
+            && let Some(rel) = written.iter().position(|&b| b == b'<')
+        {
+            self.mark = Some(self.position + rel as u64);
+            self.armed = false;
+        }
+        self.sha1.update(written);
+        self.position += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// `quick_xml::Writer` over a `Sink` that also puts the preserved elements
+/// of an owner (see `preserve`) back where they were found: each is written
+/// once `position` children of its `parent` have been, or else before the
+/// parent's end tag. Elements whose parent is not written at all go before
+/// the owner's end tag.
+pub(crate) struct XmlWriter<W> {
+    inner: Writer<Sink<W>>,
+    /// Elements opened since the outermost owner, innermost last; empty
+    /// when no owner is open.
+    open: Vec<Open>,
+    /// Elements still to write for each open owner, innermost last.
+    owners: Vec<Vec<ExtensionElement>>,
+    /// Set by `preserve`, for the next start tag.
+    armed: Option<Vec<ExtensionElement>>,
+}
+
+enum Open {
+    /// Nothing preserved belongs inside.
+    Untracked,
+    Tracked {
+        /// Relative to the owner, as in `ExtensionElement::parent`.
+        location: String,
+        /// Child elements and comments written so far.
+        children: usize,
+        seen: Vec<(String, usize)>,
+        owner: bool,
+    },
+}
+
+impl<W: Write> XmlWriter<W> {
+    pub(crate) fn new(inner: Writer<Sink<W>>) -> Self {
+        Self {
+            inner,
+            open: Vec::new(),
+            owners: Vec::new(),
+            armed: None,
+        }
+    }
+
+    pub(crate) fn get_ref(&self) -> &Sink<W> {
+        self.inner.get_ref()
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut Sink<W> {
+        self.inner.get_mut()
+    }
+
+    pub(crate) fn into_inner(self) -> Sink<W> {
+        self.inner.into_inner()
+    }
+
+    /// Makes the element whose start tag is written next the owner of
+    /// `extensions`.
+    pub(crate) fn preserve(&mut self, extensions: Option<&Extensions>) {
+        if let Some(extensions) = extensions.filter(|e| !e.elements.is_empty()) {
+            self.armed = Some(extensions.elements.clone());
+        }
+    }
+
+    pub(crate) fn write_event<'a, E: Into<Event<'a>>>(&mut self, event: E) -> io::Result<()> {
+        let event = event.into();
+        if self.open.is_empty() && self.armed.is_none() {
+            return self.inner.write_event(event);
+        }
+        match &event {
+            Event::Start(e) => {
+                self.child(Some(e.name().as_ref()))?;
+                self.enter(e.name().as_ref());
+            }
+            Event::Empty(e) => self.child(Some(e.name().as_ref()))?,
+            Event::Comment(_) => self.child(None)?,
+            Event::End(_) => self.leave()?,
+            _ => {}
+        }
+        self.inner.write_event(event)
+    }
+
+    /// Writes what is due before a child of the current element, then
+    /// counts the child.
+    fn child(&mut self, raw_name: Option<&[u8]>) -> io::Result<()> {
+        let Some(Open::Tracked { location, .. }) = self.open.last() else {
+            return Ok(());
+        };
+        let location = location.clone();
+        while let Some(element) = self.take_due(&location, false) {
+            self.write_preserved(&element.xml)?;
+        }
+        self.count(raw_name.map(path_name));
+        Ok(())
+    }
+
+    fn enter(&mut self, raw_name: &[u8]) {
+        let open = if let Some(elements) = self.armed.take() {
+            self.owners.push(elements);
+            Open::Tracked {
+                location: String::new(),
+                children: 0,
+                seen: Vec::new(),
+                owner: true,
+            }
+        } else if let Some(Open::Tracked { location, seen, .. }) = self.open.last() {
+            let location = child_location(location, seen, &path_name(raw_name));
+            let inside = format!("{location}/");
+            let pending = self.owners.last().is_some_and(|elements| {
+                elements
+                    .iter()
+                    .any(|e| e.parent == location || e.parent.starts_with(&inside))
+            });
+            if pending {
+                Open::Tracked {
+                    location,
+                    children: 0,
+                    seen: Vec::new(),
+                    owner: false,
+                }
+            } else {
+                Open::Untracked
+            }
+        } else {
+            Open::Untracked
+        };
+        self.open.push(open);
+    }
+
+    fn leave(&mut self) -> io::Result<()> {
+        if let Some(Open::Tracked {
+            location, owner, ..
+        }) = self.open.last()
+        {
+            let (location, owner) = (location.clone(), *owner);
+            while let Some(element) = self.take_due(&location, true) {
+                self.write_preserved(&element.xml)?;
+            }
+            if owner {
+                self.owners.pop();
+            }
+        }
+        self.open.pop();
+        Ok(())
+    }
+
+    /// The next element of the innermost owner to write inside the current
+    /// element, at `location`: one due at the current child count or, when
+    /// `closing`, any left for `location` (for the owner, any left at all).
+    fn take_due(&mut self, location: &str, closing: bool) -> Option<ExtensionElement> {
+        let Some(Open::Tracked {
+            children, owner, ..
+        }) = self.open.last()
+        else {
+            return None;
+        };
+        let (children, owner) = (*children, *owner);
+        let elements = self.owners.last_mut()?;
+        let i = elements.iter().position(|e| {
+            closing && owner || e.parent == location && (closing || e.position <= children)
+        })?;
+        Some(elements.remove(i))
+    }
+
+    fn count(&mut self, name: Option<Cow<'_, str>>) {
+        if let Some(Open::Tracked { children, seen, .. }) = self.open.last_mut() {
+            *children += 1;
+            if let Some(name) = name {
+                count_seen(seen, name);
+            }
+        }
+    }
+
+    /// Copies preserved XML verbatim on its own line, counted as a child of
+    /// the current element.
+    fn write_preserved(&mut self, xml: &str) -> io::Result<()> {
+        let sink = self.inner.get_mut();
+        let armed = std::mem::take(&mut sink.armed);
+        self.inner.write_indent()?;
+        self.inner.get_mut().write_all(xml.as_bytes())?;
+        self.inner.get_mut().armed = armed;
+        let name = xml
+            .strip_prefix('<')
+            .filter(|rest| !rest.starts_with("!--"))
+            .and_then(|rest| {
+                rest.split(|c: char| c.is_whitespace() || c == '/' || c == '>')
+                    .next()
+            });
+        self.count(name.map(|name| path_name(name.as_bytes())));
+        Ok(())
+    }
+}
+
+/// A spectrum or chromatogram to write, owned or borrowed.
+enum Entry<S, C> {
+    Spectrum(S),
+    Chromatogram(C),
+}
+
 #[inline]
 fn nonempty<'a>(s: Option<&'a str>) -> Option<&'a str> {
     match s {
@@ -27,47 +350,140 @@ fn nonempty<'a>(s: Option<&'a str>) -> Option<&'a str> {
 }
 
 #[inline]
-fn write_start_capture_offset(
-    writer: &mut Writer<Vec<u8>>,
+pub(crate) fn write_start_capture_offset<W: Write>(
+    writer: &mut XmlWriter<W>,
     tag: BytesStart<'_>,
 ) -> Result<u64, String> {
-    let before = writer.get_ref().len();
+    let sink = writer.get_mut();
+    sink.armed = true;
+    sink.mark = None;
     writer
         .write_event(Event::Start(tag))
         .map_err(|e| e.to_string())?;
-    let after = writer.get_ref().len();
-
-    let buf = writer.get_ref();
-    let rel = buf[before..after]
-        .iter()
-        .position(|&b| b == b'<')
-        .ok_or_else(|| "could not find '<' for start tag".to_string())?;
 
-    Ok((before + rel) as u64)
+    let sink = writer.get_mut();
+    sink.armed = false;
+    sink.mark
+        .take()
+        .ok_or_else(|| "could not find '<' for start tag".to_string())
 }
 
 pub fn bin_to_mzml(mzml: &MzML) -> Result<String, String> {
-    let bytes = convert_bin_to_mzml_bytes(mzml)?;
+    bin_to_mzml_with_options(mzml, &MzmlWriteOptions::default())
+}
+
+pub fn bin_to_mzml_with_options(mzml: &MzML, options: &MzmlWriteOptions) -> Result<String, String> {
+    let bytes = write_mzml_with_options(Vec::new(), mzml, options)?;
     String::from_utf8(bytes).map_err(|e| e.to_string())
 }
 
 pub fn convert_bin_to_mzml_bytes(mzml: &MzML) -> Result<Vec<u8>, String> {
-    let mut writer = Writer::new_with_indent(Vec::new(), b' ', 2);
+    write_mzml(Vec::new(), mzml)
+}
+
+/// Writes `mzml` as indexed mzML to `out` and returns `out`.
+///
+/// Nothing is buffered besides the index offsets, so wrap files in a
+/// `BufWriter`.
+pub fn write_mzml<W: Write>(out: W, mzml: &MzML) -> Result<W, String> {
+    write_mzml_with_options(out, mzml, &MzmlWriteOptions::default())
+}
+
+pub fn write_mzml_with_options<W: Write>(
+    out: W,
+    mzml: &MzML,
+    options: &MzmlWriteOptions,
+) -> Result<W, String> {
+    let run = &mzml.run;
+    let spectra = run
+        .spectrum_list
+        .iter()
+        .flat_map(|l| &l.spectra)
+        .map(Entry::Spectrum);
+    let chromatograms = run
+        .chromatogram_list
+        .iter()
+        .flat_map(|l| &l.chromatograms)
+        .map(Entry::Chromatogram);
+    write_document(out, mzml, spectra.chain(chromatograms).map(Ok), options)
+}
+
+/// Writes `header` as indexed mzML to `out`, taking the spectra and
+/// chromatograms from `items` instead of from the header's lists, and
+/// returns `out`.
+///
+/// Only one entry is held at a time, so memory use does not grow with the
+/// run. `items` is typically an `MzMLReader` or a `B000Reader` (see
+/// `write_b000_as_mzml`), and spectra must come before chromatograms. The
+/// header's `spectrumList` and `chromatogramList` are written with their
+/// `count` as is, so they must be present and final before the first entry;
+/// an `MzMLReader` header only has the lists it has reached so far.
+pub fn write_mzml_streaming<W, I, E>(
+    out: W,
+    header: &MzML,
+    items: I,
+    options: &MzmlWriteOptions,
+) -> Result<W, String>
+where
+    W: Write,
+    I: IntoIterator<Item = Result<MzMLItem, E>>,
+    E: Display,
+{
+    let entries = items.into_iter().map(|item| match item {
+        Ok(MzMLItem::Spectrum(s)) => Ok(Entry::Spectrum(s)),
+        Ok(MzMLItem::Chromatogram(c)) => Ok(Entry::Chromatogram(c)),
+        Err(e) => Err(e.to_string()),
+    });
+    write_document(out, header, entries, options)
+}
+
+/// Writes the B000 file read from `input` as indexed mzML to `out`, one
+/// spectrum or chromatogram at a time, and returns `out`.
+pub fn write_b000_as_mzml<W: Write, R: Read + Seek>(
+    out: W,
+    input: R,
+    options: &MzmlWriteOptions,
+) -> Result<W, String> {
+    let reader = B000Reader::new(input)?;
+    let header = reader.header().clone();
+    write_mzml_streaming(out, &header, reader, options)
+}
+
+fn write_document<W, S, C, I>(
+    out: W,
+    mzml: &MzML,
+    entries: I,
+    options: &MzmlWriteOptions,
+) -> Result<W, String>
+where
+    W: Write,
+    S: Borrow<Spectrum>,
+    C: Borrow<Chromatogram>,
+    I: Iterator<Item = Result<Entry<S, C>, String>>,
+{
+    let sink = Sink::new(out);
+    let mut writer = XmlWriter::new(if options.pretty {
+        Writer::new_with_indent(sink, b' ', 2)
+    } else {
+        Writer::new(sink)
+    });
 
     writer
         .write_event(Event::Decl(BytesDecl::new("1.0", Some("utf-8"), None)))
         .map_err(|e| e.to_string())?;
 
-    let mut idx_tag = BytesStart::new("indexedmzML");
-    idx_tag.push_attribute(("xmlns", "http://psi.hupo.org/ms/mzml"));
-    idx_tag.push_attribute(("xmlns:xsi", "http://www.w3.org/2001/XMLSchema-instance"));
-    idx_tag.push_attribute((
-        "xsi:schemaLocation",
-        "http://psi.hupo.org/ms/mzml http://psidev.info/files/ms/mzML/xsd/mzML1.1.2_idx.xsd",
-    ));
-    writer
-        .write_event(Event::Start(idx_tag))
-        .map_err(|e| e.to_string())?;
+    if options.indexed {
+        let mut idx_tag = BytesStart::new("indexedmzML");
+        idx_tag.push_attribute(("xmlns", "http://psi.hupo.org/ms/mzml"));
+        idx_tag.push_attribute(("xmlns:xsi", "http://www.w3.org/2001/XMLSchema-instance"));
+        idx_tag.push_attribute((
+            "xsi:schemaLocation",
+            "http://psi.hupo.org/ms/mzml http://psidev.info/files/ms/mzML/xsd/mzML1.1.2_idx.xsd",
+        ));
+        writer
+            .write_event(Event::Start(idx_tag))
+            .map_err(|e| e.to_string())?;
+    }
 
     let mut mzml_tag = BytesStart::new("mzML");
     mzml_tag.push_attribute(("xmlns", "http://psi.hupo.org/ms/mzml"));
@@ -78,6 +494,8 @@ pub fn convert_bin_to_mzml_bytes(mzml: &MzML) -> Result<Vec<u8>, String> {
     ));
     mzml_tag.push_attribute(("id", mzml.run.id.as_str()));
     mzml_tag.push_attribute(("version", "1.1.0"));
+    push_extension_attributes(&mut mzml_tag, mzml.extensions.as_ref());
+    writer.preserve(mzml.extensions.as_ref());
 
     writer
         .write_event(Event::Start(mzml_tag))
@@ -92,7 +510,9 @@ pub fn convert_bin_to_mzml_bytes(mzml: &MzML) -> Result<Vec<u8>, String> {
 
     write_cv_list(&mut writer, cvl)?;
 
-    write_file_description(&mut writer, &mzml.file_description.as_ref().unwrap())?;
+    let fd = mzml.file_description.as_ref();
+    let fd = fd.ok_or("mzML requires a fileDescription, and the document has none")?;
+    write_file_description(&mut writer, fd)?;
 
     if let Some(rpgl) = &mzml.referenceable_param_group_list {
         write_referenceable_param_group_list(&mut writer, rpgl)?;
@@ -120,23 +540,36 @@ pub fn convert_bin_to_mzml_bytes(mzml: &MzML) -> Result<Vec<u8>, String> {
         .map(|dp| dp.id.as_str());
 
     let mut idx = IndexAcc::default();
-    write_run(&mut writer, &mzml.run, fallback_default_dp, &mut idx)?;
+    write_run(
+        &mut writer,
+        &mzml.run,
+        fallback_default_dp,
+        mzml.referenceable_param_group_list.as_ref(),
+        options,
+        &mut idx,
+        &mut entries.peekable(),
+    )?;
 
     writer
         .write_event(Event::End(BytesEnd::new("mzML")))
         .map_err(|e| e.to_string())?;
 
-    let index_list_offset = write_index_list_with_offset(&mut writer, &idx)?;
-    write_index_list_offset(&mut writer, index_list_offset)?;
+    if options.indexed {
+        let index_list_offset = write_index_list_with_offset(&mut writer, &idx)?;
+        write_index_list_offset(&mut writer, index_list_offset)?;
+        write_file_checksum(&mut writer)?;
 
-    writer
-        .write_event(Event::End(BytesEnd::new("indexedmzML")))
-        .map_err(|e| e.to_string())?;
+        writer
+            .write_event(Event::End(BytesEnd::new("indexedmzML")))
+            .map_err(|e| e.to_string())?;
+    }
 
-    Ok(writer.into_inner())
+    let mut sink = writer.into_inner();
+    sink.flush().map_err(|e| e.to_string())?;
+    Ok(sink.into_inner())
 }
 
-fn default_cv_list() -> CvList {
+pub(crate) fn default_cv_list() -> CvList {
     CvList {
         count: Some(2),
         cv: vec![
@@ -164,7 +597,7 @@ fn default_cv_list() -> CvList {
     }
 }
 
-pub fn write_cv_list(writer: &mut Writer<Vec<u8>>, cvl: &CvList) -> Result<(), String> {
+fn write_cv_list<W: Write>(writer: &mut XmlWriter<W>, cvl: &CvList) -> Result<(), String> {
     let count = cvl.count.unwrap_or(cvl.cv.len());
     let mut tag = BytesStart::new("cvList");
     let count_s = count.to_string();
@@ -198,8 +631,8 @@ pub fn write_cv_list(writer: &mut Writer<Vec<u8>>, cvl: &CvList) -> Result<(), S
     Ok(())
 }
 
-fn write_file_description(
-    writer: &mut Writer<Vec<u8>>,
+fn write_file_description<W: Write>(
+    writer: &mut XmlWriter<W>,
     fd: &FileDescription,
 ) -> Result<(), String> {
     writer
@@ -238,8 +671,8 @@ fn write_file_description(
     Ok(())
 }
 
-fn write_source_file_list(
-    writer: &mut Writer<Vec<u8>>,
+fn write_source_file_list<W: Write>(
+    writer: &mut XmlWriter<W>,
     sfl: &SourceFileList,
 ) -> Result<(), String> {
     let count = sfl.count.unwrap_or(sfl.source_file.len());
@@ -280,8 +713,8 @@ fn write_source_file_list(
     Ok(())
 }
 
-fn write_referenceable_param_group_list(
-    writer: &mut Writer<Vec<u8>>,
+fn write_referenceable_param_group_list<W: Write>(
+    writer: &mut XmlWriter<W>,
     list: &ReferenceableParamGroupList,
 ) -> Result<(), String> {
     let count = list.count.unwrap_or(list.referenceable_param_groups.len());
@@ -314,7 +747,7 @@ fn write_referenceable_param_group_list(
     Ok(())
 }
 
-fn write_sample_list(writer: &mut Writer<Vec<u8>>, list: &SampleList) -> Result<(), String> {
+fn write_sample_list<W: Write>(writer: &mut XmlWriter<W>, list: &SampleList) -> Result<(), String> {
     let count = list.count.unwrap_or(list.samples.len() as u32) as usize;
     let mut tag = BytesStart::new("sampleList");
     let count_s = count.to_string();
@@ -352,8 +785,8 @@ fn write_sample_list(writer: &mut Writer<Vec<u8>>, list: &SampleList) -> Result<
     Ok(())
 }
 
-fn write_instrument_list(
-    writer: &mut Writer<Vec<u8>>,
+fn write_instrument_list<W: Write>(
+    writer: &mut XmlWriter<W>,
     list: &InstrumentList,
 ) -> Result<(), String> {
     let count = list.count.unwrap_or(list.instrument.len());
@@ -441,7 +874,10 @@ fn write_instrument_list(
     Ok(())
 }
 
-fn write_component_list(writer: &mut Writer<Vec<u8>>, cl: &ComponentList) -> Result<(), String> {
+fn write_component_list<W: Write>(
+    writer: &mut XmlWriter<W>,
+    cl: &ComponentList,
+) -> Result<(), String> {
     let count = cl
         .count
         .unwrap_or(cl.source.len() + cl.analyzer.len() + cl.detector.len());
@@ -490,8 +926,8 @@ fn write_component_list(writer: &mut Writer<Vec<u8>>, cl: &ComponentList) -> Res
     Ok(())
 }
 
-fn write_component_list_fallback_from_instrument_cv(
-    writer: &mut Writer<Vec<u8>>,
+fn write_component_list_fallback_from_instrument_cv<W: Write>(
+    writer: &mut XmlWriter<W>,
     params: &[CvParam],
 ) -> Result<(), String> {
     let mut tag = BytesStart::new("componentList");
@@ -539,8 +975,8 @@ fn write_component_list_fallback_from_instrument_cv(
     Ok(())
 }
 
-fn write_component(
-    writer: &mut Writer<Vec<u8>>,
+fn write_component<W: Write>(
+    writer: &mut XmlWriter<W>,
     name: &str,
     order: Option<u32>,
     refs: &[ReferenceableParamGroupRef],
@@ -567,7 +1003,10 @@ fn write_component(
     Ok(())
 }
 
-fn write_software_list(writer: &mut Writer<Vec<u8>>, list: &SoftwareList) -> Result<(), String> {
+fn write_software_list<W: Write>(
+    writer: &mut XmlWriter<W>,
+    list: &SoftwareList,
+) -> Result<(), String> {
     let count = list.count.unwrap_or(list.software.len());
     let mut tag = BytesStart::new("softwareList");
     let count_s = count.to_string();
@@ -617,8 +1056,8 @@ fn write_software_list(writer: &mut Writer<Vec<u8>>, list: &SoftwareList) -> Res
     Ok(())
 }
 
-fn write_data_processing_list(
-    writer: &mut Writer<Vec<u8>>,
+fn write_data_processing_list<W: Write>(
+    writer: &mut XmlWriter<W>,
     list: &DataProcessingList,
 ) -> Result<(), String> {
     let count = list.count.unwrap_or(list.data_processing.len());
@@ -675,8 +1114,8 @@ fn write_data_processing_list(
     Ok(())
 }
 
-fn write_scan_settings_list(
-    writer: &mut Writer<Vec<u8>>,
+fn write_scan_settings_list<W: Write>(
+    writer: &mut XmlWriter<W>,
     list: &ScanSettingsList,
 ) -> Result<(), String> {
     let count = list.count.unwrap_or(list.scan_settings.len());
@@ -727,12 +1166,21 @@ fn write_scan_settings_list(
     Ok(())
 }
 
-fn write_run(
-    writer: &mut Writer<Vec<u8>>,
+fn write_run<W, S, C, I>(
+    writer: &mut XmlWriter<W>,
     run: &Run,
     fallback_default_dp: Option<&str>,
+    groups: Option<&ReferenceableParamGroupList>,
+    options: &MzmlWriteOptions,
     idx: &mut IndexAcc,
-) -> Result<(), String> {
+    entries: &mut Peekable<I>,
+) -> Result<(), String>
+where
+    W: Write,
+    S: Borrow<Spectrum>,
+    C: Borrow<Chromatogram>,
+    I: Iterator<Item = Result<Entry<S, C>, String>>,
+{
     let mut run_tag = BytesStart::new("run");
     run_tag.push_attribute(("id", run.id.as_str()));
     if let Some(ts) = nonempty(run.start_time_stamp.as_deref()) {
@@ -747,6 +1195,8 @@ fn write_run(
     if let Some(samp) = nonempty(run.sample_ref.as_deref()) {
         run_tag.push_attribute(("sampleRef", samp));
     }
+    push_extension_attributes(&mut run_tag, run.extensions.as_ref());
+    writer.preserve(run.extensions.as_ref());
 
     writer
         .write_event(Event::Start(run_tag))
@@ -760,10 +1210,31 @@ fn write_run(
         write_source_file_ref_list(writer, sfrl)?;
     }
     if let Some(sl) = &run.spectrum_list {
-        write_spectrum_list(writer, sl, fallback_default_dp, idx)?;
+        write_spectrum_list(
+            writer,
+            sl,
+            fallback_default_dp,
+            groups,
+            options,
+            idx,
+            entries,
+        )?;
     }
     if let Some(cl) = &run.chromatogram_list {
-        write_chromatogram_list(writer, cl, fallback_default_dp, idx)?;
+        write_chromatogram_list(writer, cl, fallback_default_dp, options, idx, entries)?;
+    }
+    match entries.next() {
+        None => {}
+        Some(Err(e)) => return Err(e),
+        Some(Ok(Entry::Spectrum(_))) if run.spectrum_list.is_none() => {
+            return Err("spectra given but the run has no spectrumList".to_string());
+        }
+        Some(Ok(Entry::Spectrum(_))) => {
+            return Err("spectra must come before chromatograms".to_string());
+        }
+        Some(Ok(Entry::Chromatogram(_))) => {
+            return Err("chromatograms given but the run has no chromatogramList".to_string());
+        }
     }
 
     writer
@@ -773,7 +1244,7 @@ fn write_run(
 }
 
 #[inline]
-fn binary_len(b: &BinaryData) -> usize {
+pub(crate) fn binary_len(b: &BinaryData) -> usize {
     match b {
         BinaryData::F64(v) => v.len(),
         BinaryData::F32(v) => v.len(),
@@ -793,12 +1264,20 @@ fn default_len_from_bdal(bdal: &BinaryDataArrayList) -> Option<usize> {
     b.binary.as_ref().map(binary_len)
 }
 
-fn write_spectrum_list(
-    writer: &mut Writer<Vec<u8>>,
+fn write_spectrum_list<W, S, C, I>(
+    writer: &mut XmlWriter<W>,
     list: &SpectrumList,
     fallback_default_dp: Option<&str>,
+    groups: Option<&ReferenceableParamGroupList>,
+    options: &MzmlWriteOptions,
     idx: &mut IndexAcc,
-) -> Result<(), String> {
+    entries: &mut Peekable<I>,
+) -> Result<(), String>
+where
+    W: Write,
+    S: Borrow<Spectrum>,
+    I: Iterator<Item = Result<Entry<S, C>, String>>,
+{
     let count = list.count.unwrap_or(list.spectra.len());
     let mut tag = BytesStart::new("spectrumList");
     let count_s = count.to_string();
@@ -813,8 +1292,18 @@ fn write_spectrum_list(
         .write_event(Event::Start(tag))
         .map_err(|e| e.to_string())?;
 
-    for s in &list.spectra {
-        write_spectrum(writer, s, fallback_default_dp, idx)?;
+    while let Some(entry) = entries.next_if(|e| !matches!(e, Ok(Entry::Chromatogram(_)))) {
+        let Entry::Spectrum(s) = entry? else {
+            unreachable!()
+        };
+        write_spectrum(
+            writer,
+            s.borrow(),
+            fallback_default_dp,
+            groups,
+            options,
+            idx,
+        )?;
     }
 
     writer
@@ -823,10 +1312,12 @@ fn write_spectrum_list(
     Ok(())
 }
 
-fn write_spectrum(
-    writer: &mut Writer<Vec<u8>>,
+fn write_spectrum<W: Write>(
+    writer: &mut XmlWriter<W>,
     s: &Spectrum,
     fallback_default_dp: Option<&str>,
+    groups: Option<&ReferenceableParamGroupList>,
+    options: &MzmlWriteOptions,
     idx: &mut IndexAcc,
 ) -> Result<(), String> {
     let default_len = s
@@ -872,6 +1363,8 @@ fn write_spectrum(
     if let Some(v) = nonempty(s.spot_id.as_deref()) {
         tag.push_attribute(("spotID", v));
     }
+    push_extension_attributes(&mut tag, s.extensions.as_ref());
+    writer.preserve(s.extensions.as_ref());
 
     let off = write_start_capture_offset(writer, tag)?;
     idx.spectrum.push(IndexOffsetAcc {
@@ -894,12 +1387,12 @@ fn write_spectrum(
         };
 
     if let Some(sd) = &s.spectrum_description {
-        write_spectrum_description(writer, sd)?;
+        write_spectrum_description(writer, sd, groups)?;
     }
 
     if !sd_has_scan_list {
         if let Some(sl) = &s.scan_list {
-            write_scan_list(writer, sl)?;
+            write_scan_list(writer, sl, groups)?;
         }
     }
 
@@ -916,7 +1409,7 @@ fn write_spectrum(
     }
 
     if let Some(bdal) = &s.binary_data_array_list {
-        write_binary_data_array_list(writer, bdal, fallback_default_dp)?;
+        write_binary_data_array_list(writer, bdal, fallback_default_dp, options)?;
     }
 
     writer
@@ -925,9 +1418,10 @@ fn write_spectrum(
     Ok(())
 }
 
-fn write_spectrum_description(
-    writer: &mut Writer<Vec<u8>>,
+fn write_spectrum_description<W: Write>(
+    writer: &mut XmlWriter<W>,
     sd: &SpectrumDescription,
+    groups: Option<&ReferenceableParamGroupList>,
 ) -> Result<(), String> {
     writer
         .write_event(Event::Start(BytesStart::new("spectrumDescription")))
@@ -938,7 +1432,7 @@ fn write_spectrum_description(
     write_user_params(writer, &sd.user_params)?;
 
     if let Some(sl) = &sd.scan_list {
-        write_scan_list(writer, sl)?;
+        write_scan_list(writer, sl, groups)?;
     }
     if let Some(pl) = &sd.precursor_list {
         write_precursor_list(writer, pl)?;
@@ -953,7 +1447,11 @@ fn write_spectrum_description(
     Ok(())
 }
 
-fn write_scan_list(writer: &mut Writer<Vec<u8>>, list: &ScanList) -> Result<(), String> {
+fn write_scan_list<W: Write>(
+    writer: &mut XmlWriter<W>,
+    list: &ScanList,
+    groups: Option<&ReferenceableParamGroupList>,
+) -> Result<(), String> {
     let count = list.count.unwrap_or(list.scans.len());
     let mut tag = BytesStart::new("scanList");
     let count_s = count.to_string();
@@ -1010,11 +1508,16 @@ fn write_scan_list(writer: &mut Writer<Vec<u8>>, list: &ScanList) -> Result<(),
                 .unwrap_or("");
 
             let is_ms2 = filter_val.to_ascii_lowercase().contains("ms2");
-            Some(if is_ms2 {
+            let id = if is_ms2 {
                 "CommonMS2SpectrumParams"
             } else {
                 "CommonMS1SpectrumParams"
-            })
+            };
+            // Only point at a group the document defines; otherwise the
+            // params stay inline.
+            groups
+                .is_some_and(|g| g.referenceable_param_groups.iter().any(|g| g.id == id))
+                .then_some(id)
         } else {
             None
         };
@@ -1056,8 +1559,8 @@ fn write_scan_list(writer: &mut Writer<Vec<u8>>, list: &ScanList) -> Result<(),
     Ok(())
 }
 
-fn write_scan_window_list(
-    writer: &mut Writer<Vec<u8>>,
+fn write_scan_window_list<W: Write>(
+    writer: &mut XmlWriter<W>,
     list: &ScanWindowList,
 ) -> Result<(), String> {
     let count = list.count.unwrap_or(list.scan_windows.len());
@@ -1086,7 +1589,10 @@ fn write_scan_window_list(
     Ok(())
 }
 
-fn write_precursor_list(writer: &mut Writer<Vec<u8>>, list: &PrecursorList) -> Result<(), String> {
+fn write_precursor_list<W: Write>(
+    writer: &mut XmlWriter<W>,
+    list: &PrecursorList,
+) -> Result<(), String> {
     let count = list.count.unwrap_or(list.precursors.len());
     let mut tag = BytesStart::new("precursorList");
     let count_s = count.to_string();
@@ -1109,7 +1615,7 @@ fn write_precursor_list(writer: &mut Writer<Vec<u8>>, list: &PrecursorList) -> R
     Ok(())
 }
 
-fn write_precursor(writer: &mut Writer<Vec<u8>>, p: &Precursor) -> Result<(), String> {
+fn write_precursor<W: Write>(writer: &mut XmlWriter<W>, p: &Precursor) -> Result<(), String> {
     let mut pt = BytesStart::new("precursor");
     if let Some(v) = nonempty(p.spectrum_ref.as_deref()) {
         pt.push_attribute(("spectrumRef", v));
@@ -1153,8 +1659,8 @@ fn write_precursor(writer: &mut Writer<Vec<u8>>, p: &Precursor) -> Result<(), St
     Ok(())
 }
 
-fn write_selected_ion_list(
-    writer: &mut Writer<Vec<u8>>,
+fn write_selected_ion_list<W: Write>(
+    writer: &mut XmlWriter<W>,
     list: &SelectedIonList,
 ) -> Result<(), String> {
     let count = list.count.unwrap_or(list.selected_ions.len());
@@ -1185,7 +1691,10 @@ fn write_selected_ion_list(
         .map_err(|e| e.to_string())?;
     Ok(())
 }
-fn write_product_list(writer: &mut Writer<Vec<u8>>, list: &ProductList) -> Result<(), String> {
+fn write_product_list<W: Write>(
+    writer: &mut XmlWriter<W>,
+    list: &ProductList,
+) -> Result<(), String> {
     let count = list.count.unwrap_or(list.products.len());
     let mut tag = BytesStart::new("productList");
     let count_s = count.to_string();
@@ -1205,7 +1714,7 @@ fn write_product_list(writer: &mut Writer<Vec<u8>>, list: &ProductList) -> Resul
     Ok(())
 }
 
-fn write_product(writer: &mut Writer<Vec<u8>>, p: &Product) -> Result<(), String> {
+fn write_product<W: Write>(writer: &mut XmlWriter<W>, p: &Product) -> Result<(), String> {
     let mut pt = BytesStart::new("product");
     if let Some(v) = nonempty(p.spectrum_ref.as_deref()) {
         pt.push_attribute(("spectrumRef", v));
@@ -1240,12 +1749,19 @@ fn write_product(writer: &mut Writer<Vec<u8>>, p: &Product) -> Result<(), String
     Ok(())
 }
 
-fn write_chromatogram_list(
-    writer: &mut Writer<Vec<u8>>,
+fn write_chromatogram_list<W, S, C, I>(
+    writer: &mut XmlWriter<W>,
     list: &ChromatogramList,
     fallback_default_dp: Option<&str>,
+    options: &MzmlWriteOptions,
     idx: &mut IndexAcc,
-) -> Result<(), String> {
+    entries: &mut Peekable<I>,
+) -> Result<(), String>
+where
+    W: Write,
+    C: Borrow<Chromatogram>,
+    I: Iterator<Item = Result<Entry<S, C>, String>>,
+{
     let count = list.count.unwrap_or(list.chromatograms.len());
     let mut tag = BytesStart::new("chromatogramList");
     let count_s = count.to_string();
@@ -1260,8 +1776,11 @@ fn write_chromatogram_list(
         .write_event(Event::Start(tag))
         .map_err(|e| e.to_string())?;
 
-    for c in &list.chromatograms {
-        write_chromatogram(writer, c, fallback_default_dp, idx)?;
+    while let Some(entry) = entries.next_if(|e| !matches!(e, Ok(Entry::Spectrum(_)))) {
+        let Entry::Chromatogram(c) = entry? else {
+            unreachable!()
+        };
+        write_chromatogram(writer, c.borrow(), fallback_default_dp, options, idx)?;
     }
 
     writer
@@ -1271,10 +1790,11 @@ fn write_chromatogram_list(
 }
 
 /// <chromatogram>
-fn write_chromatogram(
-    writer: &mut Writer<Vec<u8>>,
+fn write_chromatogram<W: Write>(
+    writer: &mut XmlWriter<W>,
     c: &Chromatogram,
     fallback_default_dp: Option<&str>,
+    options: &MzmlWriteOptions,
     idx: &mut IndexAcc,
 ) -> Result<(), String> {
     let default_len = c
@@ -1302,6 +1822,8 @@ fn write_chromatogram(
     if let Some(v) = nonempty(dpr) {
         tag.push_attribute(("dataProcessingRef", v));
     }
+    push_extension_attributes(&mut tag, c.extensions.as_ref());
+    writer.preserve(c.extensions.as_ref());
 
     let off = write_start_capture_offset(writer, tag)?;
     idx.chromatogram.push(IndexOffsetAcc {
@@ -1321,7 +1843,7 @@ fn write_chromatogram(
     }
 
     if let Some(bdal) = &c.binary_data_array_list {
-        write_binary_data_array_list(writer, bdal, fallback_default_dp)?;
+        write_binary_data_array_list(writer, bdal, fallback_default_dp, options)?;
     }
 
     writer
@@ -1331,10 +1853,11 @@ fn write_chromatogram(
 }
 
 /// <binaryDataArrayList>
-fn write_binary_data_array_list(
-    writer: &mut Writer<Vec<u8>>,
+fn write_binary_data_array_list<W: Write>(
+    writer: &mut XmlWriter<W>,
     list: &BinaryDataArrayList,
     fallback_default_dp: Option<&str>,
+    options: &MzmlWriteOptions,
 ) -> Result<(), String> {
     let count = list.count.unwrap_or(list.binary_data_arrays.len());
     let mut tag = BytesStart::new("binaryDataArrayList");
@@ -1346,7 +1869,7 @@ fn write_binary_data_array_list(
         .map_err(|e| e.to_string())?;
 
     for bda in &list.binary_data_arrays {
-        write_binary_data_array(writer, bda, fallback_default_dp)?;
+        write_binary_data_array(writer, bda, fallback_default_dp, options)?;
     }
 
     writer
@@ -1355,122 +1878,235 @@ fn write_binary_data_array_list(
     Ok(())
 }
 
-/// <binaryDataArray>
-fn write_binary_data_array(
-    writer: &mut Writer<Vec<u8>>,
-    bda: &BinaryDataArray,
-    fallback_default_dp: Option<&str>,
-) -> Result<(), String> {
-    let has_accession = |acc: &str| {
-        bda.cv_params
-            .iter()
-            .any(|p| p.accession.as_deref() == Some(acc))
-    };
+/// Compression of one array, as written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Codec {
+    None,
+    Zlib,
+    /// Numpress, then zlib when set.
+    Numpress(Numpress, bool),
+}
 
-    let cv_has_zlib = has_accession("MS:1000574"); // zlib compression
-    let cv_has_no_comp = has_accession("MS:1000576"); // no compression
+impl Codec {
+    fn from_cv(params: &[CvParam]) -> Option<Self> {
+        params.iter().find_map(|p| match p.accession.as_deref()? {
+            "MS:1000574" => Some(Self::Zlib),
+            "MS:1000576" => Some(Self::None),
+            acc => Numpress::from_accession(acc).map(|(n, zlib)| Self::Numpress(n, zlib)),
+        })
+    }
 
-    let cv_has_f64 = has_accession("MS:1000523"); // 64-bit float
-    let cv_has_f32 = has_accession("MS:1000521"); // 32-bit float
-    let cv_has_i64 = has_accession("MS:1000522"); // 64-bit integer
-    let cv_has_i32 = has_accession("MS:1000519"); // 32-bit integer
-    let cv_has_i16 = has_accession("MS:1000518"); // 16-bit integer
+    fn cv_term(self) -> (&'static str, &'static str) {
+        match self {
+            Self::None => ("MS:1000576", "no compression"),
+            Self::Zlib => ("MS:1000574", "zlib compression"),
+            Self::Numpress(n, zlib) => n.cv_term(zlib),
+        }
+    }
+}
 
-    // let binary = bda.binary.as_ref().ok_or_else(|| {
-    //     "binaryDataArray.binary is None -> would produce empty <binary>. Populate BinaryDataArray.binary.".to_string()
-    // })?;
-    let Some(binary) = bda.binary.as_ref() else {
-        return Ok(());
-    };
+pub(crate) const COMPRESSION_ACCESSIONS: [&str; 8] = [
+    "MS:1000574",
+    "MS:1000576",
+    "MS:1002312",
+    "MS:1002313",
+    "MS:1002314",
+    "MS:1002746",
+    "MS:1002747",
+    "MS:1002748",
+];
+
+pub(crate) const NUMERIC_TYPE_ACCESSIONS: [&str; 6] = [
+    "MS:1000518",
+    "MS:1000519",
+    "MS:1000520",
+    "MS:1000521",
+    "MS:1000522",
+    "MS:1000523",
+];
 
-    if let Some(nt) = bda.numeric_type {
-        let ok = match (binary, nt) {
-            (BinaryData::F64(_), NumericType::Float64) => true,
-            (BinaryData::F32(_), NumericType::Float32) => true,
-            (BinaryData::F16(_), NumericType::Float16) => true,
-            (BinaryData::I64(_), NumericType::Int64) => true,
-            (BinaryData::I32(_), NumericType::Int32) => true,
-            (BinaryData::I16(_), NumericType::Int16) => true,
-            _ => false,
-        };
-        if !ok {
-            return Err("binary/numeric_type mismatch".into());
-        }
+#[inline]
+pub(crate) fn numeric_type_of(binary: &BinaryData) -> NumericType {
+    match binary {
+        BinaryData::F64(_) => NumericType::Float64,
+        BinaryData::F32(_) => NumericType::Float32,
+        BinaryData::F16(_) => NumericType::Float16,
+        BinaryData::I64(_) => NumericType::Int64,
+        BinaryData::I32(_) => NumericType::Int32,
+        BinaryData::I16(_) => NumericType::Int16,
     }
+}
 
-    let (mut raw_bytes, array_len, inferred_numeric_type) = match binary {
-        BinaryData::F64(v) => {
-            let mut bytes = Vec::with_capacity(v.len() * 8);
-            for &x in v {
-                bytes.extend_from_slice(&x.to_le_bytes());
-            }
-            (bytes, v.len(), NumericType::Float64)
-        }
-        BinaryData::F32(v) => {
-            let mut bytes = Vec::with_capacity(v.len() * 4);
-            for &x in v {
-                bytes.extend_from_slice(&x.to_le_bytes());
-            }
-            (bytes, v.len(), NumericType::Float32)
-        }
-        BinaryData::F16(v) => {
-            let mut bytes = Vec::with_capacity(v.len() * 2);
-            for &x in v {
-                bytes.extend_from_slice(&x.to_le_bytes());
-            }
-            (bytes, v.len(), NumericType::Float16)
+/// Label for error messages, binary data type accession and name.
+pub(crate) fn numeric_type_term(nt: NumericType) -> (&'static str, &'static str, &'static str) {
+    match nt {
+        NumericType::Float64 => ("F64", "MS:1000523", "64-bit float"),
+        NumericType::Float32 => ("F32", "MS:1000521", "32-bit float"),
+        NumericType::Float16 => ("F16", "MS:1000520", "16-bit float"),
+        NumericType::Int64 => ("I64", "MS:1000522", "64-bit integer"),
+        NumericType::Int32 => ("I32", "MS:1000519", "32-bit integer"),
+        NumericType::Int16 => ("I16", "MS:1000518", "16-bit integer"),
+    }
+}
+
+/// Replaces the params of `params` whose accession is in `family` by one
+/// `accession` param, where the first of them was, or appends it.
+pub(crate) fn replace_cv_term(
+    params: &mut Vec<CvParam>,
+    family: &[&str],
+    (accession, name): (&str, &str),
+) {
+    let in_family = |p: &CvParam| p.accession.as_deref().is_some_and(|a| family.contains(&a));
+    let at = params.iter().position(in_family).unwrap_or(params.len());
+    params.retain(|p| !in_family(p));
+    params.insert(
+        at.min(params.len()),
+        CvParam {
+            cv_ref: Some("MS".to_string()),
+            accession: Some(accession.to_string()),
+            name: name.to_string(),
+            ..Default::default()
+        },
+    );
+}
+
+/// Compression for `ArrayCompression::Numpress`, by array type.
+fn numpress_codec_for(params: &[CvParam]) -> Codec {
+    let has = |acc: &str| params.iter().any(|p| p.accession.as_deref() == Some(acc));
+    if has("MS:1000514") || has("MS:1000595") {
+        Codec::Numpress(Numpress::Linear, false)
+    } else if has("MS:1000515") {
+        Codec::Numpress(Numpress::Slof, false)
+    } else {
+        Codec::Zlib
+    }
+}
+
+fn with_precision(binary: &BinaryData, precision: ArrayPrecision) -> Cow<'_, BinaryData> {
+    match (precision, binary) {
+        (ArrayPrecision::F32, BinaryData::F64(v)) => {
+            Cow::Owned(BinaryData::F32(v.iter().map(|&x| x as f32).collect()))
         }
-        BinaryData::I64(v) => {
-            let mut bytes = Vec::with_capacity(v.len() * 8);
-            for &x in v {
-                bytes.extend_from_slice(&x.to_le_bytes());
-            }
-            (bytes, v.len(), NumericType::Int64)
+        (ArrayPrecision::F64, BinaryData::F32(v)) => {
+            Cow::Owned(BinaryData::F64(v.iter().map(|&x| x as f64).collect()))
         }
-        BinaryData::I32(v) => {
-            let mut bytes = Vec::with_capacity(v.len() * 4);
-            for &x in v {
-                bytes.extend_from_slice(&x.to_le_bytes());
-            }
-            (bytes, v.len(), NumericType::Int32)
+        _ => Cow::Borrowed(binary),
+    }
+}
+
+fn values_as_f64(binary: &BinaryData) -> Result<Vec<f64>, String> {
+    Ok(match binary {
+        BinaryData::F64(v) => v.clone(),
+        BinaryData::F32(v) => v.iter().map(|&x| x as f64).collect(),
+        BinaryData::I64(v) => v.iter().map(|&x| x as f64).collect(),
+        BinaryData::I32(v) => v.iter().map(|&x| x as f64).collect(),
+        BinaryData::I16(v) => v.iter().map(|&x| x as f64).collect(),
+        BinaryData::F16(_) => return Err("numpress cannot encode 16-bit float arrays".into()),
+    })
+}
+
+pub(crate) fn le_bytes(binary: &BinaryData) -> Vec<u8> {
+    fn collect<const N: usize, T: Copy>(v: &[T], to_le: fn(T) -> [u8; N]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(v.len() * N);
+        for &x in v {
+            bytes.extend_from_slice(&to_le(x));
         }
-        BinaryData::I16(v) => {
-            let mut bytes = Vec::with_capacity(v.len() * 2);
-            for &x in v {
-                bytes.extend_from_slice(&x.to_le_bytes());
+        bytes
+    }
+    match binary {
+        BinaryData::F64(v) => collect(v, f64::to_le_bytes),
+        BinaryData::F32(v) => collect(v, f32::to_le_bytes),
+        BinaryData::F16(v) => collect(v, u16::to_le_bytes),
+        BinaryData::I64(v) => collect(v, i64::to_le_bytes),
+        BinaryData::I32(v) => collect(v, i32::to_le_bytes),
+        BinaryData::I16(v) => collect(v, i16::to_le_bytes),
+    }
+}
+
+/// <binaryDataArray>
+fn write_binary_data_array<W: Write>(
+    writer: &mut XmlWriter<W>,
+    bda: &BinaryDataArray,
+    fallback_default_dp: Option<&str>,
+    options: &MzmlWriteOptions,
+) -> Result<(), String> {
+    let Some(original) = bda.binary.as_ref() else {
+        return Ok(());
+    };
+    let original_type = numeric_type_of(original);
+
+    if let Some(nt) = bda.numeric_type
+        && nt != original_type
+    {
+        return Err("binary/numeric_type mismatch".into());
+    }
+
+    let declared = Codec::from_cv(&bda.cv_params);
+    let codec = match options.compression {
+        ArrayCompression::Keep => match declared {
+            Some(codec) => codec,
+            None if options.add_missing_cv_params => Codec::None,
+            None => {
+                return Err(
+                    "binaryDataArray missing compression cvParam (MS:1000576 or MS:1000574)".into(),
+                );
             }
-            (bytes, v.len(), NumericType::Int16)
-        }
+        },
+        ArrayCompression::None => Codec::None,
+        ArrayCompression::Zlib => Codec::Zlib,
+        ArrayCompression::Numpress => numpress_codec_for(&bda.cv_params),
     };
 
-    if !cv_has_zlib && !cv_has_no_comp {
-        return Err(
-            "binaryDataArray missing compression cvParam (MS:1000576 or MS:1000574)".into(),
-        );
-    }
-    if cv_has_zlib && !raw_bytes.is_empty() {
-        raw_bytes = compress_to_vec_zlib(&raw_bytes, 6);
+    let binary = with_precision(original, options.precision);
+    let array_len = binary_len(&binary);
+    let written_type = match codec {
+        Codec::Numpress(..) => NumericType::Float64,
+        _ => numeric_type_of(&binary),
+    };
+
+    let mut cv_params = Cow::Borrowed(bda.cv_params.as_slice());
+    if declared != Some(codec) {
+        replace_cv_term(cv_params.to_mut(), &COMPRESSION_ACCESSIONS, codec.cv_term());
     }
 
-    match inferred_numeric_type {
-        NumericType::Float64 if !(cv_has_f64 || bda.numeric_type == Some(NumericType::Float64)) => {
-            return Err("binaryDataArray F64 but missing cvParam MS:1000523".into());
-        }
-        NumericType::Float32 if !(cv_has_f32 || bda.numeric_type == Some(NumericType::Float32)) => {
-            return Err("binaryDataArray F32 but missing cvParam MS:1000521".into());
-        }
-        NumericType::Int64 if !(cv_has_i64 || bda.numeric_type == Some(NumericType::Int64)) => {
-            return Err("binaryDataArray I64 but missing cvParam MS:1000522".into());
-        }
-        NumericType::Int32 if !(cv_has_i32 || bda.numeric_type == Some(NumericType::Int32)) => {
-            return Err("binaryDataArray I32 but missing cvParam MS:1000519".into());
-        }
-        NumericType::Int16 if !(cv_has_i16 || bda.numeric_type == Some(NumericType::Int16)) => {
-            return Err("binaryDataArray I16 but missing cvParam MS:1000518".into());
+    let (label, type_accession, type_name) = numeric_type_term(written_type);
+    let has = |acc: &str| {
+        bda.cv_params
+            .iter()
+            .any(|p| p.accession.as_deref() == Some(acc))
+    };
+    if !has(type_accession) {
+        let retyped = written_type != original_type;
+        let typed_elsewhere = NUMERIC_TYPE_ACCESSIONS.iter().any(|&acc| has(acc));
+        if options.add_missing_cv_params || (retyped && typed_elsewhere) {
+            replace_cv_term(
+                cv_params.to_mut(),
+                &NUMERIC_TYPE_ACCESSIONS,
+                (type_accession, type_name),
+            );
+        } else if written_type != NumericType::Float16
+            && (retyped || bda.numeric_type != Some(written_type))
+        {
+            return Err(format!(
+                "binaryDataArray {label} but missing cvParam {type_accession}"
+            ));
         }
-        _ => {}
     }
 
+    let raw_bytes = if array_len == 0 {
+        Vec::new()
+    } else {
+        let zlib = |bytes: Vec<u8>| compress_to_vec_zlib(&bytes, options.zlib_level);
+        match codec {
+            Codec::None => le_bytes(&binary),
+            Codec::Zlib => zlib(le_bytes(&binary)),
+            Codec::Numpress(n, then_zlib) => {
+                let packed = n.encode(&values_as_f64(&binary)?)?;
+                if then_zlib { zlib(packed) } else { packed }
+            }
+        }
+    };
+
     let encoded = if raw_bytes.is_empty() {
         String::new()
     } else {
@@ -1507,7 +2143,7 @@ fn write_binary_data_array(
             .map_err(|e| e.to_string())?;
     }
 
-    write_cv_params(writer, &bda.cv_params)?;
+    write_cv_params(writer, &cv_params)?;
     write_user_params(writer, &bda.user_params)?;
 
     writer
@@ -1528,7 +2164,7 @@ fn write_binary_data_array(
     Ok(())
 }
 
-fn write_target_list(writer: &mut Writer<Vec<u8>>, list: &TargetList) -> Result<(), String> {
+fn write_target_list<W: Write>(writer: &mut XmlWriter<W>, list: &TargetList) -> Result<(), String> {
     let count = list.count.unwrap_or(list.targets.len());
     let mut tag = BytesStart::new("targetList");
     let count_s = count.to_string();
@@ -1558,8 +2194,8 @@ fn write_target_list(writer: &mut Writer<Vec<u8>>, list: &TargetList) -> Result<
     Ok(())
 }
 
-fn write_source_file_ref_list(
-    writer: &mut Writer<Vec<u8>>,
+fn write_source_file_ref_list<W: Write>(
+    writer: &mut XmlWriter<W>,
     list: &SourceFileRefList,
 ) -> Result<(), String> {
     let count = list.count.unwrap_or(list.source_file_refs.len());
@@ -1586,8 +2222,8 @@ fn write_source_file_ref_list(
 }
 
 #[inline]
-fn write_referenceable_param_group_refs(
-    writer: &mut Writer<Vec<u8>>,
+fn write_referenceable_param_group_refs<W: Write>(
+    writer: &mut XmlWriter<W>,
     refs: &[ReferenceableParamGroupRef],
 ) -> Result<(), String> {
     for r in refs {
@@ -1597,8 +2233,8 @@ fn write_referenceable_param_group_refs(
 }
 
 #[inline]
-fn write_referenceable_param_group_ref(
-    writer: &mut Writer<Vec<u8>>,
+fn write_referenceable_param_group_ref<W: Write>(
+    writer: &mut XmlWriter<W>,
     r: &ReferenceableParamGroupRef,
 ) -> Result<(), String> {
     let mut tag = BytesStart::new("referenceableParamGroupRef");
@@ -1609,7 +2245,7 @@ fn write_referenceable_param_group_ref(
 }
 
 #[inline]
-fn write_cv_param(writer: &mut Writer<Vec<u8>>, cv: &CvParam) -> Result<(), String> {
+fn write_cv_param<W: Write>(writer: &mut XmlWriter<W>, cv: &CvParam) -> Result<(), String> {
     let mut tag = BytesStart::new("cvParam");
 
     if let Some(v) = cv.cv_ref.as_deref().and_then(|s| nonempty(Some(s))) {
@@ -1638,7 +2274,7 @@ fn write_cv_param(writer: &mut Writer<Vec<u8>>, cv: &CvParam) -> Result<(), Stri
         .map_err(|e| e.to_string())
 }
 
-fn write_cv_params(writer: &mut Writer<Vec<u8>>, params: &[CvParam]) -> Result<(), String> {
+fn write_cv_params<W: Write>(writer: &mut XmlWriter<W>, params: &[CvParam]) -> Result<(), String> {
     for cv in params {
         write_cv_param(writer, cv)?;
     }
@@ -1646,7 +2282,7 @@ fn write_cv_params(writer: &mut Writer<Vec<u8>>, params: &[CvParam]) -> Result<(
 }
 
 #[inline]
-fn write_user_param(writer: &mut Writer<Vec<u8>>, up: &UserParam) -> Result<(), String> {
+fn write_user_param<W: Write>(writer: &mut XmlWriter<W>, up: &UserParam) -> Result<(), String> {
     let mut tag = BytesStart::new("userParam");
     tag.push_attribute(("name", up.name.as_str()));
 
@@ -1672,15 +2308,18 @@ fn write_user_param(writer: &mut Writer<Vec<u8>>, up: &UserParam) -> Result<(),
         .map_err(|e| e.to_string())
 }
 
-fn write_user_params(writer: &mut Writer<Vec<u8>>, params: &[UserParam]) -> Result<(), String> {
+fn write_user_params<W: Write>(
+    writer: &mut XmlWriter<W>,
+    params: &[UserParam],
+) -> Result<(), String> {
     for up in params {
         write_user_param(writer, up)?;
     }
     Ok(())
 }
 
-fn write_cv_container(
-    writer: &mut Writer<Vec<u8>>,
+fn write_cv_container<W: Write>(
+    writer: &mut XmlWriter<W>,
     tag_name: &str,
     refs: &[ReferenceableParamGroupRef],
     cvs: &[CvParam],
@@ -1700,8 +2339,14 @@ fn write_cv_container(
     Ok(())
 }
 
-fn write_index_list_with_offset(
-    writer: &mut Writer<Vec<u8>>,
+fn push_extension_attributes(tag: &mut BytesStart<'_>, extensions: Option<&Extensions>) {
+    for (name, value) in extensions.map_or(&[][..], |e| &e.attributes) {
+        tag.push_attribute((name.as_str(), value.as_str()));
+    }
+}
+
+fn write_index_list_with_offset<W: Write>(
+    writer: &mut XmlWriter<W>,
     idx: &IndexAcc,
 ) -> Result<u64, String> {
     let mut count = 0usize;
@@ -1732,8 +2377,8 @@ fn write_index_list_with_offset(
     Ok(off)
 }
 
-fn write_index(
-    writer: &mut Writer<Vec<u8>>,
+fn write_index<W: Write>(
+    writer: &mut XmlWriter<W>,
     name: &str,
     offsets: &Vec<IndexOffsetAcc>,
 ) -> Result<(), String> {
@@ -1768,7 +2413,25 @@ fn write_index(
     Ok(())
 }
 
-fn write_index_list_offset(writer: &mut Writer<Vec<u8>>, off: u64) -> Result<(), String> {
+/// SHA-1 of every byte up to and including the `<fileChecksum>` start tag,
+/// as required by the indexed mzML schema.
+fn write_file_checksum<W: Write>(writer: &mut XmlWriter<W>) -> Result<(), String> {
+    writer
+        .write_event(Event::Start(BytesStart::new("fileChecksum")))
+        .map_err(|e| e.to_string())?;
+
+    let digest = writer.get_ref().digest();
+    writer
+        .write_event(Event::Text(BytesText::new(digest.as_str())))
+        .map_err(|e| e.to_string())?;
+
+    writer
+        .write_event(Event::End(BytesEnd::new("fileChecksum")))
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn write_index_list_offset<W: Write>(writer: &mut XmlWriter<W>, off: u64) -> Result<(), String> {
     writer
         .write_event(Event::Start(BytesStart::new("indexListOffset")))
         .map_err(|e| e.to_string())?;
@@ -0,0 +1,352 @@
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use std::{
+    collections::HashMap,
+    io::{BufReader, Read, Seek, SeekFrom},
+};
+
+use crate::mzml::{
+    mzml_reader::{MzMLItem, MzMLReader},
+    schema::TagId,
+    structs::*,
+    utilities::{
+        ParseError, attr, empty_chromatogram, empty_spectrum, parse_chromatogram,
+        parse_index_list::parse_index_list, parse_spectrum, parsing_workspace::ParsingWorkspace,
+        tag_id_from_bytes,
+    },
+};
+
+/// How far from the end of the file `<indexListOffset>` is searched for.
+const TAIL_WINDOW: u64 = 8 * 1024;
+
+/// Random access to the spectra and chromatograms of an mzML file.
+///
+/// The `<indexList>` is located through `<indexListOffset>` at the file tail
+/// and each lookup seeks straight to the element. When the index is missing,
+/// unreadable, or points at the wrong element, it is rebuilt with one pass
+/// over the file and the lookup is retried.
+pub struct IndexedMzMLReader<R> {
+    inner: R,
+    header: MzML,
+    index: IndexList,
+    spectrum_ids: HashMap<String, usize>,
+    chromatogram_ids: HashMap<String, usize>,
+    rebuilt: bool,
+}
+
+impl<R: Read + Seek> IndexedMzMLReader<R> {
+    pub fn new(mut inner: R) -> Result<Self, ParseError> {
+        inner.seek(SeekFrom::Start(0)).map_err(io_error)?;
+        let header = MzMLReader::new(BufReader::new(&mut inner))?.into_header();
+
+        let (index, rebuilt) = match read_index_list(&mut inner)? {
+            Some(index) => (index, false),
+            None => (scan_offsets(&mut inner)?, true),
+        };
+
+        let mut reader = Self {
+            inner,
+            header,
+            index,
+            spectrum_ids: HashMap::new(),
+            chromatogram_ids: HashMap::new(),
+            rebuilt,
+        };
+        reader.build_id_lookup();
+        Ok(reader)
+    }
+
+    /// Header elements and run attributes; spectrum and chromatogram vectors
+    /// are left empty.
+    #[inline]
+    pub fn header(&self) -> &MzML {
+        &self.header
+    }
+
+    #[inline]
+    pub fn index(&self) -> &IndexList {
+        &self.index
+    }
+
+    /// `true` when the offsets come from a scan rather than the file's own
+    /// `<indexList>`.
+    #[inline]
+    pub fn index_was_rebuilt(&self) -> bool {
+        self.rebuilt
+    }
+
+    #[inline]
+    pub fn spectrum_count(&self) -> usize {
+        self.index.spectrum.len()
+    }
+
+    #[inline]
+    pub fn chromatogram_count(&self) -> usize {
+        self.index.chromatogram.len()
+    }
+
+    pub fn spectrum(&mut self, index: usize) -> Result<Option<Spectrum>, ParseError> {
+        self.fetch(TagId::Spectrum, index)?
+            .map(|item| match item {
+                MzMLItem::Spectrum(s) => Ok(s),
+                MzMLItem::Chromatogram(_) => unreachable!(),
+            })
+            .transpose()
+    }
+
+    pub fn spectrum_by_id(&mut self, id: &str) -> Result<Option<Spectrum>, ParseError> {
+        match self.spectrum_ids.get(id) {
+            Some(&index) => self.spectrum(index),
+            None => Ok(None),
+        }
+    }
+
+    pub fn chromatogram(&mut self, index: usize) -> Result<Option<Chromatogram>, ParseError> {
+        self.fetch(TagId::Chromatogram, index)?
+            .map(|item| match item {
+                MzMLItem::Chromatogram(c) => Ok(c),
+                MzMLItem::Spectrum(_) => unreachable!(),
+            })
+            .transpose()
+    }
+
+    pub fn chromatogram_by_id(&mut self, id: &str) -> Result<Option<Chromatogram>, ParseError> {
+        match self.chromatogram_ids.get(id) {
+            Some(&index) => self.chromatogram(index),
+            None => Ok(None),
+        }
+    }
+
+    fn fetch(&mut self, tag: TagId, index: usize) -> Result<Option<MzMLItem>, ParseError> {
+        loop {
+            let entries = match tag {
+                TagId::Spectrum => &self.index.spectrum,
+                _ => &self.index.chromatogram,
+            };
+            let Some(entry) = entries.get(index) else {
+                return Ok(None);
+            };
+            let (offset, id_ref) = (entry.offset, entry.id_ref.clone());
+
+            if let Some(item) = self.parse_at(offset, tag, id_ref.as_deref())? {
+                return Ok(Some(item));
+            }
+            if self.rebuilt {
+                return Err(ParseError::UnexpectedTag {
+                    tag: format!("{tag:?}"),
+                    byte_offset: offset,
+                });
+            }
+            self.index = scan_offsets(&mut self.inner)?;
+            self.rebuilt = true;
+            self.build_id_lookup();
+        }
+    }
+
+    /// Parses the element at `offset`, or returns `None` when the bytes
+    /// there are not the expected element.
+    fn parse_at(
+        &mut self,
+        offset: u64,
+        tag: TagId,
+        id_ref: Option<&str>,
+    ) -> Result<Option<MzMLItem>, ParseError> {
+        self.inner.seek(SeekFrom::Start(offset)).map_err(io_error)?;
+        let mut ws = ParsingWorkspace::new(Reader::from_reader(BufReader::new(&mut self.inner)));
+
+        let (start, is_open) = match ws.next_event() {
+            Ok(Event::Start(e)) => (e, true),
+            Ok(Event::Empty(e)) => (e, false),
+            _ => return Ok(None),
+        };
+        if tag_id_from_bytes(start.name().as_ref()) != tag {
+            return Ok(None);
+        }
+        if id_ref.is_some_and(|id| attr(&start, b"id").as_deref() != Some(id)) {
+            return Ok(None);
+        }
+
+        Ok(Some(match (tag, is_open) {
+            (TagId::Spectrum, true) => MzMLItem::Spectrum(parse_spectrum(&mut ws, &start)?),
+            (TagId::Spectrum, false) => MzMLItem::Spectrum(empty_spectrum(&start)),
+            (_, true) => MzMLItem::Chromatogram(parse_chromatogram(&mut ws, &start)?),
+            (_, false) => MzMLItem::Chromatogram(empty_chromatogram(&start)),
+        }))
+    }
+
+    fn build_id_lookup(&mut self) {
+        self.spectrum_ids = id_lookup(&self.index.spectrum);
+        self.chromatogram_ids = id_lookup(&self.index.chromatogram);
+    }
+}
+
+#[inline]
+fn io_error(e: std::io::Error) -> ParseError {
+    ParseError::Xml(quick_xml::Error::Io(e.into()))
+}
+
+fn id_lookup(offsets: &[IndexOffset]) -> HashMap<String, usize> {
+    offsets
+        .iter()
+        .enumerate()
+        .filter_map(|(i, o)| o.id_ref.clone().map(|id| (id, i)))
+        .collect()
+}
+
+/// Loads the `<indexList>` pointed to by `<indexListOffset>`. Returns `None`
+/// when either is missing or the offset does not land on `<indexList>`.
+pub(crate) fn read_index_list<R: Read + Seek>(
+    inner: &mut R,
+) -> Result<Option<IndexList>, ParseError> {
+    let len = inner.seek(SeekFrom::End(0)).map_err(io_error)?;
+    let tail_start = len.saturating_sub(TAIL_WINDOW);
+    inner.seek(SeekFrom::Start(tail_start)).map_err(io_error)?;
+    let mut tail = Vec::with_capacity((len - tail_start) as usize);
+    inner.read_to_end(&mut tail).map_err(io_error)?;
+
+    let Some(list_offset) = find_index_list_offset(&tail) else {
+        return Ok(None);
+    };
+    if list_offset >= len {
+        return Ok(None);
+    }
+
+    inner.seek(SeekFrom::Start(list_offset)).map_err(io_error)?;
+    let mut bytes = Vec::with_capacity((len - list_offset) as usize);
+    inner.read_to_end(&mut bytes).map_err(io_error)?;
+    if !bytes.trim_ascii_start().starts_with(b"<indexList") {
+        return Ok(None);
+    }
+    // Parse the list on its own; the closing `</indexedmzML>` after it would
+    // be an unmatched end tag.
+    const CLOSE: &[u8] = b"</indexList>";
+    let Some(end) = bytes.windows(CLOSE.len()).position(|w| w == CLOSE) else {
+        return Ok(None);
+    };
+
+    Ok(parse_index_list(&bytes[..end + CLOSE.len()])
+        .ok()
+        .flatten()
+        .filter(|index| !index.spectrum.is_empty() || !index.chromatogram.is_empty())
+        .map(|index| IndexList {
+            index_list_offset: Some(list_offset),
+            ..index
+        }))
+}
+
+fn find_index_list_offset(tail: &[u8]) -> Option<u64> {
+    const OPEN: &[u8] = b"<indexListOffset>";
+    let start = tail.windows(OPEN.len()).rposition(|w| w == OPEN)? + OPEN.len();
+    let text = &tail[start..];
+    let end = text.iter().position(|&b| b == b'<')?;
+    std::str::from_utf8(&text[..end]).ok()?.trim().parse().ok()
+}
+
+/// Rebuilds spectrum and chromatogram offsets with a single pass over the
+/// file. Element contents are skipped, not parsed.
+pub(crate) fn scan_offsets<R: Read + Seek>(inner: &mut R) -> Result<IndexList, ParseError> {
+    inner.seek(SeekFrom::Start(0)).map_err(io_error)?;
+    let mut reader = Reader::from_reader(BufReader::new(&mut *inner));
+    let mut index = IndexList {
+        spectrum: Vec::new(),
+        chromatogram: Vec::new(),
+        index_list_offset: None,
+        file_checksum: None,
+    };
+    let mut buf = Vec::new();
+    let mut skip = Vec::new();
+
+    loop {
+        buf.clear();
+        let offset = reader.buffer_position();
+        let (start, is_open) = match reader.read_event_into(&mut buf)? {
+            Event::Start(e) => (e, true),
+            Event::Empty(e) => (e, false),
+            Event::Eof => break,
+            _ => continue,
+        };
+        let target = match tag_id_from_bytes(start.name().as_ref()) {
+            TagId::Spectrum => &mut index.spectrum,
+            TagId::Chromatogram => &mut index.chromatogram,
+            _ => continue,
+        };
+        target.push(IndexOffset {
+            id_ref: attr(&start, b"id"),
+            offset,
+        });
+        if is_open {
+            let end = start.to_end().into_owned();
+            skip.clear();
+            reader.read_to_end_into(end.name(), &mut skip)?;
+        }
+    }
+
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mzml::{bin_to_mzml::bin_to_mzml, parse_mzml::parse_mzml};
+    use std::io::Cursor;
+
+    fn json<T: serde::Serialize>(value: &T) -> serde_json::Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    fn assert_matches_full_parse(bytes: Vec<u8>, expect_rebuilt: bool) {
+        let full = parse_mzml(&bytes).unwrap();
+        let mut reader = IndexedMzMLReader::new(Cursor::new(bytes)).unwrap();
+
+        let spectra = &full.run.spectrum_list.as_ref().unwrap().spectra;
+        assert_eq!(reader.spectrum_count(), spectra.len());
+        for (i, want) in spectra.iter().enumerate().rev() {
+            let got = reader.spectrum(i).unwrap().unwrap();
+            assert_eq!(json(&got), json(want));
+            let by_id = reader.spectrum_by_id(&want.id).unwrap().unwrap();
+            assert_eq!(by_id.id, want.id);
+        }
+        assert!(reader.spectrum(spectra.len()).unwrap().is_none());
+
+        if let Some(list) = &full.run.chromatogram_list {
+            for (i, want) in list.chromatograms.iter().enumerate() {
+                let got = reader.chromatogram(i).unwrap().unwrap();
+                assert_eq!(json(&got), json(want));
+            }
+        }
+        assert_eq!(json(&reader.header().cv_list), json(&full.cv_list));
+        assert_eq!(reader.index_was_rebuilt(), expect_rebuilt);
+    }
+
+    #[test]
+    fn seeks_with_a_valid_index() {
+        let bytes = std::fs::read("data/mzml/tiny.pwiz.mzML0.99.10.mzML").unwrap();
+        let written = bin_to_mzml(&parse_mzml(&bytes).unwrap()).unwrap();
+        assert_matches_full_parse(written.into_bytes(), false);
+    }
+
+    #[test]
+    fn rebuilds_when_an_offset_is_wrong() {
+        let bytes = std::fs::read("data/mzml/tiny.pwiz.mzML0.99.10.mzML").unwrap();
+        let written = bin_to_mzml(&parse_mzml(&bytes).unwrap()).unwrap();
+        let list = written.find("<indexList").unwrap();
+        let open = list + written[list..].find("<offset").unwrap();
+        let digits = open + written[open..].find('>').unwrap() + 1;
+        let close = digits + written[digits..].find('<').unwrap();
+        let corrupted = format!("{}1{}", &written[..digits], &written[close..]);
+        assert_matches_full_parse(corrupted.into_bytes(), true);
+    }
+
+    #[test]
+    fn rebuilds_a_stale_index() {
+        // The fixture's offsets do not match its contents.
+        let bytes = std::fs::read("data/mzml/tiny.pwiz.mzML0.99.10.mzML").unwrap();
+        assert_matches_full_parse(bytes, true);
+    }
+
+    #[test]
+    fn scans_when_there_is_no_index() {
+        let bytes = std::fs::read("data/mzml/tiny4_LTQ-FT.mzML0.99.1.mzML").unwrap();
+        assert_matches_full_parse(bytes, true);
+    }
+}
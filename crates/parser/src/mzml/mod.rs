@@ -1,9 +1,40 @@
 pub mod parse_mzml;
-pub use parse_mzml::{parse_indexed_mzml, parse_mzml};
+pub use parse_mzml::{
+    ParseOptions, decompressing_reader, gunzip, is_gzip, parse_indexed_mzml, parse_mzml,
+    parse_mzml_parallel, parse_mzml_reader, parse_mzml_reader_with_report, parse_mzml_with_options,
+    parse_mzml_with_report,
+};
 pub mod bin_to_mzml;
-pub use bin_to_mzml::bin_to_mzml;
+pub use bin_to_mzml::{
+    ArrayCompression, ArrayPrecision, MzmlWriteOptions, bin_to_mzml, bin_to_mzml_with_options,
+    write_b000_as_mzml, write_mzml, write_mzml_streaming, write_mzml_with_options,
+};
+pub mod indexed_mzml_reader;
+pub use indexed_mzml_reader::IndexedMzMLReader;
+pub mod mzml_reader;
+pub use mzml_reader::{MzMLItem, MzMLReader};
+pub mod parse_report;
+pub use parse_report::{ParseReport, ParseWarning, WarningAction};
+pub mod salvage;
+pub use salvage::{INCOMPLETE_RUN_PARAM, SalvagedMzML, salvage_mzml, salvage_mzml_reader};
 pub mod schema;
 pub mod structs;
+pub mod validate_cv;
+pub use validate_cv::{CvIssue, CvIssueKind, CvValidation, Requirement, validate_cv_terms};
+pub mod validate_index;
+pub use validate_index::{IndexIssue, IndexValidation, validate_indexed_mzml};
+pub mod validate_refs;
+pub use validate_refs::{
+    IdKind, RefFix, RefIssue, RefIssueKind, RefValidation, fix_refs, validate_refs,
+};
+pub mod validate_schema;
+pub use validate_schema::{
+    SchemaIssue, SchemaIssueKind, SchemaValidation, validate_schema, validate_schema_mzml,
+};
+pub mod verify_roundtrip;
+pub use verify_roundtrip::{
+    CompareOptions, DiffEntry, DiffKind, DiffReport, compare_mzml, verify_roundtrip,
+};
 pub mod utilities;
 
 #[cfg(test)]
@@ -0,0 +1,311 @@
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use std::io::BufRead;
+
+use crate::mzml::{
+    parse_mzml::parse_until_run,
+    schema::TagId,
+    structs::*,
+    utilities::{
+        ParamCollector, ParseError, attr, attr_usize, drain_until_close, empty_chromatogram,
+        empty_spectrum, parse_chromatogram, parse_source_file_ref_list, parse_spectrum,
+        parsing_workspace::ParsingWorkspace, read_cv_param, read_ref_group_ref, read_user_param,
+        run_from_start, tag_id_from_bytes,
+    },
+};
+
+/// One entry of `<run>`, in document order.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone)]
+pub enum MzMLItem {
+    Spectrum(Spectrum),
+    Chromatogram(Chromatogram),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Position {
+    Run,
+    SpectrumList,
+    ChromatogramList,
+    Done,
+}
+
+/// Pull parser for mzML that keeps one spectrum or chromatogram in memory at
+/// a time.
+///
+/// `new` reads everything before the first `<spectrumList>` or
+/// `<chromatogramList>` (cvList, fileDescription, instrument list, run
+/// attributes, ...) into `header()`. Entries are then yielded lazily by
+/// `next_item` or the `Iterator` impl. The `spectra` and `chromatograms`
+/// vectors of the header are never filled.
+pub struct MzMLReader<R> {
+    ws: ParsingWorkspace<R>,
+    header: MzML,
+    position: Position,
+}
+
+impl<R: BufRead> MzMLReader<R> {
+    pub fn new(reader: R) -> Result<Self, ParseError> {
+        let mut ws = ParsingWorkspace::new(Reader::from_reader(reader));
+        let mut header = MzML::default();
+        let mut inside_mzml = false;
+
+        let position = match parse_until_run(&mut ws, &mut header, &mut inside_mzml)? {
+            Some(start) => {
+                header.run = run_from_start(&start);
+                Position::Run
+            }
+            None => Position::Done,
+        };
+
+        let mut reader = Self {
+            ws,
+            header,
+            position,
+        };
+        reader.advance_in_run()?;
+        Ok(reader)
+    }
+
+    /// Header elements and run-level metadata read so far.
+    #[inline]
+    pub fn header(&self) -> &MzML {
+        &self.header
+    }
+
+    #[inline]
+    pub fn into_header(self) -> MzML {
+        self.header
+    }
+
+    /// Byte offset just past the last event read, i.e. the end of the last
+    /// entry returned by `next_item`.
+    #[inline]
+    pub fn position(&self) -> u64 {
+        self.ws.xml_reader.buffer_position()
+    }
+
+    /// Next spectrum or chromatogram, or `None` after `</run>`.
+    pub fn next_item(&mut self) -> Result<Option<MzMLItem>, ParseError> {
+        loop {
+            match self.position {
+                Position::Done => return Ok(None),
+                Position::Run => self.advance_in_run()?,
+                Position::SpectrumList => {
+                    if let Some(spectrum) = self.next_spectrum()? {
+                        return Ok(Some(MzMLItem::Spectrum(spectrum)));
+                    }
+                }
+                Position::ChromatogramList => {
+                    if let Some(chromatogram) = self.next_chromatogram()? {
+                        return Ok(Some(MzMLItem::Chromatogram(chromatogram)));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads run children until a list opens or the run closes.
+    fn advance_in_run(&mut self) -> Result<(), ParseError> {
+        while self.position == Position::Run {
+            match self.ws.next_event()? {
+                Event::Start(e) => match tag_id_from_bytes(e.name().as_ref()) {
+                    TagId::SourceFileRefList => {
+                        self.header.run.source_file_ref_list =
+                            Some(parse_source_file_ref_list(&mut self.ws, &e)?);
+                    }
+                    TagId::SpectrumList => {
+                        self.header.run.spectrum_list = Some(SpectrumList {
+                            count: attr_usize(&e, b"count"),
+                            default_data_processing_ref: attr(&e, b"defaultDataProcessingRef"),
+                            ..Default::default()
+                        });
+                        self.position = Position::SpectrumList;
+                    }
+                    TagId::ChromatogramList => {
+                        self.header.run.chromatogram_list = Some(ChromatogramList {
+                            count: attr_usize(&e, b"count"),
+                            default_data_processing_ref: attr(&e, b"defaultDataProcessingRef"),
+                            ..Default::default()
+                        });
+                        self.position = Position::ChromatogramList;
+                    }
+                    TagId::CvParam => self.header.run.receive_cv(read_cv_param(&e)),
+                    TagId::UserParam => self.header.run.receive_user(read_user_param(&e)),
+                    TagId::ReferenceableParamGroupRef => {
+                        self.header.run.receive_ref_group(read_ref_group_ref(&e))
+                    }
+                    _ => drain_until_close(&mut self.ws, e.name().as_ref())?,
+                },
+                Event::Empty(e) => match tag_id_from_bytes(e.name().as_ref()) {
+                    TagId::CvParam => self.header.run.receive_cv(read_cv_param(&e)),
+                    TagId::UserParam => self.header.run.receive_user(read_user_param(&e)),
+                    TagId::ReferenceableParamGroupRef => {
+                        self.header.run.receive_ref_group(read_ref_group_ref(&e))
+                    }
+                    _ => {}
+                },
+                Event::End(e) if tag_id_from_bytes(e.name().as_ref()) == TagId::Run => {
+                    self.position = Position::Done;
+                }
+                Event::Eof => return Err(self.unexpected_eof("run")),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// `None` when `</spectrumList>` is reached.
+    fn next_spectrum(&mut self) -> Result<Option<Spectrum>, ParseError> {
+        loop {
+            match self.ws.next_event()? {
+                Event::Start(e) if tag_id_from_bytes(e.name().as_ref()) == TagId::Spectrum => {
+                    return parse_spectrum(&mut self.ws, &e).map(Some);
+                }
+                Event::Empty(e) if tag_id_from_bytes(e.name().as_ref()) == TagId::Spectrum => {
+                    return Ok(Some(empty_spectrum(&e)));
+                }
+                Event::Start(e) => drain_until_close(&mut self.ws, e.name().as_ref())?,
+                Event::End(e) if tag_id_from_bytes(e.name().as_ref()) == TagId::SpectrumList => {
+                    self.position = Position::Run;
+                    return Ok(None);
+                }
+                Event::Eof => return Err(self.unexpected_eof("spectrumList")),
+                _ => {}
+            }
+        }
+    }
+
+    /// `None` when `</chromatogramList>` is reached.
+    fn next_chromatogram(&mut self) -> Result<Option<Chromatogram>, ParseError> {
+        loop {
+            match self.ws.next_event()? {
+                Event::Start(e) if tag_id_from_bytes(e.name().as_ref()) == TagId::Chromatogram => {
+                    return parse_chromatogram(&mut self.ws, &e).map(Some);
+                }
+                Event::Empty(e) if tag_id_from_bytes(e.name().as_ref()) == TagId::Chromatogram => {
+                    return Ok(Some(empty_chromatogram(&e)));
+                }
+                Event::Start(e) => drain_until_close(&mut self.ws, e.name().as_ref())?,
+                Event::End(e)
+                    if tag_id_from_bytes(e.name().as_ref()) == TagId::ChromatogramList =>
+                {
+                    self.position = Position::Run;
+                    return Ok(None);
+                }
+                Event::Eof => return Err(self.unexpected_eof("chromatogramList")),
+                _ => {}
+            }
+        }
+    }
+
+    #[inline]
+    fn unexpected_eof(&mut self, context: &str) -> ParseError {
+        self.position = Position::Done;
+        ParseError::UnexpectedEof {
+            context: context.to_string(),
+            byte_offset: self.ws.xml_reader.buffer_position(),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for MzMLReader<R> {
+    type Item = Result<MzMLItem, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.next_item();
+        if item.is_err() {
+            self.position = Position::Done;
+        }
+        item.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mzml::parse_mzml::parse_mzml;
+    use std::{fs::File, io::BufReader};
+
+    fn json<T: serde::Serialize>(value: &T) -> serde_json::Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    #[test]
+    fn streams_the_same_entries_as_parse_mzml() {
+        for path in [
+            "data/mzml/test.mzML",
+            "data/mzml/tiny.pwiz.mzML0.99.10.mzML",
+            "data/mzml/tiny2_SRM.mzML0.99.1.mzML",
+        ] {
+            let full = parse_mzml(&std::fs::read(path).unwrap()).unwrap();
+            let mut reader = MzMLReader::new(BufReader::new(File::open(path).unwrap())).unwrap();
+
+            let mut spectra = Vec::new();
+            let mut chromatograms = Vec::new();
+            for item in reader.by_ref() {
+                match item.unwrap() {
+                    MzMLItem::Spectrum(s) => spectra.push(s),
+                    MzMLItem::Chromatogram(c) => chromatograms.push(c),
+                }
+            }
+
+            let run = &full.run;
+            let want_spectra = run.spectrum_list.as_ref().map_or(&[][..], |l| &l.spectra);
+            let want_chroms = run
+                .chromatogram_list
+                .as_ref()
+                .map_or(&[][..], |l| &l.chromatograms);
+            assert_eq!(json(&spectra), json(&want_spectra), "{path}");
+            assert_eq!(json(&chromatograms), json(&want_chroms), "{path}");
+
+            let mut header = reader.into_header();
+            assert_eq!(json(&header.cv_list), json(&full.cv_list), "{path}");
+            assert_eq!(
+                json(&header.instrument_list),
+                json(&full.instrument_list),
+                "{path}"
+            );
+            if let Some(list) = header.run.spectrum_list.as_mut() {
+                list.spectra = want_spectra.to_vec();
+            }
+            if let Some(list) = header.run.chromatogram_list.as_mut() {
+                list.chromatograms = want_chroms.to_vec();
+            }
+            assert_eq!(json(&header.run), json(run), "{path}");
+        }
+    }
+
+    #[test]
+    fn header_is_available_before_first_entry() {
+        let xml = br#"<mzML><cvList count="1"><cv id="MS"/></cvList>
+            <run id="r1"><cvParam cvRef="MS" accession="MS:1000001" name="x"/>
+            <spectrumList count="2"><spectrum id="a" index="0"/><spectrum id="b" index="1"/></spectrumList>
+            </run></mzML>"#;
+        let mut reader = MzMLReader::new(&xml[..]).unwrap();
+        assert_eq!(reader.header().run.id, "r1");
+        assert_eq!(reader.header().run.cv_params.len(), 1);
+        assert_eq!(
+            reader.header().run.spectrum_list.as_ref().unwrap().count,
+            Some(2)
+        );
+
+        let ids: Vec<String> = reader
+            .by_ref()
+            .map(|item| match item.unwrap() {
+                MzMLItem::Spectrum(s) => s.id,
+                MzMLItem::Chromatogram(c) => c.id,
+            })
+            .collect();
+        assert_eq!(ids, ["a", "b"]);
+        assert!(reader.next_item().unwrap().is_none());
+    }
+
+    #[test]
+    fn truncated_run_is_an_error() {
+        let xml = br#"<mzML><run id="r"><spectrumList count="1"><spectrum id="a" index="0">"#;
+        let mut reader = MzMLReader::new(&xml[..]).unwrap();
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+}
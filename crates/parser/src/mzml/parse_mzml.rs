@@ -1,65 +1,430 @@
+use flate2::read::MultiGzDecoder;
 use quick_xml::Reader;
-use quick_xml::events::Event;
-use std::io::Cursor;
+use quick_xml::events::{BytesStart, Event};
+use rayon::prelude::*;
+use std::io::{BufRead, BufReader, Cursor, Read};
 
 use crate::mzml::{
+    indexed_mzml_reader::read_index_list,
+    parse_report::{ParseReport, ParseWarning},
     schema::TagId,
     structs::*,
     utilities::{
-        ParseError, attr_u32, attr_usize, drain_until_close, parse_cv_list,
-        parse_data_processing_list, parse_file_description, parse_index_list,
-        parse_instrument_list, parse_ref_param_group_list, parse_run, parse_sample_list,
-        parse_scan_settings_list, parse_software_list, parsing_workspace::ParsingWorkspace,
+        ParseError, attr, attr_u32, attr_usize, drain_until_close, empty_chromatogram,
+        empty_spectrum, parse_chromatogram, parse_cv_list, parse_data_processing_list,
+        parse_file_description, parse_index_list, parse_instrument_list,
+        parse_ref_param_group_list, parse_run, parse_run_with, parse_sample_list,
+        parse_scan_settings_list, parse_software_list, parse_spectrum,
+        parsing_workspace::{ParsingWorkspace, UnknownMark},
         tag_id_from_bytes,
     },
 };
 
+/// Options for `parse_mzml_with_options` and `parse_mzml_with_report`.
+#[derive(Debug, Clone, Copy)]
+pub struct ParseOptions {
+    /// Fail on unreadable binary data. When unset, invalid base64 and
+    /// truncated zlib streams keep the values that could be decoded and the
+    /// problem is reported as a warning instead.
+    pub strict: bool,
+    /// Keep elements and comments the parser does not model in the
+    /// `extensions` of the nearest `MzML`, `Run`, `Spectrum` or
+    /// `Chromatogram`, with where they were found, so they survive B000 and
+    /// `bin_to_mzml`. Unknown attributes are kept on those four elements
+    /// only. `parse_mzml_parallel`, `MzMLReader` and `salvage_mzml` do not
+    /// preserve anything.
+    pub preserve_unknown: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            strict: true,
+            preserve_unknown: false,
+        }
+    }
+}
+
 pub fn parse_mzml(bytes: &[u8]) -> Result<MzML, ParseError> {
+    parse_mzml_from(Cursor::new(bytes), &ParseOptions::default(), false).map(|(mzml, _)| mzml)
+}
+
+pub fn parse_mzml_with_options(bytes: &[u8], options: &ParseOptions) -> Result<MzML, ParseError> {
+    parse_mzml_from(Cursor::new(bytes), options, false).map(|(mzml, _)| mzml)
+}
+
+/// Like `parse_mzml_with_options`, also returning everything the parser
+/// skipped, truncated or defaulted along the way, located by byte offset,
+/// line and column.
+pub fn parse_mzml_with_report(
+    bytes: &[u8],
+    options: &ParseOptions,
+) -> Result<(MzML, ParseReport), ParseError> {
+    let (mzml, warnings) = parse_mzml_from(Cursor::new(bytes), options, true)?;
+    let mut report = ParseReport { warnings };
+    report.locate(bytes);
+    Ok((mzml, report))
+}
+
+/// Parses mzML from any reader. Gzip input (`.mzML.gz`) is recognised by its
+/// magic bytes and inflated while parsing, without buffering the document.
+pub fn parse_mzml_reader<R: Read>(reader: R) -> Result<MzML, ParseError> {
+    parse_mzml_from(
+        decompressing_reader(reader)?,
+        &ParseOptions::default(),
+        false,
+    )
+    .map(|(mzml, _)| mzml)
+}
+
+/// `parse_mzml_with_report` over any reader, inflating gzip input while
+/// parsing. Warning offsets are into the inflated document; as it is not
+/// kept, their line and column are left at 0.
+pub fn parse_mzml_reader_with_report<R: Read>(
+    reader: R,
+    options: &ParseOptions,
+) -> Result<(MzML, ParseReport), ParseError> {
+    let (mzml, warnings) = parse_mzml_from(decompressing_reader(reader)?, options, true)?;
+    Ok((mzml, ParseReport { warnings }))
+}
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[inline]
+pub fn is_gzip(bytes: &[u8]) -> bool {
+    bytes.starts_with(&GZIP_MAGIC)
+}
+
+/// Wraps `reader` so gzip members are inflated transparently; plain input
+/// is passed through unchanged. Useful with `MzMLReader::new`.
+pub fn decompressing_reader<'r, R: Read + 'r>(
+    reader: R,
+) -> Result<Box<dyn BufRead + 'r>, ParseError> {
+    let mut reader = BufReader::new(reader);
+    let head = reader
+        .fill_buf()
+        .map_err(|e| ParseError::Decompress(e.to_string()))?;
+    if is_gzip(head) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Inflates a whole `.mzML.gz` buffer, e.g. before `parse_mzml_parallel`.
+pub fn gunzip(bytes: &[u8]) -> Result<Vec<u8>, ParseError> {
+    let mut out = Vec::with_capacity(bytes.len().saturating_mul(4));
+    MultiGzDecoder::new(bytes)
+        .read_to_end(&mut out)
+        .map_err(|e| ParseError::Decompress(format!("gzip: {e}")))?;
+    Ok(out)
+}
+
+fn parse_mzml_from<R: BufRead>(
+    reader: R,
+    options: &ParseOptions,
+    collect_warnings: bool,
+) -> Result<(MzML, Vec<ParseWarning>), ParseError> {
+    let mut ws = ParsingWorkspace::new(Reader::from_reader(reader));
+    ws.strict = options.strict;
+    ws.preserve_unknown = options.preserve_unknown;
+    ws.collect_warnings = collect_warnings;
+    let mut mzml = MzML::default();
+    let mut inside_mzml = false;
+
+    while let Some(run_start) = parse_until_run(&mut ws, &mut mzml, &mut inside_mzml)? {
+        mzml.run = parse_run(&mut ws, &run_start)?;
+    }
+    ws.keep_unknown_elements(&UnknownMark::default(), &mut mzml.extensions);
+    Ok((mzml, ws.warnings))
+}
+
+/// Same result as `parse_mzml`, but spectra and chromatograms are parsed
+/// (XML, base64 and decompression) on the current rayon pool.
+///
+/// A serial pass walks the document, parsing the header and run metadata and
+/// finding the byte range of every `<spectrum>` and `<chromatogram>` without
+/// decoding them; the ranges are then parsed independently. In indexedmzML
+/// the ranges come from the `<indexList>` offsets and the lists are skipped
+/// unread. Without an index, or when it does not match a list, that list is
+/// scanned instead.
+pub fn parse_mzml_parallel(bytes: &[u8]) -> Result<MzML, ParseError> {
+    // An unreadable index only loses the shortcut; the lists are scanned.
+    let index = read_index_list(&mut Cursor::new(bytes)).ok().flatten();
+    let spectrum_offsets = index.as_ref().map_or(&[][..], |i| &i.spectrum[..]);
+    let chromatogram_offsets = index.as_ref().map_or(&[][..], |i| &i.chromatogram[..]);
     let mut ws = ParsingWorkspace::new(Reader::from_reader(Cursor::new(bytes)));
     let mut mzml = MzML::default();
     let mut inside_mzml = false;
 
+    while let Some(run_start) = parse_until_run(&mut ws, &mut mzml, &mut inside_mzml)? {
+        mzml.run = parse_run_with(
+            &mut ws,
+            &run_start,
+            |ws, start| {
+                let indexed = indexed_ranges(ws, bytes, start, spectrum_offsets, b"<spectrum")?;
+                let ranges = match indexed {
+                    Some(ranges) => ranges,
+                    None => element_ranges(ws, start, TagId::Spectrum)?,
+                };
+                Ok(SpectrumList {
+                    count: attr_usize(start, b"count"),
+                    default_data_processing_ref: attr(start, b"defaultDataProcessingRef"),
+                    spectra: parse_ranges(bytes, &ranges, parse_spectrum, empty_spectrum)?,
+                })
+            },
+            |ws, start| {
+                let indexed =
+                    indexed_ranges(ws, bytes, start, chromatogram_offsets, b"<chromatogram")?;
+                let ranges = match indexed {
+                    Some(ranges) => ranges,
+                    None => element_ranges(ws, start, TagId::Chromatogram)?,
+                };
+                Ok(ChromatogramList {
+                    count: attr_usize(start, b"count"),
+                    default_data_processing_ref: attr(start, b"defaultDataProcessingRef"),
+                    chromatograms: parse_ranges(
+                        bytes,
+                        &ranges,
+                        parse_chromatogram,
+                        empty_chromatogram,
+                    )?,
+                })
+            },
+        )?;
+    }
+    Ok(mzml)
+}
+
+/// Byte ranges of the list opened by `start`, one per index offset, each
+/// running to the next offset or to the list's end tag. The reader is moved
+/// past that end tag without tokenizing the contents.
+///
+/// `None`, with the reader untouched, unless the offsets are exactly the
+/// `element` start tags between the list's start and end tags, in order.
+/// The check is a byte search for `<`, so a stale index, or one that misses
+/// an entry, falls back to scanning.
+pub(super) fn indexed_ranges<R: BufRead>(
+    ws: &mut ParsingWorkspace<R>,
+    bytes: &[u8],
+    start: &BytesStart<'_>,
+    offsets: &[IndexOffset],
+    element: &[u8],
+) -> Result<Option<Vec<(usize, usize)>>, ParseError> {
+    if offsets.is_empty() {
+        return Ok(None);
+    }
+    let from = ws.xml_reader.buffer_position() as usize;
+    let mut closing = b"</".to_vec();
+    closing.extend_from_slice(start.name().as_ref());
+    let Some(end) = bytes[from..]
+        .windows(closing.len())
+        .position(|w| w == closing.as_slice())
+        .map(|at| from + at)
+    else {
+        return Ok(None);
+    };
+
+    let at_element = |at: &usize| {
+        let rest = &bytes[*at..end];
+        rest.starts_with(element)
+            && matches!(
+                rest.get(element.len()),
+                Some(b'>' | b'/' | b' ' | b'\t' | b'\r' | b'\n')
+            )
+    };
+    let tags = (from..end)
+        .filter(|&at| bytes[at] == b'<')
+        .filter(at_element);
+    let starts: Vec<usize> = offsets.iter().map(|o| o.offset as usize).collect();
+    if !tags.eq(starts.iter().copied()) {
+        return Ok(None);
+    }
+
+    ws.xml_reader.stream().consume(end - from);
+    match ws.next_event()? {
+        Event::End(e) if e.name().as_ref() == start.name().as_ref() => {}
+        _ => {
+            return Err(ParseError::UnexpectedEof {
+                context: String::from_utf8_lossy(start.name().as_ref()).into_owned(),
+                byte_offset: end as u64,
+            });
+        }
+    }
+
+    let ends = starts.iter().skip(1).copied().chain([end]);
+    Ok(Some(starts.iter().copied().zip(ends).collect()))
+}
+
+/// Byte ranges of the `element` children of the list opened by `start`.
+/// Other children are skipped, as in the serial list parsers.
+pub(super) fn element_ranges<R: BufRead>(
+    ws: &mut ParsingWorkspace<R>,
+    start: &BytesStart<'_>,
+    element: TagId,
+) -> Result<Vec<(usize, usize)>, ParseError> {
+    let closing = start.name().as_ref().to_vec();
+    let mut ranges = Vec::new();
+    loop {
+        let from = ws.xml_reader.buffer_position() as usize;
+        match ws.next_event()? {
+            Event::Start(e) => {
+                let is_element = tag_id_from_bytes(e.name().as_ref()) == element;
+                drain_until_close(ws, e.name().as_ref())?;
+                if is_element {
+                    ranges.push((from, ws.xml_reader.buffer_position() as usize));
+                }
+            }
+            Event::Empty(e) if tag_id_from_bytes(e.name().as_ref()) == element => {
+                ranges.push((from, ws.xml_reader.buffer_position() as usize));
+            }
+            Event::End(e) if e.name().as_ref() == closing.as_slice() => break Ok(ranges),
+            Event::Eof => {
+                break Err(ParseError::UnexpectedEof {
+                    context: String::from_utf8_lossy(&closing).into_owned(),
+                    byte_offset: ws.xml_reader.buffer_position(),
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+type ChunkWorkspace<'a> = ParsingWorkspace<Cursor<&'a [u8]>>;
+
+fn parse_ranges<'a, T: Send>(
+    bytes: &'a [u8],
+    ranges: &[(usize, usize)],
+    parse_open: fn(&mut ChunkWorkspace<'a>, &BytesStart<'_>) -> Result<T, ParseError>,
+    parse_empty: fn(&BytesStart<'_>) -> T,
+) -> Result<Vec<T>, ParseError> {
+    ranges
+        .par_iter()
+        .map(|&(from, to)| {
+            parse_chunk(&bytes[from..to], parse_open, parse_empty)
+                .map_err(|e| shift_offset(e, from as u64))
+        })
+        .collect()
+}
+
+fn parse_chunk<'a, T>(
+    chunk: &'a [u8],
+    parse_open: fn(&mut ChunkWorkspace<'a>, &BytesStart<'_>) -> Result<T, ParseError>,
+    parse_empty: fn(&BytesStart<'_>) -> T,
+) -> Result<T, ParseError> {
+    let mut ws = ParsingWorkspace::new(Reader::from_reader(Cursor::new(chunk)));
+    loop {
+        match ws.next_event()? {
+            Event::Start(e) => return parse_open(&mut ws, &e),
+            Event::Empty(e) => return Ok(parse_empty(&e)),
+            Event::Eof => {
+                return Err(ParseError::UnexpectedEof {
+                    context: "element range".to_string(),
+                    byte_offset: chunk.len() as u64,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Makes offsets reported inside a range absolute again.
+fn shift_offset(error: ParseError, by: u64) -> ParseError {
+    match error {
+        ParseError::UnexpectedEof {
+            context,
+            byte_offset,
+        } => ParseError::UnexpectedEof {
+            context,
+            byte_offset: byte_offset + by,
+        },
+        ParseError::UnexpectedTag { tag, byte_offset } => ParseError::UnexpectedTag {
+            tag,
+            byte_offset: byte_offset + by,
+        },
+        ParseError::InvalidValue {
+            context,
+            value,
+            byte_offset,
+        } => ParseError::InvalidValue {
+            context,
+            value,
+            byte_offset: byte_offset + by,
+        },
+        other => other,
+    }
+}
+
+const MZML_ATTRIBUTES: &[&[u8]] = &[
+    b"xmlns",
+    b"xmlns:xsi",
+    b"xsi:schemaLocation",
+    b"id",
+    b"version",
+];
+
+/// Fills the header elements of `mzml` and stops at the opening `<run>`
+/// tag, which is returned so the caller can parse the run eagerly or stream
+/// it. Returns `None` once `</mzML>` or the end of input is reached.
+pub(crate) fn parse_until_run<R: BufRead>(
+    ws: &mut ParsingWorkspace<R>,
+    mzml: &mut MzML,
+    inside_mzml: &mut bool,
+) -> Result<Option<BytesStart<'static>>, ParseError> {
     loop {
         let event = ws.next_event()?;
         match event {
             Event::Start(e) => {
                 let tid = tag_id_from_bytes(e.name().as_ref());
-                if !inside_mzml {
+                if !*inside_mzml {
                     if tid == TagId::MzML {
-                        inside_mzml = true;
+                        *inside_mzml = true;
+                        ws.push_path(&e);
+                        ws.keep_unknown_attributes(&e, MZML_ATTRIBUTES, &mut mzml.extensions);
                     }
                     continue;
                 }
+                ws.count_child(Some(e.name().as_ref()));
                 match tid {
-                    TagId::CvList => mzml.cv_list = Some(parse_cv_list(&mut ws, &e)?),
+                    TagId::CvList => {
+                        let cv_list = parse_cv_list(ws, &e)?;
+                        if ws.collect_warnings {
+                            ws.declared_cvs =
+                                Some(cv_list.cv.iter().map(|c| c.id.clone()).collect());
+                        }
+                        mzml.cv_list = Some(cv_list);
+                    }
                     TagId::FileDescription => {
-                        mzml.file_description = Some(parse_file_description(&mut ws, &e)?)
+                        mzml.file_description = Some(parse_file_description(ws, &e)?)
                     }
                     TagId::ReferenceableParamGroupList => {
                         mzml.referenceable_param_group_list =
-                            Some(parse_ref_param_group_list(&mut ws, &e)?)
+                            Some(parse_ref_param_group_list(ws, &e)?)
                     }
-                    TagId::SampleList => mzml.sample_list = Some(parse_sample_list(&mut ws, &e)?),
+                    TagId::SampleList => mzml.sample_list = Some(parse_sample_list(ws, &e)?),
                     TagId::InstrumentConfigurationList => {
-                        mzml.instrument_list = parse_instrument_list(&mut ws, &e)?
-                    }
-                    TagId::SoftwareList => {
-                        mzml.software_list = Some(parse_software_list(&mut ws, &e)?)
+                        mzml.instrument_list = parse_instrument_list(ws, &e)?
                     }
+                    TagId::SoftwareList => mzml.software_list = Some(parse_software_list(ws, &e)?),
                     TagId::DataProcessingList => {
-                        mzml.data_processing_list = Some(parse_data_processing_list(&mut ws, &e)?)
+                        mzml.data_processing_list = Some(parse_data_processing_list(ws, &e)?)
                     }
                     TagId::ScanSettingsList | TagId::AcquisitionSettingsList => {
-                        mzml.scan_settings_list = parse_scan_settings_list(&mut ws, &e)?
+                        mzml.scan_settings_list = parse_scan_settings_list(ws, &e)?
+                    }
+                    TagId::Run => break Ok(Some(e)),
+                    _ => {
+                        let name = e.name().as_ref().to_vec();
+                        ws.warn_skipped(&name, ws.element_offset());
+                        ws.skip_element(&name, Some(e))?;
                     }
-                    TagId::Run => mzml.run = parse_run(&mut ws, &e)?,
-                    _ => drain_until_close(&mut ws, e.name().as_ref())?,
                 }
             }
             Event::Empty(e) => {
-                if !inside_mzml {
+                if !*inside_mzml {
                     continue;
                 }
+                ws.count_child(Some(e.name().as_ref()));
                 match tag_id_from_bytes(e.name().as_ref()) {
                     TagId::ReferenceableParamGroupList => {
                         mzml.referenceable_param_group_list = Some(ReferenceableParamGroupList {
@@ -97,11 +462,21 @@ pub fn parse_mzml(bytes: &[u8]) -> Result<MzML, ParseError> {
                             ..Default::default()
                         })
                     }
-                    _ => {}
+                    _ => {
+                        ws.warn_skipped(e.name().as_ref(), ws.element_offset());
+                        ws.keep_empty(&e);
+                    }
                 }
             }
-            Event::End(e) if tag_id_from_bytes(e.name().as_ref()) == TagId::MzML => break Ok(mzml),
-            Event::Eof => break Ok(mzml),
+            Event::Comment(c) if *inside_mzml => {
+                ws.count_child(None);
+                ws.keep_comment(&c);
+            }
+            Event::End(e) if tag_id_from_bytes(e.name().as_ref()) == TagId::MzML => {
+                ws.pop_path();
+                break Ok(None);
+            }
+            Event::Eof => break Ok(None),
             _ => {}
         }
     }
@@ -0,0 +1,93 @@
+use serde::Serialize;
+use std::fmt::{Display, Formatter, Result};
+
+/// What the parser did about a problem it reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WarningAction {
+    /// The element or value was dropped.
+    Skipped,
+    /// Only the readable part was kept.
+    Truncated,
+    /// A default was used in place of a missing or unknown value.
+    Defaulted,
+    /// The value was kept unchanged.
+    Kept,
+}
+
+/// A recoverable problem found while parsing.
+///
+/// `byte_offset` is the start of the innermost element being parsed; `line`
+/// and `column` are 1-based and filled in by `parse_mzml_with_report`, and
+/// 0 when unknown.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParseWarning {
+    pub byte_offset: u64,
+    pub line: u64,
+    pub column: u64,
+    pub path: String,
+    pub action: WarningAction,
+    pub message: String,
+}
+
+impl Display for ParseWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let action = match self.action {
+            WarningAction::Skipped => "skipped",
+            WarningAction::Truncated => "truncated",
+            WarningAction::Defaulted => "defaulted",
+            WarningAction::Kept => "kept",
+        };
+        if self.line > 0 {
+            write!(f, "{}:{} ", self.line, self.column)?;
+        }
+        write!(
+            f,
+            "(byte {}) {}: {} [{action}]",
+            self.byte_offset, self.path, self.message
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ParseReport {
+    pub warnings: Vec<ParseWarning>,
+}
+
+impl ParseReport {
+    #[inline]
+    pub fn is_clean(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    /// Fills `line` and `column` from `byte_offset` using the parsed bytes.
+    pub(crate) fn locate(&mut self, bytes: &[u8]) {
+        let offsets: Vec<u64> = self.warnings.iter().map(|w| w.byte_offset).collect();
+        for (warning, (line, column)) in self.warnings.iter_mut().zip(line_columns(bytes, &offsets))
+        {
+            warning.line = line;
+            warning.column = column;
+        }
+    }
+}
+
+/// 1-based line and column of each byte offset into `bytes`.
+pub(crate) fn line_columns(bytes: &[u8], offsets: &[u64]) -> Vec<(u64, u64)> {
+    let mut order: Vec<usize> = (0..offsets.len()).collect();
+    order.sort_by_key(|&i| offsets[i]);
+
+    let mut located = vec![(0, 0); offsets.len()];
+    let (mut pos, mut line, mut line_start) = (0usize, 1u64, 0usize);
+    for i in order {
+        let target = (offsets[i] as usize).min(bytes.len());
+        for (at, &b) in bytes[pos..target].iter().enumerate() {
+            if b == b'\n' {
+                line += 1;
+                line_start = pos + at + 1;
+            }
+        }
+        pos = pos.max(target);
+        located[i] = (line, (target - line_start) as u64 + 1);
+    }
+    located
+}
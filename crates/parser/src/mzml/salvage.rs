@@ -0,0 +1,166 @@
+use std::io::Read;
+
+use crate::mzml::{
+    mzml_reader::{MzMLItem, MzMLReader},
+    parse_mzml::decompressing_reader,
+    structs::*,
+    utilities::ParseError,
+};
+
+/// Name of the `<userParam>` added to the run of a salvaged document. Its
+/// value is the byte offset just past the last complete element.
+pub const INCOMPLETE_RUN_PARAM: &str = "incomplete run";
+
+/// Result of `salvage_mzml`.
+#[derive(Debug)]
+pub struct SalvagedMzML {
+    pub mzml: MzML,
+    /// Byte offset just past the last complete element. For gzip input the
+    /// offset is into the inflated document.
+    pub last_good_offset: u64,
+    /// Why reading stopped early, or `None` when the document was complete.
+    pub error: Option<ParseError>,
+}
+
+impl SalvagedMzML {
+    #[inline]
+    pub fn is_complete(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Parses as much of a possibly truncated mzML document as possible.
+///
+/// Every spectrum and chromatogram that was closed before the input ended
+/// is kept; the partial one is dropped. When the document is incomplete the
+/// list counts are set to what was recovered and the run gets an
+/// `INCOMPLETE_RUN_PARAM` user param. Fails when the header before `<run>`
+/// cannot be parsed.
+pub fn salvage_mzml(bytes: &[u8]) -> Result<SalvagedMzML, ParseError> {
+    salvage_mzml_reader(bytes)
+}
+
+/// `salvage_mzml` over any reader. Gzip input is inflated while reading, so
+/// a cut-off `.mzML.gz` is salvaged too.
+pub fn salvage_mzml_reader<R: Read>(reader: R) -> Result<SalvagedMzML, ParseError> {
+    let mut reader = MzMLReader::new(decompressing_reader(reader)?)?;
+    let mut spectra = Vec::new();
+    let mut chromatograms = Vec::new();
+    let mut last_good_offset = reader.position();
+
+    let error = loop {
+        match reader.next_item() {
+            Ok(Some(MzMLItem::Spectrum(s))) => spectra.push(s),
+            Ok(Some(MzMLItem::Chromatogram(c))) => chromatograms.push(c),
+            Ok(None) => break None,
+            Err(e) => break Some(e),
+        }
+        last_good_offset = reader.position();
+    };
+
+    let mut mzml = reader.into_header();
+    let run = &mut mzml.run;
+    if let Some(list) = run.spectrum_list.as_mut() {
+        list.spectra = spectra;
+    }
+    if let Some(list) = run.chromatogram_list.as_mut() {
+        list.chromatograms = chromatograms;
+    }
+    if error.is_some() {
+        if let Some(list) = run.spectrum_list.as_mut() {
+            list.count = Some(list.spectra.len());
+        }
+        if let Some(list) = run.chromatogram_list.as_mut() {
+            list.count = Some(list.chromatograms.len());
+        }
+        run.user_params.push(UserParam {
+            name: INCOMPLETE_RUN_PARAM.to_string(),
+            r#type: Some("xsd:unsignedLong".to_string()),
+            value: Some(last_good_offset.to_string()),
+            ..Default::default()
+        });
+    }
+
+    Ok(SalvagedMzML {
+        mzml,
+        last_good_offset,
+        error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mzml::parse_mzml::parse_mzml;
+
+    const PATH: &str = "data/mzml/tiny.pwiz.mzML0.99.10.mzML";
+
+    fn spectrum_ids(mzml: &MzML) -> Vec<&str> {
+        let list = mzml.run.spectrum_list.as_ref().unwrap();
+        list.spectra.iter().map(|s| s.id.as_str()).collect()
+    }
+
+    #[test]
+    fn complete_document_is_returned_unchanged() {
+        let bytes = std::fs::read(PATH).unwrap();
+        let salvaged = salvage_mzml(&bytes).unwrap();
+        assert!(salvaged.is_complete());
+
+        let full = parse_mzml(&bytes).unwrap();
+        assert_eq!(spectrum_ids(&salvaged.mzml), spectrum_ids(&full));
+        assert_eq!(
+            salvaged.mzml.run.user_params.len(),
+            full.run.user_params.len()
+        );
+    }
+
+    #[test]
+    fn truncated_spectrum_is_dropped_and_run_marked() {
+        let bytes = std::fs::read(PATH).unwrap();
+        let text = std::str::from_utf8(&bytes).unwrap();
+        let first_end = text.find("</spectrum>").unwrap() + "</spectrum>".len();
+        let second_end = first_end + text[first_end..].find("</spectrum>").unwrap();
+        let cut = &bytes[..second_end - 20];
+
+        let salvaged = salvage_mzml(cut).unwrap();
+        assert!(!salvaged.is_complete());
+        assert_eq!(salvaged.last_good_offset, first_end as u64);
+
+        let run = &salvaged.mzml.run;
+        assert_eq!(spectrum_ids(&salvaged.mzml), ["S19"]);
+        assert_eq!(run.spectrum_list.as_ref().unwrap().count, Some(1));
+        let param = run.user_params.last().unwrap();
+        assert_eq!(param.name, INCOMPLETE_RUN_PARAM);
+        assert_eq!(param.value, Some(first_end.to_string()));
+    }
+
+    #[test]
+    fn truncated_gzip_document_is_salvaged() {
+        use flate2::{Compression, write::GzEncoder};
+        use std::io::Write;
+
+        let bytes = std::fs::read(PATH).unwrap();
+        let text = std::str::from_utf8(&bytes).unwrap();
+        let last_end = text.rfind("</spectrum>").unwrap() + "</spectrum>".len();
+
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(&bytes[..last_end + 40]).unwrap();
+        let gz = gz.finish().unwrap();
+
+        let salvaged = salvage_mzml(&gz).unwrap();
+        assert!(!salvaged.is_complete());
+        assert_eq!(salvaged.last_good_offset, last_end as u64);
+        assert_eq!(
+            spectrum_ids(&salvaged.mzml),
+            spectrum_ids(&parse_mzml(&bytes).unwrap())
+        );
+    }
+
+    #[test]
+    fn input_cut_inside_the_header_is_an_error() {
+        let bytes = std::fs::read(PATH).unwrap();
+        let text = std::str::from_utf8(&bytes).unwrap();
+        let cv_list = text.find("<cvList").unwrap();
+        assert!(salvage_mzml(&bytes[..cv_list + 40]).is_err());
+    }
+}
@@ -190,9 +190,78 @@ impl TagId {
         }
     }
 
+    /// Element name of the tag; the inverse of `from_xml_tag`.
+    #[inline]
+    pub(crate) fn xml_tag(self) -> &'static str {
+        match self {
+            TagId::MzML => "mzML",
+            TagId::FileContent => "fileContent",
+            TagId::SourceFile => "sourceFile",
+            TagId::Contact => "contact",
+            TagId::ReferenceableParamGroup => "referenceableParamGroup",
+            TagId::Sample => "sample",
+            TagId::Instrument => "instrumentConfiguration",
+            TagId::ComponentSource => "source",
+            TagId::ComponentAnalyzer => "analyzer",
+            TagId::ComponentDetector => "detector",
+            TagId::Software => "software",
+            TagId::ProcessingMethod => "processingMethod",
+            TagId::ScanSettings => "scanSettings",
+            TagId::Target => "target",
+            TagId::Run => "run",
+            TagId::Spectrum => "spectrum",
+            TagId::SpectrumDescription => "spectrumDescription",
+            TagId::Scan => "scan",
+            TagId::ScanWindow => "scanWindow",
+            TagId::Precursor => "precursor",
+            TagId::IsolationWindow => "isolationWindow",
+            TagId::SelectedIon => "selectedIon",
+            TagId::Activation => "activation",
+            TagId::Product => "product",
+            TagId::BinaryDataArray => "binaryDataArray",
+            TagId::Chromatogram => "chromatogram",
+            TagId::FileDescription => "fileDescription",
+            TagId::SourceFileList => "sourceFileList",
+            TagId::SourceFileRef => "sourceFileRef",
+            TagId::SourceFileRefList => "sourceFileRefList",
+            TagId::ReferenceableParamGroupList => "referenceableParamGroupList",
+            TagId::ReferenceableParamGroupRef => "referenceableParamGroupRef",
+            TagId::SampleList => "sampleList",
+            TagId::InstrumentConfigurationList => "instrumentConfigurationList",
+            TagId::ComponentList => "componentList",
+            TagId::SoftwareList => "softwareList",
+            TagId::SoftwareParam => "softwareParam",
+            TagId::SoftwareRef => "softwareRef",
+            TagId::DataProcessing => "dataProcessing",
+            TagId::DataProcessingList => "dataProcessingList",
+            TagId::ScanSettingsList => "scanSettingsList",
+            TagId::AcquisitionSettings => "acquisitionSettings",
+            TagId::AcquisitionSettingsList => "acquisitionSettingsList",
+            TagId::TargetList => "targetList",
+            TagId::SpectrumList => "spectrumList",
+            TagId::ScanList => "scanList",
+            TagId::ScanWindowList => "scanWindowList",
+            TagId::PrecursorList => "precursorList",
+            TagId::SelectedIonList => "selectedIonList",
+            TagId::ProductList => "productList",
+            TagId::BinaryDataArrayList => "binaryDataArrayList",
+            TagId::Binary => "binary",
+            TagId::ChromatogramList => "chromatogramList",
+            TagId::CvParam => "cvParam",
+            TagId::UserParam => "userParam",
+            TagId::CvList => "cvList",
+            TagId::Cv => "cv",
+            TagId::IndexList => "indexList",
+            TagId::IndexListOffset => "indexListOffset",
+            TagId::FileChecksum => "fileChecksum",
+            TagId::IndexedmzML => "indexedmzML",
+            TagId::Unknown => "",
+        }
+    }
+
     #[inline]
     pub(crate) fn from_u8(b: u8) -> Option<TagId> {
-        const MAX_TAG: u8 = TagId::Cv as u8;
+        const MAX_TAG: u8 = TagId::MzML as u8;
         match b {
             0..=MAX_TAG | 255 => Some(TagId::from(b)),
             _ => None,
@@ -2,6 +2,7 @@ use serde::{Deserialize, Serialize};
 
 /// <mzML>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct MzML {
     pub cv_list: Option<CvList>,
     pub file_description: Option<FileDescription>,
@@ -12,10 +13,14 @@ pub struct MzML {
     pub data_processing_list: Option<DataProcessingList>,
     pub scan_settings_list: Option<ScanSettingsList>,
     pub run: Run,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<Extensions>,
 }
 
 /// <cvList>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct CvList {
     pub count: Option<usize>,
     pub cv: Vec<CvEntry>,
@@ -53,6 +58,7 @@ pub struct IndexedmzML {
 
 /// <cvParam>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct CvParam {
     pub cv_ref: Option<String>,
     pub accession: Option<String>,
@@ -65,6 +71,7 @@ pub struct CvParam {
 
 /// <userParam>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct UserParam {
     pub name: String,
     pub r#type: Option<String>,
@@ -74,14 +81,41 @@ pub struct UserParam {
     pub value: Option<String>,
 }
 
+/// XML the parser does not model, kept when parsing with
+/// `ParseOptions::preserve_unknown`. `attributes` are the owner's extra
+/// attributes (unescaped); `elements` are skipped subtrees and comments
+/// found anywhere inside the owner, in document order.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct Extensions {
+    pub attributes: Vec<(String, String)>,
+    pub elements: Vec<ExtensionElement>,
+}
+
+/// An unmodelled element or comment and where it was found.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct ExtensionElement {
+    /// The element it was a child of, as steps from the owner such as
+    /// `precursorList[1]/precursor[2]`; empty for the owner itself. Names
+    /// are those of mzML 1.1 and indices count same-named siblings from 1.
+    pub parent: String,
+    /// Number of child elements and comments of `parent` before it.
+    pub position: usize,
+    /// Raw XML.
+    pub xml: String,
+}
+
 /// <referenceableParamGroupRef>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct ReferenceableParamGroupRef {
     pub r#ref: String,
 }
 
 /// <dataProcessingList>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct DataProcessingList {
     pub count: Option<usize>,
     pub data_processing: Vec<DataProcessing>,
@@ -89,6 +123,7 @@ pub struct DataProcessingList {
 
 /// <dataProcessing>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct DataProcessing {
     pub id: String,
     pub software_ref: Option<String>,
@@ -97,6 +132,7 @@ pub struct DataProcessing {
 
 /// <processingMethod>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct ProcessingMethod {
     pub order: Option<u32>,
     pub software_ref: Option<String>,
@@ -107,6 +143,7 @@ pub struct ProcessingMethod {
 
 /// <fileDescription>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct FileDescription {
     pub file_content: FileContent,
     pub source_file_list: SourceFileList,
@@ -115,6 +152,7 @@ pub struct FileDescription {
 
 /// <sourceFileList>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct SourceFileList {
     pub count: Option<usize>,
     pub source_file: Vec<SourceFile>,
@@ -122,6 +160,7 @@ pub struct SourceFileList {
 
 /// <sourceFile>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct SourceFile {
     pub id: String,
     pub name: String,
@@ -133,6 +172,7 @@ pub struct SourceFile {
 
 /// <fileContent>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct FileContent {
     pub referenceable_param_group_refs: Vec<ReferenceableParamGroupRef>,
     pub cv_params: Vec<CvParam>,
@@ -141,6 +181,7 @@ pub struct FileContent {
 
 /// <contact>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Contact {
     pub referenceable_param_group_refs: Vec<ReferenceableParamGroupRef>,
     pub cv_params: Vec<CvParam>,
@@ -149,6 +190,7 @@ pub struct Contact {
 
 /// <instrumentList> / <instrumentConfigurationList>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct InstrumentList {
     pub count: Option<usize>,
     pub instrument: Vec<Instrument>,
@@ -156,6 +198,7 @@ pub struct InstrumentList {
 
 /// <instrument> / <instrumentConfiguration>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Instrument {
     pub id: String,
     pub scan_settings_ref: Option<ScanSettingsRef>,
@@ -168,12 +211,14 @@ pub struct Instrument {
 
 /// attribute scanSettingsRef
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct ScanSettingsRef {
     pub r#ref: String,
 }
 
 /// <componentList>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct ComponentList {
     pub count: Option<usize>,
     pub source: Vec<Source>,
@@ -183,6 +228,7 @@ pub struct ComponentList {
 
 /// <source>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Source {
     pub order: Option<u32>,
     pub referenceable_param_group_ref: Vec<ReferenceableParamGroupRef>,
@@ -192,6 +238,7 @@ pub struct Source {
 
 /// <analyzer>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Analyzer {
     pub order: Option<u32>,
     pub referenceable_param_group_ref: Vec<ReferenceableParamGroupRef>,
@@ -201,6 +248,7 @@ pub struct Analyzer {
 
 /// <detector>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Detector {
     pub order: Option<u32>,
     pub referenceable_param_group_ref: Vec<ReferenceableParamGroupRef>,
@@ -210,12 +258,14 @@ pub struct Detector {
 
 /// <instrumentSoftwareRef> / <softwareRef>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct InstrumentSoftwareRef {
     pub r#ref: String,
 }
 
 /// <referenceableParamGroupList>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct ReferenceableParamGroupList {
     pub count: Option<usize>,
     pub referenceable_param_groups: Vec<ReferenceableParamGroup>,
@@ -223,6 +273,7 @@ pub struct ReferenceableParamGroupList {
 
 /// <referenceableParamGroup>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct ReferenceableParamGroup {
     pub id: String,
     pub cv_params: Vec<CvParam>,
@@ -231,6 +282,7 @@ pub struct ReferenceableParamGroup {
 
 /// <sampleList>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct SampleList {
     pub count: Option<u32>,
     pub samples: Vec<Sample>,
@@ -240,6 +292,7 @@ pub struct SampleList {
 
 /// <sample>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Sample {
     pub id: String,
     pub name: String,
@@ -250,6 +303,7 @@ pub struct Sample {
 
 /// <scanSettingsList> / <acquisitionSettingsList>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct ScanSettingsList {
     pub count: Option<usize>,
     pub scan_settings: Vec<ScanSettings>,
@@ -257,6 +311,7 @@ pub struct ScanSettingsList {
 
 /// <scanSettings> / <acquisitionSettings>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct ScanSettings {
     pub id: Option<String>,
     pub instrument_configuration_ref: Option<String>,
@@ -269,6 +324,7 @@ pub struct ScanSettings {
 
 /// <sourceFileRefList>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct SourceFileRefList {
     pub count: Option<usize>,
     pub source_file_refs: Vec<SourceFileRef>,
@@ -276,12 +332,14 @@ pub struct SourceFileRefList {
 
 /// <sourceFileRef>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct SourceFileRef {
     pub r#ref: String,
 }
 
 /// <targetList>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct TargetList {
     pub count: Option<usize>,
     pub targets: Vec<Target>,
@@ -289,6 +347,7 @@ pub struct TargetList {
 
 /// <target>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Target {
     pub referenceable_param_group_refs: Vec<ReferenceableParamGroupRef>,
     pub cv_params: Vec<CvParam>,
@@ -297,6 +356,7 @@ pub struct Target {
 
 /// <softwareList>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct SoftwareList {
     pub count: Option<usize>,
     pub software: Vec<Software>,
@@ -304,6 +364,7 @@ pub struct SoftwareList {
 
 /// <software>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Software {
     pub id: String,
     pub version: Option<String>,
@@ -314,6 +375,7 @@ pub struct Software {
 
 /// <softwareParam>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct SoftwareParam {
     pub cv_ref: Option<String>,
     pub accession: String,
@@ -323,6 +385,7 @@ pub struct SoftwareParam {
 
 /// <run>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Run {
     pub id: String,
     pub start_time_stamp: Option<String>,
@@ -337,10 +400,14 @@ pub struct Run {
     pub source_file_ref_list: Option<SourceFileRefList>,
     pub spectrum_list: Option<SpectrumList>,
     pub chromatogram_list: Option<ChromatogramList>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<Extensions>,
 }
 
 /// <spectrumList>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct SpectrumList {
     pub count: Option<usize>,
     pub default_data_processing_ref: Option<String>,
@@ -349,6 +416,7 @@ pub struct SpectrumList {
 
 /// <spectrumDescription> (1.0.0)
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct SpectrumDescription {
     pub referenceable_param_group_refs: Vec<ReferenceableParamGroupRef>,
     pub cv_params: Vec<CvParam>,
@@ -361,6 +429,7 @@ pub struct SpectrumDescription {
 
 /// <scanList>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct ScanList {
     pub count: Option<usize>,
     pub cv_params: Vec<CvParam>,
@@ -370,6 +439,7 @@ pub struct ScanList {
 
 /// <scan>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Scan {
     pub instrument_configuration_ref: Option<String>,
     pub external_spectrum_id: Option<String>,
@@ -385,6 +455,7 @@ pub struct Scan {
 
 /// <scanWindowList> / <selectionWindowList>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct ScanWindowList {
     pub count: Option<usize>,
     pub scan_windows: Vec<ScanWindow>,
@@ -392,6 +463,7 @@ pub struct ScanWindowList {
 
 /// <scanWindow> / <selectionWindow>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct ScanWindow {
     pub cv_params: Vec<CvParam>,
     pub user_params: Vec<UserParam>,
@@ -399,6 +471,7 @@ pub struct ScanWindow {
 
 /// <precursorList>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct PrecursorList {
     pub count: Option<usize>,
     pub cv_params: Vec<CvParam>,
@@ -408,6 +481,7 @@ pub struct PrecursorList {
 
 /// <precursor>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Precursor {
     pub spectrum_ref: Option<String>,
     pub source_file_ref: Option<String>,
@@ -420,6 +494,7 @@ pub struct Precursor {
 
 /// <isolationWindow>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct IsolationWindow {
     pub referenceable_param_group_refs: Vec<ReferenceableParamGroupRef>,
     pub cv_params: Vec<CvParam>,
@@ -428,6 +503,7 @@ pub struct IsolationWindow {
 
 /// <selectedIonList>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct SelectedIonList {
     pub count: Option<usize>,
     pub selected_ions: Vec<SelectedIon>,
@@ -435,6 +511,7 @@ pub struct SelectedIonList {
 
 /// <selectedIon>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct SelectedIon {
     pub referenceable_param_group_refs: Vec<ReferenceableParamGroupRef>,
     pub cv_params: Vec<CvParam>,
@@ -443,6 +520,7 @@ pub struct SelectedIon {
 
 /// <activation>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Activation {
     pub referenceable_param_group_refs: Vec<ReferenceableParamGroupRef>,
     pub cv_params: Vec<CvParam>,
@@ -451,6 +529,7 @@ pub struct Activation {
 
 /// <productList>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct ProductList {
     pub count: Option<usize>,
     pub products: Vec<Product>,
@@ -460,6 +539,7 @@ pub struct ProductList {
 
 /// <product>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Product {
     pub spectrum_ref: Option<String>,
     pub source_file_ref: Option<String>,
@@ -471,6 +551,7 @@ pub struct Product {
 
 /// <binaryDataArrayList>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct BinaryDataArrayList {
     pub count: Option<usize>,
     pub binary_data_arrays: Vec<BinaryDataArray>,
@@ -500,6 +581,7 @@ pub enum BinaryData {
 
 /// <binaryDataArray>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct BinaryDataArray {
     pub array_length: Option<usize>,
     pub encoded_length: Option<usize>,
@@ -515,6 +597,7 @@ pub struct BinaryDataArray {
 
 /// <chromatogramList>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct ChromatogramList {
     pub count: Option<usize>,
     pub default_data_processing_ref: Option<String>,
@@ -523,6 +606,7 @@ pub struct ChromatogramList {
 
 /// <chromatogram>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Chromatogram {
     pub id: String,
     pub native_id: Option<String>,
@@ -538,10 +622,14 @@ pub struct Chromatogram {
     pub product: Option<Product>,
 
     pub binary_data_array_list: Option<BinaryDataArrayList>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<Extensions>,
 }
 
 /// <spectrum>
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Spectrum {
     // Attributes
     pub id: String,
@@ -567,4 +655,7 @@ pub struct Spectrum {
     pub precursor_list: Option<PrecursorList>,
     pub product_list: Option<ProductList>,
     pub binary_data_array_list: Option<BinaryDataArrayList>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<Extensions>,
 }
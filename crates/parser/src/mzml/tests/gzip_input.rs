@@ -0,0 +1,84 @@
+use flate2::{Compression, write::GzEncoder};
+use std::io::Write;
+
+use crate::mzml::{
+    mzml_reader::{MzMLItem, MzMLReader},
+    parse_mzml::{
+        ParseOptions, decompressing_reader, gunzip, is_gzip, parse_mzml, parse_mzml_reader,
+        parse_mzml_reader_with_report, parse_mzml_with_report,
+    },
+};
+
+const PATH: &str = "data/mzml/tiny.pwiz.mzML0.99.10.mzML";
+
+fn gzip(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn json<T: serde::Serialize>(value: &T) -> serde_json::Value {
+    serde_json::to_value(value).unwrap()
+}
+
+#[test]
+fn gzip_input_parses_like_plain_input() {
+    let plain = std::fs::read(PATH).unwrap();
+    let compressed = gzip(&plain);
+    assert!(is_gzip(&compressed));
+    assert!(!is_gzip(&plain));
+
+    let want = json(&parse_mzml(&plain).unwrap());
+    assert_eq!(json(&parse_mzml_reader(&compressed[..]).unwrap()), want);
+    assert_eq!(json(&parse_mzml_reader(&plain[..]).unwrap()), want);
+    assert_eq!(gunzip(&compressed).unwrap(), plain);
+}
+
+#[test]
+fn gzip_report_keeps_offsets_without_lines() {
+    let plain = std::fs::read("data/mzml/test.mzML").unwrap();
+    let options = ParseOptions {
+        strict: false,
+        preserve_unknown: false,
+    };
+    let (want, plain_report) = parse_mzml_with_report(&plain, &options).unwrap();
+    let (got, report) = parse_mzml_reader_with_report(&gzip(&plain)[..], &options).unwrap();
+    assert_eq!(json(&got), json(&want));
+    assert!(!report.is_clean());
+    for (a, b) in report.warnings.iter().zip(&plain_report.warnings) {
+        assert_eq!(a.byte_offset, b.byte_offset);
+        assert_eq!((a.line, a.column), (0, 0));
+        assert!(a.to_string().starts_with("(byte "));
+    }
+}
+
+#[test]
+fn concatenated_gzip_members_are_read_in_full() {
+    let plain = std::fs::read(PATH).unwrap();
+    let (head, tail) = plain.split_at(plain.len() / 2);
+    let mut compressed = gzip(head);
+    compressed.extend(gzip(tail));
+    assert_eq!(gunzip(&compressed).unwrap(), plain);
+}
+
+#[test]
+fn streaming_reader_accepts_gzip() {
+    let plain = std::fs::read(PATH).unwrap();
+    let compressed = gzip(&plain);
+    let reader = MzMLReader::new(decompressing_reader(&compressed[..]).unwrap()).unwrap();
+    let spectra = reader
+        .filter_map(|item| match item.unwrap() {
+            MzMLItem::Spectrum(s) => Some(s.id),
+            MzMLItem::Chromatogram(_) => None,
+        })
+        .count();
+    let full = parse_mzml(&plain).unwrap();
+    assert_eq!(spectra, full.run.spectrum_list.unwrap().spectra.len());
+}
+
+#[test]
+fn corrupt_gzip_is_a_decompress_error() {
+    let mut compressed = gzip(&std::fs::read(PATH).unwrap());
+    compressed.truncate(compressed.len() / 2);
+    assert!(gunzip(&compressed).is_err());
+}
@@ -0,0 +1,166 @@
+use crate::mzml::{
+    parse_mzml::{ParseOptions, parse_mzml, parse_mzml_with_report},
+    parse_report::{ParseReport, WarningAction},
+    structs::*,
+};
+
+/// `[1.0, 2.0, 3.0]` as little-endian doubles.
+const VALUES_B64: &str = "AAAAAAAA8D8AAAAAAAAAQAAAAAAAAAhA";
+
+const BDA_PATH: &str = "/mzML/run/spectrumList/spectrum/binaryDataArrayList/binaryDataArray";
+
+const LENIENT: ParseOptions = ParseOptions {
+    strict: false,
+    preserve_unknown: false,
+};
+
+/// One spectrum with a single m/z array. `extra` goes before the array
+/// list, `type_param` names the binary data type.
+fn document(extra: &str, array_length: usize, type_param: &str, binary: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<mzML version="1.1.0">
+  <cvList count="1">
+    <cv id="MS" fullName="PSI-MS"/>
+  </cvList>
+  <run id="r">
+    <spectrumList count="1">
+      <spectrum index="0" id="s0" defaultArrayLength="3">{extra}
+        <binaryDataArrayList count="1">
+          <binaryDataArray arrayLength="{array_length}" encodedLength="{}">
+            {type_param}
+            <cvParam cvRef="MS" accession="MS:1000514" name="m/z array"/>
+            <binary>{binary}</binary>
+          </binaryDataArray>
+        </binaryDataArrayList>
+      </spectrum>
+    </spectrumList>
+  </run>
+</mzML>"#,
+        binary.len()
+    )
+}
+
+const FLOAT64: &str = r#"<cvParam cvRef="MS" accession="MS:1000523" name="64-bit float"/>"#;
+
+fn mz_values(mzml: &MzML) -> &[f64] {
+    let spectrum = &mzml.run.spectrum_list.as_ref().unwrap().spectra[0];
+    let list = spectrum.binary_data_array_list.as_ref().unwrap();
+    match list.binary_data_arrays[0].binary.as_ref().unwrap() {
+        BinaryData::F64(v) => v,
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+fn lenient(xml: &str) -> (MzML, ParseReport) {
+    parse_mzml_with_report(xml.as_bytes(), &LENIENT).unwrap()
+}
+
+/// 1-based line and column of the first `needle` in `xml`.
+fn position_of(xml: &str, needle: &str) -> (u64, u64) {
+    let at = xml.find(needle).unwrap();
+    let line = xml[..at].matches('\n').count() as u64 + 1;
+    let column = (at - xml[..at].rfind('\n').map_or(0, |n| n + 1)) as u64 + 1;
+    (line, column)
+}
+
+#[test]
+fn well_formed_document_has_a_clean_report() {
+    let xml = document("", 3, FLOAT64, VALUES_B64);
+    let (mzml, report) = lenient(&xml);
+    assert!(report.is_clean(), "{:?}", report.warnings);
+    assert_eq!(mz_values(&mzml), [1.0, 2.0, 3.0]);
+}
+
+#[test]
+fn truncated_base64_fails_strict_and_is_recovered_lenient() {
+    let broken = format!("{}*", &VALUES_B64[..22]);
+    let xml = document("", 3, FLOAT64, &broken);
+
+    assert!(parse_mzml(xml.as_bytes()).is_err());
+
+    let (mzml, report) = lenient(&xml);
+    assert_eq!(mz_values(&mzml), [1.0]);
+
+    let first = &report.warnings[0];
+    assert_eq!(first.action, WarningAction::Truncated);
+    assert_eq!(first.path, BDA_PATH);
+    assert!(
+        first.message.contains("invalid base64"),
+        "{}",
+        first.message
+    );
+    assert_eq!(
+        first.byte_offset,
+        xml.find("<binaryDataArray ").unwrap() as u64
+    );
+    assert_eq!(
+        (first.line, first.column),
+        position_of(&xml, "<binaryDataArray ")
+    );
+
+    // The shortened array no longer matches `arrayLength`.
+    assert_eq!(report.warnings.len(), 2);
+    assert!(report.warnings[1].message.contains("arrayLength is 3"));
+}
+
+#[test]
+fn array_length_mismatch_is_reported() {
+    let xml = document("", 4, FLOAT64, VALUES_B64);
+    let (mzml, report) = lenient(&xml);
+    assert_eq!(mz_values(&mzml), [1.0, 2.0, 3.0]);
+    assert_eq!(report.warnings.len(), 1);
+    assert_eq!(report.warnings[0].action, WarningAction::Truncated);
+    assert_eq!(
+        report.warnings[0].message,
+        "arrayLength is 4 but 3 values were decoded; kept 3"
+    );
+}
+
+#[test]
+fn undeclared_cv_ref_is_reported_and_kept() {
+    let param = r#"<cvParam cvRef="XX" accession="XX:0000001" name="custom"/>"#;
+    let xml = document(param, 3, FLOAT64, VALUES_B64);
+    let (mzml, report) = lenient(&xml);
+
+    let spectrum = &mzml.run.spectrum_list.as_ref().unwrap().spectra[0];
+    assert!(
+        spectrum
+            .cv_params
+            .iter()
+            .any(|p| p.accession.as_deref() == Some("XX:0000001"))
+    );
+
+    assert_eq!(report.warnings.len(), 1);
+    let warning = &report.warnings[0];
+    assert_eq!(warning.action, WarningAction::Kept);
+    assert_eq!(warning.path, "/mzML/run/spectrumList/spectrum/cvParam");
+    assert_eq!(
+        (warning.line, warning.column),
+        position_of(&xml, r#"<cvParam cvRef="XX""#)
+    );
+}
+
+#[test]
+fn missing_numeric_type_defaults_to_float64() {
+    let xml = document("", 3, "", VALUES_B64);
+    let (mzml, report) = lenient(&xml);
+    assert_eq!(mz_values(&mzml), [1.0, 2.0, 3.0]);
+    assert_eq!(report.warnings.len(), 1);
+    assert_eq!(report.warnings[0].action, WarningAction::Defaulted);
+    assert_eq!(report.warnings[0].path, BDA_PATH);
+}
+
+#[test]
+fn unexpected_elements_are_reported_as_skipped() {
+    let xml = document("<vendorBlob><x/></vendorBlob>", 3, FLOAT64, VALUES_B64);
+    let (_, report) = lenient(&xml);
+    assert_eq!(report.warnings.len(), 1);
+    let warning = &report.warnings[0];
+    assert_eq!(warning.action, WarningAction::Skipped);
+    assert_eq!(warning.path, "/mzML/run/spectrumList/spectrum/vendorBlob");
+    assert_eq!(
+        warning.byte_offset,
+        xml.find("<vendorBlob>").unwrap() as u64
+    );
+}
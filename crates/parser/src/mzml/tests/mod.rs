@@ -1,8 +1,14 @@
+mod gzip_input;
+mod lenient_parse;
+mod parallel_parse;
+mod preserve_unknown;
+mod streaming_write;
 mod test_mzml;
 mod tiny_msdata_mzml0_99_10;
 mod tiny_msdata_mzml0_99_9;
 mod tiny_pwiz_mzml0_99_10;
 mod tiny_pwiz_mzml0_99_9;
+mod write_options;
 
 mod tiny1_mzml0_99_0;
 
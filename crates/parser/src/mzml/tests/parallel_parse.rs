@@ -0,0 +1,112 @@
+use quick_xml::{Reader, events::Event};
+use std::io::Cursor;
+
+use crate::mzml::{
+    bin_to_mzml::{MzmlWriteOptions, write_mzml_with_options},
+    indexed_mzml_reader::read_index_list,
+    parse_mzml::{element_ranges, indexed_ranges, parse_mzml, parse_mzml_parallel},
+    schema::TagId,
+    structs::IndexOffset,
+    utilities::ParsingWorkspace,
+};
+
+const PATHS: &[&str] = &[
+    "data/mzml/test.mzML",
+    "data/mzml/tiny.msdata.mzML0.99.10.mzML",
+    "data/mzml/tiny.msdata.mzML0.99.9.mzML",
+    "data/mzml/tiny.pwiz.mzML0.99.10.mzML",
+    "data/mzml/tiny.pwiz.mzML0.99.9.mzML",
+    "data/mzml/tiny1.mzML0.99.0.mzML",
+    "data/mzml/tiny1.mzML0.99.1.mzML",
+    "data/mzml/tiny2_SRM.mzML0.99.0.mzML",
+    "data/mzml/tiny2_SRM.mzML0.99.1.mzML",
+    "data/mzml/tiny4_LTQ-FT.mzML0.99.0.mzML",
+    "data/mzml/tiny4_LTQ-FT.mzML0.99.1.mzML",
+];
+
+#[test]
+fn parallel_parse_matches_serial_parse() {
+    for path in PATHS {
+        let bytes = std::fs::read(path).unwrap();
+        let serial = serde_json::to_value(parse_mzml(&bytes).unwrap()).unwrap();
+        let parallel = serde_json::to_value(parse_mzml_parallel(&bytes).unwrap()).unwrap();
+        assert_eq!(parallel, serial, "{path}");
+    }
+}
+
+#[test]
+fn parallel_parse_reports_truncated_spectrum() {
+    let xml = br#"<mzML><run id="r"><spectrumList count="2">
+        <spectrum id="a" index="0" defaultArrayLength="0"></spectrum>
+        <spectrum id="b" index="1">"#;
+    assert!(parse_mzml_parallel(xml).is_err());
+}
+
+/// Ranges of the spectrum list found through `offsets`, or by scanning when
+/// `offsets` is `None`, trimmed so both ways can be compared, and where the
+/// reader was left.
+fn spectrum_ranges<'a>(
+    bytes: &'a [u8],
+    offsets: Option<&[IndexOffset]>,
+) -> Option<(Vec<&'a [u8]>, u64)> {
+    let mut ws = ParsingWorkspace::new(Reader::from_reader(Cursor::new(bytes)));
+    let start = loop {
+        match ws.next_event().unwrap() {
+            Event::Start(e) if e.name().as_ref() == b"spectrumList" => break e,
+            Event::Eof => panic!("no spectrumList"),
+            _ => {}
+        }
+    };
+    let ranges = match offsets {
+        Some(offsets) => indexed_ranges(&mut ws, bytes, &start, offsets, b"<spectrum").unwrap()?,
+        None => element_ranges(&mut ws, &start, TagId::Spectrum).unwrap(),
+    };
+    let chunks = ranges
+        .iter()
+        .map(|&(from, to)| bytes[from..to].trim_ascii());
+    Some((chunks.collect(), ws.xml_reader.buffer_position()))
+}
+
+/// `path` rewritten as indexedmzML, so its offsets are current.
+fn indexed(path: &str) -> Vec<u8> {
+    let mzml = parse_mzml(&std::fs::read(path).unwrap()).unwrap();
+    let options = MzmlWriteOptions {
+        indexed: true,
+        ..MzmlWriteOptions::default()
+    };
+    write_mzml_with_options(Vec::new(), &mzml, &options).unwrap()
+}
+
+#[test]
+fn index_offsets_give_the_scanned_ranges() {
+    for path in PATHS {
+        let bytes = indexed(path);
+        let index = read_index_list(&mut Cursor::new(&bytes)).unwrap().unwrap();
+        let found = spectrum_ranges(&bytes, Some(&index.spectrum)).unwrap();
+        assert_eq!(found, spectrum_ranges(&bytes, None).unwrap(), "{path}");
+
+        let serial = serde_json::to_value(parse_mzml(&bytes).unwrap()).unwrap();
+        let parallel = serde_json::to_value(parse_mzml_parallel(&bytes).unwrap()).unwrap();
+        assert_eq!(parallel, serial, "{path}");
+    }
+}
+
+#[test]
+fn stale_index_falls_back_to_scanning() {
+    let bytes = indexed("data/mzml/tiny.pwiz.mzML0.99.10.mzML");
+    let index = read_index_list(&mut Cursor::new(&bytes)).unwrap().unwrap();
+
+    // Every offset now lands two bytes before its spectrum.
+    let at = bytes.windows(4).position(|w| w == b"<run").unwrap();
+    let shifted = [&bytes[..at], b"  ", &bytes[at..]].concat();
+    assert_eq!(spectrum_ranges(&shifted, Some(&index.spectrum)), None);
+
+    let mut swapped = index.spectrum.clone();
+    swapped.swap(0, 1);
+    assert_eq!(spectrum_ranges(&bytes, Some(&swapped)), None);
+    assert_eq!(spectrum_ranges(&bytes, Some(&index.spectrum[1..])), None);
+
+    let serial = serde_json::to_value(parse_mzml(&shifted).unwrap()).unwrap();
+    let parallel = serde_json::to_value(parse_mzml_parallel(&shifted).unwrap()).unwrap();
+    assert_eq!(parallel, serial);
+}
@@ -0,0 +1,223 @@
+use crate::{
+    b64::{decode, encode, encoder::encode::WritingMode},
+    mzml::{
+        bin_to_mzml::bin_to_mzml,
+        parse_mzml::{ParseOptions, parse_mzml, parse_mzml_with_options},
+        structs::*,
+        utilities::ParseError,
+        validate_schema::validate_schema,
+    },
+};
+
+const PATH: &str = "data/mzml/tiny.pwiz.mzML0.99.10.mzML";
+
+const PRESERVE: ParseOptions = ParseOptions {
+    strict: true,
+    preserve_unknown: true,
+};
+
+/// The pwiz fixture with a vendor namespace, a comment and vendor elements
+/// at document, run and spectrum level, plus one inside the precursor list
+/// of the second spectrum.
+fn vendor_document() -> String {
+    std::fs::read_to_string(PATH)
+        .unwrap()
+        .replacen(
+            r#"version="test version">"#,
+            r#"version="test version" xmlns:acme="urn:acme">"#,
+            1,
+        )
+        .replacen(
+            "<run ",
+            "<!-- acquired on bench 3 --><acme:header build=\"7\"/><run ",
+            1,
+        )
+        .replacen(
+            r#"<spectrum index="0" id="S19" nativeID="19" defaultArrayLength="15">"#,
+            r#"<spectrum index="0" id="S19" nativeID="19" defaultArrayLength="15" acme:tag="a &amp; b"><acme:peaks n="2"><acme:p>1.5</acme:p></acme:peaks>"#,
+            1,
+        )
+        .replacen(
+            "<precursor ",
+            "<acme:lock mass=\"445.12\"/><precursor ",
+            1,
+        )
+        .replacen("</run>", "<acme:runInfo>ok</acme:runInfo></run>", 1)
+}
+
+fn first_spectrum(mzml: &MzML) -> &Spectrum {
+    &mzml.run.spectrum_list.as_ref().unwrap().spectra[0]
+}
+
+#[test]
+fn unknown_content_is_dropped_by_default() {
+    let mzml = parse_mzml(vendor_document().as_bytes()).unwrap();
+    assert!(mzml.extensions.is_none());
+    assert!(mzml.run.extensions.is_none());
+    assert!(first_spectrum(&mzml).extensions.is_none());
+}
+
+/// Each element as (parent, position, xml).
+fn located(extensions: Option<&Extensions>) -> Vec<(&str, usize, &str)> {
+    extensions.map_or_else(Vec::new, |e| {
+        e.elements
+            .iter()
+            .map(|e| (e.parent.as_str(), e.position, e.xml.as_str()))
+            .collect()
+    })
+}
+
+#[test]
+fn unknown_content_is_kept_where_it_was_found() {
+    let mzml = parse_mzml_with_options(vendor_document().as_bytes(), &PRESERVE).unwrap();
+
+    let doc = mzml.extensions.as_ref().unwrap();
+    assert!(
+        doc.attributes
+            .contains(&("accession".to_string(), "test accession".to_string()))
+    );
+    assert!(
+        doc.attributes
+            .contains(&("xmlns:acme".to_string(), "urn:acme".to_string()))
+    );
+    // The legacy `<softwareRef>` child of `<dataProcessing>` is modelled.
+    let dp = &mzml.data_processing_list.as_ref().unwrap().data_processing[0];
+    assert_eq!(dp.software_ref.as_deref(), Some("Xcalibur"));
+    assert_eq!(
+        located(Some(doc)),
+        [
+            ("", 7, "<!-- acquired on bench 3 -->"),
+            ("", 8, r#"<acme:header build="7"/>"#),
+        ]
+    );
+
+    assert_eq!(
+        located(mzml.run.extensions.as_ref()),
+        [("", 3, "<acme:runInfo>ok</acme:runInfo>")]
+    );
+
+    let spectrum = first_spectrum(&mzml).extensions.as_ref().unwrap();
+    assert_eq!(
+        spectrum.attributes,
+        [("acme:tag".to_string(), "a & b".to_string())]
+    );
+    assert_eq!(
+        located(Some(spectrum)),
+        [(
+            "",
+            0,
+            r#"<acme:peaks n="2"><acme:p>1.5</acme:p></acme:peaks>"#
+        )]
+    );
+
+    let second = &mzml.run.spectrum_list.as_ref().unwrap().spectra[1];
+    assert_eq!(
+        located(second.extensions.as_ref()),
+        // Found in the 0.99 `<spectrumDescription>`, located as in mzML 1.1.
+        [("precursorList[1]", 0, r#"<acme:lock mass="445.12"/>"#)]
+    );
+}
+
+#[test]
+fn extensions_survive_b000_and_bin_to_mzml() {
+    let mzml = parse_mzml_with_options(vendor_document().as_bytes(), &PRESERVE).unwrap();
+
+    let mut encoded = Vec::new();
+    encode(&mzml, 3, false, WritingMode::Memory, &mut encoded).unwrap();
+    let decoded = decode(&encoded).unwrap();
+    assert_eq!(decoded.extensions, mzml.extensions);
+    assert_eq!(decoded.run.extensions, mzml.run.extensions);
+    assert_eq!(
+        first_spectrum(&decoded).extensions,
+        first_spectrum(&mzml).extensions
+    );
+
+    let xml = bin_to_mzml(&decoded).unwrap();
+    assert!(xml.contains(r#"acme:tag="a &amp; b""#));
+    assert!(xml.contains("<!-- acquired on bench 3 -->"));
+
+    let reparsed = parse_mzml_with_options(xml.as_bytes(), &PRESERVE).unwrap();
+    assert_eq!(reparsed.run.extensions, mzml.run.extensions);
+    for (a, b) in reparsed
+        .run
+        .spectrum_list
+        .as_ref()
+        .unwrap()
+        .spectra
+        .iter()
+        .zip(&mzml.run.spectrum_list.as_ref().unwrap().spectra)
+    {
+        assert_eq!(a.extensions, b.extensions);
+    }
+    assert_eq!(
+        reparsed.extensions.unwrap().elements,
+        mzml.extensions.unwrap().elements
+    );
+}
+
+#[test]
+fn nested_unknown_elements_go_back_in_place_and_validate() {
+    let source = std::fs::read_to_string("data/mzml/test.mzML")
+        .unwrap()
+        .replacen(
+            r#"id="anpc_file""#,
+            r#"id="anpc_file" xmlns:acme="urn:acme""#,
+            1,
+        )
+        .replacen(
+            r#"<dataProcessing id="pwiz_Reader_Bruker_conversion">"#,
+            r#"<dataProcessing id="pwiz_Reader_Bruker_conversion"><acme:note>checked</acme:note>"#,
+            1,
+        );
+    let mzml = parse_mzml_with_options(source.as_bytes(), &PRESERVE).unwrap();
+
+    // `<softwareRef>` is a modelled child of `<instrumentConfiguration>`.
+    let instrument = &mzml.instrument_list.as_ref().unwrap().instrument[0];
+    assert_eq!(
+        instrument.software_ref.as_ref().map(|r| r.r#ref.as_str()),
+        Some("micrOTOFcontrol")
+    );
+    assert_eq!(
+        located(mzml.extensions.as_ref()),
+        [(
+            "dataProcessingList[1]/dataProcessing[1]",
+            0,
+            "<acme:note>checked</acme:note>"
+        )]
+    );
+
+    let mut encoded = Vec::new();
+    encode(&mzml, 3, false, WritingMode::Memory, &mut encoded).unwrap();
+    assert_eq!(decode(&encoded).unwrap().extensions, mzml.extensions);
+
+    let xml = bin_to_mzml(&mzml).unwrap();
+    assert!(
+        xml.contains("<dataProcessing id=\"pwiz_Reader_Bruker_conversion\">\n        <acme:note>")
+    );
+    // The fixture has schema issues of its own; the round trip adds none.
+    let issues = |xml: &str| {
+        validate_schema(xml.as_bytes())
+            .unwrap()
+            .issues
+            .into_iter()
+            .map(|issue| (issue.kind, issue.path))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(issues(&xml), issues(&source));
+
+    let reparsed = parse_mzml_with_options(xml.as_bytes(), &PRESERVE).unwrap();
+    assert_eq!(reparsed.extensions, mzml.extensions);
+}
+
+#[test]
+fn truncated_unknown_element_is_an_error() {
+    let document = vendor_document();
+    let cut = document.find("<acme:p>").unwrap();
+    for options in [ParseOptions::default(), PRESERVE] {
+        let err = parse_mzml_with_options(&document.as_bytes()[..cut], &options).unwrap_err();
+        assert!(
+            matches!(&err, ParseError::UnexpectedEof { context, .. } if context == "acme:peaks"),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use std::{
+    fs::File,
+    io::{BufReader, Cursor},
+};
+
+use crate::{
+    b64::{decode, encode, encoder::encode::WritingMode},
+    mzml::{
+        bin_to_mzml::{
+            MzmlWriteOptions, convert_bin_to_mzml_bytes, write_b000_as_mzml, write_mzml,
+            write_mzml_streaming,
+        },
+        mzml_reader::{MzMLItem, MzMLReader},
+        parse_mzml::parse_mzml,
+    },
+};
+
+const PATHS: [&str; 3] = [
+    "data/mzml/test.mzML",
+    "data/mzml/tiny.pwiz.mzML0.99.10.mzML",
+    "data/mzml/tiny2_SRM.mzML0.99.1.mzML",
+];
+
+#[test]
+fn streaming_from_mzml_reader_matches_in_memory_write() {
+    for path in PATHS {
+        let mzml = parse_mzml(&std::fs::read(path).unwrap()).unwrap();
+        let expected = convert_bin_to_mzml_bytes(&mzml).unwrap();
+        assert_eq!(write_mzml(Vec::new(), &mzml).unwrap(), expected, "{path}");
+
+        // The reader only fills in the lists as it reaches them, so the
+        // header with the final counts comes from the parsed tree.
+        let mut header = mzml.clone();
+        if let Some(list) = header.run.spectrum_list.as_mut() {
+            list.spectra.clear();
+        }
+        if let Some(list) = header.run.chromatogram_list.as_mut() {
+            list.chromatograms.clear();
+        }
+        let reader = MzMLReader::new(BufReader::new(File::open(path).unwrap())).unwrap();
+        let streamed =
+            write_mzml_streaming(Vec::new(), &header, reader, &MzmlWriteOptions::default())
+                .unwrap();
+        assert_eq!(streamed, expected, "{path}");
+    }
+}
+
+#[test]
+fn b000_is_written_one_entry_at_a_time_as_decode_would() {
+    for path in PATHS {
+        let mzml = parse_mzml(&std::fs::read(path).unwrap()).unwrap();
+        let mut bytes = Vec::new();
+        encode(&mzml, 12, false, WritingMode::Memory, &mut bytes).unwrap();
+
+        let expected = convert_bin_to_mzml_bytes(&decode(&bytes).unwrap()).unwrap();
+        assert_eq!(
+            write_b000_as_mzml(
+                Vec::new(),
+                Cursor::new(&bytes),
+                &MzmlWriteOptions::default()
+            )
+            .unwrap(),
+            expected,
+            "{path}"
+        );
+    }
+}
+
+#[test]
+fn out_of_order_entries_and_reader_errors_are_reported() {
+    let mzml = parse_mzml(&std::fs::read("data/mzml/tiny.pwiz.mzML0.99.10.mzML").unwrap()).unwrap();
+    let run = &mzml.run;
+    let spectrum = run.spectrum_list.as_ref().unwrap().spectra[0].clone();
+    let chromatogram = run.chromatogram_list.as_ref().unwrap().chromatograms[0].clone();
+
+    let items = [
+        Ok::<_, String>(MzMLItem::Chromatogram(chromatogram)),
+        Ok(MzMLItem::Spectrum(spectrum)),
+    ];
+    let err =
+        write_mzml_streaming(Vec::new(), &mzml, items, &MzmlWriteOptions::default()).unwrap_err();
+    assert!(err.contains("before chromatograms"), "{err}");
+
+    let items = [Err::<MzMLItem, _>("truncated input")];
+    let err =
+        write_mzml_streaming(Vec::new(), &mzml, items, &MzmlWriteOptions::default()).unwrap_err();
+    assert_eq!(err, "truncated input");
+}
@@ -0,0 +1,159 @@
+use crate::mzml::{
+    bin_to_mzml::{
+        ArrayCompression, ArrayPrecision, MzmlWriteOptions, convert_bin_to_mzml_bytes,
+        write_mzml_with_options,
+    },
+    parse_mzml::parse_mzml,
+    structs::*,
+};
+
+const PATH: &str = "data/mzml/test.mzML";
+
+fn parsed() -> MzML {
+    parse_mzml(&std::fs::read(PATH).unwrap()).unwrap()
+}
+
+fn write(mzml: &MzML, options: &MzmlWriteOptions) -> Vec<u8> {
+    write_mzml_with_options(Vec::new(), mzml, options).unwrap()
+}
+
+fn arrays(mzml: &MzML) -> Vec<&BinaryDataArray> {
+    let run = &mzml.run;
+    let spectra = run.spectrum_list.iter().flat_map(|l| &l.spectra);
+    let chromatograms = run.chromatogram_list.iter().flat_map(|l| &l.chromatograms);
+    spectra
+        .flat_map(|s| &s.binary_data_array_list)
+        .chain(chromatograms.flat_map(|c| &c.binary_data_array_list))
+        .flat_map(|l| &l.binary_data_arrays)
+        .collect()
+}
+
+fn values(bda: &BinaryDataArray) -> Vec<f64> {
+    match bda.binary.as_ref().unwrap() {
+        BinaryData::F64(v) => v.clone(),
+        BinaryData::F32(v) => v.iter().map(|&x| x as f64).collect(),
+        BinaryData::I64(v) => v.iter().map(|&x| x as f64).collect(),
+        BinaryData::I32(v) => v.iter().map(|&x| x as f64).collect(),
+        other => panic!("unexpected array {other:?}"),
+    }
+}
+
+fn has(bda: &BinaryDataArray, accession: &str) -> bool {
+    bda.cv_params
+        .iter()
+        .any(|p| p.accession.as_deref() == Some(accession))
+}
+
+#[test]
+fn default_options_write_the_same_bytes_as_before() {
+    let mzml = parsed();
+    assert_eq!(
+        write(&mzml, &MzmlWriteOptions::default()),
+        convert_bin_to_mzml_bytes(&mzml).unwrap()
+    );
+}
+
+#[test]
+fn compact_plain_output_parses_back_to_the_same_arrays() {
+    let mzml = parsed();
+    let options = MzmlWriteOptions {
+        indexed: false,
+        pretty: false,
+        ..Default::default()
+    };
+    let bytes = write(&mzml, &options);
+    let text = std::str::from_utf8(&bytes).unwrap();
+    assert!(!text.contains("indexedmzML") && !text.contains("<indexList"));
+    assert!(!text.contains(">\n"));
+
+    let reparsed = parse_mzml(&bytes).unwrap();
+    let (before, after) = (arrays(&mzml), arrays(&reparsed));
+    assert_eq!(before.len(), after.len());
+    for (a, b) in before.iter().zip(&after) {
+        assert_eq!(values(a), values(b));
+    }
+}
+
+#[test]
+fn compression_and_precision_rewrite_the_cv_params() {
+    let mzml = parsed();
+    for (compression, accession) in [
+        (ArrayCompression::None, "MS:1000576"),
+        (ArrayCompression::Zlib, "MS:1000574"),
+    ] {
+        let options = MzmlWriteOptions {
+            compression,
+            precision: ArrayPrecision::F32,
+            zlib_level: 9,
+            ..Default::default()
+        };
+        let reparsed = parse_mzml(&write(&mzml, &options)).unwrap();
+        for (a, b) in arrays(&mzml).iter().zip(arrays(&reparsed)) {
+            assert!(has(b, accession), "{accession}");
+            if matches!(a.binary, Some(BinaryData::F64(_) | BinaryData::F32(_))) {
+                assert!(has(b, "MS:1000521") && !has(b, "MS:1000523"));
+                assert!(matches!(b.binary, Some(BinaryData::F32(_))));
+                let want: Vec<f64> = values(a).iter().map(|&x| x as f32 as f64).collect();
+                assert_eq!(values(b), want);
+            } else {
+                assert_eq!(values(b), values(a));
+            }
+        }
+    }
+}
+
+#[test]
+fn numpress_picks_a_codec_per_array_type() {
+    let mzml = parsed();
+    let options = MzmlWriteOptions {
+        compression: ArrayCompression::Numpress,
+        ..Default::default()
+    };
+    let reparsed = parse_mzml(&write(&mzml, &options)).unwrap();
+    for (a, b) in arrays(&mzml).iter().zip(arrays(&reparsed)) {
+        let (want, got) = (values(a), values(b));
+        assert_eq!(want.len(), got.len());
+        let (codec, rel) = if has(a, "MS:1000514") || has(a, "MS:1000595") {
+            ("MS:1002312", 1e-6)
+        } else if has(a, "MS:1000515") {
+            ("MS:1002314", 5e-4)
+        } else {
+            ("MS:1000574", 0.0)
+        };
+        assert!(has(b, codec) && !has(b, "MS:1000576"), "{codec}");
+        for (w, g) in want.iter().zip(&got) {
+            assert!((w - g).abs() <= rel * w.abs().max(1.0), "{g} vs {w}");
+        }
+    }
+}
+
+#[test]
+fn missing_cv_params_fail_unless_added() {
+    let mut mzml = parsed();
+    let spectrum = &mut mzml.run.spectrum_list.as_mut().unwrap().spectra[0];
+    let bda = &mut spectrum
+        .binary_data_array_list
+        .as_mut()
+        .unwrap()
+        .binary_data_arrays[0];
+    bda.cv_params.retain(|p| {
+        !matches!(
+            p.accession.as_deref(),
+            Some("MS:1000574" | "MS:1000576" | "MS:1000521" | "MS:1000523")
+        )
+    });
+    bda.numeric_type = None;
+
+    let err = write_mzml_with_options(Vec::new(), &mzml, &MzmlWriteOptions::default()).unwrap_err();
+    assert!(err.contains("missing compression cvParam"), "{err}");
+
+    let options = MzmlWriteOptions {
+        add_missing_cv_params: true,
+        ..Default::default()
+    };
+    let reparsed = parse_mzml(&write(&mzml, &options)).unwrap();
+    let bda = arrays(&reparsed)[0];
+    assert!(has(bda, "MS:1000576"));
+    assert!(has(bda, "MS:1000521") || has(bda, "MS:1000523"));
+    assert_eq!(values(bda), values(arrays(&parsed())[0]));
+}
@@ -1,4 +1,4 @@
-use std::{io::BufRead, str::from_utf8};
+use std::{borrow::Cow, io::BufRead, str::from_utf8};
 
 use quick_xml::events::{BytesStart, Event};
 
@@ -30,6 +30,36 @@ pub fn tag_id_from_bytes(raw: &[u8]) -> TagId {
     TagId::from_xml_tag(normalize_tag(local))
 }
 
+/// Name of an element in mzML 1.1 terms, as used in the paths of preserved
+/// content. Unknown elements keep their raw, prefixed name.
+pub(crate) fn path_name(raw: &[u8]) -> Cow<'_, str> {
+    match tag_id_from_bytes(raw) {
+        TagId::Unknown => String::from_utf8_lossy(raw),
+        tag => Cow::Borrowed(tag.xml_tag()),
+    }
+}
+
+/// Location of the next `name` child of the element at `parent`, given the
+/// children already `seen` there per `path_name`: `parent/name[n]`.
+pub(crate) fn child_location(parent: &str, seen: &[(String, usize)], name: &str) -> String {
+    let n = seen
+        .iter()
+        .find(|(seen, _)| seen == name)
+        .map_or(0, |(_, n)| *n);
+    if parent.is_empty() {
+        format!("{name}[{n}]")
+    } else {
+        format!("{parent}/{name}[{n}]")
+    }
+}
+
+pub(crate) fn count_seen(seen: &mut Vec<(String, usize)>, name: Cow<'_, str>) {
+    match seen.iter_mut().find(|(seen, _)| *seen == name) {
+        Some((_, n)) => *n += 1,
+        None => seen.push((name.into_owned(), 1)),
+    }
+}
+
 pub fn drain_until_close<R: BufRead>(
     ws: &mut ParsingWorkspace<R>,
     closing_bytes: &[u8],
@@ -44,12 +74,19 @@ pub fn drain_until_close<R: BufRead>(
                     break Ok(());
                 }
             }
-            Event::Eof => break Ok(()),
+            Event::Eof => break Err(unexpected_eof(ws, closing_bytes)),
             _ => {}
         }
     }
 }
 
+pub(crate) fn unexpected_eof<R>(ws: &ParsingWorkspace<R>, closing_bytes: &[u8]) -> ParseError {
+    ParseError::UnexpectedEof {
+        context: String::from_utf8_lossy(closing_bytes).into_owned(),
+        byte_offset: ws.xml_reader.buffer_position(),
+    }
+}
+
 pub fn read_element_text<R: BufRead>(
     ws: &mut ParsingWorkspace<R>,
     closing_bytes: &[u8],
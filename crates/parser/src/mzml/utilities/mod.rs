@@ -10,17 +10,21 @@ pub(crate) mod helpers;
 pub(crate) use helpers::*;
 pub(crate) mod parsing_workspace;
 pub(crate) use parsing_workspace::ParsingWorkspace;
+pub(crate) mod numpress;
 pub(crate) mod parse_bda_list;
+pub(crate) mod peak_list;
 pub(crate) use parse_bda_list::{parse_bda, parse_bda_list};
 pub(crate) mod parse_chromatogram_list;
-pub(crate) use parse_chromatogram_list::parse_chromatogram_list;
+pub(crate) use parse_chromatogram_list::{
+    empty_chromatogram, parse_chromatogram, parse_chromatogram_list,
+};
 pub(crate) mod parse_precursor_list;
 pub(crate) use parse_precursor_list::{parse_isolation_window, parse_precursor};
 pub(crate) mod parse_product_list;
 pub(crate) mod parse_scan_list;
 pub(crate) use parse_scan_list::{parse_scan, parse_scan_list};
 pub(crate) mod parse_spectrum_list;
-pub(crate) use parse_spectrum_list::parse_spectrum_list;
+pub(crate) use parse_spectrum_list::{empty_spectrum, parse_spectrum, parse_spectrum_list};
 pub(crate) mod parse_file_description;
 pub(crate) use parse_file_description::parse_file_description;
 pub(crate) mod parse_index_list;
@@ -49,7 +53,7 @@ pub(crate) use parse_scan_settings_list::parse_scan_settings_list;
 pub(crate) mod parse_source_file_ref_list;
 pub(crate) use parse_source_file_ref_list::parse_source_file_ref_list;
 pub(crate) mod parse_run;
-pub(crate) use parse_run::parse_run;
+pub(crate) use parse_run::{parse_run, parse_run_with, run_from_start};
 
 #[cfg(test)]
 mod tests;
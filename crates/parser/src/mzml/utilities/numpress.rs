@@ -0,0 +1,343 @@
+//! MS-Numpress codecs, byte compatible with the reference implementation
+//! (linear prediction, positive integer and short logged float).
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Numpress {
+    /// Linear prediction of fixed-point values, for m/z and time arrays.
+    Linear,
+    /// Positive integers, rounded.
+    Pic,
+    /// Short logged float, for intensities.
+    Slof,
+}
+
+impl Numpress {
+    /// Codec named by a numpress compression accession, and whether zlib was
+    /// applied on top of it.
+    pub(crate) fn from_accession(accession: &str) -> Option<(Self, bool)> {
+        match accession {
+            "MS:1002312" => Some((Self::Linear, false)),
+            "MS:1002313" => Some((Self::Pic, false)),
+            "MS:1002314" => Some((Self::Slof, false)),
+            "MS:1002746" => Some((Self::Linear, true)),
+            "MS:1002747" => Some((Self::Pic, true)),
+            "MS:1002748" => Some((Self::Slof, true)),
+            _ => None,
+        }
+    }
+
+    /// Accession and name of the compression cvParam.
+    pub(crate) fn cv_term(self, zlib: bool) -> (&'static str, &'static str) {
+        match (self, zlib) {
+            (Self::Linear, false) => ("MS:1002312", "MS-Numpress linear prediction compression"),
+            (Self::Pic, false) => ("MS:1002313", "MS-Numpress positive integer compression"),
+            (Self::Slof, false) => ("MS:1002314", "MS-Numpress short logged float compression"),
+            (Self::Linear, true) => (
+                "MS:1002746",
+                "MS-Numpress linear prediction compression followed by zlib compression",
+            ),
+            (Self::Pic, true) => (
+                "MS:1002747",
+                "MS-Numpress positive integer compression followed by zlib compression",
+            ),
+            (Self::Slof, true) => (
+                "MS:1002748",
+                "MS-Numpress short logged float compression followed by zlib compression",
+            ),
+        }
+    }
+
+    pub(crate) fn encode(self, values: &[f64]) -> Result<Vec<u8>, String> {
+        match self {
+            Self::Linear => encode_linear(values),
+            Self::Pic => encode_pic(values),
+            Self::Slof => encode_slof(values),
+        }
+    }
+
+    pub(crate) fn decode(self, bytes: &[u8]) -> Result<Vec<f64>, String> {
+        match self {
+            Self::Linear => decode_linear(bytes),
+            Self::Pic => decode_pic(bytes),
+            Self::Slof => decode_slof(bytes),
+        }
+    }
+}
+
+/// Half-byte packer shared by the linear and pic encoders.
+#[derive(Default)]
+struct HalfBytes {
+    out: Vec<u8>,
+    pending: Option<u8>,
+}
+
+impl HalfBytes {
+    fn push(&mut self, nibble: u8) {
+        match self.pending.take() {
+            Some(high) => self.out.push(high << 4 | (nibble & 0xf)),
+            None => self.pending = Some(nibble & 0xf),
+        }
+    }
+
+    /// Writes `x` as a count of leading zero (0..=8) or 0xf (9..=15)
+    /// half-bytes followed by the remaining half-bytes, least significant
+    /// first.
+    fn push_int(&mut self, x: u32) {
+        let top = x & 0xf000_0000;
+        let (head, skip) = if top == 0 {
+            let zeros = (0..8).find(|&i| x & (0xf000_0000 >> (4 * i)) != 0);
+            let zeros = zeros.unwrap_or(8);
+            (zeros, zeros)
+        } else if top == 0xf000_0000 {
+            let mask = |i: u32| 0xf000_0000u32 >> (4 * i);
+            let ones = (0..8).find(|&i| x & mask(i) != mask(i)).unwrap_or(7);
+            (ones + 8, ones)
+        } else {
+            (0, 0)
+        };
+        self.push(head as u8);
+        for i in 0..8 - skip {
+            self.push((x >> (4 * i)) as u8);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if let Some(high) = self.pending {
+            self.out.push(high << 4);
+        }
+        self.out
+    }
+}
+
+/// Half-byte reader matching `HalfBytes`.
+struct HalfByteReader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+    low: bool,
+}
+
+impl HalfByteReader<'_> {
+    /// True once only a zero padding half-byte, or nothing, is left.
+    fn at_end(&self) -> bool {
+        self.at >= self.bytes.len()
+            || (self.low && self.at == self.bytes.len() - 1 && self.bytes[self.at] & 0xf == 0)
+    }
+
+    fn next(&mut self) -> Result<u32, String> {
+        let byte = *self
+            .bytes
+            .get(self.at)
+            .ok_or("numpress: integer runs past the end of the data")?;
+        let nibble = if self.low {
+            self.at += 1;
+            byte & 0xf
+        } else {
+            byte >> 4
+        };
+        self.low = !self.low;
+        Ok(nibble as u32)
+    }
+
+    fn next_int(&mut self) -> Result<u32, String> {
+        let head = self.next()?;
+        let (mut x, skip) = if head <= 8 {
+            (0, head)
+        } else {
+            let ones = head - 8;
+            let fill = (0..ones).fold(0u32, |acc, i| acc | 0xf000_0000 >> (4 * i));
+            (fill, ones)
+        };
+        for i in 0..8 - skip {
+            x |= self.next()? << (4 * i);
+        }
+        Ok(x)
+    }
+}
+
+fn read_fixed_point(bytes: &[u8]) -> Result<f64, String> {
+    let head = bytes
+        .get(..8)
+        .ok_or("numpress: data shorter than the fixed point")?;
+    Ok(f64::from_le_bytes(head.try_into().unwrap()))
+}
+
+fn linear_fixed_point(values: &[f64]) -> f64 {
+    match values {
+        [] => 0.0,
+        [only] => (f64::from(u32::MAX) / only).floor(),
+        [a, b, ..] => {
+            let mut max = a.max(*b);
+            for w in values.windows(3) {
+                let extrapolated = w[1] + (w[1] - w[0]);
+                max = max.max(((w[2] - extrapolated).abs() + 1.0).ceil());
+            }
+            (f64::from(i32::MAX) / max).floor()
+        }
+    }
+}
+
+fn encode_linear(values: &[f64]) -> Result<Vec<u8>, String> {
+    let fixed_point = linear_fixed_point(values);
+    let mut out = fixed_point.to_le_bytes().to_vec();
+    let fixed = |v: f64| (v * fixed_point + 0.5) as i64;
+
+    for &v in values.iter().take(2) {
+        out.extend_from_slice(&(fixed(v) as u32).to_le_bytes());
+    }
+    if values.len() <= 2 {
+        return Ok(out);
+    }
+
+    let mut packed = HalfBytes::default();
+    let (mut before, mut last) = (fixed(values[0]), fixed(values[1]));
+    for &v in &values[2..] {
+        let current = fixed(v);
+        let diff = current - (last + (last - before));
+        let diff = i32::try_from(diff)
+            .map_err(|_| "numpress linear: value too far from the linear prediction")?;
+        packed.push_int(diff as u32);
+        (before, last) = (last, current);
+    }
+    out.extend(packed.finish());
+    Ok(out)
+}
+
+fn decode_linear(bytes: &[u8]) -> Result<Vec<f64>, String> {
+    let fixed_point = read_fixed_point(bytes)?;
+    let read_int = |at: usize| {
+        bytes
+            .get(at..at + 4)
+            .map(|b| i64::from(u32::from_le_bytes(b.try_into().unwrap())))
+            .ok_or("numpress linear: truncated leading values")
+    };
+    let mut values = Vec::new();
+    if bytes.len() == 8 {
+        return Ok(values);
+    }
+    let mut before = read_int(8)?;
+    values.push(before as f64 / fixed_point);
+    if bytes.len() == 12 {
+        return Ok(values);
+    }
+    let mut last = read_int(12)?;
+    values.push(last as f64 / fixed_point);
+
+    let mut reader = HalfByteReader {
+        bytes,
+        at: 16,
+        low: false,
+    };
+    while !reader.at_end() {
+        let diff = i64::from(reader.next_int()? as i32);
+        let current = last + (last - before) + diff;
+        values.push(current as f64 / fixed_point);
+        (before, last) = (last, current);
+    }
+    Ok(values)
+}
+
+fn encode_pic(values: &[f64]) -> Result<Vec<u8>, String> {
+    let mut packed = HalfBytes::default();
+    for &v in values {
+        if !(-0.5..f64::from(i32::MAX) - 0.5).contains(&v) {
+            return Err(format!(
+                "numpress pic: {v} is not a positive 32-bit integer"
+            ));
+        }
+        packed.push_int((v + 0.5) as u32);
+    }
+    Ok(packed.finish())
+}
+
+fn decode_pic(bytes: &[u8]) -> Result<Vec<f64>, String> {
+    let mut reader = HalfByteReader {
+        bytes,
+        at: 0,
+        low: false,
+    };
+    let mut values = Vec::new();
+    while !reader.at_end() {
+        values.push(f64::from(reader.next_int()?));
+    }
+    Ok(values)
+}
+
+fn encode_slof(values: &[f64]) -> Result<Vec<u8>, String> {
+    let max = values
+        .iter()
+        .fold(1.0f64, |max, &v| max.max((v + 1.0).ln()));
+    let fixed_point = if values.is_empty() {
+        0.0
+    } else {
+        (f64::from(u16::MAX) / max).floor()
+    };
+    let mut out = fixed_point.to_le_bytes().to_vec();
+    out.reserve(values.len() * 2);
+    for &v in values {
+        let scaled = (v + 1.0).ln() * fixed_point;
+        if !(0.0..=f64::from(u16::MAX)).contains(&scaled) {
+            return Err(format!("numpress slof: cannot encode {v}"));
+        }
+        out.extend_from_slice(&((scaled + 0.5) as u16).to_le_bytes());
+    }
+    Ok(out)
+}
+
+fn decode_slof(bytes: &[u8]) -> Result<Vec<f64>, String> {
+    let fixed_point = read_fixed_point(bytes)?;
+    if !bytes.len().is_multiple_of(2) {
+        return Err("numpress slof: odd number of bytes".to_string());
+    }
+    Ok(bytes[8..]
+        .chunks_exact(2)
+        .map(|c| (f64::from(u16::from_le_bytes([c[0], c[1]])) / fixed_point).exp() - 1.0)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(got: &[f64], want: &[f64], rel: f64) {
+        assert_eq!(got.len(), want.len());
+        for (g, w) in got.iter().zip(want) {
+            assert!((g - w).abs() <= rel * w.abs().max(1.0), "{g} vs {w}");
+        }
+    }
+
+    #[test]
+    fn linear_round_trips_mz_values() {
+        let mz = [100.0, 100.5, 101.25, 150.125, 150.13, 899.999_9, 1500.0];
+        let bytes = Numpress::Linear.encode(&mz).unwrap();
+        assert_close(&Numpress::Linear.decode(&bytes).unwrap(), &mz, 1e-8);
+
+        for n in 0..3 {
+            let bytes = Numpress::Linear.encode(&mz[..n]).unwrap();
+            assert_close(&Numpress::Linear.decode(&bytes).unwrap(), &mz[..n], 1e-8);
+        }
+    }
+
+    #[test]
+    fn pic_matches_the_reference_half_byte_layout() {
+        // 1 -> [7, 1], 2 -> [7, 2], 100 -> [6, 4, 6], padded with a zero.
+        let bytes = Numpress::Pic.encode(&[1.0, 2.0, 100.0]).unwrap();
+        assert_eq!(bytes, [0x71, 0x72, 0x64, 0x60]);
+        assert_eq!(Numpress::Pic.decode(&bytes).unwrap(), [1.0, 2.0, 100.0]);
+        assert!(Numpress::Pic.encode(&[-3.0]).is_err());
+    }
+
+    #[test]
+    fn slof_round_trips_intensities_within_its_precision() {
+        let intensities = [0.0, 1.0, 12.5, 3.4e4, 9.9e6];
+        let bytes = Numpress::Slof.encode(&intensities).unwrap();
+        assert_eq!(bytes.len(), 8 + 2 * intensities.len());
+        assert_close(&Numpress::Slof.decode(&bytes).unwrap(), &intensities, 5e-4);
+    }
+
+    #[test]
+    fn truncated_input_is_an_error() {
+        let bytes = Numpress::Linear.encode(&[1.0, 2.0, 4.0, 8.0]).unwrap();
+        assert!(Numpress::Linear.decode(&bytes[..6]).is_err());
+        assert!(Numpress::Slof.decode(&[0; 9]).is_err());
+    }
+}
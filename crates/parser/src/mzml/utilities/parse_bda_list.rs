@@ -1,3 +1,4 @@
+use base64::DecodeError;
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD;
 use miniz_oxide::inflate::decompress_to_vec_zlib;
@@ -7,10 +8,11 @@ use std::io::BufRead;
 use crate::{
     BinaryData, BinaryDataArray, BinaryDataArrayList, NumericType,
     mzml::{
+        parse_report::WarningAction,
         schema::TagId,
         utilities::{
-            ParamCollector, ParseError, ParsingWorkspace, attr, attr_usize, read_base64_binary,
-            read_cv_param, read_ref_group_ref, read_user_param,
+            ParamCollector, ParseError, ParsingWorkspace, attr, attr_usize, numpress::Numpress,
+            read_base64_binary, read_cv_param, read_ref_group_ref, read_user_param,
         },
     },
 };
@@ -47,6 +49,8 @@ pub(crate) fn parse_bda<R: BufRead>(
     ws: &mut ParsingWorkspace<R>,
     start: &BytesStart<'_>,
 ) -> Result<BinaryDataArray, ParseError> {
+    let offset = ws.element_offset();
+    let name = start.name().as_ref().to_vec();
     let mut bda = BinaryDataArray {
         array_length: attr_usize(start, b"arrayLength"),
         encoded_length: attr_usize(start, b"encodedLength"),
@@ -56,7 +60,7 @@ pub(crate) fn parse_bda<R: BufRead>(
     let mut raw_b64: Vec<u8> = Vec::new();
 
     ws.for_each_child(start, |ws, event| {
-        let (tag, element, _) = event.into_parts();
+        let (tag, element, is_open) = event.into_parts();
         match tag {
             TagId::CvParam => {
                 bda.receive_cv(read_cv_param(&element));
@@ -70,6 +74,8 @@ pub(crate) fn parse_bda<R: BufRead>(
                 bda.receive_ref_group(read_ref_group_ref(&element));
                 Ok(true)
             }
+            // `<binary/>`, as in imzML, whose data is in the `.ibd` file.
+            TagId::Binary if !is_open => Ok(true),
             TagId::Binary => {
                 if let Some(len) = bda.encoded_length {
                     raw_b64.reserve(len);
@@ -86,11 +92,74 @@ pub(crate) fn parse_bda<R: BufRead>(
     bda.numeric_type = Some(encoding.numeric_type);
 
     if !raw_b64.is_empty() {
+        if !encoding.numeric_type_known && encoding.numpress.is_none() {
+            ws.warn(
+                Some(&name),
+                offset,
+                WarningAction::Defaulted,
+                "no binary data type cvParam; decoded as 64-bit float".to_string(),
+            );
+        }
         let mut decoded = Vec::with_capacity(raw_b64.len() * 3 / 4 + 8);
-        STANDARD.decode_vec(&raw_b64, &mut decoded)?;
+        if let Err(e) = STANDARD.decode_vec(&raw_b64, &mut decoded) {
+            if ws.strict {
+                return Err(e.into());
+            }
+            decoded = decode_base64_prefix(&raw_b64);
+            ws.warn(
+                Some(&name),
+                offset,
+                WarningAction::Truncated,
+                format!(
+                    "invalid base64 ({e}); kept the first {} bytes",
+                    decoded.len()
+                ),
+            );
+        }
         if encoding.is_zlib_compressed {
-            decoded = decompress_to_vec_zlib(&decoded)
-                .map_err(|e| ParseError::Decompress(format!("{e:?}")))?;
+            decoded = match decompress_to_vec_zlib(&decoded) {
+                Ok(inflated) => inflated,
+                Err(e) if ws.strict => return Err(ParseError::Decompress(format!("{e:?}"))),
+                Err(e) => {
+                    ws.warn(
+                        Some(&name),
+                        offset,
+                        WarningAction::Truncated,
+                        format!(
+                            "zlib stream is damaged ({:?}); kept the first {} bytes",
+                            e.status,
+                            e.output.len()
+                        ),
+                    );
+                    e.output
+                }
+            };
+        }
+        if let Some(codec) = encoding.numpress {
+            bda.numeric_type = Some(NumericType::Float64);
+            match codec.decode(&decoded) {
+                Ok(values) => bda.binary = Some(BinaryData::F64(values)),
+                Err(e) if ws.strict => return Err(ParseError::Decompress(e)),
+                Err(e) => ws.warn(
+                    Some(&name),
+                    offset,
+                    WarningAction::Skipped,
+                    format!("{e}; array dropped"),
+                ),
+            }
+            return Ok(bda);
+        }
+        let available = decoded.len() / stride(encoding.numeric_type);
+        if let Some(declared) = bda.array_length.filter(|&n| n != available) {
+            ws.warn(
+                Some(&name),
+                offset,
+                WarningAction::Truncated,
+                format!(
+                    "arrayLength is {declared} but {available} values were decoded; kept {}",
+                    declared.min(available)
+                ),
+            );
         }
         bda.binary = Some(decode_binary_data(
             encoding.numeric_type,
@@ -102,19 +171,56 @@ pub(crate) fn parse_bda<R: BufRead>(
     Ok(bda)
 }
 
+/// Longest valid prefix of `raw`, decoded. Decoding stops at the first
+/// invalid symbol and drops any incomplete trailing quantum.
+fn decode_base64_prefix(raw: &[u8]) -> Vec<u8> {
+    let mut end = raw.len();
+    loop {
+        match STANDARD.decode(&raw[..end]) {
+            Ok(decoded) => return decoded,
+            Err(_) if end == 0 => return Vec::new(),
+            Err(DecodeError::InvalidByte(at, _)) | Err(DecodeError::InvalidLastSymbol(at, _))
+                if at < end =>
+            {
+                end = at.min(end - 1) / 4 * 4
+            }
+            Err(_) => end = (end - 1) / 4 * 4,
+        }
+    }
+}
+
+#[inline]
+pub(crate) fn stride(numeric_type: NumericType) -> usize {
+    match numeric_type {
+        NumericType::Float64 | NumericType::Int64 => 8,
+        NumericType::Float32 | NumericType::Int32 => 4,
+        NumericType::Float16 | NumericType::Int16 => 2,
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
-struct BinaryArrayEncoding {
-    is_zlib_compressed: bool,
-    numeric_type: NumericType,
+pub(crate) struct BinaryArrayEncoding {
+    pub(crate) is_zlib_compressed: bool,
+    /// Numpress codec applied before any zlib compression; its values are
+    /// always decoded as 64-bit floats.
+    pub(crate) numpress: Option<Numpress>,
+    pub(crate) numeric_type: NumericType,
+    /// False when no cvParam named the type and `Float64` was assumed.
+    pub(crate) numeric_type_known: bool,
 }
 
-fn encoding_for_array(bda: &BinaryDataArray) -> BinaryArrayEncoding {
+pub(crate) fn encoding_for_array(bda: &BinaryDataArray) -> BinaryArrayEncoding {
     let has = |acc: &str| {
         bda.cv_params
             .iter()
             .any(|p| p.accession.as_deref() == Some(acc))
     };
-    let is_zlib_compressed = has("MS:1000574");
+    let numpress = bda
+        .cv_params
+        .iter()
+        .find_map(|p| Numpress::from_accession(p.accession.as_deref()?));
+    let is_zlib_compressed = has("MS:1000574") || numpress.is_some_and(|(_, zlib)| zlib);
+    let numpress = numpress.map(|(codec, _)| codec);
     let (f64, f32, f16) = (has("MS:1000523"), has("MS:1000521"), has("MS:1000520"));
     let (i64, i32, i16) = (has("MS:1000522"), has("MS:1001479"), has("MS:1000519"));
 
@@ -139,16 +245,23 @@ fn encoding_for_array(bda: &BinaryDataArray) -> BinaryArrayEncoding {
     } else if f32 {
         NumericType::Float32
     } else {
-        NumericType::Float64
+        return BinaryArrayEncoding {
+            is_zlib_compressed,
+            numpress,
+            numeric_type: NumericType::Float64,
+            numeric_type_known: false,
+        };
     };
 
     BinaryArrayEncoding {
         is_zlib_compressed,
+        numpress,
         numeric_type,
+        numeric_type_known: true,
     }
 }
 
-fn decode_binary_data(
+pub(crate) fn decode_binary_data(
     numeric_type: NumericType,
     decoded: &[u8],
     array_length: Option<usize>,
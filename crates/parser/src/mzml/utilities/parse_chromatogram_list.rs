@@ -29,21 +29,35 @@ pub(crate) fn parse_chromatogram_list<R: BufRead>(
         if is_open {
             list.chromatograms.push(parse_chromatogram(ws, &element)?);
         } else {
-            list.chromatograms.push(Chromatogram {
-                id: attr(&element, b"id").unwrap_or_default(),
-                index: attr_u32(&element, b"index"),
-                ..Default::default()
-            });
+            list.chromatograms.push(empty_chromatogram(&element));
         }
         Ok(true)
     })?;
     Ok(list)
 }
 
-fn parse_chromatogram<R: BufRead>(
+/// Self-closed `<chromatogram/>`: only the identifying attributes are kept.
+pub(crate) fn empty_chromatogram(start: &BytesStart<'_>) -> Chromatogram {
+    Chromatogram {
+        id: attr(start, b"id").unwrap_or_default(),
+        index: attr_u32(start, b"index"),
+        ..Default::default()
+    }
+}
+
+const CHROMATOGRAM_ATTRIBUTES: &[&[u8]] = &[
+    b"id",
+    b"nativeID",
+    b"index",
+    b"defaultArrayLength",
+    b"dataProcessingRef",
+];
+
+pub(crate) fn parse_chromatogram<R: BufRead>(
     ws: &mut ParsingWorkspace<R>,
     start: &BytesStart<'_>,
 ) -> Result<Chromatogram, ParseError> {
+    let mark = ws.unknown_mark(start);
     let mut chrom = Chromatogram {
         id: attr(start, b"id").unwrap_or_default(),
         native_id: attr(start, b"nativeID"),
@@ -82,5 +96,7 @@ fn parse_chromatogram<R: BufRead>(
             _ => Ok(false),
         }
     })?;
+    ws.keep_unknown_attributes(start, CHROMATOGRAM_ATTRIBUTES, &mut chrom.extensions);
+    ws.keep_unknown_elements(&mark, &mut chrom.extensions);
     Ok(chrom)
 }
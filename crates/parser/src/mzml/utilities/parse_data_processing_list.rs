@@ -45,6 +45,11 @@ fn parse_data_processing<R: BufRead>(
     };
     ws.for_each_child(start, |ws, event| {
         let (tag, element, is_open) = event.into_parts();
+        // mzML 0.99 names the software in a child element.
+        if tag == TagId::SoftwareRef && !is_open {
+            dp.software_ref = attr(&element, b"ref");
+            return Ok(true);
+        }
         if tag != TagId::ProcessingMethod {
             return Ok(false);
         }
@@ -5,8 +5,19 @@ pub enum ParseError {
     Xml(quick_xml::Error),
     Base64(base64::DecodeError),
     Decompress(String),
-    UnexpectedEof { context: String, byte_offset: u64 },
-    UnexpectedTag { tag: String, byte_offset: u64 },
+    UnexpectedEof {
+        context: String,
+        byte_offset: u64,
+    },
+    UnexpectedTag {
+        tag: String,
+        byte_offset: u64,
+    },
+    InvalidValue {
+        context: String,
+        value: String,
+        byte_offset: u64,
+    },
 }
 
 impl Display for ParseError {
@@ -25,6 +36,11 @@ impl Display for ParseError {
             Self::UnexpectedTag { tag, byte_offset } => {
                 write!(f, "unexpected tag <{tag}> at byte {byte_offset}")
             }
+            Self::InvalidValue {
+                context,
+                value,
+                byte_offset,
+            } => write!(f, "invalid {context} {value:?} at byte {byte_offset}"),
         }
     }
 }
@@ -67,6 +67,11 @@ fn parse_instrument_with_body<R: BufRead>(
                 instrument.component_list = Some(parse_component_list(ws, &element)?);
                 Ok(true)
             }
+            TagId::SoftwareRef if !is_open => {
+                instrument.software_ref =
+                    attr(&element, b"ref").map(|r| InstrumentSoftwareRef { r#ref: r });
+                Ok(true)
+            }
             _ => Ok(false),
         }
     })?;
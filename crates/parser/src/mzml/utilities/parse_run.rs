@@ -15,15 +15,25 @@ pub(crate) fn parse_run<R: BufRead>(
     ws: &mut ParsingWorkspace<R>,
     start: &BytesStart<'_>,
 ) -> Result<Run, ParseError> {
-    let mut run = Run {
-        id: attr(start, b"id").unwrap_or_default(),
-        start_time_stamp: attr(start, b"startTimeStamp"),
-        default_instrument_configuration_ref: attr(start, b"defaultInstrumentConfigurationRef")
-            .or_else(|| attr(start, b"instrumentRef")),
-        default_source_file_ref: attr(start, b"defaultSourceFileRef"),
-        sample_ref: attr(start, b"sampleRef"),
-        ..Default::default()
-    };
+    parse_run_with(ws, start, parse_spectrum_list, parse_chromatogram_list)
+}
+
+/// `parse_run` with the spectrum and chromatogram list parsers supplied by
+/// the caller, so the lists can be parsed differently (e.g. in parallel).
+pub(crate) fn parse_run_with<R, S, C>(
+    ws: &mut ParsingWorkspace<R>,
+    start: &BytesStart<'_>,
+    mut spectrum_list: S,
+    mut chromatogram_list: C,
+) -> Result<Run, ParseError>
+where
+    R: BufRead,
+    S: FnMut(&mut ParsingWorkspace<R>, &BytesStart<'_>) -> Result<SpectrumList, ParseError>,
+    C: FnMut(&mut ParsingWorkspace<R>, &BytesStart<'_>) -> Result<ChromatogramList, ParseError>,
+{
+    let mark = ws.unknown_mark(start);
+    let mut run = run_from_start(start);
+    ws.keep_unknown_attributes(start, RUN_ATTRIBUTES, &mut run.extensions);
     ws.for_each_child(start, |ws, event| {
         let (tag, element, is_open) = event.into_parts();
         match tag {
@@ -44,15 +54,39 @@ pub(crate) fn parse_run<R: BufRead>(
                 Ok(true)
             }
             TagId::SpectrumList if is_open => {
-                run.spectrum_list = Some(parse_spectrum_list(ws, &element)?);
+                run.spectrum_list = Some(spectrum_list(ws, &element)?);
                 Ok(true)
             }
             TagId::ChromatogramList if is_open => {
-                run.chromatogram_list = Some(parse_chromatogram_list(ws, &element)?);
+                run.chromatogram_list = Some(chromatogram_list(ws, &element)?);
                 Ok(true)
             }
             _ => Ok(false),
         }
     })?;
+    ws.keep_unknown_elements(&mark, &mut run.extensions);
     Ok(run)
 }
+
+const RUN_ATTRIBUTES: &[&[u8]] = &[
+    b"id",
+    b"startTimeStamp",
+    b"defaultInstrumentConfigurationRef",
+    b"instrumentRef",
+    b"defaultSourceFileRef",
+    b"sampleRef",
+];
+
+/// `<run>` attributes only; children are filled by `parse_run` or streamed
+/// by `MzMLReader`.
+pub(crate) fn run_from_start(start: &BytesStart<'_>) -> Run {
+    Run {
+        id: attr(start, b"id").unwrap_or_default(),
+        start_time_stamp: attr(start, b"startTimeStamp"),
+        default_instrument_configuration_ref: attr(start, b"defaultInstrumentConfigurationRef")
+            .or_else(|| attr(start, b"instrumentRef")),
+        default_source_file_ref: attr(start, b"defaultSourceFileRef"),
+        sample_ref: attr(start, b"sampleRef"),
+        ..Default::default()
+    }
+}
@@ -29,21 +29,39 @@ pub(crate) fn parse_spectrum_list<R: BufRead>(
         if is_open {
             list.spectra.push(parse_spectrum(ws, &element)?);
         } else {
-            list.spectra.push(Spectrum {
-                id: attr(&element, b"id").unwrap_or_default(),
-                index: attr_u32(&element, b"index"),
-                ..Default::default()
-            });
+            list.spectra.push(empty_spectrum(&element));
         }
         Ok(true)
     })?;
     Ok(list)
 }
 
-fn parse_spectrum<R: BufRead>(
+/// Self-closed `<spectrum/>`: only the identifying attributes are kept.
+pub(crate) fn empty_spectrum(start: &BytesStart<'_>) -> Spectrum {
+    Spectrum {
+        id: attr(start, b"id").unwrap_or_default(),
+        index: attr_u32(start, b"index"),
+        ..Default::default()
+    }
+}
+
+const SPECTRUM_ATTRIBUTES: &[&[u8]] = &[
+    b"id",
+    b"index",
+    b"scanNumber",
+    b"msLevel",
+    b"defaultArrayLength",
+    b"nativeID",
+    b"dataProcessingRef",
+    b"sourceFileRef",
+    b"spotID",
+];
+
+pub(crate) fn parse_spectrum<R: BufRead>(
     ws: &mut ParsingWorkspace<R>,
     start: &BytesStart<'_>,
 ) -> Result<Spectrum, ParseError> {
+    let mark = ws.unknown_mark(start);
     let mut spectrum = Spectrum {
         id: attr(start, b"id").unwrap_or_default(),
         index: attr_u32(start, b"index"),
@@ -139,6 +157,8 @@ fn parse_spectrum<R: BufRead>(
             _ => Ok(false),
         }
     })?;
+    ws.keep_unknown_attributes(start, SPECTRUM_ATTRIBUTES, &mut spectrum.extensions);
+    ws.keep_unknown_elements(&mark, &mut spectrum.extensions);
     Ok(spectrum)
 }
 
@@ -1,27 +1,49 @@
+use crate::mzml::parse_report::{ParseWarning, WarningAction};
 use crate::mzml::schema::TagId;
+use crate::mzml::structs::{ExtensionElement, Extensions};
 use crate::mzml::utilities::{
-    ParamCollector, drain_until_close, read_ref_group_ref, read_software_param, read_user_param,
-    tag_id_from_bytes,
+    ParamCollector, attr, child_location, count_seen, drain_until_close, path_name,
+    read_ref_group_ref, read_software_param, read_user_param, tag_id_from_bytes, unexpected_eof,
 };
 use crate::mzml::utilities::{ParseError, read_cv_param};
-use quick_xml::Reader;
-use quick_xml::events::{BytesStart, Event};
-use std::{io::BufRead, str::from_utf8_unchecked};
-
-#[allow(dead_code)] // Coming soon!
-#[derive(Clone, Debug, Copy, PartialEq, Eq, Default)]
-pub(crate) enum StrictnessPolicy {
-    #[default]
-    Permissive,
-    Warn,
-    Error,
-}
+use quick_xml::events::{BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use std::{collections::HashSet, io::BufRead, str::from_utf8_unchecked};
 
 pub(crate) struct ParsingWorkspace<R> {
     pub(crate) xml_reader: Reader<R>,
     pool: BufferPool,
-    pub(crate) policy: StrictnessPolicy,
-    pub(crate) warnings: Vec<String>,
+    /// Fail on bad binary data instead of keeping what can be read.
+    pub(crate) strict: bool,
+    pub(crate) collect_warnings: bool,
+    pub(crate) warnings: Vec<ParseWarning>,
+    /// `<cv id>`s of the document, for checking `cvRef`s when collecting
+    /// warnings.
+    pub(crate) declared_cvs: Option<HashSet<String>>,
+    pub(crate) preserve_unknown: bool,
+    unknown: Vec<ExtensionElement>,
+    /// Open elements, innermost last.
+    path: Vec<OpenElement>,
+    element_offset: u64,
+}
+
+struct OpenElement {
+    name: Vec<u8>,
+    /// Steps from the document element, kept with `preserve_unknown`
+    /// only; empty for the document element itself.
+    location: String,
+    /// Child elements and comments read so far.
+    children: usize,
+    /// Child elements read so far, per `path_name`.
+    seen: Vec<(String, usize)>,
+}
+
+/// Where content skipped inside an owner starts, from
+/// `ParsingWorkspace::unknown_mark`.
+#[derive(Default)]
+pub(crate) struct UnknownMark {
+    index: usize,
+    owner: String,
 }
 
 impl<R: BufRead> ParsingWorkspace<R> {
@@ -30,20 +52,35 @@ impl<R: BufRead> ParsingWorkspace<R> {
         Self {
             xml_reader,
             pool: BufferPool::new(),
-            policy: StrictnessPolicy::default(),
+            strict: true,
+            collect_warnings: false,
             warnings: Vec::new(),
+            declared_cvs: None,
+            preserve_unknown: false,
+            unknown: Vec::new(),
+            path: Vec::new(),
+            element_offset: 0,
         }
     }
 
     fn read_one(&mut self) -> Result<Event<'static>, ParseError> {
         let reader = &mut self.xml_reader;
-        self.pool.with_buf(|buf| {
+        let event = self.pool.with_buf(|buf| {
             buf.clear();
             reader
                 .read_event_into(buf)
                 .map(|e| e.into_owned())
                 .map_err(ParseError::from)
-        })
+        })?;
+        // The reader sits just past `>`; `e` holds what is between `<` and
+        // `>` (or `/>`).
+        let end = self.xml_reader.buffer_position();
+        match &event {
+            Event::Start(e) => self.element_offset = end - e.len() as u64 - 2,
+            Event::Empty(e) => self.element_offset = end - e.len() as u64 - 3,
+            _ => {}
+        }
+        Ok(event)
     }
 
     pub(crate) fn next_event(&mut self) -> Result<Event<'static>, ParseError> {
@@ -53,12 +90,26 @@ impl<R: BufRead> ParsingWorkspace<R> {
     pub(crate) fn for_each_child<F>(
         &mut self,
         start: &BytesStart<'_>,
-        mut on_child: F,
+        on_child: F,
     ) -> Result<(), ParseError>
     where
         F: FnMut(&mut Self, ChildEvent) -> Result<bool, ParseError>,
     {
-        let closing: Vec<u8> = start.name().as_ref().to_vec();
+        self.push_path(start);
+        let result = self.visit_children(on_child);
+        self.pop_path();
+        result
+    }
+
+    fn visit_children<F>(&mut self, mut on_child: F) -> Result<(), ParseError>
+    where
+        F: FnMut(&mut Self, ChildEvent) -> Result<bool, ParseError>,
+    {
+        let closing: Vec<u8> = self
+            .path
+            .last()
+            .map(|open| open.name.clone())
+            .unwrap_or_default();
 
         loop {
             let event = self.read_one()?;
@@ -66,28 +117,32 @@ impl<R: BufRead> ParsingWorkspace<R> {
                 Event::Start(e) => {
                     let tag = tag_id_from_bytes(e.name().as_ref());
                     let raw_name: Vec<u8> = e.name().as_ref().to_vec();
+                    self.count_child(Some(&raw_name));
+                    let start_copy = self.preserve_unknown.then(|| e.clone());
+                    let offset = self.element_offset;
                     let handled = on_child(self, ChildEvent::Open(tag, e))?;
                     if !handled {
-                        let tag_str = unsafe { from_utf8_unchecked(&raw_name) };
-                        match self.policy {
-                            StrictnessPolicy::Error => {
-                                return Err(ParseError::UnexpectedTag {
-                                    tag: tag_str.to_string(),
-                                    byte_offset: self.xml_reader.buffer_position(),
-                                });
-                            }
-                            StrictnessPolicy::Warn => self.warnings.push(format!(
-                                "skipping unknown <{tag_str}> at byte {}",
-                                self.xml_reader.buffer_position()
-                            )),
-                            StrictnessPolicy::Permissive => {}
-                        }
-                        drain_until_close(self, &raw_name)?;
+                        self.warn_skipped(&raw_name, offset);
+                        self.skip_element(&raw_name, start_copy)?;
                     }
                 }
                 Event::Empty(e) => {
                     let tag = tag_id_from_bytes(e.name().as_ref());
-                    on_child(self, ChildEvent::SelfClosed(tag, e))?;
+                    self.count_child(Some(e.name().as_ref()));
+                    if tag == TagId::CvParam && self.declared_cvs.is_some() {
+                        self.check_cv_refs(&e);
+                    }
+                    let offset = self.element_offset;
+                    let copy = (self.preserve_unknown || self.collect_warnings).then(|| e.clone());
+                    let handled = on_child(self, ChildEvent::SelfClosed(tag, e))?;
+                    if let (false, Some(e)) = (handled, copy) {
+                        self.warn_skipped(e.name().as_ref(), offset);
+                        self.keep_empty(&e);
+                    }
+                }
+                Event::Comment(c) => {
+                    self.count_child(None);
+                    self.keep_comment(&c);
                 }
                 Event::End(e) if e.name().as_ref() == closing.as_slice() => break Ok(()),
                 Event::Eof => {
@@ -124,6 +179,240 @@ impl<R: BufRead> ParsingWorkspace<R> {
     }
 }
 
+impl<R> ParsingWorkspace<R> {
+    /// Byte offset of the last start or empty tag read.
+    #[inline]
+    pub(crate) fn element_offset(&self) -> u64 {
+        self.element_offset
+    }
+
+    /// Enters `start`, which must be the last start tag read.
+    pub(crate) fn push_path(&mut self, start: &BytesStart<'_>) {
+        let location = self.child_location(start);
+        self.path.push(OpenElement {
+            name: start.name().as_ref().to_vec(),
+            location,
+            children: 0,
+            seen: Vec::new(),
+        });
+    }
+
+    /// Location `start`, a child of the current element, has once entered.
+    fn child_location(&self, start: &BytesStart<'_>) -> String {
+        let Some(parent) = self.path.last().filter(|_| self.preserve_unknown) else {
+            return String::new();
+        };
+        match path_name(start.name().as_ref()) {
+            // What mzML 0.99 nests here is written straight into `<spectrum>`.
+            name if name == "spectrumDescription" => parent.location.clone(),
+            name => child_location(&parent.location, &parent.seen, &name),
+        }
+    }
+
+    /// Counts a child element (`None` for a comment) of the current element,
+    /// so preserved content can record where it was found.
+    pub(crate) fn count_child(&mut self, raw_name: Option<&[u8]>) {
+        if !self.preserve_unknown {
+            return;
+        }
+        let Some(parent) = self.path.last_mut() else {
+            return;
+        };
+        parent.children += 1;
+        if let Some(name) = raw_name {
+            count_seen(&mut parent.seen, path_name(name));
+        }
+    }
+
+    pub(crate) fn pop_path(&mut self) {
+        self.path.pop();
+    }
+
+    /// Records a warning about `child` of the current element (or the
+    /// current element itself when `child` is `None`) at `byte_offset`.
+    pub(crate) fn warn(
+        &mut self,
+        child: Option<&[u8]>,
+        byte_offset: u64,
+        action: WarningAction,
+        message: String,
+    ) {
+        if !self.collect_warnings {
+            return;
+        }
+        let mut path = String::new();
+        for name in self
+            .path
+            .iter()
+            .map(|open| open.name.as_slice())
+            .chain(child)
+        {
+            path.push('/');
+            path.push_str(&String::from_utf8_lossy(name));
+        }
+        self.warnings.push(ParseWarning {
+            byte_offset,
+            line: 0,
+            column: 0,
+            path,
+            action,
+            message,
+        });
+    }
+
+    pub(crate) fn warn_skipped(&mut self, raw_name: &[u8], byte_offset: u64) {
+        if self.collect_warnings && !self.preserve_unknown {
+            let name = String::from_utf8_lossy(raw_name).into_owned();
+            self.warn(
+                Some(raw_name),
+                byte_offset,
+                WarningAction::Skipped,
+                format!("<{name}> is not expected here"),
+            );
+        }
+    }
+
+    fn check_cv_refs(&mut self, cv_param: &BytesStart<'_>) {
+        let Some(declared) = &self.declared_cvs else {
+            return;
+        };
+        let undeclared: Vec<String> = [b"cvRef".as_slice(), b"unitCvRef"]
+            .into_iter()
+            .filter_map(|key| attr(cv_param, key))
+            .filter(|cv| !declared.contains(cv))
+            .collect();
+        for cv in undeclared {
+            self.warn(
+                Some(cv_param.name().as_ref()),
+                self.element_offset,
+                WarningAction::Kept,
+                format!("cvRef \"{cv}\" is not declared in <cvList>"),
+            );
+        }
+    }
+}
+
+impl<R: BufRead> ParsingWorkspace<R> {
+    /// Skips the subtree whose start tag was just read, keeping it as raw
+    /// XML when `preserve_unknown` is set and `start` is given.
+    pub(crate) fn skip_element(
+        &mut self,
+        raw_name: &[u8],
+        start: Option<BytesStart<'static>>,
+    ) -> Result<(), ParseError> {
+        let Some(start) = start.filter(|_| self.preserve_unknown) else {
+            return drain_until_close(self, raw_name);
+        };
+        // Writing into a `Vec` cannot fail.
+        let mut writer = Writer::new(Vec::new());
+        let _ = writer.write_event(Event::Start(start));
+        let mut depth = 1usize;
+        while depth > 0 {
+            let event = self.read_one()?;
+            match &event {
+                Event::Start(_) => depth += 1,
+                Event::End(_) => depth -= 1,
+                Event::Eof => return Err(unexpected_eof(self, raw_name)),
+                _ => {}
+            }
+            let _ = writer.write_event(event);
+        }
+        self.keep(writer.into_inner());
+        Ok(())
+    }
+
+    pub(crate) fn keep_empty(&mut self, element: &BytesStart<'_>) {
+        if self.preserve_unknown {
+            let mut writer = Writer::new(Vec::new());
+            let _ = writer.write_event(Event::Empty(element.borrow()));
+            self.keep(writer.into_inner());
+        }
+    }
+
+    pub(crate) fn keep_comment(&mut self, comment: &BytesText<'_>) {
+        if self.preserve_unknown {
+            self.keep(format!("<!--{}-->", String::from_utf8_lossy(comment.as_ref())).into());
+        }
+    }
+
+    /// Records `xml`, the child of the current element counted last.
+    fn keep(&mut self, xml: Vec<u8>) {
+        let (parent, position) = self.path.last().map_or((String::new(), 0), |open| {
+            (open.location.clone(), open.children.saturating_sub(1))
+        });
+        self.unknown.push(ExtensionElement {
+            parent,
+            position,
+            xml: String::from_utf8_lossy(&xml).into_owned(),
+        });
+    }
+
+    /// Mark to pass to `keep_unknown_elements` once `start`, a child of the
+    /// current element, is fully parsed.
+    pub(crate) fn unknown_mark(&self, start: &BytesStart<'_>) -> UnknownMark {
+        UnknownMark {
+            index: self.unknown.len(),
+            owner: self.child_location(start),
+        }
+    }
+
+    /// Moves everything skipped since `mark` into `target`, located relative
+    /// to the owner. Content skipped inside children without their own
+    /// `extensions` thus lands on the nearest ancestor that has one.
+    pub(crate) fn keep_unknown_elements(
+        &mut self,
+        mark: &UnknownMark,
+        target: &mut Option<Extensions>,
+    ) {
+        if self.unknown.len() <= mark.index {
+            return;
+        }
+        let mut elements = self.unknown.split_off(mark.index);
+        for element in &mut elements {
+            if element.parent == mark.owner {
+                element.parent.clear();
+            } else if let Some(inner) = element
+                .parent
+                .strip_prefix(mark.owner.as_str())
+                .and_then(|rest| rest.strip_prefix('/'))
+            {
+                element.parent = inner.to_string();
+            }
+        }
+        target
+            .get_or_insert_with(Default::default)
+            .elements
+            .extend(elements);
+    }
+
+    /// Adds the attributes of `start` not named in `known` to `target`.
+    pub(crate) fn keep_unknown_attributes(
+        &self,
+        start: &BytesStart<'_>,
+        known: &[&[u8]],
+        target: &mut Option<Extensions>,
+    ) {
+        if !self.preserve_unknown {
+            return;
+        }
+        for a in start.attributes().with_checks(false).flatten() {
+            if known.contains(&a.key.as_ref()) {
+                continue;
+            }
+            let Ok(value) = a.unescape_value() else {
+                continue;
+            };
+            target
+                .get_or_insert_with(Default::default)
+                .attributes
+                .push((
+                    String::from_utf8_lossy(a.key.as_ref()).into_owned(),
+                    value.into_owned(),
+                ));
+        }
+    }
+}
+
 struct BufferPool(Vec<Vec<u8>>);
 
 impl BufferPool {
@@ -0,0 +1,465 @@
+//! Spectrum fields read and written by the peak-list formats (MGF, ...):
+//! precursor, charge, retention time and the m/z and intensity arrays.
+
+use crate::mzml::{
+    bin_to_mzml::{binary_len, default_cv_list},
+    structs::*,
+};
+
+pub(crate) const ACC_MZ_ARRAY: &str = "MS:1000514";
+pub(crate) const ACC_INTENSITY_ARRAY: &str = "MS:1000515";
+pub(crate) const ACC_MS_LEVEL: &str = "MS:1000511";
+pub(crate) const ACC_SCAN_START_TIME: &str = "MS:1000016";
+pub(crate) const ACC_SELECTED_ION_MZ: &str = "MS:1000744";
+/// `m/z`, used for the selected ion before `selected ion m/z` existed.
+pub(crate) const ACC_MZ: &str = "MS:1000040";
+pub(crate) const ACC_PEAK_INTENSITY: &str = "MS:1000042";
+pub(crate) const ACC_CHARGE_STATE: &str = "MS:1000041";
+pub(crate) const ACC_POSSIBLE_CHARGE_STATE: &str = "MS:1000633";
+pub(crate) const ACC_ION_INJECTION_TIME: &str = "MS:1000927";
+
+/// `MS` cvParam without a unit.
+pub(crate) fn ms_cv(accession: &str, name: &str, value: Option<String>) -> CvParam {
+    CvParam {
+        cv_ref: Some("MS".to_string()),
+        accession: Some(accession.to_string()),
+        name: name.to_string(),
+        value,
+        ..Default::default()
+    }
+}
+
+/// `MS` cvParam with a unit from `unit_accession`'s ontology.
+pub(crate) fn ms_cv_with_unit(
+    accession: &str,
+    name: &str,
+    value: String,
+    (unit_accession, unit_name): (&str, &str),
+) -> CvParam {
+    CvParam {
+        unit_cv_ref: unit_accession.split(':').next().map(str::to_string),
+        unit_accession: Some(unit_accession.to_string()),
+        unit_name: Some(unit_name.to_string()),
+        ..ms_cv(accession, name, Some(value))
+    }
+}
+
+#[inline]
+pub(crate) fn find_cv<'a>(params: &'a [CvParam], accession: &str) -> Option<&'a CvParam> {
+    params
+        .iter()
+        .find(|p| p.accession.as_deref() == Some(accession))
+}
+
+#[inline]
+pub(crate) fn cv_f64(params: &[CvParam], accession: &str) -> Option<f64> {
+    find_cv(params, accession)?
+        .value
+        .as_deref()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+pub(crate) fn values_f64(binary: &BinaryData) -> Vec<f64> {
+    match binary {
+        BinaryData::F64(v) => v.clone(),
+        BinaryData::F32(v) => v.iter().map(|&x| x as f64).collect(),
+        BinaryData::F16(v) => v.iter().map(|&x| f16_to_f64(x)).collect(),
+        BinaryData::I64(v) => v.iter().map(|&x| x as f64).collect(),
+        BinaryData::I32(v) => v.iter().map(|&x| x as f64).collect(),
+        BinaryData::I16(v) => v.iter().map(|&x| x as f64).collect(),
+    }
+}
+
+fn f16_to_f64(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = i32::from((bits >> 10) & 0x1f);
+    let fraction = f64::from(bits & 0x3ff);
+    sign * match exponent {
+        0 => fraction * 2f64.powi(-24),
+        0x1f if fraction == 0.0 => f64::INFINITY,
+        0x1f => f64::NAN,
+        _ => (1.0 + fraction / 1024.0) * 2f64.powi(exponent - 15),
+    }
+}
+
+/// Data of the array of `spectrum` whose cvParams include `accession`.
+pub(crate) fn array_binary<'a>(spectrum: &'a Spectrum, accession: &str) -> Option<&'a BinaryData> {
+    let list = spectrum.binary_data_array_list.as_ref()?;
+    let bda = list
+        .binary_data_arrays
+        .iter()
+        .find(|bda| find_cv(&bda.cv_params, accession).is_some())?;
+    bda.binary.as_ref()
+}
+
+/// Values of the array of `spectrum` whose cvParams include `accession`.
+pub(crate) fn array_f64(spectrum: &Spectrum, accession: &str) -> Option<Vec<f64>> {
+    array_binary(spectrum, accession).map(values_f64)
+}
+
+#[inline]
+fn scan_list(spectrum: &Spectrum) -> Option<&ScanList> {
+    spectrum.scan_list.as_ref().or_else(|| {
+        let description = spectrum.spectrum_description.as_ref()?;
+        description.scan_list.as_ref()
+    })
+}
+
+#[inline]
+pub(crate) fn precursor_list(spectrum: &Spectrum) -> Option<&PrecursorList> {
+    spectrum.precursor_list.as_ref().or_else(|| {
+        let description = spectrum.spectrum_description.as_ref()?;
+        description.precursor_list.as_ref()
+    })
+}
+
+/// `N` of the `scan=N` key of a native id.
+pub(crate) fn native_scan_number(id: &str) -> Option<u32> {
+    id.split_whitespace()
+        .find_map(|key| key.strip_prefix("scan="))
+        .and_then(|n| n.parse().ok())
+}
+
+/// cvParams of the first scan.
+pub(crate) fn first_scan_params(spectrum: &Spectrum) -> &[CvParam] {
+    scan_list(spectrum)
+        .and_then(|l| l.scans.first())
+        .map_or(&[], |scan| &scan.cv_params)
+}
+
+pub(crate) fn ms_level(spectrum: &Spectrum) -> Option<u32> {
+    spectrum.ms_level.or_else(|| {
+        let from = |params: &[CvParam]| cv_f64(params, ACC_MS_LEVEL).map(|v| v as u32);
+        from(&spectrum.cv_params)
+            .or_else(|| from(&spectrum.spectrum_description.as_ref()?.cv_params))
+    })
+}
+
+/// Time `param` in seconds, from its UO or legacy MS unit. A value without a
+/// unit is taken as seconds.
+pub(crate) fn time_in_seconds(param: &CvParam) -> Option<f64> {
+    let value: f64 = param.value.as_deref()?.trim().parse().ok()?;
+    let unit = param
+        .unit_accession
+        .as_deref()
+        .or(param.unit_name.as_deref())
+        .unwrap_or("UO:0000010");
+    let scale = match unit {
+        "UO:0000010" | "second" => 1.0,
+        "UO:0000031" | "MS:1000038" | "minute" => 60.0,
+        "UO:0000032" | "hour" => 3600.0,
+        "UO:0000028" | "millisecond" => 1e-3,
+        _ => return None,
+    };
+    Some(value * scale)
+}
+
+pub(crate) fn scan_start_seconds(spectrum: &Spectrum) -> Option<f64> {
+    time_in_seconds(find_cv(first_scan_params(spectrum), ACC_SCAN_START_TIME)?)
+}
+
+/// `ion injection time` of the first scan in milliseconds, its default
+/// unit.
+pub(crate) fn ion_injection_ms(spectrum: &Spectrum) -> Option<f64> {
+    let param = find_cv(first_scan_params(spectrum), ACC_ION_INJECTION_TIME)?;
+    if param.unit_accession.is_none() && param.unit_name.is_none() {
+        return param.value.as_deref()?.trim().parse().ok();
+    }
+    time_in_seconds(param).map(|s| s * 1000.0)
+}
+
+/// cvParams of the first selected ion of the first precursor.
+pub(crate) fn selected_ion_params(spectrum: &Spectrum) -> &[CvParam] {
+    precursor_list(spectrum)
+        .and_then(|l| l.precursors.first())
+        .and_then(|p| p.selected_ion_list.as_ref())
+        .and_then(|l| l.selected_ions.first())
+        .map_or(&[], |ion| &ion.cv_params)
+}
+
+pub(crate) fn precursor_mz(spectrum: &Spectrum) -> Option<f64> {
+    let params = selected_ion_params(spectrum);
+    cv_f64(params, ACC_SELECTED_ION_MZ).or_else(|| cv_f64(params, ACC_MZ))
+}
+
+pub(crate) fn precursor_intensity(spectrum: &Spectrum) -> Option<f64> {
+    cv_f64(selected_ion_params(spectrum), ACC_PEAK_INTENSITY)
+}
+
+/// The charge state of the selected ion, or else its possible charge
+/// states.
+pub(crate) fn precursor_charges(spectrum: &Spectrum) -> Vec<i32> {
+    let params = selected_ion_params(spectrum);
+    let charges = |accession: &str| -> Vec<i32> {
+        params
+            .iter()
+            .filter(|p| p.accession.as_deref() == Some(accession))
+            .filter_map(|p| p.value.as_deref()?.trim().parse::<f64>().ok())
+            .map(|z| z as i32)
+            .collect()
+    };
+    let charges_set = charges(ACC_CHARGE_STATE);
+    if charges_set.is_empty() {
+        charges(ACC_POSSIBLE_CHARGE_STATE)
+    } else {
+        charges_set
+    }
+}
+
+/// Array `accession` in `unit`, compressed with zlib when written if
+/// `zlib`.
+pub(crate) fn data_array(
+    accession: &str,
+    name: &str,
+    unit: (&str, &str),
+    values: BinaryData,
+    zlib: bool,
+) -> BinaryDataArray {
+    let (precision, numeric_type) = match values {
+        BinaryData::F32(_) => (
+            ms_cv("MS:1000521", "32-bit float", None),
+            NumericType::Float32,
+        ),
+        _ => (
+            ms_cv("MS:1000523", "64-bit float", None),
+            NumericType::Float64,
+        ),
+    };
+    let compression = if zlib {
+        ms_cv("MS:1000574", "zlib compression", None)
+    } else {
+        ms_cv("MS:1000576", "no compression", None)
+    };
+    let mut kind = ms_cv(accession, name, None);
+    kind.unit_cv_ref = unit.0.split(':').next().map(str::to_string);
+    kind.unit_accession = Some(unit.0.to_string());
+    kind.unit_name = Some(unit.1.to_string());
+    BinaryDataArray {
+        array_length: Some(binary_len(&values)),
+        cv_params: vec![precision, compression, kind],
+        numeric_type: Some(numeric_type),
+        binary: Some(values),
+        ..Default::default()
+    }
+}
+
+/// m/z and intensity arrays, compressed with zlib when written if `zlib`.
+pub(crate) fn peak_arrays(
+    mz: BinaryData,
+    intensity: BinaryData,
+    zlib: bool,
+) -> BinaryDataArrayList {
+    BinaryDataArrayList {
+        count: Some(2),
+        binary_data_arrays: vec![
+            data_array(ACC_MZ_ARRAY, "m/z array", (ACC_MZ, "m/z"), mz, zlib),
+            data_array(
+                ACC_INTENSITY_ARRAY,
+                "intensity array",
+                ("MS:1000131", "number of detector counts"),
+                intensity,
+                zlib,
+            ),
+        ],
+    }
+}
+
+/// Centroided spectrum at `index` with 64-bit m/z and intensity arrays,
+/// as read from a peak list.
+pub(crate) fn peak_list_spectrum(
+    index: usize,
+    ms_level: u32,
+    mz: Vec<f64>,
+    intensity: Vec<f64>,
+) -> Spectrum {
+    let spectrum_type = if ms_level == 1 {
+        ms_cv("MS:1000579", "MS1 spectrum", None)
+    } else {
+        ms_cv("MS:1000580", "MSn spectrum", None)
+    };
+
+    Spectrum {
+        id: format!("index={index}"),
+        index: Some(index as u32),
+        default_array_length: Some(mz.len()),
+        ms_level: Some(ms_level),
+        cv_params: vec![
+            ms_cv(ACC_MS_LEVEL, "ms level", Some(ms_level.to_string())),
+            spectrum_type,
+            ms_cv("MS:1000127", "centroid spectrum", None),
+        ],
+        binary_data_array_list: Some(peak_arrays(
+            BinaryData::F64(mz),
+            BinaryData::F64(intensity),
+            false,
+        )),
+        ..Default::default()
+    }
+}
+
+/// Sets the scan start time of `spectrum`, in seconds.
+pub(crate) fn set_scan_start_seconds(spectrum: &mut Spectrum, seconds: f64) {
+    first_scan_params_mut(spectrum).push(ms_cv_with_unit(
+        ACC_SCAN_START_TIME,
+        "scan start time",
+        seconds.to_string(),
+        ("UO:0000010", "second"),
+    ));
+}
+
+/// cvParams of the first scan of `spectrum`, adding the scan when there is
+/// none.
+pub(crate) fn first_scan_params_mut(spectrum: &mut Spectrum) -> &mut Vec<CvParam> {
+    let scan_list = spectrum.scan_list.get_or_insert_with(|| ScanList {
+        count: Some(1),
+        cv_params: vec![ms_cv("MS:1000795", "no combination", None)],
+        scans: vec![Scan::default()],
+        ..Default::default()
+    });
+    if scan_list.scans.is_empty() {
+        scan_list.scans.push(Scan::default());
+        scan_list.count = Some(1);
+    }
+    &mut scan_list.scans[0].cv_params
+}
+
+/// cvParams of the selected ion of the first precursor of `spectrum`,
+/// adding the precursor when there is none.
+pub(crate) fn selected_ion_params_mut(spectrum: &mut Spectrum) -> &mut Vec<CvParam> {
+    let list = spectrum
+        .precursor_list
+        .get_or_insert_with(|| PrecursorList {
+            count: Some(1),
+            precursors: vec![Precursor {
+                selected_ion_list: Some(SelectedIonList {
+                    count: Some(1),
+                    selected_ions: vec![SelectedIon::default()],
+                }),
+                activation: Some(Activation::default()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+    if list.precursors.is_empty() {
+        list.precursors.push(Precursor::default());
+    }
+    let ions = list.precursors[0]
+        .selected_ion_list
+        .get_or_insert_with(Default::default);
+    if ions.selected_ions.is_empty() {
+        ions.selected_ions.push(SelectedIon::default());
+        ions.count = Some(1);
+    }
+    &mut ions.selected_ions[0].cv_params
+}
+
+/// Component list with one source, analyzer and detector carrying
+/// `source`, `analyzer` and `detector`, which may be empty when the input
+/// does not say.
+pub(crate) fn component_list(
+    source: Vec<CvParam>,
+    analyzer: Vec<CvParam>,
+    detector: Vec<CvParam>,
+) -> ComponentList {
+    ComponentList {
+        count: Some(3),
+        source: vec![Source {
+            order: Some(1),
+            cv_param: source,
+            ..Default::default()
+        }],
+        analyzer: vec![Analyzer {
+            order: Some(2),
+            cv_param: analyzer,
+            ..Default::default()
+        }],
+        detector: vec![Detector {
+            order: Some(3),
+            cv_param: detector,
+            ..Default::default()
+        }],
+    }
+}
+
+/// Document holding `spectra`, converted from a file that only has peak
+/// lists: the default cvList, one software, data processing and
+/// instrument configuration entry (with components of unknown type), and
+/// `sources` as the source files.
+pub(crate) fn peak_list_mzml(
+    run_id: &str,
+    sources: Vec<SourceFile>,
+    spectra: Vec<Spectrum>,
+) -> MzML {
+    let software = Software {
+        id: "octo".to_string(),
+        version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        cv_param: vec![ms_cv(
+            "MS:1000799",
+            "custom unreleased software tool",
+            Some("octo".to_string()),
+        )],
+        ..Default::default()
+    };
+    let data_processing = DataProcessing {
+        id: "octo_conversion".to_string(),
+        processing_method: vec![ProcessingMethod {
+            order: Some(0),
+            software_ref: Some(software.id.clone()),
+            cv_param: vec![ms_cv("MS:1000544", "Conversion to mzML", None)],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let mut file_content = vec![];
+    if spectra.iter().any(|s| s.ms_level == Some(1)) {
+        file_content.push(ms_cv("MS:1000579", "MS1 spectrum", None));
+    }
+    if spectra.iter().any(|s| s.ms_level.is_some_and(|l| l > 1)) {
+        file_content.push(ms_cv("MS:1000580", "MSn spectrum", None));
+    }
+
+    MzML {
+        cv_list: Some(default_cv_list()),
+        file_description: Some(FileDescription {
+            file_content: FileContent {
+                cv_params: file_content,
+                ..Default::default()
+            },
+            source_file_list: SourceFileList {
+                count: Some(sources.len()),
+                source_file: sources,
+            },
+            ..Default::default()
+        }),
+        instrument_list: Some(InstrumentList {
+            count: Some(1),
+            instrument: vec![Instrument {
+                id: "IC1".to_string(),
+                cv_param: vec![ms_cv("MS:1000031", "instrument model", None)],
+                component_list: Some(component_list(vec![], vec![], vec![])),
+                ..Default::default()
+            }],
+        }),
+        software_list: Some(SoftwareList {
+            count: Some(1),
+            software: vec![software],
+        }),
+        data_processing_list: Some(DataProcessingList {
+            count: Some(1),
+            data_processing: vec![data_processing],
+        }),
+        run: Run {
+            id: run_id.to_string(),
+            default_instrument_configuration_ref: Some("IC1".to_string()),
+            spectrum_list: Some(SpectrumList {
+                count: Some(spectra.len()),
+                default_data_processing_ref: Some("octo_conversion".to_string()),
+                spectra,
+            }),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
@@ -0,0 +1,798 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{Display, Formatter, Result as FmtResult},
+    sync::OnceLock,
+};
+
+use crate::mzml::structs::*;
+
+static MAPPING: OnceLock<CvMapping> = OnceLock::new();
+
+/// The PSI-MS mzML CV-mapping rules in `cv_mapping.json`, with each rule
+/// term expanded to the accessions it accepts.
+fn mapping() -> &'static CvMapping {
+    MAPPING.get_or_init(|| {
+        let raw: RawMapping =
+            serde_json::from_str(include_str!("cv_mapping.json")).expect("cv_mapping.json");
+        CvMapping::build(raw)
+    })
+}
+
+/// How strongly a rule asks for its terms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Requirement {
+    Must,
+    Should,
+    May,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+enum Combination {
+    /// At least one of the terms.
+    Or,
+    /// Every term.
+    And,
+}
+
+#[derive(Deserialize)]
+struct RawMapping {
+    rules: Vec<RawRule>,
+    /// Accession -> all of its descendants in the PSI-MS ontology.
+    children: HashMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct RawRule {
+    id: String,
+    paths: Vec<String>,
+    requirement: Requirement,
+    combination: Combination,
+    repeatable: bool,
+    terms: Vec<RawTerm>,
+}
+
+#[derive(Deserialize)]
+struct RawTerm {
+    accession: String,
+    name: String,
+    allow_children: bool,
+    use_term: bool,
+}
+
+struct Term {
+    label: String,
+    accepts: HashSet<String>,
+}
+
+struct Rule {
+    id: String,
+    requirement: Requirement,
+    combination: Combination,
+    repeatable: bool,
+    terms: Vec<Term>,
+}
+
+impl Rule {
+    fn accepts(&self, accession: &str) -> bool {
+        self.terms
+            .iter()
+            .any(|term| term.accepts.contains(accession))
+    }
+}
+
+struct CvMapping {
+    rules: Vec<Rule>,
+    /// Rule indices by element path, e.g. `/mzML/run/spectrumList/spectrum`.
+    by_path: HashMap<String, Vec<usize>>,
+    /// Accessions some MUST or SHOULD rule asks for; outside the paths of
+    /// the rules that accept them they are reported as forbidden.
+    constrained: HashSet<String>,
+    /// Accession -> all of its descendants in the PSI-MS ontology.
+    children: HashMap<String, Vec<String>>,
+}
+
+impl CvMapping {
+    fn build(raw: RawMapping) -> Self {
+        let mut rules = Vec::with_capacity(raw.rules.len());
+        let mut by_path: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut constrained = HashSet::new();
+
+        for rule in raw.rules {
+            let terms: Vec<Term> = rule
+                .terms
+                .into_iter()
+                .map(|term| {
+                    let mut accepts = HashSet::new();
+                    if term.use_term {
+                        accepts.insert(term.accession.clone());
+                    }
+                    if term.allow_children {
+                        let descendants = raw.children.get(&term.accession);
+                        accepts.extend(descendants.into_iter().flatten().cloned());
+                    }
+                    Term {
+                        label: format!("{} ({})", term.accession, term.name),
+                        accepts,
+                    }
+                })
+                .collect();
+
+            if rule.requirement != Requirement::May {
+                for term in &terms {
+                    constrained.extend(term.accepts.iter().cloned());
+                }
+            }
+            for path in rule.paths {
+                by_path.entry(path).or_default().push(rules.len());
+            }
+            rules.push(Rule {
+                id: rule.id,
+                requirement: rule.requirement,
+                combination: rule.combination,
+                repeatable: rule.repeatable,
+                terms,
+            });
+        }
+
+        CvMapping {
+            rules,
+            by_path,
+            constrained,
+            children: raw.children,
+        }
+    }
+}
+
+/// Descendants of `accession` in the PSI-MS ontology, as far as the
+/// mapping rules need them.
+pub(crate) fn descendants(accession: &str) -> &'static [String] {
+    mapping().children.get(accession).map_or(&[], Vec::as_slice)
+}
+
+/// What is wrong with the CV terms of an element.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum CvIssueKind {
+    /// None of `terms` (or their children, where the rule allows them) is
+    /// present. For rules that need all of their terms, one issue is
+    /// reported per absent term.
+    MissingTerm { rule: String, terms: Vec<String> },
+    /// A rule that allows one of its terms matched several.
+    TooManyTerms { rule: String, found: Vec<String> },
+    /// A term that the rules ask for elsewhere is not allowed on this
+    /// element.
+    ForbiddenTerm { accession: String, name: String },
+}
+
+/// One CV-mapping violation. `path` names the element, with its `id` or
+/// 1-based position among its siblings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CvIssue {
+    pub kind: CvIssueKind,
+    pub requirement: Requirement,
+    pub path: String,
+}
+
+impl Display for CvIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let level = match self.requirement {
+            Requirement::Must => "MUST",
+            Requirement::Should => "SHOULD",
+            Requirement::May => "MAY",
+        };
+        write!(f, "{} [{level}] ", self.path)?;
+        match &self.kind {
+            CvIssueKind::MissingTerm { rule, terms } => {
+                write!(f, "missing {} (rule {rule})", terms.join(" or "))
+            }
+            CvIssueKind::TooManyTerms { rule, found } => {
+                write!(
+                    f,
+                    "only one term allowed but found {} (rule {rule})",
+                    found.join(", ")
+                )
+            }
+            CvIssueKind::ForbiddenTerm { accession, name } => {
+                write!(f, "{accession} ({name}) is not allowed here")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CvValidation {
+    pub issues: Vec<CvIssue>,
+}
+
+impl CvValidation {
+    /// True when no MUST rule is violated; SHOULD issues are advisory.
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        self.issues
+            .iter()
+            .all(|issue| issue.requirement != Requirement::Must)
+    }
+}
+
+/// Checks the cvParams of an `MzML` tree against the bundled PSI-MS
+/// CV-mapping rules: terms an element must (or should) carry, terms it may
+/// carry only once, and terms that belong on other elements. Params of
+/// referenced `<referenceableParamGroup>`s count toward the referencing
+/// element. The rules are those of mzML 1.1; pre-release (0.99) documents
+/// keep some terms elsewhere and are reported accordingly.
+pub fn validate_cv_terms(mzml: &MzML) -> CvValidation {
+    let groups = mzml
+        .referenceable_param_group_list
+        .iter()
+        .flat_map(|list| &list.referenceable_param_groups)
+        .map(|group| (group.id.as_str(), group.cv_params.as_slice()))
+        .collect();
+    let mut checker = Checker {
+        mapping: mapping(),
+        groups,
+        issues: Vec::new(),
+    };
+    checker.document(mzml);
+    CvValidation {
+        issues: checker.issues,
+    }
+}
+
+struct Checker<'a> {
+    mapping: &'static CvMapping,
+    groups: HashMap<&'a str, &'a [CvParam]>,
+    issues: Vec<CvIssue>,
+}
+
+/// `/parent/name[@id='id']`, or `/parent/name[n]` for elements without an id.
+pub(crate) fn child(parent: &str, name: &str, id: Option<&str>, index: usize) -> String {
+    match id {
+        Some(id) => format!("{parent}/{name}[@id='{id}']"),
+        None => format!("{parent}/{name}[{}]", index + 1),
+    }
+}
+
+impl<'a> Checker<'a> {
+    fn accessions(
+        &self,
+        refs: &'a [ReferenceableParamGroupRef],
+        cv_params: &'a [CvParam],
+    ) -> Vec<&'a str> {
+        let grouped = refs
+            .iter()
+            .filter_map(|r| self.groups.get(r.r#ref.as_str()).copied())
+            .flatten();
+        cv_params
+            .iter()
+            .chain(grouped)
+            .filter_map(|p| p.accession.as_deref())
+            .collect()
+    }
+
+    /// Applies the rules for `rule_path` to the accessions of the element
+    /// at `path`.
+    fn check(&mut self, rule_path: &str, path: &str, accessions: &[&str]) {
+        let rules: Vec<&Rule> = self
+            .mapping
+            .by_path
+            .get(rule_path)
+            .into_iter()
+            .flatten()
+            .map(|&i| &self.mapping.rules[i])
+            .collect();
+
+        for rule in &rules {
+            let report = |kind| CvIssue {
+                kind,
+                requirement: rule.requirement,
+                path: path.to_string(),
+            };
+            let groups: Vec<(Vec<&Term>, Vec<&str>)> = match rule.combination {
+                Combination::Or => vec![(
+                    rule.terms.iter().collect(),
+                    accessions
+                        .iter()
+                        .copied()
+                        .filter(|a| rule.accepts(a))
+                        .collect(),
+                )],
+                Combination::And => rule
+                    .terms
+                    .iter()
+                    .map(|term| {
+                        let found = accessions
+                            .iter()
+                            .copied()
+                            .filter(|a| term.accepts.contains(*a))
+                            .collect();
+                        (vec![term], found)
+                    })
+                    .collect(),
+            };
+            for (terms, found) in groups {
+                if found.is_empty() && rule.requirement != Requirement::May {
+                    self.issues.push(report(CvIssueKind::MissingTerm {
+                        rule: rule.id.clone(),
+                        terms: terms.iter().map(|term| term.label.clone()).collect(),
+                    }));
+                } else if !rule.repeatable && found.len() > 1 {
+                    self.issues.push(report(CvIssueKind::TooManyTerms {
+                        rule: rule.id.clone(),
+                        found: found.iter().map(|a| a.to_string()).collect(),
+                    }));
+                }
+            }
+        }
+
+        for &accession in accessions {
+            if self.mapping.constrained.contains(accession)
+                && !rules.iter().any(|rule| rule.accepts(accession))
+            {
+                let name = crate::b64::decoder::utilities::cv_table::get(accession)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string();
+                self.issues.push(CvIssue {
+                    kind: CvIssueKind::ForbiddenTerm {
+                        accession: accession.to_string(),
+                        name,
+                    },
+                    requirement: Requirement::Must,
+                    path: path.to_string(),
+                });
+            }
+        }
+    }
+
+    fn element(
+        &mut self,
+        rule_path: &str,
+        path: &str,
+        refs: &'a [ReferenceableParamGroupRef],
+        cv_params: &'a [CvParam],
+    ) {
+        let accessions = self.accessions(refs, cv_params);
+        self.check(rule_path, path, &accessions);
+    }
+
+    fn document(&mut self, mzml: &'a MzML) {
+        if let Some(fd) = &mzml.file_description {
+            let base = "/mzML/fileDescription";
+            let content = &fd.file_content;
+            self.element(
+                &format!("{base}/fileContent"),
+                &format!("{base}/fileContent"),
+                &content.referenceable_param_group_refs,
+                &content.cv_params,
+            );
+            let rule = format!("{base}/sourceFileList/sourceFile");
+            for (i, sf) in fd.source_file_list.source_file.iter().enumerate() {
+                let path = child(
+                    &format!("{base}/sourceFileList"),
+                    "sourceFile",
+                    Some(&sf.id),
+                    i,
+                );
+                self.element(
+                    &rule,
+                    &path,
+                    &sf.referenceable_param_group_ref,
+                    &sf.cv_param,
+                );
+            }
+        }
+
+        if let Some(list) = &mzml.instrument_list {
+            let base = "/mzML/instrumentConfigurationList";
+            let rule = format!("{base}/instrumentConfiguration");
+            for (i, ic) in list.instrument.iter().enumerate() {
+                let path = child(base, "instrumentConfiguration", Some(&ic.id), i);
+                self.element(
+                    &rule,
+                    &path,
+                    &ic.referenceable_param_group_ref,
+                    &ic.cv_param,
+                );
+
+                let Some(components) = &ic.component_list else {
+                    continue;
+                };
+                let list_path = format!("{path}/componentList");
+                let list_rule = format!("{rule}/componentList");
+                for (j, c) in components.source.iter().enumerate() {
+                    self.element(
+                        &format!("{list_rule}/source"),
+                        &child(&list_path, "source", None, j),
+                        &c.referenceable_param_group_ref,
+                        &c.cv_param,
+                    );
+                }
+                for (j, c) in components.analyzer.iter().enumerate() {
+                    self.element(
+                        &format!("{list_rule}/analyzer"),
+                        &child(&list_path, "analyzer", None, j),
+                        &c.referenceable_param_group_ref,
+                        &c.cv_param,
+                    );
+                }
+                for (j, c) in components.detector.iter().enumerate() {
+                    self.element(
+                        &format!("{list_rule}/detector"),
+                        &child(&list_path, "detector", None, j),
+                        &c.referenceable_param_group_ref,
+                        &c.cv_param,
+                    );
+                }
+            }
+        }
+
+        if let Some(list) = &mzml.software_list {
+            let base = "/mzML/softwareList";
+            let rule = format!("{base}/software");
+            for (i, sw) in list.software.iter().enumerate() {
+                // mzML 0.99 names the software with <softwareParam>.
+                let mut accessions = self.accessions(&[], &sw.cv_param);
+                accessions.extend(sw.software_param.iter().map(|p| p.accession.as_str()));
+                self.check(
+                    &rule,
+                    &child(base, "software", Some(&sw.id), i),
+                    &accessions,
+                );
+            }
+        }
+
+        if let Some(list) = &mzml.data_processing_list {
+            let base = "/mzML/dataProcessingList";
+            let rule = format!("{base}/dataProcessing/processingMethod");
+            for (i, dp) in list.data_processing.iter().enumerate() {
+                let dp_path = child(base, "dataProcessing", Some(&dp.id), i);
+                for (j, pm) in dp.processing_method.iter().enumerate() {
+                    self.element(
+                        &rule,
+                        &child(&dp_path, "processingMethod", None, j),
+                        &pm.referenceable_param_group_ref,
+                        &pm.cv_param,
+                    );
+                }
+            }
+        }
+
+        if let Some(list) = &mzml.run.spectrum_list {
+            for (i, spectrum) in list.spectra.iter().enumerate() {
+                self.spectrum(spectrum, i);
+            }
+        }
+        if let Some(list) = &mzml.run.chromatogram_list {
+            for (i, chromatogram) in list.chromatograms.iter().enumerate() {
+                self.chromatogram(chromatogram, i);
+            }
+        }
+    }
+
+    fn spectrum(&mut self, spectrum: &'a Spectrum, index: usize) {
+        let rule = "/mzML/run/spectrumList/spectrum";
+        let path = child(
+            "/mzML/run/spectrumList",
+            "spectrum",
+            Some(&spectrum.id),
+            index,
+        );
+
+        // mzML 1.0 keeps part of the spectrum params and children in
+        // <spectrumDescription>.
+        let description = spectrum.spectrum_description.as_ref();
+        let mut accessions = self.accessions(
+            &spectrum.referenceable_param_group_refs,
+            &spectrum.cv_params,
+        );
+        if let Some(d) = description {
+            accessions.extend(self.accessions(&d.referenceable_param_group_refs, &d.cv_params));
+        }
+        self.check(rule, &path, &accessions);
+
+        let scan_list = spectrum
+            .scan_list
+            .as_ref()
+            .or_else(|| description?.scan_list.as_ref());
+        if let Some(list) = scan_list {
+            let list_rule = format!("{rule}/scanList");
+            let list_path = format!("{path}/scanList");
+            self.element(&list_rule, &list_path, &[], &list.cv_params);
+            for (i, scan) in list.scans.iter().enumerate() {
+                let scan_path = child(&list_path, "scan", None, i);
+                self.element(
+                    &format!("{list_rule}/scan"),
+                    &scan_path,
+                    &scan.referenceable_param_group_refs,
+                    &scan.cv_params,
+                );
+                let windows = scan.scan_window_list.iter().flat_map(|l| &l.scan_windows);
+                for (j, window) in windows.enumerate() {
+                    self.element(
+                        &format!("{list_rule}/scan/scanWindowList/scanWindow"),
+                        &child(
+                            &format!("{scan_path}/scanWindowList"),
+                            "scanWindow",
+                            None,
+                            j,
+                        ),
+                        &[],
+                        &window.cv_params,
+                    );
+                }
+            }
+        }
+
+        let precursor_list = spectrum
+            .precursor_list
+            .as_ref()
+            .or_else(|| description?.precursor_list.as_ref());
+        for (i, precursor) in precursor_list
+            .iter()
+            .flat_map(|l| &l.precursors)
+            .enumerate()
+        {
+            self.precursor(
+                &format!("{rule}/precursorList/precursor"),
+                &child(&format!("{path}/precursorList"), "precursor", None, i),
+                precursor,
+            );
+        }
+
+        let product_list = spectrum
+            .product_list
+            .as_ref()
+            .or_else(|| description?.product_list.as_ref());
+        for (i, product) in product_list.iter().flat_map(|l| &l.products).enumerate() {
+            self.product(
+                &format!("{rule}/productList/product"),
+                &child(&format!("{path}/productList"), "product", None, i),
+                product,
+            );
+        }
+
+        self.binary_data_arrays(rule, &path, spectrum.binary_data_array_list.as_ref());
+    }
+
+    fn chromatogram(&mut self, chromatogram: &'a Chromatogram, index: usize) {
+        let rule = "/mzML/run/chromatogramList/chromatogram";
+        let path = child(
+            "/mzML/run/chromatogramList",
+            "chromatogram",
+            Some(&chromatogram.id),
+            index,
+        );
+        self.element(
+            rule,
+            &path,
+            &chromatogram.referenceable_param_group_refs,
+            &chromatogram.cv_params,
+        );
+        if let Some(precursor) = &chromatogram.precursor {
+            self.precursor(
+                &format!("{rule}/precursor"),
+                &format!("{path}/precursor"),
+                precursor,
+            );
+        }
+        if let Some(product) = &chromatogram.product {
+            self.product(
+                &format!("{rule}/product"),
+                &format!("{path}/product"),
+                product,
+            );
+        }
+        self.binary_data_arrays(rule, &path, chromatogram.binary_data_array_list.as_ref());
+    }
+
+    fn precursor(&mut self, rule: &str, path: &str, precursor: &'a Precursor) {
+        if let Some(window) = &precursor.isolation_window {
+            self.element(
+                &format!("{rule}/isolationWindow"),
+                &format!("{path}/isolationWindow"),
+                &window.referenceable_param_group_refs,
+                &window.cv_params,
+            );
+        }
+        let ions = precursor
+            .selected_ion_list
+            .iter()
+            .flat_map(|l| &l.selected_ions);
+        for (i, ion) in ions.enumerate() {
+            self.element(
+                &format!("{rule}/selectedIonList/selectedIon"),
+                &child(&format!("{path}/selectedIonList"), "selectedIon", None, i),
+                &ion.referenceable_param_group_refs,
+                &ion.cv_params,
+            );
+        }
+        if let Some(activation) = &precursor.activation {
+            self.element(
+                &format!("{rule}/activation"),
+                &format!("{path}/activation"),
+                &activation.referenceable_param_group_refs,
+                &activation.cv_params,
+            );
+        }
+    }
+
+    fn product(&mut self, rule: &str, path: &str, product: &'a Product) {
+        if let Some(window) = &product.isolation_window {
+            self.element(
+                &format!("{rule}/isolationWindow"),
+                &format!("{path}/isolationWindow"),
+                &window.referenceable_param_group_refs,
+                &window.cv_params,
+            );
+        }
+    }
+
+    fn binary_data_arrays(
+        &mut self,
+        rule: &str,
+        path: &str,
+        list: Option<&'a BinaryDataArrayList>,
+    ) {
+        let rule = format!("{rule}/binaryDataArrayList/binaryDataArray");
+        let list_path = format!("{path}/binaryDataArrayList");
+        for (i, bda) in list.iter().flat_map(|l| &l.binary_data_arrays).enumerate() {
+            self.element(
+                &rule,
+                &child(&list_path, "binaryDataArray", None, i),
+                &bda.referenceable_param_group_refs,
+                &bda.cv_params,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mzml::parse_mzml::parse_mzml;
+
+    const PATH: &str = "data/mzml/test.mzML";
+
+    fn document() -> MzML {
+        parse_mzml(&std::fs::read(PATH).unwrap()).unwrap()
+    }
+
+    fn kinds(validation: &CvValidation) -> Vec<&CvIssueKind> {
+        validation.issues.iter().map(|issue| &issue.kind).collect()
+    }
+
+    fn param(accession: &str, name: &str) -> CvParam {
+        CvParam {
+            cv_ref: Some("MS".to_string()),
+            accession: Some(accession.to_string()),
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn bundled_file_follows_the_mapping() {
+        let validation = validate_cv_terms(&document());
+        assert!(validation.is_valid(), "{:#?}", validation.issues);
+        // The instrument model comes from a referenceableParamGroup.
+        assert!(
+            validation
+                .issues
+                .iter()
+                .all(|issue| !issue.path.contains("instrumentConfiguration"))
+        );
+    }
+
+    #[test]
+    fn spectrum_without_a_type_is_reported() {
+        let mut mzml = document();
+        let spectrum = &mut mzml.run.spectrum_list.as_mut().unwrap().spectra[0];
+        spectrum
+            .cv_params
+            .retain(|p| !matches!(p.accession.as_deref(), Some("MS:1000579" | "MS:1000580")));
+        let id = spectrum.id.clone();
+
+        let validation = validate_cv_terms(&mzml);
+        assert!(!validation.is_valid());
+        assert_eq!(
+            kinds(&validation),
+            [&CvIssueKind::MissingTerm {
+                rule: "spectrum_type".to_string(),
+                terms: vec!["MS:1000559 (spectrum type)".to_string()],
+            }]
+        );
+        assert_eq!(
+            validation.issues[0].path,
+            format!("/mzML/run/spectrumList/spectrum[@id='{id}']")
+        );
+        assert_eq!(validation.issues[0].requirement, Requirement::Must);
+    }
+
+    #[test]
+    fn second_compression_term_is_reported() {
+        let mut mzml = document();
+        let spectrum = &mut mzml.run.spectrum_list.as_mut().unwrap().spectra[0];
+        let bda = &mut spectrum
+            .binary_data_array_list
+            .as_mut()
+            .unwrap()
+            .binary_data_arrays[1];
+        bda.cv_params.push(param("MS:1000574", "zlib compression"));
+
+        let validation = validate_cv_terms(&mzml);
+        assert_eq!(
+            kinds(&validation),
+            [&CvIssueKind::TooManyTerms {
+                rule: "binary_data_compression".to_string(),
+                found: vec!["MS:1000576".to_string(), "MS:1000574".to_string()],
+            }]
+        );
+        assert!(
+            validation.issues[0]
+                .path
+                .ends_with("/binaryDataArrayList/binaryDataArray[2]")
+        );
+    }
+
+    #[test]
+    fn term_from_another_element_is_forbidden() {
+        let mut mzml = document();
+        let chromatogram = &mut mzml.run.chromatogram_list.as_mut().unwrap().chromatograms[0];
+        chromatogram
+            .cv_params
+            .push(param("MS:1000579", "MS1 spectrum"));
+
+        let validation = validate_cv_terms(&mzml);
+        assert_eq!(
+            kinds(&validation),
+            [&CvIssueKind::ForbiddenTerm {
+                accession: "MS:1000579".to_string(),
+                name: "MS1 spectrum".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn should_rules_do_not_invalidate() {
+        let mut mzml = document();
+        let source_file = &mut mzml
+            .file_description
+            .as_mut()
+            .unwrap()
+            .source_file_list
+            .source_file[0];
+        source_file
+            .cv_param
+            .retain(|p| p.accession.as_deref() != Some("MS:1000569"));
+
+        let validation = validate_cv_terms(&mzml);
+        assert!(validation.is_valid());
+        assert_eq!(validation.issues.len(), 1);
+        assert_eq!(validation.issues[0].requirement, Requirement::Should);
+    }
+
+    #[test]
+    fn scan_window_needs_both_limits() {
+        let mut mzml = document();
+        let spectrum = &mut mzml.run.spectrum_list.as_mut().unwrap().spectra[0];
+        let window = &mut spectrum.scan_list.as_mut().unwrap().scans[0]
+            .scan_window_list
+            .as_mut()
+            .unwrap()
+            .scan_windows[0];
+        window
+            .cv_params
+            .retain(|p| p.accession.as_deref() != Some("MS:1000500"));
+
+        let validation = validate_cv_terms(&mzml);
+        assert_eq!(
+            kinds(&validation),
+            [&CvIssueKind::MissingTerm {
+                rule: "scan_window_limits".to_string(),
+                terms: vec!["MS:1000500 (scan window upper limit)".to_string()],
+            }]
+        );
+    }
+}
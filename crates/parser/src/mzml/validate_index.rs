@@ -0,0 +1,225 @@
+use serde::Serialize;
+use std::{collections::HashMap, io::Cursor};
+
+use crate::mzml::{
+    indexed_mzml_reader::scan_offsets,
+    structs::*,
+    utilities::{ParseError, parse_index_list::parse_index_list},
+};
+
+const CHECKSUM_OPEN: &[u8] = b"<fileChecksum>";
+
+/// One problem found by `validate_indexed_mzml`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum IndexIssue {
+    MissingIndexList,
+    MissingIndexListOffset,
+    /// `<indexListOffset>` does not point at `<indexList>`.
+    WrongIndexListOffset {
+        stated: u64,
+        actual: Option<u64>,
+    },
+    /// An `<offset>` does not point at the element it names; `actual` is
+    /// `None` when no element with that id exists.
+    WrongOffset {
+        index: String,
+        id_ref: Option<String>,
+        stated: u64,
+        actual: Option<u64>,
+    },
+    /// Elements present in the document but absent from the index.
+    Unindexed {
+        index: String,
+        id: Option<String>,
+    },
+    MissingChecksum,
+    ChecksumMismatch {
+        stated: String,
+        actual: String,
+    },
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IndexValidation {
+    pub issues: Vec<IndexIssue>,
+}
+
+impl IndexValidation {
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Checks the `<fileChecksum>` SHA-1, `<indexListOffset>` and every
+/// `<offset>` of an indexed mzML document against its actual bytes.
+pub fn validate_indexed_mzml(bytes: &[u8]) -> Result<IndexValidation, ParseError> {
+    let mut issues = Vec::new();
+    let stated = parse_index_list(bytes)?;
+    let actual = scan_offsets(&mut Cursor::new(bytes))?;
+
+    match stated.as_ref().and_then(|s| s.index_list_offset) {
+        None => issues.push(IndexIssue::MissingIndexListOffset),
+        Some(offset) => {
+            let list_at = rfind(bytes, b"<indexList ")
+                .or_else(|| rfind(bytes, b"<indexList>"))
+                .map(|p| p as u64);
+            if list_at != Some(offset) {
+                issues.push(IndexIssue::WrongIndexListOffset {
+                    stated: offset,
+                    actual: list_at,
+                });
+            }
+        }
+    }
+
+    match &stated {
+        Some(index) if !index.spectrum.is_empty() || !index.chromatogram.is_empty() => {
+            check_offsets("spectrum", &index.spectrum, &actual.spectrum, &mut issues);
+            check_offsets(
+                "chromatogram",
+                &index.chromatogram,
+                &actual.chromatogram,
+                &mut issues,
+            );
+        }
+        _ if actual.spectrum.is_empty() && actual.chromatogram.is_empty() => {}
+        _ => issues.push(IndexIssue::MissingIndexList),
+    }
+
+    match stated.as_ref().and_then(|s| s.file_checksum.as_deref()) {
+        None => issues.push(IndexIssue::MissingChecksum),
+        Some(stated) => {
+            let actual = rfind(bytes, CHECKSUM_OPEN)
+                .map(|p| sha1_smol::Sha1::from(&bytes[..p + CHECKSUM_OPEN.len()]))
+                .map(|h| h.digest().to_string())
+                .unwrap_or_default();
+            let stated = stated.trim();
+            if !stated.eq_ignore_ascii_case(&actual) {
+                issues.push(IndexIssue::ChecksumMismatch {
+                    stated: stated.to_string(),
+                    actual,
+                });
+            }
+        }
+    }
+
+    Ok(IndexValidation { issues })
+}
+
+fn check_offsets(
+    index: &str,
+    stated: &[IndexOffset],
+    actual: &[IndexOffset],
+    issues: &mut Vec<IndexIssue>,
+) {
+    let by_id: HashMap<&str, u64> = actual
+        .iter()
+        .filter_map(|o| Some((o.id_ref.as_deref()?, o.offset)))
+        .collect();
+
+    for entry in stated {
+        let found = entry
+            .id_ref
+            .as_deref()
+            .and_then(|id| by_id.get(id).copied());
+        if found != Some(entry.offset) {
+            issues.push(IndexIssue::WrongOffset {
+                index: index.to_string(),
+                id_ref: entry.id_ref.clone(),
+                stated: entry.offset,
+                actual: found,
+            });
+        }
+    }
+
+    let listed: HashMap<&str, ()> = stated
+        .iter()
+        .filter_map(|o| Some((o.id_ref.as_deref()?, ())))
+        .collect();
+    for element in actual {
+        if !element
+            .id_ref
+            .as_deref()
+            .is_some_and(|id| listed.contains_key(id))
+        {
+            issues.push(IndexIssue::Unindexed {
+                index: index.to_string(),
+                id: element.id_ref.clone(),
+            });
+        }
+    }
+}
+
+#[inline]
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).rposition(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mzml::{bin_to_mzml::bin_to_mzml, parse_mzml::parse_mzml};
+
+    fn written() -> String {
+        let bytes = std::fs::read("data/mzml/tiny.pwiz.mzML0.99.10.mzML").unwrap();
+        bin_to_mzml(&parse_mzml(&bytes).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn written_documents_validate() {
+        let xml = written();
+        assert!(xml.contains("<fileChecksum>"));
+        let report = validate_indexed_mzml(xml.as_bytes()).unwrap();
+        assert!(report.is_valid(), "{:?}", report.issues);
+    }
+
+    #[test]
+    fn edited_document_fails_checksum() {
+        let xml = written().replacen("<cvList", "<cvList ", 1);
+        let issues = validate_indexed_mzml(xml.as_bytes()).unwrap().issues;
+        assert!(matches!(
+            issues.last(),
+            Some(IndexIssue::ChecksumMismatch { .. })
+        ));
+        assert!(
+            issues
+                .iter()
+                .any(|i| matches!(i, IndexIssue::WrongIndexListOffset { .. }))
+        );
+        assert!(
+            issues
+                .iter()
+                .any(|i| matches!(i, IndexIssue::WrongOffset { .. }))
+        );
+    }
+
+    #[test]
+    fn stale_fixture_index_is_reported() {
+        let bytes = std::fs::read("data/mzml/tiny.pwiz.mzML0.99.10.mzML").unwrap();
+        let issues = validate_indexed_mzml(&bytes).unwrap().issues;
+        assert!(issues.iter().any(|i| matches!(
+            i,
+            IndexIssue::WrongOffset { index, id_ref: Some(id), stated: 11451, actual: Some(_) }
+                if index == "chromatogram" && id == "tic"
+        )));
+        assert!(matches!(
+            issues.last(),
+            Some(IndexIssue::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn plain_mzml_reports_missing_index() {
+        let bytes = std::fs::read("data/mzml/tiny4_LTQ-FT.mzML0.99.1.mzML").unwrap();
+        let issues = validate_indexed_mzml(&bytes).unwrap().issues;
+        assert_eq!(
+            issues,
+            [
+                IndexIssue::MissingIndexListOffset,
+                IndexIssue::MissingIndexList,
+                IndexIssue::MissingChecksum
+            ]
+        );
+    }
+}
@@ -0,0 +1,1024 @@
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{Display, Formatter, Result as FmtResult},
+};
+
+use crate::mzml::{structs::*, validate_cv::child};
+
+/// The kinds of element that references point at. Ids are unique per kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IdKind {
+    ReferenceableParamGroup,
+    SourceFile,
+    Sample,
+    InstrumentConfiguration,
+    Software,
+    DataProcessing,
+    ScanSettings,
+    Spectrum,
+    Chromatogram,
+}
+
+impl IdKind {
+    fn element(self) -> &'static str {
+        match self {
+            IdKind::ReferenceableParamGroup => "referenceableParamGroup",
+            IdKind::SourceFile => "sourceFile",
+            IdKind::Sample => "sample",
+            IdKind::InstrumentConfiguration => "instrumentConfiguration",
+            IdKind::Software => "software",
+            IdKind::DataProcessing => "dataProcessing",
+            IdKind::ScanSettings => "scanSettings",
+            IdKind::Spectrum => "spectrum",
+            IdKind::Chromatogram => "chromatogram",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum RefIssueKind {
+    /// `attribute` names a `target` element that does not exist.
+    DanglingRef {
+        attribute: String,
+        target: IdKind,
+        id: String,
+    },
+    /// An earlier element of the same kind already has this id; references
+    /// resolve to the earlier one.
+    DuplicateId { kind: IdKind, id: String },
+}
+
+/// What `fix_refs` did about an issue.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum RefFix {
+    /// The reference was removed.
+    Dropped,
+    /// The reference or duplicate id was replaced by `to`.
+    Rewritten { to: String },
+}
+
+/// One integrity problem. `path` names the element holding the reference
+/// or id by its 1-based position among its siblings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RefIssue {
+    pub kind: RefIssueKind,
+    pub path: String,
+    /// Set by `fix_refs`.
+    pub fix: Option<RefFix>,
+}
+
+impl Display for RefIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}: ", self.path)?;
+        match &self.kind {
+            RefIssueKind::DanglingRef {
+                attribute,
+                target,
+                id,
+            } => write!(f, "{attribute}=\"{id}\" names no <{}>", target.element())?,
+            RefIssueKind::DuplicateId { kind, id } => {
+                write!(f, "duplicate <{}> id \"{id}\"", kind.element())?
+            }
+        }
+        match &self.fix {
+            Some(RefFix::Dropped) => write!(f, " (dropped)"),
+            Some(RefFix::Rewritten { to }) => write!(f, " (now \"{to}\")"),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RefValidation {
+    pub issues: Vec<RefIssue>,
+}
+
+impl RefValidation {
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Reports references that name no element (`dataProcessingRef`,
+/// `instrumentConfigurationRef`, `sampleRef`, `spectrumRef`,
+/// `<sourceFileRef>`, `<referenceableParamGroupRef>` and the other
+/// id references of the model) and ids used by more than one element of
+/// the same kind.
+pub fn validate_refs(mzml: &MzML) -> RefValidation {
+    let mut refs = Refs::new(false);
+    walk(mzml, &mut refs);
+    refs.phase = Phase::Refs;
+    walk(mzml, &mut refs);
+    RefValidation {
+        issues: refs.issues,
+    }
+}
+
+/// `validate_refs`, repairing what it finds: duplicate ids get a
+/// `_2`, `_3`, ... suffix, references the schema requires
+/// (`defaultInstrumentConfigurationRef`, `defaultDataProcessingRef`,
+/// `softwareRef`) are pointed at the first element of their kind when there
+/// is one, and the other dangling references are dropped. Returns the issues
+/// with the fix applied to each.
+pub fn fix_refs(mzml: &mut MzML) -> RefValidation {
+    let mut refs = Refs::new(true);
+    walk_mut(mzml, &mut refs);
+    refs.phase = Phase::Refs;
+    walk_mut(mzml, &mut refs);
+
+    let settings = mzml
+        .scan_settings_list
+        .iter_mut()
+        .flat_map(|l| &mut l.scan_settings);
+    let lists = settings
+        .filter_map(|s| s.source_file_ref_list.as_mut())
+        .chain(mzml.run.source_file_ref_list.as_mut());
+    for list in lists {
+        if list.count.is_some() {
+            list.count = Some(list.source_file_refs.len());
+        }
+    }
+
+    RefValidation {
+        issues: refs.issues,
+    }
+}
+
+/// A field naming an element by id.
+trait IdRef {
+    fn target(&self) -> &str;
+    fn retarget(&mut self, id: String);
+}
+
+impl IdRef for String {
+    fn target(&self) -> &str {
+        self
+    }
+    fn retarget(&mut self, id: String) {
+        *self = id;
+    }
+}
+
+macro_rules! impl_id_ref {
+    ($($ty:ty),*) => {$(
+        impl IdRef for $ty {
+            fn target(&self) -> &str {
+                &self.r#ref
+            }
+            fn retarget(&mut self, id: String) {
+                self.r#ref = id;
+            }
+        }
+    )*};
+}
+
+impl_id_ref!(
+    ReferenceableParamGroupRef,
+    ScanSettingsRef,
+    InstrumentSoftwareRef,
+    SourceFileRef
+);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Ids,
+    Refs,
+}
+
+/// Whether a dangling reference is repaired by pointing it at the first
+/// element of its kind (the schema requires the attribute) or by dropping it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Repair {
+    First,
+    Remove,
+}
+
+struct Refs {
+    fix: bool,
+    phase: Phase,
+    /// Ids by kind, and the first id of each kind.
+    ids: HashMap<IdKind, HashSet<String>>,
+    first: HashMap<IdKind, String>,
+    issues: Vec<RefIssue>,
+}
+
+impl Refs {
+    fn new(fix: bool) -> Self {
+        Refs {
+            fix,
+            phase: Phase::Ids,
+            ids: HashMap::new(),
+            first: HashMap::new(),
+            issues: Vec::new(),
+        }
+    }
+
+    fn exists(&self, kind: IdKind, id: &str) -> bool {
+        self.ids.get(&kind).is_some_and(|ids| ids.contains(id))
+    }
+
+    /// Records `id`; returns its replacement when it is a duplicate and
+    /// ids are being fixed.
+    fn id(&mut self, kind: IdKind, path: &str, id: &str) -> Option<String> {
+        if self.phase != Phase::Ids {
+            return None;
+        }
+        let ids = self.ids.entry(kind).or_default();
+        if ids.insert(id.to_string()) {
+            self.first.entry(kind).or_insert_with(|| id.to_string());
+            return None;
+        }
+        let renamed = self.fix.then(|| {
+            let renamed = (2..)
+                .map(|n| format!("{id}_{n}"))
+                .find(|candidate| !ids.contains(candidate))
+                .unwrap();
+            ids.insert(renamed.clone());
+            renamed
+        });
+        self.issues.push(RefIssue {
+            kind: RefIssueKind::DuplicateId {
+                kind,
+                id: id.to_string(),
+            },
+            path: path.to_string(),
+            fix: renamed.clone().map(|to| RefFix::Rewritten { to }),
+        });
+        renamed
+    }
+
+    /// Checks one reference; returns the fix to apply when it dangles and
+    /// references are being fixed.
+    fn check(
+        &mut self,
+        path: &str,
+        attribute: &str,
+        target: IdKind,
+        repair: Repair,
+        id: &str,
+    ) -> Option<RefFix> {
+        if self.phase != Phase::Refs || self.exists(target, id) {
+            return None;
+        }
+        let fix = self.fix.then(|| match (repair, self.first.get(&target)) {
+            (Repair::First, Some(first)) => RefFix::Rewritten { to: first.clone() },
+            _ => RefFix::Dropped,
+        });
+        self.issues.push(RefIssue {
+            kind: RefIssueKind::DanglingRef {
+                attribute: attribute.to_string(),
+                target,
+                id: id.to_string(),
+            },
+            path: path.to_string(),
+            fix: fix.clone(),
+        });
+        fix
+    }
+}
+
+/// Visits a reference field of type `Option<T>` or `Vec<T>`, or an id field,
+/// through `&` or `&mut` depending on the walker.
+trait Visit<Slot> {
+    fn visit(&mut self, path: &str, attribute: &str, target: IdKind, repair: Repair, slot: Slot);
+}
+
+struct Checking<'r>(&'r mut Refs);
+struct Fixing<'r>(&'r mut Refs);
+
+impl<T: IdRef> Visit<&Option<T>> for Checking<'_> {
+    fn visit(
+        &mut self,
+        path: &str,
+        attribute: &str,
+        target: IdKind,
+        repair: Repair,
+        slot: &Option<T>,
+    ) {
+        if let Some(r) = slot {
+            self.0.check(path, attribute, target, repair, r.target());
+        }
+    }
+}
+
+impl<T: IdRef> Visit<&Vec<T>> for Checking<'_> {
+    fn visit(
+        &mut self,
+        path: &str,
+        attribute: &str,
+        target: IdKind,
+        repair: Repair,
+        slot: &Vec<T>,
+    ) {
+        for r in slot {
+            self.0.check(path, attribute, target, repair, r.target());
+        }
+    }
+}
+
+impl<T: IdRef> Visit<&mut Option<T>> for Fixing<'_> {
+    fn visit(
+        &mut self,
+        path: &str,
+        attribute: &str,
+        target: IdKind,
+        repair: Repair,
+        slot: &mut Option<T>,
+    ) {
+        let Some(r) = slot else {
+            return;
+        };
+        match self.0.check(path, attribute, target, repair, r.target()) {
+            Some(RefFix::Rewritten { to }) => r.retarget(to),
+            Some(RefFix::Dropped) => *slot = None,
+            None => {}
+        }
+    }
+}
+
+impl<T: IdRef> Visit<&mut Vec<T>> for Fixing<'_> {
+    fn visit(
+        &mut self,
+        path: &str,
+        attribute: &str,
+        target: IdKind,
+        repair: Repair,
+        slot: &mut Vec<T>,
+    ) {
+        slot.retain_mut(
+            |r| match self.0.check(path, attribute, target, repair, r.target()) {
+                Some(RefFix::Rewritten { to }) => {
+                    r.retarget(to);
+                    true
+                }
+                Some(RefFix::Dropped) => false,
+                None => true,
+            },
+        );
+    }
+}
+
+impl Visit<&String> for Checking<'_> {
+    fn visit(&mut self, path: &str, _: &str, kind: IdKind, _: Repair, id: &String) {
+        self.0.id(kind, path, id);
+    }
+}
+
+impl Visit<&mut String> for Fixing<'_> {
+    fn visit(&mut self, path: &str, _: &str, kind: IdKind, _: Repair, id: &mut String) {
+        if let Some(renamed) = self.0.id(kind, path, id) {
+            *id = renamed;
+        }
+    }
+}
+
+/// Generates a walker over every id and id reference of an `MzML`, for
+/// shared (`walk`) or exclusive (`walk_mut`) access.
+macro_rules! walker {
+    ($name:ident, $visitor:ident $(, $mut:ident)?) => {
+        fn $name(mzml: &$($mut)? MzML, refs: &mut Refs) {
+            use IdKind as K;
+            use Repair as R;
+            let v = &mut $visitor(refs);
+            let id = |v: &mut $visitor, path: &str, kind, id: &$($mut)? String| {
+                v.visit(path, "id", kind, R::Remove, id)
+            };
+            let group_refs = "referenceableParamGroupRef";
+
+            if let Some(list) = &$($mut)? mzml.referenceable_param_group_list {
+                let base = "/mzML/referenceableParamGroupList";
+                let groups = &$($mut)? list.referenceable_param_groups;
+                for (i, group) in groups.into_iter().enumerate() {
+                    let path = child(base, "referenceableParamGroup", None, i);
+                    id(v, &path, K::ReferenceableParamGroup, &$($mut)? group.id);
+                }
+            }
+
+            if let Some(fd) = &$($mut)? mzml.file_description {
+                let base = "/mzML/fileDescription";
+                let content = &$($mut)? fd.file_content;
+                v.visit(
+                    &format!("{base}/fileContent"),
+                    group_refs,
+                    K::ReferenceableParamGroup,
+                    R::Remove,
+                    &$($mut)? content.referenceable_param_group_refs,
+                );
+                let list = &$($mut)? fd.source_file_list;
+                for (i, sf) in (&$($mut)? list.source_file).into_iter().enumerate() {
+                    let path = child(&format!("{base}/sourceFileList"), "sourceFile", None, i);
+                    id(v, &path, K::SourceFile, &$($mut)? sf.id);
+                    v.visit(
+                        &path,
+                        group_refs,
+                        K::ReferenceableParamGroup,
+                        R::Remove,
+                        &$($mut)? sf.referenceable_param_group_ref,
+                    );
+                }
+                for (i, contact) in (&$($mut)? fd.contacts).into_iter().enumerate() {
+                    let path = child(base, "contact", None, i);
+                    v.visit(
+                        &path,
+                        group_refs,
+                        K::ReferenceableParamGroup,
+                        R::Remove,
+                        &$($mut)? contact.referenceable_param_group_refs,
+                    );
+                }
+            }
+
+            if let Some(list) = &$($mut)? mzml.sample_list {
+                for (i, sample) in (&$($mut)? list.samples).into_iter().enumerate() {
+                    let path = child("/mzML/sampleList", "sample", None, i);
+                    id(v, &path, K::Sample, &$($mut)? sample.id);
+                    v.visit(
+                        &path,
+                        group_refs,
+                        K::ReferenceableParamGroup,
+                        R::Remove,
+                        &$($mut)? sample.referenceable_param_group_ref,
+                    );
+                }
+            }
+
+            if let Some(list) = &$($mut)? mzml.software_list {
+                for (i, sw) in (&$($mut)? list.software).into_iter().enumerate() {
+                    let path = child("/mzML/softwareList", "software", None, i);
+                    id(v, &path, K::Software, &$($mut)? sw.id);
+                }
+            }
+
+            if let Some(list) = &$($mut)? mzml.scan_settings_list {
+                let base = "/mzML/scanSettingsList";
+                for (i, settings) in (&$($mut)? list.scan_settings).into_iter().enumerate() {
+                    let path = child(base, "scanSettings", None, i);
+                    if let Some(settings_id) = &$($mut)? settings.id {
+                        id(v, &path, K::ScanSettings, settings_id);
+                    }
+                    v.visit(
+                        &path,
+                        "instrumentConfigurationRef",
+                        K::InstrumentConfiguration,
+                        R::Remove,
+                        &$($mut)? settings.instrument_configuration_ref,
+                    );
+                    v.visit(
+                        &path,
+                        group_refs,
+                        K::ReferenceableParamGroup,
+                        R::Remove,
+                        &$($mut)? settings.referenceable_param_group_refs,
+                    );
+                    if let Some(files) = &$($mut)? settings.source_file_ref_list {
+                        v.visit(
+                            &path,
+                            "sourceFileRef",
+                            K::SourceFile,
+                            R::Remove,
+                            &$($mut)? files.source_file_refs,
+                        );
+                    }
+                    let targets = (&$($mut)? settings.target_list)
+                        .into_iter()
+                        .flat_map(|l| &$($mut)? l.targets);
+                    for (j, target) in targets.enumerate() {
+                        let path = child(&format!("{path}/targetList"), "target", None, j);
+                        v.visit(
+                            &path,
+                            group_refs,
+                            K::ReferenceableParamGroup,
+                            R::Remove,
+                            &$($mut)? target.referenceable_param_group_refs,
+                        );
+                    }
+                }
+            }
+
+            if let Some(list) = &$($mut)? mzml.instrument_list {
+                let base = "/mzML/instrumentConfigurationList";
+                for (i, ic) in (&$($mut)? list.instrument).into_iter().enumerate() {
+                    let path = child(base, "instrumentConfiguration", None, i);
+                    id(v, &path, K::InstrumentConfiguration, &$($mut)? ic.id);
+                    v.visit(
+                        &path,
+                        group_refs,
+                        K::ReferenceableParamGroup,
+                        R::Remove,
+                        &$($mut)? ic.referenceable_param_group_ref,
+                    );
+                    v.visit(
+                        &path,
+                        "scanSettingsRef",
+                        K::ScanSettings,
+                        R::Remove,
+                        &$($mut)? ic.scan_settings_ref,
+                    );
+                    v.visit(
+                        &path,
+                        "softwareRef",
+                        K::Software,
+                        R::Remove,
+                        &$($mut)? ic.software_ref,
+                    );
+                    if let Some(components) = &$($mut)? ic.component_list {
+                        let list_path = format!("{path}/componentList");
+                        for (j, c) in (&$($mut)? components.source).into_iter().enumerate() {
+                            let path = child(&list_path, "source", None, j);
+                            v.visit(
+                                &path,
+                                group_refs,
+                                K::ReferenceableParamGroup,
+                                R::Remove,
+                                &$($mut)? c.referenceable_param_group_ref,
+                            );
+                        }
+                        for (j, c) in (&$($mut)? components.analyzer).into_iter().enumerate() {
+                            let path = child(&list_path, "analyzer", None, j);
+                            v.visit(
+                                &path,
+                                group_refs,
+                                K::ReferenceableParamGroup,
+                                R::Remove,
+                                &$($mut)? c.referenceable_param_group_ref,
+                            );
+                        }
+                        for (j, c) in (&$($mut)? components.detector).into_iter().enumerate() {
+                            let path = child(&list_path, "detector", None, j);
+                            v.visit(
+                                &path,
+                                group_refs,
+                                K::ReferenceableParamGroup,
+                                R::Remove,
+                                &$($mut)? c.referenceable_param_group_ref,
+                            );
+                        }
+                    }
+                }
+            }
+
+            if let Some(list) = &$($mut)? mzml.data_processing_list {
+                let base = "/mzML/dataProcessingList";
+                for (i, dp) in (&$($mut)? list.data_processing).into_iter().enumerate() {
+                    let path = child(base, "dataProcessing", None, i);
+                    id(v, &path, K::DataProcessing, &$($mut)? dp.id);
+                    v.visit(&path, "softwareRef", K::Software, R::First, &$($mut)? dp.software_ref);
+                    for (j, pm) in (&$($mut)? dp.processing_method).into_iter().enumerate() {
+                        let path = child(&path, "processingMethod", None, j);
+                        v.visit(
+                            &path,
+                            "softwareRef",
+                            K::Software,
+                            R::First,
+                            &$($mut)? pm.software_ref,
+                        );
+                        v.visit(
+                            &path,
+                            group_refs,
+                            K::ReferenceableParamGroup,
+                            R::Remove,
+                            &$($mut)? pm.referenceable_param_group_ref,
+                        );
+                    }
+                }
+            }
+
+            let run = &$($mut)? mzml.run;
+            let base = "/mzML/run";
+            v.visit(
+                base,
+                "defaultInstrumentConfigurationRef",
+                K::InstrumentConfiguration,
+                R::First,
+                &$($mut)? run.default_instrument_configuration_ref,
+            );
+            v.visit(
+                base,
+                "defaultSourceFileRef",
+                K::SourceFile,
+                R::Remove,
+                &$($mut)? run.default_source_file_ref,
+            );
+            v.visit(base, "sampleRef", K::Sample, R::Remove, &$($mut)? run.sample_ref);
+            v.visit(
+                base,
+                group_refs,
+                K::ReferenceableParamGroup,
+                R::Remove,
+                &$($mut)? run.referenceable_param_group_refs,
+            );
+            if let Some(files) = &$($mut)? run.source_file_ref_list {
+                v.visit(
+                    base,
+                    "sourceFileRef",
+                    K::SourceFile,
+                    R::Remove,
+                    &$($mut)? files.source_file_refs,
+                );
+            }
+
+            if let Some(list) = &$($mut)? run.spectrum_list {
+                let base = "/mzML/run/spectrumList";
+                v.visit(
+                    base,
+                    "defaultDataProcessingRef",
+                    K::DataProcessing,
+                    R::First,
+                    &$($mut)? list.default_data_processing_ref,
+                );
+                for (i, spectrum) in (&$($mut)? list.spectra).into_iter().enumerate() {
+                    let path = child(base, "spectrum", None, i);
+                    id(v, &path, K::Spectrum, &$($mut)? spectrum.id);
+                    v.visit(
+                        &path,
+                        "dataProcessingRef",
+                        K::DataProcessing,
+                        R::Remove,
+                        &$($mut)? spectrum.data_processing_ref,
+                    );
+                    v.visit(
+                        &path,
+                        "sourceFileRef",
+                        K::SourceFile,
+                        R::Remove,
+                        &$($mut)? spectrum.source_file_ref,
+                    );
+                    v.visit(
+                        &path,
+                        group_refs,
+                        K::ReferenceableParamGroup,
+                        R::Remove,
+                        &$($mut)? spectrum.referenceable_param_group_refs,
+                    );
+
+                    let description = &$($mut)? spectrum.spectrum_description;
+                    let mut scan_lists = Vec::new();
+                    let mut precursor_lists = Vec::new();
+                    let mut product_lists = Vec::new();
+                    if let Some(d) = description {
+                        let path = format!("{path}/spectrumDescription");
+                        v.visit(
+                            &path,
+                            group_refs,
+                            K::ReferenceableParamGroup,
+                            R::Remove,
+                            &$($mut)? d.referenceable_param_group_refs,
+                        );
+                        scan_lists.extend(&$($mut)? d.scan_list);
+                        precursor_lists.extend(&$($mut)? d.precursor_list);
+                        product_lists.extend(&$($mut)? d.product_list);
+                    }
+                    scan_lists.extend(&$($mut)? spectrum.scan_list);
+                    precursor_lists.extend(&$($mut)? spectrum.precursor_list);
+                    product_lists.extend(&$($mut)? spectrum.product_list);
+
+                    for list in scan_lists {
+                        for (j, scan) in (&$($mut)? list.scans).into_iter().enumerate() {
+                            let path = child(&format!("{path}/scanList"), "scan", None, j);
+                            v.visit(
+                                &path,
+                                "instrumentConfigurationRef",
+                                K::InstrumentConfiguration,
+                                R::Remove,
+                                &$($mut)? scan.instrument_configuration_ref,
+                            );
+                            v.visit(
+                                &path,
+                                "sourceFileRef",
+                                K::SourceFile,
+                                R::Remove,
+                                &$($mut)? scan.source_file_ref,
+                            );
+                            v.visit(
+                                &path,
+                                "spectrumRef",
+                                K::Spectrum,
+                                R::Remove,
+                                &$($mut)? scan.spectrum_ref,
+                            );
+                            v.visit(
+                                &path,
+                                group_refs,
+                                K::ReferenceableParamGroup,
+                                R::Remove,
+                                &$($mut)? scan.referenceable_param_group_refs,
+                            );
+                        }
+                    }
+                    for list in precursor_lists {
+                        for (j, precursor) in (&$($mut)? list.precursors).into_iter().enumerate() {
+                            let list_path = format!("{path}/precursorList");
+                            let path = child(&list_path, "precursor", None, j);
+                            visit_precursor!(v, &path, precursor $(, $mut)?);
+                        }
+                    }
+                    for list in product_lists {
+                        for (j, product) in (&$($mut)? list.products).into_iter().enumerate() {
+                            let path = child(&format!("{path}/productList"), "product", None, j);
+                            visit_product!(v, &path, product $(, $mut)?);
+                        }
+                    }
+                    if let Some(list) = &$($mut)? spectrum.binary_data_array_list {
+                        visit_arrays!(v, &path, list $(, $mut)?);
+                    }
+                }
+            }
+
+            if let Some(list) = &$($mut)? run.chromatogram_list {
+                let base = "/mzML/run/chromatogramList";
+                v.visit(
+                    base,
+                    "defaultDataProcessingRef",
+                    K::DataProcessing,
+                    R::First,
+                    &$($mut)? list.default_data_processing_ref,
+                );
+                for (i, chromatogram) in (&$($mut)? list.chromatograms).into_iter().enumerate() {
+                    let path = child(base, "chromatogram", None, i);
+                    id(v, &path, K::Chromatogram, &$($mut)? chromatogram.id);
+                    v.visit(
+                        &path,
+                        "dataProcessingRef",
+                        K::DataProcessing,
+                        R::Remove,
+                        &$($mut)? chromatogram.data_processing_ref,
+                    );
+                    v.visit(
+                        &path,
+                        group_refs,
+                        K::ReferenceableParamGroup,
+                        R::Remove,
+                        &$($mut)? chromatogram.referenceable_param_group_refs,
+                    );
+                    if let Some(precursor) = &$($mut)? chromatogram.precursor {
+                        let path = format!("{path}/precursor");
+                        visit_precursor!(v, &path, precursor $(, $mut)?);
+                    }
+                    if let Some(product) = &$($mut)? chromatogram.product {
+                        let path = format!("{path}/product");
+                        visit_product!(v, &path, product $(, $mut)?);
+                    }
+                    if let Some(list) = &$($mut)? chromatogram.binary_data_array_list {
+                        visit_arrays!(v, &path, list $(, $mut)?);
+                    }
+                }
+            }
+        }
+    };
+}
+
+macro_rules! visit_precursor {
+    ($v:ident, $path:expr, $precursor:ident $(, $mut:ident)?) => {{
+        let path: &str = $path;
+        let group_refs = "referenceableParamGroupRef";
+        $v.visit(
+            path,
+            "spectrumRef",
+            IdKind::Spectrum,
+            Repair::Remove,
+            &$($mut)? $precursor.spectrum_ref,
+        );
+        $v.visit(
+            path,
+            "sourceFileRef",
+            IdKind::SourceFile,
+            Repair::Remove,
+            &$($mut)? $precursor.source_file_ref,
+        );
+        if let Some(window) = &$($mut)? $precursor.isolation_window {
+            $v.visit(
+                &format!("{path}/isolationWindow"),
+                group_refs,
+                IdKind::ReferenceableParamGroup,
+                Repair::Remove,
+                &$($mut)? window.referenceable_param_group_refs,
+            );
+        }
+        let ions = (&$($mut)? $precursor.selected_ion_list)
+            .into_iter()
+            .flat_map(|l| &$($mut)? l.selected_ions);
+        for (k, ion) in ions.enumerate() {
+            let path = child(&format!("{path}/selectedIonList"), "selectedIon", None, k);
+            $v.visit(
+                &path,
+                group_refs,
+                IdKind::ReferenceableParamGroup,
+                Repair::Remove,
+                &$($mut)? ion.referenceable_param_group_refs,
+            );
+        }
+        if let Some(activation) = &$($mut)? $precursor.activation {
+            $v.visit(
+                &format!("{path}/activation"),
+                group_refs,
+                IdKind::ReferenceableParamGroup,
+                Repair::Remove,
+                &$($mut)? activation.referenceable_param_group_refs,
+            );
+        }
+    }};
+}
+
+macro_rules! visit_product {
+    ($v:ident, $path:expr, $product:ident $(, $mut:ident)?) => {{
+        let path: &str = $path;
+        $v.visit(
+            path,
+            "spectrumRef",
+            IdKind::Spectrum,
+            Repair::Remove,
+            &$($mut)? $product.spectrum_ref,
+        );
+        $v.visit(
+            path,
+            "sourceFileRef",
+            IdKind::SourceFile,
+            Repair::Remove,
+            &$($mut)? $product.source_file_ref,
+        );
+        if let Some(window) = &$($mut)? $product.isolation_window {
+            $v.visit(
+                &format!("{path}/isolationWindow"),
+                "referenceableParamGroupRef",
+                IdKind::ReferenceableParamGroup,
+                Repair::Remove,
+                &$($mut)? window.referenceable_param_group_refs,
+            );
+        }
+    }};
+}
+
+macro_rules! visit_arrays {
+    ($v:ident, $path:expr, $list:ident $(, $mut:ident)?) => {{
+        let list_path = format!("{}/binaryDataArrayList", $path);
+        for (k, bda) in (&$($mut)? $list.binary_data_arrays).into_iter().enumerate() {
+            let path = child(&list_path, "binaryDataArray", None, k);
+            $v.visit(
+                &path,
+                "dataProcessingRef",
+                IdKind::DataProcessing,
+                Repair::Remove,
+                &$($mut)? bda.data_processing_ref,
+            );
+            $v.visit(
+                &path,
+                "referenceableParamGroupRef",
+                IdKind::ReferenceableParamGroup,
+                Repair::Remove,
+                &$($mut)? bda.referenceable_param_group_refs,
+            );
+        }
+    }};
+}
+
+walker!(walk, Checking);
+walker!(walk_mut, Fixing, mut);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mzml::parse_mzml::parse_mzml;
+
+    fn parse(path: &str) -> MzML {
+        parse_mzml(&std::fs::read(path).unwrap()).unwrap()
+    }
+
+    fn dangling(attribute: &str, target: IdKind, id: &str) -> RefIssueKind {
+        RefIssueKind::DanglingRef {
+            attribute: attribute.to_string(),
+            target,
+            id: id.to_string(),
+        }
+    }
+
+    fn kinds(validation: &RefValidation) -> Vec<&RefIssueKind> {
+        validation.issues.iter().map(|issue| &issue.kind).collect()
+    }
+
+    #[test]
+    fn bundled_file_has_no_dangling_refs() {
+        let validation = validate_refs(&parse("data/mzml/test.mzML"));
+        assert!(validation.is_valid(), "{:#?}", validation.issues);
+    }
+
+    #[test]
+    fn dangling_refs_in_a_fixture_are_reported() {
+        let validation = validate_refs(&parse("data/mzml/tiny4_LTQ-FT.mzML0.99.0.mzML"));
+        assert_eq!(
+            kinds(&validation),
+            [
+                &dangling(
+                    "instrumentConfigurationRef",
+                    IdKind::InstrumentConfiguration,
+                    "LCQ Deca"
+                ),
+                &dangling("spectrumRef", IdKind::Spectrum, "19"),
+            ]
+        );
+        assert_eq!(
+            validation.issues[1].path,
+            "/mzML/run/spectrumList/spectrum[2]/precursorList/precursor[1]"
+        );
+        assert!(validation.issues.iter().all(|issue| issue.fix.is_none()));
+    }
+
+    #[test]
+    fn duplicate_ids_are_renamed_and_refs_keep_the_first() {
+        let mut mzml = parse("data/mzml/test.mzML");
+        let spectra = &mut mzml.run.spectrum_list.as_mut().unwrap().spectra;
+        let first = spectra[0].id.clone();
+        spectra[1].id = first.clone();
+
+        let validation = validate_refs(&mzml);
+        assert_eq!(
+            kinds(&validation),
+            [&RefIssueKind::DuplicateId {
+                kind: IdKind::Spectrum,
+                id: first.clone()
+            }]
+        );
+        assert_eq!(
+            validation.issues[0].path,
+            "/mzML/run/spectrumList/spectrum[2]"
+        );
+
+        let fixed = fix_refs(&mut mzml);
+        let renamed = format!("{first}_2");
+        assert_eq!(
+            fixed.issues[0].fix,
+            Some(RefFix::Rewritten {
+                to: renamed.clone()
+            })
+        );
+        let spectra = &mzml.run.spectrum_list.as_ref().unwrap().spectra;
+        assert_eq!(
+            (spectra[0].id.as_str(), spectra[1].id.as_str()),
+            (first.as_str(), renamed.as_str())
+        );
+        assert!(validate_refs(&mzml).is_valid());
+    }
+
+    #[test]
+    fn fix_drops_or_retargets_dangling_refs() {
+        let mut mzml = parse("data/mzml/test.mzML");
+        let first_dp = mzml.data_processing_list.as_ref().unwrap().data_processing[0]
+            .id
+            .clone();
+        let run = &mut mzml.run;
+        run.sample_ref = Some("gone".to_string());
+        run.source_file_ref_list = Some(SourceFileRefList {
+            count: Some(1),
+            source_file_refs: vec![SourceFileRef {
+                r#ref: "gone".to_string(),
+            }],
+        });
+        let list = run.spectrum_list.as_mut().unwrap();
+        list.default_data_processing_ref = Some("gone".to_string());
+        let spectrum = &mut list.spectra[0];
+        spectrum.data_processing_ref = Some("gone".to_string());
+        spectrum
+            .referenceable_param_group_refs
+            .push(ReferenceableParamGroupRef {
+                r#ref: "gone".to_string(),
+            });
+
+        let fixed = fix_refs(&mut mzml);
+        let fixes: Vec<_> = fixed
+            .issues
+            .iter()
+            .map(|issue| match &issue.kind {
+                RefIssueKind::DanglingRef { attribute, .. } => {
+                    (attribute.as_str(), issue.fix.clone().unwrap())
+                }
+                other => panic!("unexpected {other:?}"),
+            })
+            .collect();
+        assert_eq!(
+            fixes,
+            [
+                ("sampleRef", RefFix::Dropped),
+                ("sourceFileRef", RefFix::Dropped),
+                (
+                    "defaultDataProcessingRef",
+                    RefFix::Rewritten {
+                        to: first_dp.clone()
+                    }
+                ),
+                ("dataProcessingRef", RefFix::Dropped),
+                ("referenceableParamGroupRef", RefFix::Dropped),
+            ]
+        );
+
+        let run = &mzml.run;
+        assert_eq!(run.sample_ref, None);
+        let files = run.source_file_ref_list.as_ref().unwrap();
+        assert_eq!((files.count, files.source_file_refs.len()), (Some(0), 0));
+        let list = run.spectrum_list.as_ref().unwrap();
+        assert_eq!(list.default_data_processing_ref, Some(first_dp));
+        assert_eq!(list.spectra[0].data_processing_ref, None);
+        assert!(validate_refs(&mzml).is_valid());
+    }
+}
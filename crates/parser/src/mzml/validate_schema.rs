@@ -0,0 +1,520 @@
+use quick_xml::{
+    Reader,
+    events::{BytesStart, Event},
+};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter, Result as FmtResult},
+};
+
+use crate::mzml::{
+    bin_to_mzml::convert_bin_to_mzml_bytes,
+    parse_mzml::{gunzip, is_gzip},
+    parse_report::line_columns,
+    schema::{SchemaNode, TagId, Use, schema},
+    structs::MzML,
+    utilities::{ParseError, attr},
+};
+
+/// Children that may repeat under any parent. Items of elements with a
+/// `count` attribute repeat as well.
+const REPEATABLE: &[&str] = &[
+    "cvParam",
+    "userParam",
+    "referenceableParamGroupRef",
+    "contact",
+    "processingMethod",
+];
+
+/// Required by the tree for mzML 0.99 documents only; mzML 1.1 uses
+/// cvParams instead.
+const LEGACY_REQUIRED: &[&str] = &["softwareParam"];
+
+/// Children that are not list items, so not included in `count`.
+const PARAMS: &[&str] = &["cvParam", "userParam", "referenceableParamGroupRef"];
+
+/// What is wrong with an element.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum SchemaIssueKind {
+    /// The schema does not allow the element here. Its content is not
+    /// checked.
+    UnexpectedElement {
+        element: String,
+    },
+    /// A required child element is absent.
+    MissingElement {
+        element: String,
+    },
+    /// A child element that may occur once occurs `found` times.
+    TooManyElements {
+        element: String,
+        found: usize,
+    },
+    MissingAttribute {
+        attribute: String,
+    },
+    /// The `count` attribute does not match the number of list items.
+    CountMismatch {
+        stated: String,
+        actual: usize,
+    },
+}
+
+/// One schema violation. `path` and `byte_offset` are those of the element
+/// the issue is about (the parent, for missing and repeated children);
+/// `line` and `column` are 1-based.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SchemaIssue {
+    pub kind: SchemaIssueKind,
+    pub path: String,
+    pub byte_offset: u64,
+    pub line: u64,
+    pub column: u64,
+}
+
+impl Display for SchemaIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "{}:{} (byte {}) {}: ",
+            self.line, self.column, self.byte_offset, self.path
+        )?;
+        match &self.kind {
+            SchemaIssueKind::UnexpectedElement { element } => {
+                write!(f, "<{element}> is not allowed here")
+            }
+            SchemaIssueKind::MissingElement { element } => {
+                write!(f, "missing required <{element}>")
+            }
+            SchemaIssueKind::TooManyElements { element, found } => {
+                write!(f, "<{element}> may occur once but occurs {found} times")
+            }
+            SchemaIssueKind::MissingAttribute { attribute } => {
+                write!(f, "missing required attribute {attribute}")
+            }
+            SchemaIssueKind::CountMismatch { stated, actual } => {
+                write!(f, "count is {stated} but there are {actual} items")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SchemaValidation {
+    pub issues: Vec<SchemaIssue>,
+}
+
+impl SchemaValidation {
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Checks an mzML document against the bundled schema tree: element
+/// placement, required elements and attributes, elements that may occur
+/// only once, and `count` attributes. Child order is not checked, and
+/// elements with a namespace prefix, such as preserved vendor extensions,
+/// are skipped without counting as list items. Gzip
+/// input is inflated first and offsets refer to the inflated document.
+pub fn validate_schema(bytes: &[u8]) -> Result<SchemaValidation, ParseError> {
+    if is_gzip(bytes) {
+        return validate_schema(&gunzip(bytes)?);
+    }
+
+    let mut reader = Reader::from_reader(bytes);
+    let mut validator = Validator::default();
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        let offset = reader.buffer_position();
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) => validator.open(&e, offset),
+            Event::Empty(e) => {
+                validator.open(&e, offset);
+                validator.close();
+            }
+            Event::End(_) => validator.close(),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    let mut issues = validator.issues;
+    issues.sort_by_key(|issue| issue.byte_offset);
+    let offsets: Vec<u64> = issues.iter().map(|issue| issue.byte_offset).collect();
+    for (issue, (line, column)) in issues.iter_mut().zip(line_columns(bytes, &offsets)) {
+        issue.line = line;
+        issue.column = column;
+    }
+    Ok(SchemaValidation { issues })
+}
+
+/// `validate_schema` on the mzML written for a parsed document. List
+/// counts are written as stored, so stale counts are reported.
+pub fn validate_schema_mzml(mzml: &MzML) -> Result<SchemaValidation, String> {
+    let xml = convert_bin_to_mzml_bytes(mzml)?;
+    validate_schema(&xml).map_err(|e| e.to_string())
+}
+
+#[derive(Clone, Copy)]
+enum Rule<'s> {
+    /// `<indexedmzML>`: `<mzML>` and the index, which is not checked.
+    Wrapper,
+    /// `<mzML>`: `<cvList>` and the schema roots.
+    Document,
+    CvList,
+    Cv,
+    Node(&'s SchemaNode),
+    /// Inside an unexpected or unchecked element.
+    Skip,
+}
+
+struct Frame<'s> {
+    name: String,
+    rule: Rule<'s>,
+    offset: u64,
+    count: Option<String>,
+    /// Occurrences per child, keyed by schema key or element name.
+    children: HashMap<&'s str, usize>,
+    items: usize,
+}
+
+#[derive(Default)]
+struct Validator<'s> {
+    stack: Vec<Frame<'s>>,
+    issues: Vec<SchemaIssue>,
+}
+
+impl<'s> Validator<'s> {
+    fn path(&self) -> String {
+        self.stack.iter().fold(String::new(), |mut path, frame| {
+            path.push('/');
+            path.push_str(&frame.name);
+            path
+        })
+    }
+
+    fn report(&mut self, kind: SchemaIssueKind, byte_offset: u64) {
+        let path = self.path();
+        self.issues.push(SchemaIssue {
+            kind,
+            path,
+            byte_offset,
+            line: 0,
+            column: 0,
+        });
+    }
+
+    fn open(&mut self, e: &BytesStart, offset: u64) {
+        let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+        let parent = self.stack.last().map(|frame| frame.rule);
+        let (rule, key) = resolve(parent, &name);
+        let foreign = name.contains(':');
+
+        if let Some(frame) = self.stack.last_mut() {
+            if let Some(key) = key {
+                *frame.children.entry(key).or_default() += 1;
+            }
+            if !PARAMS.contains(&name.as_str()) && !foreign {
+                frame.items += 1;
+            }
+        }
+
+        self.stack.push(Frame {
+            name: name.clone(),
+            rule: rule.unwrap_or(Rule::Skip),
+            offset,
+            count: attr(e, b"count"),
+            children: HashMap::new(),
+            items: 0,
+        });
+
+        let Some(rule) = rule else {
+            if !foreign && !matches!(parent, Some(Rule::Skip)) {
+                self.report(SchemaIssueKind::UnexpectedElement { element: name }, offset);
+            }
+            return;
+        };
+
+        let mut required: Vec<&str> = match rule {
+            Rule::Document => vec!["version"],
+            Rule::CvList => vec!["count"],
+            Rule::Cv => vec!["id", "fullName", "URI"],
+            Rule::Node(node) => node
+                .attributes_use
+                .iter()
+                .filter(|(_, use_)| **use_ == Use::Required)
+                .filter_map(|(key, _)| node.attributes.get(key)?.first())
+                .map(String::as_str)
+                .collect(),
+            Rule::Wrapper | Rule::Skip => Vec::new(),
+        };
+        match name.as_str() {
+            "cvParam" => required.extend(["cvRef", "accession", "name"]),
+            "userParam" => required.push("name"),
+            _ => {}
+        }
+        required.sort_unstable();
+        for attribute in required {
+            if attr(e, attribute.as_bytes()).is_none() {
+                let attribute = attribute.to_string();
+                self.report(SchemaIssueKind::MissingAttribute { attribute }, offset);
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        let Some(frame) = self.stack.last() else {
+            return;
+        };
+        let offset = frame.offset;
+        let seen = |key: &str| frame.children.get(key).copied().unwrap_or(0);
+
+        // (element, occurrences, required, repeatable)
+        let expected: Vec<(&str, usize, bool, bool)> = match frame.rule {
+            Rule::Document => {
+                let mut roots: Vec<_> = schema()
+                    .roots
+                    .iter()
+                    .map(|(key, root)| {
+                        (
+                            element_name(root),
+                            seen(key),
+                            root.use_ == Use::Required,
+                            false,
+                        )
+                    })
+                    .collect();
+                roots.push(("cvList", seen("cvList"), true, false));
+                roots
+            }
+            Rule::CvList => vec![("cv", seen("cv"), true, true)],
+            Rule::Node(node) => node
+                .children
+                .iter()
+                .map(|(key, child)| {
+                    let element = element_name(child);
+                    let repeatable =
+                        REPEATABLE.contains(&element) || node.attributes.contains_key("count");
+                    let required =
+                        child.use_ == Use::Required && !LEGACY_REQUIRED.contains(&element);
+                    (element, seen(key), required, repeatable)
+                })
+                .collect(),
+            Rule::Wrapper | Rule::Cv | Rule::Skip => Vec::new(),
+        };
+
+        let mut found = Vec::new();
+        for (element, occurrences, required, repeatable) in expected {
+            let element = element.to_string();
+            if required && occurrences == 0 {
+                found.push(SchemaIssueKind::MissingElement { element });
+            } else if !repeatable && occurrences > 1 {
+                found.push(SchemaIssueKind::TooManyElements {
+                    element,
+                    found: occurrences,
+                });
+            }
+        }
+        found.sort_by(|a, b| format!("{a:?}").cmp(&format!("{b:?}")));
+
+        if !matches!(frame.rule, Rule::Skip)
+            && let Some(stated) = &frame.count
+            && stated.trim().parse::<usize>().ok() != Some(frame.items)
+        {
+            found.push(SchemaIssueKind::CountMismatch {
+                stated: stated.clone(),
+                actual: frame.items,
+            });
+        }
+
+        for kind in found {
+            self.report(kind, offset);
+        }
+        self.stack.pop();
+    }
+}
+
+/// Rule for `name` under `parent`, and the key it is counted under; `None`
+/// when the element is not allowed there.
+fn resolve<'s>(parent: Option<Rule<'s>>, name: &str) -> (Option<Rule<'s>>, Option<&'s str>) {
+    let tree = schema();
+    let prefixed = name.contains(':');
+    match parent {
+        None if name == "indexedmzML" => (Some(Rule::Wrapper), None),
+        None if name == "mzML" => (Some(Rule::Document), None),
+        Some(Rule::Wrapper) => match name {
+            "mzML" => (Some(Rule::Document), None),
+            "indexList" | "indexListOffset" | "fileChecksum" => (Some(Rule::Skip), None),
+            _ => (None, None),
+        },
+        Some(Rule::Document) if name == "cvList" => (Some(Rule::CvList), Some("cvList")),
+        Some(Rule::Document) if !prefixed => {
+            let tag = TagId::from_xml_tag(name);
+            match tree.root_key_for_tag(tag) {
+                Some(key) => (Some(Rule::Node(&tree.roots[key])), Some(key)),
+                None => (None, None),
+            }
+        }
+        Some(Rule::CvList) if name == "cv" => (Some(Rule::Cv), Some("cv")),
+        Some(Rule::Node(node)) if !prefixed => {
+            let tag = TagId::from_xml_tag(name);
+            let key = node
+                .child_key_by_tag
+                .get(tag as usize)
+                .and_then(|k| k.as_deref());
+            match key.and_then(|key| Some((key, node.children.get(key)?))) {
+                Some((key, child)) => (Some(Rule::Node(child)), Some(key)),
+                None => (None, None),
+            }
+        }
+        Some(Rule::Skip) => (None, None),
+        _ => (None, None),
+    }
+}
+
+fn element_name(node: &SchemaNode) -> &'static str {
+    node.self_tags.first().map_or("", |tag| tag.xml_tag())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mzml::parse_mzml::parse_mzml;
+
+    const PATH: &str = "data/mzml/test.mzML";
+
+    /// The fixture keeps 2 of the 3476 spectra its list count announces.
+    fn valid_document() -> String {
+        std::fs::read_to_string(PATH).unwrap().replacen(
+            r#"<spectrumList count="3476""#,
+            r#"<spectrumList count="2""#,
+            1,
+        )
+    }
+
+    fn kinds(validation: &SchemaValidation) -> Vec<&SchemaIssueKind> {
+        validation.issues.iter().map(|issue| &issue.kind).collect()
+    }
+
+    #[test]
+    fn bundled_mzml_1_1_file_is_valid() {
+        let validation = validate_schema(valid_document().as_bytes()).unwrap();
+        assert!(validation.is_valid(), "{:#?}", validation.issues);
+
+        let fixture = validate_schema(&std::fs::read(PATH).unwrap()).unwrap();
+        assert_eq!(
+            kinds(&fixture),
+            [&SchemaIssueKind::CountMismatch {
+                stated: "3476".to_string(),
+                actual: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn parsed_document_is_valid_after_writing() {
+        let mzml = parse_mzml(valid_document().as_bytes()).unwrap();
+        let validation = validate_schema_mzml(&mzml).unwrap();
+        assert!(validation.is_valid(), "{:#?}", validation.issues);
+    }
+
+    #[test]
+    fn misplaced_element_is_reported_with_its_position() {
+        let xml = valid_document().replacen("<scanList ", "<binary/><scanList ", 1);
+        let validation = validate_schema(xml.as_bytes()).unwrap();
+        assert_eq!(
+            kinds(&validation),
+            [&SchemaIssueKind::UnexpectedElement {
+                element: "binary".to_string()
+            }]
+        );
+        let issue = &validation.issues[0];
+        assert_eq!(
+            issue.path,
+            "/indexedmzML/mzML/run/spectrumList/spectrum/binary"
+        );
+        assert_eq!(issue.byte_offset, xml.find("<binary/>").unwrap() as u64);
+        let line = xml[..xml.find("<binary/>").unwrap()].matches('\n').count() as u64 + 1;
+        assert_eq!(issue.line, line);
+    }
+
+    #[test]
+    fn missing_attribute_and_element_are_reported() {
+        let xml = valid_document();
+        let spectrum = xml.find("<spectrum ").unwrap();
+        let scan_list = spectrum + xml[spectrum..].find("<scanList").unwrap();
+        let scan_list_end = scan_list + xml[scan_list..].find("</scanList>").unwrap();
+        let xml = format!(
+            "{}<scanList count=\"0\"></scanList>{}",
+            &xml[..scan_list],
+            &xml[scan_list_end + "</scanList>".len()..]
+        )
+        .replacen(r#" version="1.1.0""#, "", 1);
+
+        let validation = validate_schema(xml.as_bytes()).unwrap();
+        assert_eq!(
+            kinds(&validation),
+            [
+                &SchemaIssueKind::MissingAttribute {
+                    attribute: "version".to_string()
+                },
+                &SchemaIssueKind::MissingElement {
+                    element: "scan".to_string()
+                },
+            ]
+        );
+        assert_eq!(
+            validation.issues[1].path,
+            "/indexedmzML/mzML/run/spectrumList/spectrum/scanList"
+        );
+    }
+
+    #[test]
+    fn repeated_singleton_and_wrong_count_are_reported() {
+        let xml = valid_document()
+            .replacen(
+                "<fileContent>",
+                "<fileContent></fileContent><fileContent>",
+                1,
+            )
+            .replacen(r#"<cvList count="2">"#, r#"<cvList count="3">"#, 1);
+        let validation = validate_schema(xml.as_bytes()).unwrap();
+        assert_eq!(
+            kinds(&validation),
+            [
+                &SchemaIssueKind::CountMismatch {
+                    stated: "3".to_string(),
+                    actual: 2
+                },
+                &SchemaIssueKind::TooManyElements {
+                    element: "fileContent".to_string(),
+                    found: 2
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn stale_list_count_in_a_parsed_document_is_reported() {
+        let mut mzml = parse_mzml(valid_document().as_bytes()).unwrap();
+        let list = mzml.run.spectrum_list.as_mut().unwrap();
+        let actual = list.spectra.len();
+        list.count = Some(actual + 1);
+
+        let validation = validate_schema_mzml(&mzml).unwrap();
+        assert_eq!(
+            kinds(&validation),
+            [&SchemaIssueKind::CountMismatch {
+                stated: (actual + 1).to_string(),
+                actual
+            }]
+        );
+        assert_eq!(
+            validation.issues[0].path,
+            "/indexedmzML/mzML/run/spectrumList"
+        );
+    }
+}
@@ -0,0 +1,953 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    b64::{decode, encode, encoder::encode::WritingMode},
+    mzml::{bin_to_mzml::convert_bin_to_mzml_bytes, parse_mzml::parse_mzml, structs::*},
+};
+
+/// Fields that only describe how a value was serialized, not what it is.
+/// List counts are implied by the items, which are compared one by one.
+/// Their differences are reported as normalized.
+const SERIALIZATION_KEYS: &[&str] = &["encoded_length", "count"];
+
+/// Attributes the writer fills in from a list default or a neighbouring
+/// value when the source left them out. Their addition is reported as
+/// normalized, their loss or change as a difference.
+const DEFAULTED_KEYS: &[&str] = &[
+    "array_length",
+    "data_processing_ref",
+    "default_array_length",
+    "default_data_processing_ref",
+    "index",
+    "version",
+];
+
+/// Labels that repeat what a cvParam's accession already says; their
+/// differences are reported as normalized.
+const CV_LABEL_KEYS: &[&str] = &["name", "cv_ref", "unit_cv_ref", "unit_name"];
+
+const GROUP_REF_KEYS: &[&str] = &[
+    "referenceable_param_group_refs",
+    "referenceable_param_group_ref",
+];
+
+/// Tolerances used when comparing binary arrays and numeric cvParam values.
+/// Two numbers match when `|a - b| <= max(abs_tolerance, rel_tolerance * max(|a|, |b|))`.
+#[derive(Debug, Clone, Copy)]
+pub struct CompareOptions {
+    pub abs_tolerance: f64,
+    pub rel_tolerance: f64,
+}
+
+impl Default for CompareOptions {
+    fn default() -> Self {
+        Self {
+            abs_tolerance: 0.0,
+            rel_tolerance: 1e-9,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffKind {
+    /// Present on the left tree only.
+    Lost,
+    /// Present on both sides with different values.
+    Changed,
+    /// Present on the right tree only.
+    Added,
+}
+
+/// One difference, addressed by an mzML-style path such as
+/// `run/spectrumList/spectrum[12]/scanList/scan[0]/cvParam[MS:1000016]/@value`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiffEntry {
+    pub kind: DiffKind,
+    pub path: String,
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DiffReport {
+    pub diffs: Vec<DiffEntry>,
+    /// Differences in representation only: list counts, encoded lengths,
+    /// cvParam labels, empty versus absent attributes and attributes the
+    /// writer fills in from defaults. They do not make the trees differ.
+    pub normalized: Vec<DiffEntry>,
+}
+
+impl DiffReport {
+    /// True when there are no differences other than normalized ones.
+    #[inline]
+    pub fn is_identical(&self) -> bool {
+        self.diffs.is_empty()
+    }
+
+    #[inline]
+    pub fn count(&self, kind: DiffKind) -> usize {
+        self.diffs.iter().filter(|d| d.kind == kind).count()
+    }
+}
+
+/// Compares two `MzML` trees field by field.
+///
+/// cvParams are matched by accession (userParams by name) regardless of
+/// order. Param-group refs are replaced by the params of the group they name
+/// on each side, so inlining a group is not a difference. Differences in
+/// cvParam labels (name, cvRef and unit labels), list counts and encoded
+/// lengths, empty versus absent attributes, and attributes the writer fills
+/// in from defaults (array lengths, indices, data processing refs,
+/// versions) are reported separately as normalized. Spectra, chromatograms
+/// and other repeated elements are matched by position. Each spectrum and
+/// chromatogram is compared on its own so memory stays bounded by the
+/// largest single entry.
+pub fn compare_mzml(left: &MzML, right: &MzML, options: &CompareOptions) -> DiffReport {
+    let mut differ = Differ {
+        options,
+        groups: [param_groups(left), param_groups(right)],
+        diffs: Vec::new(),
+        normalized: Vec::new(),
+        normalizing: false,
+    };
+
+    differ.field("", "cv_list", &left.cv_list, &right.cv_list);
+    differ.field(
+        "",
+        "file_description",
+        &left.file_description,
+        &right.file_description,
+    );
+    differ.field(
+        "",
+        "referenceable_param_group_list",
+        &left.referenceable_param_group_list,
+        &right.referenceable_param_group_list,
+    );
+    differ.field("", "sample_list", &left.sample_list, &right.sample_list);
+    differ.field(
+        "",
+        "instrument_list",
+        &left.instrument_list,
+        &right.instrument_list,
+    );
+    differ.field(
+        "",
+        "software_list",
+        &left.software_list,
+        &right.software_list,
+    );
+    differ.field(
+        "",
+        "data_processing_list",
+        &left.data_processing_list,
+        &right.data_processing_list,
+    );
+    differ.field(
+        "",
+        "scan_settings_list",
+        &left.scan_settings_list,
+        &right.scan_settings_list,
+    );
+    differ.run(&left.run, &right.run);
+
+    DiffReport {
+        diffs: differ.diffs,
+        normalized: differ.normalized,
+    }
+}
+
+/// Encodes `mzml` to B000, decodes it, writes it back to mzML text, parses
+/// that again and compares the result with the original tree.
+pub fn verify_roundtrip(
+    mzml: &MzML,
+    compression_level: u8,
+    force_f32: bool,
+    options: &CompareOptions,
+) -> Result<DiffReport, String> {
+    let mut encoded = Vec::new();
+    encode(
+        mzml,
+        compression_level,
+        force_f32,
+        WritingMode::Memory,
+        &mut encoded,
+    )?;
+    let decoded = decode(&encoded)?;
+    drop(encoded);
+
+    let xml = convert_bin_to_mzml_bytes(&decoded)?;
+    drop(decoded);
+    let reparsed = parse_mzml(&xml).map_err(|e| e.to_string())?;
+
+    Ok(compare_mzml(mzml, &reparsed, options))
+}
+
+/// Identity of a param inside its list, used for set comparison.
+type ParamKey = fn(&Value) -> String;
+
+/// cvParams and userParams of each referenceable param group, by id.
+type ParamGroups = HashMap<String, (Vec<Value>, Vec<Value>)>;
+
+struct Differ<'o> {
+    options: &'o CompareOptions,
+    /// Param groups of the left and right document.
+    groups: [ParamGroups; 2],
+    diffs: Vec<DiffEntry>,
+    normalized: Vec<DiffEntry>,
+    /// Differences found now go to `normalized`.
+    normalizing: bool,
+}
+
+impl Differ<'_> {
+    fn run(&mut self, left: &Run, right: &Run) {
+        const RUN: &str = "run";
+        self.field(RUN, "id", &left.id, &right.id);
+        self.field(
+            RUN,
+            "start_time_stamp",
+            &left.start_time_stamp,
+            &right.start_time_stamp,
+        );
+        self.field(
+            RUN,
+            "default_instrument_configuration_ref",
+            &left.default_instrument_configuration_ref,
+            &right.default_instrument_configuration_ref,
+        );
+        self.field(
+            RUN,
+            "default_source_file_ref",
+            &left.default_source_file_ref,
+            &right.default_source_file_ref,
+        );
+        self.field(RUN, "sample_ref", &left.sample_ref, &right.sample_ref);
+        self.field(
+            RUN,
+            "referenceable_param_group_refs",
+            &left.referenceable_param_group_refs,
+            &right.referenceable_param_group_refs,
+        );
+        self.field(RUN, "cv_params", &left.cv_params, &right.cv_params);
+        self.field(RUN, "user_params", &left.user_params, &right.user_params);
+        self.field(
+            RUN,
+            "source_file_ref_list",
+            &left.source_file_ref_list,
+            &right.source_file_ref_list,
+        );
+
+        let path = join(RUN, "spectrumList");
+        match (&left.spectrum_list, &right.spectrum_list) {
+            (Some(l), Some(r)) => {
+                self.field(&path, "count", &l.count, &r.count);
+                self.field(
+                    &path,
+                    "default_data_processing_ref",
+                    &l.default_data_processing_ref,
+                    &r.default_data_processing_ref,
+                );
+                self.entries(&path, "spectrum", &l.spectra, &r.spectra);
+            }
+            (l, r) => self.presence(path, l.is_some(), r.is_some()),
+        }
+
+        let path = join(RUN, "chromatogramList");
+        match (&left.chromatogram_list, &right.chromatogram_list) {
+            (Some(l), Some(r)) => {
+                self.field(&path, "count", &l.count, &r.count);
+                self.field(
+                    &path,
+                    "default_data_processing_ref",
+                    &l.default_data_processing_ref,
+                    &r.default_data_processing_ref,
+                );
+                self.entries(&path, "chromatogram", &l.chromatograms, &r.chromatograms);
+            }
+            (l, r) => self.presence(path, l.is_some(), r.is_some()),
+        }
+    }
+
+    /// Compares large repeated elements one at a time instead of converting
+    /// the whole list at once.
+    fn entries<T: Serialize>(&mut self, parent: &str, element: &str, left: &[T], right: &[T]) {
+        for i in 0..left.len().max(right.len()) {
+            let path = format!("{parent}/{element}[{i}]");
+            match (left.get(i), right.get(i)) {
+                (Some(l), Some(r)) => {
+                    let (l, r) = self.values(l, r);
+                    self.element(&path, &l, &r)
+                }
+                (Some(l), None) => self.push(DiffKind::Lost, path, summary(&to_value(l)), None),
+                (None, Some(r)) => self.push(DiffKind::Added, path, None, summary(&to_value(r))),
+                (None, None) => {}
+            }
+        }
+    }
+
+    fn presence(&mut self, path: String, left: bool, right: bool) {
+        match (left, right) {
+            (true, false) => self.push(DiffKind::Lost, path, None, None),
+            (false, true) => self.push(DiffKind::Added, path, None, None),
+            _ => {}
+        }
+    }
+
+    fn field<T: Serialize>(&mut self, parent: &str, key: &str, left: &T, right: &T) {
+        let (left, right) = self.values(left, right);
+        self.node(parent, key, &left, &right);
+    }
+
+    /// Converts both sides and normalizes each against its own param groups.
+    fn values<T: Serialize>(&self, left: &T, right: &T) -> (Value, Value) {
+        let mut left = to_value(left);
+        let mut right = to_value(right);
+        normalize(&mut left, &self.groups[0]);
+        normalize(&mut right, &self.groups[1]);
+        (left, right)
+    }
+
+    fn node(&mut self, parent: &str, key: &str, left: &Value, right: &Value) {
+        if SERIALIZATION_KEYS.contains(&key) {
+            return self.normalized(|d| d.compare(parent, key, left, right));
+        }
+        let empty = |v: &Value| matches!(v, Value::String(s) if s.is_empty());
+        let absent = |v| if empty(v) { &Value::Null } else { v };
+        let (l, r) = (absent(left), absent(right));
+        if l.is_null() && r.is_null() && left != right {
+            return self.normalized(|d| d.compare(parent, key, left, right));
+        }
+        self.compare(parent, key, l, r);
+    }
+
+    fn compare(&mut self, parent: &str, key: &str, left: &Value, right: &Value) {
+        match (left, right) {
+            (Value::Null, Value::Null) => {}
+            (Value::Array(l), Value::Array(r)) if key == "binary" => {
+                self.binary(join(parent, "binary"), l, r)
+            }
+            (Value::Array(l), Value::Array(r)) => match param_element(key) {
+                Some((element, key_of)) => self.params(parent, element, key_of, l, r),
+                None => {
+                    let element = element_name(key, true);
+                    for i in 0..l.len().max(r.len()) {
+                        let path = format!("{}[{i}]", join(parent, &element));
+                        match (l.get(i), r.get(i)) {
+                            (Some(a), Some(b)) => self.element(&path, a, b),
+                            (Some(a), None) => self.push(DiffKind::Lost, path, summary(a), None),
+                            (None, Some(b)) => self.push(DiffKind::Added, path, None, summary(b)),
+                            (None, None) => {}
+                        }
+                    }
+                }
+            },
+            (Value::Object(_), Value::Object(_)) => {
+                self.element(&join(parent, &element_name(key, false)), left, right)
+            }
+            (Value::Null, other) if DEFAULTED_KEYS.contains(&key) && !other.is_object() => {
+                let path = join(parent, &attribute_name(key));
+                self.normalized(|d| d.push(DiffKind::Added, path, None, summary(other)));
+            }
+            (Value::Null, other) => {
+                let path = join(parent, &segment(key, other));
+                self.push(DiffKind::Added, path, None, summary(other));
+            }
+            (other, Value::Null) => {
+                let path = join(parent, &segment(key, other));
+                self.push(DiffKind::Lost, path, summary(other), None);
+            }
+            (l, r) => {
+                if !self.scalar_eq(key, l, r) {
+                    let path = join(parent, &attribute_name(key));
+                    self.push(DiffKind::Changed, path, summary(l), summary(r));
+                }
+            }
+        }
+    }
+
+    fn element(&mut self, path: &str, left: &Value, right: &Value) {
+        let (Value::Object(l), Value::Object(r)) = (left, right) else {
+            if left != right {
+                self.push(
+                    DiffKind::Changed,
+                    path.to_string(),
+                    summary(left),
+                    summary(right),
+                );
+            }
+            return;
+        };
+        let labelled = l
+            .get("accession")
+            .and_then(Value::as_str)
+            .is_some_and(|a| !a.is_empty());
+        let node = |d: &mut Self, key: &str, lv: &Value, rv: &Value| {
+            if labelled && CV_LABEL_KEYS.contains(&key) {
+                d.normalized(|d| d.node(path, key, lv, rv));
+            } else {
+                d.node(path, key, lv, rv);
+            }
+        };
+        for (key, lv) in l {
+            node(self, key, lv, r.get(key).unwrap_or(&Value::Null));
+        }
+        for (key, rv) in r {
+            if !l.contains_key(key) {
+                node(self, key, &Value::Null, rv);
+            }
+        }
+    }
+
+    /// Runs `compare` with the differences it finds reported as normalized.
+    fn normalized(&mut self, compare: impl FnOnce(&mut Self)) {
+        let outer = std::mem::replace(&mut self.normalizing, true);
+        compare(self);
+        self.normalizing = outer;
+    }
+
+    /// Matches params as a multiset: the n-th occurrence of a key on the left
+    /// pairs with the n-th occurrence on the right.
+    fn params(
+        &mut self,
+        parent: &str,
+        element: &str,
+        key_of: ParamKey,
+        left: &[Value],
+        right: &[Value],
+    ) {
+        let mut right_by_key: HashMap<String, Vec<&Value>> = HashMap::new();
+        for r in right {
+            right_by_key.entry(key_of(r)).or_default().push(r);
+        }
+
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for l in left {
+            let key = key_of(l);
+            let nth = *seen.entry(key.clone()).and_modify(|n| *n += 1).or_insert(0);
+            let path = param_path(parent, element, &key, nth);
+            match right_by_key.get(&key).and_then(|v| v.get(nth)) {
+                Some(r) => self.element(&path, l, r),
+                None => self.push(DiffKind::Lost, path, summary(l), None),
+            }
+        }
+
+        let mut right_seen: HashMap<String, usize> = HashMap::new();
+        for r in right {
+            let key = key_of(r);
+            let nth = *right_seen
+                .entry(key.clone())
+                .and_modify(|n| *n += 1)
+                .or_insert(0);
+            if nth >= seen.get(&key).map_or(0, |n| n + 1) {
+                let path = param_path(parent, element, &key, nth);
+                self.push(DiffKind::Added, path, None, summary(r));
+            }
+        }
+    }
+
+    fn binary(&mut self, path: String, left: &[Value], right: &[Value]) {
+        if left.len() != right.len() {
+            self.push(
+                DiffKind::Changed,
+                path,
+                Some(format!("length {}", left.len())),
+                Some(format!("length {}", right.len())),
+            );
+            return;
+        }
+        let first_mismatch =
+            left.iter()
+                .zip(right)
+                .position(|(l, r)| match (l.as_f64(), r.as_f64()) {
+                    (Some(a), Some(b)) => !self.number_eq(a, b),
+                    _ => l != r,
+                });
+        if let Some(i) = first_mismatch {
+            self.push(
+                DiffKind::Changed,
+                format!("{path}[{i}]"),
+                summary(&left[i]),
+                summary(&right[i]),
+            );
+        }
+    }
+
+    fn scalar_eq(&self, key: &str, left: &Value, right: &Value) -> bool {
+        if left == right {
+            return true;
+        }
+        match (left, right) {
+            (Value::String(l), Value::String(r)) if key == "value" => {
+                match (l.trim().parse::<f64>(), r.trim().parse::<f64>()) {
+                    (Ok(a), Ok(b)) => self.number_eq(a, b),
+                    _ => false,
+                }
+            }
+            (Value::Number(l), Value::Number(r)) => match (l.as_f64(), r.as_f64()) {
+                (Some(a), Some(b)) => self.number_eq(a, b),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    #[inline]
+    fn number_eq(&self, a: f64, b: f64) -> bool {
+        if a == b || (a.is_nan() && b.is_nan()) {
+            return true;
+        }
+        let scale = a.abs().max(b.abs());
+        (a - b).abs()
+            <= self
+                .options
+                .abs_tolerance
+                .max(self.options.rel_tolerance * scale)
+    }
+
+    #[inline]
+    fn push(&mut self, kind: DiffKind, path: String, left: Option<String>, right: Option<String>) {
+        let diffs = if self.normalizing {
+            &mut self.normalized
+        } else {
+            &mut self.diffs
+        };
+        diffs.push(DiffEntry {
+            kind,
+            path,
+            left,
+            right,
+        });
+    }
+}
+
+#[inline]
+fn to_value<T: Serialize>(value: &T) -> Value {
+    serde_json::to_value(value).unwrap_or(Value::Null)
+}
+
+fn param_groups(mzml: &MzML) -> ParamGroups {
+    mzml.referenceable_param_group_list
+        .iter()
+        .flat_map(|list| &list.referenceable_param_groups)
+        .map(|group| {
+            let params = |value: Value| match value {
+                Value::Array(items) => items,
+                _ => Vec::new(),
+            };
+            let cv_params = params(to_value(&group.cv_params));
+            let user_params = params(to_value(&group.user_params));
+            (group.id.clone(), (cv_params, user_params))
+        })
+        .collect()
+}
+
+/// Rewrites `value` into the form both sides are compared in: group refs
+/// are expanded into the params they name.
+fn normalize(value: &mut Value, groups: &ParamGroups) {
+    match value {
+        Value::Array(items) => items.iter_mut().for_each(|v| normalize(v, groups)),
+        Value::Object(map) => {
+            for key in GROUP_REF_KEYS {
+                if let Some(refs) = map.remove(*key) {
+                    expand_group_refs(map, key, refs, groups);
+                }
+            }
+            map.values_mut().for_each(|v| normalize(v, groups));
+        }
+        _ => {}
+    }
+}
+
+/// Appends the params of every group in `refs` to the owner's own lists.
+/// Refs to groups the document does not define are kept under `key`.
+fn expand_group_refs(
+    owner: &mut serde_json::Map<String, Value>,
+    key: &str,
+    refs: Value,
+    groups: &ParamGroups,
+) {
+    let refs = match refs {
+        Value::Array(items) => items,
+        Value::Null => Vec::new(),
+        other => vec![other],
+    };
+    let mut unresolved = Vec::new();
+    for group_ref in refs {
+        let group = group_ref
+            .get("ref")
+            .and_then(Value::as_str)
+            .and_then(|id| groups.get(id));
+        let Some((cv_params, user_params)) = group else {
+            unresolved.push(group_ref);
+            continue;
+        };
+        for (names, params) in [
+            (["cv_params", "cv_param"], cv_params),
+            (["user_params", "user_param"], user_params),
+        ] {
+            let list_key = names
+                .into_iter()
+                .find(|n| owner.contains_key(*n))
+                .unwrap_or(names[0]);
+            match owner
+                .entry(list_key)
+                .or_insert_with(|| Value::Array(Vec::new()))
+            {
+                Value::Array(items) => items.extend(params.iter().cloned()),
+                other => *other = Value::Array(params.clone()),
+            }
+        }
+    }
+    if !unresolved.is_empty() {
+        owner.insert(key.to_string(), Value::Array(unresolved));
+    }
+}
+
+#[inline]
+fn join(parent: &str, segment: &str) -> String {
+    if parent.is_empty() {
+        segment.to_string()
+    } else {
+        format!("{parent}/{segment}")
+    }
+}
+
+fn param_path(parent: &str, element: &str, key: &str, nth: usize) -> String {
+    if nth == 0 {
+        format!("{}[{key}]", join(parent, element))
+    } else {
+        format!("{}[{key}][{nth}]", join(parent, element))
+    }
+}
+
+fn param_element(key: &str) -> Option<(&'static str, ParamKey)> {
+    match key {
+        "cv_params" | "cv_param" => Some(("cvParam", cv_param_key)),
+        "user_params" | "user_param" => Some(("userParam", user_param_key)),
+        _ => None,
+    }
+}
+
+fn cv_param_key(value: &Value) -> String {
+    value
+        .get("accession")
+        .and_then(Value::as_str)
+        .filter(|a| !a.is_empty())
+        .or_else(|| value.get("name").and_then(Value::as_str))
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn user_param_key(value: &Value) -> String {
+    value
+        .get("name")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[inline]
+fn segment(key: &str, value: &Value) -> String {
+    match value {
+        Value::Object(_) => element_name(key, false),
+        Value::Array(_) => element_name(key, true),
+        _ => attribute_name(key),
+    }
+}
+
+/// Maps a struct field to the mzML element it was parsed from; repeated
+/// fields use the singular element name.
+fn element_name(key: &str, repeated: bool) -> String {
+    match key {
+        "spectra" => return "spectrum".to_string(),
+        "instrument" => return "instrumentConfiguration".to_string(),
+        "instrument_list" => return "instrumentConfigurationList".to_string(),
+        "scan_settings" => return "scanSettings".to_string(),
+        _ => {}
+    }
+    let name = camel_case(key);
+    match name.strip_suffix('s') {
+        Some(singular) if repeated => singular.to_string(),
+        _ => name,
+    }
+}
+
+#[inline]
+fn attribute_name(key: &str) -> String {
+    format!("@{}", camel_case(key))
+}
+
+fn camel_case(key: &str) -> String {
+    let mut out = String::with_capacity(key.len());
+    let mut upper = false;
+    for c in key.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Short printable form: scalars as text, elements by their id, accession
+/// or name.
+fn summary(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        Value::Number(_) | Value::Bool(_) => Some(value.to_string()),
+        Value::Object(map) => ["id", "accession", "name", "ref"]
+            .iter()
+            .find_map(|k| map.get(*k).and_then(Value::as_str))
+            .map(str::to_string),
+        Value::Array(items) => Some(format!("{} items", items.len())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cv(accession: &str, value: Option<&str>) -> CvParam {
+        CvParam {
+            cv_ref: Some("MS".to_string()),
+            accession: Some(accession.to_string()),
+            name: accession.to_string(),
+            value: value.map(str::to_string),
+            ..CvParam::default()
+        }
+    }
+
+    fn with_spectrum(spectrum: Spectrum) -> MzML {
+        MzML {
+            run: Run {
+                id: "run".to_string(),
+                spectrum_list: Some(SpectrumList {
+                    count: Some(1),
+                    spectra: vec![spectrum],
+                    ..SpectrumList::default()
+                }),
+                ..Run::default()
+            },
+            ..MzML::default()
+        }
+    }
+
+    /// Attributes the B000 encoder does not store yet, by path without
+    /// indices. Anything else the round trip changes fails the test.
+    const KNOWN_ENCODER_LOSSES: &[(DiffKind, &str)] = &[
+        (DiffKind::Lost, "run/@defaultSourceFileRef"),
+        (DiffKind::Lost, "run/spectrumList/spectrum/@scanNumber"),
+        (DiffKind::Lost, "run/spectrumList/spectrum/@msLevel"),
+        (
+            DiffKind::Lost,
+            "instrumentConfigurationList/instrumentConfiguration/softwareRef",
+        ),
+        (
+            DiffKind::Lost,
+            "dataProcessingList/dataProcessing/@softwareRef",
+        ),
+        (
+            DiffKind::Lost,
+            "dataProcessingList/dataProcessing/processingMethod/@order",
+        ),
+        (
+            DiffKind::Lost,
+            "dataProcessingList/dataProcessing/processingMethod/@softwareRef",
+        ),
+        (
+            DiffKind::Changed,
+            "softwareList/software/softwareParam/@version",
+        ),
+    ];
+
+    fn without_indices(path: &str) -> String {
+        let mut out = String::with_capacity(path.len());
+        let mut depth = 0;
+        for c in path.chars() {
+            match c {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                _ if depth == 0 => out.push(c),
+                _ => {}
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn fixtures_round_trip_only_lose_known_attributes() {
+        for path in [
+            "data/mzml/tiny.pwiz.mzML0.99.10.mzML",
+            "data/mzml/tiny4_LTQ-FT.mzML0.99.1.mzML",
+            "data/mzml/tiny2_SRM.mzML0.99.1.mzML",
+            "data/mzml/test.mzML",
+        ] {
+            let bytes = std::fs::read(path).unwrap();
+            let mzml = parse_mzml(&bytes).unwrap();
+            let report = verify_roundtrip(&mzml, 3, false, &CompareOptions::default()).unwrap();
+            let unexpected: Vec<_> = report
+                .diffs
+                .iter()
+                .filter(|d| {
+                    let path = without_indices(&d.path);
+                    !KNOWN_ENCODER_LOSSES.contains(&(d.kind, path.as_str()))
+                })
+                .collect();
+            assert!(unexpected.is_empty(), "{path}: {unexpected:#?}");
+        }
+    }
+
+    #[test]
+    fn group_refs_are_expanded_and_cv_labels_normalized() {
+        let group = ReferenceableParamGroup {
+            id: "g".to_string(),
+            cv_params: vec![cv("MS:1000130", None)],
+            user_params: Vec::new(),
+        };
+        let mut left = with_spectrum(Spectrum {
+            id: "s".to_string(),
+            referenceable_param_group_refs: vec![ReferenceableParamGroupRef {
+                r#ref: "g".to_string(),
+            }],
+            cv_params: vec![cv("MS:1000511", Some("1"))],
+            ..Spectrum::default()
+        });
+        left.referenceable_param_group_list = Some(ReferenceableParamGroupList {
+            count: Some(1),
+            referenceable_param_groups: vec![group],
+        });
+        let mut relabelled = cv("MS:1000511", Some("1"));
+        relabelled.name = "ms level".to_string();
+        relabelled.unit_cv_ref = Some("MS".to_string());
+        let mut right = with_spectrum(Spectrum {
+            id: "s".to_string(),
+            cv_params: vec![relabelled, cv("MS:1000130", None)],
+            ..Spectrum::default()
+        });
+        right.referenceable_param_group_list = left.referenceable_param_group_list.clone();
+        right.run.spectrum_list.as_mut().unwrap().count = Some(2);
+
+        let report = compare_mzml(&left, &right, &CompareOptions::default());
+        assert!(report.is_identical(), "{:#?}", report.diffs);
+        let cv_param = "run/spectrumList/spectrum[0]/cvParam[MS:1000511]";
+        let normalized: Vec<_> = report
+            .normalized
+            .iter()
+            .map(|d| (d.kind, d.path.as_str()))
+            .collect();
+        assert_eq!(
+            normalized,
+            [
+                (DiffKind::Changed, "run/spectrumList/@count"),
+                (DiffKind::Changed, format!("{cv_param}/@name").as_str()),
+                (DiffKind::Added, format!("{cv_param}/@unitCvRef").as_str()),
+            ]
+        );
+    }
+
+    #[test]
+    fn cv_params_compare_as_unordered_sets() {
+        let left = with_spectrum(Spectrum {
+            id: "s".to_string(),
+            cv_params: vec![cv("MS:1000511", Some("1")), cv("MS:1000130", None)],
+            ..Spectrum::default()
+        });
+        let right = with_spectrum(Spectrum {
+            id: "s".to_string(),
+            cv_params: vec![cv("MS:1000130", None), cv("MS:1000511", Some("1.0"))],
+            ..Spectrum::default()
+        });
+        assert!(compare_mzml(&left, &right, &CompareOptions::default()).is_identical());
+    }
+
+    #[test]
+    fn reports_lost_changed_and_added_with_paths() {
+        let left = with_spectrum(Spectrum {
+            id: "s".to_string(),
+            scan_list: Some(ScanList {
+                scans: vec![Scan {
+                    cv_params: vec![cv("MS:1000016", Some("5.0")), cv("MS:1000512", None)],
+                    ..Scan::default()
+                }],
+                ..ScanList::default()
+            }),
+            ..Spectrum::default()
+        });
+        let right = with_spectrum(Spectrum {
+            id: "s".to_string(),
+            scan_list: Some(ScanList {
+                scans: vec![Scan {
+                    cv_params: vec![cv("MS:1000016", Some("5.5")), cv("MS:1000927", None)],
+                    ..Scan::default()
+                }],
+                ..ScanList::default()
+            }),
+            ..Spectrum::default()
+        });
+
+        let report = compare_mzml(&left, &right, &CompareOptions::default());
+        let scan = "run/spectrumList/spectrum[0]/scanList/scan[0]";
+        assert_eq!(
+            report.diffs,
+            vec![
+                DiffEntry {
+                    kind: DiffKind::Changed,
+                    path: format!("{scan}/cvParam[MS:1000016]/@value"),
+                    left: Some("5.0".to_string()),
+                    right: Some("5.5".to_string()),
+                },
+                DiffEntry {
+                    kind: DiffKind::Lost,
+                    path: format!("{scan}/cvParam[MS:1000512]"),
+                    left: Some("MS:1000512".to_string()),
+                    right: None,
+                },
+                DiffEntry {
+                    kind: DiffKind::Added,
+                    path: format!("{scan}/cvParam[MS:1000927]"),
+                    left: None,
+                    right: Some("MS:1000927".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn binary_arrays_use_tolerance() {
+        let spectrum = |values: Vec<f64>| Spectrum {
+            id: "s".to_string(),
+            binary_data_array_list: Some(BinaryDataArrayList {
+                binary_data_arrays: vec![BinaryDataArray {
+                    binary: Some(BinaryData::F64(values)),
+                    ..BinaryDataArray::default()
+                }],
+                ..BinaryDataArrayList::default()
+            }),
+            ..Spectrum::default()
+        };
+        let left = with_spectrum(spectrum(vec![100.0, 200.0]));
+        let right = with_spectrum(spectrum(vec![100.0, 200.0001]));
+
+        let strict = compare_mzml(&left, &right, &CompareOptions::default());
+        assert_eq!(strict.diffs.len(), 1);
+        assert_eq!(
+            strict.diffs[0].path,
+            "run/spectrumList/spectrum[0]/binaryDataArrayList/binaryDataArray[0]/binary[1]"
+        );
+
+        let loose = CompareOptions {
+            rel_tolerance: 1e-6,
+            ..CompareOptions::default()
+        };
+        assert!(compare_mzml(&left, &right, &loose).is_identical());
+    }
+}
@@ -0,0 +1,4 @@
+pub mod parse_mzxml;
+pub use parse_mzxml::parse_mzxml;
+pub mod write_mzxml;
+pub use write_mzxml::{write_b000_as_mzxml, write_mzxml, write_mzxml_streaming};
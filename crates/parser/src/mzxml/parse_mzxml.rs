@@ -0,0 +1,1042 @@
+use std::io::{BufRead, Cursor};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use miniz_oxide::inflate::decompress_to_vec_zlib;
+use quick_xml::{
+    Reader,
+    events::{BytesStart, Event},
+};
+
+use crate::b64::utilities::cv_table;
+use crate::mzml::{
+    parse_mzml::{gunzip, is_gzip},
+    structs::*,
+    utilities::{
+        ParseError, attr, attr_u32, attr_usize,
+        parsing_workspace::ParsingWorkspace,
+        peak_list::{
+            ACC_CHARGE_STATE, ACC_MS_LEVEL, ACC_MZ, ACC_PEAK_INTENSITY, ACC_POSSIBLE_CHARGE_STATE,
+            ACC_SCAN_START_TIME, ACC_SELECTED_ION_MZ, ms_cv, ms_cv_with_unit, peak_arrays,
+            peak_list_mzml,
+        },
+        read_base64_binary, read_element_text,
+    },
+    validate_cv::descendants,
+};
+
+/// Unit accession and name.
+type Unit = (&'static str, &'static str);
+
+pub(crate) const MZ_UNIT: Unit = (ACC_MZ, "m/z");
+pub(crate) const COUNTS_UNIT: Unit = ("MS:1000131", "number of detector counts");
+
+/// Scan attributes kept as spectrum cvParams, with their unit.
+pub(crate) const SCAN_SUMMARY_TERMS: [(&str, &str, &str, Option<Unit>); 5] = [
+    ("lowMz", "MS:1000528", "lowest observed m/z", Some(MZ_UNIT)),
+    (
+        "highMz",
+        "MS:1000527",
+        "highest observed m/z",
+        Some(MZ_UNIT),
+    ),
+    ("basePeakMz", "MS:1000504", "base peak m/z", Some(MZ_UNIT)),
+    (
+        "basePeakIntensity",
+        "MS:1000505",
+        "base peak intensity",
+        Some(COUNTS_UNIT),
+    ),
+    ("totIonCurrent", "MS:1000285", "total ion current", None),
+];
+
+/// `activationMethod` values and their dissociation method terms.
+pub(crate) const ACTIVATION_TERMS: [(&str, &str, &str); 6] = [
+    ("CID", "MS:1000133", "collision-induced dissociation"),
+    (
+        "HCD",
+        "MS:1000422",
+        "beam-type collision-induced dissociation",
+    ),
+    ("ETD", "MS:1000598", "electron transfer dissociation"),
+    ("ECD", "MS:1000250", "electron capture dissociation"),
+    ("PQD", "MS:1000599", "pulsed q dissociation"),
+    ("IRMPD", "MS:1000262", "infrared multiphoton dissociation"),
+];
+
+/// `msIonisation` values and their ionization type terms.
+pub(crate) const IONISATION_TERMS: [(&str, &str, &str); 5] = [
+    ("ESI", "MS:1000073", "electrospray ionization"),
+    ("NSI", "MS:1000398", "nanoelectrospray"),
+    (
+        "MALDI",
+        "MS:1000075",
+        "matrix-assisted laser desorption ionization",
+    ),
+    (
+        "APCI",
+        "MS:1000070",
+        "atmospheric pressure chemical ionization",
+    ),
+    ("EI", "MS:1000389", "electron ionization"),
+];
+
+/// `msMassAnalyzer` values and their mass analyzer type terms.
+pub(crate) const ANALYZER_TERMS: [(&str, &str, &str); 6] = [
+    ("ITMS", "MS:1000264", "ion trap"),
+    (
+        "FTMS",
+        "MS:1000079",
+        "fourier transform ion cyclotron resonance mass spectrometer",
+    ),
+    ("Orbitrap", "MS:1000484", "orbitrap"),
+    ("TOF", "MS:1000084", "time-of-flight"),
+    ("Quadrupole", "MS:1000081", "quadrupole"),
+    ("TQMS", "MS:1000081", "quadrupole"),
+];
+
+/// `msDetector` values and their detector type terms.
+pub(crate) const DETECTOR_TERMS: [(&str, &str, &str); 1] =
+    [("EMT", "MS:1000253", "electron multiplier")];
+
+/// Software names with their own term; others are custom tools.
+pub(crate) const SOFTWARE_TERMS: [(&str, &str, &str); 5] = [
+    ("Xcalibur", "MS:1000532", "Xcalibur"),
+    ("ProteoWizard", "MS:1000615", "ProteoWizard software"),
+    ("pwiz", "MS:1000615", "ProteoWizard software"),
+    ("ReAdW", "MS:1000541", "ReAdW"),
+    ("MassWolf", "MS:1000538", "MassWolf"),
+];
+
+#[inline]
+pub(crate) fn lookup<'a>(
+    terms: &'a [(&str, &str, &str)],
+    value: &str,
+) -> Option<(&'a str, &'a str)> {
+    terms
+        .iter()
+        .find(|(key, ..)| key.eq_ignore_ascii_case(value))
+        .map(|&(_, accession, name)| (accession, name))
+}
+
+/// The term for `value`: its entry in `terms`, or else the PSI-MS term
+/// under `category` of that name, which is what the writer puts for terms
+/// without an mzXML value.
+fn term_under<'a>(
+    terms: &'a [(&str, &str, &str)],
+    category: &str,
+    value: &str,
+) -> Option<(&'a str, &'a str)> {
+    lookup(terms, value).or_else(|| {
+        descendants(category).iter().find_map(|accession| {
+            let name = cv_table::get(accession)?.as_str()?;
+            name.eq_ignore_ascii_case(value)
+                .then_some((accession.as_str(), name))
+        })
+    })
+}
+
+/// Reads an mzXML document (2.x or 3.x, optionally gzipped) into the mzML
+/// structs.
+///
+/// Every `<scan>`, nested ones included, becomes a spectrum with id
+/// `scan=<num>`, in document order. Scan attributes map to their PSI-MS
+/// terms, `<precursorMz>` to a precursor referring to `precursorScanNum`
+/// (or the enclosing scan), and `<peaks>` to 32- or 64-bit m/z and
+/// intensity arrays. `<parentFile>`s become source files, `<msInstrument>`s
+/// instrument configurations and `<dataProcessing>` data processing
+/// entries. The scan index is not used.
+pub fn parse_mzxml(bytes: &[u8]) -> Result<MzML, ParseError> {
+    if is_gzip(bytes) {
+        return parse_mzxml(&gunzip(bytes)?);
+    }
+    let mut ws = ParsingWorkspace::new(Reader::from_reader(Cursor::new(bytes)));
+    let mut doc = Document::default();
+    loop {
+        match ws.next_event()? {
+            Event::Start(e) if e.local_name().as_ref() == b"msRun" => {
+                doc.parse_ms_run(&mut ws)?;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(doc.into_mzml())
+}
+
+#[derive(Default)]
+struct Document {
+    sources: Vec<SourceFile>,
+    instruments: Vec<Instrument>,
+    software: Vec<Software>,
+    data_processing: Vec<DataProcessing>,
+    /// `centroided` of the first `<dataProcessing>`, for scans without one.
+    centroided: Option<bool>,
+    spectra: Vec<Spectrum>,
+}
+
+impl Document {
+    fn parse_ms_run<R: BufRead>(&mut self, ws: &mut ParsingWorkspace<R>) -> Result<(), ParseError> {
+        loop {
+            match ws.next_event()? {
+                Event::Start(e) => match e.local_name().as_ref() {
+                    b"parentFile" => {
+                        self.add_parent_file(&e);
+                        ws.skip_element(b"parentFile", None)?;
+                    }
+                    b"msInstrument" => self.parse_ms_instrument(ws, &e)?,
+                    b"instrument" => {
+                        self.add_legacy_instrument(&e);
+                        ws.skip_element(b"instrument", None)?;
+                    }
+                    b"dataProcessing" => self.parse_data_processing(ws, &e)?,
+                    b"scan" => self.parse_scan(ws, &e, None, false)?,
+                    other => ws.skip_element(other, None)?,
+                },
+                Event::Empty(e) => match e.local_name().as_ref() {
+                    b"parentFile" => self.add_parent_file(&e),
+                    b"instrument" => self.add_legacy_instrument(&e),
+                    b"dataProcessing" => self.add_data_processing(&e, Vec::new()),
+                    b"scan" => self.parse_scan(ws, &e, None, true)?,
+                    _ => {}
+                },
+                Event::End(e) if e.local_name().as_ref() == b"msRun" => return Ok(()),
+                Event::Eof => {
+                    return Err(ParseError::UnexpectedEof {
+                        context: "msRun".to_string(),
+                        byte_offset: ws.xml_reader.buffer_position(),
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// `fileName` is a path or `file://` URI; `fileSha1` is kept as the
+    /// checksum.
+    fn add_parent_file(&mut self, e: &BytesStart<'_>) {
+        let path = attr(e, b"fileName").unwrap_or_default();
+        let (location, name) = match path.rfind(['/', '\\']) {
+            Some(i) => (path[..i].to_string(), path[i + 1..].to_string()),
+            None => (String::new(), path.clone()),
+        };
+        let location = if location.contains("://") {
+            location
+        } else {
+            format!("file://{location}")
+        };
+        let mut cv_param = vec![ms_cv(
+            "MS:1000776",
+            "scan number only nativeID format",
+            None,
+        )];
+        if let Some(format) = file_format_term(&name) {
+            cv_param.push(format);
+        }
+        if let Some(sha1) = attr(e, b"fileSha1").filter(|s| !s.is_empty()) {
+            cv_param.push(ms_cv("MS:1000569", "SHA-1", Some(sha1)));
+        }
+        self.sources.push(SourceFile {
+            id: format!("PF{}", self.sources.len() + 1),
+            name,
+            location,
+            cv_param,
+            ..Default::default()
+        });
+    }
+
+    fn parse_ms_instrument<R: BufRead>(
+        &mut self,
+        ws: &mut ParsingWorkspace<R>,
+        start: &BytesStart<'_>,
+    ) -> Result<(), ParseError> {
+        let id = attr(start, b"msInstrumentID")
+            .or_else(|| attr(start, b"id"))
+            .unwrap_or_else(|| (self.instruments.len() + 1).to_string());
+        let mut instrument = new_instrument(&id);
+        let mut components = ComponentList::default();
+        loop {
+            let element = match ws.next_event()? {
+                Event::Empty(e) => e,
+                Event::Start(e) => {
+                    let name = e.local_name().as_ref().to_vec();
+                    ws.skip_element(&name, None)?;
+                    e
+                }
+                Event::End(e) if e.local_name().as_ref() == b"msInstrument" => break,
+                Event::Eof => {
+                    return Err(ParseError::UnexpectedEof {
+                        context: "msInstrument".to_string(),
+                        byte_offset: ws.xml_reader.buffer_position(),
+                    });
+                }
+                _ => continue,
+            };
+            let value = attr(&element, b"value").unwrap_or_default();
+            match element.local_name().as_ref() {
+                b"msIonisation" => {
+                    add_component(&mut components, Component::Source, "msIonisation", &value);
+                }
+                b"msMassAnalyzer" => {
+                    add_component(
+                        &mut components,
+                        Component::Analyzer,
+                        "msMassAnalyzer",
+                        &value,
+                    );
+                }
+                b"msDetector" => {
+                    add_component(&mut components, Component::Detector, "msDetector", &value);
+                }
+                b"software" => {
+                    let software_id = self.add_software(&element);
+                    instrument.software_ref = Some(InstrumentSoftwareRef { r#ref: software_id });
+                }
+                b"msManufacturer" | b"msModel" | b"msResolution" | b"nameValue" => {
+                    let name = match element.local_name().as_ref() {
+                        b"nameValue" => attr(&element, b"name").unwrap_or_default(),
+                        other => String::from_utf8_lossy(other).into_owned(),
+                    };
+                    instrument.user_param.push(user_param(name, value));
+                }
+                _ => {}
+            }
+        }
+        set_component_list(&mut instrument, components);
+        self.instruments.push(instrument);
+        Ok(())
+    }
+
+    /// mzXML 2.0 `<instrument manufacturer model ionisation msType>`.
+    fn add_legacy_instrument(&mut self, e: &BytesStart<'_>) {
+        let mut instrument = new_instrument(&(self.instruments.len() + 1).to_string());
+        for key in ["manufacturer", "model"] {
+            if let Some(value) = attr(e, key.as_bytes()) {
+                instrument
+                    .user_param
+                    .push(user_param(key.to_string(), value));
+            }
+        }
+        let mut components = ComponentList::default();
+        for (attribute, kind) in [
+            ("ionisation", Component::Source),
+            ("msType", Component::Analyzer),
+            ("detector", Component::Detector),
+        ] {
+            if let Some(value) = attr(e, attribute.as_bytes()) {
+                add_component(&mut components, kind, attribute, &value);
+            }
+        }
+        set_component_list(&mut instrument, components);
+        self.instruments.push(instrument);
+    }
+
+    /// Adds the `<software type name version>` entry unless already listed
+    /// and returns its id.
+    fn add_software(&mut self, e: &BytesStart<'_>) -> String {
+        let name = attr(e, b"name").unwrap_or_else(|| "unknown".to_string());
+        let version = attr(e, b"version");
+        let id = match &version {
+            Some(version) => format!("{name}_{version}"),
+            None => name.clone(),
+        }
+        .replace(char::is_whitespace, "_");
+        if self.software.iter().all(|s| s.id != id) {
+            let cv = match lookup(&SOFTWARE_TERMS, &name) {
+                Some((accession, term)) => ms_cv(accession, term, None),
+                None => ms_cv("MS:1000799", "custom unreleased software tool", Some(name)),
+            };
+            self.software.push(Software {
+                id: id.clone(),
+                version,
+                cv_param: vec![cv],
+                ..Default::default()
+            });
+        }
+        id
+    }
+
+    fn parse_data_processing<R: BufRead>(
+        &mut self,
+        ws: &mut ParsingWorkspace<R>,
+        start: &BytesStart<'_>,
+    ) -> Result<(), ParseError> {
+        let mut children = Vec::new();
+        loop {
+            match ws.next_event()? {
+                Event::Empty(e) => children.push(e),
+                Event::Start(e) => {
+                    let name = e.local_name().as_ref().to_vec();
+                    ws.skip_element(&name, None)?;
+                    children.push(e);
+                }
+                Event::End(e) if e.local_name().as_ref() == b"dataProcessing" => break,
+                Event::Eof => {
+                    return Err(ParseError::UnexpectedEof {
+                        context: "dataProcessing".to_string(),
+                        byte_offset: ws.xml_reader.buffer_position(),
+                    });
+                }
+                _ => {}
+            }
+        }
+        self.add_data_processing(start, children);
+        Ok(())
+    }
+
+    /// One processing method per `<software>`: file format conversion for
+    /// conversion software, otherwise the operations flagged on
+    /// `<dataProcessing>`. `<processingOperation>`s become userParams of
+    /// the last method.
+    fn add_data_processing(&mut self, start: &BytesStart<'_>, children: Vec<BytesStart<'_>>) {
+        let flag = |name: &[u8]| attr(start, name).map(|v| v == "1" || v == "true");
+        let centroided = flag(b"centroided");
+        if self.centroided.is_none() {
+            self.centroided = centroided;
+        }
+        let mut operations = Vec::new();
+        if centroided == Some(true) {
+            operations.push(ms_cv("MS:1000035", "peak picking", None));
+        }
+        if flag(b"deisotoped") == Some(true) {
+            operations.push(ms_cv("MS:1000033", "deisotoping", None));
+        }
+        if flag(b"chargeDeconvoluted") == Some(true) {
+            operations.push(ms_cv("MS:1000034", "charge deconvolution", None));
+        }
+
+        let mut methods: Vec<ProcessingMethod> = Vec::new();
+        for child in &children {
+            match child.local_name().as_ref() {
+                b"software" => {
+                    let cv_param = if attr(child, b"type").as_deref() == Some("conversion") {
+                        vec![ms_cv("MS:1000530", "file format conversion", None)]
+                    } else if operations.is_empty() {
+                        vec![ms_cv("MS:1000452", "data transformation", None)]
+                    } else {
+                        operations.clone()
+                    };
+                    methods.push(ProcessingMethod {
+                        order: Some(methods.len() as u32),
+                        software_ref: Some(self.add_software(child)),
+                        cv_param,
+                        ..Default::default()
+                    });
+                }
+                b"processingOperation" => {
+                    if let Some(method) = methods.last_mut() {
+                        let name = attr(child, b"name").unwrap_or_default();
+                        let value = attr(child, b"value").unwrap_or_default();
+                        method.user_param.push(user_param(name, value));
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !methods.is_empty() {
+            self.data_processing.push(DataProcessing {
+                id: format!("mzXML_processing_{}", self.data_processing.len() + 1),
+                processing_method: methods,
+                ..Default::default()
+            });
+        }
+    }
+
+    /// Pushes the spectrum of the `<scan>` just read, then those of its
+    /// nested scans. `parent` is the num of the enclosing scan.
+    fn parse_scan<R: BufRead>(
+        &mut self,
+        ws: &mut ParsingWorkspace<R>,
+        start: &BytesStart<'_>,
+        parent: Option<u32>,
+        empty: bool,
+    ) -> Result<(), ParseError> {
+        let offset = ws.element_offset();
+        let index = self.spectra.len();
+        let num = attr_u32(start, b"num").unwrap_or(index as u32 + 1);
+        let spectrum = scan_spectrum(start, index, num, self.centroided, offset)?;
+        self.spectra.push(spectrum);
+        if empty {
+            return Ok(());
+        }
+
+        let collision_energy = attr(start, b"collisionEnergy");
+        loop {
+            match ws.next_event()? {
+                Event::Start(e) => match e.local_name().as_ref() {
+                    b"scan" => self.parse_scan(ws, &e, Some(num), false)?,
+                    b"precursorMz" => {
+                        let mz = read_element_text(ws, b"precursorMz")?;
+                        let precursor = precursor(&e, mz.trim(), parent, &collision_energy);
+                        push_precursor(&mut self.spectra[index], precursor);
+                    }
+                    b"peaks" => {
+                        let offset = ws.element_offset();
+                        let mut raw = Vec::new();
+                        read_base64_binary(ws, b"peaks", &mut raw)?;
+                        set_peaks(&mut self.spectra[index], &e, &raw, offset)?;
+                    }
+                    other => ws.skip_element(other, None)?,
+                },
+                Event::Empty(e) => match e.local_name().as_ref() {
+                    b"scan" => self.parse_scan(ws, &e, Some(num), true)?,
+                    b"peaks" => set_peaks(&mut self.spectra[index], &e, &[], ws.element_offset())?,
+                    b"nameValue" => {
+                        let name = attr(&e, b"name").unwrap_or_default();
+                        let value = attr(&e, b"value").unwrap_or_default();
+                        self.spectra[index]
+                            .user_params
+                            .push(user_param(name, value));
+                    }
+                    _ => {}
+                },
+                Event::End(e) if e.local_name().as_ref() == b"scan" => return Ok(()),
+                Event::Eof => {
+                    return Err(ParseError::UnexpectedEof {
+                        context: "scan".to_string(),
+                        byte_offset: ws.xml_reader.buffer_position(),
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn into_mzml(self) -> MzML {
+        let run_id = self
+            .sources
+            .first()
+            .map(|s| s.name.rsplit_once('.').map_or(&*s.name, |(stem, _)| stem))
+            .filter(|stem| !stem.is_empty())
+            .unwrap_or("mzXML")
+            .to_string();
+        let mut mzml = peak_list_mzml(&run_id, self.sources, self.spectra);
+        if let Some(first) = self.instruments.first() {
+            mzml.run.default_instrument_configuration_ref = Some(first.id.clone());
+            mzml.instrument_list = Some(InstrumentList {
+                count: Some(self.instruments.len()),
+                instrument: self.instruments,
+            });
+        }
+        if let Some(list) = &mut mzml.software_list {
+            list.software.splice(0..0, self.software);
+            list.count = Some(list.software.len());
+        }
+        if let Some(list) = &mut mzml.data_processing_list {
+            list.data_processing.splice(0..0, self.data_processing);
+            list.count = Some(list.data_processing.len());
+        }
+        mzml
+    }
+}
+
+fn new_instrument(id: &str) -> Instrument {
+    Instrument {
+        id: format!("IC{id}"),
+        cv_param: vec![ms_cv("MS:1000031", "instrument model", None)],
+        ..Default::default()
+    }
+}
+
+fn set_component_list(instrument: &mut Instrument, components: ComponentList) {
+    let count = components.source.len() + components.analyzer.len() + components.detector.len();
+    if count > 0 {
+        instrument.component_list = Some(ComponentList {
+            count: Some(count),
+            ..components
+        });
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Component {
+    Source,
+    Analyzer,
+    Detector,
+}
+
+/// Adds the component described by `value`: its term, or a userParam
+/// named `attribute` when there is none.
+fn add_component(list: &mut ComponentList, kind: Component, attribute: &str, value: &str) {
+    let (terms, category): (&[(&str, &str, &str)], _) = match kind {
+        Component::Source => (&IONISATION_TERMS, "MS:1000008"),
+        Component::Analyzer => (&ANALYZER_TERMS, "MS:1000443"),
+        Component::Detector => (&DETECTOR_TERMS, "MS:1000026"),
+    };
+    let (cv_param, user_param) = match term_under(terms, category, value) {
+        Some((accession, name)) => (vec![ms_cv(accession, name, None)], vec![]),
+        None => (
+            vec![],
+            vec![user_param(attribute.to_string(), value.to_string())],
+        ),
+    };
+    match kind {
+        Component::Source => list.source.push(Source {
+            order: Some(1),
+            cv_param,
+            user_param,
+            ..Default::default()
+        }),
+        Component::Analyzer => list.analyzer.push(Analyzer {
+            order: Some(2),
+            cv_param,
+            user_param,
+            ..Default::default()
+        }),
+        Component::Detector => list.detector.push(Detector {
+            order: Some(3),
+            cv_param,
+            user_param,
+            ..Default::default()
+        }),
+    }
+}
+
+#[inline]
+fn user_param(name: String, value: String) -> UserParam {
+    UserParam {
+        name,
+        value: Some(value),
+        ..Default::default()
+    }
+}
+
+/// File format of a parent file, from its extension.
+fn file_format_term(name: &str) -> Option<CvParam> {
+    let extension = name.rsplit_once('.')?.1.to_ascii_lowercase();
+    let (accession, term) = match extension.as_str() {
+        "raw" => ("MS:1000563", "Thermo RAW format"),
+        "wiff" => ("MS:1000562", "ABI WIFF format"),
+        "mzxml" => ("MS:1000566", "ISB mzXML format"),
+        "mzml" => ("MS:1000584", "mzML format"),
+        "mgf" => ("MS:1001062", "Mascot MGF format"),
+        "baf" => ("MS:1000815", "Bruker BAF format"),
+        _ => return None,
+    };
+    Some(ms_cv(accession, term, None))
+}
+
+/// Seconds in an `xs:duration` such as `PT12.5S` or `P0DT1M2.5S`. Years
+/// and months have no fixed length and are rejected.
+pub(crate) fn duration_seconds(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let (sign, value) = match value.strip_prefix('-') {
+        Some(rest) => (-1.0, rest),
+        None => (1.0, value),
+    };
+    let mut rest = value.strip_prefix('P')?;
+    let (mut seconds, mut in_time) = (0.0, false);
+    while !rest.is_empty() {
+        if let Some(time) = rest.strip_prefix('T') {
+            (rest, in_time) = (time, true);
+            continue;
+        }
+        let end = rest.find(|c: char| c.is_ascii_alphabetic())?;
+        let amount: f64 = rest[..end].parse().ok()?;
+        seconds += amount
+            * match (rest.as_bytes()[end], in_time) {
+                (b'D', false) => 86400.0,
+                (b'H', true) => 3600.0,
+                (b'M', true) => 60.0,
+                (b'S', true) => 1.0,
+                _ => return None,
+            };
+        rest = &rest[end + 1..];
+    }
+    Some(sign * seconds)
+}
+
+/// Spectrum of a `<scan>` start tag, without precursors or peaks.
+fn scan_spectrum(
+    start: &BytesStart<'_>,
+    index: usize,
+    num: u32,
+    default_centroided: Option<bool>,
+    offset: u64,
+) -> Result<Spectrum, ParseError> {
+    let ms_level = attr_u32(start, b"msLevel").unwrap_or(1);
+    let mut cv_params = vec![
+        ms_cv(ACC_MS_LEVEL, "ms level", Some(ms_level.to_string())),
+        if ms_level == 1 {
+            ms_cv("MS:1000579", "MS1 spectrum", None)
+        } else {
+            ms_cv("MS:1000580", "MSn spectrum", None)
+        },
+    ];
+    // `centroided` defaults to "0" in the mzXML schema.
+    let centroided = attr(start, b"centroided").map(|v| v == "1" || v == "true");
+    if centroided.or(default_centroided).unwrap_or(false) {
+        cv_params.push(ms_cv("MS:1000127", "centroid spectrum", None));
+    } else {
+        cv_params.push(ms_cv("MS:1000128", "profile spectrum", None));
+    }
+    match attr(start, b"polarity").as_deref() {
+        Some("+") => cv_params.push(ms_cv("MS:1000130", "positive scan", None)),
+        Some("-") => cv_params.push(ms_cv("MS:1000129", "negative scan", None)),
+        _ => {}
+    }
+    for (attribute, accession, name, unit) in SCAN_SUMMARY_TERMS {
+        if let Some(value) = attr(start, attribute.as_bytes()) {
+            cv_params.push(match unit {
+                Some(unit) => ms_cv_with_unit(accession, name, value, unit),
+                None => ms_cv(accession, name, Some(value)),
+            });
+        }
+    }
+    let user_params = attr(start, b"scanType")
+        .map(|value| vec![user_param("scanType".to_string(), value)])
+        .unwrap_or_default();
+
+    let mut scan = Scan {
+        instrument_configuration_ref: attr(start, b"msInstrumentID").map(|id| format!("IC{id}")),
+        ..Default::default()
+    };
+    if let Some(value) = attr(start, b"retentionTime") {
+        let seconds = duration_seconds(&value).ok_or_else(|| ParseError::InvalidValue {
+            context: "scan retentionTime".to_string(),
+            value: value.clone(),
+            byte_offset: offset,
+        })?;
+        scan.cv_params.push(ms_cv_with_unit(
+            ACC_SCAN_START_TIME,
+            "scan start time",
+            seconds.to_string(),
+            ("UO:0000010", "second"),
+        ));
+    }
+    if let Some(filter) = attr(start, b"filterLine") {
+        scan.cv_params
+            .push(ms_cv("MS:1000512", "filter string", Some(filter)));
+    }
+    let mut window = Vec::new();
+    if let Some(value) = attr(start, b"startMz") {
+        window.push(ms_cv_with_unit(
+            "MS:1000501",
+            "scan window lower limit",
+            value,
+            MZ_UNIT,
+        ));
+    }
+    if let Some(value) = attr(start, b"endMz") {
+        window.push(ms_cv_with_unit(
+            "MS:1000500",
+            "scan window upper limit",
+            value,
+            MZ_UNIT,
+        ));
+    }
+    if !window.is_empty() {
+        scan.scan_window_list = Some(ScanWindowList {
+            count: Some(1),
+            scan_windows: vec![ScanWindow {
+                cv_params: window,
+                ..Default::default()
+            }],
+        });
+    }
+
+    Ok(Spectrum {
+        id: format!("scan={num}"),
+        index: Some(index as u32),
+        default_array_length: attr_usize(start, b"peaksCount"),
+        ms_level: Some(ms_level),
+        cv_params,
+        user_params,
+        scan_list: Some(ScanList {
+            count: Some(1),
+            cv_params: vec![ms_cv("MS:1000795", "no combination", None)],
+            scans: vec![scan],
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+/// Precursor of a `<precursorMz>` with m/z `mz`. The activation holds the
+/// method and the `collisionEnergy` of the scan.
+fn precursor(
+    e: &BytesStart<'_>,
+    mz: &str,
+    parent: Option<u32>,
+    collision_energy: &Option<String>,
+) -> Precursor {
+    let isolation_window = attr(e, b"windowWideness")
+        .and_then(|w| w.trim().parse::<f64>().ok())
+        .map(|width| {
+            let half = (width / 2.0).to_string();
+            IsolationWindow {
+                cv_params: vec![
+                    ms_cv_with_unit(
+                        "MS:1000827",
+                        "isolation window target m/z",
+                        mz.to_string(),
+                        MZ_UNIT,
+                    ),
+                    ms_cv_with_unit(
+                        "MS:1000828",
+                        "isolation window lower offset",
+                        half.clone(),
+                        MZ_UNIT,
+                    ),
+                    ms_cv_with_unit("MS:1000829", "isolation window upper offset", half, MZ_UNIT),
+                ],
+                ..Default::default()
+            }
+        });
+
+    let mut ion = vec![ms_cv_with_unit(
+        ACC_SELECTED_ION_MZ,
+        "selected ion m/z",
+        mz.to_string(),
+        MZ_UNIT,
+    )];
+    if let Some(charge) = attr(e, b"precursorCharge") {
+        ion.push(ms_cv(ACC_CHARGE_STATE, "charge state", Some(charge)));
+    } else if let Some(charges) = attr(e, b"possibleCharges") {
+        for z in charges.split([',', ' ']).filter(|z| !z.is_empty()) {
+            ion.push(ms_cv(
+                ACC_POSSIBLE_CHARGE_STATE,
+                "possible charge state",
+                Some(z.to_string()),
+            ));
+        }
+    }
+    // Some writers put 0 for an unknown intensity.
+    let intensity = attr(e, b"precursorIntensity");
+    if let Some(intensity) = intensity.filter(|i| i.parse::<f64>() != Ok(0.0)) {
+        ion.push(ms_cv_with_unit(
+            ACC_PEAK_INTENSITY,
+            "peak intensity",
+            intensity,
+            COUNTS_UNIT,
+        ));
+    }
+
+    let mut activation = Activation::default();
+    if let Some(method) = attr(e, b"activationMethod") {
+        match term_under(&ACTIVATION_TERMS, "MS:1000044", &method) {
+            Some((accession, name)) => activation.cv_params.push(ms_cv(accession, name, None)),
+            None => activation
+                .user_params
+                .push(user_param("activationMethod".to_string(), method)),
+        }
+    }
+    if let Some(energy) = collision_energy {
+        activation.cv_params.push(ms_cv_with_unit(
+            "MS:1000045",
+            "collision energy",
+            energy.clone(),
+            ("UO:0000266", "electronvolt"),
+        ));
+    }
+
+    Precursor {
+        spectrum_ref: attr_u32(e, b"precursorScanNum")
+            .or(parent)
+            .map(|num| format!("scan={num}")),
+        isolation_window,
+        selected_ion_list: Some(SelectedIonList {
+            count: Some(1),
+            selected_ions: vec![SelectedIon {
+                cv_params: ion,
+                ..Default::default()
+            }],
+        }),
+        activation: Some(activation),
+        ..Default::default()
+    }
+}
+
+fn push_precursor(spectrum: &mut Spectrum, precursor: Precursor) {
+    let list = spectrum.precursor_list.get_or_insert_with(Default::default);
+    list.precursors.push(precursor);
+    list.count = Some(list.precursors.len());
+}
+
+/// Decodes `<peaks>` (base64 `text`) into the m/z and intensity arrays of
+/// `spectrum`.
+fn set_peaks(
+    spectrum: &mut Spectrum,
+    e: &BytesStart<'_>,
+    text: &[u8],
+    offset: u64,
+) -> Result<(), ParseError> {
+    let invalid = |context: &str, value: String| ParseError::InvalidValue {
+        context: format!("peaks {context}"),
+        value,
+        byte_offset: offset,
+    };
+    let precision = attr(e, b"precision").unwrap_or_else(|| "32".to_string());
+    let width = match precision.as_str() {
+        "32" => 4,
+        "64" => 8,
+        _ => return Err(invalid("precision", precision)),
+    };
+    let byte_order = attr(e, b"byteOrder").unwrap_or_else(|| "network".to_string());
+    if byte_order != "network" {
+        return Err(invalid("byteOrder", byte_order));
+    }
+    let content = attr(e, b"contentType")
+        .or_else(|| attr(e, b"pairOrder"))
+        .unwrap_or_else(|| "m/z-int".to_string());
+    if content != "m/z-int" {
+        return Err(invalid("contentType", content));
+    }
+    let zlib = match attr(e, b"compressionType").as_deref() {
+        None | Some("none") => false,
+        Some("zlib") => true,
+        Some(other) => return Err(invalid("compressionType", other.to_string())),
+    };
+
+    let mut bytes = STANDARD.decode(text)?;
+    if zlib && !bytes.is_empty() {
+        bytes = decompress_to_vec_zlib(&bytes)
+            .map_err(|err| ParseError::Decompress(format!("{err:?}")))?;
+    }
+    if bytes.len() % (2 * width) != 0 {
+        return Err(invalid("length", bytes.len().to_string()));
+    }
+
+    let pairs = bytes.chunks_exact(2 * width);
+    let (mz, intensity) = if width == 4 {
+        let value = |b: &[u8]| f32::from_be_bytes(b.try_into().unwrap());
+        let (mz, intensity) = pairs.map(|p| (value(&p[..4]), value(&p[4..]))).unzip();
+        (BinaryData::F32(mz), BinaryData::F32(intensity))
+    } else {
+        let value = |b: &[u8]| f64::from_be_bytes(b.try_into().unwrap());
+        let (mz, intensity) = pairs.map(|p| (value(&p[..8]), value(&p[8..]))).unzip();
+        (BinaryData::F64(mz), BinaryData::F64(intensity))
+    };
+    spectrum.default_array_length = Some(bytes.len() / (2 * width));
+    spectrum.binary_data_array_list = Some(peak_arrays(mz, intensity, zlib));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mzml::utilities::peak_list::{
+        array_f64, find_cv, precursor_charges, precursor_intensity, precursor_mz,
+        scan_start_seconds,
+    };
+    use miniz_oxide::deflate::compress_to_vec_zlib;
+
+    fn peaks_32(pairs: &[(f32, f32)]) -> Vec<u8> {
+        pairs
+            .iter()
+            .flat_map(|(mz, i)| [mz.to_be_bytes(), i.to_be_bytes()].concat())
+            .collect()
+    }
+
+    fn peaks_64(pairs: &[(f64, f64)]) -> Vec<u8> {
+        pairs
+            .iter()
+            .flat_map(|(mz, i)| [mz.to_be_bytes(), i.to_be_bytes()].concat())
+            .collect()
+    }
+
+    fn document() -> String {
+        let ms1 = STANDARD.encode(compress_to_vec_zlib(
+            &peaks_32(&[(400.5, 10.0), (401.25, 20.0)]),
+            6,
+        ));
+        let ms2 = STANDARD.encode(peaks_64(&[(150.125, 3.5)]));
+        format!(
+            r#"<?xml version="1.0" encoding="ISO-8859-1"?>
+<mzXML xmlns="http://sashimi.sourceforge.net/schema_revision/mzXML_3.2">
+ <msRun scanCount="2">
+  <parentFile fileName="file://C:/data/sample.RAW" fileType="RAWData" fileSha1="0123456789abcdef0123456789abcdef01234567"/>
+  <msInstrument msInstrumentID="1">
+   <msManufacturer category="msManufacturer" value="Thermo Scientific"/>
+   <msModel category="msModel" value="LTQ Orbitrap"/>
+   <msIonisation category="msIonisation" value="ESI"/>
+   <msMassAnalyzer category="msMassAnalyzer" value="FTMS"/>
+   <msDetector category="msDetector" value="unknown"/>
+   <software type="acquisition" name="Xcalibur" version="2.0"/>
+  </msInstrument>
+  <dataProcessing centroided="1">
+   <software type="conversion" name="ReAdW" version="4.0"/>
+  </dataProcessing>
+  <scan num="7" msLevel="1" peaksCount="2" polarity="+" retentionTime="PT1M0.5S" lowMz="400.5" highMz="401.25" totIonCurrent="30" filterLine="FTMS + p ESI Full ms">
+   <peaks precision="32" byteOrder="network" contentType="m/z-int" compressionType="zlib" compressedLen="20">{ms1}</peaks>
+   <scan num="8" msLevel="2" peaksCount="1" retentionTime="PT61S" collisionEnergy="35">
+    <precursorMz precursorIntensity="1200" precursorCharge="2" activationMethod="CID" windowWideness="2">400.5</precursorMz>
+    <peaks precision="64" byteOrder="network" pairOrder="m/z-int">{ms2}</peaks>
+   </scan>
+  </scan>
+ </msRun>
+ <index name="scan"><offset id="7">0</offset></index>
+ <indexOffset>0</indexOffset>
+</mzXML>
+"#
+        )
+    }
+
+    #[test]
+    fn maps_nested_scans_to_spectra() {
+        let mzml = parse_mzxml(document().as_bytes()).unwrap();
+        assert_eq!(mzml.run.id, "sample");
+        let spectra = &mzml.run.spectrum_list.as_ref().unwrap().spectra;
+        assert_eq!(spectra.len(), 2);
+
+        let ms1 = &spectra[0];
+        assert_eq!(ms1.id, "scan=7");
+        assert_eq!(ms1.ms_level, Some(1));
+        assert_eq!(scan_start_seconds(ms1), Some(60.5));
+        assert!(
+            find_cv(&ms1.cv_params, "MS:1000127").is_some(),
+            "centroided default"
+        );
+        assert!(matches!(
+            &ms1.binary_data_array_list.as_ref().unwrap().binary_data_arrays[0].binary,
+            Some(BinaryData::F32(v)) if v == &[400.5, 401.25]
+        ));
+
+        let ms2 = &spectra[1];
+        assert_eq!(ms2.id, "scan=8");
+        assert_eq!(precursor_mz(ms2), Some(400.5));
+        assert_eq!(precursor_charges(ms2), vec![2]);
+        let precursor = &ms2.precursor_list.as_ref().unwrap().precursors[0];
+        assert_eq!(precursor.spectrum_ref.as_deref(), Some("scan=7"));
+        let activation = &precursor.activation.as_ref().unwrap().cv_params;
+        assert!(find_cv(activation, "MS:1000133").is_some());
+        assert!(find_cv(activation, "MS:1000045").is_some());
+        assert_eq!(array_f64(ms2, "MS:1000515"), Some(vec![3.5]));
+
+        let instrument = &mzml.instrument_list.as_ref().unwrap().instrument[0];
+        let components = instrument.component_list.as_ref().unwrap();
+        assert_eq!(components.count, Some(3));
+        assert!(find_cv(&components.source[0].cv_param, "MS:1000073").is_some());
+        assert_eq!(
+            components.detector[0].user_param[0].value.as_deref(),
+            Some("unknown")
+        );
+        let software = &mzml.software_list.as_ref().unwrap().software;
+        assert_eq!(software[0].id, "Xcalibur_2.0");
+        assert_eq!(software[1].id, "ReAdW_4.0");
+    }
+
+    #[test]
+    fn reads_schema_defaults_as_absent_or_profile() {
+        let xml = document()
+            .replace(r#"<dataProcessing centroided="1">"#, "<dataProcessing>")
+            .replace(r#"precursorIntensity="1200""#, r#"precursorIntensity="0""#);
+        let mzml = parse_mzxml(xml.as_bytes()).unwrap();
+        let spectra = &mzml.run.spectrum_list.as_ref().unwrap().spectra;
+        assert!(find_cv(&spectra[0].cv_params, "MS:1000128").is_some());
+        assert!(find_cv(&spectra[0].cv_params, "MS:1000127").is_none());
+        assert_eq!(precursor_intensity(&spectra[1]), None);
+    }
+
+    #[test]
+    fn rejects_unsupported_peaks() {
+        let xml = document().replace(r#"precision="64""#, r#"precision="16""#);
+        let err = parse_mzxml(xml.as_bytes()).unwrap_err();
+        assert!(
+            matches!(&err, ParseError::InvalidValue { context, .. } if context == "peaks precision"),
+            "{err}"
+        );
+        assert_eq!(duration_seconds("P1DT1H"), Some(90000.0));
+        assert_eq!(duration_seconds("PT0.25S"), Some(0.25));
+        assert_eq!(duration_seconds("P1M"), None);
+    }
+}
@@ -0,0 +1,682 @@
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    io::{Read, Seek, Write},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use quick_xml::{
+    Writer,
+    events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event},
+};
+
+use crate::{
+    b64::B000Reader,
+    mzml::{
+        bin_to_mzml::{Sink, XmlWriter, write_start_capture_offset},
+        structs::*,
+        utilities::peak_list::{
+            ACC_CHARGE_STATE, ACC_INTENSITY_ARRAY, ACC_MZ, ACC_MZ_ARRAY, ACC_PEAK_INTENSITY,
+            ACC_POSSIBLE_CHARGE_STATE, ACC_SELECTED_ION_MZ, array_binary, cv_f64, find_cv,
+            first_scan_params, ms_level, native_scan_number, precursor_list, scan_start_seconds,
+            values_f64,
+        },
+        validate_cv::descendants,
+    },
+    mzxml::parse_mzxml::{
+        ACTIVATION_TERMS, ANALYZER_TERMS, DETECTOR_TERMS, IONISATION_TERMS, SCAN_SUMMARY_TERMS,
+        SOFTWARE_TERMS,
+    },
+};
+
+const MZXML_NAMESPACE: &str = "http://sashimi.sourceforge.net/schema_revision/mzXML_3.2";
+
+/// Writes `mzml` as indexed mzXML 3.2 to `out` and returns `out`.
+pub fn write_mzxml<W: Write>(out: W, mzml: &MzML) -> Result<W, String> {
+    let spectra = mzml.run.spectrum_list.iter().flat_map(|l| &l.spectra);
+    write_mzxml_streaming(out, mzml, spectra)
+}
+
+/// Writes the B000 file read from `input` as indexed mzXML 3.2, decoding
+/// one spectrum at a time.
+pub fn write_b000_as_mzxml<W: Write, R: Read + Seek>(out: W, input: R) -> Result<W, String> {
+    let reader = B000Reader::new(input)?;
+    let header = reader.header().clone();
+    let mut spectra = reader.spectra();
+    let out = write_mzxml_streaming(out, &header, spectra.by_ref())?;
+    spectra.finish().map(|()| out)
+}
+
+/// Writes the metadata of `header` and `spectra` as indexed mzXML 3.2,
+/// for tools that only read mzXML.
+///
+/// Scans are written flat, in order, with uncompressed network-order
+/// peaks: 64-bit when either array is 64-bit, else 32-bit. A scan's `num`
+/// is the `scan=` number of its id when that keeps the nums increasing,
+/// otherwise the previous num plus one. Chromatograms are not written.
+pub fn write_mzxml_streaming<W, S, I>(out: W, header: &MzML, spectra: I) -> Result<W, String>
+where
+    W: Write,
+    S: Borrow<Spectrum>,
+    I: IntoIterator<Item = S>,
+{
+    let mut writer = XmlWriter::new(Writer::new_with_indent(Sink::new(out), b' ', 1));
+    writer
+        .write_event(Event::Decl(BytesDecl::new("1.0", Some("ISO-8859-1"), None)))
+        .map_err(|e| e.to_string())?;
+
+    let mut root = BytesStart::new("mzXML");
+    root.push_attribute(("xmlns", MZXML_NAMESPACE));
+    root.push_attribute(("xmlns:xsi", "http://www.w3.org/2001/XMLSchema-instance"));
+    let location = format!("{MZXML_NAMESPACE} {MZXML_NAMESPACE}/mzXML_idx_3.2.xsd");
+    root.push_attribute(("xsi:schemaLocation", location.as_str()));
+    start(&mut writer, root)?;
+
+    let mut run = BytesStart::new("msRun");
+    if let Some(count) = header.run.spectrum_list.as_ref().and_then(|l| l.count) {
+        run.push_attribute(("scanCount", count.to_string().as_str()));
+    }
+    start(&mut writer, run)?;
+
+    write_parent_files(&mut writer, header)?;
+    let instrument_ids = write_instruments(&mut writer, header)?;
+    write_data_processing(&mut writer, header)?;
+
+    let mut offsets: Vec<(u32, u64)> = Vec::new();
+    let mut nums: HashMap<String, u32> = HashMap::new();
+    for spectrum in spectra {
+        let spectrum = spectrum.borrow();
+        let last = offsets.last().map_or(0, |&(num, _)| num);
+        let num = native_scan_number(&spectrum.id)
+            .filter(|&n| n > last)
+            .unwrap_or(last + 1);
+        nums.insert(spectrum.id.clone(), num);
+        let offset = write_scan(&mut writer, spectrum, num, &nums, &instrument_ids)?;
+        offsets.push((num, offset));
+    }
+    end(&mut writer, "msRun")?;
+
+    let mut index = BytesStart::new("index");
+    index.push_attribute(("name", "scan"));
+    let index_offset = write_start_capture_offset(&mut writer, index)?;
+    for (num, offset) in &offsets {
+        let mut tag = BytesStart::new("offset");
+        tag.push_attribute(("id", num.to_string().as_str()));
+        text_element(&mut writer, tag, &offset.to_string())?;
+    }
+    end(&mut writer, "index")?;
+    text_element(
+        &mut writer,
+        BytesStart::new("indexOffset"),
+        &index_offset.to_string(),
+    )?;
+
+    // The digest covers every byte up to and including the `<sha1>` tag.
+    start(&mut writer, BytesStart::new("sha1"))?;
+    let digest = writer.get_ref().digest();
+    writer
+        .write_event(Event::Text(BytesText::new(&digest)))
+        .map_err(|e| e.to_string())?;
+    end(&mut writer, "sha1")?;
+    end(&mut writer, "mzXML")?;
+
+    let mut sink = writer.into_inner();
+    sink.flush().map_err(|e| e.to_string())?;
+    Ok(sink.into_inner())
+}
+
+#[inline]
+fn start<W: Write>(writer: &mut XmlWriter<W>, tag: BytesStart<'_>) -> Result<(), String> {
+    writer
+        .write_event(Event::Start(tag))
+        .map_err(|e| e.to_string())
+}
+
+#[inline]
+fn end<W: Write>(writer: &mut XmlWriter<W>, name: &str) -> Result<(), String> {
+    writer
+        .write_event(Event::End(BytesEnd::new(name)))
+        .map_err(|e| e.to_string())
+}
+
+#[inline]
+fn empty<W: Write>(writer: &mut XmlWriter<W>, tag: BytesStart<'_>) -> Result<(), String> {
+    writer
+        .write_event(Event::Empty(tag))
+        .map_err(|e| e.to_string())
+}
+
+fn text_element<W: Write>(
+    writer: &mut XmlWriter<W>,
+    tag: BytesStart<'_>,
+    text: &str,
+) -> Result<(), String> {
+    let name = String::from_utf8_lossy(tag.name().as_ref()).into_owned();
+    start(writer, tag)?;
+    writer
+        .write_event(Event::Text(BytesText::new(text)))
+        .map_err(|e| e.to_string())?;
+    end(writer, &name)
+}
+
+/// Short name of the first cvParam found in `terms` (e.g. `ESI`), else
+/// the name of the first cvParam, else the value of the first userParam.
+fn term_key(terms: &[(&str, &str, &str)], cv: &[CvParam], user: &[UserParam]) -> String {
+    cv.iter()
+        .find_map(|p| {
+            let accession = p.accession.as_deref()?;
+            terms
+                .iter()
+                .find(|(_, a, _)| *a == accession)
+                .map(|(key, ..)| key.to_string())
+        })
+        .or_else(|| cv.first().map(|p| p.name.clone()))
+        .or_else(|| user.first().and_then(|p| p.value.clone()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn write_parent_files<W: Write>(writer: &mut XmlWriter<W>, header: &MzML) -> Result<(), String> {
+    let sources = header
+        .file_description
+        .iter()
+        .flat_map(|d| &d.source_file_list.source_file);
+    for source in sources {
+        let file_name = match source.location.as_str() {
+            "" | "file://" => source.name.clone(),
+            location => format!("{}/{}", location.trim_end_matches('/'), source.name),
+        };
+        let raw = source.cv_param.iter().any(|p| {
+            matches!(
+                p.accession.as_deref(),
+                Some("MS:1000563" | "MS:1000562" | "MS:1000526" | "MS:1000815")
+            )
+        });
+        let mut tag = BytesStart::new("parentFile");
+        tag.push_attribute(("fileName", file_name.as_str()));
+        tag.push_attribute(("fileType", if raw { "RAWData" } else { "processedData" }));
+        if let Some(sha1) = find_cv(&source.cv_param, "MS:1000569").and_then(|p| p.value.as_deref())
+        {
+            tag.push_attribute(("fileSha1", sha1));
+        }
+        empty(writer, tag)?;
+    }
+    Ok(())
+}
+
+/// Writes one `<msInstrument>` per instrument configuration and returns
+/// their ids keyed by configuration id.
+fn write_instruments<W: Write>(
+    writer: &mut XmlWriter<W>,
+    header: &MzML,
+) -> Result<HashMap<String, u32>, String> {
+    let mut ids = HashMap::new();
+    let instruments = header.instrument_list.iter().flat_map(|l| &l.instrument);
+    for (i, instrument) in instruments.enumerate() {
+        let id = i as u32 + 1;
+        ids.insert(instrument.id.clone(), id);
+        let mut tag = BytesStart::new("msInstrument");
+        tag.push_attribute(("msInstrumentID", id.to_string().as_str()));
+        start(writer, tag)?;
+
+        let user = |name: &str| {
+            instrument
+                .user_param
+                .iter()
+                .find(|p| p.name == name)
+                .and_then(|p| p.value.clone())
+        };
+        // The model term is often in a referenceable param group.
+        let groups = header
+            .referenceable_param_group_list
+            .iter()
+            .flat_map(|l| &l.referenceable_param_groups)
+            .filter(|g| {
+                let refs = &instrument.referenceable_param_group_ref;
+                refs.iter().any(|r| r.r#ref == g.id)
+            });
+        let model = user("msModel").or_else(|| {
+            let mut params = instrument
+                .cv_param
+                .iter()
+                .chain(groups.flat_map(|g| &g.cv_params));
+            params
+                .find(|p| {
+                    let accession = p.accession.as_deref();
+                    // Generic instrument model and instrument serial number.
+                    !matches!(accession, Some("MS:1000031" | "MS:1000529"))
+                })
+                .map(|p| p.name.clone())
+        });
+        let components = instrument.component_list.as_ref();
+        let source = components.and_then(|c| c.source.first());
+        let analyzer = components.and_then(|c| c.analyzer.first());
+        let detector = components.and_then(|c| c.detector.first());
+        let entries = [
+            (
+                "msManufacturer",
+                user("msManufacturer").unwrap_or_else(|| "unknown".to_string()),
+            ),
+            ("msModel", model.unwrap_or_else(|| "unknown".to_string())),
+            (
+                "msIonisation",
+                source.map_or("unknown".to_string(), |s| {
+                    term_key(&IONISATION_TERMS, &s.cv_param, &s.user_param)
+                }),
+            ),
+            (
+                "msMassAnalyzer",
+                analyzer.map_or("unknown".to_string(), |a| {
+                    term_key(&ANALYZER_TERMS, &a.cv_param, &a.user_param)
+                }),
+            ),
+            (
+                "msDetector",
+                detector.map_or("unknown".to_string(), |d| {
+                    term_key(&DETECTOR_TERMS, &d.cv_param, &d.user_param)
+                }),
+            ),
+        ];
+        for (category, value) in entries {
+            let mut tag = BytesStart::new(category);
+            tag.push_attribute(("category", category));
+            tag.push_attribute(("value", value.as_str()));
+            empty(writer, tag)?;
+        }
+
+        let software = instrument
+            .software_ref
+            .as_ref()
+            .and_then(|r| find_software(header, &r.r#ref));
+        if let Some(software) = software {
+            empty(writer, software_tag("acquisition", software))?;
+        }
+        end(writer, "msInstrument")?;
+    }
+    Ok(ids)
+}
+
+fn find_software<'a>(header: &'a MzML, id: &str) -> Option<&'a Software> {
+    header
+        .software_list
+        .as_ref()?
+        .software
+        .iter()
+        .find(|s| s.id == id)
+}
+
+fn software_tag(kind: &str, software: &Software) -> BytesStart<'static> {
+    let name = software
+        .cv_param
+        .iter()
+        .find_map(|p| match p.accession.as_deref() {
+            Some("MS:1000799") => p.value.clone(),
+            Some(accession) => SOFTWARE_TERMS
+                .iter()
+                .find(|(_, a, _)| *a == accession)
+                .map(|(key, ..)| key.to_string())
+                .or_else(|| Some(p.name.clone())),
+            None => None,
+        })
+        .unwrap_or_else(|| software.id.clone());
+    let mut tag = BytesStart::new("software");
+    tag.push_attribute(("type", kind));
+    tag.push_attribute(("name", name.as_str()));
+    tag.push_attribute(("version", software.version.as_deref().unwrap_or("unknown")));
+    tag
+}
+
+/// One `<dataProcessing>` per processing method with software, then this
+/// conversion.
+fn write_data_processing<W: Write>(writer: &mut XmlWriter<W>, header: &MzML) -> Result<(), String> {
+    let methods = header
+        .data_processing_list
+        .iter()
+        .flat_map(|l| &l.data_processing)
+        .flat_map(|dp| &dp.processing_method);
+    for method in methods {
+        let Some(software) = method
+            .software_ref
+            .as_ref()
+            .filter(|id| *id != "octo")
+            .and_then(|id| find_software(header, id))
+        else {
+            continue;
+        };
+        let conversion = method
+            .cv_param
+            .iter()
+            .any(|p| matches!(p.accession.as_deref(), Some("MS:1000530" | "MS:1000544")));
+        let mut tag = BytesStart::new("dataProcessing");
+        if find_cv(&method.cv_param, "MS:1000035").is_some() {
+            tag.push_attribute(("centroided", "1"));
+        }
+        start(writer, tag)?;
+        let kind = if conversion {
+            "conversion"
+        } else {
+            "processing"
+        };
+        empty(writer, software_tag(kind, software))?;
+        end(writer, "dataProcessing")?;
+    }
+
+    start(writer, BytesStart::new("dataProcessing"))?;
+    let mut tag = BytesStart::new("software");
+    tag.push_attribute(("type", "conversion"));
+    tag.push_attribute(("name", "octo"));
+    tag.push_attribute(("version", env!("CARGO_PKG_VERSION")));
+    empty(writer, tag)?;
+    end(writer, "dataProcessing")
+}
+
+/// Writes `spectrum` as `<scan num>` and returns the offset of its start
+/// tag.
+fn write_scan<W: Write>(
+    writer: &mut XmlWriter<W>,
+    spectrum: &Spectrum,
+    num: u32,
+    nums: &HashMap<String, u32>,
+    instrument_ids: &HashMap<String, u32>,
+) -> Result<u64, String> {
+    let mz = array_binary(spectrum, ACC_MZ_ARRAY);
+    let intensity = array_binary(spectrum, ACC_INTENSITY_ARRAY);
+    let mz_values = mz.map(values_f64).unwrap_or_default();
+    let intensity_values = intensity.map(values_f64).unwrap_or_default();
+    let params = spectrum_params(spectrum);
+    let scan_params = first_scan_params(spectrum);
+
+    let mut tag = BytesStart::new("scan");
+    let mut attributes: Vec<(&str, String)> = vec![("num", num.to_string())];
+    if let Some(kind) = spectrum.user_params.iter().find(|p| p.name == "scanType") {
+        attributes.push(("scanType", kind.value.clone().unwrap_or_default()));
+    }
+    if find_cv(params, "MS:1000127").is_some() {
+        attributes.push(("centroided", "1".to_string()));
+    } else if find_cv(params, "MS:1000128").is_some() {
+        attributes.push(("centroided", "0".to_string()));
+    }
+    attributes.push(("msLevel", ms_level(spectrum).unwrap_or(1).to_string()));
+    attributes.push(("peaksCount", mz_values.len().to_string()));
+    if find_cv(params, "MS:1000130").is_some() {
+        attributes.push(("polarity", "+".to_string()));
+    } else if find_cv(params, "MS:1000129").is_some() {
+        attributes.push(("polarity", "-".to_string()));
+    }
+    if let Some(seconds) = scan_start_seconds(spectrum) {
+        attributes.push(("retentionTime", format!("PT{seconds}S")));
+    }
+    let precursors: Vec<&Precursor> = precursor_list(spectrum)
+        .map(|l| l.precursors.iter().collect())
+        .unwrap_or_default();
+    let collision_energy = precursors.iter().find_map(|p| {
+        let activation = p.activation.as_ref()?;
+        find_cv(&activation.cv_params, "MS:1000045")?.value.clone()
+    });
+    if let Some(energy) = collision_energy {
+        attributes.push(("collisionEnergy", energy));
+    }
+    for (attribute, accession, ..) in SCAN_SUMMARY_TERMS {
+        if let Some(value) = find_cv(params, accession).and_then(|p| p.value.clone()) {
+            attributes.push((attribute, value));
+        }
+    }
+    if let Some(filter) = find_cv(scan_params, "MS:1000512").and_then(|p| p.value.clone()) {
+        attributes.push(("filterLine", filter));
+    }
+    let scan = spectrum.scan_list.as_ref().and_then(|l| l.scans.first());
+    if let Some(id) = scan
+        .and_then(|s| s.instrument_configuration_ref.as_ref())
+        .and_then(|r| instrument_ids.get(r))
+    {
+        attributes.push(("msInstrumentID", id.to_string()));
+    }
+    let window = scan
+        .and_then(|s| s.scan_window_list.as_ref())
+        .and_then(|l| l.scan_windows.first())
+        .map_or(&[][..], |w| &w.cv_params);
+    for (attribute, accession) in [("startMz", "MS:1000501"), ("endMz", "MS:1000500")] {
+        if let Some(value) = find_cv(window, accession).and_then(|p| p.value.clone()) {
+            attributes.push((attribute, value));
+        }
+    }
+    for (key, value) in &attributes {
+        tag.push_attribute((*key, value.as_str()));
+    }
+    let offset = write_start_capture_offset(writer, tag)?;
+
+    for precursor in precursors {
+        write_precursor(writer, precursor, nums)?;
+    }
+
+    let wide = |b: Option<&BinaryData>| !matches!(b, Some(BinaryData::F32(_) | BinaryData::F16(_)));
+    let double = (mz.is_some() || intensity.is_some()) && (wide(mz) || wide(intensity));
+    let mut bytes = Vec::with_capacity(mz_values.len() * if double { 16 } else { 8 });
+    for (i, &mz) in mz_values.iter().enumerate() {
+        let intensity = intensity_values.get(i).copied().unwrap_or(0.0);
+        if double {
+            bytes.extend_from_slice(&mz.to_be_bytes());
+            bytes.extend_from_slice(&intensity.to_be_bytes());
+        } else {
+            bytes.extend_from_slice(&(mz as f32).to_be_bytes());
+            bytes.extend_from_slice(&(intensity as f32).to_be_bytes());
+        }
+    }
+    let mut peaks = BytesStart::new("peaks");
+    peaks.push_attribute(("precision", if double { "64" } else { "32" }));
+    peaks.push_attribute(("byteOrder", "network"));
+    peaks.push_attribute(("contentType", "m/z-int"));
+    peaks.push_attribute(("compressionType", "none"));
+    peaks.push_attribute(("compressedLen", "0"));
+    text_element(writer, peaks, &STANDARD.encode(&bytes))?;
+
+    end(writer, "scan")?;
+    Ok(offset)
+}
+
+/// Spectrum cvParams, from `<spectrumDescription>` in mzML 1.0.
+fn spectrum_params(spectrum: &Spectrum) -> &[CvParam] {
+    match &spectrum.spectrum_description {
+        Some(description) if spectrum.cv_params.is_empty() => &description.cv_params,
+        _ => &spectrum.cv_params,
+    }
+}
+
+fn write_precursor<W: Write>(
+    writer: &mut XmlWriter<W>,
+    precursor: &Precursor,
+    nums: &HashMap<String, u32>,
+) -> Result<(), String> {
+    let ion: &[CvParam] = precursor
+        .selected_ion_list
+        .as_ref()
+        .and_then(|l| l.selected_ions.first())
+        .map_or(&[], |ion| &ion.cv_params);
+    let Some(mz) = find_cv(ion, ACC_SELECTED_ION_MZ)
+        .or_else(|| find_cv(ion, ACC_MZ))
+        .and_then(|p| p.value.clone())
+    else {
+        return Ok(());
+    };
+
+    let mut tag = BytesStart::new("precursorMz");
+    if let Some(num) = precursor
+        .spectrum_ref
+        .as_ref()
+        .and_then(|r| nums.get(r).copied().or_else(|| native_scan_number(r)))
+    {
+        tag.push_attribute(("precursorScanNum", num.to_string().as_str()));
+    }
+    if let Some(intensity) = find_cv(ion, ACC_PEAK_INTENSITY).and_then(|p| p.value.as_deref()) {
+        tag.push_attribute(("precursorIntensity", intensity));
+    }
+
+    let charges = |accession: &str| -> Vec<&str> {
+        ion.iter()
+            .filter(|p| p.accession.as_deref() == Some(accession))
+            .filter_map(|p| p.value.as_deref())
+            .collect()
+    };
+    let charge = charges(ACC_CHARGE_STATE);
+    let possible = charges(ACC_POSSIBLE_CHARGE_STATE);
+    if let [z] = charge[..] {
+        tag.push_attribute(("precursorCharge", z));
+    } else if !possible.is_empty() {
+        tag.push_attribute(("possibleCharges", possible.join(",").as_str()));
+    }
+
+    if let Some(window) = &precursor.isolation_window {
+        let lower = cv_f64(&window.cv_params, "MS:1000828");
+        let upper = cv_f64(&window.cv_params, "MS:1000829");
+        if let (Some(lower), Some(upper)) = (lower, upper) {
+            tag.push_attribute(("windowWideness", (lower + upper).to_string().as_str()));
+        }
+    }
+    let method = precursor.activation.as_ref().and_then(|a| {
+        let term = a.cv_params.iter().find_map(|p| {
+            let accession = p.accession.as_deref()?;
+            ACTIVATION_TERMS
+                .iter()
+                .find(|(_, a, _)| *a == accession)
+                .map(|(key, ..)| *key)
+        });
+        let dissociation = descendants("MS:1000044");
+        term.or_else(|| {
+            a.cv_params
+                .iter()
+                .find(|p| dissociation.iter().any(|d| p.accession.as_ref() == Some(d)))
+                .map(|p| p.name.as_str())
+        })
+        .or_else(|| {
+            a.user_params
+                .iter()
+                .find(|p| p.name == "activationMethod")?
+                .value
+                .as_deref()
+        })
+    });
+    if let Some(method) = method {
+        tag.push_attribute(("activationMethod", method));
+    }
+    text_element(writer, tag, &mz)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        b64::{encode, encoder::encode::WritingMode},
+        mzml::{
+            parse_mzml::parse_mzml,
+            utilities::peak_list::{array_f64, precursor_charges, precursor_mz},
+            validate_cv::validate_cv_terms,
+        },
+        mzxml::parse_mzxml::parse_mzxml,
+    };
+    use std::io::Cursor;
+
+    #[test]
+    fn written_mzxml_reads_back_with_a_valid_index() {
+        let bytes = std::fs::read("data/mzml/tiny.pwiz.mzML0.99.10.mzML").unwrap();
+        let mzml = parse_mzml(&bytes).unwrap();
+        let xml = write_mzxml(Vec::new(), &mzml).unwrap();
+        let text = String::from_utf8(xml.clone()).unwrap();
+
+        let index_offset: usize = text
+            .split("<indexOffset>")
+            .nth(1)
+            .and_then(|s| s.split('<').next())
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(text[index_offset..].starts_with("<index name=\"scan\">"));
+        for offset in text[index_offset..].split("\">").skip(2).filter_map(|s| {
+            s.split("</offset>")
+                .next()
+                .and_then(|n| n.parse::<usize>().ok())
+        }) {
+            assert!(text[offset..].starts_with("<scan num="), "offset {offset}");
+        }
+        let sha1_end = text.find("<sha1>").unwrap() + "<sha1>".len();
+        let mut sha1 = sha1_smol::Sha1::new();
+        sha1.update(&xml[..sha1_end]);
+        assert!(text[sha1_end..].starts_with(&sha1.digest().to_string()));
+
+        let reread = parse_mzxml(&xml).unwrap();
+        let before = &mzml.run.spectrum_list.as_ref().unwrap().spectra;
+        let after = &reread.run.spectrum_list.as_ref().unwrap().spectra;
+        assert_eq!(before.len(), after.len());
+        for (a, b) in before.iter().zip(after) {
+            assert_eq!(ms_level(a), ms_level(b));
+            assert_eq!(scan_start_seconds(a), scan_start_seconds(b));
+            assert_eq!(precursor_mz(a), precursor_mz(b));
+            assert_eq!(precursor_charges(a), precursor_charges(b));
+            assert_eq!(array_f64(a, ACC_MZ_ARRAY), array_f64(b, ACC_MZ_ARRAY));
+            assert_eq!(
+                array_f64(a, ACC_INTENSITY_ARRAY),
+                array_f64(b, ACC_INTENSITY_ARRAY)
+            );
+        }
+    }
+
+    #[test]
+    fn b000_input_writes_the_same_scans() {
+        let bytes = std::fs::read("data/mzml/test.mzML").unwrap();
+        let mzml = parse_mzml(&bytes).unwrap();
+        let mut b000 = Vec::new();
+        encode(&mzml, 12, false, WritingMode::Memory, &mut b000).unwrap();
+
+        let from_b000 =
+            parse_mzxml(&write_b000_as_mzxml(Vec::new(), Cursor::new(&b000)).unwrap()).unwrap();
+        let from_mzml = parse_mzxml(&write_mzxml(Vec::new(), &mzml).unwrap()).unwrap();
+        let spectra = |m: &MzML| m.run.spectrum_list.as_ref().unwrap().spectra.clone();
+        let (a, b) = (spectra(&from_b000), spectra(&from_mzml));
+        assert_eq!(a.len(), 2);
+        assert_eq!(a[1].id, "scan=3476");
+        for (a, b) in a.iter().zip(&b) {
+            assert_eq!(a.id, b.id);
+            assert_eq!(scan_start_seconds(a), scan_start_seconds(b));
+            assert_eq!(precursor_mz(a), precursor_mz(b));
+            assert_eq!(array_f64(a, ACC_MZ_ARRAY), array_f64(b, ACC_MZ_ARRAY));
+        }
+    }
+
+    #[test]
+    fn mzml_round_trip_through_mzxml_keeps_required_terms() {
+        let bytes = std::fs::read("data/mzml/test.mzML").unwrap();
+        let mut mzml = parse_mzml(&bytes).unwrap();
+        assert!(validate_cv_terms(&mzml).is_valid());
+        for spectrum in &mut mzml.run.spectrum_list.as_mut().unwrap().spectra {
+            for precursor in spectrum
+                .precursor_list
+                .iter_mut()
+                .flat_map(|l| &mut l.precursors)
+            {
+                for ion in precursor
+                    .selected_ion_list
+                    .iter_mut()
+                    .flat_map(|l| &mut l.selected_ions)
+                {
+                    ion.cv_params
+                        .retain(|p| p.accession.as_deref() != Some(ACC_PEAK_INTENSITY));
+                }
+            }
+        }
+
+        let xml = write_mzxml(Vec::new(), &mzml).unwrap();
+        assert!(!String::from_utf8_lossy(&xml).contains("precursorIntensity"));
+        let reread = parse_mzxml(&xml).unwrap();
+        let validation = validate_cv_terms(&reread);
+        assert!(validation.is_valid(), "{:?}", validation.issues);
+
+        let components = reread.instrument_list.as_ref().unwrap().instrument[0]
+            .component_list
+            .as_ref()
+            .unwrap();
+        assert!(find_cv(&components.detector[0].cv_param, "MS:1000114").is_some());
+        let precursor = &precursor_list(&reread.run.spectrum_list.as_ref().unwrap().spectra[1])
+            .unwrap()
+            .precursors[0];
+        let activation = &precursor.activation.as_ref().unwrap().cv_params;
+        assert!(find_cv(activation, "MS:1001880").is_some());
+    }
+}
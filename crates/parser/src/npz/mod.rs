@@ -0,0 +1,3 @@
+pub(crate) mod npy;
+pub mod write_npz;
+pub use write_npz::{write_b000_as_npz, write_npz};
@@ -0,0 +1,557 @@
+//! `.npy` arrays and the zip container of `.npz` files, as written by
+//! `numpy.savez` and `numpy.savez_compressed`.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    marker::PhantomData,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use flate2::{Compression, Crc, write::DeflateEncoder};
+
+/// Element type of a `.npy` array.
+pub(crate) trait NpyElement: Copy {
+    /// NumPy `descr`, e.g. `<f8`.
+    const DESCR: &'static str;
+    fn write_le<W: Write>(self, out: &mut W) -> io::Result<()>;
+}
+
+macro_rules! npy_element {
+    ($($ty:ty => $descr:literal),*) => {$(
+        impl NpyElement for $ty {
+            const DESCR: &'static str = $descr;
+            #[inline]
+            fn write_le<W: Write>(self, out: &mut W) -> io::Result<()> {
+                out.write_all(&self.to_le_bytes())
+            }
+        }
+    )*};
+}
+
+npy_element!(f64 => "<f8", f32 => "<f4", i64 => "<i8", i32 => "<i4");
+
+/// Version 1.0 header of a 1-D array of `len` elements of `descr`, padded
+/// so the data starts at a multiple of 64 bytes.
+fn header(descr: &str, len: u64) -> Vec<u8> {
+    let dict = format!("{{'descr': '{descr}', 'fortran_order': False, 'shape': ({len},), }}");
+    let unpadded = 10 + dict.len() + 1;
+    let padding = unpadded.next_multiple_of(64) - unpadded;
+    let header_len = (dict.len() + padding + 1) as u16;
+
+    let mut out = Vec::with_capacity(unpadded + padding);
+    out.extend_from_slice(b"\x93NUMPY\x01\x00");
+    out.extend_from_slice(&header_len.to_le_bytes());
+    out.extend_from_slice(dict.as_bytes());
+    out.resize(out.len() + padding, b' ');
+    out.push(b'\n');
+    out
+}
+
+/// Temporary file in the system temp directory, removed when dropped.
+struct Spool {
+    path: PathBuf,
+    file: BufWriter<File>,
+}
+
+impl Spool {
+    fn new() -> Result<Self, String> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos());
+        let name = format!(
+            "octo-npz-{}-{nanos}-{}.tmp",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = std::env::temp_dir().join(name);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|e| format!("create {} failed: {e}", path.display()))?;
+        Ok(Self {
+            path,
+            file: BufWriter::new(file),
+        })
+    }
+
+    /// Everything written so far, from the start.
+    fn reader(&mut self) -> io::Result<BufReader<&mut File>> {
+        self.file.flush()?;
+        let file = self.file.get_mut();
+        file.seek(SeekFrom::Start(0))?;
+        Ok(BufReader::new(file))
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// 1-D `.npy` array whose values are spooled to a temporary file as they
+/// are pushed, so it is never held in memory.
+pub(crate) struct NpyColumn<T> {
+    spool: Spool,
+    len: u64,
+    element: PhantomData<T>,
+}
+
+impl<T: NpyElement> NpyColumn<T> {
+    pub(crate) fn new() -> Result<Self, String> {
+        Ok(Self {
+            spool: Spool::new()?,
+            len: 0,
+            element: PhantomData,
+        })
+    }
+
+    #[inline]
+    pub(crate) fn len(&self) -> u64 {
+        self.len
+    }
+
+    #[inline]
+    pub(crate) fn push(&mut self, value: T) -> Result<(), String> {
+        value
+            .write_le(&mut self.spool.file)
+            .map_err(|e| format!("npz spool write failed: {e}"))?;
+        self.len += 1;
+        Ok(())
+    }
+
+    pub(crate) fn extend(&mut self, values: impl IntoIterator<Item = T>) -> Result<(), String> {
+        values.into_iter().try_for_each(|value| self.push(value))
+    }
+
+    /// Writes the `.npy` file: its header, then the spooled values.
+    pub(crate) fn write_to(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(&header(T::DESCR, self.len))?;
+        io::copy(&mut self.spool.reader()?, out)?;
+        Ok(())
+    }
+}
+
+/// 1-D `.npy` array of strings, written as a fixed-width unicode (`<U`)
+/// array as wide as the longest string. The strings are spooled like
+/// `NpyColumn` values until then.
+pub(crate) struct NpyStrings {
+    spool: Spool,
+    len: u64,
+    width: usize,
+}
+
+impl NpyStrings {
+    pub(crate) fn new() -> Result<Self, String> {
+        Ok(Self {
+            spool: Spool::new()?,
+            len: 0,
+            width: 1,
+        })
+    }
+
+    pub(crate) fn push(&mut self, value: &str) -> Result<(), String> {
+        let spool = &mut self.spool.file;
+        spool
+            .write_all(&(value.len() as u64).to_le_bytes())
+            .and_then(|()| spool.write_all(value.as_bytes()))
+            .map_err(|e| format!("npz spool write failed: {e}"))?;
+        self.width = self.width.max(value.chars().count());
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Writes the `.npy` file: its header, then each string padded with NUL
+    /// to the common width.
+    pub(crate) fn write_to(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(&header(&format!("<U{}", self.width), self.len))?;
+        let mut spool = self.spool.reader()?;
+        let mut value = Vec::new();
+        let mut chars = Vec::with_capacity(self.width * 4);
+        for _ in 0..self.len {
+            let mut len = [0u8; 8];
+            spool.read_exact(&mut len)?;
+            value.resize(u64::from_le_bytes(len) as usize, 0);
+            spool.read_exact(&mut value)?;
+            let value = std::str::from_utf8(&value)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+            chars.clear();
+            let padded = value.chars().map(u32::from).chain(std::iter::repeat(0));
+            for c in padded.take(self.width) {
+                chars.extend_from_slice(&c.to_le_bytes());
+            }
+            out.write_all(&chars)?;
+        }
+        Ok(())
+    }
+}
+
+struct CentralEntry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: u64,
+    size: u64,
+    offset: u64,
+}
+
+/// Zip archive written one entry at a time, straight to the output. The
+/// sizes and CRC of each entry follow its data in a data descriptor, and
+/// zip64 records are written for entries and archives over 4 GiB.
+pub(crate) struct ZipWriter<W> {
+    out: W,
+    offset: u64,
+    compress: bool,
+    entries: Vec<CentralEntry>,
+    /// Largest size, offset or count written without zip64.
+    zip32_limit: u64,
+}
+
+/// 1980-01-01 00:00, the earliest DOS date.
+const DOS_DATE: u16 = 0x21;
+const DOS_TIME: u16 = 0;
+/// General purpose flag: sizes and CRC are in the data descriptor.
+const FLAG_DATA_DESCRIPTOR: u16 = 0x0008;
+const VERSION_ZIP32: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+
+/// Passes writes to `out`, counting the bytes.
+struct Counter<W> {
+    out: W,
+    count: u64,
+}
+
+impl<W: Write> Write for Counter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.out.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Passes writes to `out`, keeping their CRC-32 and length.
+struct Checksum<W> {
+    out: W,
+    crc: Crc,
+    size: u64,
+}
+
+impl<W> Checksum<W> {
+    fn new(out: W) -> Self {
+        Self {
+            out,
+            crc: Crc::new(),
+            size: 0,
+        }
+    }
+}
+
+impl<W: Write> Write for Checksum<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.out.write(buf)?;
+        self.crc.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl<W: Write> ZipWriter<W> {
+    /// Entries are deflated if `compress`, stored otherwise.
+    pub(crate) fn new(out: W, compress: bool) -> Self {
+        Self {
+            out,
+            offset: 0,
+            compress,
+            entries: Vec::new(),
+            zip32_limit: u32::MAX as u64 - 1,
+        }
+    }
+
+    /// Adds the entry `name` with the data that `write` writes, which is
+    /// checksummed and deflated as it goes.
+    pub(crate) fn add(
+        &mut self,
+        name: &str,
+        write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+    ) -> Result<(), String> {
+        let method: u16 = if self.compress { 8 } else { 0 };
+        let mut local = Vec::with_capacity(30 + name.len());
+        local.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        local.extend_from_slice(&VERSION_ZIP32.to_le_bytes());
+        local.extend_from_slice(&FLAG_DATA_DESCRIPTOR.to_le_bytes());
+        local.extend_from_slice(&method.to_le_bytes());
+        local.extend_from_slice(&DOS_TIME.to_le_bytes());
+        local.extend_from_slice(&DOS_DATE.to_le_bytes());
+        // CRC, compressed size and size, in the data descriptor.
+        local.extend_from_slice(&[0; 12]);
+        local.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        local.extend_from_slice(&0u16.to_le_bytes());
+        local.extend_from_slice(name.as_bytes());
+        let offset = self.offset;
+        self.write(&local)?;
+
+        let failed = |e: io::Error| format!("{name}: {e}");
+        let mut stored = Counter {
+            out: &mut self.out,
+            count: 0,
+        };
+        let (crc, size) = if self.compress {
+            let mut entry = Checksum::new(DeflateEncoder::new(&mut stored, Compression::default()));
+            write(&mut entry).map_err(failed)?;
+            entry.out.finish().map_err(failed)?;
+            (entry.crc.sum(), entry.size)
+        } else {
+            let mut entry = Checksum::new(&mut stored);
+            write(&mut entry).map_err(failed)?;
+            (entry.crc.sum(), entry.size)
+        };
+        let compressed_size = stored.count;
+        self.offset += compressed_size;
+
+        let entry = CentralEntry {
+            name: name.to_string(),
+            method,
+            crc,
+            compressed_size,
+            size,
+            offset,
+        };
+        let mut descriptor = Vec::with_capacity(24);
+        descriptor.extend_from_slice(&0x0807_4b50u32.to_le_bytes());
+        descriptor.extend_from_slice(&entry.crc.to_le_bytes());
+        if self.is_zip64(entry.size) || self.is_zip64(entry.compressed_size) {
+            descriptor.extend_from_slice(&entry.compressed_size.to_le_bytes());
+            descriptor.extend_from_slice(&entry.size.to_le_bytes());
+        } else {
+            descriptor.extend_from_slice(&(entry.compressed_size as u32).to_le_bytes());
+            descriptor.extend_from_slice(&(entry.size as u32).to_le_bytes());
+        }
+        self.write(&descriptor)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Writes the central directory and returns the output.
+    pub(crate) fn finish(mut self) -> Result<W, String> {
+        let start = self.offset;
+        let mut central = Vec::new();
+        for entry in &self.entries {
+            // Values too large for their 32-bit field go in the zip64 extra
+            // field, in this order.
+            let mut zip64 = Vec::new();
+            let mut field = |value: u64| {
+                if self.is_zip64(value) {
+                    zip64.extend_from_slice(&value.to_le_bytes());
+                    u32::MAX
+                } else {
+                    value as u32
+                }
+            };
+            let size = field(entry.size);
+            let compressed_size = field(entry.compressed_size);
+            let offset = field(entry.offset);
+            let version = if zip64.is_empty() {
+                VERSION_ZIP32
+            } else {
+                VERSION_ZIP64
+            };
+
+            central.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+            central.extend_from_slice(&version.to_le_bytes());
+            central.extend_from_slice(&version.to_le_bytes());
+            central.extend_from_slice(&FLAG_DATA_DESCRIPTOR.to_le_bytes());
+            central.extend_from_slice(&entry.method.to_le_bytes());
+            central.extend_from_slice(&DOS_TIME.to_le_bytes());
+            central.extend_from_slice(&DOS_DATE.to_le_bytes());
+            central.extend_from_slice(&entry.crc.to_le_bytes());
+            central.extend_from_slice(&compressed_size.to_le_bytes());
+            central.extend_from_slice(&size.to_le_bytes());
+            central.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            let extra_len = if zip64.is_empty() { 0 } else { 4 + zip64.len() };
+            central.extend_from_slice(&(extra_len as u16).to_le_bytes());
+            // Comment, disk number, internal and external attributes.
+            central.extend_from_slice(&[0; 10]);
+            central.extend_from_slice(&offset.to_le_bytes());
+            central.extend_from_slice(entry.name.as_bytes());
+            if !zip64.is_empty() {
+                central.extend_from_slice(&1u16.to_le_bytes());
+                central.extend_from_slice(&(zip64.len() as u16).to_le_bytes());
+                central.extend_from_slice(&zip64);
+            }
+        }
+        let count = self.entries.len() as u64;
+        let central_len = central.len() as u64;
+        self.write(&central)?;
+
+        let zip64 = self.is_zip64(start) || self.is_zip64(central_len) || count >= u16::MAX as u64;
+        let mut end = Vec::with_capacity(98);
+        if zip64 {
+            let record = self.offset;
+            end.extend_from_slice(&0x0606_4b50u32.to_le_bytes());
+            end.extend_from_slice(&44u64.to_le_bytes());
+            end.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+            end.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+            end.extend_from_slice(&[0; 8]);
+            end.extend_from_slice(&count.to_le_bytes());
+            end.extend_from_slice(&count.to_le_bytes());
+            end.extend_from_slice(&central_len.to_le_bytes());
+            end.extend_from_slice(&start.to_le_bytes());
+
+            end.extend_from_slice(&0x0706_4b50u32.to_le_bytes());
+            end.extend_from_slice(&0u32.to_le_bytes());
+            end.extend_from_slice(&record.to_le_bytes());
+            end.extend_from_slice(&1u32.to_le_bytes());
+        }
+        let count = if zip64 { u16::MAX } else { count as u16 };
+        let (central_len, start) = if zip64 {
+            (u32::MAX, u32::MAX)
+        } else {
+            (central_len as u32, start as u32)
+        };
+        end.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        end.extend_from_slice(&[0; 4]);
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&central_len.to_le_bytes());
+        end.extend_from_slice(&start.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+        self.write(&end)?;
+        self.out.flush().map_err(|e| e.to_string())?;
+        Ok(self.out)
+    }
+
+    /// True when `value` does not fit a 32-bit zip field.
+    #[inline]
+    fn is_zip64(&self, value: u64) -> bool {
+        value > self.zip32_limit
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.out.write_all(bytes).map_err(|e| e.to_string())?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn u64_at(bytes: &[u8], at: usize) -> u64 {
+        u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+    }
+
+    #[test]
+    fn npy_headers_align_the_data() {
+        let mut column = NpyColumn::new().unwrap();
+        column.extend([1.5f64, -2.0]).unwrap();
+        let mut bytes = Vec::new();
+        column.write_to(&mut bytes).unwrap();
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        assert_eq!(bytes.len(), 10 + header_len + 16);
+        let header = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+        assert!(header.starts_with("{'descr': '<f8', 'fortran_order': False, 'shape': (2,), }"));
+        assert!(header.ends_with(" \n"));
+        assert_eq!(bytes[10 + header_len..][..8], 1.5f64.to_le_bytes());
+
+        let mut strings = NpyStrings::new().unwrap();
+        strings.push("ab").unwrap();
+        strings.push("é").unwrap();
+        let mut bytes = Vec::new();
+        strings.write_to(&mut bytes).unwrap();
+        let data = &bytes[bytes.len() - 16..];
+        assert_eq!(data, [97, 0, 0, 0, 98, 0, 0, 0, 0xe9, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn zip_entries_are_listed_in_the_central_directory() {
+        for compress in [false, true] {
+            let mut zip = ZipWriter::new(Vec::new(), compress);
+            zip.add("a.npy", |entry| entry.write_all(&[7; 100]))
+                .unwrap();
+            zip.add("b.npy", |_| Ok(())).unwrap();
+            let bytes = zip.finish().unwrap();
+
+            let end = &bytes[bytes.len() - 22..];
+            assert_eq!(u32_at(end, 0), 0x0605_4b50);
+            assert_eq!(u16::from_le_bytes([end[10], end[11]]), 2);
+            let start = u32_at(end, 16) as usize;
+            let central = &bytes[start..];
+            assert_eq!(u32_at(central, 0), 0x0201_4b50);
+            let mut crc = Crc::new();
+            crc.update(&[7; 100]);
+            assert_eq!(u32_at(central, 16), crc.sum());
+            let compressed_size = u32_at(central, 20);
+            assert_eq!(u32_at(central, 24), 100);
+            assert_eq!(compressed_size < 100, compress);
+
+            // The data descriptor follows the data.
+            let descriptor = &bytes[30 + 5 + compressed_size as usize..];
+            assert_eq!(u32_at(descriptor, 0), 0x0807_4b50);
+            assert_eq!(u32_at(descriptor, 4), crc.sum());
+            assert_eq!(u32_at(descriptor, 8), compressed_size);
+        }
+    }
+
+    #[test]
+    fn large_values_are_written_as_zip64() {
+        let mut zip = ZipWriter::new(Vec::new(), false);
+        zip.zip32_limit = 50;
+        zip.add("a.npy", |entry| entry.write_all(&[7; 100]))
+            .unwrap();
+        zip.add("b.npy", |entry| entry.write_all(&[8; 10])).unwrap();
+        let bytes = zip.finish().unwrap();
+
+        let descriptor = &bytes[30 + 5 + 100..];
+        assert_eq!(u64_at(descriptor, 8), 100);
+        assert_eq!(u64_at(descriptor, 16), 100);
+
+        let end = &bytes[bytes.len() - 22..];
+        assert_eq!(u32_at(end, 0), 0x0605_4b50);
+        assert_eq!(u32_at(end, 16), u32::MAX);
+        let locator = &bytes[bytes.len() - 42..];
+        assert_eq!(u32_at(locator, 0), 0x0706_4b50);
+        let record = &bytes[u64_at(locator, 8) as usize..];
+        assert_eq!(u32_at(record, 0), 0x0605_4b50 + 0x10000);
+        assert_eq!(u64_at(record, 32), 2);
+        let start = u64_at(record, 48) as usize;
+
+        // a.npy: sizes over the limit, offset 0 within it.
+        let central = &bytes[start..];
+        assert_eq!(u32_at(central, 20), u32::MAX);
+        assert_eq!(u32_at(central, 24), u32::MAX);
+        assert_eq!(u32_at(central, 42), 0);
+        let extra = &central[46 + 5..];
+        assert_eq!(u16::from_le_bytes([extra[0], extra[1]]), 1);
+        assert_eq!(u16::from_le_bytes([extra[2], extra[3]]), 16);
+        assert_eq!(u64_at(extra, 4), 100);
+        assert_eq!(u64_at(extra, 12), 100);
+
+        // b.npy: only its offset is over the limit.
+        let central = &extra[20..];
+        assert_eq!(u32_at(central, 0), 0x0201_4b50);
+        assert_eq!(u32_at(central, 24), 10);
+        assert_eq!(u32_at(central, 42), u32::MAX);
+        assert_eq!(u64_at(&central[46 + 5..], 4), 30 + 5 + 100 + 24);
+    }
+}
@@ -0,0 +1,231 @@
+use std::io::{Read, Seek, Write};
+
+use crate::{
+    b64::B000Reader,
+    mzml::{
+        mzml_reader::MzMLItem,
+        structs::*,
+        utilities::peak_list::{
+            ACC_INTENSITY_ARRAY, ACC_MZ_ARRAY, array_f64, find_cv, ms_level, precursor_charges,
+            precursor_mz, scan_start_seconds, values_f64,
+        },
+    },
+    npz::npy::{NpyColumn, NpyStrings, ZipWriter},
+};
+
+const ACC_TIME_ARRAY: &str = "MS:1000595";
+
+/// Columns of an `.npz` export, filled one spectrum or chromatogram at a
+/// time.
+///
+/// Peaks are concatenated: the peaks of spectrum `i` are
+/// `mz[offsets[i]:offsets[i + 1]]`, and likewise for chromatograms.
+/// Missing retention times and precursor m/z are NaN, missing ms levels and
+/// charges 0.
+///
+/// Each column is spooled to a temporary file until `write` copies it into
+/// the archive, so only one spectrum is held in memory at a time.
+struct NpzColumns {
+    spectrum_id: NpyStrings,
+    mz: NpyColumn<f64>,
+    intensity: NpyColumn<f64>,
+    offsets: NpyColumn<i64>,
+    rt: NpyColumn<f64>,
+    ms_level: NpyColumn<i32>,
+    precursor_mz: NpyColumn<f64>,
+    charge: NpyColumn<i32>,
+    chromatogram_id: NpyStrings,
+    chromatogram_time: NpyColumn<f64>,
+    chromatogram_intensity: NpyColumn<f64>,
+    chromatogram_offsets: NpyColumn<i64>,
+}
+
+impl NpzColumns {
+    fn new() -> Result<Self, String> {
+        let mut columns = Self {
+            spectrum_id: NpyStrings::new()?,
+            mz: NpyColumn::new()?,
+            intensity: NpyColumn::new()?,
+            offsets: NpyColumn::new()?,
+            rt: NpyColumn::new()?,
+            ms_level: NpyColumn::new()?,
+            precursor_mz: NpyColumn::new()?,
+            charge: NpyColumn::new()?,
+            chromatogram_id: NpyStrings::new()?,
+            chromatogram_time: NpyColumn::new()?,
+            chromatogram_intensity: NpyColumn::new()?,
+            chromatogram_offsets: NpyColumn::new()?,
+        };
+        columns.offsets.push(0)?;
+        columns.chromatogram_offsets.push(0)?;
+        Ok(columns)
+    }
+
+    fn add_spectrum(&mut self, spectrum: &Spectrum) -> Result<(), String> {
+        let mz = array_f64(spectrum, ACC_MZ_ARRAY).unwrap_or_default();
+        let mut intensity = array_f64(spectrum, ACC_INTENSITY_ARRAY).unwrap_or_default();
+        intensity.resize(mz.len(), 0.0);
+        self.mz.extend(mz)?;
+        self.intensity.extend(intensity)?;
+        self.offsets.push(self.mz.len() as i64)?;
+
+        self.spectrum_id.push(&spectrum.id)?;
+        self.rt
+            .push(scan_start_seconds(spectrum).unwrap_or(f64::NAN))?;
+        self.ms_level
+            .push(ms_level(spectrum).map_or(0, |l| l as i32))?;
+        self.precursor_mz
+            .push(precursor_mz(spectrum).unwrap_or(f64::NAN))?;
+        self.charge
+            .push(precursor_charges(spectrum).first().copied().unwrap_or(0))
+    }
+
+    fn add_chromatogram(&mut self, chromatogram: &Chromatogram) -> Result<(), String> {
+        let arrays = chromatogram.binary_data_array_list.as_ref();
+        let array = |accession| {
+            let arrays = arrays?.binary_data_arrays.iter();
+            arrays
+                .filter_map(|bda| Some((find_cv(&bda.cv_params, accession)?, bda)))
+                .find_map(|(param, bda)| Some((param, values_f64(bda.binary.as_ref()?))))
+        };
+        let time = array(ACC_TIME_ARRAY).map_or_else(Vec::new, |(param, mut time)| {
+            let unit = param
+                .unit_accession
+                .as_deref()
+                .or(param.unit_name.as_deref());
+            if matches!(unit, Some("UO:0000031" | "MS:1000038" | "minute")) {
+                time.iter_mut().for_each(|t| *t *= 60.0);
+            }
+            time
+        });
+        let mut intensity = array(ACC_INTENSITY_ARRAY).map_or_else(Vec::new, |(_, v)| v);
+        intensity.resize(time.len(), 0.0);
+        self.chromatogram_time.extend(time)?;
+        self.chromatogram_intensity.extend(intensity)?;
+        self.chromatogram_offsets
+            .push(self.chromatogram_time.len() as i64)?;
+        self.chromatogram_id.push(&chromatogram.id)
+    }
+
+    fn write<W: Write>(mut self, out: W, compress: bool) -> Result<W, String> {
+        let mut zip = ZipWriter::new(out, compress);
+        zip.add("spectrum_id.npy", |e| self.spectrum_id.write_to(e))?;
+        zip.add("mz.npy", |e| self.mz.write_to(e))?;
+        zip.add("intensity.npy", |e| self.intensity.write_to(e))?;
+        zip.add("offsets.npy", |e| self.offsets.write_to(e))?;
+        zip.add("rt.npy", |e| self.rt.write_to(e))?;
+        zip.add("ms_level.npy", |e| self.ms_level.write_to(e))?;
+        zip.add("precursor_mz.npy", |e| self.precursor_mz.write_to(e))?;
+        zip.add("charge.npy", |e| self.charge.write_to(e))?;
+        zip.add("chromatogram_id.npy", |e| self.chromatogram_id.write_to(e))?;
+        zip.add("chromatogram_time.npy", |e| {
+            self.chromatogram_time.write_to(e)
+        })?;
+        zip.add("chromatogram_intensity.npy", |e| {
+            self.chromatogram_intensity.write_to(e)
+        })?;
+        zip.add("chromatogram_offsets.npy", |e| {
+            self.chromatogram_offsets.write_to(e)
+        })?;
+        zip.finish()
+    }
+}
+
+/// Writes the spectra and chromatograms of `mzml` as an `.npz` archive to
+/// `out` and returns `out`; the arrays are deflated if `compress`, as with
+/// `numpy.savez_compressed`.
+///
+/// The archive has the concatenated `mz` and `intensity` arrays (f8) with
+/// `offsets` (i8, one more than the spectra) delimiting each spectrum, and
+/// one entry per spectrum in `spectrum_id` (unicode), `rt` (seconds, f8),
+/// `ms_level` (i4), `precursor_mz` (f8) and `charge` (i4). Missing values
+/// are NaN for floats and 0 for integers. Chromatograms are stored the same
+/// way in `chromatogram_id`, `chromatogram_time` (seconds),
+/// `chromatogram_intensity` and `chromatogram_offsets`.
+pub fn write_npz<W: Write>(out: W, mzml: &MzML, compress: bool) -> Result<W, String> {
+    let mut columns = NpzColumns::new()?;
+    for spectrum in mzml.run.spectrum_list.iter().flat_map(|l| &l.spectra) {
+        columns.add_spectrum(spectrum)?;
+    }
+    let chromatograms = mzml.run.chromatogram_list.iter();
+    for chromatogram in chromatograms.flat_map(|l| &l.chromatograms) {
+        columns.add_chromatogram(chromatogram)?;
+    }
+    columns.write(out, compress)
+}
+
+/// Writes the B000 file read from `input` as an `.npz` archive (see
+/// `write_npz`), decoding one spectrum or chromatogram at a time.
+pub fn write_b000_as_npz<W: Write, R: Read + Seek>(
+    out: W,
+    input: R,
+    compress: bool,
+) -> Result<W, String> {
+    let mut columns = NpzColumns::new()?;
+    for item in B000Reader::new(input)? {
+        match item? {
+            MzMLItem::Spectrum(spectrum) => columns.add_spectrum(&spectrum)?,
+            MzMLItem::Chromatogram(chromatogram) => columns.add_chromatogram(&chromatogram)?,
+        }
+    }
+    columns.write(out, compress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        b64::{encode, encoder::encode::WritingMode},
+        mzml::parse_mzml::parse_mzml,
+    };
+    use std::io::Cursor;
+
+    /// Data of the stored entry `name` of the uncompressed archive `zip`,
+    /// after its `.npy` header.
+    fn entry<'a>(zip: &'a [u8], name: &str) -> &'a [u8] {
+        let u16_at = |at: usize| u16::from_le_bytes([zip[at], zip[at + 1]]) as usize;
+        let u32_at = |at: usize| u32::from_le_bytes(zip[at..at + 4].try_into().unwrap()) as usize;
+        let mut central = u32_at(zip.len() - 6);
+        while &zip[central + 46..][..u16_at(central + 28)] != name.as_bytes() {
+            central += 46 + u16_at(central + 28) + u16_at(central + 30);
+        }
+        let local = u32_at(central + 42);
+        let data = &zip[local + 30 + name.len()..][..u32_at(central + 24)];
+        let header_len = u16::from_le_bytes([data[8], data[9]]) as usize;
+        &data[10 + header_len..]
+    }
+
+    fn f64s(data: &[u8]) -> Vec<f64> {
+        data.chunks_exact(8)
+            .map(|c| f64::from_le_bytes(c.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn exports_spectra_and_chromatograms() {
+        let bytes = std::fs::read("data/mzml/tiny.pwiz.mzML0.99.10.mzML").unwrap();
+        let mzml = parse_mzml(&bytes).unwrap();
+        let zip = write_npz(Vec::new(), &mzml, false).unwrap();
+
+        let offsets: Vec<i64> = entry(&zip, "offsets.npy")
+            .chunks_exact(8)
+            .map(|c| i64::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        assert_eq!(offsets, [0, 10, 20]);
+        assert_eq!(f64s(entry(&zip, "mz.npy")).len(), 20);
+        assert_eq!(entry(&zip, "ms_level.npy"), [1, 0, 0, 0, 2, 0, 0, 0]);
+        assert_eq!(entry(&zip, "charge.npy"), [0, 0, 0, 0, 2, 0, 0, 0]);
+        let precursor = f64s(entry(&zip, "precursor_mz.npy"));
+        assert!(precursor[0].is_nan() && precursor[1] == 445.34);
+        assert_eq!(f64s(entry(&zip, "rt.npy")), [353.43, 359.43]);
+        let chromatogram_offsets = entry(&zip, "chromatogram_offsets.npy");
+        assert_eq!(chromatogram_offsets.len(), 8 * 3);
+
+        let mut b000 = Vec::new();
+        encode(&mzml, 12, false, WritingMode::Memory, &mut b000).unwrap();
+        assert_eq!(
+            write_b000_as_npz(Vec::new(), Cursor::new(&b000), false).unwrap(),
+            zip
+        );
+    }
+}
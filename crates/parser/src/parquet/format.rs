@@ -0,0 +1,570 @@
+//! Minimal Parquet writer: one PLAIN-encoded data page (v1) per column and
+//! row group, uncompressed or zstd-compressed, with RLE levels and the
+//! footer in the Thrift compact protocol.
+
+use std::io::Write;
+
+const MAGIC: &[u8; 4] = b"PAR1";
+
+const ENCODING_PLAIN: i32 = 0;
+const ENCODING_RLE: i32 = 3;
+const CODEC_UNCOMPRESSED: i32 = 0;
+const CODEC_ZSTD: i32 = 6;
+const ZSTD_LEVEL: i32 = 3;
+
+/// Parquet physical type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PhysicalType {
+    Boolean = 0,
+    Int32 = 1,
+    Int64 = 2,
+    Double = 5,
+    ByteArray = 6,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Repetition {
+    Required = 0,
+    Optional = 1,
+    Repeated = 2,
+}
+
+/// Legacy converted types, which every reader understands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ConvertedType {
+    Utf8 = 0,
+    Map = 1,
+    MapKeyValue = 2,
+}
+
+/// Field of a schema: a leaf column or a group of fields.
+pub(crate) struct Field {
+    name: &'static str,
+    repetition: Repetition,
+    converted: Option<ConvertedType>,
+    kind: FieldKind,
+}
+
+enum FieldKind {
+    Leaf(PhysicalType),
+    Group(Vec<Field>),
+}
+
+impl Field {
+    pub(crate) fn leaf(name: &'static str, repetition: Repetition, ty: PhysicalType) -> Self {
+        Self {
+            name,
+            repetition,
+            converted: None,
+            kind: FieldKind::Leaf(ty),
+        }
+    }
+
+    /// UTF-8 string column.
+    pub(crate) fn string(name: &'static str, repetition: Repetition) -> Self {
+        Self {
+            converted: Some(ConvertedType::Utf8),
+            ..Self::leaf(name, repetition, PhysicalType::ByteArray)
+        }
+    }
+
+    /// Required map of strings to optional strings, as the leaf columns
+    /// `<name>.key_value.key` and `<name>.key_value.value`.
+    pub(crate) fn string_map(name: &'static str) -> Self {
+        let key_value = Self {
+            name: "key_value",
+            repetition: Repetition::Repeated,
+            converted: Some(ConvertedType::MapKeyValue),
+            kind: FieldKind::Group(vec![
+                Self::string("key", Repetition::Required),
+                Self::string("value", Repetition::Optional),
+            ]),
+        };
+        Self {
+            name,
+            repetition: Repetition::Required,
+            converted: Some(ConvertedType::Map),
+            kind: FieldKind::Group(vec![key_value]),
+        }
+    }
+}
+
+/// Values that have a PLAIN encoding.
+pub(crate) trait PlainValue {
+    fn encode(self, column: &mut Column);
+}
+
+impl PlainValue for bool {
+    fn encode(self, column: &mut Column) {
+        column.booleans.push(self);
+    }
+}
+
+impl PlainValue for i32 {
+    fn encode(self, column: &mut Column) {
+        column.values.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl PlainValue for i64 {
+    fn encode(self, column: &mut Column) {
+        column.values.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl PlainValue for f64 {
+    fn encode(self, column: &mut Column) {
+        column.values.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl PlainValue for &str {
+    fn encode(self, column: &mut Column) {
+        column
+            .values
+            .extend_from_slice(&(self.len() as u32).to_le_bytes());
+        column.values.extend_from_slice(self.as_bytes());
+    }
+}
+
+/// Buffered values and levels of one leaf column in the current row group.
+pub(crate) struct Column {
+    path: Vec<&'static str>,
+    ty: PhysicalType,
+    max_definition: u8,
+    max_repetition: u8,
+    values: Vec<u8>,
+    booleans: Vec<bool>,
+    definitions: Vec<u8>,
+    repetitions: Vec<u8>,
+}
+
+impl Column {
+    /// Appends a value of a required top-level column.
+    pub(crate) fn push<T: PlainValue>(&mut self, value: T) {
+        self.push_nested(0, self.max_definition, Some(value));
+    }
+
+    /// Appends a value of an optional top-level column, null for `None`.
+    pub(crate) fn push_option<T: PlainValue>(&mut self, value: Option<T>) {
+        let definition = if value.is_some() { 1 } else { 0 };
+        self.push_nested(0, definition, value);
+    }
+
+    /// Appends an entry with explicit levels; `value` is only written when
+    /// `definition` is the maximum definition level.
+    pub(crate) fn push_nested<T: PlainValue>(
+        &mut self,
+        repetition: u8,
+        definition: u8,
+        value: Option<T>,
+    ) {
+        if self.max_repetition > 0 {
+            self.repetitions.push(repetition);
+        }
+        self.definitions.push(definition);
+        if definition == self.max_definition
+            && let Some(value) = value
+        {
+            value.encode(self);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.definitions.len()
+    }
+
+    /// Levels and PLAIN values of the buffered entries, as a data page body.
+    fn page_body(&self) -> Vec<u8> {
+        let mut body = Vec::with_capacity(self.values.len() + 16);
+        if self.max_repetition > 0 {
+            rle_levels(&mut body, &self.repetitions, self.max_repetition);
+        }
+        if self.max_definition > 0 {
+            rle_levels(&mut body, &self.definitions, self.max_definition);
+        }
+        match self.ty {
+            PhysicalType::Boolean => {
+                let mut packed = vec![0u8; self.booleans.len().div_ceil(8)];
+                for (i, _) in self.booleans.iter().enumerate().filter(|(_, b)| **b) {
+                    packed[i / 8] |= 1 << (i % 8);
+                }
+                body.extend_from_slice(&packed);
+            }
+            _ => body.extend_from_slice(&self.values),
+        }
+        body
+    }
+
+    fn clear(&mut self) {
+        self.values.clear();
+        self.booleans.clear();
+        self.definitions.clear();
+        self.repetitions.clear();
+    }
+}
+
+/// `levels` in the RLE/bit-packing hybrid encoding, runs only, after their
+/// 4-byte length.
+fn rle_levels(out: &mut Vec<u8>, levels: &[u8], max_level: u8) {
+    let width = (8 - max_level.leading_zeros()).div_ceil(8) as usize;
+    let start = out.len();
+    out.extend_from_slice(&[0; 4]);
+    for run in levels.chunk_by(|a, b| a == b) {
+        varint(out, (run.len() as u64) << 1);
+        out.extend_from_slice(&[run[0]][..width]);
+    }
+    let len = (out.len() - start - 4) as u32;
+    out[start..start + 4].copy_from_slice(&len.to_le_bytes());
+}
+
+fn varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+struct ChunkMeta {
+    offset: u64,
+    num_values: i64,
+    uncompressed_size: i64,
+    compressed_size: i64,
+}
+
+struct RowGroupMeta {
+    chunks: Vec<ChunkMeta>,
+    num_rows: i64,
+}
+
+/// Parquet file written one row group at a time: fill the `columns`, in
+/// schema order, then call `flush_row_group`.
+pub(crate) struct ParquetWriter<W> {
+    out: W,
+    offset: u64,
+    compress: bool,
+    fields: Vec<Field>,
+    pub(crate) columns: Vec<Column>,
+    row_groups: Vec<RowGroupMeta>,
+    buffered_rows: i64,
+}
+
+impl<W: Write> ParquetWriter<W> {
+    /// Pages are zstd-compressed if `compress`.
+    pub(crate) fn new(out: W, fields: Vec<Field>, compress: bool) -> Result<Self, String> {
+        let mut columns = Vec::new();
+        for field in &fields {
+            collect_columns(field, &mut Vec::new(), 0, 0, &mut columns);
+        }
+        let mut writer = Self {
+            out,
+            offset: 0,
+            compress,
+            fields,
+            columns,
+            row_groups: Vec::new(),
+            buffered_rows: 0,
+        };
+        writer.write(MAGIC)?;
+        Ok(writer)
+    }
+
+    /// Counts `rows` rows whose columns were pushed.
+    pub(crate) fn end_rows(&mut self, rows: i64) {
+        self.buffered_rows += rows;
+    }
+
+    pub(crate) fn buffered_rows(&self) -> i64 {
+        self.buffered_rows
+    }
+
+    /// Writes the buffered rows as a row group, if any.
+    pub(crate) fn flush_row_group(&mut self) -> Result<(), String> {
+        if self.buffered_rows == 0 {
+            return Ok(());
+        }
+        let mut chunks = Vec::with_capacity(self.columns.len());
+        for i in 0..self.columns.len() {
+            let column = &self.columns[i];
+            let body = column.page_body();
+            let num_values = column.len();
+            let page = if self.compress {
+                zstd::bulk::compress(&body, ZSTD_LEVEL).map_err(|e| e.to_string())?
+            } else {
+                body.clone()
+            };
+            let too_large = || format!("column {} has a page over 2 GiB", column.path.join("."));
+            let mut header = Thrift::default();
+            header.i32(1, 0);
+            header.i32(2, i32::try_from(body.len()).map_err(|_| too_large())?);
+            header.i32(3, i32::try_from(page.len()).map_err(|_| too_large())?);
+            header.begin_struct(5);
+            header.i32(1, i32::try_from(num_values).map_err(|_| too_large())?);
+            header.i32(2, ENCODING_PLAIN);
+            header.i32(3, ENCODING_RLE);
+            header.i32(4, ENCODING_RLE);
+            header.end_struct();
+            header.end_struct();
+
+            let offset = self.offset;
+            self.write(&header.out)?;
+            self.write(&page)?;
+            chunks.push(ChunkMeta {
+                offset,
+                num_values: num_values as i64,
+                uncompressed_size: (header.out.len() + body.len()) as i64,
+                compressed_size: (header.out.len() + page.len()) as i64,
+            });
+            self.columns[i].clear();
+        }
+        self.row_groups.push(RowGroupMeta {
+            chunks,
+            num_rows: self.buffered_rows,
+        });
+        self.buffered_rows = 0;
+        Ok(())
+    }
+
+    /// Flushes the last row group, writes the footer and returns the
+    /// output.
+    pub(crate) fn finish(mut self) -> Result<W, String> {
+        self.flush_row_group()?;
+        let footer = self.file_metadata();
+        self.write(&footer)?;
+        self.write(&(footer.len() as u32).to_le_bytes())?;
+        self.write(MAGIC)?;
+        self.out.flush().map_err(|e| e.to_string())?;
+        Ok(self.out)
+    }
+
+    fn file_metadata(&self) -> Vec<u8> {
+        let mut t = Thrift::default();
+        t.i32(1, 1);
+
+        let mut elements = 1;
+        self.fields
+            .iter()
+            .for_each(|f| elements += count_elements(f));
+        t.list_begin(2, Thrift::STRUCT, elements);
+        t.begin_element();
+        t.binary(4, b"schema");
+        t.i32(5, self.fields.len() as i32);
+        t.end_struct();
+        for field in &self.fields {
+            schema_elements(&mut t, field);
+        }
+
+        let num_rows = self.row_groups.iter().map(|g| g.num_rows).sum();
+        t.i64(3, num_rows);
+        t.list_begin(4, Thrift::STRUCT, self.row_groups.len());
+        for group in &self.row_groups {
+            t.begin_element();
+            t.list_begin(1, Thrift::STRUCT, group.chunks.len());
+            for (chunk, column) in group.chunks.iter().zip(&self.columns) {
+                t.begin_element();
+                t.i64(2, chunk.offset as i64);
+                t.begin_struct(3);
+                t.i32(1, column.ty as i32);
+                t.list_begin(2, Thrift::I32, 2);
+                t.list_i32(ENCODING_PLAIN);
+                t.list_i32(ENCODING_RLE);
+                t.list_begin(3, Thrift::BINARY, column.path.len());
+                column.path.iter().for_each(|p| t.list_binary(p.as_bytes()));
+                let codec = if self.compress {
+                    CODEC_ZSTD
+                } else {
+                    CODEC_UNCOMPRESSED
+                };
+                t.i32(4, codec);
+                t.i64(5, chunk.num_values);
+                t.i64(6, chunk.uncompressed_size);
+                t.i64(7, chunk.compressed_size);
+                t.i64(9, chunk.offset as i64);
+                t.end_struct();
+                t.end_struct();
+            }
+            let byte_size = group.chunks.iter().map(|c| c.uncompressed_size).sum();
+            t.i64(2, byte_size);
+            t.i64(3, group.num_rows);
+            t.end_struct();
+        }
+        let created_by = format!("octo version {}", env!("CARGO_PKG_VERSION"));
+        t.binary(6, created_by.as_bytes());
+        t.end_struct();
+        t.out
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.out.write_all(bytes).map_err(|e| e.to_string())?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+fn collect_columns(
+    field: &Field,
+    path: &mut Vec<&'static str>,
+    definition: u8,
+    repetition: u8,
+    columns: &mut Vec<Column>,
+) {
+    let definition = definition + u8::from(field.repetition != Repetition::Required);
+    let repetition = repetition + u8::from(field.repetition == Repetition::Repeated);
+    path.push(field.name);
+    match &field.kind {
+        FieldKind::Leaf(ty) => columns.push(Column {
+            path: path.clone(),
+            ty: *ty,
+            max_definition: definition,
+            max_repetition: repetition,
+            values: Vec::new(),
+            booleans: Vec::new(),
+            definitions: Vec::new(),
+            repetitions: Vec::new(),
+        }),
+        FieldKind::Group(children) => {
+            for child in children {
+                collect_columns(child, path, definition, repetition, columns);
+            }
+        }
+    }
+    path.pop();
+}
+
+fn count_elements(field: &Field) -> usize {
+    match &field.kind {
+        FieldKind::Leaf(_) => 1,
+        FieldKind::Group(children) => 1 + children.iter().map(count_elements).sum::<usize>(),
+    }
+}
+
+/// `SchemaElement`s of `field` and its children, depth first.
+fn schema_elements(t: &mut Thrift, field: &Field) {
+    t.begin_element();
+    if let FieldKind::Leaf(ty) = field.kind {
+        t.i32(1, ty as i32);
+    }
+    t.i32(3, field.repetition as i32);
+    t.binary(4, field.name.as_bytes());
+    if let FieldKind::Group(children) = &field.kind {
+        t.i32(5, children.len() as i32);
+    }
+    if let Some(converted) = field.converted {
+        t.i32(6, converted as i32);
+    }
+    t.end_struct();
+    if let FieldKind::Group(children) = &field.kind {
+        children.iter().for_each(|child| schema_elements(t, child));
+    }
+}
+
+/// Thrift compact protocol encoder for the structs of the Parquet footer and
+/// page headers.
+#[derive(Default)]
+struct Thrift {
+    out: Vec<u8>,
+    last_field: i16,
+    /// Last field ids of the enclosing structs.
+    stack: Vec<i16>,
+}
+
+impl Thrift {
+    const I32: u8 = 5;
+    const I64: u8 = 6;
+    const BINARY: u8 = 8;
+    const LIST: u8 = 9;
+    const STRUCT: u8 = 12;
+
+    fn field(&mut self, id: i16, ty: u8) {
+        let delta = id - self.last_field;
+        if (1..=15).contains(&delta) {
+            self.out.push((delta as u8) << 4 | ty);
+        } else {
+            self.out.push(ty);
+            self.zigzag(i64::from(id));
+        }
+        self.last_field = id;
+    }
+
+    fn zigzag(&mut self, value: i64) {
+        varint(&mut self.out, ((value << 1) ^ (value >> 63)) as u64);
+    }
+
+    fn i32(&mut self, id: i16, value: i32) {
+        self.field(id, Self::I32);
+        self.zigzag(i64::from(value));
+    }
+
+    fn i64(&mut self, id: i16, value: i64) {
+        self.field(id, Self::I64);
+        self.zigzag(value);
+    }
+
+    fn binary(&mut self, id: i16, value: &[u8]) {
+        self.field(id, Self::BINARY);
+        self.list_binary(value);
+    }
+
+    fn begin_struct(&mut self, id: i16) {
+        self.field(id, Self::STRUCT);
+        self.begin_element();
+    }
+
+    /// Starts a struct that is a list element, without a field header.
+    fn begin_element(&mut self) {
+        self.stack.push(self.last_field);
+        self.last_field = 0;
+    }
+
+    fn end_struct(&mut self) {
+        self.out.push(0);
+        self.last_field = self.stack.pop().unwrap_or(0);
+    }
+
+    fn list_begin(&mut self, id: i16, element: u8, len: usize) {
+        self.field(id, Self::LIST);
+        if len < 15 {
+            self.out.push((len as u8) << 4 | element);
+        } else {
+            self.out.push(0xf0 | element);
+            varint(&mut self.out, len as u64);
+        }
+    }
+
+    fn list_i32(&mut self, value: i32) {
+        self.zigzag(i64::from(value));
+    }
+
+    fn list_binary(&mut self, value: &[u8]) {
+        varint(&mut self.out, value.len() as u64);
+        self.out.extend_from_slice(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_are_run_length_encoded() {
+        let mut out = Vec::new();
+        rle_levels(&mut out, &[0, 0, 0, 1, 2, 2], 2);
+        assert_eq!(out, [6, 0, 0, 0, 6, 0, 2, 1, 4, 2]);
+    }
+
+    #[test]
+    fn thrift_fields_use_deltas_and_zigzag() {
+        let mut t = Thrift::default();
+        t.i32(1, -1);
+        t.begin_struct(5);
+        t.i64(20, 64);
+        t.end_struct();
+        t.binary(6, b"ab");
+        t.end_struct();
+        assert_eq!(
+            t.out,
+            [0x15, 1, 0x4c, 0x06, 40, 128, 1, 0, 0x18, 2, b'a', b'b', 0]
+        );
+    }
+}
@@ -0,0 +1,3 @@
+pub(crate) mod format;
+pub mod write_parquet;
+pub use write_parquet::{write_b000_as_parquet, write_parquet, write_parquet_streaming};
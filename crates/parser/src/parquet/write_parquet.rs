@@ -0,0 +1,673 @@
+use std::{
+    borrow::Borrow,
+    io::{Read, Seek, Write},
+};
+
+use crate::{
+    b64::B000Reader,
+    mzml::{
+        structs::*,
+        utilities::peak_list::{
+            ACC_INTENSITY_ARRAY, ACC_MS_LEVEL, ACC_MZ_ARRAY, array_f64, find_cv, first_scan_params,
+            ion_injection_ms, ms_level, precursor_charges, precursor_intensity, precursor_mz,
+            scan_start_seconds,
+        },
+    },
+    parquet::format::{Field, ParquetWriter, PhysicalType, Repetition},
+};
+
+const ACC_CENTROID: &str = "MS:1000127";
+const ACC_PROFILE: &str = "MS:1000128";
+const ACC_NEGATIVE_SCAN: &str = "MS:1000129";
+const ACC_POSITIVE_SCAN: &str = "MS:1000130";
+const ACC_BASE_PEAK_MZ: &str = "MS:1000504";
+const ACC_BASE_PEAK_INTENSITY: &str = "MS:1000505";
+const ACC_TOTAL_ION_CURRENT: &str = "MS:1000285";
+const ACC_LOWEST_OBSERVED_MZ: &str = "MS:1000528";
+const ACC_HIGHEST_OBSERVED_MZ: &str = "MS:1000527";
+const ACC_FILTER_STRING: &str = "MS:1000512";
+/// Ion mobility arrays, the first one present is exported.
+const ACC_ION_MOBILITY_ARRAYS: [&str; 7] = [
+    "MS:1002893",
+    "MS:1002816",
+    "MS:1003006",
+    "MS:1003007",
+    "MS:1003008",
+    "MS:1003154",
+    "MS:1003155",
+];
+
+/// Spectrum cvParams flattened into columns, left out of `params`.
+const FLATTENED: [&str; 10] = [
+    ACC_MS_LEVEL,
+    ACC_CENTROID,
+    ACC_PROFILE,
+    ACC_NEGATIVE_SCAN,
+    ACC_POSITIVE_SCAN,
+    ACC_BASE_PEAK_MZ,
+    ACC_BASE_PEAK_INTENSITY,
+    ACC_TOTAL_ION_CURRENT,
+    ACC_LOWEST_OBSERVED_MZ,
+    ACC_HIGHEST_OBSERVED_MZ,
+];
+
+/// Rows per row group of the peaks and spectra tables.
+const PEAK_ROW_GROUP: i64 = 1 << 20;
+const SPECTRUM_ROW_GROUP: i64 = 1 << 16;
+
+/// Writes the spectra of `mzml` as the peaks and spectra Parquet tables
+/// (see `write_parquet_streaming`) and returns both outputs.
+pub fn write_parquet<P: Write, S: Write>(
+    peaks: P,
+    spectra: S,
+    mzml: &MzML,
+    compress: bool,
+) -> Result<(P, S), String> {
+    let items = mzml.run.spectrum_list.iter().flat_map(|l| &l.spectra);
+    write_parquet_streaming(peaks, spectra, items, compress)
+}
+
+/// Writes the spectra of the B000 file read from `input` as the peaks and
+/// spectra Parquet tables, decoding one spectrum at a time.
+pub fn write_b000_as_parquet<P: Write, S: Write, R: Read + Seek>(
+    peaks: P,
+    spectra: S,
+    input: R,
+    compress: bool,
+) -> Result<(P, S), String> {
+    let mut items = B000Reader::new(input)?.spectra();
+    let out = write_parquet_streaming(peaks, spectra, items.by_ref(), compress)?;
+    items.finish().map(|()| out)
+}
+
+/// Writes `spectra` as two Parquet tables, zstd-compressed if `compress`.
+///
+/// `peaks` gets one row per peak: `spectrum_index` (int64), `mz`,
+/// `intensity` and the optional `ion_mobility` (double). `spectra` gets one
+/// row per spectrum: `index` (its position, as in `spectrum_index`), `id`,
+/// `native_id`, `ms_level`, `centroid`, `polarity`, `rt` (seconds),
+/// `total_ion_current`, `base_peak_mz`, `base_peak_intensity`,
+/// `lowest_observed_mz`, `highest_observed_mz`, `filter_string`,
+/// `ion_injection_time` (milliseconds), `precursor_mz`, `precursor_charge`,
+/// `precursor_intensity` and `peak_count`, null when absent, then the other
+/// spectrum cvParams and userParams as the `params` map of names to values.
+pub fn write_parquet_streaming<P, S, T, I>(
+    peaks: P,
+    spectra: S,
+    items: I,
+    compress: bool,
+) -> Result<(P, S), String>
+where
+    P: Write,
+    S: Write,
+    T: Borrow<Spectrum>,
+    I: IntoIterator<Item = T>,
+{
+    let mut peaks = ParquetWriter::new(peaks, peak_fields(), compress)?;
+    let mut spectra = ParquetWriter::new(spectra, spectrum_fields(), compress)?;
+    for (index, spectrum) in items.into_iter().enumerate() {
+        let spectrum = spectrum.borrow();
+        let count = push_peaks(&mut peaks, index as i64, spectrum);
+        push_spectrum(&mut spectra, index as i64, spectrum, count);
+        if peaks.buffered_rows() >= PEAK_ROW_GROUP {
+            peaks.flush_row_group()?;
+        }
+        if spectra.buffered_rows() >= SPECTRUM_ROW_GROUP {
+            spectra.flush_row_group()?;
+        }
+    }
+    Ok((peaks.finish()?, spectra.finish()?))
+}
+
+fn peak_fields() -> Vec<Field> {
+    use {PhysicalType::*, Repetition::*};
+    vec![
+        Field::leaf("spectrum_index", Required, Int64),
+        Field::leaf("mz", Required, Double),
+        Field::leaf("intensity", Required, Double),
+        Field::leaf("ion_mobility", Optional, Double),
+    ]
+}
+
+fn spectrum_fields() -> Vec<Field> {
+    use {PhysicalType::*, Repetition::*};
+    vec![
+        Field::leaf("index", Required, Int64),
+        Field::string("id", Required),
+        Field::string("native_id", Optional),
+        Field::leaf("ms_level", Optional, Int32),
+        Field::leaf("centroid", Optional, Boolean),
+        Field::string("polarity", Optional),
+        Field::leaf("rt", Optional, Double),
+        Field::leaf("total_ion_current", Optional, Double),
+        Field::leaf("base_peak_mz", Optional, Double),
+        Field::leaf("base_peak_intensity", Optional, Double),
+        Field::leaf("lowest_observed_mz", Optional, Double),
+        Field::leaf("highest_observed_mz", Optional, Double),
+        Field::string("filter_string", Optional),
+        Field::leaf("ion_injection_time", Optional, Double),
+        Field::leaf("precursor_mz", Optional, Double),
+        Field::leaf("precursor_charge", Optional, Int32),
+        Field::leaf("precursor_intensity", Optional, Double),
+        Field::leaf("peak_count", Required, Int64),
+        Field::string_map("params"),
+    ]
+}
+
+/// Pushes the peaks of `spectrum` and returns their count.
+fn push_peaks<W: Write>(table: &mut ParquetWriter<W>, index: i64, spectrum: &Spectrum) -> i64 {
+    let mz = array_f64(spectrum, ACC_MZ_ARRAY).unwrap_or_default();
+    let intensity = array_f64(spectrum, ACC_INTENSITY_ARRAY).unwrap_or_default();
+    let mobility = ACC_ION_MOBILITY_ARRAYS
+        .iter()
+        .find_map(|accession| array_f64(spectrum, accession))
+        .unwrap_or_default();
+    let [index_column, mz_column, intensity_column, mobility_column] = &mut table.columns[..]
+    else {
+        unreachable!("peaks table has four columns");
+    };
+    for (i, &mz) in mz.iter().enumerate() {
+        index_column.push(index);
+        mz_column.push(mz);
+        intensity_column.push(intensity.get(i).copied().unwrap_or(0.0));
+        mobility_column.push_option(mobility.get(i).copied());
+    }
+    table.end_rows(mz.len() as i64);
+    mz.len() as i64
+}
+
+fn push_spectrum<W: Write>(
+    table: &mut ParquetWriter<W>,
+    index: i64,
+    spectrum: &Spectrum,
+    peak_count: i64,
+) {
+    let description = spectrum.spectrum_description.as_ref();
+    let params = || {
+        let legacy = description.map_or(&[][..], |d| &d.cv_params);
+        spectrum.cv_params.iter().chain(legacy)
+    };
+    let has = |accession: &str| params().any(|p| p.accession.as_deref() == Some(accession));
+    let value = |accession: &str| {
+        let param = params().find(|p| p.accession.as_deref() == Some(accession))?;
+        param.value.as_deref()?.trim().parse::<f64>().ok()
+    };
+    let centroid = if has(ACC_CENTROID) {
+        Some(true)
+    } else if has(ACC_PROFILE) {
+        Some(false)
+    } else {
+        None
+    };
+    let polarity = if has(ACC_POSITIVE_SCAN) {
+        Some("positive")
+    } else if has(ACC_NEGATIVE_SCAN) {
+        Some("negative")
+    } else {
+        None
+    };
+    let filter_string =
+        find_cv(first_scan_params(spectrum), ACC_FILTER_STRING).and_then(|p| p.value.as_deref());
+
+    let mut columns = table.columns.iter_mut();
+    let mut next = || columns.next().expect("spectra table column");
+    next().push(index);
+    next().push(spectrum.id.as_str());
+    next().push_option(spectrum.native_id.as_deref());
+    next().push_option(ms_level(spectrum).map(|l| l as i32));
+    next().push_option(centroid);
+    next().push_option(polarity);
+    next().push_option(scan_start_seconds(spectrum));
+    next().push_option(value(ACC_TOTAL_ION_CURRENT));
+    next().push_option(value(ACC_BASE_PEAK_MZ));
+    next().push_option(value(ACC_BASE_PEAK_INTENSITY));
+    next().push_option(value(ACC_LOWEST_OBSERVED_MZ));
+    next().push_option(value(ACC_HIGHEST_OBSERVED_MZ));
+    next().push_option(filter_string);
+    next().push_option(ion_injection_ms(spectrum));
+    next().push_option(precursor_mz(spectrum));
+    next().push_option(precursor_charges(spectrum).first().copied());
+    next().push_option(precursor_intensity(spectrum));
+    next().push(peak_count);
+
+    let rest = params()
+        .filter(|p| {
+            !p.accession
+                .as_deref()
+                .is_some_and(|a| FLATTENED.contains(&a))
+        })
+        .map(|p| (p.name.as_str(), p.value.as_deref()));
+    let user = spectrum.user_params.iter();
+    let entries: Vec<_> = rest
+        .chain(user.map(|p| (p.name.as_str(), p.value.as_deref())))
+        .collect();
+    let (keys, values) = (next(), next());
+    if entries.is_empty() {
+        keys.push_nested::<&str>(0, 0, None);
+        values.push_nested::<&str>(0, 0, None);
+    }
+    for (i, (key, value)) in entries.into_iter().enumerate() {
+        let repetition = u8::from(i > 0);
+        keys.push_nested(repetition, 1, Some(key));
+        values.push_nested(repetition, 1 + u8::from(value.is_some()), value);
+    }
+    table.end_rows(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        b64::{encode, encoder::encode::WritingMode},
+        mzml::parse_mzml::parse_mzml,
+    };
+    use std::io::Cursor;
+
+    /// Thrift footer of the Parquet file `bytes`.
+    fn footer(bytes: &[u8]) -> &[u8] {
+        assert_eq!(&bytes[..4], b"PAR1");
+        assert_eq!(&bytes[bytes.len() - 4..], b"PAR1");
+        let len = u32::from_le_bytes(bytes[bytes.len() - 8..][..4].try_into().unwrap());
+        &bytes[bytes.len() - 8 - len as usize..bytes.len() - 8]
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|w| w == needle)
+    }
+
+    #[test]
+    fn writes_peak_and_spectrum_tables() {
+        let bytes = std::fs::read("data/mzml/tiny.pwiz.mzML0.99.10.mzML").unwrap();
+        let mzml = parse_mzml(&bytes).unwrap();
+        let (peaks, spectra) = write_parquet(Vec::new(), Vec::new(), &mzml, false).unwrap();
+
+        let peak_footer = footer(&peaks);
+        assert!(contains(peak_footer, b"spectrum_index"));
+        assert!(contains(peak_footer, b"ion_mobility"));
+        // Plain m/z values of the first spectrum follow the first page.
+        let mz = array_f64(
+            &mzml.run.spectrum_list.as_ref().unwrap().spectra[0],
+            ACC_MZ_ARRAY,
+        );
+        let first: Vec<u8> = mz.unwrap()[..2]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        assert!(contains(&peaks, &first));
+
+        let spectrum_footer = footer(&spectra);
+        assert!(contains(spectrum_footer, b"key_value"));
+        assert!(contains(&spectra, b"S20"));
+        assert!(contains(&spectra, &445.34f64.to_le_bytes()));
+
+        let mut b000 = Vec::new();
+        encode(&mzml, 12, false, WritingMode::Memory, &mut b000).unwrap();
+        let streamed =
+            write_b000_as_parquet(Vec::new(), Vec::new(), Cursor::new(&b000), false).unwrap();
+        assert_eq!(streamed.0, peaks);
+    }
+
+    /// Thrift compact protocol value, as far as the footer and page headers
+    /// use it.
+    #[derive(Debug)]
+    enum Thrift {
+        Int(i64),
+        Binary(Vec<u8>),
+        List(Vec<Thrift>),
+        Struct(Vec<(i16, Thrift)>),
+    }
+
+    impl Thrift {
+        fn get(&self, id: i16) -> Option<&Thrift> {
+            let Thrift::Struct(fields) = self else {
+                panic!("not a struct: {self:?}");
+            };
+            fields.iter().find(|(i, _)| *i == id).map(|(_, v)| v)
+        }
+
+        fn field(&self, id: i16) -> &Thrift {
+            self.get(id).unwrap_or_else(|| panic!("no field {id}"))
+        }
+
+        fn int(&self) -> i64 {
+            match self {
+                Thrift::Int(v) => *v,
+                _ => panic!("not an integer: {self:?}"),
+            }
+        }
+
+        fn string(&self) -> &str {
+            match self {
+                Thrift::Binary(v) => std::str::from_utf8(v).unwrap(),
+                _ => panic!("not a binary: {self:?}"),
+            }
+        }
+
+        fn list(&self) -> &[Thrift] {
+            match self {
+                Thrift::List(v) => v,
+                _ => panic!("not a list: {self:?}"),
+            }
+        }
+    }
+
+    struct Input<'a> {
+        bytes: &'a [u8],
+        pos: usize,
+    }
+
+    impl Input<'_> {
+        fn byte(&mut self) -> u8 {
+            self.pos += 1;
+            self.bytes[self.pos - 1]
+        }
+
+        fn take(&mut self, len: usize) -> &[u8] {
+            self.pos += len;
+            &self.bytes[self.pos - len..self.pos]
+        }
+
+        fn varint(&mut self) -> u64 {
+            let (mut value, mut shift) = (0, 0);
+            loop {
+                let b = self.byte();
+                value |= u64::from(b & 0x7f) << shift;
+                if b < 0x80 {
+                    return value;
+                }
+                shift += 7;
+            }
+        }
+
+        fn zigzag(&mut self) -> i64 {
+            let v = self.varint();
+            (v >> 1) as i64 ^ -((v & 1) as i64)
+        }
+
+        fn value(&mut self, ty: u8) -> Thrift {
+            match ty {
+                1 | 2 => Thrift::Int(i64::from(ty == 1)),
+                5 | 6 => Thrift::Int(self.zigzag()),
+                8 => {
+                    let len = self.varint() as usize;
+                    Thrift::Binary(self.take(len).to_vec())
+                }
+                9 => {
+                    let header = self.byte();
+                    let len = match header >> 4 {
+                        15 => self.varint() as usize,
+                        len => len as usize,
+                    };
+                    Thrift::List((0..len).map(|_| self.value(header & 0x0f)).collect())
+                }
+                12 => self.structure(),
+                _ => panic!("unexpected Thrift type {ty}"),
+            }
+        }
+
+        fn structure(&mut self) -> Thrift {
+            let (mut fields, mut last) = (Vec::new(), 0);
+            loop {
+                let header = self.byte();
+                if header == 0 {
+                    return Thrift::Struct(fields);
+                }
+                last = match header >> 4 {
+                    0 => self.zigzag() as i16,
+                    delta => last + i16::from(delta),
+                };
+                fields.push((last, self.value(header & 0x0f)));
+            }
+        }
+
+        /// RLE levels of `count` entries, runs only, as the writer makes
+        /// them.
+        fn levels(&mut self, count: usize, max_level: u8) -> Vec<u8> {
+            if max_level == 0 {
+                return vec![0; count];
+            }
+            let len = u32::from_le_bytes(self.take(4).try_into().unwrap()) as usize;
+            let end = self.pos + len;
+            let mut levels = Vec::new();
+            while levels.len() < count {
+                let header = self.varint();
+                assert_eq!(header & 1, 0, "bit-packed run");
+                let level = self.byte();
+                assert!(level <= max_level);
+                levels.extend(std::iter::repeat_n(level, (header >> 1) as usize));
+            }
+            assert_eq!((levels.len(), self.pos), (count, end));
+            levels
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Value {
+        Bool(bool),
+        I32(i32),
+        I64(i64),
+        F64(f64),
+        Str(String),
+    }
+
+    /// Leaf column read back from all row groups.
+    #[derive(Debug, Default)]
+    struct ColumnData {
+        repetitions: Vec<u8>,
+        definitions: Vec<u8>,
+        values: Vec<Value>,
+    }
+
+    /// Leaf columns of the schema, depth first, as their dotted path,
+    /// physical type and maximum definition and repetition levels.
+    fn leaves(
+        schema: &[Thrift],
+        at: &mut usize,
+        path: &str,
+        levels: (u8, u8),
+        out: &mut Vec<(String, i64, u8, u8)>,
+    ) {
+        let element = &schema[*at];
+        *at += 1;
+        let repetition = element.field(3).int();
+        let levels = (
+            levels.0 + u8::from(repetition != 0),
+            levels.1 + u8::from(repetition == 2),
+        );
+        let name = element.field(4).string();
+        let path = if path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{path}.{name}")
+        };
+        match element.get(5) {
+            Some(children) => {
+                for _ in 0..children.int() {
+                    leaves(schema, at, &path, levels, out);
+                }
+            }
+            None => out.push((path, element.field(1).int(), levels.0, levels.1)),
+        }
+    }
+
+    /// Decodes the Parquet file `bytes`: its row count and every leaf
+    /// column by dotted path.
+    fn read_table(bytes: &[u8]) -> (i64, Vec<(String, ColumnData)>) {
+        let metadata = Input {
+            bytes: footer(bytes),
+            pos: 0,
+        }
+        .structure();
+        assert_eq!(metadata.field(1).int(), 1);
+        let schema = metadata.field(2).list();
+        let root_children = schema[0].field(5).int();
+        let (mut at, mut columns) = (1, Vec::new());
+        for _ in 0..root_children {
+            leaves(schema, &mut at, "", (0, 0), &mut columns);
+        }
+        assert_eq!(at, schema.len());
+
+        let mut data: Vec<ColumnData> = columns.iter().map(|_| ColumnData::default()).collect();
+        for group in metadata.field(4).list() {
+            let chunks = group.field(1).list();
+            assert_eq!(chunks.len(), columns.len());
+            for ((path, ty, max_definition, max_repetition), (chunk, column)) in
+                columns.iter().zip(chunks.iter().zip(&mut data))
+            {
+                let meta = chunk.field(3);
+                assert_eq!(meta.field(1).int(), *ty, "{path}");
+                let meta_path: Vec<&str> =
+                    meta.field(3).list().iter().map(Thrift::string).collect();
+                assert_eq!(meta_path.join("."), *path);
+                let offset = meta.field(9).int() as usize;
+                assert_eq!(chunk.field(2).int() as usize, offset);
+
+                let mut input = Input { bytes, pos: offset };
+                let header = input.structure();
+                assert_eq!(header.field(1).int(), 0, "data page");
+                let page = input.take(header.field(3).int() as usize);
+                let body = match meta.field(4).int() {
+                    0 => page.to_vec(),
+                    6 => zstd::bulk::decompress(page, header.field(2).int() as usize).unwrap(),
+                    codec => panic!("codec {codec}"),
+                };
+                assert_eq!(body.len(), header.field(2).int() as usize);
+                let page_header = header.field(5);
+                let count = page_header.field(1).int() as usize;
+                assert_eq!(meta.field(5).int() as usize, count);
+                assert_eq!(page_header.field(2).int(), 0, "PLAIN");
+
+                let mut body = Input {
+                    bytes: &body,
+                    pos: 0,
+                };
+                let repetitions = body.levels(count, *max_repetition);
+                let definitions = body.levels(count, *max_definition);
+                let present = definitions
+                    .iter()
+                    .filter(|&&d| d == *max_definition)
+                    .count();
+                let values: Vec<Value> = match ty {
+                    0 => {
+                        let packed = body.take(present.div_ceil(8));
+                        (0..present)
+                            .map(|i| Value::Bool(packed[i / 8] >> (i % 8) & 1 == 1))
+                            .collect()
+                    }
+                    1 => (0..present)
+                        .map(|_| Value::I32(i32::from_le_bytes(body.take(4).try_into().unwrap())))
+                        .collect(),
+                    2 => (0..present)
+                        .map(|_| Value::I64(i64::from_le_bytes(body.take(8).try_into().unwrap())))
+                        .collect(),
+                    5 => (0..present)
+                        .map(|_| Value::F64(f64::from_le_bytes(body.take(8).try_into().unwrap())))
+                        .collect(),
+                    6 => (0..present)
+                        .map(|_| {
+                            let len = u32::from_le_bytes(body.take(4).try_into().unwrap());
+                            let text = std::str::from_utf8(body.take(len as usize)).unwrap();
+                            Value::Str(text.to_string())
+                        })
+                        .collect(),
+                    _ => panic!("type {ty}"),
+                };
+                assert_eq!(body.pos, body.bytes.len(), "{path}");
+
+                let rows = repetitions.iter().filter(|&&r| r == 0).count();
+                assert_eq!(rows as i64, group.field(3).int(), "{path}");
+                column.repetitions.extend(repetitions);
+                column.definitions.extend(definitions);
+                column.values.extend(values);
+            }
+        }
+        let paths = columns.into_iter().map(|(path, ..)| path);
+        (metadata.field(3).int(), paths.zip(data).collect())
+    }
+
+    #[test]
+    fn tables_decode_to_the_written_rows() {
+        let bytes = std::fs::read("data/mzml/tiny.pwiz.mzML0.99.10.mzML").unwrap();
+        let mut mzml = parse_mzml(&bytes).unwrap();
+        let spectra = &mut mzml.run.spectrum_list.as_mut().unwrap().spectra;
+        spectra[0].user_params = vec![UserParam {
+            name: "note".to_string(),
+            ..UserParam::default()
+        }];
+        // No params at all: an empty map.
+        spectra[1].cv_params.clear();
+        spectra[1].spectrum_description = None;
+        let mz: Vec<Vec<f64>> = spectra
+            .iter()
+            .map(|s| array_f64(s, ACC_MZ_ARRAY).unwrap())
+            .collect();
+
+        for compress in [false, true] {
+            let (peaks, spectra) = write_parquet(Vec::new(), Vec::new(), &mzml, compress).unwrap();
+
+            let (rows, columns) = read_table(&peaks);
+            assert_eq!(rows as usize, mz.iter().map(Vec::len).sum::<usize>());
+            let paths: Vec<&str> = columns.iter().map(|(p, _)| p.as_str()).collect();
+            assert_eq!(paths, ["spectrum_index", "mz", "intensity", "ion_mobility"]);
+            let index: Vec<Value> = mz
+                .iter()
+                .enumerate()
+                .flat_map(|(i, mz)| mz.iter().map(move |_| Value::I64(i as i64)))
+                .collect();
+            assert_eq!(columns[0].1.values, index);
+            let all_mz: Vec<Value> = mz.iter().flatten().map(|&v| Value::F64(v)).collect();
+            assert_eq!(columns[1].1.values, all_mz);
+            assert_eq!(columns[2].1.values.len(), rows as usize);
+            // No ion mobility array: every entry is null.
+            assert!(columns[3].1.values.is_empty());
+            assert!(columns[3].1.definitions.iter().all(|&d| d == 0));
+
+            let (rows, columns) = read_table(&spectra);
+            assert_eq!(rows, 2);
+            let column = |path: &str| &columns.iter().find(|(p, _)| p == path).unwrap().1;
+            let strings = |values: &[&str]| -> Vec<Value> {
+                values.iter().map(|v| Value::Str(v.to_string())).collect()
+            };
+            assert_eq!(column("id").values, strings(&["S19", "S20"]));
+            assert_eq!(column("ms_level").values, [Value::I32(1), Value::I32(2)]);
+            // The second spectrum lost its description and so its centroid
+            // cvParam.
+            assert_eq!(column("centroid").definitions, [1, 0]);
+            assert_eq!(column("centroid").values, [Value::Bool(true)]);
+            assert_eq!(column("base_peak_mz").values, [Value::F64(445.347)]);
+            assert_eq!(column("precursor_charge").definitions, [0, 1]);
+            assert_eq!(column("precursor_charge").values, [Value::I32(2)]);
+            assert_eq!(
+                column("peak_count").values,
+                mz.iter()
+                    .map(|m| Value::I64(m.len() as i64))
+                    .collect::<Vec<_>>()
+            );
+
+            // First row: "MSn spectrum" with an empty value, then "note"
+            // without one; second row: an empty map.
+            let keys = column("params.key_value.key");
+            assert_eq!(keys.repetitions, [0, 1, 0]);
+            assert_eq!(keys.definitions, [1, 1, 0]);
+            assert_eq!(keys.values, strings(&["MSn spectrum", "note"]));
+            let values = column("params.key_value.value");
+            assert_eq!(values.repetitions, [0, 1, 0]);
+            assert_eq!(values.definitions, [2, 1, 0]);
+            assert_eq!(values.values, strings(&[""]));
+        }
+    }
+
+    #[test]
+    fn compressed_tables_are_smaller() {
+        let bytes = std::fs::read("data/mzml/tiny.pwiz.mzML0.99.10.mzML").unwrap();
+        let mut mzml = parse_mzml(&bytes).unwrap();
+        let spectra = &mut mzml.run.spectrum_list.as_mut().unwrap().spectra;
+        let spectrum = spectra[0].clone();
+        spectra.extend(std::iter::repeat_n(spectrum, 200));
+
+        let plain = write_parquet(Vec::new(), Vec::new(), &mzml, false).unwrap();
+        let zstd = write_parquet(Vec::new(), Vec::new(), &mzml, true).unwrap();
+        assert!(zstd.0.len() < plain.0.len() / 2);
+        assert!(zstd.1.len() < plain.1.len() / 2);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{
+    mzml::{
+        structs::{Chromatogram, IsolationWindow, MzML},
+        utilities::peak_list::{ACC_MZ, ACC_SELECTED_ION_MZ, cv_f64},
+    },
+    traml::structs::{ACC_TARGET_MZ, TraML, Transition},
+};
+
+/// A chromatogram and the transition it was acquired for, if any.
+#[derive(Debug, Clone, Copy)]
+pub struct TransitionMatch<'a> {
+    pub chromatogram: &'a Chromatogram,
+    pub transition: Option<&'a Transition>,
+}
+
+/// Q1 of `chromatogram`: the precursor isolation window target, else the
+/// selected ion m/z.
+pub fn chromatogram_precursor_mz(chromatogram: &Chromatogram) -> Option<f64> {
+    let precursor = chromatogram.precursor.as_ref()?;
+    let window = precursor.isolation_window.as_ref().and_then(window_target);
+    window.or_else(|| {
+        let ion = precursor
+            .selected_ion_list
+            .as_ref()?
+            .selected_ions
+            .first()?;
+        cv_f64(&ion.cv_params, ACC_SELECTED_ION_MZ).or_else(|| cv_f64(&ion.cv_params, ACC_MZ))
+    })
+}
+
+/// Q3 of `chromatogram`: the product isolation window target.
+pub fn chromatogram_product_mz(chromatogram: &Chromatogram) -> Option<f64> {
+    window_target(chromatogram.product.as_ref()?.isolation_window.as_ref()?)
+}
+
+fn window_target(window: &IsolationWindow) -> Option<f64> {
+    cv_f64(&window.cv_params, ACC_TARGET_MZ)
+}
+
+/// Links each chromatogram of `mzml` to a transition of `traml`.
+///
+/// A transition whose id is the chromatogram id wins. Otherwise the
+/// transition whose Q1 and Q3 are both within `tolerance` (in m/z) of the
+/// chromatogram's isolation windows is taken, the closest one by summed
+/// difference if there are several. Chromatograms without both windows,
+/// like the TIC, are left unmatched.
+pub fn match_transitions<'a>(
+    mzml: &'a MzML,
+    traml: &'a TraML,
+    tolerance: f64,
+) -> Vec<TransitionMatch<'a>> {
+    let chromatograms = mzml
+        .run
+        .chromatogram_list
+        .iter()
+        .flat_map(|l| &l.chromatograms);
+    chromatograms
+        .map(|chromatogram| TransitionMatch {
+            chromatogram,
+            transition: find_transition(chromatogram, traml, tolerance),
+        })
+        .collect()
+}
+
+fn find_transition<'a>(
+    chromatogram: &Chromatogram,
+    traml: &'a TraML,
+    tolerance: f64,
+) -> Option<&'a Transition> {
+    if let Some(t) = traml.transitions.iter().find(|t| t.id == chromatogram.id) {
+        return Some(t);
+    }
+    let q1 = chromatogram_precursor_mz(chromatogram)?;
+    let q3 = chromatogram_product_mz(chromatogram)?;
+    traml
+        .transitions
+        .iter()
+        .filter_map(|t| {
+            let d1 = (t.precursor_mz()? - q1).abs();
+            let d3 = (t.product_mz()? - q3).abs();
+            (d1 <= tolerance && d3 <= tolerance).then_some((d1 + d3, t))
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, t)| t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mzml::{
+            structs::{ChromatogramList, Precursor, Product},
+            utilities::peak_list::{ms_cv, peak_list_mzml},
+        },
+        traml::parse_traml,
+    };
+
+    fn window(mz: f64) -> Option<IsolationWindow> {
+        Some(IsolationWindow {
+            cv_params: vec![ms_cv(
+                ACC_TARGET_MZ,
+                "isolation window target m/z",
+                Some(mz.to_string()),
+            )],
+            ..Default::default()
+        })
+    }
+
+    fn srm(id: &str, q1: f64, q3: f64) -> Chromatogram {
+        Chromatogram {
+            id: id.to_string(),
+            precursor: Some(Precursor {
+                isolation_window: window(q1),
+                ..Default::default()
+            }),
+            product: Some(Product {
+                isolation_window: window(q3),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn links_chromatograms_by_id_then_by_windows() {
+        let traml = parse_traml(&std::fs::read("data/traml/tiny2_SRM.TraML").unwrap()).unwrap();
+        let mut mzml = peak_list_mzml("srm", Vec::new(), Vec::new());
+        let chromatograms = vec![
+            Chromatogram {
+                id: "TIC".to_string(),
+                ..Default::default()
+            },
+            srm("SRM SIC Q1=445.3 Q3=520.1", 445.3, 520.1),
+            srm("SRM SIC Q1=445.3 Q3=672.6", 445.3, 672.6),
+            srm("SRM SIC Q1=445.3 Q3=900", 445.3, 900.0),
+            srm("glucose_203", 0.0, 0.0),
+        ];
+        mzml.run.chromatogram_list = Some(ChromatogramList {
+            count: Some(chromatograms.len()),
+            chromatograms,
+            ..Default::default()
+        });
+
+        let matched: Vec<_> = match_transitions(&mzml, &traml, 0.1)
+            .iter()
+            .map(|m| m.transition.map(|t| t.id.as_str()))
+            .collect();
+        assert_eq!(
+            matched,
+            [
+                None,
+                Some("ELLPEGPK_2_y5"),
+                Some("ELLPEGPK_2_y6"),
+                None,
+                Some("glucose_203")
+            ]
+        );
+        assert!(
+            match_transitions(&mzml, &traml, 0.01)[1]
+                .transition
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,8 @@
+pub mod structs;
+pub use structs::*;
+pub mod parse_traml;
+pub use parse_traml::parse_traml;
+pub mod match_transitions;
+pub use match_transitions::{
+    TransitionMatch, chromatogram_precursor_mz, chromatogram_product_mz, match_transitions,
+};
@@ -0,0 +1,489 @@
+use std::io::{BufRead, Cursor};
+
+use quick_xml::{
+    Reader,
+    events::{BytesStart, Event},
+};
+
+use crate::{
+    mzml::{
+        parse_mzml::{gunzip, is_gzip},
+        schema::TagId,
+        structs::{CvParam, UserParam},
+        utilities::{
+            ParseError, attr, attr_u32, parse_cv_list, parsing_workspace::ParsingWorkspace,
+            read_cv_param, read_element_text, read_user_param,
+        },
+    },
+    traml::structs::*,
+};
+
+/// Reads a TraML document (optionally gzipped): the proteins, peptides,
+/// compounds, transitions and target lists.
+///
+/// Source files, contacts, publications, instruments, software, evidence,
+/// predictions and validation statuses are skipped; the `instrumentRef`s
+/// and `softwareRef`s pointing at them are kept as they are.
+pub fn parse_traml(bytes: &[u8]) -> Result<TraML, ParseError> {
+    if is_gzip(bytes) {
+        return parse_traml(&gunzip(bytes)?);
+    }
+    let mut ws = ParsingWorkspace::new(Reader::from_reader(Cursor::new(bytes)));
+    let mut traml = TraML::default();
+    loop {
+        match ws.next_event()? {
+            Event::Start(e) if e.local_name().as_ref() == b"TraML" => {
+                traml.version = attr(&e, b"version");
+                traml.id = attr(&e, b"id");
+                parse_root(&mut ws, &e, &mut traml)?;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(traml)
+}
+
+/// Visits the children of `start`, collecting its cvParams and userParams
+/// and passing the other elements, with whether they are open, to
+/// `on_child`. Elements `on_child` returns `false` for are skipped.
+fn for_each_child_with_params<R, F>(
+    ws: &mut ParsingWorkspace<R>,
+    start: &BytesStart<'_>,
+    cv_params: &mut Vec<CvParam>,
+    user_params: &mut Vec<UserParam>,
+    mut on_child: F,
+) -> Result<(), ParseError>
+where
+    R: BufRead,
+    F: FnMut(&mut ParsingWorkspace<R>, &BytesStart<'static>, bool) -> Result<bool, ParseError>,
+{
+    ws.for_each_child(start, |ws, event| {
+        let (tag, element, is_open) = event.into_parts();
+        match tag {
+            TagId::CvParam => cv_params.push(read_cv_param(&element)),
+            TagId::UserParam => user_params.push(read_user_param(&element)),
+            _ => return on_child(ws, &element, is_open),
+        }
+        Ok(true)
+    })
+}
+
+/// Calls `on_item` for each open or empty `item` child of the list
+/// element `start`.
+fn for_each_item<R, F>(
+    ws: &mut ParsingWorkspace<R>,
+    start: &BytesStart<'_>,
+    is_open: bool,
+    item: &[u8],
+    mut on_item: F,
+) -> Result<(), ParseError>
+where
+    R: BufRead,
+    F: FnMut(&mut ParsingWorkspace<R>, &BytesStart<'static>, bool) -> Result<(), ParseError>,
+{
+    if !is_open {
+        return Ok(());
+    }
+    ws.for_each_child(start, |ws, event| {
+        let (_, element, is_open) = event.into_parts();
+        if element.local_name().as_ref() != item {
+            return Ok(false);
+        }
+        on_item(ws, &element, is_open)?;
+        Ok(true)
+    })
+}
+
+fn parse_root<R: BufRead>(
+    ws: &mut ParsingWorkspace<R>,
+    start: &BytesStart<'_>,
+    traml: &mut TraML,
+) -> Result<(), ParseError> {
+    ws.for_each_child(start, |ws, event| {
+        let (_, element, is_open) = event.into_parts();
+        match element.local_name().as_ref() {
+            b"cvList" if is_open => traml.cv_list = Some(parse_cv_list(ws, &element)?),
+            b"ProteinList" => for_each_item(ws, &element, is_open, b"Protein", |ws, e, open| {
+                traml.proteins.push(parse_protein(ws, e, open)?);
+                Ok(())
+            })?,
+            b"CompoundList" if is_open => ws.for_each_child(&element, |ws, event| {
+                let (_, element, is_open) = event.into_parts();
+                match element.local_name().as_ref() {
+                    b"Peptide" => traml.peptides.push(parse_peptide(ws, &element, is_open)?),
+                    b"Compound" => traml.compounds.push(parse_compound(ws, &element, is_open)?),
+                    _ => return Ok(false),
+                }
+                Ok(true)
+            })?,
+            b"TransitionList" => {
+                for_each_item(ws, &element, is_open, b"Transition", |ws, e, open| {
+                    traml.transitions.push(parse_transition(ws, e, open)?);
+                    Ok(())
+                })?
+            }
+            b"TargetList" => traml.target_list = Some(parse_target_list(ws, &element, is_open)?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })
+}
+
+fn parse_protein<R: BufRead>(
+    ws: &mut ParsingWorkspace<R>,
+    start: &BytesStart<'_>,
+    is_open: bool,
+) -> Result<Protein, ParseError> {
+    let mut protein = Protein {
+        id: attr(start, b"id").unwrap_or_default(),
+        ..Default::default()
+    };
+    if !is_open {
+        return Ok(protein);
+    }
+    let (cv_params, user_params) = (&mut protein.cv_params, &mut protein.user_params);
+    let mut sequence = None;
+    for_each_child_with_params(ws, start, cv_params, user_params, |ws, e, open| {
+        if e.local_name().as_ref() != b"Sequence" {
+            return Ok(false);
+        }
+        if open {
+            let closing = e.name().as_ref().to_vec();
+            sequence = Some(read_element_text(ws, &closing)?);
+        }
+        Ok(true)
+    })?;
+    protein.sequence = sequence;
+    Ok(protein)
+}
+
+fn parse_peptide<R: BufRead>(
+    ws: &mut ParsingWorkspace<R>,
+    start: &BytesStart<'_>,
+    is_open: bool,
+) -> Result<Peptide, ParseError> {
+    let mut peptide = Peptide {
+        id: attr(start, b"id").unwrap_or_default(),
+        sequence: attr(start, b"sequence").unwrap_or_default(),
+        ..Default::default()
+    };
+    if !is_open {
+        return Ok(peptide);
+    }
+    let Peptide {
+        protein_refs,
+        modifications,
+        retention_times,
+        cv_params,
+        user_params,
+        ..
+    } = &mut peptide;
+    for_each_child_with_params(ws, start, cv_params, user_params, |ws, e, open| {
+        match e.local_name().as_ref() {
+            b"ProteinRef" => protein_refs.extend(attr(e, b"ref")),
+            b"Modification" => modifications.push(parse_modification(ws, e, open)?),
+            b"RetentionTimeList" => parse_retention_times(ws, e, open, retention_times)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })?;
+    Ok(peptide)
+}
+
+fn parse_modification<R: BufRead>(
+    ws: &mut ParsingWorkspace<R>,
+    start: &BytesStart<'_>,
+    is_open: bool,
+) -> Result<Modification, ParseError> {
+    let mass = |name: &[u8]| attr(start, name).and_then(|v| v.trim().parse().ok());
+    let mut modification = Modification {
+        location: attr_u32(start, b"location"),
+        monoisotopic_mass_delta: mass(b"monoisotopicMassDelta"),
+        average_mass_delta: mass(b"averageMassDelta"),
+        ..Default::default()
+    };
+    if is_open {
+        let (cv_params, user_params) = (&mut modification.cv_params, &mut modification.user_params);
+        for_each_child_with_params(ws, start, cv_params, user_params, |_, _, _| Ok(false))?;
+    }
+    Ok(modification)
+}
+
+fn parse_compound<R: BufRead>(
+    ws: &mut ParsingWorkspace<R>,
+    start: &BytesStart<'_>,
+    is_open: bool,
+) -> Result<Compound, ParseError> {
+    let mut compound = Compound {
+        id: attr(start, b"id").unwrap_or_default(),
+        ..Default::default()
+    };
+    if !is_open {
+        return Ok(compound);
+    }
+    let Compound {
+        retention_times,
+        cv_params,
+        user_params,
+        ..
+    } = &mut compound;
+    for_each_child_with_params(ws, start, cv_params, user_params, |ws, e, open| {
+        if e.local_name().as_ref() != b"RetentionTimeList" {
+            return Ok(false);
+        }
+        parse_retention_times(ws, e, open, retention_times)?;
+        Ok(true)
+    })?;
+    Ok(compound)
+}
+
+fn parse_retention_times<R: BufRead>(
+    ws: &mut ParsingWorkspace<R>,
+    start: &BytesStart<'_>,
+    is_open: bool,
+    out: &mut Vec<RetentionTime>,
+) -> Result<(), ParseError> {
+    for_each_item(ws, start, is_open, b"RetentionTime", |ws, e, open| {
+        out.push(parse_retention_time(ws, e, open)?);
+        Ok(())
+    })
+}
+
+fn parse_retention_time<R: BufRead>(
+    ws: &mut ParsingWorkspace<R>,
+    start: &BytesStart<'_>,
+    is_open: bool,
+) -> Result<RetentionTime, ParseError> {
+    let mut retention_time = RetentionTime {
+        software_ref: attr(start, b"softwareRef"),
+        ..Default::default()
+    };
+    if is_open {
+        let (cv_params, user_params) = (
+            &mut retention_time.cv_params,
+            &mut retention_time.user_params,
+        );
+        for_each_child_with_params(ws, start, cv_params, user_params, |_, _, _| Ok(false))?;
+    }
+    Ok(retention_time)
+}
+
+fn parse_configurations<R: BufRead>(
+    ws: &mut ParsingWorkspace<R>,
+    start: &BytesStart<'_>,
+    is_open: bool,
+    out: &mut Vec<Configuration>,
+) -> Result<(), ParseError> {
+    for_each_item(ws, start, is_open, b"Configuration", |ws, e, open| {
+        let mut configuration = Configuration {
+            instrument_ref: attr(e, b"instrumentRef"),
+            contact_ref: attr(e, b"contactRef"),
+            ..Default::default()
+        };
+        if open {
+            let (cv_params, user_params) =
+                (&mut configuration.cv_params, &mut configuration.user_params);
+            for_each_child_with_params(ws, e, cv_params, user_params, |_, _, _| Ok(false))?;
+        }
+        out.push(configuration);
+        Ok(())
+    })
+}
+
+/// <Precursor>, <IntermediateProduct> or <Product>.
+fn parse_ion<R: BufRead>(
+    ws: &mut ParsingWorkspace<R>,
+    start: &BytesStart<'_>,
+    is_open: bool,
+) -> Result<TransitionIon, ParseError> {
+    let mut ion = TransitionIon::default();
+    if !is_open {
+        return Ok(ion);
+    }
+    let TransitionIon {
+        cv_params,
+        user_params,
+        interpretations,
+        configurations,
+    } = &mut ion;
+    for_each_child_with_params(ws, start, cv_params, user_params, |ws, e, open| {
+        match e.local_name().as_ref() {
+            b"InterpretationList" => {
+                for_each_item(ws, e, open, b"Interpretation", |ws, e, open| {
+                    let mut interpretation = Interpretation {
+                        primary: attr(e, b"primary").map(|v| v == "true" || v == "1"),
+                        ..Default::default()
+                    };
+                    if open {
+                        let (cv_params, user_params) = (
+                            &mut interpretation.cv_params,
+                            &mut interpretation.user_params,
+                        );
+                        for_each_child_with_params(ws, e, cv_params, user_params, |_, _, _| {
+                            Ok(false)
+                        })?;
+                    }
+                    interpretations.push(interpretation);
+                    Ok(())
+                })?
+            }
+            b"ConfigurationList" => parse_configurations(ws, e, open, configurations)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })?;
+    Ok(ion)
+}
+
+fn parse_transition<R: BufRead>(
+    ws: &mut ParsingWorkspace<R>,
+    start: &BytesStart<'_>,
+    is_open: bool,
+) -> Result<Transition, ParseError> {
+    let mut transition = Transition {
+        id: attr(start, b"id").unwrap_or_default(),
+        peptide_ref: attr(start, b"peptideRef"),
+        compound_ref: attr(start, b"compoundRef"),
+        ..Default::default()
+    };
+    if !is_open {
+        return Ok(transition);
+    }
+    let Transition {
+        precursor,
+        intermediate_products,
+        product,
+        retention_time,
+        cv_params,
+        user_params,
+        ..
+    } = &mut transition;
+    for_each_child_with_params(ws, start, cv_params, user_params, |ws, e, open| {
+        match e.local_name().as_ref() {
+            b"Precursor" => *precursor = Some(parse_ion(ws, e, open)?),
+            b"IntermediateProduct" => intermediate_products.push(parse_ion(ws, e, open)?),
+            b"Product" => *product = Some(parse_ion(ws, e, open)?),
+            b"RetentionTime" => *retention_time = Some(parse_retention_time(ws, e, open)?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })?;
+    Ok(transition)
+}
+
+fn parse_target<R: BufRead>(
+    ws: &mut ParsingWorkspace<R>,
+    start: &BytesStart<'_>,
+    is_open: bool,
+) -> Result<Target, ParseError> {
+    let mut target = Target {
+        id: attr(start, b"id").unwrap_or_default(),
+        peptide_ref: attr(start, b"peptideRef"),
+        compound_ref: attr(start, b"compoundRef"),
+        ..Default::default()
+    };
+    if !is_open {
+        return Ok(target);
+    }
+    let Target {
+        precursor,
+        retention_time,
+        configurations,
+        cv_params,
+        user_params,
+        ..
+    } = &mut target;
+    for_each_child_with_params(ws, start, cv_params, user_params, |ws, e, open| {
+        match e.local_name().as_ref() {
+            b"Precursor" => *precursor = Some(parse_ion(ws, e, open)?),
+            b"RetentionTime" => *retention_time = Some(parse_retention_time(ws, e, open)?),
+            b"ConfigurationList" => parse_configurations(ws, e, open, configurations)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })?;
+    Ok(target)
+}
+
+fn parse_target_list<R: BufRead>(
+    ws: &mut ParsingWorkspace<R>,
+    start: &BytesStart<'_>,
+    is_open: bool,
+) -> Result<TargetList, ParseError> {
+    let mut list = TargetList::default();
+    if !is_open {
+        return Ok(list);
+    }
+    let TargetList {
+        include,
+        exclude,
+        cv_params,
+        user_params,
+    } = &mut list;
+    for_each_child_with_params(ws, start, cv_params, user_params, |ws, e, open| {
+        let targets = match e.local_name().as_ref() {
+            b"TargetIncludeList" => &mut *include,
+            b"TargetExcludeList" => &mut *exclude,
+            _ => return Ok(false),
+        };
+        for_each_item(ws, e, open, b"Target", |ws, e, open| {
+            targets.push(parse_target(ws, e, open)?);
+            Ok(())
+        })?;
+        Ok(true)
+    })?;
+    Ok(list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_srm_fixture() {
+        let bytes = std::fs::read("data/traml/tiny2_SRM.TraML").unwrap();
+        let traml = parse_traml(&bytes).unwrap();
+        assert_eq!(traml.version.as_deref(), Some("1.0.0"));
+        assert_eq!(traml.cv_list.as_ref().unwrap().cv.len(), 3);
+        assert_eq!(traml.proteins[0].sequence.as_deref(), Some("MELLPEGPK"));
+
+        let peptide = &traml.peptides[0];
+        assert_eq!(peptide.sequence, "ELLPEGPK");
+        assert_eq!(peptide.protein_refs, ["P1"]);
+        assert_eq!(
+            peptide.modifications[0].monoisotopic_mass_delta,
+            Some(0.984)
+        );
+        assert_eq!(peptide.retention_times[0].seconds(), Some(353.43));
+        assert_eq!(traml.compounds[0].id, "glucose");
+
+        assert_eq!(traml.transitions.len(), 3);
+        let t = &traml.transitions[0];
+        assert_eq!(t.peptide_ref.as_deref(), Some("ELLPEGPK_2"));
+        assert_eq!(
+            (t.precursor_mz(), t.product_mz()),
+            (Some(445.34), Some(520.13))
+        );
+        assert_eq!(t.precursor.as_ref().unwrap().charge(), Some(2));
+        assert_eq!(t.collision_energy(), Some(26.0));
+        assert_eq!(t.retention_time_seconds(), Some(353.43));
+        let product = t.product.as_ref().unwrap();
+        assert_eq!(product.interpretations[0].primary, Some(true));
+        assert_eq!(
+            product.configurations[0].instrument_ref.as_deref(),
+            Some("TSQ")
+        );
+        assert_eq!(
+            traml.transitions[2].compound_ref.as_deref(),
+            Some("glucose")
+        );
+
+        let targets = traml.target_list.as_ref().unwrap();
+        assert_eq!(
+            targets.include[0].precursor.as_ref().unwrap().mz(),
+            Some(445.34)
+        );
+        assert_eq!(targets.exclude.len(), 1);
+    }
+}
@@ -0,0 +1,188 @@
+use serde::{Deserialize, Serialize};
+
+use crate::mzml::{
+    structs::{CvList, CvParam, UserParam},
+    utilities::peak_list::{ACC_CHARGE_STATE, cv_f64, find_cv, time_in_seconds},
+};
+
+pub(crate) const ACC_TARGET_MZ: &str = "MS:1000827";
+pub(crate) const ACC_COLLISION_ENERGY: &str = "MS:1000045";
+/// Local, predicted and normalized retention time.
+pub(crate) const RETENTION_TIME_ACCESSIONS: [&str; 3] = ["MS:1000895", "MS:1000897", "MS:1000896"];
+
+/// <TraML>
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct TraML {
+    pub version: Option<String>,
+    pub id: Option<String>,
+    pub cv_list: Option<CvList>,
+    pub proteins: Vec<Protein>,
+    pub peptides: Vec<Peptide>,
+    pub compounds: Vec<Compound>,
+    pub transitions: Vec<Transition>,
+    pub target_list: Option<TargetList>,
+}
+
+/// <Protein>
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Protein {
+    pub id: String,
+    pub sequence: Option<String>,
+    pub cv_params: Vec<CvParam>,
+    pub user_params: Vec<UserParam>,
+}
+
+/// <Peptide>
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Peptide {
+    pub id: String,
+    pub sequence: String,
+    pub protein_refs: Vec<String>,
+    pub modifications: Vec<Modification>,
+    pub retention_times: Vec<RetentionTime>,
+    pub cv_params: Vec<CvParam>,
+    pub user_params: Vec<UserParam>,
+}
+
+/// <Modification>
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Modification {
+    /// 0 is the N-terminus, `sequence.len() + 1` the C-terminus.
+    pub location: Option<u32>,
+    pub monoisotopic_mass_delta: Option<f64>,
+    pub average_mass_delta: Option<f64>,
+    pub cv_params: Vec<CvParam>,
+    pub user_params: Vec<UserParam>,
+}
+
+/// <Compound>
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Compound {
+    pub id: String,
+    pub retention_times: Vec<RetentionTime>,
+    pub cv_params: Vec<CvParam>,
+    pub user_params: Vec<UserParam>,
+}
+
+/// <RetentionTime>
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct RetentionTime {
+    pub software_ref: Option<String>,
+    pub cv_params: Vec<CvParam>,
+    pub user_params: Vec<UserParam>,
+}
+
+impl RetentionTime {
+    /// Local, else predicted retention time, in seconds.
+    pub fn seconds(&self) -> Option<f64> {
+        let param = RETENTION_TIME_ACCESSIONS[..2]
+            .iter()
+            .find_map(|&acc| find_cv(&self.cv_params, acc))?;
+        time_in_seconds(param)
+    }
+
+    /// Normalized retention time (iRT and the like), which has no unit.
+    pub fn normalized(&self) -> Option<f64> {
+        cv_f64(&self.cv_params, RETENTION_TIME_ACCESSIONS[2])
+    }
+}
+
+/// <Configuration>: the instrument settings of a transition or target.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Configuration {
+    pub instrument_ref: Option<String>,
+    pub contact_ref: Option<String>,
+    pub cv_params: Vec<CvParam>,
+    pub user_params: Vec<UserParam>,
+}
+
+/// <Interpretation>: ion series, ordinal and the like of a product ion.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Interpretation {
+    pub primary: Option<bool>,
+    pub cv_params: Vec<CvParam>,
+    pub user_params: Vec<UserParam>,
+}
+
+/// <Precursor>, <IntermediateProduct> or <Product> of a transition or
+/// target.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct TransitionIon {
+    pub cv_params: Vec<CvParam>,
+    pub user_params: Vec<UserParam>,
+    pub interpretations: Vec<Interpretation>,
+    pub configurations: Vec<Configuration>,
+}
+
+impl TransitionIon {
+    /// `isolation window target m/z`.
+    pub fn mz(&self) -> Option<f64> {
+        cv_f64(&self.cv_params, ACC_TARGET_MZ)
+    }
+
+    pub fn charge(&self) -> Option<i32> {
+        cv_f64(&self.cv_params, ACC_CHARGE_STATE).map(|z| z as i32)
+    }
+}
+
+/// <Transition>
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Transition {
+    pub id: String,
+    pub peptide_ref: Option<String>,
+    pub compound_ref: Option<String>,
+    pub precursor: Option<TransitionIon>,
+    pub intermediate_products: Vec<TransitionIon>,
+    pub product: Option<TransitionIon>,
+    pub retention_time: Option<RetentionTime>,
+    pub cv_params: Vec<CvParam>,
+    pub user_params: Vec<UserParam>,
+}
+
+impl Transition {
+    /// Q1: target m/z of the precursor.
+    pub fn precursor_mz(&self) -> Option<f64> {
+        self.precursor.as_ref()?.mz()
+    }
+
+    /// Q3: target m/z of the product.
+    pub fn product_mz(&self) -> Option<f64> {
+        self.product.as_ref()?.mz()
+    }
+
+    /// Collision energy of the first product configuration that has one.
+    pub fn collision_energy(&self) -> Option<f64> {
+        let configurations = self.product.iter().flat_map(|p| &p.configurations);
+        configurations
+            .map(|c| &c.cv_params)
+            .chain([&self.cv_params])
+            .find_map(|params| cv_f64(params, ACC_COLLISION_ENERGY))
+    }
+
+    /// Expected retention time in seconds (see `RetentionTime::seconds`).
+    pub fn retention_time_seconds(&self) -> Option<f64> {
+        self.retention_time.as_ref()?.seconds()
+    }
+}
+
+/// <Target>: a precursor to include in or exclude from acquisition.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Target {
+    pub id: String,
+    pub peptide_ref: Option<String>,
+    pub compound_ref: Option<String>,
+    pub precursor: Option<TransitionIon>,
+    pub retention_time: Option<RetentionTime>,
+    pub configurations: Vec<Configuration>,
+    pub cv_params: Vec<CvParam>,
+    pub user_params: Vec<UserParam>,
+}
+
+/// <TargetList>
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct TargetList {
+    pub include: Vec<Target>,
+    pub exclude: Vec<Target>,
+    pub cv_params: Vec<CvParam>,
+    pub user_params: Vec<UserParam>,
+}